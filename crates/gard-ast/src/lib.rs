@@ -1,20 +1,51 @@
 use serde::{Deserialize, Serialize};
 
+mod messages;
+mod pretty;
+mod source_map;
+mod visit;
+
+pub use messages::{codes, explain, message, Locale};
+pub use pretty::{format, to_source, type_to_source};
+pub use source_map::{FileId, Location, SourceMap};
+pub use gard_intern::Symbol;
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Node {
     // Top-level declarations
     Program(Vec<Node>),
     
     // Class and Contract declarations
+    /// An `abstract` class can't be instantiated, and can declare
+    /// `abstract` methods that its concrete subclasses must implement.
     Class {
         name: String,
         extends: Option<String>,
         implements: Vec<String>,
+        is_abstract: bool,
         members: Vec<Node>,
+        /// Its doc comment, without the `///` or `/** */` markers
+        docs: Option<String>,
     },
     Contract {
         name: String,
         members: Vec<Node>,
+        /// Its doc comment, without the `///` or `/** */` markers
+        docs: Option<String>,
+    },
+    /// `type Name = A | B(T)`: a value of the union is one of the variants,
+    /// and a match on it must cover them all. A variant names a class, or
+    /// is a tag of the union's own, with an optional payload.
+    Union {
+        name: String,
+        variants: Vec<UnionVariant>,
+    },
+    /// `interface Name { function f(a: int): int; }`: a value of the
+    /// interface is an instance of any class implementing it, and only the
+    /// declared methods can be called on it.
+    Interface {
+        name: String,
+        methods: Vec<MethodSignature>,
     },
 
     // Function declarations
@@ -24,6 +55,8 @@ pub enum Node {
         return_type: Type,
         body: Box<Node>,
         modifiers: Vec<FunctionModifier>,
+        /// Its doc comment, without the `///` or `/** */` markers
+        docs: Option<String>,
     },
     Constructor {
         params: Vec<Parameter>,
@@ -38,6 +71,20 @@ pub enum Node {
         initializer: Option<Box<Node>>,
         is_mutable: bool,
     },
+    /// `const NAME: type = value`; the value is evaluated at compile time.
+    Const {
+        name: String,
+        type_annotation: Option<Type>,
+        value: Box<Node>,
+    },
+    /// `tasklocal name: type = value` at the top level: a variable each
+    /// task has its own of, starting at the value. `actorlocal` declares
+    /// the same, since an actor is a task and its mailbox.
+    TaskLocal {
+        name: String,
+        type_annotation: Option<Type>,
+        initializer: Box<Node>,
+    },
     If {
         condition: Box<Node>,
         then_branch: Box<Node>,
@@ -64,6 +111,11 @@ pub enum Node {
     },
     Return(Option<Box<Node>>),
     Throw(Box<Node>),
+    Assertion {
+        kind: AssertionKind,
+        condition: Box<Node>,
+        message: Option<Box<Node>>,
+    },
     Try {
         body: Box<Node>,
         catch_clauses: Vec<Node>,
@@ -88,20 +140,49 @@ pub enum Node {
         object: Box<Node>,
         property: String,
     },
+    /// `object[index]`. `checked` is cleared where the index is known to be
+    /// in bounds, and in `@unchecked` functions.
+    Index {
+        object: Box<Node>,
+        index: Box<Node>,
+        checked: bool,
+    },
+    /// `value is T`: whether a class instance or interface value is a `T`,
+    /// a class or interface, checked against its class's type info.
+    TypeTest {
+        value: Box<Node>,
+        target: Type,
+    },
+    /// `value as? T`: the value as a `T` if it is one, otherwise `null`.
+    SafeCast {
+        value: Box<Node>,
+        target: Type,
+    },
     Array {
         elements: Vec<Node>,
     },
     Map {
         entries: Vec<(Node, Node)>,
     },
+    /// `{ name: value, .. }`: an object of the anonymous type with those
+    /// fields, inferred from their values.
+    Object {
+        fields: Vec<(String, Node)>,
+    },
     Await(Box<Node>),
     
     // Literals and Identifiers
-    Identifier(String),
+    Identifier(Symbol),
     IntLiteral(i64),
     UIntLiteral(u64),
+    UInt256Literal(String),
     FloatLiteral(f64),
     StringLiteral(String),
+    /// `` `Hello ${user.name}` ``: its text parts are string literals, and
+    /// the rest are the interpolated expressions.
+    TemplateString {
+        parts: Vec<Node>,
+    },
     BooleanLiteral(bool),
     NullLiteral,
     This,
@@ -116,6 +197,45 @@ pub enum Node {
     Event {
         name: String,
         fields: Vec<Parameter>,
+        /// Its doc comment, without the `///` or `/** */` markers
+        docs: Option<String>,
+    },
+    StorageSlot {
+        slot: Box<Node>,
+        declaration: Box<Node>,
+    },
+
+    // WebAssembly interop
+    WasmExport {
+        export_name: Option<String>,
+        declaration: Box<Node>,
+    },
+    WasmImport {
+        module: String,
+        name: String,
+        params: Vec<Parameter>,
+        return_type: Type,
+    },
+
+    // Attributes
+    /// `@derive(Equals, ToString) class ..`; replaced by the class with the
+    /// derived methods added before type checking.
+    Derive {
+        derives: Vec<String>,
+        declaration: Box<Node>,
+    },
+    /// `@cfg(target = "wasm32", feature = "x") ..`; the declaration is only
+    /// kept when every `(key, value)` condition holds for the build.
+    Cfg {
+        conditions: Vec<(String, String)>,
+        declaration: Box<Node>,
+    },
+    /// `@name(arguments) ..` for an attribute the compiler doesn't know;
+    /// replaced by the plugin that handles `name` before type checking.
+    Attribute {
+        name: String,
+        arguments: Vec<Node>,
+        declaration: Box<Node>,
     },
 
     // Actor System
@@ -155,6 +275,17 @@ pub enum Node {
     Atomic {
         body: Box<Node>,
     },
+    // Structured concurrency
+    /// `scope { .. }`: runs its body, then waits for every task spawned in
+    /// it before exiting. The first failure, of the body or a task, cancels
+    /// the tasks that haven't finished and propagates out of the scope.
+    Scope {
+        body: Box<Node>,
+    },
+    /// `spawn f(..);`: starts a call as a task of the innermost enclosing
+    /// `scope`. The arguments are evaluated when the task is spawned.
+    Spawn(Box<Node>),
+
     CatchClause {
         param_name: String,
         param_type: Type,
@@ -166,32 +297,142 @@ pub enum Node {
     },
     Break,
     Continue,
+
+    // Macros
+    /// `macro name(params) { .. }`; the body is a block of declarations or
+    /// statements that calls expand to.
+    MacroDefinition {
+        name: String,
+        params: Vec<String>,
+        body: Box<Node>,
+    },
+    /// `name!(arguments)` in declaration or statement position.
+    MacroCall {
+        name: String,
+        arguments: Vec<Node>,
+        span: Span,
+    },
+
+    // Inline IR
+    /// `llvm { "..." }`: raw LLVM IR, either at the top level or as the whole
+    /// body of a function.
+    InlineIr(String),
+
+    // Source locations
+    /// A statement together with the byte range it was parsed from.
+    Located {
+        span: Span,
+        node: Box<Node>,
+    },
+
+    // Parse errors
+    /// A region the parser couldn't make sense of, from `parse_recovering`,
+    /// standing in for the declaration or statement that should be there
+    /// so tools can skip it and still see the rest of the file.
+    Error {
+        span: Span,
+        consumed_tokens: usize,
+    },
+}
+
+impl Node {
+    /// The node without any `Located` wrappers.
+    pub fn unlocated(&self) -> &Node {
+        match self {
+            Node::Located { node, .. } => node.unlocated(),
+            node => node,
+        }
+    }
+
+    pub fn into_unlocated(self) -> Node {
+        match self {
+            Node::Located { node, .. } => node.into_unlocated(),
+            node => node,
+        }
+    }
+
+    /// The doc comment of the class, function, contract or event this is,
+    /// or is wrapped in attributes around.
+    pub fn docs(&self) -> Option<&str> {
+        match self {
+            Node::Class { docs, .. } | Node::Function { docs, .. } | Node::Contract { docs, .. } | Node::Event { docs, .. } => docs.as_deref(),
+            Node::Located { node: declaration, .. }
+            | Node::Attribute { declaration, .. }
+            | Node::Derive { declaration, .. }
+            | Node::Cfg { declaration, .. }
+            | Node::WasmExport { declaration, .. }
+            | Node::StorageSlot { declaration, .. } => declaration.docs(),
+            _ => None,
+        }
+    }
+
+    /// Where `docs` is kept, if this is a node that can have docs.
+    pub fn docs_mut(&mut self) -> Option<&mut Option<String>> {
+        match self {
+            Node::Class { docs, .. } | Node::Function { docs, .. } | Node::Contract { docs, .. } | Node::Event { docs, .. } => Some(docs),
+            Node::Located { node: declaration, .. }
+            | Node::Attribute { declaration, .. }
+            | Node::Derive { declaration, .. }
+            | Node::Cfg { declaration, .. }
+            | Node::WasmExport { declaration, .. }
+            | Node::StorageSlot { declaration, .. } => declaration.docs_mut(),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Parameter {
-    pub name: String,
+    pub name: Symbol,
     pub type_annotation: Type,
 }
 
+/// A variant of a union: `Logout`, or `UpdateProfile(Profile)` with a
+/// payload, which its construction takes and a match binds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnionVariant {
+    pub name: String,
+    pub payload: Vec<Type>,
+}
+
+impl UnionVariant {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), payload: Vec::new() }
+    }
+}
+
+/// A method an interface declares, without a body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MethodSignature {
+    pub name: String,
+    pub params: Vec<Parameter>,
+    pub return_type: Type,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Type {
     Int,
     UInt,
+    UInt256,
     Float,
     Double,
     String,
+    /// Binary data, such as calldata or a network packet
+    Bytes,
     Boolean,
     Void,
     Array(Box<Type>),
     Map { key: Box<Type>, value: Box<Type> },
     Set(Box<Type>),
     Address,
-    Custom(String),
+    Custom(Symbol),
     Function {
         params: Vec<Type>,
         return_type: Box<Type>,
     },
+    /// The anonymous type of an object literal, its fields in order. An
+    /// object has a type when it has at least its fields.
+    Object(Vec<(String, Type)>),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -200,6 +441,7 @@ pub enum BinaryOp {
     Eq, NotEq, Lt, LtEq, Gt, GtEq,
     And, Or,
     NullCoalesce,
+    BitAnd, BitOr, BitXor, Shl, Shr,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -208,6 +450,17 @@ pub enum UnaryOp {
     Not,
     Increment,
     Decrement,
+    BitNot,
+    /// `x++` and `x--`, whose value is the operand's from before the step
+    PostIncrement,
+    PostDecrement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssertionKind {
+    Validate,
+    Require,
+    Assert,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -233,6 +486,15 @@ pub enum FunctionModifier {
     View,
     Pure,
     Payable,
+    /// `@unchecked`: index expressions in the function skip bounds checks
+    Unchecked,
+    /// `abstract`: declared without a body, for subclasses to implement
+    Abstract,
+    /// `@concurrent`: a test `gard simulate` runs under many schedules
+    Concurrent,
+    /// `@export`: callable from C under its own name, and declared in the
+    /// header `--emit header` writes
+    Export,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -0,0 +1,255 @@
+//! The diagnostic message catalog. Each diagnostic has a stable code, which
+//! tools match on, and its text in each locale, with `{name}` where its
+//! arguments go. A message missing in a locale falls back to English. Each
+//! also has a longer explanation, in English, that `gard explain` prints.
+//!
+//! Lexer errors are the first diagnostics with codes (`L` and four digits);
+//! others move here as they get theirs.
+
+use std::env;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+}
+
+impl Locale {
+    /// A language tag or POSIX locale: `es`, `es-MX` or `es_ES.UTF-8`.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let language = tag.split(['-', '_', '.']).next().unwrap_or_default().to_ascii_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Self::En),
+            "es" => Some(Self::Es),
+            _ => None,
+        }
+    }
+
+    /// The first of `GARD_LANG`, `LC_ALL`, `LC_MESSAGES` and `LANG` that's
+    /// set, or English if it names a language without a catalog.
+    pub fn from_env() -> Self {
+        ["GARD_LANG", "LC_ALL", "LC_MESSAGES", "LANG"].into_iter()
+            .filter_map(|variable| env::var(variable).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::parse(&value))
+            .unwrap_or_default()
+    }
+}
+
+struct Entry {
+    code: &'static str,
+    en: &'static str,
+    es: Option<&'static str>,
+    /// What causes it and how to fix it, with examples, for `gard explain`
+    explanation: &'static str,
+}
+
+const CATALOG: &[Entry] = &[
+    Entry {
+        code: "L0001",
+        en: "Invalid token '{found}' at position {position}, expected one of: {expected}",
+        es: Some("Token no válido '{found}' en la posición {position}; se esperaba uno de: {expected}"),
+        explanation: r#"A character or sequence of characters that doesn't start any Gard token.
+
+Erroneous code example:
+
+    let price = 10 § 3;
+
+Only the operators, punctuation and literals Gard defines can appear outside
+strings and comments. Remove the character, or put it in a string:
+
+    let label = "10 § 3";
+
+A number that runs into a word, like `0xg` or `1e`, is one invalid token too.
+The message lists what could have come there instead, such as a hex digit
+after `0x`.
+"#,
+    },
+    Entry {
+        code: "L0002",
+        en: "Unterminated string literal starting at position {position}: '{partial}'",
+        es: Some("Cadena sin terminar que empieza en la posición {position}: '{partial}'"),
+        explanation: r#"A string literal that isn't closed before the end of the file.
+
+Erroneous code example:
+
+    let greeting = "Hello;
+
+Close the string with a quote. A quote inside the string is escaped with a
+backslash:
+
+    let greeting = "Hello";
+    let quoted = "She said \"hi\"";
+
+Template strings, written with backticks, report this code too when their
+closing backtick or an interpolation's closing brace is missing.
+"#,
+    },
+    Entry {
+        code: "L0003",
+        en: "Invalid escape sequence '{sequence}' at position {position}",
+        es: Some("Secuencia de escape no válida '{sequence}' en la posición {position}"),
+        explanation: r#"A backslash in a string that doesn't start a known escape sequence.
+
+Erroneous code example:
+
+    let path = "C:\data";
+
+The escapes are `\n`, `\r`, `\t`, `\0`, `\\`, `\"`, `\'`, `\xNN` for an ASCII
+character and `\u{N}` with one to six hex digits for any other. Escape the
+backslash itself to keep it:
+
+    let path = "C:\\data";
+"#,
+    },
+    Entry {
+        code: "L0004",
+        en: "Invalid number format '{value}' at position {position}",
+        es: Some("Formato de número no válido '{value}' en la posición {position}"),
+        explanation: r#"A number literal that's malformed, usually by its digit separators.
+
+Erroneous code example:
+
+    let million = 1__000_000;
+    let mask = 0x_ff;
+
+Underscores go between digits: not first or last in a group of digits, and
+not two in a row:
+
+    let million = 1_000_000;
+    let mask = 0xff;
+"#,
+    },
+    Entry {
+        code: "L0005",
+        en: "Unterminated comment starting at position {position}: '{partial}'",
+        es: Some("Comentario sin terminar que empieza en la posición {position}: '{partial}'"),
+        explanation: r#"A block comment that isn't closed before the end of the file.
+
+Erroneous code example:
+
+    /* outer /* inner */
+    function main { }
+
+Block comments nest, so every `/*` needs its own `*/`:
+
+    /* outer /* inner */ */
+    function main { }
+"#,
+    },
+    Entry {
+        code: "L0006",
+        en: "Invalid character '{character}' at position {position}",
+        es: Some("Carácter no válido '{character}' en la posición {position}"),
+        explanation: r#"A character that can't appear in Gard source outside strings and comments.
+
+Erroneous code example:
+
+    let my#count = 1;
+
+Identifiers are letters, digits and underscores, starting with a letter or
+an underscore:
+
+    let my_count = 1;
+"#,
+    },
+    Entry {
+        code: "L0007",
+        en: "Invalid actor message '{message}' at position {position}",
+        es: Some("Mensaje de actor no válido '{message}' en la posición {position}"),
+        explanation: r#"An actor message with characters in it that don't lex.
+
+A message is an ordinary expression, so the rules for tokens (L0001) and
+characters (L0006) apply to it.
+"#,
+    },
+    Entry {
+        code: "L0008",
+        en: "Invalid transaction state '{state}' at position {position}",
+        es: Some("Estado de transacción no válido '{state}' en la posición {position}"),
+        explanation: r#"Characters that don't lex right after `Transaction`.
+
+Erroneous code example:
+
+    Transaction$begin
+
+Check the spelling of the transaction code and the punctuation after it.
+"#,
+    },
+    Entry {
+        code: "L0009",
+        en: "Invalid supervision decision '{decision}' at position {position}",
+        es: Some("Decisión de supervisión no válida '{decision}' en la posición {position}"),
+        explanation: r#"A supervision decision that Gard doesn't define.
+
+Erroneous code example:
+
+    Decision.RETRY => { }
+
+A supervisor decides `Decision.RESTART`, `Decision.STOP` or
+`Decision.ESCALATE` for a failed actor:
+
+    Decision.RESTART => { }
+"#,
+    },
+    Entry {
+        code: "L0010",
+        en: "Invalid actor behavior '{behavior}' at position {position}",
+        es: Some("Comportamiento de actor no válido '{behavior}' en la posición {position}"),
+        explanation: r#"Characters that don't lex right after `Actor`.
+
+Erroneous code example:
+
+    Actor$counter
+
+Check the spelling of the actor code and the punctuation after it.
+"#,
+    },
+];
+
+/// The text of the diagnostic `code` in `locale`, with its arguments filled
+/// in. An unknown code is its own text.
+pub fn message(code: &str, locale: Locale, arguments: &[(&str, &dyn Display)]) -> String {
+    let Some(entry) = CATALOG.iter().find(|entry| entry.code == code) else {
+        return code.to_string();
+    };
+    let template = match locale {
+        Locale::En => entry.en,
+        Locale::Es => entry.es.unwrap_or(entry.en),
+    };
+    // In one pass, so braces in an argument are left alone
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let argument = after.find('}').and_then(|end| {
+            arguments.iter().find(|(name, _)| *name == &after[..end]).map(|(_, value)| (value, end))
+        });
+        match argument {
+            Some((value, end)) => {
+                text.push_str(&value.to_string());
+                rest = &after[end + 1..];
+            },
+            None => {
+                text.push('{');
+                rest = after;
+            },
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// The explanation of the diagnostic `code`, in either case: `L0004` or
+/// `l0004`.
+pub fn explain(code: &str) -> Option<&'static str> {
+    CATALOG.iter().find(|entry| entry.code.eq_ignore_ascii_case(code)).map(|entry| entry.explanation)
+}
+
+/// Every diagnostic code, in order.
+pub fn codes() -> impl Iterator<Item = &'static str> {
+    CATALOG.iter().map(|entry| entry.code)
+}
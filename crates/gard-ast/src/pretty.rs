@@ -0,0 +1,680 @@
+use crate::{AssertionKind, BinaryOp, FunctionModifier, Node, Parameter, SupervisionStrategy, Type, UnaryOp};
+
+const INDENT: &str = "    ";
+
+/// Gard source for a node, parseable back into the same tree except for
+/// spans. Nodes without syntax of their own print as the closest
+/// construct: an `STMTransaction` as an `atomic` block, a `Receive` as a
+/// `receive` handler.
+pub fn to_source(node: &Node) -> String {
+    Printer::default().print(node)
+}
+
+/// Like `to_source`, but the regions that didn't parse are copied from
+/// `source` as they were written, so a file with errors in it can be
+/// formatted without losing them. `to_source` prints them as comments.
+pub fn format(node: &Node, source: &str) -> String {
+    Printer { source: Some(source), ..Printer::default() }.print(node)
+}
+
+/// Gard source for a type annotation.
+pub fn type_to_source(ty: &Type) -> String {
+    match ty {
+        Type::Int => "int".to_string(),
+        Type::UInt => "uint".to_string(),
+        Type::UInt256 => "uint256".to_string(),
+        Type::Float => "float".to_string(),
+        Type::Double => "double".to_string(),
+        Type::String => "string".to_string(),
+        Type::Bytes => "bytes".to_string(),
+        Type::Boolean => "boolean".to_string(),
+        Type::Void => "void".to_string(),
+        Type::Address => "address".to_string(),
+        Type::Array(element) => format!("array<{}>", type_to_source(element)),
+        Type::Map { key, value } => format!("map<{}, {}>", type_to_source(key), type_to_source(value)),
+        Type::Set(element) => format!("set<{}>", type_to_source(element)),
+        Type::Custom(name) => name.to_string(),
+        Type::Function { params, return_type } => {
+            let params: Vec<String> = params.iter().map(type_to_source).collect();
+            format!("function({}): {}", params.join(", "), type_to_source(return_type))
+        },
+        Type::Object(fields) if fields.is_empty() => "{}".to_string(),
+        Type::Object(fields) => {
+            let fields: Vec<String> = fields.iter().map(|(name, ty)| format!("{}: {}", name, type_to_source(ty))).collect();
+            format!("{{ {} }}", fields.join(", "))
+        },
+    }
+}
+
+#[derive(Default)]
+struct Printer<'a> {
+    out: String,
+    indent: usize,
+    /// Whether the next line goes on the end of the last one
+    continued: bool,
+    /// The text `Error` nodes' spans are in
+    source: Option<&'a str>,
+}
+
+impl Printer<'_> {
+    fn print(mut self, node: &Node) -> String {
+        match node {
+            Node::Program(nodes) => self.declarations(nodes),
+            node if is_expression(node) => return expression(node),
+            node => self.item(node),
+        }
+        self.out
+    }
+
+    fn line(&mut self, text: &str) {
+        if !std::mem::take(&mut self.continued) {
+            self.out.push_str(&INDENT.repeat(self.indent));
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Top-level items, separated by blank lines.
+    fn declarations(&mut self, nodes: &[Node]) {
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+            }
+            self.item(node);
+        }
+    }
+
+    /// `head {`, the items, then `}` and `tail`.
+    fn braced(&mut self, head: &str, items: &[Node], tail: &str) {
+        if items.is_empty() {
+            self.line(&format!("{}{{}}{}", head, tail));
+            return;
+        }
+        self.line(&format!("{}{{", head));
+        self.indent += 1;
+        for item in items {
+            self.item(item);
+        }
+        self.indent -= 1;
+        self.line(&format!("}}{}", tail));
+    }
+
+    /// A block body; anything else is printed as a block holding it.
+    fn body(&mut self, head: &str, body: &Node, tail: &str) {
+        match body.unlocated() {
+            Node::Block(items) if !is_expression_statement(body.unlocated()) => self.braced(head, items, tail),
+            other => self.braced(head, std::slice::from_ref(other), tail),
+        }
+    }
+
+    /// A declaration or statement, after its doc comment.
+    fn item(&mut self, node: &Node) {
+        if let Some(docs) = node.docs() {
+            for line in docs.lines() {
+                self.line(format!("/// {}", line).trim_end());
+            }
+        }
+        self.statement(node);
+    }
+
+    fn statement(&mut self, node: &Node) {
+        match node {
+            Node::Located { node, .. } => self.statement(node),
+            Node::Program(nodes) => self.declarations(nodes),
+            Node::Class { name, extends, implements, is_abstract, members, .. } => {
+                let mut head = format!("{}class {} ", if *is_abstract { "abstract " } else { "" }, name);
+                if let Some(extends) = extends {
+                    head.push_str(&format!("extends {} ", extends));
+                }
+                if !implements.is_empty() {
+                    head.push_str(&format!("implements {} ", implements.join(", ")));
+                }
+                self.braced(&head, members, "");
+            },
+            Node::Contract { name, members, .. } => self.braced(&format!("contract {} ", name), members, ""),
+            Node::Union { name, variants } => {
+                let variants: Vec<String> = variants.iter()
+                    .map(|variant| match variant.payload.is_empty() {
+                        true => variant.name.clone(),
+                        false => {
+                            let payload: Vec<String> = variant.payload.iter().map(type_to_source).collect();
+                            format!("{}({})", variant.name, payload.join(", "))
+                        },
+                    })
+                    .collect();
+                self.line(&format!("type {} = {}", name, variants.join(" | ")))
+            },
+            Node::Interface { name, methods } => {
+                self.line(&format!("interface {} {{", name));
+                self.indent += 1;
+                for method in methods {
+                    let mut line = format!("function {}({})", method.name, parameters(&method.params));
+                    if method.return_type != Type::Void {
+                        line.push_str(&format!(": {}", type_to_source(&method.return_type)));
+                    }
+                    line.push(';');
+                    self.line(&line);
+                }
+                self.indent -= 1;
+                self.line("}");
+            },
+            Node::Function { name, params, return_type, body, modifiers, .. } => {
+                if modifiers.contains(&FunctionModifier::Unchecked) {
+                    self.line("@unchecked");
+                }
+                if modifiers.contains(&FunctionModifier::Concurrent) {
+                    self.line("@concurrent");
+                }
+                if modifiers.contains(&FunctionModifier::Export) {
+                    self.line("@export");
+                }
+                let mut head: String = modifiers.iter()
+                    .filter_map(modifier_source)
+                    .map(|modifier| format!("{} ", modifier))
+                    .collect();
+                head.push_str(&format!("function {}", name));
+                let is_abstract = modifiers.contains(&FunctionModifier::Abstract);
+                if !params.is_empty() || *return_type != Type::Void || is_abstract {
+                    head.push_str(&format!("({})", parameters(params)));
+                }
+                if *return_type != Type::Void {
+                    head.push_str(&format!(": {}", type_to_source(return_type)));
+                }
+                if is_abstract {
+                    head.push(';');
+                    self.line(&head);
+                } else {
+                    head.push(' ');
+                    self.body(&head, body, "");
+                }
+            },
+            Node::Constructor { params, body } => self.body(&format!("constructor({}) ", parameters(params)), body, ""),
+            Node::Block(items) => match items.as_slice() {
+                // How the parser represents `expression;`
+                [expression] if is_expression(expression) => self.line(&format!("{};", self::expression(expression))),
+                items => self.braced("", items, ""),
+            },
+            Node::Let { .. } => self.line(&let_source(node)),
+            Node::Const { name, type_annotation, value } => {
+                let ty = type_annotation.as_ref().map(|ty| format!(": {}", type_to_source(ty))).unwrap_or_default();
+                self.line(&format!("const {}{} = {}", name, ty, expression(value)));
+            },
+            Node::TaskLocal { name, type_annotation, initializer } => {
+                let ty = type_annotation.as_ref().map(|ty| format!(": {}", type_to_source(ty))).unwrap_or_default();
+                self.line(&format!("tasklocal {}{} = {}", name, ty, expression(initializer)));
+            },
+            Node::If { condition, then_branch, else_branch } => self.if_statement(condition, then_branch, else_branch.as_deref(), ""),
+            Node::While { condition, body } => self.body(&format!("while ({}) ", expression(condition)), body, ""),
+            Node::For { initializer, condition, increment, body } => {
+                let initializer = initializer.as_deref().map(for_initializer).unwrap_or_default();
+                let condition = condition.as_deref().map(expression).unwrap_or_default();
+                let increment = increment.as_deref().map(expression).unwrap_or_default();
+                self.body(&format!("for ({}; {}; {}) ", initializer, condition, increment), body, "");
+            },
+            Node::Foreach { item, collection, body } => {
+                self.body(&format!("foreach ({} in {}) ", item, expression(collection)), body, "");
+            },
+            Node::Match { value, cases } => {
+                self.line(&format!("match {} {{", expression(value)));
+                self.indent += 1;
+                for case in cases {
+                    self.body(&format!("{} => ", expression(&case.pattern)), &case.body, "");
+                }
+                self.indent -= 1;
+                self.line("}");
+            },
+            Node::Return(Some(value)) => self.line(&format!("return {};", expression(value))),
+            Node::Return(None) => self.line("return;"),
+            Node::Throw(value) => self.line(&format!("throw {};", expression(value))),
+            Node::Assertion { kind, condition, message } => {
+                let keyword = match kind {
+                    AssertionKind::Validate => "validate",
+                    AssertionKind::Require => "require",
+                    AssertionKind::Assert => "assert",
+                };
+                match message {
+                    Some(message) => self.line(&format!("{}({}, {});", keyword, expression(condition), expression(message))),
+                    None => self.line(&format!("{}({});", keyword, expression(condition))),
+                }
+            },
+            Node::Try { body, catch_clauses, finally } => {
+                let mut clauses: Vec<(String, &Node)> = catch_clauses.iter()
+                    .map(|clause| match clause.unlocated() {
+                        Node::CatchClause { param_name, param_type, body } => {
+                            (format!("catch {}: {} ", param_name, type_to_source(param_type)), body.as_ref())
+                        },
+                        other => ("catch ".to_string(), other),
+                    })
+                    .collect();
+                if let Some(finally) = finally {
+                    clauses.push(("finally ".to_string(), finally));
+                }
+                // Each clause continues the line that closes the one before
+                let mut head = "try ".to_string();
+                let mut body = body.as_ref();
+                for (next_head, next_body) in clauses {
+                    self.body(&head, body, "");
+                    self.join_last_line();
+                    head = next_head;
+                    body = next_body;
+                }
+                self.body(&head, body, "");
+            },
+            Node::CatchClause { param_name, param_type, body } => {
+                self.body(&format!("catch {}: {} ", param_name, type_to_source(param_type)), body, "");
+            },
+            Node::Event { name, fields, .. } => {
+                self.line(&format!("@event {} {{", name));
+                self.indent += 1;
+                for field in fields {
+                    self.line(&parameter(field));
+                }
+                self.indent -= 1;
+                self.line("}");
+            },
+            Node::StorageSlot { slot, declaration } => {
+                self.line(&format!("@slot({}) {}", expression(slot), let_source(declaration.unlocated())));
+            },
+            Node::WasmExport { export_name, declaration } => {
+                match export_name {
+                    Some(export_name) => self.prefixed(&format!("@WasmExport({})", string_literal(export_name)), declaration),
+                    None => self.prefixed("@WasmExport", declaration),
+                }
+            },
+            Node::WasmImport { module, name, params, return_type } => {
+                // The parser ignores the function's own name, so any
+                // identifier does when the import name isn't one
+                let function = if is_identifier(name) { name.as_str() } else { "_import" };
+                let mut line = format!(
+                    "@WasmImport({}, {}) function {}({})",
+                    string_literal(module), string_literal(name), function, parameters(params),
+                );
+                if *return_type != Type::Void {
+                    line.push_str(&format!(": {}", type_to_source(return_type)));
+                }
+                line.push(';');
+                self.line(&line);
+            },
+            Node::Derive { derives, declaration } => self.prefixed(&format!("@derive({})", derives.join(", ")), declaration),
+            Node::Cfg { conditions, declaration } => {
+                let conditions: Vec<String> = conditions.iter()
+                    .map(|(key, value)| format!("{} = {}", key, string_literal(value)))
+                    .collect();
+                self.prefixed(&format!("@cfg({})", conditions.join(", ")), declaration);
+            },
+            Node::Attribute { name, arguments, declaration } if arguments.is_empty() => self.prefixed(&format!("@{}", name), declaration),
+            Node::Attribute { name, arguments, declaration } => {
+                self.prefixed(&format!("@{}({})", name, expressions(arguments)), declaration);
+            },
+            Node::Actor { name, type_param, members, .. } => {
+                let type_param = type_param.as_ref().map(|ty| format!("<{}>", type_to_source(ty))).unwrap_or_default();
+                self.braced(&format!("Actor {}{} ", name, type_param), members, "");
+            },
+            Node::Behavior { name, handlers } => self.braced(&format!("ActorBehavior {} ", name), handlers, ""),
+            Node::Receive { message_param, body } => {
+                self.body(&format!("function receive({}) ", parameter(message_param)), body, "");
+            },
+            Node::Become { behavior } => self.line(&format!("become {};", expression(behavior))),
+            Node::Supervise { strategy, children } => {
+                let strategy = match strategy {
+                    SupervisionStrategy::OneForOne => "Decision.RESTART",
+                    SupervisionStrategy::OneForAll => "Decision.STOP",
+                    SupervisionStrategy::RestForOne => "Decision.ESCALATE",
+                    SupervisionStrategy::Custom(name) => name,
+                };
+                self.braced(&format!("SupervisionStrategy {} ", strategy), children, "");
+            },
+            Node::STMTransaction { variables, operations } => {
+                let items: Vec<Node> = variables.iter().chain(operations).cloned().collect();
+                self.braced("atomic ", &items, "");
+            },
+            Node::TVar { name, value_type, initial_value } => {
+                let mut line = format!("TVar {}<{}>", name, type_to_source(value_type));
+                if let Some(value) = initial_value {
+                    line.push_str(&format!(" = {}", expression(value)));
+                }
+                self.line(&line);
+            },
+            Node::Atomic { body } => self.body("atomic ", body, ""),
+            Node::Scope { body } => self.body("scope ", body, ""),
+            Node::Spawn(task) => self.line(&format!("spawn {};", expression(task))),
+            Node::DoWhile { body, condition } => self.body("do ", body, &format!(" while ({});", expression(condition))),
+            Node::Break => self.line("break;"),
+            Node::Continue => self.line("continue;"),
+            Node::MacroDefinition { name, params, body } => {
+                self.body(&format!("macro {}({}) ", name, params.join(", ")), body, "");
+            },
+            Node::MacroCall { name, arguments, .. } => self.line(&format!("{}!({});", name, expressions(arguments))),
+            Node::InlineIr(ir) => self.line(&format!("llvm {{ {} }}", string_literal(ir))),
+            Node::Error { span, consumed_tokens } => match self.source.and_then(|source| source.get(span.start..span.end)) {
+                Some(text) => self.line(text.trim()),
+                None => self.line(&format!("/* {} tokens that didn't parse */", consumed_tokens)),
+            },
+            expression @ (Node::Binary { .. }
+            | Node::Unary { .. }
+            | Node::Call { .. }
+            | Node::Member { .. }
+            | Node::Index { .. }
+            | Node::TypeTest { .. }
+            | Node::SafeCast { .. }
+            | Node::Array { .. }
+            | Node::Map { .. }
+            | Node::Object { .. }
+            | Node::Await(_)
+            | Node::Identifier(_)
+            | Node::IntLiteral(_)
+            | Node::UIntLiteral(_)
+            | Node::UInt256Literal(_)
+            | Node::FloatLiteral(_)
+            | Node::StringLiteral(_)
+            | Node::TemplateString { .. }
+            | Node::BooleanLiteral(_)
+            | Node::NullLiteral
+            | Node::This
+            | Node::Super
+            | Node::Transaction { .. }) => self.line(&format!("{};", self::expression(expression))),
+        }
+    }
+
+    fn if_statement(&mut self, condition: &Node, then_branch: &Node, else_branch: Option<&Node>, prefix: &str) {
+        let head = format!("{}if ({}) ", prefix, expression(condition));
+        let Some(else_branch) = else_branch else {
+            return self.body(&head, then_branch, "");
+        };
+        self.body(&head, then_branch, "");
+        self.join_last_line();
+        match else_branch.unlocated() {
+            Node::If { condition, then_branch, else_branch } => {
+                self.if_statement(condition, then_branch, else_branch.as_deref(), "else ");
+            },
+            other => self.body("else ", other, ""),
+        }
+    }
+
+    /// An attribute on the line before the declaration it applies to.
+    fn prefixed(&mut self, attribute: &str, declaration: &Node) {
+        self.line(attribute);
+        self.statement(declaration);
+    }
+
+    /// Makes the next line continue the last one: `} else {`.
+    fn join_last_line(&mut self) {
+        self.out.pop();
+        self.out.push(' ');
+        self.continued = true;
+    }
+}
+
+fn is_expression(node: &Node) -> bool {
+    matches!(node,
+        Node::Binary { .. }
+        | Node::Unary { .. }
+        | Node::Call { .. }
+        | Node::Member { .. }
+        | Node::Index { .. }
+        | Node::TypeTest { .. }
+        | Node::SafeCast { .. }
+        | Node::Array { .. }
+        | Node::Map { .. }
+        | Node::Object { .. }
+        | Node::Await(_)
+        | Node::Identifier(_)
+        | Node::IntLiteral(_)
+        | Node::UIntLiteral(_)
+        | Node::UInt256Literal(_)
+        | Node::FloatLiteral(_)
+        | Node::StringLiteral(_)
+        | Node::TemplateString { .. }
+        | Node::BooleanLiteral(_)
+        | Node::NullLiteral
+        | Node::This
+        | Node::Super
+        | Node::Transaction { .. })
+}
+
+fn is_expression_statement(node: &Node) -> bool {
+    matches!(node, Node::Block(items) if items.len() == 1 && is_expression(&items[0]))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn let_source(node: &Node) -> String {
+    match node {
+        Node::Let { name, type_annotation, initializer, .. } => {
+            let mut source = format!("let {}", name);
+            if let Some(ty) = type_annotation {
+                source.push_str(&format!(": {}", type_to_source(ty)));
+            }
+            if let Some(initializer) = initializer {
+                source.push_str(&format!(" = {}", expression(initializer)));
+            }
+            source
+        },
+        other => expression(other),
+    }
+}
+
+fn for_initializer(node: &Node) -> String {
+    match node.unlocated() {
+        Node::Block(items) if is_expression_statement(node.unlocated()) => expression(&items[0]),
+        other => let_source(other),
+    }
+}
+
+/// The keyword of a modifier; `Unchecked`, `Concurrent` and `Export` are
+/// written as attributes.
+fn modifier_source(modifier: &FunctionModifier) -> Option<&'static str> {
+    Some(match modifier {
+        FunctionModifier::Public => "public",
+        FunctionModifier::Private => "private",
+        FunctionModifier::Protected => "protected",
+        FunctionModifier::Static => "static",
+        FunctionModifier::Async => "async",
+        FunctionModifier::View => "view",
+        FunctionModifier::Pure => "pure",
+        FunctionModifier::Payable => "payable",
+        FunctionModifier::Abstract => "abstract",
+        FunctionModifier::Unchecked | FunctionModifier::Concurrent | FunctionModifier::Export => return None,
+    })
+}
+
+fn parameter(param: &Parameter) -> String {
+    format!("{}: {}", param.name, type_to_source(&param.type_annotation))
+}
+
+fn parameters(params: &[Parameter]) -> String {
+    params.iter().map(parameter).collect::<Vec<_>>().join(", ")
+}
+
+fn expressions(nodes: &[Node]) -> String {
+    nodes.iter().map(expression).collect::<Vec<_>>().join(", ")
+}
+
+fn string_literal(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn template_string(parts: &[Node]) -> String {
+    let mut literal = String::from("`");
+    for part in parts {
+        match part.unlocated() {
+            Node::StringLiteral(text) => {
+                for c in text.chars() {
+                    match c {
+                        '`' | '$' | '\\' => {
+                            literal.push('\\');
+                            literal.push(c);
+                        },
+                        '\n' => literal.push_str("\\n"),
+                        '\r' => literal.push_str("\\r"),
+                        '\t' => literal.push_str("\\t"),
+                        c => literal.push(c),
+                    }
+                }
+            },
+            expression_part => {
+                literal.push_str("${");
+                literal.push_str(&expression(expression_part));
+                literal.push('}');
+            },
+        }
+    }
+    literal.push('`');
+    literal
+}
+
+/// Binding strength, matching the parser's levels. `&&` and `||` share a
+/// level there, and every level is left-associative.
+fn precedence(operator: &BinaryOp) -> u8 {
+    match operator {
+        BinaryOp::NullCoalesce => 0,
+        BinaryOp::And | BinaryOp::Or => 1,
+        BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => 2,
+        BinaryOp::BitOr => 3,
+        BinaryOp::BitXor => 4,
+        BinaryOp::BitAnd => 5,
+        BinaryOp::Shl | BinaryOp::Shr => 6,
+        BinaryOp::Add | BinaryOp::Sub => 7,
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 8,
+    }
+}
+
+fn operator_source(operator: &BinaryOp) -> &'static str {
+    match operator {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::Eq => "==",
+        BinaryOp::NotEq => "!=",
+        BinaryOp::Lt => "<",
+        BinaryOp::LtEq => "<=",
+        BinaryOp::Gt => ">",
+        BinaryOp::GtEq => ">=",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+        BinaryOp::NullCoalesce => "??",
+        BinaryOp::BitAnd => "&",
+        BinaryOp::BitOr => "|",
+        BinaryOp::BitXor => "^",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr => ">>",
+    }
+}
+
+fn expression(node: &Node) -> String {
+    match node {
+        Node::Located { node, .. } => expression(node),
+        Node::Binary { left, operator, right } => {
+            let level = precedence(operator);
+            let operand = |node: &Node, min: u8| match node.unlocated() {
+                Node::Binary { operator, .. } if precedence(operator) < min => format!("({})", expression(node)),
+                _ => expression(node),
+            };
+            // Left-associative: an equal-level right operand needs parentheses
+            format!("{} {} {}", operand(left, level), operator_source(operator), operand(right, level + 1))
+        },
+        Node::Unary { operator: UnaryOp::PostIncrement, operand } => format!("{}++", member_object(operand)),
+        Node::Unary { operator: UnaryOp::PostDecrement, operand } => format!("{}--", member_object(operand)),
+        Node::Unary { operator, operand } => {
+            let operator = match operator {
+                UnaryOp::Minus => "-",
+                UnaryOp::Not => "!",
+                UnaryOp::Increment => "++",
+                UnaryOp::Decrement => "--",
+                UnaryOp::BitNot => "~",
+                UnaryOp::PostIncrement | UnaryOp::PostDecrement => unreachable!("printed above"),
+            };
+            format!("{}{}", operator, postfix(operand))
+        },
+        Node::Call { callee, arguments } => format!("{}({})", member_object(callee), expressions(arguments)),
+        Node::Member { object, property } => format!("{}.{}", member_object(object), property),
+        Node::Index { object, index, .. } => format!("{}[{}]", member_object(object), expression(index)),
+        Node::TypeTest { value, target } => format!("{} is {}", postfix(value), type_to_source(target)),
+        Node::SafeCast { value, target } => format!("{} as? {}", postfix(value), type_to_source(target)),
+        Node::Array { elements } => format!("[{}]", expressions(elements)),
+        Node::Map { entries } if entries.is_empty() => "{}".to_string(),
+        Node::Map { entries } => {
+            let entries: Vec<String> = entries.iter()
+                .map(|(key, value)| format!("{}: {}", expression(key), expression(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        },
+        Node::Object { fields } if fields.is_empty() => "{}".to_string(),
+        Node::Object { fields } => {
+            let fields: Vec<String> = fields.iter()
+                .map(|(name, value)| format!("{}: {}", name, expression(value)))
+                .collect();
+            format!("{{ {} }}", fields.join(", "))
+        },
+        Node::Await(value) => format!("await {}", postfix(value)),
+        Node::Identifier(name) => name.to_string(),
+        Node::IntLiteral(value) => value.to_string(),
+        // The suffix keeps it a uint when parsed again
+        Node::UIntLiteral(value) => format!("{}u", value),
+        Node::UInt256Literal(value) => value.clone(),
+        // `{:?}` keeps the `.0` of whole numbers
+        Node::FloatLiteral(value) => format!("{:?}", value),
+        Node::StringLiteral(value) => string_literal(value),
+        Node::TemplateString { parts } => template_string(parts),
+        Node::BooleanLiteral(value) => value.to_string(),
+        Node::NullLiteral => "null".to_string(),
+        Node::This => "this".to_string(),
+        Node::Super => "super".to_string(),
+        Node::Transaction { from, to, amount } => {
+            format!("transaction {{ {} => {}: {} }}", expression(from), expression(to), expression(amount))
+        },
+        // Statements in expression position, e.g. a `for` increment
+        other => {
+            let mut printer = Printer::default();
+            printer.statement(other);
+            printer.out.trim_end().trim_end_matches(';').to_string()
+        },
+    }
+}
+
+/// Operands of unary operators, `await`, `is` and `as?`, which bind tighter
+/// than any binary operator.
+fn postfix(node: &Node) -> String {
+    match node.unlocated() {
+        Node::Binary { .. } | Node::Unary { .. } | Node::Await(_) | Node::TypeTest { .. } | Node::SafeCast { .. } => {
+            format!("({})", expression(node))
+        },
+        _ => expression(node),
+    }
+}
+
+/// Callees, member objects and indexed objects: the parser only takes
+/// atoms and member and index chains there.
+fn member_object(node: &Node) -> String {
+    match node.unlocated() {
+        Node::Member { .. }
+        | Node::Index { .. }
+        | Node::Identifier(_)
+        | Node::StringLiteral(_)
+        | Node::TemplateString { .. }
+        | Node::BooleanLiteral(_)
+        | Node::NullLiteral
+        | Node::This
+        | Node::Super => expression(node),
+        _ => format!("({})", expression(node)),
+    }
+}
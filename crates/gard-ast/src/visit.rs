@@ -0,0 +1,225 @@
+use crate::Node;
+
+impl Node {
+    /// The nodes directly inside this one, in source order.
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Program(nodes)
+            | Node::Block(nodes)
+            | Node::Class { members: nodes, .. }
+            | Node::Contract { members: nodes, .. }
+            | Node::Array { elements: nodes }
+            | Node::Behavior { handlers: nodes, .. }
+            | Node::Supervise { children: nodes, .. }
+            | Node::TemplateString { parts: nodes }
+            | Node::MacroCall { arguments: nodes, .. } => nodes.iter().collect(),
+            Node::Function { body, .. } | Node::Constructor { body, .. } => vec![body],
+            Node::While { condition, body } => vec![condition, body],
+            Node::Let { initializer, .. } => initializer.iter().map(|node| node.as_ref()).collect(),
+            Node::If { condition, then_branch, else_branch } => {
+                let mut children = vec![condition.as_ref(), then_branch.as_ref()];
+                children.extend(else_branch.as_deref());
+                children
+            },
+            Node::For { initializer, condition, increment, body } => {
+                let mut children: Vec<&Node> = [initializer, condition, increment].into_iter()
+                    .flat_map(|node| node.as_deref())
+                    .collect();
+                children.push(body);
+                children
+            },
+            Node::Foreach { collection, body, .. } => vec![collection, body],
+            Node::Match { value, cases } => {
+                let mut children = vec![value.as_ref()];
+                for case in cases {
+                    children.push(&case.pattern);
+                    children.push(&case.body);
+                }
+                children
+            },
+            Node::Return(value) => value.iter().map(|node| node.as_ref()).collect(),
+            Node::Throw(node)
+            | Node::Await(node)
+            | Node::Unary { operand: node, .. }
+            | Node::TypeTest { value: node, .. }
+            | Node::SafeCast { value: node, .. }
+            | Node::Member { object: node, .. }
+            | Node::WasmExport { declaration: node, .. }
+            | Node::Derive { declaration: node, .. }
+            | Node::Cfg { declaration: node, .. }
+            | Node::Receive { body: node, .. }
+            | Node::Become { behavior: node }
+            | Node::Atomic { body: node }
+            | Node::Scope { body: node }
+            | Node::Spawn(node)
+            | Node::Const { value: node, .. }
+            | Node::TaskLocal { initializer: node, .. }
+            | Node::CatchClause { body: node, .. }
+            | Node::MacroDefinition { body: node, .. }
+            | Node::Located { node, .. } => vec![node],
+            Node::Assertion { condition, message, .. } => {
+                let mut children = vec![condition.as_ref()];
+                children.extend(message.as_deref());
+                children
+            },
+            Node::Try { body, catch_clauses, finally } => {
+                let mut children = vec![body.as_ref()];
+                children.extend(catch_clauses);
+                children.extend(finally.as_deref());
+                children
+            },
+            Node::Binary { left, right, .. } | Node::Index { object: left, index: right, .. } => vec![left, right],
+            Node::Call { callee, arguments } => {
+                let mut children = vec![callee.as_ref()];
+                children.extend(arguments);
+                children
+            },
+            Node::Attribute { arguments, declaration, .. } => {
+                let mut children: Vec<&Node> = arguments.iter().collect();
+                children.push(declaration);
+                children
+            },
+            Node::Map { entries } => entries.iter().flat_map(|(key, value)| [key, value]).collect(),
+            Node::Object { fields } => fields.iter().map(|(_, value)| value).collect(),
+            Node::Transaction { from, to, amount } => vec![from, to, amount],
+            Node::StorageSlot { slot, declaration } => vec![slot, declaration],
+            Node::Actor { mailbox, behavior, members, .. } => {
+                let mut children = vec![mailbox.as_ref(), behavior.as_ref()];
+                children.extend(members);
+                children
+            },
+            Node::STMTransaction { variables, operations } => variables.iter().chain(operations).collect(),
+            Node::TVar { initial_value, .. } => initial_value.iter().map(|node| node.as_ref()).collect(),
+            Node::DoWhile { body, condition } => vec![body, condition],
+            Node::Identifier(_)
+            | Node::IntLiteral(_)
+            | Node::UIntLiteral(_)
+            | Node::UInt256Literal(_)
+            | Node::FloatLiteral(_)
+            | Node::StringLiteral(_)
+            | Node::BooleanLiteral(_)
+            | Node::NullLiteral
+            | Node::This
+            | Node::Super
+            | Node::Event { .. }
+            | Node::Union { .. }
+            | Node::Interface { .. }
+            | Node::WasmImport { .. }
+            | Node::InlineIr(_)
+            | Node::Break
+            | Node::Continue
+            | Node::Error { .. } => vec![],
+        }
+    }
+
+    /// Mutable access to `children`, for passes that rewrite the tree.
+    pub fn children_mut(&mut self) -> Vec<&mut Node> {
+        match self {
+            Node::Program(nodes)
+            | Node::Block(nodes)
+            | Node::Class { members: nodes, .. }
+            | Node::Contract { members: nodes, .. }
+            | Node::Array { elements: nodes }
+            | Node::Behavior { handlers: nodes, .. }
+            | Node::Supervise { children: nodes, .. }
+            | Node::TemplateString { parts: nodes }
+            | Node::MacroCall { arguments: nodes, .. } => nodes.iter_mut().collect(),
+            Node::Function { body, .. } | Node::Constructor { body, .. } => vec![body],
+            Node::While { condition, body } => vec![condition, body],
+            Node::Let { initializer, .. } => initializer.iter_mut().map(|node| node.as_mut()).collect(),
+            Node::If { condition, then_branch, else_branch } => {
+                let mut children = vec![condition.as_mut(), then_branch.as_mut()];
+                children.extend(else_branch.as_deref_mut());
+                children
+            },
+            Node::For { initializer, condition, increment, body } => {
+                let mut children: Vec<&mut Node> = [initializer, condition, increment].into_iter()
+                    .flat_map(|node| node.as_deref_mut())
+                    .collect();
+                children.push(body);
+                children
+            },
+            Node::Foreach { collection, body, .. } => vec![collection, body],
+            Node::Match { value, cases } => {
+                let mut children = vec![value.as_mut()];
+                for case in cases.iter_mut() {
+                    children.push(&mut case.pattern);
+                    children.push(&mut case.body);
+                }
+                children
+            },
+            Node::Return(value) => value.iter_mut().map(|node| node.as_mut()).collect(),
+            Node::Throw(node)
+            | Node::Await(node)
+            | Node::Unary { operand: node, .. }
+            | Node::TypeTest { value: node, .. }
+            | Node::SafeCast { value: node, .. }
+            | Node::Member { object: node, .. }
+            | Node::WasmExport { declaration: node, .. }
+            | Node::Derive { declaration: node, .. }
+            | Node::Cfg { declaration: node, .. }
+            | Node::Receive { body: node, .. }
+            | Node::Become { behavior: node }
+            | Node::Atomic { body: node }
+            | Node::Scope { body: node }
+            | Node::Spawn(node)
+            | Node::Const { value: node, .. }
+            | Node::TaskLocal { initializer: node, .. }
+            | Node::CatchClause { body: node, .. }
+            | Node::MacroDefinition { body: node, .. }
+            | Node::Located { node, .. } => vec![node],
+            Node::Assertion { condition, message, .. } => {
+                let mut children = vec![condition.as_mut()];
+                children.extend(message.as_deref_mut());
+                children
+            },
+            Node::Try { body, catch_clauses, finally } => {
+                let mut children = vec![body.as_mut()];
+                children.extend(catch_clauses);
+                children.extend(finally.as_deref_mut());
+                children
+            },
+            Node::Binary { left, right, .. } | Node::Index { object: left, index: right, .. } => vec![left, right],
+            Node::Call { callee, arguments } => {
+                let mut children = vec![callee.as_mut()];
+                children.extend(arguments);
+                children
+            },
+            Node::Attribute { arguments, declaration, .. } => {
+                let mut children: Vec<&mut Node> = arguments.iter_mut().collect();
+                children.push(declaration);
+                children
+            },
+            Node::Map { entries } => entries.iter_mut().flat_map(|(key, value)| [key, value]).collect(),
+            Node::Object { fields } => fields.iter_mut().map(|(_, value)| value).collect(),
+            Node::Transaction { from, to, amount } => vec![from, to, amount],
+            Node::StorageSlot { slot, declaration } => vec![slot, declaration],
+            Node::Actor { mailbox, behavior, members, .. } => {
+                let mut children = vec![mailbox.as_mut(), behavior.as_mut()];
+                children.extend(members);
+                children
+            },
+            Node::STMTransaction { variables, operations } => variables.iter_mut().chain(operations).collect(),
+            Node::TVar { initial_value, .. } => initial_value.iter_mut().map(|node| node.as_mut()).collect(),
+            Node::DoWhile { body, condition } => vec![body, condition],
+            Node::Identifier(_)
+            | Node::IntLiteral(_)
+            | Node::UIntLiteral(_)
+            | Node::UInt256Literal(_)
+            | Node::FloatLiteral(_)
+            | Node::StringLiteral(_)
+            | Node::BooleanLiteral(_)
+            | Node::NullLiteral
+            | Node::This
+            | Node::Super
+            | Node::Event { .. }
+            | Node::Union { .. }
+            | Node::Interface { .. }
+            | Node::WasmImport { .. }
+            | Node::InlineIr(_)
+            | Node::Break
+            | Node::Continue
+            | Node::Error { .. } => vec![],
+        }
+    }
+}
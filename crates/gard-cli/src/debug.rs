@@ -0,0 +1,137 @@
+//! Line-oriented front end for the interpreter's debugger, used by `gard debug`.
+
+use gard_ast::SourceMap;
+use gard_interp::debugger::{DebugConfig, PauseReason, PausedState};
+use gard_interp::{DebugHandler, StepCommand, Value};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+  c, continue       run to the next breakpoint
+  s, step           step into calls
+  n, next           step over calls
+  f, finish         run until the current function returns
+  b, break <line>   set a breakpoint
+  d, delete <line>  remove a breakpoint
+  bt, backtrace     show the call stack
+  l, locals         show the variables of the current function
+  p, print <name>   show a variable
+  w, watch <name>   show a variable at every pause
+  unwatch <name>    stop watching a variable
+  list              show the source around the current line
+  q, quit           stop the program";
+
+pub struct Terminal<R, W> {
+    source_map: SourceMap,
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Terminal<R, W> {
+    pub fn new(source_map: SourceMap, input: R, output: W) -> Self {
+        Self { source_map, input, output }
+    }
+
+    fn prompt(&mut self, state: &PausedState, config: &mut DebugConfig) -> io::Result<StepCommand> {
+        let reason = match state.reason {
+            PauseReason::Entry => "Paused on entry",
+            PauseReason::Breakpoint => "Breakpoint",
+            PauseReason::Step => "Stepped",
+        };
+        writeln!(self.output, "{} at {}:{}:{} in {}", reason, state.file, state.location.line, state.location.column, state.frames[0].function)?;
+        self.list(state.location.line)?;
+        for (name, value) in &state.watches {
+            self.show_variable(name, value.as_ref())?;
+        }
+
+        loop {
+            write!(self.output, "(gard) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(StepCommand::Terminate);
+            }
+
+            let mut words = line.split_whitespace();
+            let (command, argument) = (words.next().unwrap_or(""), words.next());
+            match (command, argument) {
+                ("", _) => {},
+                ("c" | "continue", _) => return Ok(StepCommand::Continue),
+                ("s" | "step", _) => return Ok(StepCommand::StepInto),
+                ("n" | "next", _) => return Ok(StepCommand::StepOver),
+                ("f" | "finish", _) => return Ok(StepCommand::StepOut),
+                ("q" | "quit", _) => return Ok(StepCommand::Terminate),
+                ("b" | "break" | "d" | "delete", Some(line)) => match line.parse::<usize>() {
+                    Ok(line) if matches!(command, "b" | "break") => {
+                        config.add_breakpoint(&state.file, line);
+                        writeln!(self.output, "Breakpoint at {}:{}", state.file, line)?;
+                    },
+                    Ok(line) => {
+                        if !config.remove_breakpoint(&state.file, line) {
+                            writeln!(self.output, "No breakpoint at {}:{}", state.file, line)?;
+                        }
+                    },
+                    Err(_) => writeln!(self.output, "Invalid line number: {}", line)?,
+                },
+                ("bt" | "backtrace", _) => {
+                    for (depth, frame) in state.frames.iter().enumerate() {
+                        match frame.location {
+                            Some(location) => writeln!(self.output, "#{} {} at {}:{}", depth, frame.function, state.file, location.line)?,
+                            None => writeln!(self.output, "#{} {}", depth, frame.function)?,
+                        }
+                    }
+                },
+                ("l" | "locals", _) => {
+                    for (name, value) in &state.frames[0].locals {
+                        self.show_variable(name, Some(value))?;
+                    }
+                },
+                ("p" | "print", Some(name)) => self.show_variable(name, variable(state, name))?,
+                ("w" | "watch", Some(name)) => {
+                    config.add_watch(name);
+                    self.show_variable(name, variable(state, name))?;
+                },
+                ("unwatch", Some(name)) => {
+                    if !config.remove_watch(name) {
+                        writeln!(self.output, "Not watching {}", name)?;
+                    }
+                },
+                ("list", _) => self.list(state.location.line)?,
+                ("h" | "help", _) => writeln!(self.output, "{}", HELP)?,
+                _ => writeln!(self.output, "Unknown command: {} (try help)", line.trim())?,
+            }
+        }
+    }
+
+    /// Shows two lines of context either side of `current`.
+    fn list(&mut self, current: usize) -> io::Result<()> {
+        for number in current.saturating_sub(2).max(1)..=current + 2 {
+            if let Some(text) = self.source_map.line(number) {
+                let marker = if number == current { ">" } else { " " };
+                writeln!(self.output, "{} {:>4} | {}", marker, number, text)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn show_variable(&mut self, name: &str, value: Option<&Value>) -> io::Result<()> {
+        match value {
+            Some(value) => writeln!(self.output, "{} = {}", name, value),
+            None => writeln!(self.output, "{} is not in scope", name),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> DebugHandler for Terminal<R, W> {
+    fn paused(&mut self, state: &PausedState, config: &mut DebugConfig) -> StepCommand {
+        // There's no one left to ask once the terminal is gone
+        self.prompt(state, config).unwrap_or(StepCommand::Terminate)
+    }
+}
+
+/// A variable of the current function, or failing that a global.
+fn variable<'a>(state: &'a PausedState, name: &str) -> Option<&'a Value> {
+    let frames = [state.frames.first(), state.frames.last()];
+    frames.into_iter().flatten()
+        .find_map(|frame| frame.locals.iter().find(|(local, _)| local == name))
+        .map(|(_, value)| value)
+}
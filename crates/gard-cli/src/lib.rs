@@ -1,8 +1,1231 @@
-use clap::Parser;
+pub mod debug;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use gard_ast::{Locale, Node, SourceMap};
+use gard_compiler::cfg::{self, CfgSet};
+use gard_compiler::checker::TypeChecker;
+use gard_compiler::index::{self, Index};
+use gard_compiler::library::{Library, Runtime};
+use gard_compiler::machine;
+use gard_compiler::plugin::{LintLevel, Registry};
+use gard_compiler::profile::{self, Profile};
+use gard_compiler::{CodegenOptions, LibraryKind, bounds, cheader, compdb, consteval, derive, destructors, edition, graph, macros, nested, prebuild, proto, refactor, rename, solidity, storage, typescript};
+use gard_interp::checkpoint::Checkpoint;
+use gard_interp::guardian::Guardian;
+use gard_interp::introspect::Snapshot;
+use gard_interp::{replay, simulate, Debugger, Interpreter, Metrics, RuntimeError, SourceWatcher};
+use gard_lexer::{Lexer, LexerError, Token, TokenWithSpan};
+use gard_parser::{GardParser, GardParserTrait};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+/// Where symbol indexes are cached between builds
+const INDEX_DIR: &str = ".gard/index";
+
+/// Interpreter steps between pushes of metrics to an OTLP collector
+const OTLP_INTERVAL: u64 = 1_000_000;
+
+/// Interpreter steps between snapshots for `gard run --introspect`
+const INTROSPECT_INTERVAL: u64 = 10_000;
+
+/// How often `gard run --guardian restart` restarts a failing task before
+/// giving up
+const MAX_RESTARTS: u32 = 3;
+
+/// How often `gard top` redraws
+const TOP_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
     #[arg(short, long)]
-    pub file: String,
-} 
\ No newline at end of file
+    pub file: Option<String>,
+
+    /// What to generate from --file
+    #[arg(long, value_enum)]
+    pub emit: Option<Emit>,
+
+    /// Where to write --emit output (defaults to stdout for source output)
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Features that enable `@cfg(feature = "..")` declarations
+    #[arg(long = "feature", global = true, value_delimiter = ',')]
+    pub features: Vec<String>,
+
+    /// Compiler plugin library to load
+    #[arg(long = "plugin", global = true)]
+    pub plugins: Vec<String>,
+
+    /// Language of diagnostics, like `es`; defaults to the one GARD_LANG
+    /// or LANG names
+    #[arg(long, global = true)]
+    pub lang: Option<String>,
+
+    /// Lint to silence, unless an attribute sets its level
+    #[arg(short = 'A', long = "allow", global = true, value_delimiter = ',')]
+    pub allow: Vec<String>,
+
+    /// Lint to warn about, unless an attribute sets its level
+    #[arg(short = 'W', long = "warn", global = true, value_delimiter = ',')]
+    pub warn: Vec<String>,
+
+    /// Lint to fail the build on, unless an attribute sets its level. Wins
+    /// over -W, which wins over -A.
+    #[arg(short = 'D', long = "deny", global = true, value_delimiter = ',')]
+    pub deny: Vec<String>,
+
+    /// Trap on `int` overflow, reporting where it happened, instead of
+    /// wrapping. `uint256` and EVM arithmetic is always checked.
+    #[arg(long, global = true)]
+    pub overflow_checks: bool,
+
+    /// How native code is relocated, over gard.toml's `[codegen]`
+    #[arg(long, global = true, value_enum)]
+    pub relocation_model: Option<RelocationModel>,
+
+    /// How far apart native code and data may be, over gard.toml's
+    /// `[codegen]`; defaults to the target's usual model
+    #[arg(long, global = true, value_enum)]
+    pub code_model: Option<CodeModel>,
+
+    /// Optimize native libraries as they're linked, over gard.toml's
+    /// `[codegen]`; `--lto` alone is full LTO. Needs clang and lld.
+    #[arg(long, global = true, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "full")]
+    pub lto: Option<Lto>,
+
+    /// Split native libraries into this many codegen units, compiled in
+    /// parallel, over gard.toml's `[codegen]`; defaults to 1
+    #[arg(long, global = true, value_parser = clap::value_parser!(u16).range(1..))]
+    pub codegen_units: Option<u16>,
+
+    /// Print how long each stage of the build took, and the memory held
+    /// after it, to stderr when the command ends
+    #[arg(long, global = true, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "text")]
+    pub time_passes: Option<TimePasses>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Emit {
+    /// Solidity source for each contract
+    Solidity,
+    /// wasm32 object file; contracts target a wasm contract host, other
+    /// programs get a .d.ts and JS loader next to the output
+    Wasm,
+    /// Gard source after macro, derive, cfg and plugin expansion
+    Expanded,
+    /// C header declaring the `@export`ed functions
+    Header,
+    /// Static library for C programs, with its header next to the output
+    Staticlib,
+    /// Shared library for C programs, with its header next to the output
+    Dylib,
+    /// The libraries the `[library]` table of gard.toml lists, with their
+    /// header, into the --output directory
+    Library,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check that an upgraded contract keeps the storage layout of the deployed one
+    StorageDiff {
+        old: String,
+        new: String,
+    },
+    /// Run a program in the interpreter
+    Run {
+        file: String,
+
+        /// Reload functions when the file changes, without restarting
+        #[arg(long)]
+        watch: bool,
+
+        /// Also report mutexes acquired in inconsistent orders, which can
+        /// deadlock under another schedule
+        #[arg(long)]
+        debug_runtime: bool,
+
+        /// Print the task runtime's metrics to stderr when the program ends
+        #[arg(long)]
+        metrics: bool,
+
+        /// Push the metrics to an OpenTelemetry collector while the program
+        /// runs, like `http://localhost:4318/v1/metrics`
+        #[arg(long, value_name = "URL")]
+        otlp: Option<String>,
+
+        /// Log the order tasks start and receive messages in to a file, even
+        /// when the program fails, to replay the run with --replay
+        #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+        record: Option<String>,
+
+        /// Run the tasks in the order of a log written by --record
+        #[arg(long, value_name = "FILE")]
+        replay: Option<String>,
+
+        /// Serve snapshots of the task runtime while the program runs, as
+        /// text at `/` and as JSON at `/json`, for `gard top`
+        #[arg(long, value_name = "ADDRESS")]
+        introspect: Option<String>,
+
+        /// What happens when a task the program spawns itself fails
+        #[arg(long, value_enum, default_value = "shutdown")]
+        guardian: GuardianPolicy,
+
+        /// Resume the program's globals and channels from a file, if it
+        /// exists, and save them there when the program ends, for scripts
+        /// that carry their state from one run to the next
+        #[arg(long, value_name = "FILE")]
+        checkpoint: Option<String>,
+    },
+    /// Run each function marked `@concurrent` under many schedules, picked
+    /// at random, to find bugs that depend on the order tasks run in
+    Simulate {
+        file: String,
+
+        /// How many seeds to try for each test
+        #[arg(long, default_value_t = 100)]
+        seeds: u64,
+
+        /// Run only this seed, printing the output and the schedule, to
+        /// reproduce a failure
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Show the tasks of a program run with `--introspect`, redrawn each
+    /// second until it ends
+    Top {
+        /// The address it serves on, like `localhost:9898`
+        address: String,
+    },
+    /// Run a program in the interpreter under an interactive debugger
+    Debug {
+        file: String,
+
+        /// Line to stop at; without any, the debugger stops on entry
+        #[arg(short, long)]
+        breakpoint: Vec<usize>,
+    },
+    /// Serve the Debug Adapter Protocol on stdin/stdout for editors
+    Dap,
+    /// List the references to a symbol, like `Token.transfer`
+    Refs {
+        file: String,
+        symbol: String,
+    },
+    /// Rename a class, function, field or local (`main.total`) everywhere
+    Rename {
+        file: String,
+        symbol: String,
+        new_name: String,
+
+        /// Rewrite the file instead of printing the edits
+        #[arg(long)]
+        write: bool,
+    },
+    /// Move a run of statements into a new function and call it instead
+    ExtractFunction {
+        file: String,
+        name: String,
+
+        /// First line of the statements
+        #[arg(long)]
+        from: usize,
+
+        /// Last line of the statements
+        #[arg(long)]
+        to: usize,
+
+        /// Rewrite the file instead of printing the edits
+        #[arg(long)]
+        write: bool,
+    },
+    /// Replace a local that is used once with its initializer
+    InlineVariable {
+        file: String,
+
+        /// Line of the `let`
+        line: usize,
+
+        /// Rewrite the file instead of printing the edits
+        #[arg(long)]
+        write: bool,
+    },
+    /// Print the dependency and call graphs of a program
+    Graph {
+        file: String,
+
+        #[arg(long, value_enum, default_value = "dot")]
+        format: GraphFormat,
+    },
+    /// Explain a diagnostic code, like `gard explain L0002`
+    Explain {
+        code: String,
+    },
+    /// Generate Gard declarations from an interface definition, like
+    /// `gard bindgen proto orders.proto`
+    Bindgen {
+        #[arg(value_enum)]
+        idl: Idl,
+        file: String,
+
+        /// Write the declarations here instead of printing them
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Idl {
+    /// A proto3 file's messages, enums and services
+    Proto,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz digraphs
+    Dot,
+    /// Adjacency lists
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelocationModel {
+    /// Fixed addresses, for executables that aren't position-independent
+    Static,
+    /// Position-independent code, for shared libraries and anything else
+    Pic,
+    /// Position-independent code for executables only
+    Pie,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeModel {
+    Small,
+    Kernel,
+    Medium,
+    Large,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lto {
+    /// Optimize each module apart, with summaries of the others
+    Thin,
+    /// Merge the modules into one and optimize that
+    Full,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimePasses {
+    /// A table
+    Text,
+    /// One JSON object, to compare builds with
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GuardianPolicy {
+    /// End the program with the task's error
+    #[default]
+    Shutdown,
+    /// Warn and let the other tasks go on
+    Stop,
+    /// Warn and run the task again, a few times at most
+    Restart,
+}
+
+/// How `gard run` runs a program, besides printing its output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunOptions {
+    pub watch: bool,
+    pub debug_runtime: bool,
+    pub metrics: bool,
+    /// The OTLP collector to push metrics to
+    pub otlp: Option<String>,
+    pub schedule: Option<ScheduleLog>,
+    /// The address to serve snapshots of the task runtime on
+    pub introspect: Option<String>,
+    pub guardian: GuardianPolicy,
+    /// The file to resume from and save to
+    pub checkpoint: Option<String>,
+}
+
+/// The file `gard run` records the task schedule to or replays it from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleLog {
+    Record(String),
+    Replay(String),
+}
+
+/// What every command that compiles a program shares.
+pub struct Build {
+    pub features: Vec<String>,
+    pub plugins: Registry,
+    pub overflow_checks: bool,
+    /// Set on the command line, over the manifest's
+    pub relocation_model: Option<machine::RelocationModel>,
+    pub code_model: Option<machine::CodeModel>,
+    pub lto: Option<machine::Lto>,
+    pub codegen_units: Option<usize>,
+    /// Set by `--time-passes`
+    pub profile: Option<Arc<Profile>>,
+    /// The project's gard.toml
+    pub manifest: edition::Manifest,
+    pub locale: Locale,
+    /// The plugin libraries loaded into `plugins`
+    pub plugin_paths: Vec<String>,
+    /// The command-line flags that change what's built, for the
+    /// compilation database to record
+    pub flags: Vec<String>,
+}
+
+impl Build {
+    pub fn new(features: Vec<String>, plugins: &[String]) -> Result<Self, String> {
+        let mut registry = Registry::new();
+        registry.add_lint("dead_code", index::dead_code_lint);
+        registry.add_lint("unused_variable", index::unused_variable_lint);
+        registry.add_attribute(bounds::UNCHECKED, bounds::unchecked_attribute);
+        registry.add_attribute(simulate::ATTRIBUTE, simulate::concurrent_attribute);
+        registry.add_attribute(cheader::ATTRIBUTE, cheader::export_attribute);
+        for path in plugins {
+            registry.load(Path::new(path))?;
+        }
+        Ok(Self {
+            features,
+            plugins: registry,
+            overflow_checks: false,
+            relocation_model: None,
+            code_model: None,
+            lto: None,
+            codegen_units: None,
+            profile: None,
+            manifest: edition::Manifest::default(),
+            locale: Locale::default(),
+            plugin_paths: plugins.to_vec(),
+            flags: Vec::new(),
+        })
+    }
+
+    fn interpreter(&self) -> Interpreter {
+        let interpreter = Interpreter::new().with_output(io::stdout());
+        if self.overflow_checks {
+            interpreter
+        } else {
+            interpreter.with_wrapping_arithmetic()
+        }
+    }
+
+    fn cfg(&self, target: &str) -> CfgSet {
+        CfgSet::new(target).with_features(self.features.iter().cloned())
+    }
+
+    /// How to generate code for the file at `path`, from the flags and
+    /// gard.toml.
+    fn codegen_options(&self, path: &str) -> Result<CodegenOptions, String> {
+        Ok(CodegenOptions {
+            overflow_checks: self.overflow_checks,
+            source_map: Some(SourceMap::new(path, &read_file(path)?)),
+            relocation_model: self.relocation_model.or(self.manifest.codegen.relocation_model).unwrap_or_default(),
+            code_model: self.code_model.or(self.manifest.codegen.code_model),
+            lto: self.lto.or(self.manifest.codegen.lto),
+            profile: self.profile.clone(),
+            codegen_units: self.codegen_units.or(self.manifest.codegen.codegen_units).unwrap_or(1),
+        })
+    }
+}
+
+pub fn run(args: Args) -> Result<(), String> {
+    let mut build = Build::new(args.features, &args.plugins)?;
+    build.overflow_checks = args.overflow_checks;
+    if args.time_passes.is_some() {
+        build.profile = Some(Arc::new(Profile::new()));
+    }
+    build.flags.extend(build.features.iter().flat_map(|feature| ["--feature".to_string(), feature.clone()]));
+    build.flags.extend(args.plugins.iter().flat_map(|plugin| ["--plugin".to_string(), plugin.clone()]));
+    if args.overflow_checks {
+        build.flags.push("--overflow-checks".to_string());
+    }
+    if let Some(model) = args.relocation_model {
+        build.relocation_model = Some(match model {
+            RelocationModel::Static => machine::RelocationModel::Static,
+            RelocationModel::Pic => machine::RelocationModel::Pic,
+            RelocationModel::Pie => machine::RelocationModel::Pie,
+        });
+        build.flags.push("--relocation-model".to_string());
+        build.flags.extend(model.to_possible_value().map(|value| value.get_name().to_string()));
+    }
+    if let Some(model) = args.code_model {
+        build.code_model = Some(match model {
+            CodeModel::Small => machine::CodeModel::Small,
+            CodeModel::Kernel => machine::CodeModel::Kernel,
+            CodeModel::Medium => machine::CodeModel::Medium,
+            CodeModel::Large => machine::CodeModel::Large,
+        });
+        build.flags.push("--code-model".to_string());
+        build.flags.extend(model.to_possible_value().map(|value| value.get_name().to_string()));
+    }
+    if let Some(lto) = args.lto {
+        build.lto = Some(match lto {
+            Lto::Thin => machine::Lto::Thin,
+            Lto::Full => machine::Lto::Full,
+        });
+        build.flags.extend(lto.to_possible_value().map(|value| format!("--lto={}", value.get_name())));
+    }
+    if let Some(units) = args.codegen_units {
+        build.codegen_units = Some(units.into());
+        build.flags.extend(["--codegen-units".to_string(), units.to_string()]);
+    }
+    let levels = [(args.allow, LintLevel::Allow, "-A"), (args.warn, LintLevel::Warn, "-W"), (args.deny, LintLevel::Deny, "-D")];
+    for (lints, level, flag) in levels {
+        for lint in lints {
+            build.plugins.set_level(&lint, level);
+            build.flags.extend([flag.to_string(), lint]);
+        }
+    }
+    build.manifest = edition::Manifest::find(Path::new("."))?;
+    // Build steps often run `gard bindgen` themselves
+    if !matches!(args.command, Some(Command::Explain { .. } | Command::Top { .. } | Command::Dap | Command::Bindgen { .. })) {
+        let project = edition::Manifest::locate(Path::new(".")).and_then(|manifest| manifest.parent().map(Path::to_path_buf));
+        let ran = profile::time(build.profile.as_deref(), "build steps", || prebuild::run(&build.manifest.build, project.as_deref().unwrap_or(Path::new("."))))?;
+        for command in ran {
+            eprintln!("Ran build step '{}'", command);
+        }
+    }
+    build.locale = match &args.lang {
+        Some(lang) => Locale::parse(lang).ok_or_else(|| format!("No diagnostics in language '{}'", lang))?,
+        None => Locale::from_env(),
+    };
+    let result = match args.command {
+        Some(Command::StorageDiff { old, new }) => {
+            if storage_diff(&old, &new, &build)? {
+                Err("storage layout is not upgrade-safe".to_string())
+            } else {
+                Ok(())
+            }
+        },
+        Some(Command::Run { file, watch, debug_runtime, metrics, otlp, record, replay, introspect, guardian, checkpoint }) => {
+            let schedule = record.map(ScheduleLog::Record).or(replay.map(ScheduleLog::Replay));
+            run_file(&file, &RunOptions { watch, debug_runtime, metrics, otlp, schedule, introspect, guardian, checkpoint }, &build)
+        },
+        Some(Command::Simulate { file, seeds, seed }) => simulate_file(&file, seeds, seed, &build),
+        Some(Command::Top { address }) => top(&address),
+        Some(Command::Debug { file, breakpoint }) => debug_file(&file, &breakpoint, &build),
+        Some(Command::Dap) => gard_dap::Server::new(io::stdin().lock(), io::stdout())
+            .serve()
+            .map_err(|e| format!("Debug adapter failed: {}", e)),
+        Some(Command::Refs { file, symbol }) => refs(&file, &symbol, &build),
+        Some(Command::Rename { file, symbol, new_name, write }) => rename_symbol(&file, &symbol, &new_name, write, build.locale),
+        Some(Command::ExtractFunction { file, name, from, to, write }) => extract_function(&file, &name, from, to, write, build.locale),
+        Some(Command::InlineVariable { file, line, write }) => inline_variable(&file, line, write, build.locale),
+        Some(Command::Explain { code }) => explain(&code),
+        Some(Command::Bindgen { idl, file, output }) => bindgen(idl, &file, output.as_deref()),
+        Some(Command::Graph { file, format }) => {
+            let index = index_source(&file, &read_file(&file)?, &build)?;
+            match format {
+                GraphFormat::Dot => print!("{}", graph::dot(&index)),
+                GraphFormat::Json => println!("{}", graph::json(&index)),
+            }
+            Ok(())
+        },
+        None => match (args.file, args.emit) {
+            (Some(file), Some(emit)) => emit_file(&file, emit, args.output.as_deref(), &build),
+            (None, Some(_)) => Err("--emit requires --file".to_string()),
+            _ => Ok(()),
+        },
+    };
+    if let (Some(format), Some(profile)) = (args.time_passes, &build.profile) {
+        match format {
+            TimePasses::Text => eprint!("{}", profile.report()),
+            TimePasses::Json => eprintln!("{}", profile.to_json()),
+        }
+    }
+    result
+}
+
+/// Parses a file for `target`: checks its feature gates, expands its macros
+/// and derives, keeps the declarations the build's features enable,
+/// evaluates its constants, runs its plugins, and hoists its nested classes.
+/// Lint warnings are printed as they come; a denied lint fails the parse.
+/// Destructor calls are inserted last, then the bounds checks that can't
+/// fail are elided, and the result is type checked.
+pub fn parse_file(path: &str, build: &Build, target: &str) -> Result<Node, String> {
+    parse_source(path, &read_file(path)?, build, target)
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))
+}
+
+fn parse_source(path: &str, source: &str, build: &Build, target: &str) -> Result<Node, String> {
+    let profile = build.profile.as_deref();
+    let tokens = profile::time(profile, "lex", || Lexer::new(source).tokenize())
+        .map_err(|e| lexer_error(path, &e, build.locale))?;
+    let program = profile::time(profile, "parse", || GardParser::parse(tokens))
+        .map_err(|errors| format!("{}: {:?}", path, errors))?;
+    let expanded = profile::time(profile, "feature gates", || edition::check(program, &build.manifest))
+        .and_then(|program| profile::time(profile, "macros", || macros::expand(program)))
+        .and_then(|program| profile::time(profile, "cfg", || cfg::evaluate(program, &build.cfg(target))))
+        .and_then(|program| profile::time(profile, "const eval", || consteval::fold(program)))
+        .and_then(|program| build.plugins.run_profiled(program, profile))
+        .and_then(|(program, warnings)| {
+            for warning in warnings {
+                eprintln!("warning: {}: {}", path, warning);
+            }
+            profile::time(profile, "nested classes", || nested::hoist(program))
+        })
+        .and_then(|program| profile::time(profile, "derive", || derive::expand(program)))
+        .and_then(|program| profile::time(profile, "destructors", || destructors::insert(program)))
+        .map(|program| profile::time(profile, "bounds checks", || bounds::elide_checks(program)))
+        .and_then(|program| profile::time(profile, "type check", || TypeChecker::new().check(&program)).map(|()| program));
+    expanded.map_err(|errors| {
+        errors.iter().map(|e| format!("{}: {}", path, e)).collect::<Vec<_>>().join("\n")
+    })
+}
+
+pub fn explain(code: &str) -> Result<(), String> {
+    match gard_ast::explain(code) {
+        Some(explanation) => {
+            print!("{}", explanation);
+            Ok(())
+        },
+        None => Err(format!("No diagnostic has the code '{}'; the codes are {}", code, gard_ast::codes().collect::<Vec<_>>().join(", "))),
+    }
+}
+
+pub fn bindgen(idl: Idl, path: &str, output: Option<&str>) -> Result<(), String> {
+    let declarations = match idl {
+        Idl::Proto => proto::generate(&read_file(path)?).map_err(|e| format!("{}: {}", path, e))?,
+    };
+    let source = gard_ast::to_source(&declarations);
+    match output {
+        Some(output) => fs::write(output, source).map_err(|e| format!("Failed to write {}: {}", output, e)),
+        None => {
+            print!("{}", source);
+            Ok(())
+        },
+    }
+}
+
+/// `path: L0002: message`, with the code for tools to match on, and
+/// `gard explain` to look it up with.
+fn lexer_error(path: &str, error: &LexerError, locale: Locale) -> String {
+    format!("{}: {}: {}", path, error.code(), error.message(locale))
+}
+
+pub fn emit_file(path: &str, emit: Emit, output: Option<&str>, build: &Build) -> Result<(), String> {
+    let target = match emit {
+        Emit::Solidity => cfg::TARGET_EVM,
+        Emit::Wasm => cfg::TARGET_WASM32,
+        Emit::Expanded | Emit::Header | Emit::Staticlib | Emit::Dylib | Emit::Library => cfg::TARGET_NATIVE,
+    };
+    let program = parse_file(path, build, target)?;
+    let source = match emit {
+        Emit::Solidity => profile::time(build.profile.as_deref(), "codegen", || solidity::transpile(&program)).map_err(|e| format!("{}: {}", path, e))?,
+        Emit::Wasm => {
+            let output = output.ok_or_else(|| "--emit wasm requires --output".to_string())?;
+            let options = build.codegen_options(path)?;
+            let outputs = emit_wasm(path, program, Path::new(output), &options)?;
+            return record_compile(path, emit, Some(output), target, outputs, build);
+        },
+        Emit::Staticlib | Emit::Dylib => {
+            let output = output.ok_or_else(|| "--emit staticlib and --emit dylib require --output".to_string())?;
+            let kind = if emit == Emit::Staticlib { LibraryKind::Static } else { LibraryKind::Shared };
+            let options = build.codegen_options(path)?;
+            let runtime = build.manifest.library.as_ref().map(|library| library.runtime).unwrap_or_default();
+            let outputs = emit_native_library(path, program, Path::new(output), kind, runtime, &options)?;
+            return record_compile(path, emit, Some(output), target, outputs, build);
+        },
+        Emit::Library => {
+            let library = build.manifest.library.as_ref()
+                .ok_or_else(|| "--emit library needs a [library] table in gard.toml".to_string())?;
+            let options = build.codegen_options(path)?;
+            let outputs = emit_libraries(path, program, library, Path::new(output.unwrap_or(".")), &options)?;
+            return record_compile(path, emit, output, target, outputs, build);
+        },
+        Emit::Expanded => gard_ast::to_source(&program),
+        Emit::Header => {
+            let library = Path::new(output.unwrap_or(path)).file_stem()
+                .and_then(|stem| stem.to_str())
+                .ok_or_else(|| format!("Invalid path {}", output.unwrap_or(path)))?;
+            cheader::generate(&program, library).map_err(|e| format!("{}: {}", path, e))?
+        },
+    };
+
+    match output {
+        Some(output) => {
+            fs::write(output, source).map_err(|e| format!("Failed to write {}: {}", output, e))?;
+            record_compile(path, emit, Some(output), target, vec![output.to_string()], build)
+        },
+        None => {
+            print!("{}", source);
+            record_compile(path, emit, None, target, Vec::new(), build)
+        },
+    }
+}
+
+/// Records how `path` was compiled in the compilation database, replacing
+/// the entry from its last compile for `target`.
+fn record_compile(path: &str, emit: Emit, output: Option<&str>, target: &str, outputs: Vec<String>, build: &Build) -> Result<(), String> {
+    let mut arguments = vec!["gard".to_string(), "--file".to_string(), path.to_string(), "--emit".to_string()];
+    arguments.extend(emit.to_possible_value().map(|value| value.get_name().to_string()));
+    if let Some(output) = output {
+        arguments.extend(["--output".to_string(), output.to_string()]);
+    }
+    arguments.extend(build.flags.iter().cloned());
+
+    let manifest = edition::Manifest::locate(Path::new(".")).map(|manifest| manifest.display().to_string());
+    let inputs = std::iter::once(path.to_string()).chain(manifest).chain(build.plugin_paths.iter().cloned())
+        .map(|input| compdb::Input::read(&input))
+        .collect::<Result<_, _>>()?;
+    let directory = std::env::current_dir().map_err(|e| format!("Failed to read the working directory: {}", e))?;
+    let entry = compdb::Entry {
+        file: path.to_string(),
+        directory: directory.display().to_string(),
+        arguments,
+        target: target.to_string(),
+        edition: build.manifest.edition.clone(),
+        features: build.features.clone(),
+        experimental: build.manifest.experimental.clone(),
+        overflow_checks: build.overflow_checks,
+        inputs,
+        outputs,
+    };
+
+    let database_path = Path::new(compdb::PATH);
+    let mut database = compdb::Database::load(database_path)?;
+    database.record(entry);
+    database.save(database_path)
+}
+
+/// Compiles to wasm, returning the files it wrote.
+fn emit_wasm(path: &str, program: Node, output: &Path, options: &CodegenOptions) -> Result<Vec<String>, String> {
+    let has_contracts = matches!(&program, Node::Program(nodes) if nodes.iter().any(|n| matches!(n, Node::Contract { .. })));
+    if has_contracts {
+        let abi = gard_compiler::build_wasm_contract(program, output, options)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        for entry in &abi.entry_points {
+            println!("export {} -> {}", entry.export, entry.function);
+        }
+        return Ok(vec![output.display().to_string()]);
+    }
+
+    let module_name = output.file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| format!("Invalid output path {}", output.display()))?;
+    let bindings = typescript::generate(&program, module_name).map_err(|e| format!("{}: {}", path, e))?;
+    gard_compiler::build_wasm_module(program, module_name, output, options).map_err(|e| format!("{}: {}", path, e))?;
+
+    let mut outputs = vec![output.display().to_string()];
+    for (extension, contents) in [("d.ts", &bindings.declarations), ("js", &bindings.loader)] {
+        let target = output.with_extension(extension);
+        fs::write(&target, contents).map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+        outputs.push(target.display().to_string());
+    }
+    Ok(outputs)
+}
+
+/// Compiles to a native library with its C header next to it, returning the
+/// files it wrote.
+fn emit_native_library(path: &str, program: Node, output: &Path, kind: LibraryKind, runtime: Runtime, options: &CodegenOptions) -> Result<Vec<String>, String> {
+    let library = output.file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| format!("Invalid output path {}", output.display()))?;
+    let header = cheader::generate(&program, library).map_err(|e| format!("{}: {}", path, e))?;
+    gard_compiler::build_native_library(program, library, output, kind, runtime, options).map_err(|e| format!("{}: {}", path, e))?;
+
+    let target = output.with_extension("h");
+    fs::write(&target, header).map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+    Ok(vec![output.display().to_string(), target.display().to_string()])
+}
+
+/// Compiles to each kind of library gard.toml lists, named for the
+/// platform, with one header for all of them, returning the files it wrote.
+fn emit_libraries(path: &str, program: Node, library: &Library, directory: &Path, options: &CodegenOptions) -> Result<Vec<String>, String> {
+    let name = match &library.name {
+        Some(name) => name.as_str(),
+        None => Path::new(path).file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("Invalid path {}", path))?,
+    };
+    let header = cheader::generate(&program, name).map_err(|e| format!("{}: {}", path, e))?;
+    fs::create_dir_all(directory).map_err(|e| format!("Failed to create {}: {}", directory.display(), e))?;
+
+    let mut outputs = Vec::new();
+    for kind in &library.types {
+        let output = directory.join(kind.file_name(name, std::env::consts::OS));
+        gard_compiler::build_native_library(program.clone(), name, &output, *kind, library.runtime, options)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        outputs.push(output.display().to_string());
+    }
+    let target = directory.join(format!("{}.h", name));
+    fs::write(&target, header).map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+    outputs.push(target.display().to_string());
+    Ok(outputs)
+}
+
+/// Prints every storage layout change between two versions of a program and
+/// returns whether any of them is breaking.
+pub fn storage_diff(old: &str, new: &str, build: &Build) -> Result<bool, String> {
+    let mut breaking = false;
+
+    let (old, new) = (parse_file(old, build, cfg::TARGET_EVM)?, parse_file(new, build, cfg::TARGET_EVM)?);
+    for (contract, changes) in storage::diff_programs(&old, &new)? {
+        for change in changes {
+            let level = if change.is_breaking() { "error" } else { "note" };
+            breaking |= change.is_breaking();
+            println!("{}: {}: {}", level, contract, change);
+        }
+    }
+
+    Ok(breaking)
+}
+
+/// Prints where `symbol` is referred to and from which definition.
+pub fn refs(path: &str, symbol: &str, build: &Build) -> Result<(), String> {
+    let source = read_file(path)?;
+    let index = index_source(path, &source, build)?;
+    if index.definition(symbol).is_none() {
+        return Err(format!("{}: no symbol '{}'", path, symbol));
+    }
+
+    let source_map = SourceMap::new(path, &source);
+    for reference in index.references_to(symbol) {
+        let location = match reference.span {
+            Some(span) => {
+                let location = source_map.location(span.start);
+                format!("{}:{}:{}", path, location.line, location.column)
+            },
+            None => path.to_string(),
+        };
+        let from = if reference.from.is_empty() { "top level" } else { reference.from.as_str() };
+        println!("{}: in {}", location, from);
+    }
+    Ok(())
+}
+
+/// Renames `symbol` in a file, printing the edits or, with `write`,
+/// rewriting the file.
+pub fn rename_symbol(path: &str, symbol: &str, new_name: &str, write: bool, locale: Locale) -> Result<(), String> {
+    if !matches!(Lexer::new(new_name).tokenize().as_deref(), Ok([TokenWithSpan { token: Token::Identifier(_), .. }, TokenWithSpan { token: Token::Eof, .. }])) {
+        return Err(format!("'{}' is not a valid name", new_name));
+    }
+
+    let (source, names, program) = parse_for_editing(path, locale)?;
+    let source_map = SourceMap::new(path, &source);
+    let edits = rename::rename(&program, &names, &source_map, symbol, new_name)
+        .map_err(|errors| errors.join("\n"))?;
+    if write {
+        return write_edits(path, &source, &edits);
+    }
+    for edit in &edits {
+        let location = source_map.location(edit.span.start);
+        println!("{}:{}:{}: {} -> {}", path, location.line, location.column, &source[edit.span.start..edit.span.end], edit.new_text);
+    }
+    Ok(())
+}
+
+/// Moves lines `from` to `to` of a file into a new function `name`.
+pub fn extract_function(path: &str, name: &str, from: usize, to: usize, write: bool, locale: Locale) -> Result<(), String> {
+    if !matches!(Lexer::new(name).tokenize().as_deref(), Ok([TokenWithSpan { token: Token::Identifier(_), .. }, TokenWithSpan { token: Token::Eof, .. }])) {
+        return Err(format!("'{}' is not a valid name", name));
+    }
+    let (source, _, program) = parse_for_editing(path, locale)?;
+    let (start, _) = line_span(&source, from).ok_or_else(|| format!("{} has no line {}", path, from))?;
+    let (_, end) = line_span(&source, to).ok_or_else(|| format!("{} has no line {}", path, to))?;
+    if start > end {
+        return Err(format!("Line {} comes after line {}", from, to));
+    }
+    let edits = refactor::extract_function(&program, &source, gard_ast::Span { start, end }, name)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    show_edits(path, &source, &edits, write)
+}
+
+/// Inlines the local declared on `line` of a file.
+pub fn inline_variable(path: &str, line: usize, write: bool, locale: Locale) -> Result<(), String> {
+    let (source, names, program) = parse_for_editing(path, locale)?;
+    let (start, end) = line_span(&source, line).ok_or_else(|| format!("{} has no line {}", path, line))?;
+    let at = start + source[start..end].len() - source[start..end].trim_start().len();
+    let edits = refactor::inline_variable(&program, &names, &source, at)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    show_edits(path, &source, &edits, write)
+}
+
+/// The text and span of every identifier in a source file, in order.
+type Names = Vec<(String, gard_ast::Span)>;
+
+/// A file's source, its identifiers, and the program parsed from it before
+/// any expansion, for refactorings to edit. Declarations that don't parse
+/// are left out of the edits, with a warning, rather than failing them all.
+fn parse_for_editing(path: &str, locale: Locale) -> Result<(String, Names, Node), String> {
+    let source = read_file(path)?;
+    let tokens = Lexer::new(&source).tokenize()
+        .map_err(|e| lexer_error(path, &e, locale))?;
+    let names = tokens.iter()
+        .filter_map(|token| match &token.token {
+            Token::Identifier(name) => Some((name.to_string(), gard_ast::Span { start: token.span.start, end: token.span.end })),
+            _ => None,
+        })
+        .collect();
+    let (program, _) = GardParser::parse_recovering(tokens);
+    if let Node::Program(declarations) = &program {
+        for declaration in declarations {
+            if let Node::Error { span, .. } = declaration {
+                let line = source[..span.start].matches('\n').count() + 1;
+                eprintln!("warning: {}: line {} doesn't parse; leaving it as it is", path, line);
+            }
+        }
+    }
+    Ok((source, names, program))
+}
+
+/// The start and end of 1-based `line`, without its newline.
+fn line_span(source: &str, line: usize) -> Option<(usize, usize)> {
+    let mut start = 0;
+    for (i, text) in source.split_inclusive('\n').enumerate() {
+        if i + 1 == line {
+            return Some((start, start + text.trim_end_matches(['\r', '\n']).len()));
+        }
+        start += text.len();
+    }
+    None
+}
+
+fn write_edits(path: &str, source: &str, edits: &[rename::TextEdit]) -> Result<(), String> {
+    fs::write(path, rename::apply(source, edits)).map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+/// Prints each edit as the lines it removes and adds, or applies them with
+/// `write`.
+fn show_edits(path: &str, source: &str, edits: &[rename::TextEdit], write: bool) -> Result<(), String> {
+    if write {
+        return write_edits(path, source, edits);
+    }
+    let source_map = SourceMap::new(path, source);
+    for edit in edits {
+        let location = source_map.location(edit.span.start);
+        println!("{}:{}:{}:", path, location.line, location.column);
+        for line in source[edit.span.start..edit.span.end].lines() {
+            println!("- {}", line);
+        }
+        for line in edit.new_text.lines() {
+            println!("+ {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// The symbol index of a source file. It is cached under `INDEX_DIR` and
+/// rebuilt when the source or the build's features change.
+pub fn index_source(path: &str, source: &str, build: &Build) -> Result<Index, String> {
+    let mut hasher = DefaultHasher::new();
+    (source, &build.features, &build.manifest).hash(&mut hasher);
+    let fingerprint = hasher.finish();
+
+    let cache = Path::new(INDEX_DIR).join(format!("{}.json", path.replace(['/', '\\'], "_")));
+    if let Ok(index) = Index::load(&cache) {
+        if index.fingerprint == fingerprint {
+            return Ok(index);
+        }
+    }
+
+    let mut index = Index::build(&parse_source(path, source, build, cfg::TARGET_NATIVE)?);
+    index.fingerprint = fingerprint;
+    fs::create_dir_all(INDEX_DIR).map_err(|e| format!("Failed to create {}: {}", INDEX_DIR, e))?;
+    index.save(&cache)?;
+    Ok(index)
+}
+
+/// A `help:` line for each other file of `path`'s directory that declares
+/// `name`, which didn't resolve in it. They're indexed as written, without
+/// the build's passes and their warnings; files that don't parse are left
+/// out.
+fn declaring_files_help(path: &str, name: &str) -> String {
+    let path = Path::new(path);
+    let directory = path.parent().filter(|directory| !directory.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let Ok(entries) = fs::read_dir(directory) else {
+        return String::new();
+    };
+    let mut files: Vec<String> = entries.flatten()
+        .map(|entry| path.with_file_name(entry.file_name()))
+        .filter(|file| file.extension().is_some_and(|extension| extension == "gard") && file != path)
+        .map(|file| file.display().to_string())
+        .collect();
+    files.sort();
+    let indexes: Vec<(String, Index)> = files.into_iter()
+        .filter_map(|file| {
+            let source = read_file(&file).ok()?;
+            let program = GardParser::parse_all(Lexer::new(&source).tokenize().ok()?).ok()?;
+            Some((file, Index::build(&program)))
+        })
+        .collect();
+    index::declaring_files(name, &indexes).into_iter()
+        .map(|file| format!("\nhelp: '{}' is declared in {}; there are no imports yet, so move it into {} to use it", name, file, path.display()))
+        .collect()
+}
+
+/// Runs a program in the interpreter, printing its output as it goes.
+pub fn run_file(path: &str, options: &RunOptions, build: &Build) -> Result<(), String> {
+    let source = read_file(path)?;
+    let program = parse_source(path, &source, build, cfg::TARGET_NATIVE)?;
+    let mut interpreter = build.interpreter().with_source_map(SourceMap::new(path, &source));
+    if options.debug_runtime {
+        interpreter = interpreter.with_debug_runtime();
+    }
+    interpreter = interpreter.with_guardian(match options.guardian {
+        GuardianPolicy::Shutdown => Guardian::Shutdown,
+        GuardianPolicy::Stop => Guardian::Stop,
+        GuardianPolicy::Restart => Guardian::Restart { max_restarts: MAX_RESTARTS },
+    });
+    match &options.schedule {
+        Some(ScheduleLog::Record(_)) => interpreter = interpreter.with_recorded_schedule(),
+        Some(ScheduleLog::Replay(log)) => {
+            let events = replay::parse_log(&read_file(log)?).map_err(|e| format!("{}: {}", log, e))?;
+            interpreter = interpreter.with_replayed_schedule(events);
+        },
+        None => {},
+    }
+    if let Some(checkpoint) = options.checkpoint.as_deref().filter(|file| Path::new(file).exists()) {
+        let checkpoint = Checkpoint::from_text(&read_file(checkpoint)?).map_err(|e| format!("{}: {}", checkpoint, e))?;
+        interpreter = interpreter.with_checkpoint(checkpoint);
+    }
+    if let Some(endpoint) = options.otlp.clone() {
+        let service = Path::new(path).file_stem().map_or_else(|| path.to_string(), |stem| stem.to_string_lossy().into_owned());
+        interpreter = interpreter.with_metrics_exporter(OTLP_INTERVAL, move |metrics| export_otlp(&endpoint, &service, metrics));
+    }
+    if let Some(address) = &options.introspect {
+        let latest = serve_introspection(address)?;
+        interpreter = interpreter.with_introspection(INTROSPECT_INTERVAL, move |snapshot| {
+            *latest.lock().unwrap_or_else(PoisonError::into_inner) = Some(snapshot);
+        });
+    }
+    if options.watch {
+        let reported_path = path.to_string();
+        interpreter.watch(SourceWatcher::new(path), move |result| match result {
+            Ok(summary) => eprintln!("note: reloaded {}: {}", reported_path, summary),
+            Err(e) => eprintln!("error: reloading {} failed: {}", reported_path, e),
+        });
+    }
+
+    let result = interpreter.run(&program);
+    if let Some(ScheduleLog::Record(log)) = &options.schedule {
+        fs::write(log, replay::to_log(interpreter.recorded_schedule())).map_err(|e| format!("Failed to write {}: {}", log, e))?;
+    }
+    for violation in interpreter.lock_order_violations() {
+        eprintln!("warning: {}: {}", path, violation);
+    }
+    for supervised in interpreter.supervised() {
+        eprintln!("warning: {}: {}", path, supervised);
+    }
+    if options.metrics {
+        eprint!("{}", interpreter.metrics());
+    }
+    let help = match &result {
+        Err(RuntimeError::UndefinedVariable(name) | RuntimeError::UndefinedFunction(name)) => declaring_files_help(path, name),
+        _ => String::new(),
+    };
+    let result = result.map(|_| ()).map_err(|e| format!("{}: {}{}", path, e, help));
+    if let (Ok(()), Some(file)) = (&result, &options.checkpoint) {
+        let checkpoint = interpreter.checkpoint().map_err(|e| format!("{}: {}", path, e))?;
+        fs::write(file, checkpoint.to_text()).map_err(|e| format!("Failed to write {}: {}", file, e))?;
+    }
+    result
+}
+
+/// Explores the schedules of each `@concurrent` test in a program, or runs
+/// one seed of each to reproduce a failure.
+pub fn simulate_file(path: &str, seeds: u64, seed: Option<u64>, build: &Build) -> Result<(), String> {
+    let program = parse_file(path, build, cfg::TARGET_NATIVE)?;
+    let tests = simulate::concurrent_tests(&program);
+    if tests.is_empty() {
+        return Err(format!("{}: no function is marked @{}", path, simulate::ATTRIBUTE));
+    }
+    let mut failed = 0;
+    for test in &tests {
+        if let Some(seed) = seed {
+            let run = simulate::run_seed(&program, test, seed);
+            print!("{}", run.output);
+            eprint!("{}", replay::to_log(&run.schedule));
+            if let Err(e) = run.result {
+                eprintln!("{}: seed {}: {}", test, seed, e);
+                failed += 1;
+            }
+        } else {
+            let exploration = simulate::explore(&program, test, 0..seeds);
+            println!("{}", exploration);
+            if !exploration.failures.is_empty() {
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{}: {} of {} test(s) failed", path, failed, tests.len()));
+    }
+    Ok(())
+}
+
+/// Serves the latest snapshot the interpreter publishes on `address`, from
+/// a background thread that lives as long as the program.
+fn serve_introspection(address: &str) -> Result<Arc<Mutex<Option<Snapshot>>>, String> {
+    let listener = TcpListener::bind(address).map_err(|e| format!("Failed to listen on {}: {}", address, e))?;
+    let latest = Arc::new(Mutex::new(None));
+    let served = Arc::clone(&latest);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A client that hangs up early only loses its own response
+            let _ = respond_with_snapshot(stream, &served);
+        }
+    });
+    Ok(latest)
+}
+
+fn respond_with_snapshot(mut stream: TcpStream, latest: &Mutex<Option<Snapshot>>) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, up to the blank line ending them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let snapshot = latest.lock().unwrap_or_else(PoisonError::into_inner).clone();
+    let (status, content_type, body) = match (path, snapshot) {
+        ("/" | "/json", None) => ("503 Service Unavailable", "text/plain", "The program hasn't started yet\n".to_string()),
+        ("/", Some(snapshot)) => ("200 OK", "text/plain; charset=utf-8", snapshot.to_string()),
+        ("/json", Some(snapshot)) => ("200 OK", "application/json", snapshot.to_json()),
+        _ => ("404 Not Found", "text/plain", format!("No page at {}\n", path)),
+    };
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, content_type, body.len(), body)
+}
+
+/// Redraws the tasks of a program run with `--introspect` until it ends.
+pub fn top(address: &str) -> Result<(), String> {
+    let url = format!("http://{}/", address);
+    let mut connected = false;
+    loop {
+        match gard_vm::http::get(&url) {
+            Ok(response) if response.status == 200 => {
+                // Clear the screen and draw from the top left
+                print!("\x1b[2J\x1b[H{}", String::from_utf8_lossy(&response.body));
+                io::stdout().flush().map_err(|e| format!("Failed to write to stdout: {}", e))?;
+                connected = true;
+            },
+            Ok(response) if response.status == 503 => {},
+            Ok(response) => return Err(format!("{} answered with status {}", url, response.status)),
+            // The server goes away with the program
+            Err(_) if connected => {
+                eprintln!("note: the program at {} has ended", address);
+                return Ok(());
+            },
+            Err(e) => return Err(format!("Failed to reach {}: {}", address, e)),
+        }
+        thread::sleep(TOP_INTERVAL);
+    }
+}
+
+/// Pushes metrics to an OTLP/HTTP collector. Failing to reach it is only a
+/// warning, so the program keeps running.
+fn export_otlp(endpoint: &str, service: &str, metrics: &Metrics) {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let body = metrics.to_otlp_json(service, now.as_nanos() as u64);
+    match gard_vm::http::post(endpoint, body.as_bytes(), "application/json") {
+        Ok(response) if response.status < 300 => {},
+        Ok(response) => eprintln!("warning: exporting metrics to {} failed with status {}", endpoint, response.status),
+        Err(e) => eprintln!("warning: exporting metrics to {} failed: {}", endpoint, e),
+    }
+}
+
+/// Runs a program in the interpreter with the `gard debug` terminal attached.
+pub fn debug_file(path: &str, breakpoints: &[usize], build: &Build) -> Result<(), String> {
+    let source = read_file(path)?;
+    let program = parse_source(path, &source, build, cfg::TARGET_NATIVE)?;
+    let source_map = SourceMap::new(path, &source);
+
+    let terminal = debug::Terminal::new(source_map.clone(), io::stdin().lock(), io::stdout());
+    let mut debugger = Debugger::new(terminal);
+    if !breakpoints.is_empty() {
+        for &line in breakpoints {
+            debugger.config_mut().add_breakpoint(path, line);
+        }
+        debugger = debugger.without_stop_on_entry();
+    }
+
+    let mut interpreter = build.interpreter().with_source_map(source_map);
+    interpreter.attach(debugger);
+    match interpreter.run(&program) {
+        Ok(_) | Err(RuntimeError::Terminated) => Ok(()),
+        Err(e) => Err(format!("{}: {}", path, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds `source` as `gard --file <file> --emit expanded` would.
+    fn build(name: &str, source: &str) -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("gard-cli-{}-{}.gard", name, std::process::id()));
+        fs::write(&path, source).unwrap();
+        let result = run(Args::parse_from(["gard", "--file", path.to_str().unwrap(), "--emit", "expanded"]));
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn test_type_error_fails_the_build() {
+        let owner = "tasklocal owner: address = 0x7e5f4552091a69125d5dfcb7b8c2659029395bdf\n";
+        assert_eq!(build("address", &format!("{}tasklocal next = owner", owner)), Ok(()));
+
+        let error = build("address-arithmetic", &format!("{}tasklocal doubled = owner * 2", owner)).unwrap_err();
+        assert!(error.ends_with("Arithmetic operator Mul is not defined on address values"), "{}", error);
+    }
+
+    #[test]
+    fn test_unsendable_spawn_fails_the_build() {
+        let error = build("spawn", "function main { scope { spawn print(main); } }").unwrap_err();
+        assert!(error.ends_with("to a spawned task: functions can capture mutable state"), "{}", error);
+    }
+
+    #[test]
+    fn test_inferred_type_mismatch_fails_the_build() {
+        let error = build("inference", "tasklocal count = 42i\ntasklocal name: string = count").unwrap_err();
+        assert!(error.ends_with("Cannot assign a value of type Int to 'name' of type String"), "{}", error);
+    }
+
+    #[test]
+    fn test_non_exhaustive_match_fails_the_build() {
+        let source = |cases: &str| format!(
+            "@feature(unions);\ntype Msg = Update | Logout\ntasklocal msg: Msg = Logout\nfunction main {{\n    match msg {{\n{}    }}\n}}",
+            cases,
+        );
+        assert_eq!(build("match", &source("        Update => {}\n        Logout => {}\n")), Ok(()));
+
+        let error = build("match-missing", &source("        Update => {}\n")).unwrap_err();
+        assert!(error.ends_with("Match on Msg does not cover Logout"), "{}", error);
+    }
+
+    #[test]
+    fn test_abstract_instantiation_fails_the_build() {
+        let source = "abstract class Shape {\n    abstract function area(): int;\n}\nfunction main { new Shape(); }";
+        let error = build("abstract", source).unwrap_err();
+        assert!(error.ends_with("Cannot instantiate abstract class 'Shape'"), "{}", error);
+    }
+
+    #[test]
+    fn test_escaping_task_local_fails_the_build() {
+        let source = "tasklocal requestId: int = 0\nfunction main { scope { spawn print(requestId); } }";
+        let error = build("tasklocal", source).unwrap_err();
+        assert!(error.ends_with("Cannot send task-local 'requestId' to a spawned task; each task has its own"), "{}", error);
+    }
+}
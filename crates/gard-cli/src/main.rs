@@ -0,0 +1,9 @@
+use clap::Parser;
+use gard_cli::Args;
+
+fn main() {
+    if let Err(error) = gard_cli::run(Args::parse()) {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,77 @@
+use gard_ast::{BinaryOp, Node};
+
+/// Integer overflow semantics. `int` arithmetic wraps unless the program is
+/// built with overflow checks, which trap with the source location instead.
+/// `uint256` and `address` arithmetic is always checked, and so is every
+/// integer on the EVM, like Solidity since 0.8. These builtins pick the
+/// semantics explicitly whatever the build: `wrapping*` always wraps and
+/// `checked*` always raises a catchable overflow error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticBuiltin {
+    WrappingAdd,
+    WrappingSub,
+    WrappingMul,
+    CheckedAdd,
+    CheckedSub,
+    CheckedMul,
+}
+
+impl ArithmeticBuiltin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wrappingAdd" => Some(ArithmeticBuiltin::WrappingAdd),
+            "wrappingSub" => Some(ArithmeticBuiltin::WrappingSub),
+            "wrappingMul" => Some(ArithmeticBuiltin::WrappingMul),
+            "checkedAdd" => Some(ArithmeticBuiltin::CheckedAdd),
+            "checkedSub" => Some(ArithmeticBuiltin::CheckedSub),
+            "checkedMul" => Some(ArithmeticBuiltin::CheckedMul),
+            _ => None,
+        }
+    }
+
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee {
+            Node::Identifier(name) => Self::from_name(name),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArithmeticBuiltin::WrappingAdd => "wrappingAdd",
+            ArithmeticBuiltin::WrappingSub => "wrappingSub",
+            ArithmeticBuiltin::WrappingMul => "wrappingMul",
+            ArithmeticBuiltin::CheckedAdd => "checkedAdd",
+            ArithmeticBuiltin::CheckedSub => "checkedSub",
+            ArithmeticBuiltin::CheckedMul => "checkedMul",
+        }
+    }
+
+    pub fn operator(&self) -> BinaryOp {
+        match self {
+            ArithmeticBuiltin::WrappingAdd | ArithmeticBuiltin::CheckedAdd => BinaryOp::Add,
+            ArithmeticBuiltin::WrappingSub | ArithmeticBuiltin::CheckedSub => BinaryOp::Sub,
+            ArithmeticBuiltin::WrappingMul | ArithmeticBuiltin::CheckedMul => BinaryOp::Mul,
+        }
+    }
+
+    pub fn is_wrapping(&self) -> bool {
+        matches!(self, ArithmeticBuiltin::WrappingAdd | ArithmeticBuiltin::WrappingSub | ArithmeticBuiltin::WrappingMul)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_names() {
+        for name in ["wrappingAdd", "wrappingSub", "wrappingMul", "checkedAdd", "checkedSub", "checkedMul"] {
+            assert_eq!(ArithmeticBuiltin::from_name(name).map(|builtin| builtin.name()), Some(name));
+        }
+        let builtin = ArithmeticBuiltin::from_callee(&Node::Identifier("checkedMul".into())).unwrap();
+        assert_eq!(builtin.operator(), BinaryOp::Mul);
+        assert!(!builtin.is_wrapping());
+        assert_eq!(ArithmeticBuiltin::from_name("add"), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Bounds checks. Every `a[i]` is checked against the length of `a` and
+//! traps with its source location when out of bounds. `elide_checks` clears
+//! the checks that can't fail: indexing an array with the induction variable
+//! of a loop like `for (let i = 0; i < a.length; i++)`, where the body
+//! neither steps `i` nor rebinds `i` or `a`. A function marked `@unchecked`
+//! opts out of the checks altogether.
+
+use gard_ast::{BinaryOp, FunctionModifier, Node, UnaryOp};
+
+/// Name of the attribute that turns bounds checks off in a function.
+pub const UNCHECKED: &str = "unchecked";
+
+/// Handles `@unchecked` on a function declaration.
+pub fn unchecked_attribute(arguments: &[Node], declaration: Node) -> Result<Node, String> {
+    if !arguments.is_empty() {
+        return Err("@unchecked takes no arguments".to_string());
+    }
+    match declaration {
+        Node::Located { span, node } => Ok(Node::Located { span, node: Box::new(unchecked_attribute(arguments, *node)?) }),
+        Node::Function { name, params, return_type, body, mut modifiers, docs } => {
+            if !modifiers.contains(&FunctionModifier::Unchecked) {
+                modifiers.push(FunctionModifier::Unchecked);
+            }
+            Ok(Node::Function { name, params, return_type, body, modifiers, docs })
+        },
+        _ => Err("@unchecked only applies to functions".to_string()),
+    }
+}
+
+/// Clears the checks on index expressions that are known to be in bounds
+/// or are in `@unchecked` functions.
+pub fn elide_checks(mut program: Node) -> Node {
+    visit(&mut program, false, &mut Vec::new());
+    program
+}
+
+/// `in_bounds` holds the `(index, array)` pairs of the enclosing loops
+/// whose indexing can't fail.
+fn visit(node: &mut Node, unchecked: bool, in_bounds: &mut Vec<(String, String)>) {
+    match node {
+        Node::Function { modifiers, body, .. } => {
+            // A nested function may run after the loop around it has moved on
+            let unchecked = modifiers.contains(&FunctionModifier::Unchecked);
+            visit(body, unchecked, &mut Vec::new());
+        },
+        Node::Index { object, index, checked } => {
+            if unchecked {
+                *checked = false;
+            } else if let (Node::Identifier(array), Node::Identifier(variable)) = (object.unlocated(), index.unlocated()) {
+                if in_bounds.iter().any(|(i, a)| i == variable && a == array) {
+                    *checked = false;
+                }
+            }
+            visit(object, unchecked, in_bounds);
+            visit(index, unchecked, in_bounds);
+        },
+        Node::For { initializer, condition, increment, body } => {
+            let induction = induction_variable(initializer.as_deref(), condition.as_deref(), increment.as_deref())
+                .filter(|(index, array)| !rebinds_or_steps(body, index, array));
+            for child in [initializer, condition, increment].into_iter().flatten() {
+                visit(child, unchecked, in_bounds);
+            }
+            match induction {
+                Some(pair) => {
+                    in_bounds.push(pair);
+                    visit(body, unchecked, in_bounds);
+                    in_bounds.pop();
+                },
+                None => visit(body, unchecked, in_bounds),
+            }
+        },
+        _ => {
+            for child in node.children_mut() {
+                visit(child, unchecked, in_bounds);
+            }
+        },
+    }
+}
+
+/// `(i, a)` for a loop `for (let i = n; i < a.length; i++)` with `n` a
+/// non-negative literal; the step can also be `++i` or left out.
+fn induction_variable(initializer: Option<&Node>, condition: Option<&Node>, increment: Option<&Node>) -> Option<(String, String)> {
+    let index = match initializer?.unlocated() {
+        Node::Let { name, initializer: Some(value), .. } if matches!(value.unlocated(), Node::IntLiteral(n) if *n >= 0) => name,
+        _ => return None,
+    };
+    let array = match condition?.unlocated() {
+        Node::Binary { left, operator: BinaryOp::Lt, right } if is_identifier(left, index) => length_of(right)?,
+        Node::Binary { left, operator: BinaryOp::Gt, right } if is_identifier(right, index) => length_of(left)?,
+        _ => return None,
+    };
+    match increment.map(Node::unlocated) {
+        None => {},
+        Some(Node::Unary { operator: UnaryOp::Increment, operand }) if is_identifier(operand, index) => {},
+        Some(_) => return None,
+    }
+    Some((index.clone(), array))
+}
+
+fn length_of(node: &Node) -> Option<String> {
+    match node.unlocated() {
+        Node::Member { object, property } if property == "length" => match object.unlocated() {
+            Node::Identifier(array) => Some(array.to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_identifier(node: &Node, name: &str) -> bool {
+    matches!(node.unlocated(), Node::Identifier(identifier) if identifier == name)
+}
+
+/// Whether the loop body steps the index or declares a local shadowing the
+/// index or the array.
+fn rebinds_or_steps(node: &Node, index: &str, array: &str) -> bool {
+    match node {
+        Node::Let { name, .. } if name == index || name == array => true,
+        Node::Unary { operator: UnaryOp::Increment | UnaryOp::Decrement, operand } if is_identifier(operand, index) => true,
+        _ => node.children().into_iter().any(|child| rebinds_or_steps(child, index, array)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::Type;
+
+    fn identifier(name: &str) -> Node {
+        Node::Identifier(name.into())
+    }
+
+    fn index(array: &str, variable: &str) -> Node {
+        Node::Index { object: Box::new(identifier(array)), index: Box::new(identifier(variable)), checked: true }
+    }
+
+    fn counting_loop(array: &str, body: Vec<Node>) -> Node {
+        Node::For {
+            initializer: Some(Box::new(Node::Let {
+                name: "i".to_string(),
+                type_annotation: None,
+                initializer: Some(Box::new(Node::IntLiteral(0))),
+                is_mutable: true,
+            })),
+            condition: Some(Box::new(Node::Binary {
+                left: Box::new(identifier("i")),
+                operator: BinaryOp::Lt,
+                right: Box::new(Node::Member { object: Box::new(identifier(array)), property: "length".to_string() }),
+            })),
+            increment: Some(Box::new(Node::Unary { operator: UnaryOp::Increment, operand: Box::new(identifier("i")) })),
+            body: Box::new(Node::Block(body)),
+        }
+    }
+
+    fn function(body: Vec<Node>) -> Node {
+        Node::Function {
+            name: "f".to_string(),
+            params: vec![],
+            return_type: Type::Void,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn checks(node: &Node) -> Vec<bool> {
+        let mut checks: Vec<bool> = node.children().into_iter().flat_map(checks).collect();
+        if let Node::Index { checked, .. } = node {
+            checks.insert(0, *checked);
+        }
+        checks
+    }
+
+    #[test]
+    fn test_elides_induction_variable_checks() {
+        let program = function(vec![
+            counting_loop("a", vec![index("a", "i"), index("b", "i")]),
+            index("a", "i"),
+        ]);
+        assert_eq!(checks(&elide_checks(program)), vec![false, true, true]);
+
+        let stepped = function(vec![counting_loop("a", vec![
+            Node::Unary { operator: UnaryOp::Increment, operand: Box::new(identifier("i")) },
+            index("a", "i"),
+        ])]);
+        assert_eq!(checks(&elide_checks(stepped)), vec![true]);
+    }
+
+    #[test]
+    fn test_unchecked_functions() {
+        let program = unchecked_attribute(&[], function(vec![index("a", "j")])).unwrap();
+        assert_eq!(checks(&elide_checks(program)), vec![false]);
+        assert!(unchecked_attribute(&[], identifier("x")).is_err());
+        assert!(unchecked_attribute(&[Node::IntLiteral(1)], function(vec![])).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use gard_ast::{Node, Type};
+
+/// Builtins of the `bytes` type, called as `bytes.toHex(data)`. Besides
+/// these, `a + b` concatenates, `data[i]` reads a byte as an int and
+/// `data.length` counts bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BytesBuiltin {
+    FromHex,
+    ToHex,
+    FromString,
+    ToString,
+    Slice,
+    Concat,
+}
+
+pub const MODULE: &str = "bytes";
+
+impl BytesBuiltin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fromHex" => Some(BytesBuiltin::FromHex),
+            "toHex" => Some(BytesBuiltin::ToHex),
+            "fromString" => Some(BytesBuiltin::FromString),
+            "toString" => Some(BytesBuiltin::ToString),
+            "slice" => Some(BytesBuiltin::Slice),
+            "concat" => Some(BytesBuiltin::Concat),
+            _ => None,
+        }
+    }
+
+    /// Resolves `bytes.slice(..)` style callees.
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee.unlocated() {
+            Node::Member { object, property } => match object.unlocated() {
+                Node::Identifier(module) if module == MODULE => Self::from_name(property),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BytesBuiltin::FromHex => "fromHex",
+            BytesBuiltin::ToHex => "toHex",
+            BytesBuiltin::FromString => "fromString",
+            BytesBuiltin::ToString => "toString",
+            BytesBuiltin::Slice => "slice",
+            BytesBuiltin::Concat => "concat",
+        }
+    }
+
+    /// `fromHex` takes an optional `0x` prefix and `toHex` adds none;
+    /// `slice(data, start, end)` excludes `end`.
+    pub fn signature(&self) -> Type {
+        let (params, return_type) = match self {
+            BytesBuiltin::FromHex | BytesBuiltin::FromString => (vec![Type::String], Type::Bytes),
+            BytesBuiltin::ToHex | BytesBuiltin::ToString => (vec![Type::Bytes], Type::String),
+            BytesBuiltin::Slice => (vec![Type::Bytes, Type::Int, Type::Int], Type::Bytes),
+            BytesBuiltin::Concat => (vec![Type::Bytes, Type::Bytes], Type::Bytes),
+        };
+        Type::Function { params, return_type: Box::new(return_type) }
+    }
+
+    /// Symbol of the native implementation in gard-vm.
+    pub fn runtime_symbol(&self) -> &'static str {
+        match self {
+            BytesBuiltin::FromHex => "gard_bytes_from_hex",
+            BytesBuiltin::ToHex => "gard_bytes_to_hex",
+            BytesBuiltin::FromString => "gard_bytes_from_string",
+            BytesBuiltin::ToString => "gard_bytes_to_string",
+            BytesBuiltin::Slice => "gard_bytes_slice",
+            BytesBuiltin::Concat => "gard_bytes_concat",
+        }
+    }
+
+    /// Solidity has `bytes.concat`; hex and slices of memory bytes have no
+    /// equivalent.
+    pub fn solidity_name(&self) -> Result<&'static str, String> {
+        match self {
+            BytesBuiltin::Concat => Ok("bytes.concat"),
+            BytesBuiltin::FromString => Ok("bytes"),
+            BytesBuiltin::ToString => Ok("string"),
+            _ => Err(format!("bytes.{}() has no Solidity equivalent", self.name())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_member_callee() {
+        let callee = |module: &str, name: &str| Node::Member {
+            object: Box::new(Node::Identifier(module.into())),
+            property: name.to_string(),
+        };
+        assert_eq!(BytesBuiltin::from_callee(&callee("bytes", "slice")), Some(BytesBuiltin::Slice));
+        assert_eq!(BytesBuiltin::from_callee(&callee("bytes", "reverse")), None);
+        assert_eq!(BytesBuiltin::from_callee(&callee("io", "concat")), None);
+        assert_eq!(BytesBuiltin::Concat.solidity_name(), Ok("bytes.concat"));
+        assert!(BytesBuiltin::FromHex.solidity_name().is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Conditional compilation. The driver evaluates every `@cfg(..)` against
+//! the build's target and features before any other pass: declarations whose
+//! conditions hold are kept without the attribute, the rest are dropped.
+
+use gard_ast::Node;
+use std::collections::BTreeSet;
+
+/// The native LLVM backend, and the interpreter
+pub const TARGET_NATIVE: &str = "native";
+/// `--emit wasm`, for both wasm modules and wasm contracts
+pub const TARGET_WASM32: &str = "wasm32";
+/// EVM bytecode and `--emit solidity`
+pub const TARGET_EVM: &str = "evm";
+
+const TARGETS: [&str; 3] = [TARGET_NATIVE, TARGET_WASM32, TARGET_EVM];
+
+/// What a build is compiled for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfgSet {
+    pub target: String,
+    pub features: BTreeSet<String>,
+}
+
+impl CfgSet {
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.to_string(),
+            features: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_features<S: Into<String>>(mut self, features: impl IntoIterator<Item = S>) -> Self {
+        self.features.extend(features.into_iter().map(Into::into));
+        self
+    }
+
+    /// Whether `key = "value"` holds. Unknown keys and targets are errors
+    /// rather than false, so a typo can't silently drop a declaration.
+    pub fn holds(&self, key: &str, value: &str) -> Result<bool, String> {
+        match key {
+            "target" if TARGETS.contains(&value) => Ok(self.target == value),
+            "target" => Err(format!("Unknown cfg target '{}', expected one of {}", value, TARGETS.join(", "))),
+            "feature" => Ok(self.features.contains(value)),
+            other => Err(format!("Unknown cfg key '{}', expected 'target' or 'feature'", other)),
+        }
+    }
+}
+
+/// Keeps the declarations enabled for `cfg` and drops the others.
+pub fn evaluate(mut program: Node, cfg: &CfgSet) -> Result<Node, Vec<String>> {
+    let mut errors = Vec::new();
+    evaluate_node(&mut program, cfg, &mut errors);
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+fn evaluate_node(node: &mut Node, cfg: &CfgSet, errors: &mut Vec<String>) {
+    match node {
+        Node::Program(nodes)
+        | Node::Block(nodes)
+        | Node::Class { members: nodes, .. }
+        | Node::Contract { members: nodes, .. } => {
+            *nodes = evaluate_list(std::mem::take(nodes), cfg, errors);
+        },
+        node => {
+            for child in node.children_mut() {
+                evaluate_node(child, cfg, errors);
+            }
+        },
+    }
+}
+
+fn evaluate_list(nodes: Vec<Node>, cfg: &CfgSet, errors: &mut Vec<String>) -> Vec<Node> {
+    let mut kept = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let mut node = match node {
+            Node::Cfg { conditions, declaration } => {
+                let mut enabled = true;
+                for (key, value) in &conditions {
+                    match cfg.holds(key, value) {
+                        Ok(holds) => enabled &= holds,
+                        Err(e) => errors.push(e),
+                    }
+                }
+                if !enabled {
+                    continue;
+                }
+                *declaration
+            },
+            node => node,
+        };
+        evaluate_node(&mut node, cfg, errors);
+        kept.push(node);
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::Type;
+
+    fn function(name: &str) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type: Type::Void,
+            body: Box::new(Node::Block(vec![])),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn cfg(conditions: &[(&str, &str)], declaration: Node) -> Node {
+        Node::Cfg {
+            conditions: conditions.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            declaration: Box::new(declaration),
+        }
+    }
+
+    #[test]
+    fn test_keeps_enabled_declarations() {
+        let program = Node::Program(vec![
+            function("shared"),
+            cfg(&[("target", TARGET_WASM32)], function("wasm_only")),
+            cfg(&[("target", TARGET_EVM)], function("evm_only")),
+            cfg(&[("target", TARGET_WASM32), ("feature", "logging")], function("wasm_logging")),
+            Node::Contract {
+                name: "Token".to_string(),
+                members: vec![cfg(&[("feature", "mint")], function("mint")), function("transfer")],
+                docs: None,
+            },
+        ]);
+
+        let wasm = CfgSet::new(TARGET_WASM32).with_features(["mint"]);
+        assert_eq!(evaluate(program.clone(), &wasm).unwrap(), Node::Program(vec![
+            function("shared"),
+            function("wasm_only"),
+            Node::Contract { name: "Token".to_string(), members: vec![function("mint"), function("transfer")], docs: None },
+        ]));
+
+        let evm = CfgSet::new(TARGET_EVM).with_features(["logging"]);
+        assert_eq!(evaluate(program, &evm).unwrap(), Node::Program(vec![
+            function("shared"),
+            function("evm_only"),
+            Node::Contract { name: "Token".to_string(), members: vec![function("transfer")], docs: None },
+        ]));
+    }
+
+    #[test]
+    fn test_unknown_keys_and_targets() {
+        let program = Node::Program(vec![
+            cfg(&[("target", "wasm64")], function("a")),
+            cfg(&[("os", "linux")], function("b")),
+        ]);
+        assert_eq!(evaluate(program, &CfgSet::new(TARGET_NATIVE)).unwrap_err(), vec![
+            "Unknown cfg target 'wasm64', expected one of native, wasm32, evm".to_string(),
+            "Unknown cfg key 'os', expected 'target' or 'feature'".to_string(),
+        ]);
+    }
+}
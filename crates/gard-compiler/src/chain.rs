@@ -0,0 +1,101 @@
+use crate::crypto::EvmLowering;
+use gard_ast::Node;
+
+pub const EVM_TIMESTAMP: u8 = 0x42;
+pub const EVM_NUMBER: u8 = 0x43;
+
+/// Block-environment intrinsics. `block.number` and `block.timestamp` are
+/// readable everywhere; `mine`, `advanceTime` and `warp` only exist under the
+/// chain simulator used by contract tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainIntrinsic {
+    BlockNumber,
+    BlockTimestamp,
+    Mine,
+    AdvanceTime,
+    Warp,
+}
+
+impl ChainIntrinsic {
+    /// Resolves `mine(..)`, `advanceTime(..)` and `warp(..)` callees.
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee {
+            Node::Identifier(name) => match name.as_str() {
+                "mine" => Some(ChainIntrinsic::Mine),
+                "advanceTime" => Some(ChainIntrinsic::AdvanceTime),
+                "warp" => Some(ChainIntrinsic::Warp),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Resolves `block.number` and `block.timestamp`.
+    pub fn from_member(object: &Node, property: &str) -> Option<Self> {
+        match (object, property) {
+            (Node::Identifier(block), "number") if block == "block" => Some(ChainIntrinsic::BlockNumber),
+            (Node::Identifier(block), "timestamp") if block == "block" => Some(ChainIntrinsic::BlockTimestamp),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChainIntrinsic::BlockNumber => "block.number",
+            ChainIntrinsic::BlockTimestamp => "block.timestamp",
+            ChainIntrinsic::Mine => "mine",
+            ChainIntrinsic::AdvanceTime => "advanceTime",
+            ChainIntrinsic::Warp => "warp",
+        }
+    }
+
+    /// Accepted argument counts. `mine()` mines one block, `mine(n)` mines n.
+    pub fn arity(&self) -> (usize, usize) {
+        match self {
+            ChainIntrinsic::BlockNumber | ChainIntrinsic::BlockTimestamp => (0, 0),
+            ChainIntrinsic::Mine => (0, 1),
+            ChainIntrinsic::AdvanceTime | ChainIntrinsic::Warp => (1, 1),
+        }
+    }
+
+    /// Symbol of the simulator entry point in gard-vm.
+    pub fn runtime_symbol(&self) -> &'static str {
+        match self {
+            ChainIntrinsic::BlockNumber => "gard_chain_block_number",
+            ChainIntrinsic::BlockTimestamp => "gard_chain_timestamp",
+            ChainIntrinsic::Mine => "gard_chain_mine",
+            ChainIntrinsic::AdvanceTime => "gard_chain_advance_time",
+            ChainIntrinsic::Warp => "gard_chain_warp",
+        }
+    }
+
+    pub fn evm_lowering(&self) -> Result<EvmLowering, String> {
+        match self {
+            ChainIntrinsic::BlockNumber => Ok(EvmLowering::Opcode(EVM_NUMBER)),
+            ChainIntrinsic::BlockTimestamp => Ok(EvmLowering::Opcode(EVM_TIMESTAMP)),
+            ChainIntrinsic::Mine | ChainIntrinsic::AdvanceTime | ChainIntrinsic::Warp => {
+                Err(format!("{}() is only available under the chain simulator", self.name()))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_intrinsics() {
+        let block = Node::Identifier("block".into());
+        assert_eq!(ChainIntrinsic::from_member(&block, "timestamp"), Some(ChainIntrinsic::BlockTimestamp));
+        assert_eq!(ChainIntrinsic::from_member(&block, "hash"), None);
+        assert_eq!(ChainIntrinsic::from_callee(&Node::Identifier("mine".into())), Some(ChainIntrinsic::Mine));
+        assert_eq!(ChainIntrinsic::from_callee(&Node::Identifier("mint".into())), None);
+    }
+
+    #[test]
+    fn test_simulator_only_on_evm() {
+        assert_eq!(ChainIntrinsic::BlockNumber.evm_lowering(), Ok(EvmLowering::Opcode(EVM_NUMBER)));
+        assert!(ChainIntrinsic::Mine.evm_lowering().is_err());
+    }
+}
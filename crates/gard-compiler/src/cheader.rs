@@ -0,0 +1,205 @@
+//! C headers for the functions a program exports with `@export`, so C and
+//! C++ programs can link the library `--emit staticlib` or `--emit dylib`
+//! builds from it.
+//!
+//! An exported function keeps its name as its symbol, and takes and
+//! returns values in the C ABI: ints as `int64_t`, uints as `uint64_t`,
+//! floats as `double`, booleans as `bool` and strings as NUL-terminated
+//! UTF-8. A class instance is a pointer to an incomplete struct, which C
+//! code only passes back to the library. Arrays, maps and the other types
+//! have no C equivalent yet, so functions taking or returning them can't
+//! be exported.
+
+use gard_ast::{FunctionModifier, Node, Parameter, Type};
+
+/// `@export`, which exports the function it's on
+pub const ATTRIBUTE: &str = "export";
+
+/// Handles `@export` on a function declaration, in the shape
+/// `plugin::Registry::add_attribute` takes.
+pub fn export_attribute(arguments: &[Node], declaration: Node) -> Result<Node, String> {
+    if !arguments.is_empty() {
+        return Err("@export takes no arguments; the function's name is its symbol".to_string());
+    }
+    match declaration {
+        Node::Located { span, node } => Ok(Node::Located { span, node: Box::new(export_attribute(arguments, *node)?) }),
+        Node::Function { name, params, return_type, body, mut modifiers, docs } => {
+            if !modifiers.contains(&FunctionModifier::Export) {
+                modifiers.push(FunctionModifier::Export);
+            }
+            Ok(Node::Function { name, params, return_type, body, modifiers, docs })
+        },
+        _ => Err("@export only applies to functions".to_string()),
+    }
+}
+
+/// The program's exported functions. Only top-level functions can be
+/// exported; methods have no symbol of their own.
+pub fn exports(program: &Node) -> Vec<&Node> {
+    let Node::Program(nodes) = program else {
+        return Vec::new();
+    };
+    nodes.iter()
+        .map(Node::unlocated)
+        .filter(|node| matches!(node, Node::Function { modifiers, .. } if modifiers.contains(&FunctionModifier::Export)))
+        .collect()
+}
+
+/// The header declaring the exported functions of `program`, for the
+/// library named `library`.
+pub fn generate(program: &Node, library: &str) -> Result<String, String> {
+    let exports = exports(program);
+    if exports.is_empty() {
+        return Err("Nothing to declare: no function is marked @export".to_string());
+    }
+
+    let mut structs = Vec::new();
+    let mut declarations = Vec::new();
+    let mut errors = Vec::new();
+    for function in exports {
+        let Node::Function { name, params, return_type, docs, .. } = function else { unreachable!() };
+        let mut c_type = |ty: &Type, what: String| match c_type(ty) {
+            Some(c_type) => {
+                if let Type::Custom(class) = ty {
+                    if !structs.contains(class) {
+                        structs.push(*class);
+                    }
+                }
+                c_type
+            },
+            None => {
+                errors.push(format!("Function '{}' can't be exported to C: {} has type {}, which has no C equivalent", name, what, gard_ast::type_to_source(ty)));
+                String::new()
+            },
+        };
+        let result = match return_type {
+            Type::Void => "void ".to_string(),
+            ty => c_type(ty, "its result".to_string()),
+        };
+        let params: Vec<String> = params.iter()
+            .map(|Parameter { name, type_annotation }| format!("{}{}", c_type(type_annotation, format!("parameter '{}'", name)), name))
+            .collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+
+        let mut declaration = String::new();
+        if let Some(docs) = docs {
+            declaration.push_str("/*\n");
+            for line in docs.lines() {
+                declaration.push_str(&format!(" * {}\n", line).replace(" * \n", " *\n"));
+            }
+            declaration.push_str(" */\n");
+        }
+        declaration.push_str(&format!("{}{}({});\n", result, name, params));
+        declarations.push(declaration);
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    let guard = format!("{}_H", library.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect::<String>());
+    let mut header = format!("/* C declarations of {}, generated by `gard --emit header`. */\n", library);
+    header.push_str(&format!("#ifndef {}\n#define {}\n\n", guard, guard));
+    header.push_str("#include <stdbool.h>\n#include <stdint.h>\n\n");
+    header.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+    for class in &structs {
+        header.push_str(&format!("struct {};\n", class));
+    }
+    if !structs.is_empty() {
+        header.push('\n');
+    }
+    header.push_str(&declarations.join("\n"));
+    header.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n");
+    header.push_str(&format!("#endif /* {} */\n", guard));
+    Ok(header)
+}
+
+/// `ty` in C, with the space or `*` that separates it from a name.
+fn c_type(ty: &Type) -> Option<String> {
+    Some(match ty {
+        Type::Int => "int64_t ".to_string(),
+        Type::UInt => "uint64_t ".to_string(),
+        Type::Float | Type::Double => "double ".to_string(),
+        Type::Boolean => "bool ".to_string(),
+        Type::String => "const char *".to_string(),
+        Type::Custom(class) => format!("struct {} *", class),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::Span;
+
+    fn function(name: &str, params: Vec<(&str, Type)>, return_type: Type, docs: Option<&str>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: params.into_iter().map(|(name, ty)| Parameter { name: name.into(), type_annotation: ty }).collect(),
+            return_type,
+            body: Box::new(Node::Block(vec![])),
+            modifiers: vec![],
+            docs: docs.map(str::to_string),
+        }
+    }
+
+    fn export(function: Node) -> Node {
+        let located = Node::Located { span: Span { start: 0, end: 1 }, node: Box::new(function) };
+        export_attribute(&[], located).unwrap()
+    }
+
+    #[test]
+    fn test_generate() {
+        let program = Node::Program(vec![
+            export(function("gard_add", vec![("a", Type::Int), ("b", Type::Int)], Type::Int, Some("Adds two numbers.\n\nWraps on overflow."))),
+            export(function("gard_greet", vec![("name", Type::String), ("loud", Type::Boolean)], Type::Void, None)),
+            export(function("gard_area", vec![("shape", Type::Custom("Shape".into()))], Type::Double, None)),
+            export(function("gard_version", vec![], Type::UInt, None)),
+            function("helper", vec![("xs", Type::Array(Box::new(Type::Int)))], Type::Int, None),
+        ]);
+        assert_eq!(generate(&program, "geometry-utils").unwrap(), concat!(
+            "/* C declarations of geometry-utils, generated by `gard --emit header`. */\n",
+            "#ifndef GEOMETRY_UTILS_H\n",
+            "#define GEOMETRY_UTILS_H\n",
+            "\n",
+            "#include <stdbool.h>\n",
+            "#include <stdint.h>\n",
+            "\n",
+            "#ifdef __cplusplus\n",
+            "extern \"C\" {\n",
+            "#endif\n",
+            "\n",
+            "struct Shape;\n",
+            "\n",
+            "/*\n",
+            " * Adds two numbers.\n",
+            " *\n",
+            " * Wraps on overflow.\n",
+            " */\n",
+            "int64_t gard_add(int64_t a, int64_t b);\n",
+            "\n",
+            "void gard_greet(const char *name, bool loud);\n",
+            "\n",
+            "double gard_area(struct Shape *shape);\n",
+            "\n",
+            "uint64_t gard_version(void);\n",
+            "\n",
+            "#ifdef __cplusplus\n",
+            "}\n",
+            "#endif\n",
+            "\n",
+            "#endif /* GEOMETRY_UTILS_H */\n",
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let program = Node::Program(vec![export(function("sum", vec![("xs", Type::Array(Box::new(Type::Int)))], Type::Bytes, None))]);
+        assert_eq!(generate(&program, "sum").unwrap_err(), [
+            "Function 'sum' can't be exported to C: its result has type bytes, which has no C equivalent",
+            "Function 'sum' can't be exported to C: parameter 'xs' has type array<int>, which has no C equivalent",
+        ].join("\n"));
+        assert_eq!(generate(&Node::Program(vec![]), "empty").unwrap_err(), "Nothing to declare: no function is marked @export");
+        assert_eq!(export_attribute(&[Node::Identifier("add".into())], function("f", vec![], Type::Void, None)).unwrap_err(), "@export takes no arguments; the function's name is its symbol");
+        assert_eq!(export_attribute(&[], Node::NullLiteral).unwrap_err(), "@export only applies to functions");
+    }
+}
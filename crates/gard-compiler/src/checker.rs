@@ -0,0 +1,2282 @@
+use crate::arithmetic::ArithmeticBuiltin;
+use crate::bytes::BytesBuiltin;
+use crate::chain::ChainIntrinsic;
+use crate::datetime::{self, DateTimeBuiltin};
+use crate::http::HttpBuiltin;
+use crate::io::{self, IoBuiltin};
+use crate::narrowing::{narrowings, right_operand_narrowings};
+use crate::net::{self, NetBuiltin};
+use crate::process::ProcessBuiltin;
+use crate::regex::RegexBuiltin;
+use gard_ast::{AssertionKind, BinaryOp, FunctionModifier, MatchCase, Node, Parameter, Symbol, Type, UnaryOp, type_to_source};
+use num_bigint::BigUint;
+use num_traits::Num;
+use std::collections::{HashMap, HashSet};
+
+/// Types whose values are shared safely by synchronizing every access.
+const SYNCHRONIZED: [&str; 3] = ["TVar", "Mutex", "Channel"];
+
+/// Parses a decimal or `0x`-prefixed hexadecimal uint256 literal.
+pub fn parse_uint256_literal(literal: &str) -> Result<BigUint, String> {
+    let value = match literal.strip_prefix("0x").or_else(|| literal.strip_prefix("0X")) {
+        Some(hex) => BigUint::from_str_radix(hex, 16),
+        None => BigUint::from_str_radix(literal, 10),
+    }.map_err(|_| format!("Invalid uint256 literal '{}'", literal))?;
+
+    if value.bits() > 256 {
+        return Err(format!("Literal '{}' does not fit in uint256", literal));
+    }
+    Ok(value)
+}
+
+/// Static type checks run before codegen. Expressions whose type can't be
+/// determined yet (unresolved names, calls to unknown functions) are skipped
+/// rather than reported.
+///
+/// Types are inferred in both directions: an expression's type is
+/// synthesized from its parts, so a `let` without an annotation takes the
+/// type of its initializer, and where the context expects a type (an
+/// annotated `let`, a parameter, a return value, an array element) the
+/// expression is checked against it, which types what can't be synthesized
+/// alone, like an empty array or `null`.
+///
+/// Values that cross to another task or actor, as spawn arguments, channel
+/// payloads or actor messages, must be sendable: copied (scalars, and
+/// collections of sendable values), immutable (classes whose fields are all
+/// immutable and sendable) or synchronized (`TVar`, `Mutex`, `Channel`, and
+/// actors, which only share their mailbox). A `tasklocal` can't be passed
+/// to another task at all: each task has its own, so the other task should
+/// use that.
+///
+/// A match on a union (`type Msg = Update | Logout`) must cover each
+/// variant, by name or with a `Variant(..)` pattern, or have a `_` case.
+///
+/// A variable typed as an interface holds an instance of any class that
+/// declares it `implements` it, and only the interface's methods can be
+/// used through it. Such a class must define each of them with the same
+/// signature.
+///
+/// An abstract class can't be instantiated. A concrete class must define,
+/// or inherit a definition of, each abstract method of its base classes,
+/// and an instance of it can be stored where a base class is expected.
+///
+/// A constructor of a class whose base class has a constructor taking
+/// arguments must start by calling it with `super(..)`; otherwise the base
+/// constructor runs implicitly before the body. `super.m` is the base
+/// class's member, which can't be an abstract method.
+pub struct TypeChecker {
+    /// Variables in scope, innermost scope last. Keyed by symbol, so a
+    /// lookup hashes a handle instead of the name's text.
+    scopes: Vec<HashMap<Symbol, Type>>,
+    /// Fields of every class and contract, by name
+    classes: HashMap<String, Vec<Field>>,
+    actors: HashSet<String>,
+    /// Variants of every union, by name
+    unions: HashMap<String, Vec<String>>,
+    /// The union and payload types of every union variant, by name. The
+    /// ones that aren't classes are tags of their union's own.
+    variants: HashMap<String, (String, Vec<Type>)>,
+    /// Types of the methods of every class and contract, by name
+    methods: HashMap<String, HashMap<String, Type>>,
+    /// Methods every interface declares, in order, by name
+    interfaces: HashMap<String, Vec<(String, Type)>>,
+    /// Interfaces each class implements, by class name
+    implements: HashMap<String, Vec<String>>,
+    /// Base class of each class that extends one
+    bases: HashMap<String, String>,
+    /// Abstract methods of each abstract class, in order
+    abstract_classes: HashMap<String, Vec<String>>,
+    /// Parameter types of each class's constructor, for classes with one
+    constructors: HashMap<String, Vec<Type>>,
+    /// Whether the call being checked is a constructor's leading `super(..)`
+    super_call: bool,
+    /// The class whose members are being checked, the type of `this`
+    class: Option<String>,
+    /// Return type of the function being checked
+    return_type: Option<Type>,
+    /// `scope` blocks enclosing the current statement in its function
+    task_scopes: usize,
+    /// The depth of the scope each `tasklocal` is declared in, by name
+    task_locals: HashMap<Symbol, usize>,
+    errors: Vec<String>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            classes: HashMap::new(),
+            actors: HashSet::new(),
+            unions: HashMap::from([
+                (io::STREAM.to_string(), vec![io::READER.to_string(), io::WRITER.to_string()]),
+                (net::ENDPOINT.to_string(), [net::LISTENER, net::CONNECTION, net::SOCKET].map(str::to_string).to_vec()),
+            ]),
+            variants: HashMap::new(),
+            methods: HashMap::new(),
+            interfaces: HashMap::new(),
+            implements: HashMap::new(),
+            bases: HashMap::new(),
+            abstract_classes: HashMap::new(),
+            constructors: HashMap::new(),
+            super_call: false,
+            class: None,
+            return_type: None,
+            task_scopes: 0,
+            task_locals: HashMap::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn check(mut self, program: &Node) -> Result<(), Vec<String>> {
+        self.collect_types(program);
+        self.check_node(program);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn check_node(&mut self, node: &Node) -> Option<Type> {
+        match node {
+            Node::Program(nodes) | Node::Block(nodes) => {
+                self.check_scope(nodes, true);
+                None
+            },
+            Node::Class { name, members, .. } | Node::Contract { name, members, .. } => {
+                self.check_abstract_methods(name, members);
+                self.check_implementations(name);
+                if !members.iter().any(|member| matches!(member.unlocated(), Node::Constructor { .. })) {
+                    if let Some(base) = self.base_constructor_arguments(name) {
+                        self.errors.push(format!("Class '{}' needs a constructor calling super(..), since {}'s constructor takes arguments",
+                            name, base));
+                    }
+                }
+                let class = self.class.replace(name.clone());
+                self.check_scope(members, false);
+                self.class = class;
+                None
+            },
+            Node::Actor { name, members, .. } => {
+                for member in members {
+                    if let Node::Receive { message_param, .. } = member.unlocated() {
+                        if let Err(reason) = self.sendable(&message_param.type_annotation, &mut Vec::new()) {
+                            self.errors.push(format!("Actor '{}' can't receive messages of type {:?}: {}",
+                                name, message_param.type_annotation, reason));
+                        }
+                    }
+                }
+                let class = self.class.replace(name.clone());
+                self.check_scope(members, false);
+                self.class = class;
+                None
+            },
+            Node::Function { params, return_type, body, .. } => {
+                self.scopes.push(HashMap::new());
+                for param in params {
+                    self.declare(param.name, param.type_annotation.clone());
+                }
+                // A nested function's tasks can't belong to the scopes it's declared in
+                let task_scopes = std::mem::take(&mut self.task_scopes);
+                let outer = self.return_type.replace(return_type.clone());
+                self.check_node(body);
+                self.return_type = outer;
+                self.task_scopes = task_scopes;
+                self.scopes.pop();
+                None
+            },
+            Node::Constructor { params, body } => {
+                self.check_constructor(params, body);
+                None
+            },
+            Node::Let { name, type_annotation, initializer, .. } => {
+                self.check_let(name, type_annotation.as_ref(), initializer.as_deref());
+                None
+            },
+            Node::TaskLocal { name, type_annotation, initializer } => {
+                self.check_let(name, type_annotation.as_ref(), Some(initializer));
+                self.task_locals.insert(Symbol::intern(name), self.scopes.len() - 1);
+                None
+            },
+            Node::StorageSlot { declaration, .. } | Node::WasmExport { declaration, .. } => self.check_node(declaration),
+            Node::Located { node, .. } => self.check_node(node),
+            // The parser reported it; the rest is checked as if it weren't there
+            Node::Error { .. } => None,
+            Node::If { condition, then_branch, else_branch } => {
+                self.check_node(condition);
+                self.check_narrowed(then_branch, narrowings(condition, true));
+                if let Some(else_branch) = else_branch {
+                    self.check_narrowed(else_branch, narrowings(condition, false));
+                }
+                None
+            },
+            Node::While { condition, body } => {
+                self.check_node(condition);
+                self.check_narrowed(body, narrowings(condition, true));
+                None
+            },
+            Node::DoWhile { body, condition } => {
+                self.check_node(condition);
+                self.check_node(body);
+                None
+            },
+            Node::Foreach { item, collection, body } => {
+                self.check_foreach(item, collection, body);
+                None
+            },
+            Node::Scope { body } => {
+                self.task_scopes += 1;
+                self.check_node(body);
+                self.task_scopes -= 1;
+                None
+            },
+            Node::Spawn(task) => {
+                if self.task_scopes == 0 {
+                    self.errors.push("spawn must be inside a scope, which waits for the task to finish".to_string());
+                }
+                match task.unlocated() {
+                    Node::Call { callee, arguments } => self.check_call(callee, arguments, true),
+                    other => {
+                        self.errors.push("spawn expects a function call".to_string());
+                        self.check_node(other)
+                    },
+                }
+            },
+            Node::Assertion { kind, condition, message } => {
+                self.check_assertion(*kind, condition, message.as_deref());
+                None
+            },
+            Node::Return(Some(value)) => {
+                match self.return_type.clone() {
+                    Some(Type::Void) | None => {
+                        self.check_node(value);
+                    },
+                    Some(return_type) => {
+                        if let Some(value_type) = self.mismatch(value, &return_type) {
+                            self.errors.push(format!("Cannot return a value of type {:?} from a function returning {:?}",
+                                value_type, return_type));
+                        }
+                    },
+                }
+                None
+            },
+            Node::Throw(value) => {
+                self.check_node(value);
+                None
+            },
+            Node::Match { value, cases } => {
+                self.check_match(value, cases);
+                None
+            },
+            Node::Call { callee, arguments } => self.check_call(callee, arguments, false),
+            Node::Member { object, property } => match ChainIntrinsic::from_member(object, property) {
+                Some(_) => Some(Type::UInt256),
+                None => self.check_member(object, property),
+            },
+            Node::Array { elements } => self.check_array(elements),
+            Node::Map { entries } => self.check_map(entries),
+            Node::Object { fields } => self.check_object(fields, &[]),
+            Node::This => self.class.as_deref().map(|class| Type::Custom(class.into())),
+            Node::Super => {
+                self.errors.push("'super' can only be called or have its members used".to_string());
+                None
+            },
+            Node::Index { object, index, .. } => self.check_index(object, index),
+            Node::TypeTest { value, target } => {
+                self.check_type_test(value, target);
+                Some(Type::Boolean)
+            },
+            Node::SafeCast { value, target } => {
+                self.check_type_test(value, target);
+                Some(target.clone())
+            },
+            Node::Binary { left, operator, right } => self.check_binary(left, operator, right),
+            Node::Unary { operator, operand } => self.check_unary(operator, operand),
+            Node::Identifier(name) => self.lookup(*name).or_else(|| self.tag_type(name)),
+            Node::IntLiteral(_) => Some(Type::Int),
+            Node::UIntLiteral(_) => Some(Type::UInt),
+            Node::UInt256Literal(literal) => match parse_uint256_literal(literal) {
+                Ok(_) => Some(Type::UInt256),
+                Err(error) => {
+                    self.errors.push(error);
+                    None
+                },
+            },
+            Node::FloatLiteral(_) => Some(Type::Float),
+            Node::StringLiteral(_) => Some(Type::String),
+            // Any value can be interpolated
+            Node::TemplateString { parts } => {
+                for part in parts {
+                    self.check_node(part);
+                }
+                Some(Type::String)
+            },
+            Node::BooleanLiteral(_) => Some(Type::Boolean),
+            _ => None,
+        }
+    }
+
+    /// Records the fields of classes and contracts, and which types are
+    /// actors, so sendability can look through them.
+    fn collect_types(&mut self, node: &Node) {
+        match node {
+            Node::Program(nodes) => nodes.iter().for_each(|node| self.collect_types(node)),
+            Node::Class { name, members, .. } | Node::Contract { name, members, .. } => {
+                let fields = members.iter().filter_map(Field::from_member).collect();
+                self.classes.insert(name.clone(), fields);
+                let methods = members.iter().filter_map(Self::signature).map(|(name, ty)| (name.to_string(), ty)).collect();
+                self.methods.insert(name.clone(), methods);
+                if let Node::Class { extends, implements, is_abstract, .. } = node {
+                    self.implements.insert(name.clone(), implements.clone());
+                    if let Some(base) = extends {
+                        self.bases.insert(name.clone(), base.clone());
+                    }
+                    if *is_abstract {
+                        let methods = members.iter().filter_map(Self::abstract_method).map(str::to_string).collect();
+                        self.abstract_classes.insert(name.clone(), methods);
+                    }
+                    for member in members {
+                        if let Node::Constructor { params, .. } = member.unlocated() {
+                            let params = params.iter().map(|param| param.type_annotation.clone()).collect();
+                            self.constructors.insert(name.clone(), params);
+                        }
+                    }
+                }
+            },
+            Node::Interface { name, methods } => {
+                let methods = methods.iter().map(|method| (method.name.clone(), Type::Function {
+                    params: method.params.iter().map(|param| param.type_annotation.clone()).collect(),
+                    return_type: Box::new(method.return_type.clone()),
+                })).collect();
+                self.interfaces.insert(name.clone(), methods);
+            },
+            Node::Actor { name, .. } => {
+                self.actors.insert(name.clone());
+            },
+            Node::Union { name, variants } => {
+                self.unions.insert(name.clone(), variants.iter().map(|variant| variant.name.clone()).collect());
+                for variant in variants {
+                    self.variants.insert(variant.name.clone(), (name.clone(), variant.payload.clone()));
+                }
+            },
+            Node::Located { node: declaration, .. }
+            | Node::Derive { declaration, .. }
+            | Node::Cfg { declaration, .. }
+            | Node::Attribute { declaration, .. } => self.collect_types(declaration),
+            _ => {},
+        }
+    }
+
+    /// Checks a call, and that what a spawned one or a send passes to
+    /// another task or actor is sendable.
+    fn check_call(&mut self, callee: &Node, arguments: &[Node], spawned: bool) -> Option<Type> {
+        if let Some(intrinsic) = ChainIntrinsic::from_callee(callee) {
+            return self.check_chain_call(intrinsic, arguments);
+        }
+        if let Some(builtin) = ArithmeticBuiltin::from_callee(callee.unlocated()) {
+            return self.check_arithmetic_call(builtin, arguments);
+        }
+        if let Node::Identifier(class) = callee.unlocated() {
+            if self.abstract_classes.contains_key(class.as_str()) && self.lookup(*class).is_none() {
+                self.errors.push(format!("Cannot instantiate abstract class '{}'", class));
+            }
+        }
+        let callee_type = match (Self::module_function(callee), callee.unlocated()) {
+            (Some(signature), _) => Some(signature),
+            (None, Node::Super) => self.check_super_call(),
+            (None, _) => self.check_node(callee),
+        };
+        let (params, return_type) = match callee_type {
+            Some(Type::Function { params, return_type }) => (Some(params), Some(*return_type)),
+            _ => (None, None),
+        };
+        if let Some(params) = params.as_ref().filter(|params| params.len() != arguments.len()) {
+            self.errors.push(format!("{}() expects {} argument(s), found {}",
+                Self::callee_name(callee), params.len(), arguments.len()));
+        }
+        let types: Vec<Option<Type>> = arguments.iter().enumerate().map(|(i, argument)| {
+            match params.as_ref().and_then(|params| params.get(i)) {
+                Some(param) => {
+                    let argument_type = self.check_expected(argument, Some(param));
+                    if let Some(argument_type) = argument_type.as_ref().filter(|ty| !self.is_assignable(param, ty, Some(argument.unlocated()))) {
+                        self.errors.push(format!("Argument {} of {}() expects {:?}, found {:?}",
+                            i + 1, Self::callee_name(callee), param, argument_type));
+                    }
+                    argument_type
+                },
+                None => self.check_node(argument),
+            }
+        }).collect();
+
+        if spawned {
+            // A spawned method shares its receiver with the spawner
+            if let Node::Member { object, .. } = callee.unlocated() {
+                let receiver = self.receiver_type(object);
+                self.check_sendable(receiver.as_ref(), "a spawned task");
+            }
+            for (ty, argument) in types.iter().zip(arguments) {
+                self.check_sendable(ty.as_ref(), "a spawned task");
+                self.check_task_local_escape(argument, "a spawned task");
+            }
+            return None;
+        }
+        match (callee.unlocated(), types.as_slice()) {
+            (Node::Identifier(name), [_, message]) if name == "send" => {
+                self.check_sendable(message.as_ref(), "a channel");
+                self.check_task_local_escape(&arguments[1], "a channel");
+            },
+            (Node::Member { object, property }, [message]) if property == "send" => {
+                if let Some(Type::Custom(actor)) = self.receiver_type(object) {
+                    if self.actors.contains(actor.as_str()) {
+                        self.check_sendable(message.as_ref(), &format!("actor '{}'", actor));
+                        self.check_task_local_escape(&arguments[0], &format!("actor '{}'", actor));
+                    }
+                }
+            },
+            _ => {},
+        }
+        return_type
+    }
+
+    /// Reports a task-local passed to another task, unless a local
+    /// variable of the same name hides it.
+    fn check_task_local_escape(&mut self, argument: &Node, to: &str) {
+        let Node::Identifier(name) = argument.unlocated() else {
+            return;
+        };
+        let Some(&depth) = self.task_locals.get(name) else {
+            return;
+        };
+        if !self.scopes[depth + 1..].iter().any(|scope| scope.contains_key(name)) {
+            self.errors.push(format!("Cannot send task-local '{}' to {}; each task has its own", name, to));
+        }
+    }
+
+    /// Checks a node where the variables in `narrowed` are known to have
+    /// narrower types than declared, or are bound by a match case.
+    fn check_narrowed(&mut self, node: &Node, narrowed: Vec<(Symbol, Type)>) -> Option<Type> {
+        if narrowed.is_empty() {
+            return self.check_node(node);
+        }
+        self.scopes.push(narrowed.into_iter().collect());
+        let ty = self.check_node(node);
+        self.scopes.pop();
+        ty
+    }
+
+    /// Checks `value is T` and `value as? T`: both sides must be classes or
+    /// interfaces, and two classes must be related for the test to ever
+    /// hold.
+    fn check_type_test(&mut self, value: &Node, target: &Type) {
+        let value_type = self.check_node(value);
+        let target = match target {
+            Type::Custom(name) if self.is_class_or_interface(target) => name,
+            _ => {
+                self.errors.push(format!("'is' and 'as?' need a class or interface, found {:?}", target));
+                return;
+            },
+        };
+        match value_type {
+            Some(Type::Custom(class)) if self.classes.contains_key(class.as_str()) && self.classes.contains_key(target.as_str())
+                && class != *target && !self.ancestors(&class).iter().any(|ancestor| ancestor == target) && !self.ancestors(target).iter().any(|ancestor| *ancestor == class) => {
+                self.errors.push(format!("A {} is never a {}", class, target));
+            },
+            Some(value_type) if !self.is_class_or_interface(&value_type) => {
+                self.errors.push(format!("Only class instances and interface values have types to test, found {:?}", value_type));
+            },
+            _ => {},
+        }
+    }
+
+    fn is_class_or_interface(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Custom(name) => self.classes.contains_key(name.as_str()) || self.interfaces.contains_key(name.as_str()),
+            _ => false,
+        }
+    }
+
+    /// Checks a constructor, which may only call `super(..)` as its first
+    /// statement, and must when the base constructor takes arguments.
+    fn check_constructor(&mut self, params: &[Parameter], body: &Node) {
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.declare(param.name, param.type_annotation.clone());
+        }
+        let task_scopes = std::mem::take(&mut self.task_scopes);
+        let outer = self.return_type.replace(Type::Void);
+        let statements = match body.unlocated() {
+            Node::Block(statements) => statements.as_slice(),
+            body => std::slice::from_ref(body),
+        };
+        let calls_super = statements.first().is_some_and(Self::is_super_call);
+        if let (false, Some(class)) = (calls_super, self.class.clone()) {
+            if let Some(base) = self.base_constructor_arguments(&class) {
+                self.errors.push(format!("Constructor of '{}' must call super(..) first, since {}'s constructor takes arguments",
+                    class, base));
+            }
+        }
+        self.scopes.push(HashMap::new());
+        for (i, statement) in statements.iter().enumerate() {
+            self.super_call = i == 0 && calls_super;
+            self.check_node(statement);
+        }
+        self.super_call = false;
+        self.scopes.pop();
+        self.return_type = outer;
+        self.task_scopes = task_scopes;
+        self.scopes.pop();
+    }
+
+    /// Whether a statement is a `super(..)` call.
+    fn is_super_call(statement: &Node) -> bool {
+        match statement.unlocated() {
+            Node::Block(expressions) if expressions.len() == 1 => Self::is_super_call(&expressions[0]),
+            Node::Call { callee, .. } => matches!(callee.unlocated(), Node::Super),
+            _ => false,
+        }
+    }
+
+    /// The base class of a class, when its constructor takes arguments.
+    fn base_constructor_arguments(&self, class: &str) -> Option<String> {
+        let base = self.bases.get(class)?;
+        self.constructors.get(base).filter(|params| !params.is_empty()).map(|_| base.clone())
+    }
+
+    /// The base class `super` refers to in the class being checked.
+    fn super_class(&mut self) -> Option<String> {
+        let Some(class) = self.class.clone() else {
+            self.errors.push("'super' used outside a class".to_string());
+            return None;
+        };
+        let base = self.bases.get(&class).cloned();
+        if base.is_none() {
+            self.errors.push(format!("'super' used in class '{}', which has no base class", class));
+        }
+        base
+    }
+
+    /// Type of `super` as a callee: the base class's constructor.
+    fn check_super_call(&mut self) -> Option<Type> {
+        if !std::mem::take(&mut self.super_call) {
+            self.errors.push("super(..) can only be the first statement of a constructor".to_string());
+            return None;
+        }
+        let base = self.super_class()?;
+        let params = self.constructors.get(&base).cloned().unwrap_or_default();
+        Some(Type::Function { params, return_type: Box::new(Type::Void) })
+    }
+
+    /// Type of `super.property`: the field or method of the nearest base
+    /// class that has it.
+    fn check_super_member(&mut self, property: &str) -> Option<Type> {
+        let base = self.super_class()?;
+        for class in std::iter::once(base.clone()).chain(self.ancestors(&base)) {
+            if let Some(field) = self.classes.get(&class).and_then(|fields| fields.iter().find(|field| field.name == property)) {
+                return field.ty.clone();
+            }
+            if let Some(method) = self.methods.get(&class).and_then(|methods| methods.get(property)).cloned() {
+                if self.abstract_classes.get(&class).is_some_and(|methods| methods.iter().any(|name| name == property)) {
+                    self.errors.push(format!("Cannot call abstract method '{}' of {} through super", property, class));
+                }
+                return Some(method);
+            }
+        }
+        None
+    }
+
+    /// Type of a standard library function such as `regex.find`; the
+    /// modules aren't variables.
+    fn module_function(callee: &Node) -> Option<Type> {
+        RegexBuiltin::from_callee(callee).map(|builtin| builtin.signature())
+            .or_else(|| DateTimeBuiltin::from_callee(callee).map(|builtin| builtin.signature()))
+            .or_else(|| BytesBuiltin::from_callee(callee).map(|builtin| builtin.signature()))
+            .or_else(|| IoBuiltin::from_callee(callee).map(|builtin| builtin.signature()))
+            .or_else(|| NetBuiltin::from_callee(callee).map(|builtin| builtin.signature()))
+            .or_else(|| HttpBuiltin::from_callee(callee).map(|builtin| builtin.signature()))
+            .or_else(|| ProcessBuiltin::from_callee(callee).map(|builtin| builtin.signature()))
+    }
+
+    fn callee_name(callee: &Node) -> String {
+        match callee.unlocated() {
+            Node::Identifier(name) => name.to_string(),
+            Node::Member { property, .. } => property.clone(),
+            Node::Super => "super".to_string(),
+            _ => "function".to_string(),
+        }
+    }
+
+    /// Type of a field or method of a class instance, a method of an
+    /// interface value or a string, or of the length of an array or bytes.
+    fn check_member(&mut self, object: &Node, property: &str) -> Option<Type> {
+        if let Node::Super = object.unlocated() {
+            return self.check_super_member(property);
+        }
+        match self.check_node(object)? {
+            Type::Array(_) | Type::String | Type::Bytes if property == "length" => Some(Type::Int),
+            Type::String => Self::string_method(property),
+            Type::Custom(interface) if self.interfaces.contains_key(interface.as_str()) => {
+                let method = self.interfaces[interface.as_str()].iter().find(|(name, _)| name == property);
+                if method.is_none() {
+                    self.errors.push(format!("'{}' is not a method of interface {}", property, interface));
+                }
+                method.map(|(_, ty)| ty.clone())
+            },
+            Type::Object(fields) => {
+                let field = fields.iter().find(|(name, _)| name == property);
+                if field.is_none() {
+                    self.errors.push(format!("Object of type {} has no field '{}'", type_to_source(&Type::Object(fields.clone())), property));
+                }
+                field.map(|(_, ty)| ty.clone())
+            },
+            Type::Custom(class) => {
+                let field = self.classes.get(class.as_str())?.iter().find(|field| field.name == property);
+                match field {
+                    Some(field) => field.ty.clone(),
+                    None => self.methods.get(class.as_str())?.get(property).cloned(),
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Types of the methods strings have at runtime.
+    fn string_method(name: &str) -> Option<Type> {
+        let (params, return_type) = match name {
+            "substring" => (vec![Type::Int, Type::Int], Type::String),
+            "indexOf" => (vec![Type::String], Type::Int),
+            "split" => (vec![Type::String], Type::Array(Box::new(Type::String))),
+            "startsWith" | "endsWith" => (vec![Type::String], Type::Boolean),
+            "trim" | "toUpper" | "toLower" => (vec![], Type::String),
+            "chars" => (vec![], Type::Array(Box::new(Type::String))),
+            _ => return None,
+        };
+        Some(Type::Function { params, return_type: Box::new(return_type) })
+    }
+
+    /// An array literal's type is the type its elements share.
+    fn check_array(&mut self, elements: &[Node]) -> Option<Type> {
+        let (first, rest) = elements.split_first()?;
+        let element_type = self.check_node(first);
+        for element in rest {
+            match (&element_type, self.check_node(element)) {
+                (Some(expected), Some(found)) if !self.is_assignable(expected, &found, Some(element.unlocated())) => {
+                    self.errors.push(format!("Array elements must share a type, found {:?} and {:?}", expected, found));
+                },
+                _ => {},
+            }
+        }
+        element_type.map(|ty| Type::Array(Box::new(ty)))
+    }
+
+    /// An object literal's type has its fields, typed by their values, or
+    /// by the fields of the same name `expected` has.
+    fn check_object(&mut self, fields: &[(String, Node)], expected: &[(String, Type)]) -> Option<Type> {
+        let mut types = Vec::new();
+        for (name, value) in fields {
+            if types.iter().any(|(field, _)| field == name) {
+                self.errors.push(format!("Field '{}' is given more than once", name));
+            }
+            let expected = expected.iter().find(|(field, _)| field == name).map(|(_, ty)| ty);
+            types.push((name.clone(), self.check_expected(value, expected)));
+        }
+        types.into_iter()
+            .map(|(name, ty)| ty.map(|ty| (name, ty)))
+            .collect::<Option<_>>()
+            .map(Type::Object)
+    }
+
+    fn check_map(&mut self, entries: &[(Node, Node)]) -> Option<Type> {
+        let types: Vec<_> = entries.iter().map(|(key, value)| (self.check_node(key), self.check_node(value))).collect();
+        match types.into_iter().next()? {
+            (Some(key), Some(value)) => Some(Type::Map { key: Box::new(key), value: Box::new(value) }),
+            _ => None,
+        }
+    }
+
+    /// Checks an expression against the type its context expects. Besides
+    /// what `check_node` synthesizes, the expected type is given to what
+    /// can't be typed alone: empty arrays and maps, `null`, non-negative
+    /// integer literals where an unsigned type is expected, the elements
+    /// of array and map literals, and the fields of object literals.
+    fn check_expected(&mut self, node: &Node, expected: Option<&Type>) -> Option<Type> {
+        let Some(expected) = expected else {
+            return self.check_node(node);
+        };
+        match (node, expected) {
+            (Node::Located { node, .. }, _) => self.check_expected(node, Some(expected)),
+            (Node::IntLiteral(value), Type::UInt | Type::UInt256) if *value >= 0 => Some(expected.clone()),
+            (Node::NullLiteral, Type::Custom(_)) => Some(expected.clone()),
+            (Node::Array { elements }, Type::Array(element_type)) => {
+                for element in elements {
+                    if let Some(found) = self.mismatch(element, element_type) {
+                        self.errors.push(format!("Array element of type {:?} in an array of {:?}", found, element_type));
+                    }
+                }
+                Some(expected.clone())
+            },
+            (Node::Map { entries }, Type::Map { key: key_type, value: value_type }) => {
+                for (key, value) in entries {
+                    if let Some(found) = self.mismatch(key, key_type) {
+                        self.errors.push(format!("Map key of type {:?} in a map keyed by {:?}", found, key_type));
+                    }
+                    if let Some(found) = self.mismatch(value, value_type) {
+                        self.errors.push(format!("Map value of type {:?} in a map of {:?}", found, value_type));
+                    }
+                }
+                Some(expected.clone())
+            },
+            (Node::Object { fields }, Type::Object(expected)) => self.check_object(fields, expected),
+            _ => self.check_node(node),
+        }
+    }
+
+    /// Checks `node` against `target`, and returns its type if that isn't
+    /// assignable to `target`.
+    fn mismatch(&mut self, node: &Node, target: &Type) -> Option<Type> {
+        let value = self.check_expected(node, Some(target))?;
+        (!self.is_assignable(target, &value, Some(node.unlocated()))).then_some(value)
+    }
+
+    fn receiver_type(&self, object: &Node) -> Option<Type> {
+        match object.unlocated() {
+            Node::This => self.class.as_deref().map(|class| Type::Custom(class.into())),
+            Node::Identifier(name) => self.lookup(*name),
+            _ => None,
+        }
+    }
+
+    fn check_sendable(&mut self, ty: Option<&Type>, to: &str) {
+        let Some(ty) = ty else {
+            return;
+        };
+        if let Err(reason) = self.sendable(ty, &mut Vec::new()) {
+            self.errors.push(format!("Cannot send a value of type {:?} to {}: {}", ty, to, reason));
+        }
+    }
+
+    /// Why values of a type can't be shared with another task, if they
+    /// can't. `visiting` holds the classes being looked through, which a
+    /// recursive field assumes sendable.
+    fn sendable(&self, ty: &Type, visiting: &mut Vec<String>) -> Result<(), String> {
+        match ty {
+            Type::Array(element) | Type::Set(element) => self.sendable(element, visiting),
+            Type::Map { key, value } => {
+                self.sendable(key, visiting)?;
+                self.sendable(value, visiting)
+            },
+            Type::Object(fields) => {
+                for (name, ty) in fields {
+                    self.sendable(ty, visiting).map_err(|reason| format!("field '{}': {}", name, reason))?;
+                }
+                Ok(())
+            },
+            Type::Function { .. } => Err("functions can capture mutable state".to_string()),
+            Type::Custom(name) if SYNCHRONIZED.contains(&name.as_str()) || self.actors.contains(name.as_str()) => Ok(()),
+            Type::Custom(name) if self.unions.contains_key(name.as_str()) => {
+                for variant in &self.unions[name.as_str()] {
+                    let payload = match self.tag(variant) {
+                        Some((_, payload)) => payload.clone(),
+                        None => vec![Type::Custom(variant.into())],
+                    };
+                    for ty in &payload {
+                        self.sendable(ty, visiting).map_err(|reason| format!("{} variant {}: {}", name, variant, reason))?;
+                    }
+                }
+                Ok(())
+            },
+            Type::Custom(name) if self.interfaces.contains_key(name.as_str()) => {
+                for class in self.implementations(name) {
+                    self.sendable(&Type::Custom(class.as_str().into()), visiting)
+                        .map_err(|reason| format!("{} implementation {}: {}", name, class, reason))?;
+                }
+                Ok(())
+            },
+            Type::Custom(name) => {
+                // Unknown types are skipped, like unresolved names
+                let Some(fields) = self.classes.get(name.as_str()) else {
+                    return Ok(());
+                };
+                if visiting.iter().any(|visited| visited == name) {
+                    return Ok(());
+                }
+                visiting.push(name.to_string());
+                for field in fields {
+                    if field.is_mutable {
+                        return Err(format!("{} has mutable field '{}'", name, field.name));
+                    }
+                    if let Some(ty) = &field.ty {
+                        self.sendable(ty, visiting).map_err(|reason| format!("{}.{}: {}", name, field.name, reason))?;
+                    }
+                }
+                visiting.pop();
+                Ok(())
+            },
+            // Scalars are copied
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that a class defines every method of the interfaces it
+    /// implements, with the declared signature.
+    fn check_implementations(&mut self, class: &str) {
+        // An abstract class leaves them to its subclasses
+        let Some(interfaces) = self.implements.get(class).filter(|_| !self.abstract_classes.contains_key(class)) else {
+            return;
+        };
+        let mut errors = Vec::new();
+        for interface in interfaces {
+            // Unknown interfaces are skipped, like unresolved names
+            for (method, declared) in self.interfaces.get(interface).into_iter().flatten() {
+                match self.concrete_method(class, method) {
+                    None => errors.push(format!("Class '{}' does not implement '{}' of interface {}",
+                        class, method, interface)),
+                    Some(defined) if defined != *declared => errors.push(format!(
+                        "Method '{}' of class '{}' has type {:?}, but interface {} declares {:?}",
+                        method, class, defined, interface, declared)),
+                    Some(_) => {},
+                }
+            }
+        }
+        self.errors.extend(errors);
+    }
+
+    /// Checks that only abstract classes declare abstract methods, and that
+    /// a concrete class has a definition of each abstract method of its
+    /// base classes, with the declared signature.
+    fn check_abstract_methods(&mut self, class: &str, members: &[Node]) {
+        if self.abstract_classes.contains_key(class) {
+            return;
+        }
+        for method in members.iter().filter_map(Self::abstract_method) {
+            self.errors.push(format!("Class '{}' declares abstract method '{}' but isn't abstract", class, method));
+        }
+        let mut errors = Vec::new();
+        for base in self.ancestors(class) {
+            for method in self.abstract_classes.get(&base).into_iter().flatten() {
+                let declared = self.methods.get(&base).and_then(|methods| methods.get(method));
+                match (self.concrete_method(class, method), declared) {
+                    (None, _) => errors.push(format!("Class '{}' does not implement abstract method '{}' of {}",
+                        class, method, base)),
+                    (Some(defined), Some(declared)) if defined != *declared => errors.push(format!(
+                        "Method '{}' of class '{}' has type {:?}, but {} declares {:?}",
+                        method, class, defined, base, declared)),
+                    _ => {},
+                }
+            }
+        }
+        self.errors.extend(errors);
+    }
+
+    /// The name of an abstract method declaration.
+    fn abstract_method(member: &Node) -> Option<&str> {
+        match member.unlocated() {
+            Node::Function { name, modifiers, .. } if modifiers.contains(&FunctionModifier::Abstract) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Base classes of a class, nearest first.
+    fn ancestors(&self, class: &str) -> Vec<String> {
+        let mut ancestors: Vec<String> = Vec::new();
+        let mut class = class;
+        while let Some(base) = self.bases.get(class) {
+            // An inheritance cycle ends the chain
+            if ancestors.contains(base) {
+                break;
+            }
+            ancestors.push(base.clone());
+            class = base;
+        }
+        ancestors
+    }
+
+    /// Type of the definition of a method that a class's instances run: its
+    /// own or the nearest base class's, unless that one is abstract.
+    fn concrete_method(&self, class: &str, method: &str) -> Option<Type> {
+        for class in std::iter::once(class.to_string()).chain(self.ancestors(class)) {
+            if let Some(ty) = self.methods.get(&class).and_then(|methods| methods.get(method)) {
+                let is_abstract = self.abstract_classes.get(&class).is_some_and(|methods| methods.iter().any(|name| name == method));
+                return (!is_abstract).then(|| ty.clone());
+            }
+        }
+        None
+    }
+
+    /// Classes implementing an interface, by name.
+    fn implementations(&self, interface: &str) -> Vec<String> {
+        let mut classes: Vec<String> = self.implements.iter()
+            .filter(|(_, interfaces)| interfaces.iter().any(|implemented| implemented == interface))
+            .map(|(class, _)| class.clone())
+            .collect();
+        classes.sort();
+        classes
+    }
+
+    /// Checks a block, or the members of a class with `functions` unset. A
+    /// block's functions are declared first, so calls can come before them.
+    fn check_scope(&mut self, nodes: &[Node], functions: bool) {
+        self.scopes.push(HashMap::new());
+        if functions {
+            for (name, ty) in nodes.iter().filter_map(Self::signature) {
+                self.declare(name, ty);
+            }
+        }
+        for node in nodes {
+            self.check_node(node);
+        }
+        self.scopes.pop();
+    }
+
+    /// Checks each case, and that a match on a union covers its variants.
+    fn check_match(&mut self, value: &Node, cases: &[MatchCase]) {
+        let union = match self.check_node(value) {
+            Some(Type::Custom(name)) => self.unions.get(name.as_str()).cloned().map(|variants| (name, variants)),
+            _ => None,
+        };
+        let mut covered = HashSet::new();
+        let mut wildcard = false;
+        for MatchCase { pattern, body } in cases {
+            match (Self::variant(pattern), &union) {
+                (Some("_"), _) => wildcard = true,
+                (Some(variant), Some((name, variants))) => {
+                    if variants.iter().any(|known| known == variant) {
+                        covered.insert(variant.to_string());
+                    } else {
+                        self.errors.push(format!("'{}' is not a variant of {}", variant, name));
+                    }
+                },
+                _ => {
+                    self.check_node(pattern);
+                },
+            }
+            let bindings = self.bindings(pattern);
+            self.check_narrowed(body, bindings);
+        }
+
+        if let (Some((name, variants)), false) = (union, wildcard) {
+            let missing: Vec<&str> = variants.iter()
+                .filter(|variant| !covered.contains(*variant))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() {
+                self.errors.push(format!("Match on {} does not cover {}", name, missing.join(", ")));
+            }
+        }
+    }
+
+    /// The variables a `Tag(binding, ..)` pattern binds to the tag's payload.
+    fn bindings(&mut self, pattern: &Node) -> Vec<(Symbol, Type)> {
+        let Node::Call { callee, arguments } = pattern.unlocated() else {
+            return Vec::new();
+        };
+        let Some((variant, (union, payload))) = Self::variant(callee).and_then(|name| Some((name, self.tag(name)?.clone()))) else {
+            return Vec::new();
+        };
+        if payload.len() != arguments.len() {
+            self.errors.push(format!("Variant '{}' of {} has {} value(s), found {} binding(s)",
+                variant, union, payload.len(), arguments.len()));
+        }
+        arguments.iter().zip(payload).filter_map(|(argument, ty)| match argument.unlocated() {
+            Node::Identifier(name) if name == "_" => None,
+            Node::Identifier(name) => Some((*name, ty)),
+            _ => {
+                self.errors.push(format!("A pattern of variant '{}' can only bind names", variant));
+                None
+            },
+        }).collect()
+    }
+
+    /// The union and payload of a variant that is a tag of its union's own,
+    /// not a class.
+    fn tag(&self, name: &str) -> Option<&(String, Vec<Type>)> {
+        self.variants.get(name).filter(|_| !self.classes.contains_key(name) && !self.interfaces.contains_key(name))
+    }
+
+    /// The type of a tag written as a value: its union, or a function
+    /// constructing one from the payload.
+    fn tag_type(&self, name: &str) -> Option<Type> {
+        let (union, payload) = self.tag(name)?;
+        let union = Type::Custom(union.into());
+        match payload.is_empty() {
+            true => Some(union),
+            false => Some(Type::Function { params: payload.clone(), return_type: Box::new(union) }),
+        }
+    }
+
+    /// The variant a match pattern names: `Variant`, `Variant(binding, ..)`
+    /// or `_`.
+    fn variant(pattern: &Node) -> Option<&str> {
+        match pattern.unlocated() {
+            Node::Identifier(name) => Some(name),
+            Node::Call { callee, .. } => match callee.unlocated() {
+                Node::Identifier(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The function declared by a statement or member, with its type.
+    fn signature(node: &Node) -> Option<(Symbol, Type)> {
+        match node {
+            Node::Function { name, params, return_type, .. } => Some((Symbol::intern(name), Type::Function {
+                params: params.iter().map(|param| param.type_annotation.clone()).collect(),
+                return_type: Box::new(return_type.clone()),
+            })),
+            Node::Located { node: declaration, .. }
+            | Node::WasmExport { declaration, .. }
+            | Node::Derive { declaration, .. }
+            | Node::Cfg { declaration, .. }
+            | Node::Attribute { declaration, .. } => Self::signature(declaration),
+            _ => None,
+        }
+    }
+
+    fn check_let(&mut self, name: &str, type_annotation: Option<&Type>, initializer: Option<&Node>) {
+        let value_type = initializer.and_then(|init| self.check_expected(init, type_annotation));
+
+        let declared = match (type_annotation, value_type) {
+            (Some(target), Some(value)) => {
+                if !self.is_assignable(target, &value, initializer.map(Node::unlocated)) {
+                    self.errors.push(format!("Cannot assign a value of type {:?} to '{}' of type {:?}",
+                        value, name, target));
+                }
+                Some(target.clone())
+            },
+            (Some(target), None) => Some(target.clone()),
+            (None, value) => value,
+        };
+
+        if let Some(ty) = declared {
+            self.declare(Symbol::intern(name), ty);
+        }
+    }
+
+    fn check_assertion(&mut self, kind: AssertionKind, condition: &Node, message: Option<&Node>) {
+        let name = match kind {
+            AssertionKind::Validate => "validate",
+            AssertionKind::Require => "require",
+            AssertionKind::Assert => "assert",
+        };
+
+        if let Some(condition_type) = self.check_node(condition) {
+            if condition_type != Type::Boolean {
+                self.errors.push(format!("{}() condition must be boolean, found {:?}", name, condition_type));
+            }
+        }
+
+        match message {
+            Some(message) => {
+                if let Some(message_type) = self.check_node(message) {
+                    if message_type != Type::String {
+                        self.errors.push(format!("{}() reason must be a string, found {:?}", name, message_type));
+                    }
+                }
+            },
+            None if kind != AssertionKind::Assert => {
+                self.errors.push(format!("{}() requires a reason string", name));
+            },
+            None => {},
+        }
+    }
+
+    fn check_chain_call(&mut self, intrinsic: ChainIntrinsic, arguments: &[Node]) -> Option<Type> {
+        let (min, max) = intrinsic.arity();
+        if arguments.len() < min || arguments.len() > max {
+            self.errors.push(format!("{}() expects {} argument(s), found {}",
+                intrinsic.name(), if min == max { min.to_string() } else { format!("{} to {}", min, max) },
+                arguments.len()));
+        }
+
+        for argument in arguments {
+            if let Some(argument_type) = self.check_node(argument) {
+                if !self.is_assignable(&Type::UInt, &argument_type, Some(argument)) {
+                    self.errors.push(format!("{}() expects a uint argument, found {:?}",
+                        intrinsic.name(), argument_type));
+                }
+            }
+        }
+
+        match intrinsic {
+            ChainIntrinsic::Warp => Some(Type::Boolean),
+            _ => None,
+        }
+    }
+
+    fn check_arithmetic_call(&mut self, builtin: ArithmeticBuiltin, arguments: &[Node]) -> Option<Type> {
+        let [left, right] = arguments else {
+            self.errors.push(format!("{}() expects 2 argument(s), found {}", builtin.name(), arguments.len()));
+            return None;
+        };
+        let (left_type, right_type) = (self.check_node(left)?, self.check_node(right)?);
+        match Self::numeric_join(&left_type, left, &right_type, right) {
+            Some(ty @ (Type::Int | Type::UInt | Type::UInt256)) => Some(ty),
+            _ => {
+                self.errors.push(format!("{}() expects integer arguments, found {:?} and {:?}",
+                    builtin.name(), left_type, right_type));
+                None
+            },
+        }
+    }
+
+    fn check_index(&mut self, object: &Node, index: &Node) -> Option<Type> {
+        let object_type = self.check_node(object);
+        match self.check_node(index) {
+            Some(Type::Int | Type::UInt) | None => {},
+            Some(index_type) => self.errors.push(format!("Index must be an integer, found {:?}", index_type)),
+        }
+        match object_type? {
+            Type::Array(element) => Some(*element),
+            Type::Bytes => Some(Type::Int),
+            // The character at the index, counting characters rather than bytes
+            Type::String => Some(Type::String),
+            object_type => {
+                self.errors.push(format!("Cannot index a value of type {:?}", object_type));
+                None
+            },
+        }
+    }
+
+    /// `foreach (item in collection)`: an array or set gives its elements,
+    /// bytes their values and a string its characters, each a string.
+    fn check_foreach(&mut self, item: &str, collection: &Node, body: &Node) {
+        let item_type = match self.check_node(collection) {
+            Some(Type::Array(element) | Type::Set(element)) => Some(*element),
+            Some(Type::Bytes) => Some(Type::Int),
+            Some(Type::String) => Some(Type::String),
+            Some(other) => {
+                self.errors.push(format!("Cannot iterate over a value of type {:?}", other));
+                None
+            },
+            None => None,
+        };
+        self.scopes.push(HashMap::new());
+        if let Some(item_type) = item_type {
+            self.declare(Symbol::intern(item), item_type);
+        }
+        self.check_node(body);
+        self.scopes.pop();
+    }
+
+    fn check_binary(&mut self, left: &Node, operator: &BinaryOp, right: &Node) -> Option<Type> {
+        let left_type = self.check_node(left);
+        let right_type = self.check_narrowed(right, right_operand_narrowings(left, operator));
+
+        let (left_type, right_type) = match (left_type, right_type) {
+            (Some(left_type), Some(right_type)) => (left_type, right_type),
+            _ => return match operator {
+                BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::LtEq |
+                BinaryOp::Gt | BinaryOp::GtEq | BinaryOp::And | BinaryOp::Or => Some(Type::Boolean),
+                _ => None,
+            },
+        };
+        if let Some(result) = datetime::binary(operator, &left_type, &right_type) {
+            return result.map_err(|error| self.errors.push(error)).ok();
+        }
+
+        match operator {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                if left_type == Type::Address || right_type == Type::Address {
+                    self.errors.push(format!("Arithmetic operator {:?} is not defined on address values", operator));
+                    return None;
+                }
+                if left_type == right_type && matches!(left_type, Type::String | Type::Bytes) && *operator == BinaryOp::Add {
+                    return Some(left_type);
+                }
+                let joined = Self::numeric_join(&left_type, left, &right_type, right);
+                if joined.is_none() {
+                    self.errors.push(format!("Operator {:?} cannot be applied to {:?} and {:?}",
+                        operator, left_type, right_type));
+                }
+                joined
+            },
+            BinaryOp::Eq | BinaryOp::NotEq => {
+                if left_type != right_type && Self::numeric_join(&left_type, left, &right_type, right).is_none() {
+                    self.errors.push(format!("Cannot compare {:?} with {:?}{}", left_type, right_type,
+                        Self::character_hint(&left_type, &right_type)));
+                }
+                Some(Type::Boolean)
+            },
+            BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => {
+                if Self::numeric_join(&left_type, left, &right_type, right).is_none() {
+                    self.errors.push(format!("Cannot order {:?} and {:?}{}", left_type, right_type,
+                        Self::character_hint(&left_type, &right_type)));
+                }
+                Some(Type::Boolean)
+            },
+            BinaryOp::And | BinaryOp::Or => {
+                if left_type != Type::Boolean || right_type != Type::Boolean {
+                    self.errors.push(format!("Operator {:?} expects boolean operands, found {:?} and {:?}",
+                        operator, left_type, right_type));
+                }
+                Some(Type::Boolean)
+            },
+            BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor => {
+                let joined = Self::numeric_join(&left_type, left, &right_type, right).filter(Self::is_integer);
+                if joined.is_none() {
+                    self.errors.push(format!("Operator {:?} expects integer operands, found {:?} and {:?}",
+                        operator, left_type, right_type));
+                }
+                joined
+            },
+            // The shift amount needn't have the shifted value's type
+            BinaryOp::Shl | BinaryOp::Shr => {
+                if !Self::is_integer(&left_type) || !Self::is_integer(&right_type) {
+                    self.errors.push(format!("Operator {:?} expects integer operands, found {:?} and {:?}",
+                        operator, left_type, right_type));
+                    return None;
+                }
+                Some(left_type)
+            },
+            BinaryOp::NullCoalesce => Some(left_type),
+        }
+    }
+
+    fn check_unary(&mut self, operator: &UnaryOp, operand: &Node) -> Option<Type> {
+        let operand_type = self.check_node(operand)?;
+        match operator {
+            UnaryOp::Minus if matches!(operand_type, Type::UInt | Type::UInt256 | Type::Address) => {
+                self.errors.push(format!("Cannot negate unsigned value of type {:?}", operand_type));
+                None
+            },
+            UnaryOp::Not if operand_type != Type::Boolean => {
+                self.errors.push(format!("Operator ! expects a boolean operand, found {:?}", operand_type));
+                None
+            },
+            UnaryOp::BitNot if !Self::is_integer(&operand_type) => {
+                self.errors.push(format!("Operator ~ expects an integer operand, found {:?}", operand_type));
+                None
+            },
+            UnaryOp::Increment | UnaryOp::Decrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement => {
+                let symbol = if matches!(operator, UnaryOp::Increment | UnaryOp::PostIncrement) { "++" } else { "--" };
+                if !matches!(operand.unlocated(), Node::Identifier(_) | Node::Member { .. } | Node::Index { .. }) {
+                    self.errors.push(format!("Operator {} needs a variable, field or element", symbol));
+                    return None;
+                }
+                if !matches!(operand_type, Type::Int | Type::UInt | Type::UInt256 | Type::Float | Type::Double) {
+                    self.errors.push(format!("Operator {} expects a numeric operand, found {:?}", symbol, operand_type));
+                    return None;
+                }
+                Some(operand_type)
+            },
+            _ => Some(operand_type),
+        }
+    }
+
+    fn is_integer(ty: &Type) -> bool {
+        matches!(ty, Type::Int | Type::UInt | Type::UInt256)
+    }
+
+    /// Strings are indexed and iterated by character, so a string meeting
+    /// an integer is likely a character taken for a byte or code point.
+    fn character_hint(left: &Type, right: &Type) -> &'static str {
+        let mixed = |text: &Type, number: &Type| *text == Type::String && Self::is_integer(number);
+        if mixed(left, right) || mixed(right, left) {
+            "; a string's elements are its characters, as strings like \"a\", and bytes.fromString(..) gives its UTF-8 bytes"
+        } else {
+            ""
+        }
+    }
+
+    /// Common numeric type of two operands. Non-negative integer literals adopt
+    /// the unsigned type of the other side, and uint widens to uint256.
+    fn numeric_join(left_type: &Type, left: &Node, right_type: &Type, right: &Node) -> Option<Type> {
+        let is_numeric = |ty: &Type| matches!(ty, Type::Int | Type::UInt | Type::UInt256 | Type::Float | Type::Double);
+
+        match (left_type, right_type) {
+            (l, r) if l == r && is_numeric(l) => Some(l.clone()),
+            (Type::UInt256, Type::UInt) | (Type::UInt, Type::UInt256) => Some(Type::UInt256),
+            (Type::UInt256 | Type::UInt, Type::Int) if Self::is_unsigned_literal(right) => Some(left_type.clone()),
+            (Type::Int, Type::UInt256 | Type::UInt) if Self::is_unsigned_literal(left) => Some(right_type.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether a value of type `value` can be stored in a `target`. A union
+    /// takes a value of any of its variants, an interface an instance of
+    /// any class implementing it, and a class an instance of a subclass.
+    fn is_assignable(&self, target: &Type, value: &Type, initializer: Option<&Node>) -> bool {
+        match (target, value) {
+            (target, value) if target == value => true,
+            (Type::UInt256, Type::UInt) => true,
+            (Type::UInt256 | Type::UInt, Type::Int) => initializer.is_some_and(Self::is_unsigned_literal),
+            (Type::Address, Type::UInt256) => match initializer {
+                Some(Node::UInt256Literal(literal)) => {
+                    parse_uint256_literal(literal).is_ok_and(|value| value.bits() <= 160)
+                },
+                _ => false,
+            },
+            (Type::Custom(union), Type::Custom(variant)) if self.unions.contains_key(union.as_str()) => {
+                self.unions[union.as_str()].iter().any(|member| member == variant)
+            },
+            (Type::Custom(interface), Type::Custom(class)) if self.interfaces.contains_key(interface.as_str()) => {
+                self.implements.get(class.as_str()).is_some_and(|interfaces| interfaces.iter().any(|implemented| implemented == interface))
+            },
+            (Type::Custom(base), Type::Custom(class)) => self.ancestors(class).iter().any(|ancestor| ancestor == base),
+            // An object can have more fields than the type needs
+            (Type::Object(target), Type::Object(value)) => target.iter().all(|(name, ty)| {
+                value.iter().any(|(field, value)| field == name && self.is_assignable(ty, value, None))
+            }),
+            _ => false,
+        }
+    }
+
+    fn is_unsigned_literal(node: &Node) -> bool {
+        matches!(node, Node::IntLiteral(value) if *value >= 0) || matches!(node, Node::UIntLiteral(_))
+    }
+
+    fn declare(&mut self, name: Symbol, ty: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, ty);
+        }
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<Type> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(&name).cloned())
+    }
+}
+
+struct Field {
+    name: String,
+    ty: Option<Type>,
+    is_mutable: bool,
+}
+
+impl Field {
+    fn from_member(member: &Node) -> Option<Self> {
+        match member.unlocated() {
+            Node::Let { name, type_annotation, is_mutable, .. } => Some(Self {
+                name: name.clone(),
+                ty: type_annotation.clone(),
+                is_mutable: *is_mutable,
+            }),
+            Node::StorageSlot { declaration, .. } => Self::from_member(declaration),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::UnionVariant;
+
+    fn ident(name: &str) -> Node {
+        Node::Identifier(name.into())
+    }
+
+    fn let_typed(name: &str, ty: Type, initializer: Node) -> Node {
+        Node::Let {
+            name: name.to_string(),
+            type_annotation: Some(ty),
+            initializer: Some(Box::new(initializer)),
+            is_mutable: true,
+        }
+    }
+
+    fn binary(left: Node, operator: BinaryOp, right: Node) -> Node {
+        Node::Binary {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        }
+    }
+
+    fn check(statements: Vec<Node>) -> Result<(), Vec<String>> {
+        TypeChecker::new().check(&Node::Program(vec![Node::Block(statements)]))
+    }
+
+    #[test]
+    fn test_uint256_arithmetic() {
+        let result = check(vec![
+            let_typed("supply", Type::UInt256, Node::UInt256Literal("0xffff".to_string())),
+            let_typed("fee", Type::UInt, Node::IntLiteral(3)),
+            let_typed("total", Type::UInt256, binary(ident("supply"), BinaryOp::Add, ident("fee"))),
+            let_typed("next", Type::UInt256, binary(ident("total"), BinaryOp::Add, Node::IntLiteral(1))),
+        ]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_parse_errors_are_skipped() {
+        let broken = Node::Error { span: gard_ast::Span { start: 0, end: 12 }, consumed_tokens: 4 };
+        assert!(check(vec![broken.clone(), let_typed("count", Type::Int, Node::IntLiteral(1))]).is_ok());
+        assert_eq!(check(vec![broken, let_typed("count", Type::Int, Node::StringLiteral("one".to_string()))]).unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_signed_and_uint256_do_not_mix() {
+        let result = check(vec![
+            let_typed("supply", Type::UInt256, Node::IntLiteral(10)),
+            let_typed("delta", Type::Int, Node::IntLiteral(-1)),
+            binary(ident("supply"), BinaryOp::Add, ident("delta")),
+        ]);
+        assert_eq!(result.unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_template_strings() {
+        let template = |parts: Vec<Node>| Node::TemplateString { parts };
+        let result = check(vec![
+            let_typed("supply", Type::UInt256, Node::IntLiteral(10)),
+            let_typed("delta", Type::Int, Node::IntLiteral(-1)),
+            let_typed("label", Type::String, template(vec![Node::StringLiteral("supply ".to_string()), ident("supply")])),
+            let_typed("count", Type::Int, template(vec![binary(ident("supply"), BinaryOp::Add, ident("delta"))])),
+        ]);
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[1], "Cannot assign a value of type String to 'count' of type Int");
+    }
+
+    #[test]
+    fn test_negative_literal_not_assignable_to_uint256() {
+        let result = check(vec![let_typed("supply", Type::UInt256, Node::IntLiteral(-1))]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_oversized_literal() {
+        let literal = format!("0x1{}", "0".repeat(64));
+        let result = check(vec![let_typed("supply", Type::UInt256, Node::UInt256Literal(literal))]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_address_literal_width() {
+        let short = Node::UInt256Literal("0x7e5f4552091a69125d5dfcb7b8c2659029395bdf".to_string());
+        assert!(check(vec![let_typed("owner", Type::Address, short)]).is_ok());
+
+        let wide = Node::UInt256Literal(format!("0x{}", "f".repeat(41)));
+        assert!(check(vec![let_typed("owner", Type::Address, wide)]).is_err());
+    }
+
+    fn assertion(kind: AssertionKind, condition: Node, message: Option<Node>) -> Node {
+        Node::Assertion {
+            kind,
+            condition: Box::new(condition),
+            message: message.map(Box::new),
+        }
+    }
+
+    #[test]
+    fn test_assertion_types() {
+        let reason = || Some(Node::StringLiteral("Not authorized".to_string()));
+        let condition = || binary(Node::IntLiteral(1), BinaryOp::Gt, Node::IntLiteral(0));
+
+        assert!(check(vec![assertion(AssertionKind::Require, condition(), reason())]).is_ok());
+        assert!(check(vec![assertion(AssertionKind::Assert, condition(), None)]).is_ok());
+        assert!(check(vec![assertion(AssertionKind::Validate, Node::IntLiteral(1), reason())]).is_err());
+        assert!(check(vec![assertion(AssertionKind::Require, condition(), Some(Node::IntLiteral(3)))]).is_err());
+        assert!(check(vec![assertion(AssertionKind::Validate, condition(), None)]).is_err());
+    }
+
+    #[test]
+    fn test_chain_intrinsic_types() {
+        let call = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(ident(name)),
+            arguments,
+        };
+        let timestamp = Node::Member {
+            object: Box::new(ident("block")),
+            property: "timestamp".to_string(),
+        };
+
+        assert!(check(vec![
+            call("mine", vec![]),
+            call("advanceTime", vec![Node::IntLiteral(86400)]),
+            let_typed("now", Type::UInt256, timestamp),
+        ]).is_ok());
+        assert!(check(vec![call("mine", vec![Node::IntLiteral(1), Node::IntLiteral(2)])]).is_err());
+        assert!(check(vec![call("advanceTime", vec![Node::StringLiteral("1d".to_string())])]).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic_builtin_types() {
+        let call = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(ident(name)),
+            arguments,
+        };
+
+        assert!(check(vec![
+            let_typed("supply", Type::UInt256, Node::UInt256Literal("0x01".to_string())),
+            let_typed("next", Type::UInt256, call("wrappingAdd", vec![ident("supply"), Node::IntLiteral(1)])),
+            let_typed("delta", Type::Int, call("checkedSub", vec![Node::IntLiteral(1), Node::IntLiteral(2)])),
+        ]).is_ok());
+        assert_eq!(check(vec![call("wrappingMul", vec![Node::IntLiteral(1)])]).unwrap_err(), vec![
+            "wrappingMul() expects 2 argument(s), found 1".to_string(),
+        ]);
+        assert!(check(vec![call("checkedAdd", vec![Node::FloatLiteral(1.0), Node::FloatLiteral(2.0)])]).is_err());
+    }
+
+    #[test]
+    fn test_index_types() {
+        let index = |object: Node, index: Node| Node::Index { object: Box::new(object), index: Box::new(index), checked: true };
+        let array = Node::Array { elements: vec![Node::IntLiteral(1)] };
+
+        assert!(check(vec![
+            let_typed("xs", Type::Array(Box::new(Type::Int)), array),
+            let_typed("x", Type::Int, index(ident("xs"), Node::IntLiteral(0))),
+        ]).is_ok());
+        assert_eq!(check(vec![index(Node::IntLiteral(1), Node::StringLiteral("0".to_string()))]).unwrap_err(), vec![
+            "Index must be an integer, found String".to_string(),
+            "Cannot index a value of type Int".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_inference() {
+        let square = Node::Function {
+            name: "square".to_string(),
+            params: vec![gard_ast::Parameter { name: "x".into(), type_annotation: Type::UInt }],
+            return_type: Type::UInt,
+            body: Box::new(Node::Block(vec![Node::Return(Some(Box::new(binary(ident("x"), BinaryOp::Mul, ident("x")))))])),
+            modifiers: vec![],
+            docs: None,
+        };
+        let call = |arguments: Vec<Node>| Node::Call { callee: Box::new(ident("square")), arguments };
+        let let_inferred = |name: &str, initializer: Node| Node::Let {
+            name: name.to_string(),
+            type_annotation: None,
+            initializer: Some(Box::new(initializer)),
+            is_mutable: false,
+        };
+
+        // Calls can come before the function, and the literal takes the parameter's type
+        assert!(check(vec![
+            let_inferred("area", call(vec![Node::IntLiteral(3)])),
+            let_typed("total", Type::UInt256, binary(ident("area"), BinaryOp::Add, Node::UInt256Literal("0x01".to_string()))),
+            let_typed("empty", Type::Array(Box::new(Type::UInt)), Node::Array { elements: vec![] }),
+            let_typed("sizes", Type::Array(Box::new(Type::UInt)), Node::Array { elements: vec![Node::IntLiteral(1)] }),
+            square.clone(),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            square,
+            let_inferred("area", call(vec![Node::IntLiteral(3)])),
+            let_typed("label", Type::String, ident("area")),
+            call(vec![Node::StringLiteral("3".to_string())]),
+            call(vec![]),
+            let_inferred("xs", Node::Array { elements: vec![Node::IntLiteral(1)] }),
+            let_typed("x", Type::String, Node::Index { object: Box::new(ident("xs")), index: Box::new(Node::IntLiteral(0)), checked: true }),
+        ]).unwrap_err(), vec![
+            "Cannot assign a value of type UInt to 'label' of type String".to_string(),
+            "Argument 1 of square() expects UInt, found String".to_string(),
+            "square() expects 1 argument(s), found 0".to_string(),
+            "Cannot assign a value of type Int to 'x' of type String".to_string(),
+        ]);
+
+        let negative = Node::Function {
+            name: "negative".to_string(),
+            params: vec![],
+            return_type: Type::UInt,
+            body: Box::new(Node::Block(vec![Node::Return(Some(Box::new(Node::IntLiteral(-1))))])),
+            modifiers: vec![],
+            docs: None,
+        };
+        assert_eq!(check(vec![negative]).unwrap_err(), vec![
+            "Cannot return a value of type Int from a function returning UInt".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_address_arithmetic_rejected() {
+        let result = check(vec![
+            let_typed("owner", Type::Address, Node::UInt256Literal("0x01".to_string())),
+            binary(ident("owner"), BinaryOp::Add, ident("owner")),
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let not = |operand: Node| Node::Unary { operator: UnaryOp::BitNot, operand: Box::new(operand) };
+
+        assert!(check(vec![
+            let_typed("flags", Type::UInt256, Node::UInt256Literal("0xff".to_string())),
+            let_typed("masked", Type::UInt256, binary(ident("flags"), BinaryOp::BitAnd, Node::IntLiteral(15))),
+            let_typed("shifted", Type::UInt256, binary(ident("flags"), BinaryOp::Shl, Node::IntLiteral(8))),
+            let_typed("mixed", Type::Int, binary(not(Node::IntLiteral(1)), BinaryOp::BitXor, Node::IntLiteral(2))),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            binary(Node::FloatLiteral(1.5), BinaryOp::BitOr, Node::FloatLiteral(2.0)),
+            binary(Node::IntLiteral(1), BinaryOp::Shr, Node::BooleanLiteral(true)),
+            not(Node::StringLiteral("a".to_string())),
+        ]).unwrap_err(), vec![
+            "Operator BitOr expects integer operands, found Float and Float".to_string(),
+            "Operator Shr expects integer operands, found Int and Boolean".to_string(),
+            "Operator ~ expects an integer operand, found String".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_increment_places() {
+        let step = |operator: UnaryOp, operand: Node| Node::Unary { operator, operand: Box::new(operand) };
+        let first = Node::Index { object: Box::new(ident("counts")), index: Box::new(Node::IntLiteral(0)), checked: false };
+
+        assert!(check(vec![
+            let_typed("counts", Type::Array(Box::new(Type::UInt)), Node::Array { elements: vec![Node::IntLiteral(1)] }),
+            let_typed("before", Type::UInt, step(UnaryOp::PostIncrement, first.clone())),
+            let_typed("ratio", Type::Float, Node::FloatLiteral(0.5)),
+            let_typed("after", Type::Float, step(UnaryOp::Decrement, ident("ratio"))),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            let_typed("name", Type::String, Node::StringLiteral("a".to_string())),
+            step(UnaryOp::Increment, ident("name")),
+            step(UnaryOp::PostDecrement, Node::IntLiteral(1)),
+        ]).unwrap_err(), vec![
+            "Operator ++ expects a numeric operand, found String".to_string(),
+            "Operator -- needs a variable, field or element".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_string_characters() {
+        let text = || Node::StringLiteral("héllo".to_string());
+        let first = |object: Node| Node::Index { object: Box::new(object), index: Box::new(Node::IntLiteral(0)), checked: true };
+        let foreach = |collection: Node, body: Node| Node::Foreach { item: "c".to_string(), collection: Box::new(collection), body: Box::new(body) };
+
+        assert!(check(vec![
+            let_typed("initial", Type::String, first(text())),
+            foreach(text(), let_typed("letter", Type::String, ident("c"))),
+            foreach(Node::Call {
+                callee: Box::new(Node::Member { object: Box::new(ident("bytes")), property: "fromString".to_string() }),
+                arguments: vec![text()],
+            }, let_typed("byte", Type::Int, ident("c"))),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            binary(first(text()), BinaryOp::Eq, Node::IntLiteral(104)),
+            foreach(text(), let_typed("code", Type::Int, ident("c"))),
+            foreach(Node::IntLiteral(3), Node::Block(vec![])),
+        ]).unwrap_err(), vec![
+            "Cannot compare String with Int; a string's elements are its characters, as strings like \"a\", \
+                and bytes.fromString(..) gives its UTF-8 bytes".to_string(),
+            "Cannot assign a value of type String to 'code' of type Int".to_string(),
+            "Cannot iterate over a value of type Int".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_spawn_needs_a_scope() {
+        let spawn = |callee: Node| Node::Spawn(Box::new(Node::Call { callee: Box::new(callee), arguments: vec![] }));
+        let scope = |body: Vec<Node>| Node::Scope { body: Box::new(Node::Block(body)) };
+
+        assert!(check(vec![scope(vec![spawn(ident("work")), scope(vec![spawn(ident("work"))])])]).is_ok());
+        assert_eq!(check(vec![spawn(ident("work"))]).unwrap_err(), vec![
+            "spawn must be inside a scope, which waits for the task to finish".to_string(),
+        ]);
+        assert_eq!(check(vec![scope(vec![Node::Spawn(Box::new(ident("work")))])]).unwrap_err(), vec![
+            "spawn expects a function call".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_task_locals_stay_in_their_task() {
+        let spawn_work = |argument: &str| Node::Scope { body: Box::new(Node::Block(vec![Node::Spawn(Box::new(Node::Call {
+            callee: Box::new(ident("work")),
+            arguments: vec![ident(argument)],
+        }))])) };
+        let function = |name: &str, params: Vec<Parameter>, body: Vec<Node>| Node::Function {
+            name: name.to_string(),
+            params,
+            return_type: Type::Void,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        };
+        let program = Node::Program(vec![
+            Node::TaskLocal { name: "requestId".to_string(), type_annotation: Some(Type::String), initializer: Box::new(Node::StringLiteral("".to_string())) },
+            Node::TaskLocal { name: "handled".to_string(), type_annotation: Some(Type::Int), initializer: Box::new(Node::BooleanLiteral(false)) },
+            function("main", vec![], vec![
+                spawn_work("requestId"),
+                Node::Call { callee: Box::new(ident("send")), arguments: vec![Node::StringLiteral("log".to_string()), ident("requestId")] },
+            ]),
+            // The parameter hides the task-local
+            function("forward", vec![Parameter { name: "requestId".into(), type_annotation: Type::String }], vec![spawn_work("requestId")]),
+        ]);
+        assert_eq!(TypeChecker::new().check(&program).unwrap_err(), vec![
+            "Cannot assign a value of type Boolean to 'handled' of type Int".to_string(),
+            "Cannot send task-local 'requestId' to a spawned task; each task has its own".to_string(),
+            "Cannot send task-local 'requestId' to a channel; each task has its own".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_string_methods() {
+        let method = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(ident("name")), property: name.to_string() }),
+            arguments,
+        };
+        let name = || let_typed("name", Type::String, Node::StringLiteral(" Gard ".to_string()));
+
+        assert!(check(vec![
+            name(),
+            let_typed("words", Type::Array(Box::new(Type::String)), method("split", vec![Node::StringLiteral(" ".to_string())])),
+            let_typed("at", Type::Int, method("indexOf", vec![Node::StringLiteral("a".to_string())])),
+            let_typed("size", Type::Int, Node::Member { object: Box::new(ident("name")), property: "length".to_string() }),
+        ]).is_ok());
+        assert_eq!(check(vec![name(), let_typed("upper", Type::Int, method("toUpper", vec![])), method("substring", vec![Node::IntLiteral(1)])]).unwrap_err(), vec![
+            "Cannot assign a value of type String to 'upper' of type Int".to_string(),
+            "substring() expects 2 argument(s), found 1".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_regex_calls() {
+        let regex = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(ident("regex")), property: name.to_string() }),
+            arguments,
+        };
+        let string = |value: &str| Node::StringLiteral(value.to_string());
+
+        assert!(check(vec![
+            let_typed("digits", Type::String, regex("compile", vec![string(r"\d+")])),
+            let_typed("found", Type::Boolean, regex("isMatch", vec![ident("digits"), string("a1")])),
+            let_typed("groups", Type::Array(Box::new(Type::String)), regex("captures", vec![ident("digits"), string("a1")])),
+        ]).is_ok());
+        assert_eq!(check(vec![regex("replace", vec![string("a"), Node::IntLiteral(1), string("b")]), regex("find", vec![string("a")])]).unwrap_err(), vec![
+            "Argument 2 of replace() expects String, found Int".to_string(),
+            "find() expects 2 argument(s), found 1".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_datetime_types() {
+        let datetime = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(ident("datetime")), property: name.to_string() }),
+            arguments,
+        };
+        let custom = |name: &str| Type::Custom(name.into());
+
+        assert!(check(vec![
+            let_typed("start", custom("Instant"), datetime("now", vec![])),
+            let_typed("deadline", custom("Instant"), binary(ident("start"), BinaryOp::Add, datetime("hours", vec![Node::IntLiteral(2)]))),
+            let_typed("left", custom("Duration"), binary(ident("deadline"), BinaryOp::Sub, datetime("now", vec![]))),
+            let_typed("late", Type::Boolean, binary(ident("left"), BinaryOp::Lt, binary(Node::IntLiteral(3), BinaryOp::Mul, datetime("minutes", vec![Node::IntLiteral(1)])))),
+            let_typed("text", Type::String, datetime("format", vec![datetime("toDateTime", vec![ident("deadline")])])),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            let_typed("start", custom("Instant"), datetime("now", vec![])),
+            binary(ident("start"), BinaryOp::Sub, datetime("parse", vec![Node::StringLiteral("2024-06-01".to_string())])),
+            let_typed("elapsed", Type::Int, binary(ident("start"), BinaryOp::Sub, ident("start"))),
+        ]).unwrap_err(), vec![
+            "Operator Sub cannot be applied to Custom(\"Instant\") and Custom(\"DateTime\")".to_string(),
+            "Cannot assign a value of type Custom(\"Duration\") to 'elapsed' of type Int".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_bytes_and_io() {
+        let module = |module: &str, name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(ident(module)), property: name.to_string() }),
+            arguments,
+        };
+        let string = |value: &str| Node::StringLiteral(value.to_string());
+
+        assert!(check(vec![
+            let_typed("data", Type::Bytes, module("bytes", "fromHex", vec![string("0xcafe")])),
+            let_typed("both", Type::Bytes, binary(ident("data"), BinaryOp::Add, module("bytes", "slice", vec![ident("data"), Node::IntLiteral(0), Node::IntLiteral(1)]))),
+            let_typed("first", Type::Int, Node::Index { object: Box::new(ident("both")), index: Box::new(Node::IntLiteral(0)), checked: true }),
+            let_typed("out", Type::Custom("Writer".into()), module("io", "openWriter", vec![string("out.bin")])),
+            module("io", "write", vec![ident("out"), ident("both")]),
+            module("io", "close", vec![ident("out")]),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            let_typed("input", Type::Custom("Reader".into()), module("io", "stdin", vec![])),
+            module("io", "writeLine", vec![ident("input"), string("hi")]),
+            binary(module("bytes", "fromString", vec![string("a")]), BinaryOp::Add, string("b")),
+        ]).unwrap_err(), vec![
+            "Argument 1 of writeLine() expects Custom(\"Writer\"), found Custom(\"Reader\")".to_string(),
+            "Operator Add cannot be applied to Bytes and String".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_net_handles() {
+        let net = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(ident("net")), property: name.to_string() }),
+            arguments,
+        };
+        let custom = |name: &str| Type::Custom(name.into());
+
+        assert!(check(vec![
+            let_typed("listener", custom("Listener"), net("listen", vec![Node::StringLiteral("127.0.0.1:0".to_string())])),
+            let_typed("client", custom("Connection"), net("accept", vec![ident("listener")])),
+            let_typed("request", Type::Bytes, net("receive", vec![ident("client"), Node::IntLiteral(512)])),
+            net("send", vec![ident("client"), ident("request")]),
+            net("close", vec![ident("client")]),
+            net("close", vec![ident("listener")]),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            let_typed("socket", custom("Socket"), net("bind", vec![Node::StringLiteral("0.0.0.0:9000".to_string())])),
+            net("receive", vec![ident("socket"), Node::IntLiteral(512)]),
+        ]).unwrap_err(), vec!["Argument 1 of receive() expects Custom(\"Connection\"), found Custom(\"Socket\")".to_string()]);
+    }
+
+    #[test]
+    fn test_http_responses() {
+        let http = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(ident("http")), property: name.to_string() }),
+            arguments,
+        };
+        let url = Node::StringLiteral("http://localhost:8545".to_string());
+
+        assert!(check(vec![
+            let_typed("response", Type::Custom("Response".into()), http("post", vec![url.clone(), ident("payload"), Node::StringLiteral("application/json".to_string())])),
+            let_typed("ok", Type::Boolean, binary(http("status", vec![ident("response")]), BinaryOp::Eq, Node::IntLiteral(200))),
+            let_typed("text", Type::String, http("text", vec![ident("response")])),
+            http("close", vec![ident("response")]),
+        ]).is_ok());
+        assert_eq!(check(vec![let_typed("body", Type::String, http("body", vec![http("get", vec![url])]))]).unwrap_err(),
+            vec!["Cannot assign a value of type Bytes to 'body' of type String".to_string()]);
+    }
+
+    #[test]
+    fn test_process_commands() {
+        let process = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(ident("process")), property: name.to_string() }),
+            arguments,
+        };
+        let string = |value: &str| Node::StringLiteral(value.to_string());
+
+        assert!(check(vec![
+            let_typed("build", Type::Custom("Command".into()), process("command", vec![string("cargo")])),
+            process("arg", vec![ident("build"), string("build")]),
+            process("env", vec![ident("build"), string("RUSTFLAGS"), string("-Dwarnings")]),
+            let_typed("child", Type::Custom("Child".into()), process("start", vec![ident("build")])),
+            let_typed("code", Type::Int, process("finish", vec![ident("child")])),
+            let_typed("log", Type::Bytes, process("stderr", vec![ident("child")])),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            let_typed("ls", Type::Custom("Command".into()), process("command", vec![string("ls")])),
+            process("finish", vec![ident("ls")]),
+        ]).unwrap_err(), vec!["Argument 1 of finish() expects Custom(\"Child\"), found Custom(\"Command\")".to_string()]);
+    }
+
+    #[test]
+    fn test_union_matches() {
+        let custom = |name: &str| Type::Custom(name.into());
+        let class = |name: &str, is_mutable: bool| Node::Class {
+            name: name.to_string(),
+            extends: None,
+            implements: vec![],
+            is_abstract: false,
+            members: vec![Node::Let { name: "id".to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable }],
+            docs: None,
+        };
+        let case = |pattern: Node| MatchCase { pattern, body: Node::Block(vec![]) };
+        let check = |cases: Vec<MatchCase>| TypeChecker::new().check(&Node::Program(vec![
+            Node::Union { name: "Msg".to_string(), variants: vec![UnionVariant::new("Update"), UnionVariant::new("Logout")] },
+            class("Update", false),
+            class("Logout", true),
+            Node::Block(vec![
+                let_typed("update", custom("Update"), Node::NullLiteral),
+                let_typed("msg", custom("Msg"), ident("update")),
+                Node::Match { value: Box::new(ident("msg")), cases },
+            ]),
+        ]));
+        let update = || Node::Call { callee: Box::new(ident("Update")), arguments: vec![ident("profile")] };
+
+        assert!(check(vec![case(update()), case(ident("Logout"))]).is_ok());
+        assert!(check(vec![case(update()), case(ident("_"))]).is_ok());
+        assert_eq!(check(vec![case(update())]).unwrap_err(), vec!["Match on Msg does not cover Logout".to_string()]);
+        assert_eq!(check(vec![case(ident("Login")), case(ident("_"))]).unwrap_err(), vec![
+            "'Login' is not a variant of Msg".to_string(),
+        ]);
+
+        assert_eq!(check(vec![]).unwrap_err(), vec!["Match on Msg does not cover Update, Logout".to_string()]);
+
+        let mut checker = TypeChecker::new();
+        checker.collect_types(&Node::Program(vec![
+            Node::Union { name: "Msg".to_string(), variants: vec![UnionVariant::new("Update"), UnionVariant::new("Logout")] },
+            class("Update", false),
+            class("Logout", true),
+        ]));
+        assert_eq!(checker.sendable(&custom("Msg"), &mut Vec::new()).unwrap_err(),
+            "Msg variant Logout: Logout has mutable field 'id'");
+        assert!(!checker.is_assignable(&custom("Update"), &custom("Msg"), None));
+    }
+
+    #[test]
+    fn test_interface_values() {
+        let custom = |name: &str| Type::Custom(name.into());
+        let method = |name: &str, return_type: Type| Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type,
+            body: Box::new(Node::Block(vec![])),
+            modifiers: vec![],
+            docs: None,
+        };
+        let class = |name: &str, implements: &[&str], members: Vec<Node>| Node::Class {
+            name: name.to_string(),
+            extends: None,
+            implements: implements.iter().map(|name| name.to_string()).collect(),
+            is_abstract: false,
+            members,
+            docs: None,
+        };
+        let declarations = || vec![
+            Node::Interface {
+                name: "Shape".to_string(),
+                methods: vec![gard_ast::MethodSignature { name: "area".to_string(), params: vec![], return_type: Type::Int }],
+            },
+            class("Square", &["Shape"], vec![
+                Node::Let { name: "side".to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable: false },
+                method("area", Type::Int),
+            ]),
+            class("Circle", &["Shape"], vec![method("area", Type::Int)]),
+            class("Point", &[], vec![method("area", Type::Int)]),
+        ];
+        let check = |statements: Vec<Node>| {
+            let mut program = declarations();
+            program.push(Node::Block(statements));
+            TypeChecker::new().check(&Node::Program(program))
+        };
+        let shapes = Type::Map { key: Box::new(Type::String), value: Box::new(custom("Shape")) };
+        let entry = |key: &str, value: &str| (Node::StringLiteral(key.to_string()), ident(value));
+        let member = |object: &str, property: &str| Node::Member { object: Box::new(ident(object)), property: property.to_string() };
+
+        assert!(check(vec![
+            let_typed("square", custom("Square"), Node::NullLiteral),
+            let_typed("circle", custom("Circle"), Node::NullLiteral),
+            let_typed("shapes", shapes.clone(), Node::Map { entries: vec![entry("a", "square"), entry("b", "circle")] }),
+            let_typed("shape", custom("Shape"), ident("square")),
+            let_typed("area", Type::Int, Node::Call { callee: Box::new(member("shape", "area")), arguments: vec![] }),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            let_typed("point", custom("Point"), Node::NullLiteral),
+            let_typed("shapes", shapes, Node::Map { entries: vec![entry("a", "point")] }),
+            let_typed("shape", custom("Shape"), Node::NullLiteral),
+            member("shape", "side"),
+        ]).unwrap_err(), vec![
+            "Map value of type Custom(\"Point\") in a map of Custom(\"Shape\")".to_string(),
+            "'side' is not a method of interface Shape".to_string(),
+        ]);
+
+        let mut program = declarations();
+        program.push(class("Label", &["Shape"], vec![method("area", Type::String)]));
+        program.push(class("Blank", &["Shape"], vec![]));
+        assert_eq!(TypeChecker::new().check(&Node::Program(program)).unwrap_err(), vec![
+            "Method 'area' of class 'Label' has type Function { params: [], return_type: String }, but interface Shape declares Function { params: [], return_type: Int }".to_string(),
+            "Class 'Blank' does not implement 'area' of interface Shape".to_string(),
+        ]);
+
+        let mut checker = TypeChecker::new();
+        let mut program = declarations();
+        program.push(class("Counter", &["Shape"], vec![
+            Node::Let { name: "count".to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable: true },
+        ]));
+        checker.collect_types(&Node::Program(program));
+        assert_eq!(checker.sendable(&custom("Shape"), &mut Vec::new()).unwrap_err(),
+            "Shape implementation Counter: Counter has mutable field 'count'");
+        assert!(!checker.is_assignable(&custom("Square"), &custom("Shape"), None));
+    }
+
+    #[test]
+    fn test_abstract_classes() {
+        let custom = |name: &str| Type::Custom(name.into());
+        let method = |name: &str, return_type: Type, modifiers: Vec<FunctionModifier>| Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type,
+            body: Box::new(Node::Block(vec![])),
+            modifiers,
+            docs: None,
+        };
+        let class = |name: &str, extends: Option<&str>, is_abstract: bool, members: Vec<Node>| Node::Class {
+            name: name.to_string(),
+            extends: extends.map(str::to_string),
+            implements: vec![],
+            is_abstract,
+            members,
+            docs: None,
+        };
+        let check = |mut program: Vec<Node>, statements: Vec<Node>| {
+            program.insert(0, class("Shape", None, true, vec![
+                method("area", Type::Int, vec![FunctionModifier::Abstract]),
+                method("name", Type::String, vec![]),
+            ]));
+            program.push(Node::Block(statements));
+            TypeChecker::new().check(&Node::Program(program))
+        };
+        let instantiate = |class: &str| Node::Call { callee: Box::new(ident(class)), arguments: vec![] };
+
+        // Implemented by a subclass, or inherited through an abstract one
+        assert!(check(vec![
+            class("Square", Some("Shape"), false, vec![method("area", Type::Int, vec![])]),
+            class("Polygon", Some("Shape"), true, vec![]),
+            class("Triangle", Some("Polygon"), false, vec![method("area", Type::Int, vec![])]),
+        ], vec![
+            let_typed("square", custom("Square"), Node::NullLiteral),
+            let_typed("shape", custom("Shape"), ident("square")),
+            instantiate("Square"),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            class("Blank", Some("Shape"), false, vec![]),
+            class("Label", Some("Shape"), false, vec![method("area", Type::String, vec![])]),
+            class("Point", None, false, vec![method("area", Type::Int, vec![FunctionModifier::Abstract])]),
+        ], vec![instantiate("Shape")]).unwrap_err(), vec![
+            "Class 'Blank' does not implement abstract method 'area' of Shape".to_string(),
+            "Method 'area' of class 'Label' has type Function { params: [], return_type: String }, but Shape declares Function { params: [], return_type: Int }".to_string(),
+            "Class 'Point' declares abstract method 'area' but isn't abstract".to_string(),
+            "Cannot instantiate abstract class 'Shape'".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_type_tests_narrow() {
+        let custom = |name: &str| Type::Custom(name.into());
+        let class = |name: &str, extends: Option<&str>| Node::Class {
+            name: name.to_string(),
+            extends: extends.map(str::to_string),
+            implements: vec!["Shape".to_string()],
+            is_abstract: false,
+            members: vec![Node::Let { name: "side".to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable: false }],
+            docs: None,
+        };
+        let check = |statements: Vec<Node>| TypeChecker::new().check(&Node::Program(vec![
+            Node::Interface { name: "Shape".to_string(), methods: vec![] },
+            class("Square", None),
+            class("Tile", Some("Square")),
+            class("Circle", None),
+            let_typed("shape", custom("Shape"), Node::NullLiteral),
+            Node::Block(statements),
+        ]));
+        let is = |name: &str, class: &str| Node::TypeTest { value: Box::new(ident(name)), target: custom(class) };
+        let side = |name: &str| Node::Member { object: Box::new(ident(name)), property: "side".to_string() };
+        let if_then = |condition: Node, then_branch: Node, else_branch: Option<Node>| Node::If {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch: else_branch.map(Box::new),
+        };
+
+        assert!(check(vec![
+            if_then(is("shape", "Square"), let_typed("a", Type::Int, side("shape")), None),
+            if_then(Node::Unary { operator: UnaryOp::Not, operand: Box::new(is("shape", "Tile")) },
+                Node::Block(vec![]), Some(let_typed("b", Type::Int, side("shape")))),
+            Node::Binary { left: Box::new(is("shape", "Circle")), operator: BinaryOp::And, right: Box::new(Node::Binary {
+                left: Box::new(side("shape")),
+                operator: BinaryOp::Gt,
+                right: Box::new(Node::IntLiteral(0)),
+            }) },
+            let_typed("square", custom("Square"), Node::SafeCast { value: Box::new(ident("shape")), target: custom("Square") }),
+            let_typed("tile", Type::Boolean, is("square", "Tile")),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            side("shape"),
+            let_typed("circle", custom("Circle"), Node::NullLiteral),
+            is("circle", "Square"),
+            is("shape", "int"),
+            Node::TypeTest { value: Box::new(Node::IntLiteral(1)), target: custom("Square") },
+        ]).unwrap_err(), vec![
+            "'side' is not a method of interface Shape".to_string(),
+            "A Circle is never a Square".to_string(),
+            "'is' and 'as?' need a class or interface, found Custom(\"int\")".to_string(),
+            "Only class instances and interface values have types to test, found Int".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_super_calls() {
+        let param = |name: &str| Parameter { name: name.into(), type_annotation: Type::Int };
+        let method = |name: &str, modifiers: Vec<FunctionModifier>| Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type: Type::Int,
+            body: Box::new(Node::Block(vec![])),
+            modifiers,
+            docs: None,
+        };
+        let constructor = |params: Vec<Parameter>, body: Vec<Node>| Node::Constructor { params, body: Box::new(Node::Block(body)) };
+        let class = |name: &str, extends: Option<&str>, members: Vec<Node>| Node::Class {
+            name: name.to_string(),
+            extends: extends.map(str::to_string),
+            implements: vec![],
+            is_abstract: name == "Shape",
+            members,
+            docs: None,
+        };
+        let super_call = |arguments: Vec<Node>| Node::Call { callee: Box::new(Node::Super), arguments };
+        let super_method = |name: &str| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(Node::Super), property: name.to_string() }),
+            arguments: vec![],
+        };
+        let check = |classes: Vec<Node>| {
+            let mut program = vec![class("Shape", None, vec![
+                constructor(vec![param("sides")], vec![]),
+                method("area", vec![FunctionModifier::Abstract]),
+                method("sides", vec![]),
+            ])];
+            program.extend(classes);
+            TypeChecker::new().check(&Node::Program(program))
+        };
+
+        assert!(check(vec![class("Square", Some("Shape"), vec![
+            constructor(vec![param("side")], vec![
+                super_call(vec![Node::IntLiteral(4)]),
+                let_typed("sides", Type::Int, super_method("sides")),
+            ]),
+            method("area", vec![]),
+        ])]).is_ok());
+        assert_eq!(check(vec![
+            class("Square", Some("Shape"), vec![
+                constructor(vec![], vec![super_call(vec![ident("side")]), super_method("area")]),
+                method("area", vec![]),
+            ]),
+            class("Circle", Some("Shape"), vec![
+                constructor(vec![], vec![let_typed("late", Type::Int, Node::IntLiteral(0)), super_call(vec![Node::IntLiteral(1)])]),
+                method("area", vec![]),
+            ]),
+            class("Point", None, vec![constructor(vec![], vec![super_call(vec![])])]),
+            class("Line", Some("Shape"), vec![method("area", vec![])]),
+        ]).unwrap_err(), vec![
+            "Cannot call abstract method 'area' of Shape through super".to_string(),
+            "Constructor of 'Circle' must call super(..) first, since Shape's constructor takes arguments".to_string(),
+            "super(..) can only be the first statement of a constructor".to_string(),
+            "'super' used in class 'Point', which has no base class".to_string(),
+            "Class 'Line' needs a constructor calling super(..), since Shape's constructor takes arguments".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_sendability() {
+        let field = |name: &str, ty: Type, is_mutable: bool| Node::Let {
+            name: name.to_string(),
+            type_annotation: Some(ty),
+            initializer: None,
+            is_mutable,
+        };
+        let class = |name: &str, members: Vec<Node>| Node::Class {
+            name: name.to_string(),
+            extends: None,
+            implements: vec![],
+            is_abstract: false,
+            members,
+            docs: None,
+        };
+        let call = |callee: Node, arguments: Vec<Node>| Node::Call { callee: Box::new(callee), arguments };
+        let custom = |name: &str| Type::Custom(name.into());
+        let program = |statements: Vec<Node>| Node::Program(vec![
+            class("Account", vec![field("balance", Type::Int, true)]),
+            class("Point", vec![field("x", Type::Int, false), field("tags", Type::Array(Box::new(Type::String)), false)]),
+            class("Owner", vec![field("account", custom("Account"), false)]),
+            Node::Block(statements),
+        ]);
+        let check = |statements: Vec<Node>| TypeChecker::new().check(&program(statements));
+        let spawned = |name: &str, ty: Type| vec![
+            let_typed(name, ty, Node::NullLiteral),
+            Node::Scope { body: Box::new(Node::Block(vec![Node::Spawn(Box::new(call(ident("work"), vec![ident(name)])))])) },
+        ];
+
+        assert!(check(spawned("point", custom("Point"))).is_ok());
+        assert!(check(spawned("counter", custom("TVar"))).is_ok());
+        assert_eq!(check(spawned("account", custom("Account"))).unwrap_err(), vec![
+            "Cannot send a value of type Custom(\"Account\") to a spawned task: Account has mutable field 'balance'".to_string(),
+        ]);
+        assert_eq!(check(spawned("owner", custom("Owner"))).unwrap_err(), vec![
+            "Cannot send a value of type Custom(\"Owner\") to a spawned task: Owner.account: Account has mutable field 'balance'".to_string(),
+        ]);
+        let callback = Type::Function { params: vec![], return_type: Box::new(Type::Void) };
+        assert!(check(spawned("callback", callback)).is_err());
+
+        let send = vec![
+            let_typed("accounts", Type::Array(Box::new(custom("Account"))), Node::NullLiteral),
+            call(ident("send"), vec![Node::StringLiteral("jobs".to_string()), ident("accounts")]),
+        ];
+        assert_eq!(check(send).unwrap_err().len(), 1);
+
+        let actor = Node::Actor {
+            name: "Bank".to_string(),
+            type_param: None,
+            mailbox: Box::new(Node::NullLiteral),
+            behavior: Box::new(Node::NullLiteral),
+            members: vec![Node::Receive {
+                message_param: gard_ast::Parameter { name: "message".into(), type_annotation: custom("Account") },
+                body: Box::new(Node::Block(vec![])),
+            }],
+        };
+        assert_eq!(TypeChecker::new().check(&Node::Program(vec![class("Account", vec![field("balance", Type::Int, true)]), actor])).unwrap_err(), vec![
+            "Actor 'Bank' can't receive messages of type Custom(\"Account\"): Account has mutable field 'balance'".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_object_literals() {
+        let object = |fields: Vec<(&str, Node)>| Node::Object {
+            fields: fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect(),
+        };
+        let object_type = |fields: Vec<(&str, Type)>| Type::Object(fields.into_iter().map(|(name, ty)| (name.to_string(), ty)).collect());
+        let point = object_type(vec![("x", Type::Int), ("y", Type::Int)]);
+        let field = |name: &str| Node::Member { object: Box::new(ident("labeled")), property: name.to_string() };
+
+        assert!(check(vec![
+            let_typed("labeled", object_type(vec![("x", Type::Int), ("y", Type::Int), ("label", Type::String)]),
+                object(vec![("x", Node::IntLiteral(1)), ("y", Node::IntLiteral(2)), ("label", Node::StringLiteral("a".to_string()))])),
+            // An object with more fields than the type needs
+            let_typed("point", point.clone(), ident("labeled")),
+            let_typed("x", Type::Int, field("x")),
+            let_typed("sizes", object_type(vec![("sizes", Type::Array(Box::new(Type::UInt)))]),
+                object(vec![("sizes", Node::Array { elements: vec![] })])),
+        ]).is_ok());
+        assert_eq!(check(vec![
+            let_typed("labeled", point.clone(), object(vec![("x", Node::IntLiteral(1)), ("y", Node::IntLiteral(2))])),
+            let_typed("missing", point, object(vec![("x", Node::IntLiteral(1))])),
+            object(vec![("x", Node::IntLiteral(1)), ("x", Node::IntLiteral(2))]),
+            field("label"),
+        ]).unwrap_err(), vec![
+            "Cannot assign a value of type Object([(\"x\", Int)]) to 'missing' of type Object([(\"x\", Int), (\"y\", Int)])".to_string(),
+            "Field 'x' is given more than once".to_string(),
+            "Object of type { x: int, y: int } has no field 'label'".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_union_tags() {
+        let custom = |name: &str| Type::Custom(name.into());
+        let union = Node::Union { name: "Msg".to_string(), variants: vec![
+            UnionVariant { name: "Rename".to_string(), payload: vec![Type::String, Type::Int] },
+            UnionVariant::new("Logout"),
+        ] };
+        let call = |name: &str, arguments: Vec<Node>| Node::Call { callee: Box::new(ident(name)), arguments };
+        let case = |pattern: Node, body: Node| MatchCase { pattern, body: Node::Block(vec![body]) };
+        let check = |statements: Vec<Node>| TypeChecker::new().check(&Node::Program(vec![union.clone(), Node::Block(statements)]));
+        let rename = || call("Rename", vec![Node::StringLiteral("ada".to_string()), Node::IntLiteral(1)]);
+
+        assert!(check(vec![
+            let_typed("renamed", custom("Msg"), rename()),
+            let_typed("logout", custom("Msg"), ident("Logout")),
+            Node::Match { value: Box::new(ident("renamed")), cases: vec![
+                case(call("Rename", vec![ident("name"), ident("_")]), let_typed("named", Type::String, ident("name"))),
+                case(ident("Logout"), Node::Block(vec![])),
+            ] },
+        ]).is_ok());
+        assert_eq!(check(vec![
+            call("Rename", vec![Node::IntLiteral(1)]),
+            let_typed("renamed", custom("Msg"), rename()),
+            Node::Match { value: Box::new(ident("renamed")), cases: vec![
+                case(call("Rename", vec![ident("name")]), let_typed("id", Type::Int, ident("name"))),
+                case(call("Logout", vec![]), Node::Block(vec![])),
+            ] },
+        ]).unwrap_err(), vec![
+            "Rename() expects 2 argument(s), found 1".to_string(),
+            "Argument 1 of Rename() expects String, found Int".to_string(),
+            "Variant 'Rename' of Msg has 2 value(s), found 1 binding(s)".to_string(),
+            "Cannot assign a value of type String to 'id' of type Int".to_string(),
+        ]);
+
+        let mut checker = TypeChecker::new();
+        checker.collect_types(&Node::Program(vec![
+            Node::Union { name: "Job".to_string(), variants: vec![UnionVariant { name: "Run".to_string(), payload: vec![Type::Function {
+                params: vec![],
+                return_type: Box::new(Type::Void),
+            }] }] },
+        ]));
+        assert_eq!(checker.sendable(&custom("Job"), &mut Vec::new()).unwrap_err(),
+            "Job variant Run: functions can capture mutable state");
+    }
+}
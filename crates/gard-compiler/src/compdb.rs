@@ -0,0 +1,171 @@
+//! The compilation database: how each file was last compiled, the files it
+//! read and the files it wrote, like a C `compile_commands.json`. Build
+//! systems use it to rerun a compile exactly and to tell when its outputs
+//! are out of date; editors use it to check a file the way the build does.
+//!
+//! ```json
+//! [{
+//!   "file": "src/token.gard",
+//!   "directory": "/home/me/token",
+//!   "arguments": ["gard", "--file", "src/token.gard", "--emit", "wasm", "--output", "token.wasm"],
+//!   "target": "wasm32",
+//!   "edition": "2024",
+//!   "features": ["metrics"],
+//!   "experimental": [],
+//!   "overflow_checks": false,
+//!   "inputs": [{ "path": "src/token.gard", "hash": "5d1b6e3c0a9f2e41" }],
+//!   "outputs": ["token.wasm"]
+//! }]
+//! ```
+//!
+//! There's one entry per file and target: compiling a file again for the
+//! same target replaces its entry.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Where the driver keeps the database, from the working directory
+pub const PATH: &str = ".gard/compile_commands.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// The source file, as the command line named it
+    pub file: String,
+    /// The working directory the paths are relative to
+    pub directory: String,
+    /// The command line that compiles it again
+    pub arguments: Vec<String>,
+    pub target: String,
+    pub edition: String,
+    /// The `@cfg` features
+    pub features: Vec<String>,
+    /// The experimental language features `gard.toml` enables
+    pub experimental: Vec<String>,
+    pub overflow_checks: bool,
+    /// The source, the manifest and the plugins, as they were read
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Input {
+    pub path: String,
+    /// `hash` of its contents
+    pub hash: String,
+}
+
+impl Input {
+    pub fn read(path: &str) -> Result<Self, String> {
+        let contents = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        Ok(Self { path: path.to_string(), hash: hash(&contents) })
+    }
+}
+
+impl Entry {
+    /// Whether an input has changed or gone since the compile, so its
+    /// outputs are out of date.
+    pub fn is_stale(&self) -> bool {
+        self.inputs.iter().any(|input| Input::read(&input.path).as_ref() != Ok(input))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Database {
+    pub entries: Vec<Entry>,
+}
+
+impl Database {
+    /// The database at `path`, or an empty one if there's none yet.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid compilation database {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| format!("Failed to create {}: {}", directory.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize compilation database: {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Adds `entry` in place of the one for the same file and target.
+    pub fn record(&mut self, entry: Entry) {
+        match self.entries.iter_mut().find(|old| old.file == entry.file && old.target == entry.target) {
+            Some(old) => *old = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// The entries for `file`, one per target it was compiled for.
+    pub fn find<'a>(&'a self, file: &'a str) -> impl Iterator<Item = &'a Entry> {
+        self.entries.iter().filter(move |entry| entry.file == file)
+    }
+}
+
+/// FNV-1a in hex. The database outlives the build that wrote it, so the
+/// hash can't be std's, which may change between Rust releases.
+pub fn hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3));
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file: &str, target: &str, inputs: Vec<Input>) -> Entry {
+        Entry {
+            file: file.to_string(),
+            directory: ".".to_string(),
+            arguments: vec!["gard".to_string(), "--file".to_string(), file.to_string()],
+            target: target.to_string(),
+            edition: "2024".to_string(),
+            features: vec![],
+            experimental: vec![],
+            overflow_checks: false,
+            inputs,
+            outputs: vec![],
+        }
+    }
+
+    #[test]
+    fn test_record_and_reload() {
+        let directory = std::env::temp_dir().join(format!("gard-compdb-{}", std::process::id()));
+        let source = directory.join("main.gard");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(&source, "function main { }").unwrap();
+        let source = source.to_str().unwrap();
+
+        let mut database = Database::default();
+        database.record(entry(source, "native", vec![Input::read(source).unwrap()]));
+        database.record(entry(source, "wasm32", vec![]));
+        let mut recompiled = entry(source, "native", vec![Input::read(source).unwrap()]);
+        recompiled.overflow_checks = true;
+        database.record(recompiled.clone());
+        assert_eq!(database.find(source).map(|entry| entry.target.as_str()).collect::<Vec<_>>(), ["native", "wasm32"]);
+
+        let path = directory.join(".gard").join("compile_commands.json");
+        database.save(&path).unwrap();
+        let loaded = Database::load(&path).unwrap();
+        assert_eq!(loaded, database);
+        assert!(!loaded.entries[0].is_stale());
+
+        fs::write(source, "function main { f(); }").unwrap();
+        assert!(loaded.entries[0].is_stale());
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(loaded.entries[0].is_stale());
+        assert_eq!(Database::load(&path).unwrap(), Database::default());
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(b""), "cbf29ce484222325");
+        assert_eq!(hash(b"a"), "af63dc4c8601ec8c");
+    }
+}
@@ -0,0 +1,968 @@
+//! Compile-time evaluation. `const` declarations, `@slot` positions and
+//! attribute arguments are evaluated by a small interpreter for the pure
+//! subset of Gard: literals, constants, operators, array literals,
+//! `.length`, indexing, and calls to `pure` functions, whose bodies may use
+//! `let`, `if`, loops and `return`. Anything else, such as a call to an
+//! impure function, is an error naming the construct.
+//!
+//! `fold` substitutes each constant's value for the references to it, so
+//! the backends only see literals. The declarations stay as written, for
+//! the symbol index to see what they use, and the backends skip them.
+//! Top-level and class constants can be used before they are declared;
+//! constants in blocks, like locals, only after.
+//!
+//! Once constants are substituted, adjacent string literals in a `+` chain
+//! are merged, so `"Hello " + NAME + "!"` with a constant `NAME` is one
+//! literal and builds no string at runtime.
+
+use crate::checker::parse_uint256_literal;
+use gard_ast::{BinaryOp, FunctionModifier, Node, Span, Type, UnaryOp};
+use num_bigint::BigUint;
+use num_traits::Zero;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Evaluation steps one constant may take, so a runaway loop in a `pure`
+/// function fails the build instead of hanging it.
+pub const MAX_STEPS: u64 = 1_000_000;
+/// Nested `pure` calls one constant may make.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    UInt(u64),
+    UInt256(BigUint),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::UInt(_) => "uint",
+            Value::UInt256(_) => "uint256",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "boolean",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+        }
+    }
+
+    /// The literal the value is substituted as.
+    pub fn to_node(&self) -> Node {
+        match self {
+            Value::Int(value) => Node::IntLiteral(*value),
+            Value::UInt(value) => Node::UIntLiteral(*value),
+            Value::UInt256(value) => Node::UInt256Literal(format!("0x{:x}", value)),
+            Value::Float(value) => Node::FloatLiteral(*value),
+            Value::Bool(value) => Node::BooleanLiteral(*value),
+            Value::String(value) => Node::StringLiteral(value.clone()),
+            Value::Array(elements) => Node::Array { elements: elements.iter().map(Value::to_node).collect() },
+        }
+    }
+
+    /// Converts the value to a declared type, as the checker would allow
+    /// when assigning it.
+    fn coerce(self, ty: &Type) -> Result<Value, String> {
+        Ok(match (self, ty) {
+            (value @ Value::Int(_), Type::Int)
+            | (value @ Value::UInt(_), Type::UInt)
+            | (value @ Value::UInt256(_), Type::UInt256)
+            | (value @ Value::Float(_), Type::Float | Type::Double)
+            | (value @ Value::Bool(_), Type::Boolean)
+            | (value @ Value::String(_), Type::String) => value,
+            (Value::Int(value), Type::UInt) if value >= 0 => Value::UInt(value as u64),
+            (Value::Int(value), Type::UInt256) if value >= 0 => Value::UInt256(BigUint::from(value as u64)),
+            (Value::UInt(value), Type::UInt256) => Value::UInt256(BigUint::from(value)),
+            (Value::Int(value), Type::Float | Type::Double) => Value::Float(value as f64),
+            (Value::Array(elements), Type::Array(element)) => {
+                Value::Array(elements.into_iter().map(|value| value.coerce(element)).collect::<Result<_, _>>()?)
+            },
+            (value, ty) => return Err(format!("a {} value doesn't fit type {}", value.type_name(), gard_ast::type_to_source(ty))),
+        })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::UInt(value) => write!(f, "{}", value),
+            Value::UInt256(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            },
+        }
+    }
+}
+
+/// Evaluates an expression that only uses literals and operators.
+pub fn evaluate(expression: &Node) -> Result<Value, String> {
+    Folder::default().evaluate(expression, 0)
+}
+
+/// Evaluates every constant and substitutes it for its references.
+pub fn fold(mut program: Node) -> Result<Node, Vec<String>> {
+    let mut folder = Folder::default();
+    folder.visit(&mut program);
+    if folder.errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(folder.errors)
+    }
+}
+
+enum Binding {
+    /// A constant that hasn't been evaluated yet
+    Pending(Node),
+    Evaluating,
+    Constant(Value),
+    /// A local, parameter or field hiding any constant of the same name
+    Runtime,
+}
+
+#[derive(Default)]
+struct Folder {
+    /// Innermost last
+    scopes: Vec<HashMap<String, Binding>>,
+    /// `pure` functions, by name
+    functions: HashMap<String, Node>,
+    errors: Vec<String>,
+    steps: u64,
+}
+
+/// How a `pure` function's body finished.
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+impl Folder {
+    fn visit(&mut self, node: &mut Node) {
+        match node {
+            Node::Program(nodes) => {
+                for node in nodes.iter() {
+                    if let Node::Function { name, modifiers, .. } = node.unlocated() {
+                        if modifiers.contains(&FunctionModifier::Pure) {
+                            self.functions.insert(name.clone(), node.unlocated().clone());
+                        }
+                    }
+                }
+                self.scopes.push(HashMap::new());
+                self.declare_members(nodes);
+                self.visit_members(nodes);
+                self.scopes.pop();
+            },
+            Node::Class { members, .. } | Node::Contract { members, .. } => {
+                self.scopes.push(HashMap::new());
+                for member in members.iter() {
+                    if let Node::Let { name, .. } = member.unlocated() {
+                        self.bind(name, Binding::Runtime);
+                    }
+                }
+                self.declare_members(members);
+                self.visit_members(members);
+                self.scopes.pop();
+            },
+            Node::Function { params, body, .. } | Node::Constructor { params, body } => {
+                self.scopes.push(params.iter().map(|param| (param.name.to_string(), Binding::Runtime)).collect());
+                self.visit(body);
+                self.scopes.pop();
+            },
+            Node::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                for statement in statements {
+                    match statement.unlocated() {
+                        Node::Const { name, type_annotation, value } => {
+                            let binding = match self.constant(name, type_annotation.as_ref(), value) {
+                                Some(value) => Binding::Constant(value),
+                                None => Binding::Runtime,
+                            };
+                            self.bind(&name.clone(), binding);
+                        },
+                        _ => self.visit(statement),
+                    }
+                    if let Node::Let { name, .. } = statement.unlocated() {
+                        self.bind(&name.clone(), Binding::Runtime);
+                    }
+                }
+                self.scopes.pop();
+            },
+            Node::For { initializer, condition, increment, body } => {
+                self.scopes.push(HashMap::new());
+                if let Some(initializer) = initializer {
+                    self.visit(initializer);
+                    if let Node::Let { name, .. } = initializer.unlocated() {
+                        self.bind(&name.clone(), Binding::Runtime);
+                    }
+                }
+                for child in [condition, increment].into_iter().flatten() {
+                    self.visit(child);
+                }
+                self.visit(body);
+                self.scopes.pop();
+            },
+            Node::Foreach { item, collection, body } => {
+                self.visit(collection);
+                self.scopes.push(HashMap::from([(item.clone(), Binding::Runtime)]));
+                self.visit(body);
+                self.scopes.pop();
+            },
+            Node::CatchClause { param_name, body, .. } => {
+                self.scopes.push(HashMap::from([(param_name.clone(), Binding::Runtime)]));
+                self.visit(body);
+                self.scopes.pop();
+            },
+            Node::StorageSlot { slot, declaration } => {
+                match self.evaluate(slot, 0) {
+                    Ok(value) => **slot = value.to_node(),
+                    Err(e) => self.errors.push(format!("@slot: {}", e)),
+                }
+                self.visit(declaration);
+            },
+            Node::Attribute { arguments, declaration, .. } => {
+                // Arguments can also be names for the plugin; only the ones
+                // that evaluate are replaced
+                for argument in arguments.iter_mut() {
+                    match self.evaluate(argument, 0) {
+                        Ok(value) => *argument = value.to_node(),
+                        Err(_) => self.visit(argument),
+                    }
+                }
+                self.visit(declaration);
+            },
+            Node::Identifier(name) => {
+                if let Some(value) = self.lookup(name, self.scopes.len()) {
+                    *node = value.to_node();
+                }
+            },
+            Node::Binary { operator: BinaryOp::Add, .. } => {
+                for child in node.children_mut() {
+                    self.visit(child);
+                }
+                concatenate(node);
+            },
+            _ => {
+                for child in node.children_mut() {
+                    self.visit(child);
+                }
+            },
+        }
+    }
+
+    /// Declares the top-level or class constants of a scope, so members can
+    /// use them in any order.
+    fn declare_members(&mut self, members: &[Node]) {
+        for member in members {
+            if let Node::Const { name, .. } = member.unlocated() {
+                if matches!(self.scopes.last().and_then(|scope| scope.get(name)), Some(Binding::Pending(_))) {
+                    self.errors.push(format!("Constant '{}' is declared twice", name));
+                }
+                self.bind(name, Binding::Pending(member.unlocated().clone()));
+            }
+        }
+    }
+
+    /// Visits the members of a scope, evaluating the constants that nothing
+    /// used too so their errors are reported.
+    fn visit_members(&mut self, members: &mut [Node]) {
+        for member in members {
+            match member.unlocated() {
+                Node::Const { name, .. } => {
+                    let depth = self.scopes.len();
+                    self.lookup(&name.clone(), depth);
+                },
+                _ => self.visit(member),
+            }
+        }
+    }
+
+    fn bind(&mut self, name: &str, binding: Binding) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), binding);
+        }
+    }
+
+    /// The value of the constant `name` resolves to in the innermost `depth`
+    /// scopes, evaluating it first if needed. Errors are recorded; the name
+    /// is then left as it is.
+    fn lookup(&mut self, name: &str, depth: usize) -> Option<Value> {
+        let level = (0..depth).rev().find(|&level| self.scopes[level].contains_key(name))?;
+        let pending = match self.scopes[level].get_mut(name)? {
+            Binding::Constant(value) => return Some(value.clone()),
+            Binding::Runtime => return None,
+            Binding::Evaluating => {
+                self.errors.push(format!("Constant '{}' depends on itself", name));
+                self.scopes[level].insert(name.to_string(), Binding::Runtime);
+                return None;
+            },
+            pending => std::mem::replace(pending, Binding::Evaluating),
+        };
+        let value = match &pending {
+            Binding::Pending(Node::Const { type_annotation, value, .. }) => {
+                // A constant only sees the scopes it was declared in
+                let scopes = self.scopes.split_off(level + 1);
+                let result = self.constant(name, type_annotation.as_ref(), value);
+                self.scopes.extend(scopes);
+                result
+            },
+            _ => None,
+        };
+        let binding = match &value {
+            Some(value) => Binding::Constant(value.clone()),
+            None => Binding::Runtime,
+        };
+        self.scopes[level].insert(name.to_string(), binding);
+        value
+    }
+
+    /// Evaluates a constant's initializer, recording why it can't be.
+    fn constant(&mut self, name: &str, type_annotation: Option<&Type>, value: &Node) -> Option<Value> {
+        self.steps = 0;
+        let result = self.evaluate(value, 0).and_then(|value| match type_annotation {
+            Some(ty) => value.coerce(ty),
+            None => Ok(value),
+        });
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.errors.push(format!("Constant '{}': {}", name, e));
+                None
+            },
+        }
+    }
+
+    fn evaluate(&mut self, node: &Node, calls: usize) -> Result<Value, String> {
+        self.eval(node, &mut Vec::new(), calls)
+    }
+
+    fn tick(&mut self) -> Result<(), String> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(format!("evaluation took more than {} steps", MAX_STEPS));
+        }
+        Ok(())
+    }
+
+    /// `locals` are the frames of the `pure` call in progress, innermost
+    /// last, and `calls` how deeply calls are nested.
+    fn eval(&mut self, node: &Node, locals: &mut Vec<HashMap<String, Value>>, calls: usize) -> Result<Value, String> {
+        self.tick()?;
+        match node {
+            Node::Located { node, .. } => self.eval(node, locals, calls),
+            Node::IntLiteral(value) => Ok(Value::Int(*value)),
+            Node::UIntLiteral(value) => Ok(Value::UInt(*value)),
+            Node::UInt256Literal(literal) => parse_uint256_literal(literal).map(Value::UInt256),
+            Node::FloatLiteral(value) => Ok(Value::Float(*value)),
+            Node::BooleanLiteral(value) => Ok(Value::Bool(*value)),
+            Node::StringLiteral(value) => Ok(Value::String(value.clone())),
+            Node::Array { elements } => elements.iter()
+                .map(|element| self.eval(element, locals, calls))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            Node::Identifier(name) => {
+                if let Some(value) = locals.iter().rev().find_map(|frame| frame.get(name.as_str())) {
+                    return Ok(value.clone());
+                }
+                // Inside a call only the top-level constants are visible
+                let depth = if calls == 0 { self.scopes.len() } else { self.scopes.len().min(1) };
+                let errors = self.errors.len();
+                match self.lookup(name, depth) {
+                    Some(value) => Ok(value),
+                    // The constant's own error has been recorded
+                    None if self.errors.len() > errors => Err(format!("'{}' could not be evaluated", name)),
+                    None => Err(format!("'{}' is not a constant", name)),
+                }
+            },
+            Node::Unary { operator: UnaryOp::Minus, operand } => match self.eval(operand, locals, calls)? {
+                Value::Int(value) => value.checked_neg().map(Value::Int).ok_or_else(overflow),
+                Value::Float(value) => Ok(Value::Float(-value)),
+                value => Err(format!("can't negate a {} value", value.type_name())),
+            },
+            Node::Unary { operator: UnaryOp::Not, operand } => match self.eval(operand, locals, calls)? {
+                Value::Bool(value) => Ok(Value::Bool(!value)),
+                value => Err(format!("'!' expects a boolean, found a {} value", value.type_name())),
+            },
+            Node::Unary { operator: UnaryOp::BitNot, operand } => match self.eval(operand, locals, calls)? {
+                Value::Int(value) => Ok(Value::Int(!value)),
+                Value::UInt(value) => Ok(Value::UInt(!value)),
+                Value::UInt256(value) => Ok(Value::UInt256(uint256_max() - value)),
+                value => Err(format!("'~' expects an integer, found a {} value", value.type_name())),
+            },
+            Node::Unary { operator, operand } => {
+                let name = match operand.unlocated() {
+                    Node::Identifier(name) => name,
+                    _ => return Err("'++' and '--' need a local variable".to_string()),
+                };
+                let frame = locals.iter_mut().rev().find(|frame| frame.contains_key(name.as_str()))
+                    .ok_or_else(|| format!("'{}' can't be changed at compile time", name))?;
+                let step = match operator {
+                    UnaryOp::Increment | UnaryOp::PostIncrement => BinaryOp::Add,
+                    _ => BinaryOp::Sub,
+                };
+                let old = frame[name.as_str()].clone();
+                let value = binary(&step, old.clone(), Value::Int(1))?;
+                frame.insert(name.to_string(), value.clone());
+                match operator {
+                    UnaryOp::PostIncrement | UnaryOp::PostDecrement => Ok(old),
+                    _ => Ok(value),
+                }
+            },
+            Node::Binary { left, operator: BinaryOp::And, right } => {
+                Ok(Value::Bool(self.condition(left, locals, calls)? && self.condition(right, locals, calls)?))
+            },
+            Node::Binary { left, operator: BinaryOp::Or, right } => {
+                Ok(Value::Bool(self.condition(left, locals, calls)? || self.condition(right, locals, calls)?))
+            },
+            Node::Binary { left, operator, right } => {
+                let left = self.eval(left, locals, calls)?;
+                let right = self.eval(right, locals, calls)?;
+                binary(operator, left, right)
+            },
+            Node::Member { object, property } if property == "length" => match self.eval(object, locals, calls)? {
+                Value::Array(elements) => Ok(Value::Int(elements.len() as i64)),
+                Value::String(value) => Ok(Value::Int(value.chars().count() as i64)),
+                value => Err(format!("a {} value has no length", value.type_name())),
+            },
+            Node::Index { object, index, .. } => {
+                let object = self.eval(object, locals, calls)?;
+                let index = match self.eval(index, locals, calls)? {
+                    Value::Int(index) => index,
+                    value => return Err(format!("index must be an int, found a {} value", value.type_name())),
+                };
+                match object {
+                    Value::Array(elements) => usize::try_from(index).ok()
+                        .and_then(|position| elements.into_iter().nth(position))
+                        .ok_or_else(|| format!("index {} is out of bounds", index)),
+                    value => Err(format!("can't index a {} value", value.type_name())),
+                }
+            },
+            Node::Call { callee, arguments } => {
+                let name = match callee.unlocated() {
+                    Node::Identifier(name) => name,
+                    _ => return Err("only calls to pure functions can be evaluated at compile time".to_string()),
+                };
+                let arguments = arguments.iter()
+                    .map(|argument| self.eval(argument, locals, calls))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, arguments, calls)
+            },
+            other => Err(format!("{} can't be evaluated at compile time", describe(other))),
+        }
+    }
+
+    fn condition(&mut self, node: &Node, locals: &mut Vec<HashMap<String, Value>>, calls: usize) -> Result<bool, String> {
+        match self.eval(node, locals, calls)? {
+            Value::Bool(value) => Ok(value),
+            value => Err(format!("conditions must be booleans, found a {} value", value.type_name())),
+        }
+    }
+
+    fn call(&mut self, name: &str, arguments: Vec<Value>, calls: usize) -> Result<Value, String> {
+        let function = self.functions.get(name).cloned()
+            .ok_or_else(|| format!("'{}' is not a pure function, so it can't be called at compile time", name))?;
+        let (params, return_type, body) = match &function {
+            Node::Function { params, return_type, body, .. } => (params, return_type, body),
+            _ => unreachable!("only functions are collected"),
+        };
+        if params.len() != arguments.len() {
+            return Err(format!("{}() expects {} argument(s), found {}", name, params.len(), arguments.len()));
+        }
+        if calls >= MAX_CALL_DEPTH {
+            return Err(format!("more than {} nested calls", MAX_CALL_DEPTH));
+        }
+
+        let frame = params.iter().zip(arguments)
+            .map(|(param, value)| Ok((param.name.to_string(), value.coerce(&param.type_annotation)?)))
+            .collect::<Result<HashMap<_, _>, String>>()
+            .map_err(|e| format!("in a call to {}(): {}", name, e))?;
+        let mut locals = vec![frame];
+        let value = match body.unlocated() {
+            Node::Block(_) => match self.exec(body, &mut locals, calls + 1)? {
+                Flow::Return(value) => value,
+                _ => return Err(format!("{}() ended without returning a value", name)),
+            },
+            // An expression body
+            expression => self.eval(expression, &mut locals, calls + 1)?,
+        };
+        value.coerce(return_type).map_err(|e| format!("{}() returned {}", name, e))
+    }
+
+    fn exec(&mut self, node: &Node, locals: &mut Vec<HashMap<String, Value>>, calls: usize) -> Result<Flow, String> {
+        self.tick()?;
+        match node {
+            Node::Located { node, .. } => self.exec(node, locals, calls),
+            Node::Block(statements) => {
+                locals.push(HashMap::new());
+                let flow = self.exec_statements(statements, locals, calls);
+                locals.pop();
+                flow
+            },
+            Node::Let { name, type_annotation, initializer: Some(initializer), .. }
+            | Node::Const { name, type_annotation, value: initializer } => {
+                let value = self.eval(initializer, locals, calls)?;
+                let value = match type_annotation {
+                    Some(ty) => value.coerce(ty).map_err(|e| format!("'{}' is given {}", name, e))?,
+                    None => value,
+                };
+                locals.last_mut().expect("statements run in a block").insert(name.clone(), value);
+                Ok(Flow::Next)
+            },
+            Node::If { condition, then_branch, else_branch } => {
+                if self.condition(condition, locals, calls)? {
+                    self.exec(then_branch, locals, calls)
+                } else if let Some(else_branch) = else_branch {
+                    self.exec(else_branch, locals, calls)
+                } else {
+                    Ok(Flow::Next)
+                }
+            },
+            Node::While { condition, body } => {
+                while self.condition(condition, locals, calls)? {
+                    match self.exec(body, locals, calls)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Next | Flow::Continue => {},
+                    }
+                }
+                Ok(Flow::Next)
+            },
+            Node::For { initializer, condition, increment, body } => {
+                locals.push(HashMap::new());
+                let flow = self.exec_for(initializer.as_deref(), condition.as_deref(), increment.as_deref(), body, locals, calls);
+                locals.pop();
+                flow
+            },
+            Node::Return(Some(value)) => Ok(Flow::Return(self.eval(value, locals, calls)?)),
+            Node::Break => Ok(Flow::Break),
+            Node::Continue => Ok(Flow::Continue),
+            Node::Let { name, initializer: None, .. } => Err(format!("'{}' needs an initializer at compile time", name)),
+            Node::Return(None) | Node::Assertion { .. } | Node::Throw(_) | Node::Try { .. } => {
+                Err(format!("{} can't be evaluated at compile time", describe(node)))
+            },
+            expression => {
+                self.eval(expression, locals, calls)?;
+                Ok(Flow::Next)
+            },
+        }
+    }
+
+    fn exec_statements(&mut self, statements: &[Node], locals: &mut Vec<HashMap<String, Value>>, calls: usize) -> Result<Flow, String> {
+        for statement in statements {
+            match self.exec(statement, locals, calls)? {
+                Flow::Next => {},
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec_for(
+        &mut self,
+        initializer: Option<&Node>,
+        condition: Option<&Node>,
+        increment: Option<&Node>,
+        body: &Node,
+        locals: &mut Vec<HashMap<String, Value>>,
+        calls: usize,
+    ) -> Result<Flow, String> {
+        if let Some(initializer) = initializer {
+            self.exec(initializer, locals, calls)?;
+        }
+        loop {
+            if let Some(condition) = condition {
+                if !self.condition(condition, locals, calls)? {
+                    return Ok(Flow::Next);
+                }
+            }
+            match self.exec(body, locals, calls)? {
+                Flow::Break => return Ok(Flow::Next),
+                Flow::Return(value) => return Ok(Flow::Return(value)),
+                Flow::Next | Flow::Continue => {},
+            }
+            if let Some(increment) = increment {
+                self.eval(increment, locals, calls)?;
+            }
+        }
+    }
+}
+
+fn overflow() -> String {
+    "integer overflow".to_string()
+}
+
+/// Applies an operator with the checker's numeric rules: an `int` mixes
+/// with unsigned values when it isn't negative, and with floats.
+fn binary(operator: &BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+    use Value::*;
+    if matches!(operator, BinaryOp::Shl | BinaryOp::Shr) {
+        return shift(operator, left, right);
+    }
+    let (left, right) = match (left, right) {
+        (Int(left), UInt(right)) if left >= 0 => (UInt(left as u64), UInt(right)),
+        (UInt(left), Int(right)) if right >= 0 => (UInt(left), UInt(right as u64)),
+        (Int(left), UInt256(right)) if left >= 0 => (UInt256(BigUint::from(left as u64)), UInt256(right)),
+        (UInt256(left), Int(right)) if right >= 0 => (UInt256(left), UInt256(BigUint::from(right as u64))),
+        (UInt(left), UInt256(right)) => (UInt256(BigUint::from(left)), UInt256(right)),
+        (UInt256(left), UInt(right)) => (UInt256(left), UInt256(BigUint::from(right))),
+        (Int(left), Float(right)) => (Float(left as f64), Float(right)),
+        (Float(left), Int(right)) => (Float(left), Float(right as f64)),
+        pair => pair,
+    };
+
+    match (operator, left, right) {
+        (BinaryOp::Eq, left, right) => Ok(Bool(left == right)),
+        (BinaryOp::NotEq, left, right) => Ok(Bool(left != right)),
+        (BinaryOp::Add, String(left), String(right)) => Ok(String(left + &right)),
+        (operator, Int(left), Int(right)) => match operator {
+            BinaryOp::Add => left.checked_add(right).map(Int).ok_or_else(overflow),
+            BinaryOp::Sub => left.checked_sub(right).map(Int).ok_or_else(overflow),
+            BinaryOp::Mul => left.checked_mul(right).map(Int).ok_or_else(overflow),
+            BinaryOp::Div | BinaryOp::Mod if right == 0 => Err("division by zero".to_string()),
+            BinaryOp::Div => left.checked_div(right).map(Int).ok_or_else(overflow),
+            BinaryOp::Mod => left.checked_rem(right).map(Int).ok_or_else(overflow),
+            BinaryOp::BitAnd => Ok(Int(left & right)),
+            BinaryOp::BitOr => Ok(Int(left | right)),
+            BinaryOp::BitXor => Ok(Int(left ^ right)),
+            operator => compare(operator, left.cmp(&right)),
+        },
+        (operator, UInt(left), UInt(right)) => match operator {
+            BinaryOp::Add => left.checked_add(right).map(UInt).ok_or_else(overflow),
+            BinaryOp::Sub => left.checked_sub(right).map(UInt).ok_or_else(overflow),
+            BinaryOp::Mul => left.checked_mul(right).map(UInt).ok_or_else(overflow),
+            BinaryOp::Div | BinaryOp::Mod if right == 0 => Err("division by zero".to_string()),
+            BinaryOp::Div => Ok(UInt(left / right)),
+            BinaryOp::Mod => Ok(UInt(left % right)),
+            BinaryOp::BitAnd => Ok(UInt(left & right)),
+            BinaryOp::BitOr => Ok(UInt(left | right)),
+            BinaryOp::BitXor => Ok(UInt(left ^ right)),
+            operator => compare(operator, left.cmp(&right)),
+        },
+        (operator, UInt256(left), UInt256(right)) => {
+            let value = match operator {
+                BinaryOp::Add => left + right,
+                BinaryOp::Sub if left < right => return Err(overflow()),
+                BinaryOp::Sub => left - right,
+                BinaryOp::Mul => left * right,
+                BinaryOp::Div | BinaryOp::Mod if right.is_zero() => return Err("division by zero".to_string()),
+                BinaryOp::Div => left / right,
+                BinaryOp::Mod => left % right,
+                BinaryOp::BitAnd => left & right,
+                BinaryOp::BitOr => left | right,
+                BinaryOp::BitXor => left ^ right,
+                operator => return compare(operator, left.cmp(&right)),
+            };
+            if value.bits() > 256 {
+                return Err(overflow());
+            }
+            Ok(UInt256(value))
+        },
+        (operator, Float(left), Float(right)) => match operator {
+            BinaryOp::Add => Ok(Float(left + right)),
+            BinaryOp::Sub => Ok(Float(left - right)),
+            BinaryOp::Mul => Ok(Float(left * right)),
+            BinaryOp::Div => Ok(Float(left / right)),
+            BinaryOp::Mod => Ok(Float(left % right)),
+            operator => match left.partial_cmp(&right) {
+                Some(ordering) => compare(operator, ordering),
+                None => Ok(Bool(false)),
+            },
+        },
+        (operator @ (BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq), String(left), String(right)) => {
+            compare(operator, left.cmp(&right))
+        },
+        (operator, left, right) => Err(format!(
+            "{:?} isn't defined on {} and {} values", operator, left.type_name(), right.type_name(),
+        )),
+    }
+}
+
+/// `value << amount` or `value >> amount`, where the amount can be any
+/// integer. Bits shifted out are dropped, like at run time, and shifting by
+/// the value's width or more overflows. `>>` keeps an `int`'s sign.
+fn shift(operator: &BinaryOp, value: Value, amount: Value) -> Result<Value, String> {
+    use Value::*;
+    let amount = match amount {
+        Int(amount) => u64::try_from(amount).map_err(|_| overflow())?,
+        UInt(amount) => amount,
+        UInt256(amount) => u64::try_from(amount).map_err(|_| overflow())?,
+        amount => return Err(format!("{:?} isn't defined on {} and {} values", operator, value.type_name(), amount.type_name())),
+    };
+    let left = *operator == BinaryOp::Shl;
+    match value {
+        Int(value) if amount < 64 => Ok(Int(if left { value << amount } else { value >> amount })),
+        UInt(value) if amount < 64 => Ok(UInt(if left { value << amount } else { value >> amount })),
+        UInt256(value) if amount < 256 => Ok(UInt256(if left { (value << amount) & uint256_max() } else { value >> amount })),
+        Int(_) | UInt(_) | UInt256(_) => Err(overflow()),
+        value => Err(format!("{:?} isn't defined on {} values", operator, value.type_name())),
+    }
+}
+
+/// Merges the adjacent string literals of a `+` chain, as in `x + "a" + "b"`
+/// to `x + "ab"`. `+` is left-associative and a string on either side makes
+/// it a concatenation, so wherever two literals meet in the chain the part
+/// before them is a string by then and they can be joined first.
+fn concatenate(node: &mut Node) {
+    let mut operands = Vec::new();
+    chain(node, &mut operands);
+    let adjacent = operands.windows(2)
+        .any(|pair| pair.iter().all(|operand| matches!(operand.unlocated(), Node::StringLiteral(_))));
+    if !adjacent {
+        return;
+    }
+
+    let mut operands = Vec::new();
+    into_chain(std::mem::replace(node, Node::NullLiteral), None, &mut operands);
+    let mut merged: Vec<(Node, Option<Span>)> = Vec::new();
+    for (operand, span) in operands {
+        match (merged.last().map(|(operand, _)| operand.unlocated()), operand.unlocated()) {
+            // The `+` that ends with the joined literal keeps its span
+            (Some(Node::StringLiteral(left)), Node::StringLiteral(right)) => {
+                let joined = format!("{}{}", left, right);
+                *merged.last_mut().expect("matched above") = (Node::StringLiteral(joined), span);
+            },
+            _ => merged.push((operand, span)),
+        }
+    }
+    let mut merged = merged.into_iter();
+    let (first, _) = merged.next().expect("a chain has an operand");
+    *node = merged.fold(first, |left, (right, span)| {
+        let sum = Node::Binary { left: Box::new(left), operator: BinaryOp::Add, right: Box::new(right) };
+        match span {
+            Some(span) => Node::Located { span, node: Box::new(sum) },
+            None => sum,
+        }
+    });
+}
+
+/// The operands of a left-associative `+` chain, in order.
+fn chain<'a>(node: &'a Node, operands: &mut Vec<&'a Node>) {
+    match node.unlocated() {
+        Node::Binary { left, operator: BinaryOp::Add, right } => {
+            chain(left, operands);
+            operands.push(right);
+        },
+        _ => operands.push(node),
+    }
+}
+
+/// Like `chain`, but taking the operands, each with the span of the `+`
+/// it's the right side of, where that `+` was located. `span` is the span
+/// of `node`.
+fn into_chain(node: Node, span: Option<Span>, operands: &mut Vec<(Node, Option<Span>)>) {
+    match node {
+        Node::Located { span, node } if matches!(node.unlocated(), Node::Binary { operator: BinaryOp::Add, .. }) => {
+            into_chain(*node, Some(span), operands)
+        },
+        Node::Binary { left, operator: BinaryOp::Add, right } => {
+            into_chain(*left, None, operands);
+            operands.push((*right, span));
+        },
+        operand => operands.push((operand, None)),
+    }
+}
+
+fn uint256_max() -> BigUint {
+    (BigUint::from(1u8) << 256u32) - 1u8
+}
+
+fn compare(operator: &BinaryOp, ordering: std::cmp::Ordering) -> Result<Value, String> {
+    use std::cmp::Ordering::*;
+    Ok(Value::Bool(match operator {
+        BinaryOp::Lt => ordering == Less,
+        BinaryOp::LtEq => ordering != Greater,
+        BinaryOp::Gt => ordering == Greater,
+        BinaryOp::GtEq => ordering != Less,
+        operator => return Err(format!("{:?} isn't defined on these values", operator)),
+    }))
+}
+
+/// Names a construct for an error message.
+fn describe(node: &Node) -> String {
+    match node {
+        Node::Member { property, .. } => format!("'.{}'", property),
+        Node::This => "'this'".to_string(),
+        Node::NullLiteral => "null".to_string(),
+        other => {
+            let debug = format!("{:?}", other);
+            let name = debug.split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default();
+            format!("{} expression", name)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::Parameter;
+
+    fn ident(name: &str) -> Box<Node> {
+        Box::new(Node::Identifier(name.into()))
+    }
+
+    fn int(value: i64) -> Box<Node> {
+        Box::new(Node::IntLiteral(value))
+    }
+
+    fn binary(left: Box<Node>, operator: BinaryOp, right: Box<Node>) -> Box<Node> {
+        Box::new(Node::Binary { left, operator, right })
+    }
+
+    fn constant(name: &str, type_annotation: Option<Type>, value: Box<Node>) -> Node {
+        Node::Const { name: name.to_string(), type_annotation, value }
+    }
+
+    fn function(name: &str, modifiers: Vec<FunctionModifier>, params: &[&str], body: Vec<Node>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: params.iter().map(|name| Parameter { name: (*name).into(), type_annotation: Type::Int }).collect(),
+            return_type: Type::Int,
+            body: Box::new(Node::Block(body)),
+            modifiers,
+            docs: None,
+        }
+    }
+
+    fn call(name: &str, arguments: Vec<Node>) -> Box<Node> {
+        Box::new(Node::Call { callee: ident(name), arguments })
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let program = Node::Program(vec![
+            function("main", vec![], &["SIZE"], vec![
+                Node::Return(Some(binary(ident("LIMIT"), BinaryOp::Add, ident("SIZE")))),
+            ]),
+            // Used before it is declared, and typed from its annotation
+            constant("LIMIT", Some(Type::UInt), binary(ident("SIZE"), BinaryOp::Mul, call("square", vec![*int(2)]))),
+            constant("SIZE", None, int(8)),
+            function("square", vec![FunctionModifier::Pure], &["x"], vec![
+                Node::Let { name: "y".to_string(), type_annotation: None, initializer: Some(binary(ident("x"), BinaryOp::Mul, ident("x"))), is_mutable: false },
+                Node::Return(Some(ident("y"))),
+            ]),
+        ]);
+
+        let folded = fold(program).unwrap();
+        let nodes = match &folded {
+            Node::Program(nodes) => nodes,
+            other => panic!("expected program, found {:?}", other),
+        };
+        // The parameter hides the constant of the same name
+        assert_eq!(nodes[0], function("main", vec![], &["SIZE"], vec![
+            Node::Return(Some(binary(Box::new(Node::UIntLiteral(32)), BinaryOp::Add, ident("SIZE")))),
+        ]));
+    }
+
+    #[test]
+    fn test_fold_bitwise_operators() {
+        let apply = |left: Value, operator: BinaryOp, right: Value| super::binary(&operator, left, right);
+        let uint256 = |value: &str| Value::UInt256(BigUint::parse_bytes(value.as_bytes(), 16).unwrap());
+
+        assert_eq!(apply(Value::Int(0b1100), BinaryOp::BitXor, Value::Int(0b1010)), Ok(Value::Int(0b0110)));
+        assert_eq!(apply(Value::UInt(0xf0), BinaryOp::BitAnd, Value::Int(0x3c)), Ok(Value::UInt(0x30)));
+        assert_eq!(apply(Value::Int(-16), BinaryOp::Shr, Value::UInt(2)), Ok(Value::Int(-4)));
+        assert_eq!(apply(Value::Int(1), BinaryOp::Shl, Value::Int(64)), Err(overflow()));
+        // The top bit is shifted out of the 256 bits
+        assert_eq!(apply(uint256("8000000000000000000000000000000000000000000000000000000000000001"), BinaryOp::Shl, Value::Int(1)),
+            Ok(uint256("2")));
+        assert!(apply(Value::Float(1.0), BinaryOp::BitOr, Value::Float(2.0)).is_err());
+
+        let program = Node::Program(vec![
+            constant("MASK", Some(Type::Int), Box::new(Node::Unary { operator: UnaryOp::BitNot, operand: binary(int(1), BinaryOp::Shl, int(4)) })),
+            function("main", vec![], &[], vec![Node::Return(Some(ident("MASK")))]),
+        ]);
+        let Node::Program(nodes) = fold(program).unwrap() else {
+            panic!("expected program");
+        };
+        assert_eq!(nodes[1], function("main", vec![], &[], vec![Node::Return(Some(int(-17)))]));
+    }
+
+    #[test]
+    fn test_fold_string_concatenation() {
+        let string = |value: &str| Box::new(Node::StringLiteral(value.to_string()));
+        let program = Node::Program(vec![
+            constant("NAME", None, string("Gard")),
+            function("main", vec![], &["x"], vec![
+                Node::Return(Some(binary(binary(string("Hello "), BinaryOp::Add, ident("NAME")), BinaryOp::Add, string("!")))),
+                Node::Return(Some(binary(binary(binary(ident("x"), BinaryOp::Add, string("a")), BinaryOp::Add, string("b")), BinaryOp::Add, ident("x")))),
+                // Parenthesized on the right, so "a" is added to x first
+                Node::Return(Some(binary(string("a"), BinaryOp::Add, binary(ident("x"), BinaryOp::Add, string("b"))))),
+            ]),
+        ]);
+        let Node::Program(nodes) = fold(program).unwrap() else {
+            panic!("expected program");
+        };
+        assert_eq!(nodes[1], function("main", vec![], &["x"], vec![
+            Node::Return(Some(string("Hello Gard!"))),
+            Node::Return(Some(binary(binary(ident("x"), BinaryOp::Add, string("ab")), BinaryOp::Add, ident("x")))),
+            Node::Return(Some(binary(string("a"), BinaryOp::Add, binary(ident("x"), BinaryOp::Add, string("b"))))),
+        ]));
+
+        // The `+`s inside the chain keep their spans
+        let located = |start: usize, end: usize, node: Box<Node>| Box::new(Node::Located { span: Span { start, end }, node });
+        let mut chain = *binary(
+            located(0, 13, binary(located(0, 7, binary(ident("x"), BinaryOp::Add, string("a"))), BinaryOp::Add, string("b"))),
+            BinaryOp::Add,
+            ident("x"),
+        );
+        concatenate(&mut chain);
+        assert_eq!(chain, *binary(located(0, 13, binary(ident("x"), BinaryOp::Add, string("ab"))), BinaryOp::Add, ident("x")));
+    }
+
+    #[test]
+    fn test_non_constant_errors() {
+        let program = Node::Program(vec![
+            constant("A", None, binary(ident("B"), BinaryOp::Add, int(1))),
+            constant("B", None, ident("A")),
+            constant("NOW", None, call("clock", vec![])),
+            constant("HUGE", None, binary(int(i64::MAX), BinaryOp::Mul, int(2))),
+            function("clock", vec![], &[], vec![Node::Return(Some(int(0)))]),
+        ]);
+
+        let errors = fold(program).unwrap_err();
+        assert!(errors.contains(&"Constant 'A' depends on itself".to_string()));
+        assert!(errors.contains(&"Constant 'NOW': 'clock' is not a pure function, so it can't be called at compile time".to_string()));
+        assert!(errors.contains(&"Constant 'HUGE': integer overflow".to_string()));
+    }
+
+    #[test]
+    fn test_slots_and_attribute_arguments() {
+        let slot = Node::Contract {
+            name: "Vault".to_string(),
+            members: vec![
+                constant("BASE", None, int(4)),
+                Node::StorageSlot {
+                    slot: binary(ident("BASE"), BinaryOp::Add, int(1)),
+                    declaration: Box::new(Node::Let { name: "owner".to_string(), type_annotation: Some(Type::Address), initializer: None, is_mutable: true }),
+                },
+            ],
+            docs: None,
+        };
+        let attribute = Node::Attribute {
+            name: "route".to_string(),
+            arguments: vec![*binary(Box::new(Node::StringLiteral("/v".to_string())), BinaryOp::Add, Box::new(Node::StringLiteral("1".to_string()))), *ident("get")],
+            declaration: Box::new(function("handler", vec![], &[], vec![])),
+        };
+
+        let folded = fold(Node::Program(vec![slot, attribute])).unwrap();
+        let nodes = match &folded {
+            Node::Program(nodes) => nodes,
+            other => panic!("expected program, found {:?}", other),
+        };
+        assert!(matches!(&nodes[0], Node::Contract { members, .. } if matches!(&members[1], Node::StorageSlot { slot, .. } if **slot == Node::IntLiteral(5))));
+        assert!(matches!(&nodes[1], Node::Attribute { arguments, .. }
+            if arguments[0] == Node::StringLiteral("/v1".to_string()) && *arguments[1].unlocated() == Node::Identifier("get".into())));
+        assert_eq!(evaluate(&Node::Index { object: Box::new(Node::Array { elements: vec![*int(1)] }), index: int(3), checked: true }),
+            Err("index 3 is out of bounds".to_string()));
+    }
+}
@@ -0,0 +1,107 @@
+use gard_ast::{Node, Type};
+
+/// Builtins of `std.crypto`. `hash` and `sign` are keyword aliases for
+/// keccak256 and sign respectively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoBuiltin {
+    Keccak256,
+    Sha256,
+    EcRecover,
+    VerifySignature,
+    Sign,
+    RandomSalt,
+}
+
+/// How a builtin is lowered on the EVM target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvmLowering {
+    Opcode(u8),
+    Precompile(u8),
+}
+
+impl CryptoBuiltin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hash" | "keccak256" => Some(CryptoBuiltin::Keccak256),
+            "sha256" => Some(CryptoBuiltin::Sha256),
+            "ecrecover" => Some(CryptoBuiltin::EcRecover),
+            "verifySignature" => Some(CryptoBuiltin::VerifySignature),
+            "sign" => Some(CryptoBuiltin::Sign),
+            "randomSalt" => Some(CryptoBuiltin::RandomSalt),
+            _ => None,
+        }
+    }
+
+    /// Resolves `keccak256(..)`, `hash(..)` and `crypto.keccak256(..)` style callees.
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee {
+            Node::Identifier(name) => Self::from_name(name),
+            Node::Member { object, property } => match object.as_ref() {
+                Node::Identifier(module) if module == "crypto" => Self::from_name(property),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CryptoBuiltin::Keccak256 => "keccak256",
+            CryptoBuiltin::Sha256 => "sha256",
+            CryptoBuiltin::EcRecover => "ecrecover",
+            CryptoBuiltin::VerifySignature => "verifySignature",
+            CryptoBuiltin::Sign => "sign",
+            CryptoBuiltin::RandomSalt => "randomSalt",
+        }
+    }
+
+    /// Hashes, signatures, keys and addresses are `bytes`. A hash takes
+    /// bytes or a string, which it hashes as its UTF-8 bytes.
+    pub fn signature(&self) -> Type {
+        let (params, return_type) = match self {
+            CryptoBuiltin::Keccak256 | CryptoBuiltin::Sha256 => (vec![Type::Bytes], Type::Bytes),
+            CryptoBuiltin::EcRecover | CryptoBuiltin::Sign => (vec![Type::Bytes; 2], Type::Bytes),
+            CryptoBuiltin::VerifySignature => (vec![Type::Bytes; 3], Type::Boolean),
+            CryptoBuiltin::RandomSalt => (vec![], Type::Bytes),
+        };
+        Type::Function { params, return_type: Box::new(return_type) }
+    }
+
+    /// Whether gard-vm takes the builtin's data as a pointer and a length
+    /// rather than a bytes block.
+    pub fn is_hash(&self) -> bool {
+        matches!(self, CryptoBuiltin::Keccak256 | CryptoBuiltin::Sha256)
+    }
+
+    /// Symbol of the native implementation in gard-vm.
+    pub fn runtime_symbol(&self) -> &'static str {
+        match self {
+            CryptoBuiltin::Keccak256 => "gard_crypto_keccak256",
+            CryptoBuiltin::Sha256 => "gard_crypto_sha256",
+            CryptoBuiltin::EcRecover => "gard_crypto_ecrecover",
+            CryptoBuiltin::VerifySignature => "gard_crypto_verify",
+            CryptoBuiltin::Sign => "gard_crypto_sign",
+            CryptoBuiltin::RandomSalt => "gard_crypto_random_salt",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_aliases() {
+        assert_eq!(CryptoBuiltin::from_name("hash"), Some(CryptoBuiltin::Keccak256));
+        assert_eq!(CryptoBuiltin::from_name("sign"), Some(CryptoBuiltin::Sign));
+        assert_eq!(CryptoBuiltin::from_name("print"), None);
+    }
+
+    #[test]
+    fn test_module_member_callee() {
+        let callee = Node::Member {
+            object: Box::new(Node::Identifier("crypto".into())),
+            property: "sha256".to_string(),
+        };
+        assert_eq!(CryptoBuiltin::from_callee(&callee), Some(CryptoBuiltin::Sha256));
+    }}
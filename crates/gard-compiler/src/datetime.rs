@@ -0,0 +1,192 @@
+use gard_ast::{BinaryOp, Node, Type};
+
+/// Builtins of `std.datetime`, called as `datetime.now()`. Instants and
+/// date-times are milliseconds since the Unix epoch in UTC and durations are
+/// milliseconds, all stored as an int; the checker keeps the three apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateTimeBuiltin {
+    Now,
+    FromTimestamp,
+    ToDateTime,
+    Parse,
+    Format,
+    Milliseconds,
+    Seconds,
+    Minutes,
+    Hours,
+    Days,
+}
+
+pub const MODULE: &str = "datetime";
+pub const INSTANT: &str = "Instant";
+pub const DURATION: &str = "Duration";
+pub const DATE_TIME: &str = "DateTime";
+
+/// Whether `ty` is one of the `std.datetime` types.
+pub fn is_time_type(ty: &Type) -> bool {
+    matches!(ty, Type::Custom(name) if name == INSTANT || name == DURATION || name == DATE_TIME)
+}
+
+fn custom(name: &str) -> Type {
+    Type::Custom(name.into())
+}
+
+/// Result of `left operator right` when either side is a time type, or
+/// `None` when neither is or the operator isn't arithmetic or a comparison.
+/// A point in time plus or minus a duration is a point of the same kind, two
+/// points of the same kind differ by a duration and durations scale by ints.
+pub fn binary(operator: &BinaryOp, left: &Type, right: &Type) -> Option<Result<Type, String>> {
+    if matches!(operator, BinaryOp::And | BinaryOp::Or | BinaryOp::NullCoalesce) || (!is_time_type(left) && !is_time_type(right)) {
+        return None;
+    }
+    let duration = custom(DURATION);
+    let point = |ty: &Type| is_time_type(ty) && *ty != duration;
+    let integer = |ty: &Type| matches!(ty, Type::Int | Type::UInt);
+    let result = match operator {
+        BinaryOp::Add if point(left) && *right == duration => Some(left.clone()),
+        BinaryOp::Add if *left == duration && point(right) => Some(right.clone()),
+        BinaryOp::Sub if point(left) && *right == duration => Some(left.clone()),
+        BinaryOp::Sub if point(left) && left == right => Some(duration),
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mod if *left == duration && *right == duration => Some(duration),
+        BinaryOp::Mul if *left == duration && integer(right) => Some(duration),
+        BinaryOp::Mul if integer(left) && *right == duration => Some(duration),
+        BinaryOp::Div if *left == duration && integer(right) => Some(duration),
+        BinaryOp::Div if *left == duration && *right == duration => Some(Type::Int),
+        BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq if left == right => {
+            Some(Type::Boolean)
+        },
+        _ => None,
+    };
+    Some(result.ok_or_else(|| format!("Operator {:?} cannot be applied to {:?} and {:?}", operator, left, right)))
+}
+
+impl DateTimeBuiltin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "now" => Some(DateTimeBuiltin::Now),
+            "fromTimestamp" => Some(DateTimeBuiltin::FromTimestamp),
+            "toDateTime" => Some(DateTimeBuiltin::ToDateTime),
+            "parse" => Some(DateTimeBuiltin::Parse),
+            "format" => Some(DateTimeBuiltin::Format),
+            "milliseconds" => Some(DateTimeBuiltin::Milliseconds),
+            "seconds" => Some(DateTimeBuiltin::Seconds),
+            "minutes" => Some(DateTimeBuiltin::Minutes),
+            "hours" => Some(DateTimeBuiltin::Hours),
+            "days" => Some(DateTimeBuiltin::Days),
+            _ => None,
+        }
+    }
+
+    /// Resolves `datetime.now()` style callees.
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee.unlocated() {
+            Node::Member { object, property } => match object.unlocated() {
+                Node::Identifier(module) if module == MODULE => Self::from_name(property),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DateTimeBuiltin::Now => "now",
+            DateTimeBuiltin::FromTimestamp => "fromTimestamp",
+            DateTimeBuiltin::ToDateTime => "toDateTime",
+            DateTimeBuiltin::Parse => "parse",
+            DateTimeBuiltin::Format => "format",
+            DateTimeBuiltin::Milliseconds => "milliseconds",
+            DateTimeBuiltin::Seconds => "seconds",
+            DateTimeBuiltin::Minutes => "minutes",
+            DateTimeBuiltin::Hours => "hours",
+            DateTimeBuiltin::Days => "days",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            DateTimeBuiltin::Now => 0,
+            _ => 1,
+        }
+    }
+
+    /// `fromTimestamp` takes Unix seconds, like `block.timestamp`; `parse`
+    /// and `format` use ISO-8601.
+    pub fn signature(&self) -> Type {
+        let (params, return_type) = match self {
+            DateTimeBuiltin::Now => (vec![], custom(INSTANT)),
+            DateTimeBuiltin::FromTimestamp => (vec![Type::UInt], custom(INSTANT)),
+            DateTimeBuiltin::ToDateTime => (vec![custom(INSTANT)], custom(DATE_TIME)),
+            DateTimeBuiltin::Parse => (vec![Type::String], custom(DATE_TIME)),
+            DateTimeBuiltin::Format => (vec![custom(DATE_TIME)], Type::String),
+            _ => (vec![Type::Int], custom(DURATION)),
+        };
+        Type::Function { params, return_type: Box::new(return_type) }
+    }
+
+    /// Milliseconds per unit for the builtins that only scale their
+    /// argument.
+    pub fn scale(&self) -> Option<i64> {
+        match self {
+            DateTimeBuiltin::FromTimestamp | DateTimeBuiltin::Seconds => Some(1_000),
+            DateTimeBuiltin::ToDateTime | DateTimeBuiltin::Milliseconds => Some(1),
+            DateTimeBuiltin::Minutes => Some(60_000),
+            DateTimeBuiltin::Hours => Some(3_600_000),
+            DateTimeBuiltin::Days => Some(86_400_000),
+            DateTimeBuiltin::Now | DateTimeBuiltin::Parse | DateTimeBuiltin::Format => None,
+        }
+    }
+
+    /// Symbol of the native implementation in gard-vm, for the builtins
+    /// that aren't a multiplication.
+    pub fn runtime_symbol(&self) -> Option<&'static str> {
+        match self {
+            DateTimeBuiltin::Now => Some("gard_datetime_now"),
+            DateTimeBuiltin::Parse => Some("gard_datetime_parse"),
+            DateTimeBuiltin::Format => Some("gard_datetime_format"),
+            _ => None,
+        }
+    }
+
+    /// Contracts must agree on every value they compute, so on the EVM the
+    /// current time is the block's and there is no text to parse or format.
+    pub fn evm_check(&self) -> Result<(), String> {
+        match self {
+            DateTimeBuiltin::Parse | DateTimeBuiltin::Format => Err(format!(
+                "datetime.{}() is not available on the EVM target; contract times derive from block.timestamp",
+                self.name()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_member_callee() {
+        let callee = |module: &str, name: &str| Node::Member {
+            object: Box::new(Node::Identifier(module.into())),
+            property: name.to_string(),
+        };
+        assert_eq!(DateTimeBuiltin::from_callee(&callee("datetime", "hours")), Some(DateTimeBuiltin::Hours));
+        assert_eq!(DateTimeBuiltin::from_callee(&callee("datetime", "weeks")), None);
+        assert_eq!(DateTimeBuiltin::from_callee(&callee("regex", "now")), None);
+        assert!(DateTimeBuiltin::Format.evm_check().is_err());
+        assert!(DateTimeBuiltin::Now.evm_check().is_ok());
+    }
+
+    #[test]
+    fn test_time_arithmetic() {
+        let (instant, duration, date_time) = (custom(INSTANT), custom(DURATION), custom(DATE_TIME));
+        assert_eq!(binary(&BinaryOp::Add, &instant, &duration), Some(Ok(instant.clone())));
+        assert_eq!(binary(&BinaryOp::Sub, &date_time, &date_time), Some(Ok(duration.clone())));
+        assert_eq!(binary(&BinaryOp::Mul, &Type::Int, &duration), Some(Ok(duration.clone())));
+        assert_eq!(binary(&BinaryOp::Lt, &instant, &instant), Some(Ok(Type::Boolean)));
+        assert!(matches!(binary(&BinaryOp::Sub, &instant, &date_time), Some(Err(_))));
+        assert!(matches!(binary(&BinaryOp::Add, &instant, &instant), Some(Err(_))));
+        assert_eq!(binary(&BinaryOp::Add, &Type::Int, &Type::Int), None);
+    }
+}
@@ -0,0 +1,259 @@
+//! `@derive(..)` expansion. Like macros this runs before type checking: each
+//! `Node::Derive` is replaced by its class with the derived methods added.
+//!
+//! Derived methods compare, print or serialize fields one by one. A field
+//! whose type is another class uses that class's own method, so it has to
+//! derive (or define) it too.
+
+use gard_ast::{BinaryOp, Node, Parameter, Type};
+
+/// `equals(other: Self): boolean`, comparing every field
+pub const EQUALS: &str = "Equals";
+/// `toString(): string`, as `Point(x=1, y=2)`
+pub const TO_STRING: &str = "ToString";
+/// `serialize(): string`, a JSON object of the fields
+pub const SERIALIZE: &str = "Serialize";
+
+/// Replaces every `@derive` in the program with the class it annotates.
+pub fn expand(mut program: Node) -> Result<Node, Vec<String>> {
+    let mut errors = Vec::new();
+    expand_node(&mut program, &mut errors);
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+fn expand_node(node: &mut Node, errors: &mut Vec<String>) {
+    if let Node::Derive { derives, declaration } = node {
+        let derives = std::mem::take(derives);
+        let mut class = std::mem::replace(declaration.as_mut(), Node::NullLiteral);
+        derive_all(&mut class, &derives, errors);
+        *node = class;
+    }
+    for child in node.children_mut() {
+        expand_node(child, errors);
+    }
+}
+
+fn derive_all(class: &mut Node, derives: &[String], errors: &mut Vec<String>) {
+    let (name, members) = match class {
+        Node::Class { name, members, .. } => (name.clone(), members),
+        other => {
+            errors.push(format!("@derive can only be applied to a class, found {:?}", other.unlocated()));
+            return;
+        },
+    };
+    let fields: Vec<(String, Type)> = members.iter()
+        .filter_map(|member| match member.unlocated() {
+            Node::Let { name, type_annotation, .. } => Some((name.clone(), type_annotation.clone().unwrap_or(Type::Void))),
+            _ => None,
+        })
+        .collect();
+
+    for derive in derives {
+        let method = match derive.as_str() {
+            EQUALS => equals(&name, &fields),
+            TO_STRING => to_string(&name, &fields),
+            SERIALIZE => serialize(&fields),
+            other => {
+                errors.push(format!("Unknown derive '{}' on class '{}'", other, name));
+                continue;
+            },
+        };
+
+        let Node::Function { name: method_name, .. } = &method else { unreachable!() };
+        let defined = members.iter().any(|member| matches!(member.unlocated(), Node::Function { name, .. } if name == method_name));
+        if defined {
+            errors.push(format!("Class '{}' derives {} but already defines '{}'", name, derive, method_name));
+        } else {
+            members.push(method);
+        }
+    }
+}
+
+fn equals(class: &str, fields: &[(String, Type)]) -> Node {
+    let comparisons = fields.iter().map(|(field, ty)| match ty {
+        Type::Custom(_) => method_call(field_of(Node::This, field), "equals", vec![field_of(identifier("other"), field)]),
+        _ => binary(field_of(Node::This, field), BinaryOp::Eq, field_of(identifier("other"), field)),
+    });
+    let body = comparisons.reduce(|all, comparison| binary(all, BinaryOp::And, comparison))
+        .unwrap_or(Node::BooleanLiteral(true));
+
+    method(
+        "equals",
+        vec![Parameter { name: "other".into(), type_annotation: Type::Custom(class.into()) }],
+        Type::Boolean,
+        body,
+    )
+}
+
+fn to_string(class: &str, fields: &[(String, Type)]) -> Node {
+    let mut parts = vec![string(&format!("{}(", class))];
+    for (i, (field, ty)) in fields.iter().enumerate() {
+        let separator = if i == 0 { "" } else { ", " };
+        parts.push(string(&format!("{}{}=", separator, field)));
+        parts.push(match ty {
+            Type::Custom(_) => method_call(field_of(Node::This, field), "toString", vec![]),
+            _ => field_of(Node::This, field),
+        });
+    }
+    parts.push(string(")"));
+    method("toString", vec![], Type::String, concat(parts))
+}
+
+fn serialize(fields: &[(String, Type)]) -> Node {
+    let mut parts = vec![string("{")];
+    for (i, (field, ty)) in fields.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        parts.push(string(&format!("{}\"{}\":", separator, field)));
+        match ty {
+            Type::Custom(_) => parts.push(method_call(field_of(Node::This, field), "serialize", vec![])),
+            Type::String => {
+                parts.push(string("\""));
+                parts.push(field_of(Node::This, field));
+                parts.push(string("\""));
+            },
+            _ => parts.push(field_of(Node::This, field)),
+        }
+    }
+    parts.push(string("}"));
+    method("serialize", vec![], Type::String, concat(parts))
+}
+
+fn method(name: &str, params: Vec<Parameter>, return_type: Type, result: Node) -> Node {
+    Node::Function {
+        name: name.to_string(),
+        params,
+        return_type,
+        body: Box::new(Node::Block(vec![Node::Return(Some(Box::new(result)))])),
+        modifiers: vec![],
+        docs: None,
+    }
+}
+
+fn identifier(name: &str) -> Node {
+    Node::Identifier(name.into())
+}
+
+fn string(value: &str) -> Node {
+    Node::StringLiteral(value.to_string())
+}
+
+fn field_of(object: Node, field: &str) -> Node {
+    Node::Member { object: Box::new(object), property: field.to_string() }
+}
+
+fn method_call(object: Node, method: &str, arguments: Vec<Node>) -> Node {
+    Node::Call { callee: Box::new(field_of(object, method)), arguments }
+}
+
+fn binary(left: Node, operator: BinaryOp, right: Node) -> Node {
+    Node::Binary { left: Box::new(left), operator, right: Box::new(right) }
+}
+
+/// Joins string parts with `+`, merging adjacent literals.
+fn concat(parts: Vec<Node>) -> Node {
+    let mut merged: Vec<Node> = Vec::new();
+    for part in parts {
+        match (merged.last_mut(), part) {
+            (Some(Node::StringLiteral(previous)), Node::StringLiteral(next)) => previous.push_str(&next),
+            (_, part) => merged.push(part),
+        }
+    }
+    merged.into_iter()
+        .reduce(|left, right| binary(left, BinaryOp::Add, right))
+        .unwrap_or_else(|| string(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, ty: Type) -> Node {
+        Node::Let { name: name.to_string(), type_annotation: Some(ty), initializer: None, is_mutable: false }
+    }
+
+    fn derived(derives: &[&str], members: Vec<Node>) -> Node {
+        Node::Program(vec![Node::Derive {
+            derives: derives.iter().map(|derive| derive.to_string()).collect(),
+            declaration: Box::new(Node::Class {
+                name: "User".to_string(),
+                extends: None,
+                implements: vec![],
+                is_abstract: false,
+                members,
+                docs: None,
+            }),
+        }])
+    }
+
+    fn methods(program: &Node) -> Vec<&Node> {
+        match program {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Class { members, .. } => members.iter().filter(|member| matches!(member, Node::Function { .. })).collect(),
+                other => panic!("expected class, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+    }
+
+    fn returned(method: &Node) -> &Node {
+        match method {
+            Node::Function { body, .. } => match body.as_ref() {
+                Node::Block(statements) => match &statements[0] {
+                    Node::Return(Some(value)) => value,
+                    other => panic!("expected return, found {:?}", other),
+                },
+                other => panic!("expected block, found {:?}", other),
+            },
+            other => panic!("expected function, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_derives_methods() {
+        let program = derived(&[EQUALS, TO_STRING, SERIALIZE], vec![
+            field("id", Type::Int),
+            field("name", Type::String),
+            field("address", Type::Custom("Address".into())),
+        ]);
+        let program = expand(program).unwrap();
+        let methods = methods(&program);
+        let names: Vec<&str> = methods.iter()
+            .map(|method| match method { Node::Function { name, .. } => name.as_str(), _ => unreachable!() })
+            .collect();
+        assert_eq!(names, vec!["equals", "toString", "serialize"]);
+
+        let this = |field: &str| field_of(Node::This, field);
+        let other = |field: &str| field_of(identifier("other"), field);
+        assert_eq!(returned(methods[0]), &binary(
+            binary(binary(this("id"), BinaryOp::Eq, other("id")), BinaryOp::And, binary(this("name"), BinaryOp::Eq, other("name"))),
+            BinaryOp::And,
+            method_call(this("address"), "equals", vec![other("address")]),
+        ));
+        assert_eq!(returned(methods[1]), &concat(vec![
+            string("User(id="), this("id"), string(", name="), this("name"),
+            string(", address="), method_call(this("address"), "toString", vec![]), string(")"),
+        ]));
+        assert_eq!(returned(methods[2]), &concat(vec![
+            string("{\"id\":"), this("id"), string(",\"name\":\""), this("name"),
+            string("\",\"address\":"), method_call(this("address"), "serialize", vec![]), string("}"),
+        ]));
+    }
+
+    #[test]
+    fn test_empty_class_and_errors() {
+        let program = expand(derived(&[EQUALS, TO_STRING], vec![])).unwrap();
+        assert_eq!(returned(methods(&program)[0]), &Node::BooleanLiteral(true));
+        assert_eq!(returned(methods(&program)[1]), &string("User()"));
+
+        let existing = method("toString", vec![], Type::String, string(""));
+        let errors = expand(derived(&["Hash", TO_STRING], vec![existing])).unwrap_err();
+        assert_eq!(errors, vec![
+            "Unknown derive 'Hash' on class 'User'".to_string(),
+            "Class 'User' derives ToString but already defines 'toString'".to_string(),
+        ]);
+    }
+}
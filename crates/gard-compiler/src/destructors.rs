@@ -0,0 +1,298 @@
+//! Deterministic destruction. A class declares a destructor as a `drop()`
+//! method; a local declared with that class as its type owns its value,
+//! and `drop()` is called on it when the local goes out of scope, on every
+//! path: falling off the end of the block, `return`, `break` and `continue`,
+//! and exceptions. Locals are dropped in the reverse order of declaration.
+//!
+//! Ownership moves out of a local that is returned, that initializes
+//! another local, or that is passed to a spawned task or sent on a channel.
+//! Its owner then doesn't drop it; a local that is only moved on some paths
+//! keeps a flag, counting its moves, so it is dropped on the others. Using a
+//! local after a move in the same block is an error. Passing a local to a
+//! function only lends it, so parameters are never dropped.
+
+use gard_ast::{BinaryOp, Node, Type, UnaryOp};
+use std::collections::HashSet;
+
+/// Name of the method that makes a class's values owned.
+pub const DESTRUCTOR: &str = "drop";
+
+/// Inserts the destructor calls for every owned local in the program.
+pub fn insert(mut program: Node) -> Result<Node, Vec<String>> {
+    let mut droppable = HashSet::new();
+    collect_droppable(&program, &mut droppable);
+    let mut errors = Vec::new();
+    if !droppable.is_empty() {
+        visit(&mut program, &droppable, false, &mut errors);
+    }
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+fn collect_droppable(node: &Node, droppable: &mut HashSet<String>) {
+    if let Node::Class { name, members, .. } = node {
+        let destructor = members.iter().any(|member| matches!(
+            member.unlocated(),
+            Node::Function { name, params, .. } if name == DESTRUCTOR && params.is_empty()
+        ));
+        if destructor {
+            droppable.insert(name.clone());
+        }
+    }
+    for child in node.children() {
+        collect_droppable(child, droppable);
+    }
+}
+
+/// Processes the blocks of every function; top-level locals live until the
+/// program exits.
+fn visit(node: &mut Node, droppable: &HashSet<String>, in_function: bool, errors: &mut Vec<String>) {
+    match node {
+        Node::Block(statements) if in_function => process(statements, droppable, errors),
+        Node::Function { body, .. } | Node::Constructor { body, .. } => visit(body, droppable, true, errors),
+        node => {
+            for child in node.children_mut() {
+                visit(child, droppable, in_function, errors);
+            }
+        },
+    }
+}
+
+/// Wraps what follows the first owned local of a block in a `try` whose
+/// `finally` drops it; the later ones are wrapped in turn inside it.
+fn process(statements: &mut Vec<Node>, droppable: &HashSet<String>, errors: &mut Vec<String>) {
+    for i in 0..statements.len() {
+        let Some(name) = owned(&statements[i], droppable) else {
+            for child in statements[i].children_mut() {
+                visit(child, droppable, true, errors);
+            }
+            continue;
+        };
+
+        let mut rest = statements.split_off(i + 1);
+        let flag = format!("__moved_{}", name);
+        let moves = mark_moves(&mut rest, &name, &flag, errors);
+        process(&mut rest, droppable, errors);
+
+        let drop = Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(identifier(&name)), property: DESTRUCTOR.to_string() }),
+            arguments: vec![],
+        };
+        let cleanup = if moves == 0 {
+            drop
+        } else {
+            statements.push(Node::Let {
+                name: flag.clone(),
+                type_annotation: Some(Type::Int),
+                initializer: Some(Box::new(Node::IntLiteral(0))),
+                is_mutable: true,
+            });
+            Node::If {
+                condition: Box::new(Node::Binary {
+                    left: Box::new(identifier(&flag)),
+                    operator: BinaryOp::Eq,
+                    right: Box::new(Node::IntLiteral(0)),
+                }),
+                then_branch: Box::new(Node::Block(vec![drop])),
+                else_branch: None,
+            }
+        };
+        statements.push(Node::Try {
+            body: Box::new(Node::Block(rest)),
+            catch_clauses: vec![],
+            finally: Some(Box::new(Node::Block(vec![cleanup]))),
+        });
+        return;
+    }
+}
+
+/// The local a statement declares, if its type has a destructor.
+fn owned(statement: &Node, droppable: &HashSet<String>) -> Option<String> {
+    match statement.unlocated() {
+        Node::Let { name, type_annotation: Some(Type::Custom(class)), .. } if droppable.contains(class.as_str()) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+/// Counts a move of `name` in `flag` before each statement that moves it,
+/// in `statements` and the blocks nested in them, and returns how many
+/// there are.
+fn mark_moves(statements: &mut Vec<Node>, name: &str, flag: &str, errors: &mut Vec<String>) -> usize {
+    let mut moves = 0;
+    let mut i = 0;
+    while i < statements.len() {
+        if moves_out(&statements[i], name) {
+            statements.insert(i, Node::Unary { operator: UnaryOp::Increment, operand: Box::new(identifier(flag)) });
+            i += 1;
+            moves += 1;
+            let later = &statements[i + 1..];
+            if later.iter().take_while(|statement| !declares(statement, name)).any(|statement| uses(statement, name)) {
+                errors.push(format!("'{}' is used after it is moved", name));
+            }
+        } else if declares(&statements[i], name) {
+            // Shadowed from here on
+            break;
+        } else {
+            for child in statements[i].children_mut() {
+                moves += mark_moves_in(child, name, flag, errors);
+            }
+        }
+        i += 1;
+    }
+    moves
+}
+
+fn mark_moves_in(node: &mut Node, name: &str, flag: &str, errors: &mut Vec<String>) -> usize {
+    match node {
+        Node::Block(statements) => mark_moves(statements, name, flag, errors),
+        // A nested function's names are its own
+        Node::Function { .. } => 0,
+        node => node.children_mut().into_iter().map(|child| mark_moves_in(child, name, flag, errors)).sum(),
+    }
+}
+
+/// Whether a statement moves `name` out: returns it, initializes another
+/// local with it, or passes it to a spawned task or a channel.
+fn moves_out(statement: &Node, name: &str) -> bool {
+    let is_name = |node: &Node| matches!(node.unlocated(), Node::Identifier(used) if used == name);
+    match statement.unlocated() {
+        Node::Return(Some(value)) => is_name(value),
+        Node::Let { initializer: Some(value), .. } => is_name(value),
+        Node::Spawn(task) => matches!(task.unlocated(), Node::Call { arguments, .. } if arguments.iter().any(is_name)),
+        Node::Call { callee, arguments } => {
+            matches!(callee.unlocated(), Node::Identifier(send) if send == "send") && arguments.get(1).is_some_and(is_name)
+        },
+        // An expression statement
+        Node::Block(statements) => matches!(statements.as_slice(), [expression] if moves_out(expression, name)),
+        _ => false,
+    }
+}
+
+fn declares(statement: &Node, name: &str) -> bool {
+    matches!(statement.unlocated(), Node::Let { name: declared, .. } if declared == name)
+}
+
+fn uses(node: &Node, name: &str) -> bool {
+    match node {
+        Node::Identifier(used) => used == name,
+        Node::Function { .. } => false,
+        node => node.children().into_iter().any(|child| uses(child, name)),
+    }
+}
+
+fn identifier(name: &str) -> Node {
+    Node::Identifier(name.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::Parameter;
+
+    fn file_class() -> Node {
+        Node::Class {
+            name: "File".to_string(),
+            extends: None,
+            implements: vec![],
+            is_abstract: false,
+            members: vec![Node::Function {
+                name: DESTRUCTOR.to_string(),
+                params: vec![],
+                return_type: Type::Void,
+                body: Box::new(Node::Block(vec![])),
+                modifiers: vec![],
+                docs: None,
+            }],
+            docs: None,
+        }
+    }
+
+    fn open(name: &str) -> Node {
+        Node::Let {
+            name: name.to_string(),
+            type_annotation: Some(Type::Custom("File".into())),
+            initializer: Some(Box::new(Node::Call { callee: Box::new(identifier("open")), arguments: vec![] })),
+            is_mutable: false,
+        }
+    }
+
+    fn main(body: Vec<Node>) -> Node {
+        Node::Program(vec![file_class(), Node::Function {
+            name: "main".to_string(),
+            params: vec![Parameter { name: "log".into(), type_annotation: Type::Custom("File".into()) }],
+            return_type: Type::Void,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        }])
+    }
+
+    fn body(program: &Node) -> String {
+        let Node::Program(nodes) = program else { unreachable!() };
+        let Node::Function { body, .. } = &nodes[1] else { unreachable!() };
+        gard_ast::to_source(body)
+    }
+
+    #[test]
+    fn test_drops_in_reverse_order() {
+        let use_log = Node::Call { callee: Box::new(identifier("write")), arguments: vec![identifier("log")] };
+        let program = insert(main(vec![open("a"), open("b"), use_log])).unwrap();
+        assert_eq!(body(&program), "\
+{
+    let a: File = open()
+    try {
+        let b: File = open()
+        try {
+            write(log);
+        } finally {
+            b.drop();
+        }
+    } finally {
+        a.drop();
+    }
+}
+");
+        // Without destructors nothing changes
+        let Node::Program(mut nodes) = main(vec![open("a")]) else { unreachable!() };
+        nodes.remove(0);
+        assert_eq!(insert(Node::Program(nodes.clone())).unwrap(), Node::Program(nodes));
+    }
+
+    #[test]
+    fn test_moves() {
+        let give = Node::If {
+            condition: Box::new(identifier("done")),
+            then_branch: Box::new(Node::Block(vec![Node::Return(Some(Box::new(identifier("a"))))])),
+            else_branch: None,
+        };
+        let program = insert(main(vec![open("a"), give])).unwrap();
+        assert_eq!(body(&program), "\
+{
+    let a: File = open()
+    let __moved_a: int = 0
+    try {
+        if (done) {
+            ++__moved_a;
+            return a;
+        }
+    } finally {
+        if (__moved_a == 0) {
+            a.drop();
+        }
+    }
+}
+");
+
+        let reuse = Node::Call { callee: Box::new(identifier("write")), arguments: vec![identifier("a")] };
+        let moved = Node::Let {
+            name: "b".to_string(),
+            type_annotation: None,
+            initializer: Some(Box::new(identifier("a"))),
+            is_mutable: false,
+        };
+        assert_eq!(insert(main(vec![open("a"), moved, reuse])).unwrap_err(), vec!["'a' is used after it is moved".to_string()]);
+    }
+}
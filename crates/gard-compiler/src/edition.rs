@@ -0,0 +1,264 @@
+//! Editions and feature gates. A project's `gard.toml` names the edition
+//! its sources are written in, and experimental constructs only compile
+//! where they're enabled: for the whole project in `gard.toml`, for a file
+//! with `@feature(..);` on its own, or for one declaration with
+//! `@feature(..)` on it. A feature stabilized in an edition needs
+//! neither from that edition on.
+//!
+//! ```toml
+//! edition = "2024"
+//! experimental = ["unions"]
+//! ```
+//!
+//! The driver checks the gates before any other pass, while macros are
+//! still unexpanded and the attributes are still there.
+
+use crate::library::Library;
+use crate::machine::Codegen;
+use crate::prebuild::BuildStep;
+use gard_ast::Node;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The project file, looked up from the working directory upwards
+pub const MANIFEST: &str = "gard.toml";
+
+/// Oldest first; the last is the default.
+pub const EDITIONS: [&str; 1] = ["2024"];
+
+/// `@feature(macros, unions)`, which enables features on the declaration
+/// it's on.
+pub const ATTRIBUTE: &str = "feature";
+
+pub struct Feature {
+    pub name: &'static str,
+    /// The first edition it's stable in, if it is in any yet
+    pub stable_since: Option<&'static str>,
+}
+
+pub const FEATURES: [Feature; 2] = [
+    Feature { name: "macros", stable_since: None },
+    Feature { name: "unions", stable_since: None },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default = "latest_edition")]
+    pub edition: String,
+    /// Features enabled in every file
+    #[serde(default)]
+    pub experimental: Vec<String>,
+    /// Commands to run before compiling, in `[[build]]` tables
+    #[serde(default)]
+    pub build: Vec<BuildStep>,
+    /// The native libraries `gard --emit library` builds
+    pub library: Option<Library>,
+    /// How native code is generated
+    #[serde(default)]
+    pub codegen: Codegen,
+}
+
+fn latest_edition() -> String {
+    EDITIONS[EDITIONS.len() - 1].to_string()
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self { edition: latest_edition(), experimental: Vec::new(), build: Vec::new(), library: None, codegen: Codegen::default() }
+    }
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let manifest: Manifest = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        if !EDITIONS.contains(&manifest.edition.as_str()) {
+            return Err(format!("Unknown edition '{}', expected one of {}", manifest.edition, EDITIONS.join(", ")));
+        }
+        for feature in &manifest.experimental {
+            find_feature(feature)?;
+        }
+        if let Some(library) = &manifest.library {
+            library.validate()?;
+        }
+        Ok(manifest)
+    }
+
+    /// The manifest in `directory` or the nearest directory above it, or
+    /// the default if there is none.
+    pub fn find(directory: &Path) -> Result<Self, String> {
+        let Some(path) = Self::locate(directory) else {
+            return Ok(Self::default());
+        };
+        let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The path of the manifest `find` reads.
+    pub fn locate(directory: &Path) -> Option<PathBuf> {
+        directory.ancestors().map(|directory| directory.join(MANIFEST)).find(|path| path.is_file())
+    }
+
+    /// The features enabled everywhere: the experimental ones it names
+    /// and the ones stable in its edition.
+    pub fn enabled(&self) -> BTreeSet<String> {
+        let edition = EDITIONS.iter().position(|edition| *edition == self.edition);
+        let stable = FEATURES.iter().filter(|feature| {
+            let since = feature.stable_since.and_then(|since| EDITIONS.iter().position(|edition| *edition == since));
+            since.is_some() && since <= edition
+        });
+        stable.map(|feature| feature.name.to_string()).chain(self.experimental.iter().cloned()).collect()
+    }
+}
+
+fn find_feature(name: &str) -> Result<&'static Feature, String> {
+    FEATURES.iter().find(|feature| feature.name == name).ok_or_else(|| {
+        let names: Vec<&str> = FEATURES.iter().map(|feature| feature.name).collect();
+        format!("Unknown feature '{}', expected one of {}", name, names.join(", "))
+    })
+}
+
+/// Checks that every experimental construct is enabled where it's used,
+/// and removes the `@feature` attributes.
+pub fn check(mut program: Node, manifest: &Manifest) -> Result<Node, Vec<String>> {
+    let mut errors = Vec::new();
+    let mut enabled = manifest.enabled();
+    if let Node::Program(nodes) = &mut program {
+        nodes.retain(|node| match node {
+            Node::Attribute { name, arguments, declaration } if name == ATTRIBUTE && declaration.as_ref() == &Node::Program(Vec::new()) => {
+                enable(arguments, &mut enabled, &mut errors);
+                false
+            },
+            _ => true,
+        });
+    }
+    check_node(&mut program, &enabled, &mut errors);
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(errors)
+    }
+}
+
+fn check_node(node: &mut Node, enabled: &BTreeSet<String>, errors: &mut Vec<String>) {
+    if let Node::Attribute { name, arguments, declaration } = node {
+        if name == ATTRIBUTE {
+            let mut enabled = enabled.clone();
+            enable(arguments, &mut enabled, errors);
+            let mut declaration = std::mem::replace(declaration.as_mut(), Node::Program(Vec::new()));
+            check_node(&mut declaration, &enabled, errors);
+            *node = declaration;
+            return;
+        }
+    }
+
+    let gated = match node {
+        Node::MacroDefinition { name, .. } => Some((format!("Macro '{}'", name), "macros")),
+        Node::MacroCall { name, .. } => Some((format!("'{}!'", name), "macros")),
+        Node::Union { name, .. } => Some((format!("Union '{}'", name), "unions")),
+        _ => None,
+    };
+    if let Some((construct, feature)) = gated.filter(|(_, feature)| !enabled.contains(*feature)) {
+        errors.push(format!(
+            "{} uses the experimental feature '{}'; enable it with @{}({}) or in the `experimental` list of {}",
+            construct, feature, ATTRIBUTE, feature, MANIFEST,
+        ));
+    }
+    for child in node.children_mut() {
+        check_node(child, enabled, errors);
+    }
+}
+
+fn enable(arguments: &[Node], enabled: &mut BTreeSet<String>, errors: &mut Vec<String>) {
+    for argument in arguments {
+        match argument.unlocated() {
+            Node::Identifier(feature) => match find_feature(feature) {
+                Ok(feature) => {
+                    enabled.insert(feature.name.to_string());
+                },
+                Err(e) => errors.push(e),
+            },
+            _ => errors.push(format!("@{} takes feature names, like @{}(macros)", ATTRIBUTE, ATTRIBUTE)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{LibraryKind, Runtime};
+    use crate::machine::RelocationModel;
+    use gard_ast::{Span, Type, UnionVariant};
+
+    fn union(name: &str) -> Node {
+        Node::Union { name: name.to_string(), variants: vec![UnionVariant { name: "Logout".to_string(), payload: vec![] }] }
+    }
+
+    fn feature(features: &[&str], declaration: Node) -> Node {
+        Node::Attribute {
+            name: ATTRIBUTE.to_string(),
+            arguments: features.iter().map(|feature| Node::Identifier((*feature).into())).collect(),
+            declaration: Box::new(declaration),
+        }
+    }
+
+    fn function(name: &str, body: Vec<Node>) -> Node {
+        Node::Function { name: name.to_string(), params: vec![], return_type: Type::Void, body: Box::new(Node::Block(body)), modifiers: vec![], docs: None }
+    }
+
+    #[test]
+    fn test_gates() {
+        let twice = || Node::MacroCall { name: "twice".to_string(), arguments: vec![], span: Span { start: 0, end: 6 } };
+        let program = Node::Program(vec![
+            feature(&["unions"], union("Msg")),
+            feature(&["macros", "unions"], function("main", vec![twice()])),
+            function("other", vec![twice()]),
+            union("Event"),
+        ]);
+        assert_eq!(check(program.clone(), &Manifest::default()).unwrap_err(), vec![
+            "'twice!' uses the experimental feature 'macros'; enable it with @feature(macros) or in the `experimental` list of gard.toml".to_string(),
+            "Union 'Event' uses the experimental feature 'unions'; enable it with @feature(unions) or in the `experimental` list of gard.toml".to_string(),
+        ]);
+
+        // Enabled for the project, the attributes are only removed
+        let manifest = Manifest::parse("experimental = [\"macros\", \"unions\"]").unwrap();
+        assert_eq!(check(program, &manifest).unwrap(), Node::Program(vec![
+            union("Msg"),
+            function("main", vec![twice()]),
+            function("other", vec![twice()]),
+            union("Event"),
+        ]));
+
+        // For the whole file
+        let file = Node::Program(vec![feature(&["unions"], Node::Program(vec![])), union("Event")]);
+        assert_eq!(check(file, &Manifest::default()).unwrap(), Node::Program(vec![union("Event")]));
+
+        let unknown = Node::Program(vec![feature(&["traits"], function("main", vec![]))]);
+        assert_eq!(check(unknown, &Manifest::default()).unwrap_err(), vec!["Unknown feature 'traits', expected one of macros, unions".to_string()]);
+    }
+
+    #[test]
+    fn test_manifest() {
+        assert_eq!(Manifest::parse("").unwrap(), Manifest::default());
+        assert_eq!(Manifest::parse("edition = \"2024\"\nexperimental = [\"unions\"]").unwrap().enabled(), BTreeSet::from(["unions".to_string()]));
+        assert_eq!(Manifest::parse("edition = \"2019\""), Err("Unknown edition '2019', expected one of 2024".to_string()));
+        assert_eq!(Manifest::parse("experimental = [\"traits\"]"), Err("Unknown feature 'traits', expected one of macros, unions".to_string()));
+        assert!(Manifest::parse("editon = \"2024\"").unwrap_err().contains("unknown field `editon`"));
+
+        let manifest = Manifest::parse("[[build]]\ncommand = [\"abigen\", \"token.abi\"]\ninputs = [\"token.abi\"]\noutputs = [\"gen/token.gard\"]").unwrap();
+        assert_eq!(manifest.build, vec![BuildStep {
+            command: vec!["abigen".to_string(), "token.abi".to_string()],
+            inputs: vec!["token.abi".to_string()],
+            outputs: vec!["gen/token.gard".to_string()],
+        }]);
+
+        let manifest = Manifest::parse("[library]\ntypes = [\"staticlib\"]\nruntime = \"separate\"").unwrap();
+        assert_eq!(manifest.library, Some(Library { name: None, types: vec![LibraryKind::Static], runtime: Runtime::Separate }));
+        assert_eq!(Manifest::parse("[library]\ntypes = []"), Err("[library] lists no types; expected staticlib, dylib or both".to_string()));
+
+        let manifest = Manifest::parse("[codegen]\nrelocation-model = \"static\"").unwrap();
+        assert_eq!(manifest.codegen, Codegen { relocation_model: Some(RelocationModel::Static), code_model: None, lto: None, codegen_units: None });
+    }
+}
@@ -0,0 +1,306 @@
+//! Runs compiled programs in process, for the end-to-end tests. A program
+//! is JIT-compiled with the gard-vm functions it calls linked in, and its
+//! `main` run with stdout captured. `main` returns the exit code, or
+//! nothing for 0; an error it raises and doesn't catch exits with 1, its
+//! message on stderr, as in a native build.
+
+use crate::Compiler;
+use gard_ast::Node;
+use gard_vm::error::catch;
+use gard_vm::io::capture_stdout;
+use inkwell::context::Context;
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::targets::{InitializationConfig, Target};
+use inkwell::OptimizationLevel;
+use std::mem;
+
+/// What running a program did.
+#[derive(Debug, PartialEq)]
+pub struct Run {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i64,
+}
+
+/// The gard-vm functions linked into JIT-compiled code, by symbol.
+fn runtime() -> Vec<(&'static str, usize)> {
+    use gard_vm::{error, io, memory, rtti, strings};
+    vec![
+        ("gard_alloc", memory::gard_alloc as usize),
+        ("gard_free", memory::gard_free as usize),
+        ("gard_raise", error::gard_raise as usize),
+        ("gard_trap", error::gard_trap as usize),
+        ("gard_try", error::gard_try as usize),
+        ("gard_catch", error::gard_catch as usize),
+        ("gard_reraise", error::gard_reraise as usize),
+        ("gard_io_stdout", io::gard_io_stdout as usize),
+        ("gard_io_write", io::gard_io_write as usize),
+        ("gard_io_write_line", io::gard_io_write_line as usize),
+        ("gard_io_flush", io::gard_io_flush as usize),
+        ("gard_rtti_is", rtti::gard_rtti_is as usize),
+        ("gard_rtti_itable", rtti::gard_rtti_itable as usize),
+        ("gard_string_char_at", strings::gard_string_char_at as usize),
+        ("gard_string_next", strings::gard_string_next as usize),
+    ]
+}
+
+/// JIT-compiles the compiler's module, linking each function it declares
+/// but doesn't define to gard-vm's.
+pub fn jit<'ctx>(compiler: &Compiler<'ctx>) -> Result<ExecutionEngine<'ctx>, String> {
+    Target::initialize_native(&InitializationConfig::default())?;
+    let engine = compiler.module.create_jit_execution_engine(OptimizationLevel::None)
+        .map_err(|error| error.to_string())?;
+    let runtime = runtime();
+    for function in compiler.module.get_functions().filter(|function| function.count_basic_blocks() == 0) {
+        let name = function.get_name().to_str().unwrap_or_default();
+        if name.starts_with("llvm.") {
+            continue;
+        }
+        let address = runtime.iter()
+            .find(|(symbol, _)| *symbol == name)
+            .map(|(_, address)| *address)
+            .ok_or_else(|| format!("Runtime function '{}' isn't available to the JIT", name))?;
+        engine.add_global_mapping(&function, address);
+    }
+    Ok(engine)
+}
+
+/// Compiles a program and runs its `main`.
+pub fn run(program: Node) -> Result<Run, String> {
+    let context = Context::create();
+    let mut compiler = Compiler::new(&context, "main");
+    compiler.compile(program)?;
+    compiler.module.verify().map_err(|error| error.to_string())?;
+    let main = compiler.module.get_function("main").ok_or_else(|| "The program has no main function".to_string())?;
+    let returns_code = main.get_type().get_return_type().is_some();
+
+    let engine = jit(&compiler)?;
+    let address = engine.get_function_address("main").map_err(|error| format!("{:?}", error))?;
+    // Called as C-unwind so a raised error unwinds out to `catch`
+    let (result, stdout) = capture_stdout(|| catch(|| unsafe {
+        if returns_code {
+            mem::transmute::<usize, unsafe extern "C-unwind" fn() -> i64>(address)()
+        } else {
+            mem::transmute::<usize, unsafe extern "C-unwind" fn()>(address)();
+            0
+        }
+    }));
+    Ok(match result {
+        Ok(exit_code) => Run { stdout, stderr: String::new(), exit_code },
+        Err(error) => Run { stdout, stderr: error.to_string(), exit_code: 1 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::{AssertionKind, BinaryOp, Parameter, Type, UnaryOp};
+
+    fn main(return_type: Type, statements: Vec<Node>) -> Node {
+        Node::Function {
+            name: "main".to_string(),
+            params: vec![],
+            return_type,
+            body: Box::new(Node::Block(statements)),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn int(value: i64) -> Box<Node> {
+        Box::new(Node::IntLiteral(value))
+    }
+
+    fn var(name: &str) -> Box<Node> {
+        Box::new(Node::Identifier(name.into()))
+    }
+
+    fn binary(left: Box<Node>, operator: BinaryOp, right: Box<Node>) -> Box<Node> {
+        Box::new(Node::Binary { left, operator, right })
+    }
+
+    fn let_int(name: &str, value: i64) -> Node {
+        Node::Let { name: name.to_string(), type_annotation: Some(Type::Int), initializer: Some(int(value)), is_mutable: true }
+    }
+
+    fn increment(name: &str) -> Node {
+        Node::Unary { operator: UnaryOp::PostIncrement, operand: var(name) }
+    }
+
+    /// `io.writeLine(io.stdout(), text)`
+    fn print(text: &str) -> Node {
+        write_line(Node::StringLiteral(text.to_string()))
+    }
+
+    fn write_line(value: Node) -> Node {
+        let io = |function: &str| Box::new(Node::Member { object: var("io"), property: function.to_string() });
+        Node::Call {
+            callee: io("writeLine"),
+            arguments: vec![Node::Call { callee: io("stdout"), arguments: vec![] }, value],
+        }
+    }
+
+    fn if_else(condition: Box<Node>, then: Node, otherwise: Node) -> Node {
+        Node::If { condition, then_branch: Box::new(then), else_branch: Some(Box::new(otherwise)) }
+    }
+
+    fn exit_code(run: Result<Run, String>) -> i64 {
+        let run = run.unwrap();
+        assert_eq!(run.stderr, "");
+        run.exit_code
+    }
+
+    #[test]
+    fn test_run_arithmetic() {
+        // (7 * 6 - 2) / 4 % 7 + (5 << 2 ^ 3)
+        let left = binary(binary(binary(binary(int(7), BinaryOp::Mul, int(6)), BinaryOp::Sub, int(2)), BinaryOp::Div, int(4)), BinaryOp::Mod, int(7));
+        let right = binary(binary(int(5), BinaryOp::Shl, int(2)), BinaryOp::BitXor, int(3));
+        assert_eq!(exit_code(run(Node::Program(vec![main(Type::Int, vec![*binary(left, BinaryOp::Add, right)])]))), 26);
+        assert_eq!(exit_code(run(Node::Program(vec![main(Type::Int, vec![*binary(int(3), BinaryOp::Sub, int(10))])]))), -7);
+
+        let divided = run(Node::Program(vec![main(Type::Int, vec![let_int("zero", 0), *binary(int(1), BinaryOp::Div, var("zero"))])])).unwrap();
+        assert_eq!(divided, Run { stdout: String::new(), stderr: "Arithmetic failed: Division by zero".to_string(), exit_code: 1 });
+    }
+
+    #[test]
+    fn test_run_control_flow() {
+        // Prints and counts the multiples of 3 below 10
+        let program = Node::Program(vec![main(Type::Int, vec![
+            let_int("i", 0),
+            let_int("multiples", 0),
+            Node::While {
+                condition: binary(var("i"), BinaryOp::Lt, int(10)),
+                body: Box::new(Node::Block(vec![
+                    if_else(
+                        binary(binary(var("i"), BinaryOp::Mod, int(3)), BinaryOp::Eq, int(0)),
+                        Node::Block(vec![print("multiple"), increment("multiples")]),
+                        print("other"),
+                    ),
+                    increment("i"),
+                ])),
+            },
+            *var("multiples"),
+        ])]);
+        let counted = run(program).unwrap();
+        assert_eq!(counted.stdout, "multiple\nother\nother\nmultiple\nother\nother\nmultiple\nother\nother\nmultiple\n");
+        assert_eq!(counted.exit_code, 4);
+
+        // The right side of && isn't run when the left decides it, so this
+        // doesn't divide by zero
+        let guarded = binary(binary(var("zero"), BinaryOp::NotEq, int(0)), BinaryOp::And, binary(binary(int(10), BinaryOp::Div, var("zero")), BinaryOp::Gt, int(2)));
+        let program = Node::Program(vec![main(Type::Int, vec![let_int("zero", 0), if_else(guarded, *int(1), *int(2))])]);
+        assert_eq!(exit_code(run(program)), 2);
+    }
+
+    #[test]
+    fn test_run_raised_errors() {
+        let program = Node::Program(vec![main(Type::Void, vec![
+            print("checking"),
+            Node::Assertion {
+                kind: AssertionKind::Require,
+                condition: Box::new(Node::BooleanLiteral(false)),
+                message: Some(Box::new(Node::StringLiteral("Insufficient balance".to_string()))),
+            },
+            print("unreachable"),
+        ])]);
+        assert_eq!(run(program), Ok(Run {
+            stdout: "checking\n".to_string(),
+            stderr: "Requirement failed: Insufficient balance".to_string(),
+            exit_code: 1,
+        }));
+    }
+
+    #[test]
+    fn test_run_try_catch() {
+        let require = |message: &str| Node::Assertion {
+            kind: AssertionKind::Require,
+            condition: Box::new(Node::BooleanLiteral(false)),
+            message: Some(Box::new(Node::StringLiteral(message.to_string()))),
+        };
+        let guarded = |body: Vec<Node>, catch_clauses: Vec<Node>, finally: &str| Node::Try {
+            body: Box::new(Node::Block(body)),
+            catch_clauses,
+            finally: Some(Box::new(Node::Block(vec![print(finally)]))),
+        };
+        let catch_error = Node::CatchClause {
+            param_name: "e".to_string(),
+            param_type: Type::String,
+            body: Box::new(Node::Block(vec![write_line(*var("e"))])),
+        };
+
+        // The first try catches the error, the second returns through its finally
+        let program = Node::Program(vec![main(Type::Int, vec![
+            guarded(vec![print("trying"), require("Insufficient balance"), print("unreachable")], vec![catch_error], "finally"),
+            guarded(vec![Node::Return(Some(int(3)))], vec![], "cleanup"),
+            *int(0),
+        ])]);
+        assert_eq!(run(program), Ok(Run {
+            stdout: "trying\nRequirement failed: Insufficient balance\nfinally\ncleanup\n".to_string(),
+            stderr: String::new(),
+            exit_code: 3,
+        }));
+
+        // Without a catch clause, the error is raised again after the finally
+        let program = Node::Program(vec![main(Type::Void, vec![guarded(vec![require("Not authorized")], vec![], "cleanup")])]);
+        assert_eq!(run(program), Ok(Run {
+            stdout: "cleanup\n".to_string(),
+            stderr: "Requirement failed: Not authorized".to_string(),
+            exit_code: 1,
+        }));
+    }
+
+    #[test]
+    fn test_run_class_constructor() {
+        let program = Node::Program(vec![Node::Class {
+            name: "Counter".to_string(),
+            extends: None,
+            implements: vec![],
+            is_abstract: false,
+            docs: None,
+            members: vec![
+                Node::Let { name: "count".to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable: true },
+                Node::Constructor {
+                    params: vec![Parameter { name: "step".into(), type_annotation: Type::Int }],
+                    body: Box::new(Node::Block(vec![
+                        Node::Unary { operator: UnaryOp::Increment, operand: Box::new(Node::Member { object: Box::new(Node::This), property: "count".to_string() }) },
+                        if_else(binary(var("step"), BinaryOp::Gt, int(1)), Node::Unary {
+                            operator: UnaryOp::Increment,
+                            operand: Box::new(Node::Member { object: Box::new(Node::This), property: "count".to_string() }),
+                        }, *int(0)),
+                    ])),
+                },
+            ],
+        }]);
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "counter");
+        compiler.compile(program).unwrap();
+        let engine = jit(&compiler).unwrap();
+
+        // The instance is allocated by the caller, as by `new`
+        let constructor = engine.get_function_address("Counter.constructor").unwrap();
+        let constructor = unsafe { mem::transmute::<usize, unsafe extern "C-unwind" fn(*mut i64, i64)>(constructor) };
+        let mut counter = 40_i64;
+        unsafe {
+            constructor(&mut counter, 1);
+            constructor(&mut counter, 2);
+        }
+        assert_eq!(counter, 43);
+    }
+
+    #[test]
+    fn test_run_unsupported_concurrency() {
+        // Actors and STM transactions aren't compiled natively yet: gard-vm
+        // has no mailboxes or transactions for them to run on
+        let actor = Node::Actor {
+            name: "Greeter".to_string(),
+            type_param: None,
+            mailbox: Box::new(Node::Block(vec![])),
+            behavior: Box::new(Node::Block(vec![])),
+            members: vec![],
+        };
+        assert!(run(Node::Program(vec![actor])).unwrap_err().contains("MessageQueue"));
+
+        let atomic = Node::Atomic { body: Box::new(Node::Block(vec![])) };
+        assert!(run(Node::Program(vec![main(Type::Int, vec![atomic])])).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+//! FileCheck-style assertions on emitted IR, for the codegen tests. The
+//! checks are directives, one per line, matched against the IR in order:
+//!
+//! - `CHECK: text` matches on a line at or after the previous match
+//! - `CHECK-NEXT: text` matches on the line right after the previous match
+//! - `CHECK-SAME: text` matches later on the previous match's line
+//! - `CHECK-NOT: text` must not occur between the matches around it
+//! - `CHECK-LABEL: text` matches like `CHECK`, and first splits the IR at
+//!   each label, so the checks after one only look up to the next
+//!
+//! In `text`, `{{.*}}` matches any run of characters on a line; the rest
+//! is matched literally, ignoring leading and trailing whitespace. Lines
+//! that aren't directives are ignored, so the checks can be commented.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Check,
+    Next,
+    Same,
+    Not,
+    Label,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Check => "CHECK",
+            Kind::Next => "CHECK-NEXT",
+            Kind::Same => "CHECK-SAME",
+            Kind::Not => "CHECK-NOT",
+            Kind::Label => "CHECK-LABEL",
+        }
+    }
+}
+
+struct Directive<'a> {
+    kind: Kind,
+    pattern: &'a str,
+}
+
+/// A place in the IR: a line, and a byte offset in it.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+/// Matches `checks` against `ir`, describing the first directive that
+/// fails.
+pub fn check(ir: &str, checks: &str) -> Result<(), String> {
+    let directives = parse(checks)?;
+    let lines: Vec<&str> = ir.lines().collect();
+    let end = Position { line: lines.len(), column: 0 };
+
+    // Where each label matches, each after the one before it
+    let mut labels = Vec::new();
+    let mut from = Position { line: 0, column: 0 };
+    for directive in directives.iter().filter(|directive| directive.kind == Kind::Label) {
+        let (start, after) = find(&lines, directive.pattern, from, end)
+            .ok_or_else(|| failure(directive, from))?;
+        labels.push(start);
+        from = after;
+    }
+
+    let mut labels = labels.into_iter().peekable();
+    let mut bound = end;
+    let mut position = Position { line: 0, column: 0 };
+    // The start of the region the pending CHECK-NOTs cover
+    let mut nots: Vec<&Directive> = Vec::new();
+    let mut not_from = position;
+    for directive in &directives {
+        let (start, after) = match directive.kind {
+            Kind::Not => {
+                nots.push(directive);
+                continue;
+            },
+            Kind::Label => {
+                let start = labels.next().expect("every label was matched");
+                bound = labels.peek().copied().unwrap_or(end);
+                let after = find(&lines, directive.pattern, start, bound).expect("matched before").1;
+                (start, after)
+            },
+            Kind::Check => find(&lines, directive.pattern, position, bound).ok_or_else(|| failure(directive, position))?,
+            Kind::Next | Kind::Same => {
+                let line = match directive.kind {
+                    Kind::Next => position.line + 1,
+                    _ => position.line,
+                };
+                let from = match directive.kind {
+                    Kind::Next => Position { line, column: 0 },
+                    _ => position,
+                };
+                let next_line = Position { line: line + 1, column: 0 };
+                let within = if bound < next_line { bound } else { next_line };
+                find(&lines, directive.pattern, from, within).ok_or_else(|| failure(directive, position))?
+            },
+        };
+        for not in nots.drain(..) {
+            if let Some((found, _)) = find(&lines, not.pattern, not_from, start) {
+                return Err(format!("{}: {:?} occurs on line {}: {}", not.kind.name(), not.pattern, found.line + 1, lines[found.line]));
+            }
+        }
+        position = after;
+        not_from = after;
+    }
+    for not in nots {
+        if let Some((found, _)) = find(&lines, not.pattern, not_from, bound) {
+            return Err(format!("{}: {:?} occurs on line {}: {}", not.kind.name(), not.pattern, found.line + 1, lines[found.line]));
+        }
+    }
+    Ok(())
+}
+
+fn parse(checks: &str) -> Result<Vec<Directive<'_>>, String> {
+    let mut directives = Vec::new();
+    for line in checks.lines() {
+        let Some((prefix, pattern)) = line.split_once(':') else {
+            continue;
+        };
+        let kind = match prefix.trim() {
+            "CHECK" => Kind::Check,
+            "CHECK-NEXT" => Kind::Next,
+            "CHECK-SAME" => Kind::Same,
+            "CHECK-NOT" => Kind::Not,
+            "CHECK-LABEL" => Kind::Label,
+            _ => continue,
+        };
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Err(format!("{} needs a pattern", kind.name()));
+        }
+        if directives.is_empty() && matches!(kind, Kind::Next | Kind::Same) {
+            return Err(format!("{} can't be the first directive", kind.name()));
+        }
+        directives.push(Directive { kind, pattern });
+    }
+    Ok(directives)
+}
+
+fn failure(directive: &Directive, from: Position) -> String {
+    format!("{}: {:?} not found from line {}", directive.kind.name(), directive.pattern, from.line + 1)
+}
+
+/// The first match of `pattern` at or after `from` and before `until`,
+/// from its start to just after it.
+fn find(lines: &[&str], pattern: &str, from: Position, until: Position) -> Option<(Position, Position)> {
+    let parts: Vec<&str> = pattern.split("{{.*}}").collect();
+    for line in from.line..lines.len().min(until.line + 1) {
+        let text = match line == until.line {
+            true => &lines[line][..until.column.min(lines[line].len())],
+            false => lines[line],
+        };
+        let column = if line == from.line { from.column } else { 0 };
+        if let Some((start, end)) = find_in_line(text, &parts, column) {
+            return Some((Position { line, column: start }, Position { line, column: end }));
+        }
+    }
+    None
+}
+
+/// Matches the literal parts of a pattern in order, with anything between
+/// them, starting at or after `column`. Taking the first occurrence of each
+/// part leaves the most room for the ones after it.
+fn find_in_line(text: &str, parts: &[&str], column: usize) -> Option<(usize, usize)> {
+    let (first, rest) = parts.split_first()?;
+    let start = column + text.get(column..)?.find(first)?;
+    let mut end = start + first.len();
+    for part in rest {
+        end += text[end..].find(part)? + part.len();
+    }
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IR: &str = "\
+define i64 @abs(i64 %0) {
+entry:
+  %x = alloca i64, align 8
+  %neg = icmp slt i64 %0, 0
+  br i1 %neg, label %then, label %else
+
+merge:
+  %if_result = phi i64 [ %1, %then ], [ %0, %else ]
+  ret i64 %if_result
+}
+
+define i64 @id(i64 %0) {
+entry:
+  ret i64 %0
+}";
+
+    #[test]
+    fn test_directives() {
+        assert_eq!(check(IR, "
+            CHECK-LABEL: define i64 @abs(
+            CHECK: icmp slt
+            CHECK-NEXT: br i1 %neg, label %then,
+            CHECK-SAME: label %else
+            CHECK-NOT: ret
+            CHECK: phi i64 [ {{.*}}, %then ], [ {{.*}}, %else ]
+            CHECK-LABEL: define i64 @id(
+            CHECK-NOT: phi
+            CHECK: ret i64 %0
+        "), Ok(()));
+    }
+
+    #[test]
+    fn test_failures() {
+        // Labels keep checks from matching in the next function
+        assert_eq!(check(IR, "CHECK-LABEL: @abs(\nCHECK: alloca\nCHECK-LABEL: @id(\nCHECK: alloca"),
+            Err("CHECK: \"alloca\" not found from line 12".to_string()));
+        assert_eq!(check(IR, "CHECK: alloca\nCHECK-NEXT: br"), Err("CHECK-NEXT: \"br\" not found from line 3".to_string()));
+        assert_eq!(check(IR, "CHECK: entry:\nCHECK-NOT: phi\nCHECK: ret"),
+            Err("CHECK-NOT: \"phi\" occurs on line 8:   %if_result = phi i64 [ %1, %then ], [ %0, %else ]".to_string()));
+        assert_eq!(check(IR, "CHECK: ret\nCHECK: phi"), Err("CHECK: \"phi\" not found from line 9".to_string()));
+        assert!(check(IR, "CHECK-NEXT: ret").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! `gard graph`: a program's dependency and call graphs, from its symbol
+//! index, as Graphviz or JSON.
+//!
+//! Gard has no imports yet, so the dependency graph is between top-level
+//! declarations: `Token -> Ledger` when anything in `Token` refers to
+//! anything in `Ledger`.
+
+use crate::index::{Index, SymbolKind};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub type Graph = BTreeMap<String, BTreeSet<String>>;
+
+/// Every top-level declaration, to the others it refers to.
+pub fn dependencies(index: &Index) -> Graph {
+    let mut graph: Graph = index.definitions.iter()
+        .filter(|definition| !definition.name.contains('.'))
+        .map(|definition| (definition.name.clone(), BTreeSet::new()))
+        .collect();
+    for reference in &index.references {
+        let (from, to) = (top_level(&reference.from), top_level(&reference.symbol));
+        if !from.is_empty() && from != to {
+            graph.entry(from.to_string()).or_default().insert(to.to_string());
+        }
+    }
+    graph
+}
+
+/// Every function and method, to the ones it calls.
+pub fn calls(index: &Index) -> Graph {
+    let mut graph: Graph = index.definitions.iter()
+        .filter(|definition| matches!(definition.kind, SymbolKind::Function | SymbolKind::Method))
+        .map(|definition| (definition.name.clone(), BTreeSet::new()))
+        .collect();
+    for (caller, callees) in &index.calls {
+        graph.entry(caller.clone()).or_default().extend(callees.iter().cloned());
+    }
+    graph
+}
+
+/// Both graphs as Graphviz digraphs, in one file.
+pub fn dot(index: &Index) -> String {
+    let boxed = |name: &str| index.definition(name)
+        .is_some_and(|definition| matches!(definition.kind, SymbolKind::Class | SymbolKind::Contract | SymbolKind::Actor));
+    format!(
+        "{}\n{}",
+        digraph("dependencies", &dependencies(index), &boxed),
+        digraph("calls", &calls(index), &|_| false),
+    )
+}
+
+/// `{"dependencies": {..}, "calls": {..}}`, each an adjacency list.
+pub fn json(index: &Index) -> String {
+    let graphs = BTreeMap::from([("calls", calls(index)), ("dependencies", dependencies(index))]);
+    // Maps of strings always serialize
+    serde_json::to_string_pretty(&graphs).unwrap()
+}
+
+fn top_level(name: &str) -> &str {
+    name.split('.').next().unwrap_or(name)
+}
+
+fn digraph(name: &str, graph: &Graph, boxed: &dyn Fn(&str) -> bool) -> String {
+    let mut dot = format!("digraph {} {{\n", name);
+    for node in graph.keys() {
+        let shape = if boxed(node) { " [shape=box]" } else { "" };
+        dot.push_str(&format!("    {}{};\n", quote(node), shape));
+    }
+    for (from, targets) in graph {
+        for to in targets {
+            dot.push_str(&format!("    {} -> {};\n", quote(from), quote(to)));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::{Node, Type};
+
+    fn function(name: &str, body: Vec<Node>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type: Type::Void,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn call(callee: Node) -> Node {
+        Node::Block(vec![Node::Call { callee: Box::new(callee), arguments: vec![] }])
+    }
+
+    fn index() -> Index {
+        let ledger_record = Node::Member { object: Box::new(Node::Identifier("Ledger".into())), property: "record".to_string() };
+        Index::build(&Node::Program(vec![
+            Node::Contract { name: "Ledger".to_string(), members: vec![function("record", vec![])], docs: None },
+            Node::Contract { name: "Token".to_string(), members: vec![function("transfer", vec![call(ledger_record)])], docs: None },
+            function("main", vec![call(Node::Identifier("log".into()))]),
+            function("log", vec![]),
+        ]))
+    }
+
+    #[test]
+    fn test_graphs() {
+        let index = index();
+        assert_eq!(dependencies(&index), Graph::from([
+            ("Ledger".to_string(), BTreeSet::new()),
+            ("Token".to_string(), BTreeSet::from(["Ledger".to_string()])),
+            ("log".to_string(), BTreeSet::new()),
+            ("main".to_string(), BTreeSet::from(["log".to_string()])),
+        ]));
+        assert_eq!(calls(&index)["Token.transfer"], BTreeSet::from(["Ledger.record".to_string()]));
+        assert_eq!(calls(&index).len(), 4);
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = dot(&index());
+        assert!(dot.starts_with("digraph dependencies {\n    \"Ledger\" [shape=box];\n"));
+        assert!(dot.contains("    \"Token\" -> \"Ledger\";\n"));
+        assert!(dot.contains("digraph calls {\n"));
+        assert!(dot.contains("    \"Token.transfer\" -> \"Ledger.record\";\n"));
+        assert_eq!(quote("a\"b"), "\"a\\\"b\"");
+
+        let json: serde_json::Value = serde_json::from_str(&json(&index())).unwrap();
+        assert_eq!(json["dependencies"]["main"], serde_json::json!(["log"]));
+    }
+}
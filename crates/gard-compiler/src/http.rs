@@ -0,0 +1,102 @@
+use gard_ast::{Node, Type};
+
+/// Builtins of `std.http`, called as `http.get(url)`. A request returns a
+/// `Response`, a handle to gard-vm's copy of it stored as an int, which the
+/// other builtins read until `http.close(response)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpBuiltin {
+    Get,
+    Post,
+    Status,
+    Header,
+    Body,
+    Text,
+    Close,
+}
+
+pub const MODULE: &str = "http";
+pub const RESPONSE: &str = "Response";
+
+impl HttpBuiltin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "get" => Some(HttpBuiltin::Get),
+            "post" => Some(HttpBuiltin::Post),
+            "status" => Some(HttpBuiltin::Status),
+            "header" => Some(HttpBuiltin::Header),
+            "body" => Some(HttpBuiltin::Body),
+            "text" => Some(HttpBuiltin::Text),
+            "close" => Some(HttpBuiltin::Close),
+            _ => None,
+        }
+    }
+
+    /// Resolves `http.get(..)` style callees.
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee.unlocated() {
+            Node::Member { object, property } => match object.unlocated() {
+                Node::Identifier(module) if module == MODULE => Self::from_name(property),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HttpBuiltin::Get => "get",
+            HttpBuiltin::Post => "post",
+            HttpBuiltin::Status => "status",
+            HttpBuiltin::Header => "header",
+            HttpBuiltin::Body => "body",
+            HttpBuiltin::Text => "text",
+            HttpBuiltin::Close => "close",
+        }
+    }
+
+    /// `post(url, body, contentType)`. An error status is a response like
+    /// any other; only a failed connection raises. `header` ignores case
+    /// and returns null for a missing header.
+    pub fn signature(&self) -> Type {
+        let response = Type::Custom(RESPONSE.into());
+        let (params, return_type) = match self {
+            HttpBuiltin::Get => (vec![Type::String], response),
+            HttpBuiltin::Post => (vec![Type::String, Type::Bytes, Type::String], response),
+            HttpBuiltin::Status => (vec![response], Type::Int),
+            HttpBuiltin::Header => (vec![response, Type::String], Type::String),
+            HttpBuiltin::Body => (vec![response], Type::Bytes),
+            HttpBuiltin::Text => (vec![response], Type::String),
+            HttpBuiltin::Close => (vec![response], Type::Void),
+        };
+        Type::Function { params, return_type: Box::new(return_type) }
+    }
+
+    /// Symbol of the native implementation in gard-vm.
+    pub fn runtime_symbol(&self) -> &'static str {
+        match self {
+            HttpBuiltin::Get => "gard_http_get",
+            HttpBuiltin::Post => "gard_http_post",
+            HttpBuiltin::Status => "gard_http_status",
+            HttpBuiltin::Header => "gard_http_header",
+            HttpBuiltin::Body => "gard_http_body",
+            HttpBuiltin::Text => "gard_http_text",
+            HttpBuiltin::Close => "gard_http_close",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_member_callee() {
+        let callee = |module: &str, name: &str| Node::Member {
+            object: Box::new(Node::Identifier(module.into())),
+            property: name.to_string(),
+        };
+        assert_eq!(HttpBuiltin::from_callee(&callee("http", "post")), Some(HttpBuiltin::Post));
+        assert_eq!(HttpBuiltin::from_callee(&callee("http", "put")), None);
+        assert_eq!(HttpBuiltin::from_callee(&callee("net", "get")), None);
+    }
+}
@@ -0,0 +1,927 @@
+//! Symbol index: the definitions in a program, the references to them and
+//! the call graph between functions. `gard refs`, the lints and
+//! editor tooling all query it instead of walking the AST themselves.
+//!
+//! Symbols are named by their declaration path: `transfer` for a top-level
+//! function, `Token.transfer` for a member. A member access is resolved when
+//! the object's class is known: `this`, `super`, a class name, or a
+//! parameter, field or local declared with a class type. Other accesses and
+//! names that refer to locals are not indexed. A method called on an
+//! interface value refers to the interface's method and to each class's
+//! implementation of it, any of which the call may reach.
+//!
+//! Spans are those of the statements a reference or definition is in, so
+//! top-level declarations have none.
+
+use crate::plugin::Finding;
+use gard_ast::{FunctionModifier, Node, Parameter, Span, Type};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SymbolKind {
+    Class,
+    Contract,
+    Actor,
+    Function,
+    Method,
+    Constructor,
+    Field,
+    Constant,
+    Union,
+    Interface,
+    Event,
+    Macro,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub span: Option<Span>,
+    /// Callable from outside the program: `main`, contract members and wasm
+    /// exports
+    pub entry_point: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reference {
+    pub symbol: String,
+    /// The definition the reference is in, or empty at the top level
+    pub from: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    /// What the index was built from, set by the driver so a cached index
+    /// can be checked for staleness
+    pub fingerprint: u64,
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    /// Function or method to the functions and methods it calls
+    pub calls: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Index {
+    pub fn build(program: &Node) -> Self {
+        let mut builder = IndexBuilder::default();
+        builder.declare(program, None, false);
+        builder.visit(program);
+        builder.index
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&Definition> {
+        self.definitions.iter().find(|definition| definition.name == name)
+    }
+
+    pub fn references_to<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Reference> + 'a {
+        self.references.iter().filter(move |reference| reference.symbol == name)
+    }
+
+    pub fn callers<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.calls.iter()
+            .filter(move |(_, callees)| callees.contains(name))
+            .map(|(caller, _)| caller.as_str())
+    }
+
+    pub fn callees(&self, name: &str) -> impl Iterator<Item = &str> {
+        self.calls.get(name).into_iter().flatten().map(String::as_str)
+    }
+
+    /// Functions and methods that aren't entry points and are only referred
+    /// to from their own body, if at all.
+    pub fn dead_code(&self) -> Vec<&Definition> {
+        self.definitions.iter()
+            .filter(|definition| matches!(definition.kind, SymbolKind::Function | SymbolKind::Method))
+            .filter(|definition| !definition.entry_point)
+            .filter(|definition| self.references_to(&definition.name).all(|reference| reference.from == definition.name))
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| format!("Failed to serialize index: {}", e))?;
+        std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid index {}: {}", path.display(), e))
+    }
+}
+
+/// Warnings for `Index::dead_code`, in the shape `plugin::Registry::add_lint`
+/// takes.
+pub fn dead_code_lint(program: &Node) -> Vec<Finding> {
+    Index::build(program).dead_code().into_iter()
+        .map(|definition| Finding {
+            declaration: definition.name.clone(),
+            message: match definition.kind {
+                SymbolKind::Method => format!("method '{}' is never used", definition.name),
+                _ => format!("function '{}' is never used", definition.name),
+            },
+        })
+        .collect()
+}
+
+/// Warnings for locals declared with `let`, `const` or `tvar` and never
+/// used, except those named with a leading `_`. Locals of the same name in
+/// one function are one symbol, so a use of either counts for both.
+pub fn unused_variable_lint(program: &Node) -> Vec<Finding> {
+    let mut builder = IndexBuilder::default();
+    builder.declare(program, None, false);
+    builder.visit(program);
+    let mut uses: HashMap<&str, usize> = HashMap::new();
+    for symbol in builder.occurrences.iter().filter_map(|occurrence| occurrence.symbol.as_deref()) {
+        *uses.entry(symbol).or_default() += 1;
+    }
+    let mut reported = HashSet::new();
+    builder.variables.iter()
+        .filter(|symbol| uses.get(symbol.as_str()) == Some(&1) && reported.insert(symbol.as_str()))
+        .filter_map(|symbol| symbol.rsplit_once('.'))
+        .filter(|(_, name)| !name.starts_with('_'))
+        .map(|(function, name)| Finding { declaration: function.to_string(), message: format!("variable '{}' is never used", name) })
+        .collect()
+}
+
+/// The files among `indexes` that declare `name` at the top level, for a
+/// fix-it on a name that didn't resolve. There's no `import` to suggest
+/// yet, so the fix-it names the file instead.
+pub fn declaring_files<'a>(name: &str, indexes: &'a [(String, Index)]) -> Vec<&'a str> {
+    if name.contains('.') {
+        return Vec::new();
+    }
+    indexes.iter()
+        .filter(|(_, index)| index.definition(name).is_some())
+        .map(|(path, _)| path.as_str())
+        .collect()
+}
+
+/// A name as written in the source, with the symbol it refers to. Locals
+/// are named `function.local`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Occurrence {
+    pub name: String,
+    pub symbol: Option<String>,
+}
+
+/// Reads some occurrences of a name as another name, to see what they and
+/// every other name would then resolve to.
+pub(crate) struct Renaming {
+    pub symbol: String,
+    pub new_name: String,
+    /// Positions in the list `occurrences` returns
+    pub at: HashSet<usize>,
+}
+
+/// Every name in the program in source order, declarations included.
+pub(crate) fn occurrences(program: &Node, renaming: Option<Renaming>) -> Vec<Occurrence> {
+    let mut builder = IndexBuilder { renaming, ..IndexBuilder::default() };
+    builder.declare(program, None, false);
+    builder.visit(program);
+    builder.occurrences
+}
+
+/// What an expression refers to, as far as names go.
+#[derive(Default)]
+struct Resolved {
+    symbol: Option<String>,
+    /// The class of its value, for resolving members of it
+    class: Option<String>,
+}
+
+#[derive(Default)]
+struct IndexBuilder {
+    index: Index,
+    occurrences: Vec<Occurrence>,
+    renaming: Option<Renaming>,
+    /// Each class-like declaration's members and base class
+    classes: HashMap<String, (HashSet<String>, Option<String>)>,
+    /// Classes implementing each interface
+    implementations: HashMap<String, Vec<String>>,
+    globals: HashSet<String>,
+    /// Fields declared with a custom type, to that type's name
+    field_types: HashMap<String, String>,
+    /// Locals in scope, with their class if they have one
+    scopes: Vec<HashMap<String, Option<String>>>,
+    /// The symbols of the locals `let`, `const` and `tvar` declare
+    variables: Vec<String>,
+    class: Option<String>,
+    from: String,
+    span: Option<Span>,
+}
+
+impl IndexBuilder {
+    /// Records definitions before any body is visited, so references can
+    /// point forward.
+    fn declare(&mut self, node: &Node, class: Option<&str>, entry_point: bool) {
+        match node {
+            Node::Program(nodes) | Node::Block(nodes) => {
+                for node in nodes {
+                    self.declare(node, class, entry_point);
+                }
+            },
+            Node::Located { span, node } => {
+                let outer = self.span.replace(*span);
+                self.declare(node, class, entry_point);
+                self.span = outer;
+            },
+            // A nested class is a member of the class it's in
+            Node::Class { name, extends, implements, members, .. } => {
+                let name = qualify(class, name);
+                let extends = extends.as_deref().map(|extends| self.renamed(extends.to_string()));
+                self.declare_class(&name, SymbolKind::Class, extends, members, false);
+                for interface in implements {
+                    let class = self.renamed(name.clone());
+                    self.implementations.entry(self.renamed(interface.clone())).or_default().push(class);
+                }
+            },
+            Node::Interface { name, methods } => {
+                let name = self.renamed(name.clone());
+                self.define(name.clone(), SymbolKind::Interface, false);
+                let before = self.index.definitions.len();
+                for method in methods {
+                    self.define(qualify(Some(&name), &method.name), SymbolKind::Method, false);
+                }
+                let prefix = format!("{}.", name);
+                let methods = self.index.definitions[before..].iter()
+                    .filter_map(|definition| definition.name.strip_prefix(&prefix).map(str::to_string))
+                    .collect();
+                self.classes.insert(name, (methods, None));
+            },
+            Node::Contract { name, members, .. } => self.declare_class(name, SymbolKind::Contract, None, members, true),
+            Node::Actor { name, members, .. } => self.declare_class(name, SymbolKind::Actor, None, members, false),
+            Node::Function { name, modifiers, .. } => {
+                let kind = if class.is_some() { SymbolKind::Method } else { SymbolKind::Function };
+                // `gard simulate` calls tests, and C calls exported functions
+                let entry_point = entry_point || (class.is_none() && name == "main") || modifiers.contains(&FunctionModifier::Concurrent) || modifiers.contains(&FunctionModifier::Export);
+                self.define(qualify(class, name), kind, entry_point);
+            },
+            Node::Constructor { .. } => self.define(qualify(class, "constructor"), SymbolKind::Constructor, true),
+            Node::Let { name, type_annotation, .. } if class.is_some() => {
+                if let Some(Type::Custom(ty)) = type_annotation {
+                    let (field, ty) = (self.renamed(qualify(class, name)), self.renamed(ty.to_string()));
+                    self.field_types.insert(field, ty);
+                }
+                self.define(qualify(class, name), SymbolKind::Field, false);
+            },
+            Node::Const { name, .. } => self.define(qualify(class, name), SymbolKind::Constant, false),
+            Node::Event { name, .. } => self.define(qualify(class, name), SymbolKind::Event, false),
+            // A union has no members, but is a type like a class
+            Node::Union { name, .. } => {
+                self.define(name.clone(), SymbolKind::Union, false);
+                self.classes.insert(self.renamed(name.clone()), (HashSet::new(), None));
+            },
+            Node::MacroDefinition { name, .. } => self.define(name.clone(), SymbolKind::Macro, false),
+            Node::WasmExport { declaration, .. } => self.declare(declaration, class, true),
+            Node::StorageSlot { declaration, .. }
+            | Node::Derive { declaration, .. }
+            | Node::Cfg { declaration, .. }
+            | Node::Attribute { declaration, .. } => self.declare(declaration, class, entry_point),
+            _ => {},
+        }
+    }
+
+    fn declare_class(&mut self, name: &str, kind: SymbolKind, extends: Option<String>, members: &[Node], entry_point: bool) {
+        let name = self.renamed(name.to_string());
+        self.define(name.clone(), kind, false);
+        let before = self.index.definitions.len();
+        for member in members {
+            self.declare(member, Some(&name), entry_point);
+        }
+        let prefix = format!("{}.", name);
+        let names = self.index.definitions[before..].iter()
+            .filter_map(|definition| definition.name.strip_prefix(&prefix).map(str::to_string))
+            .collect();
+        self.classes.insert(name, (names, extends));
+    }
+
+    fn define(&mut self, name: String, kind: SymbolKind, entry_point: bool) {
+        let name = self.renamed(name);
+        if !name.contains('.') {
+            self.globals.insert(name.clone());
+        }
+        self.index.definitions.push(Definition { name, kind, span: self.span, entry_point });
+    }
+
+    /// A declared symbol's name, after the renaming if it's the one renamed.
+    fn renamed(&self, symbol: String) -> String {
+        match &self.renaming {
+            Some(renaming) if renaming.symbol == symbol => match symbol.rsplit_once('.') {
+                Some((parent, _)) => format!("{}.{}", parent, renaming.new_name),
+                None => renaming.new_name.clone(),
+            },
+            _ => symbol,
+        }
+    }
+
+    /// Records the next name in the source and returns the name to resolve
+    /// it by, with what it resolves to.
+    fn occur(&mut self, written: &str, resolve: impl FnOnce(&Self, &str) -> Option<String>) -> (String, Option<String>) {
+        let name = match &self.renaming {
+            Some(renaming) if renaming.at.contains(&self.occurrences.len()) => renaming.new_name.clone(),
+            _ => written.to_string(),
+        };
+        let symbol = resolve(self, &name);
+        self.occurrences.push(Occurrence { name: written.to_string(), symbol: symbol.clone() });
+        (name, symbol)
+    }
+
+    fn refer(&mut self, symbol: String) {
+        self.index.references.push(Reference { symbol, from: self.from.clone(), span: self.span });
+    }
+
+    /// The methods a call to `symbol` may reach when it's an interface's
+    /// method: each implementing class's.
+    fn implementations_of(&self, symbol: &str) -> Vec<String> {
+        let Some((interface, method)) = symbol.rsplit_once('.') else {
+            return Vec::new();
+        };
+        self.implementations.get(interface).into_iter().flatten()
+            .filter_map(|class| self.find_member(class, method))
+            .collect()
+    }
+
+    fn call(&mut self, callee: String) {
+        let is_function = self.index.definition(&callee)
+            .is_some_and(|definition| matches!(definition.kind, SymbolKind::Function | SymbolKind::Method));
+        if is_function && !self.from.is_empty() {
+            self.index.calls.entry(self.from.clone()).or_default().insert(callee);
+        }
+    }
+
+    fn visit(&mut self, node: &Node) -> Resolved {
+        match node {
+            Node::Located { span, node } => {
+                let outer = self.span.replace(*span);
+                let resolved = self.visit(node);
+                self.span = outer;
+                return resolved;
+            },
+            Node::Class { name, extends, implements, members, .. } => {
+                let (name, _) = self.occur(name, |s, name| Some(qualify(s.class.as_deref(), name)));
+                let name = qualify(self.class.as_deref(), &name);
+                for base in extends.iter().chain(implements) {
+                    self.occur(base, |s, name| s.resolve_class(name));
+                }
+                self.visit_members(name, members);
+            },
+            Node::Contract { name, members, .. } => {
+                let (name, _) = self.occur(name, |_, name| Some(name.to_string()));
+                self.visit_members(name, members);
+            },
+            // The mailbox and behavior aren't in the source
+            Node::Actor { name, type_param, members, .. } => {
+                let (name, _) = self.occur(name, |_, name| Some(name.to_string()));
+                if let Some(ty) = type_param {
+                    self.visit_type(ty);
+                }
+                self.visit_members(name, members);
+            },
+            Node::Function { name, params, return_type, body, .. } => {
+                let (_, symbol) = self.occur(name, |s, name| Some(qualify(s.class.as_deref(), name)));
+                self.visit_body(symbol.unwrap_or_default(), params, Some(return_type), body);
+            },
+            Node::Constructor { params, body } => {
+                let name = qualify(self.class.as_deref(), "constructor");
+                self.visit_body(name, params, None, body);
+            },
+            Node::MacroDefinition { name, params, body } => {
+                let (name, _) = self.occur(name, |_, name| Some(name.to_string()));
+                let outer = std::mem::replace(&mut self.from, name);
+                let scopes = std::mem::take(&mut self.scopes);
+                self.scopes.push(HashMap::new());
+                for param in params {
+                    let (param, _) = self.occur(param, |s, name| Some(s.local_symbol(name)));
+                    self.declare_local(&param, None);
+                }
+                self.visit(body);
+                self.scopes = scopes;
+                self.from = outer;
+            },
+            Node::Block(nodes) => {
+                self.scopes.push(HashMap::new());
+                for node in nodes {
+                    self.visit(node);
+                }
+                self.scopes.pop();
+            },
+            Node::Let { name, type_annotation, initializer, .. } => {
+                self.visit_variable(name, type_annotation.as_ref(), initializer.as_deref());
+            },
+            // A constant's initializer is evaluated on its own behalf
+            Node::Const { name, type_annotation, value } if self.scopes.is_empty() => {
+                let (_, symbol) = self.occur(name, |s, name| Some(qualify(s.class.as_deref(), name)));
+                if let Some(ty) = type_annotation {
+                    self.visit_type(ty);
+                }
+                let outer = std::mem::replace(&mut self.from, symbol.unwrap_or_default());
+                self.visit(value);
+                self.from = outer;
+            },
+            Node::Const { name, type_annotation, value } => {
+                self.visit_variable(name, type_annotation.as_ref(), Some(value));
+            },
+            Node::TVar { name, value_type, initial_value } => {
+                self.visit_variable(name, Some(value_type), initial_value.as_deref());
+            },
+            Node::Foreach { item, collection, body } => {
+                self.scopes.push(HashMap::new());
+                let (item, _) = self.occur(item, |s, name| Some(s.local_symbol(name)));
+                self.visit(collection);
+                self.declare_local(&item, None);
+                self.visit(body);
+                self.scopes.pop();
+            },
+            Node::CatchClause { param_name, param_type, body } => {
+                self.scopes.push(HashMap::new());
+                self.visit_params(std::slice::from_ref(&Parameter { name: param_name.into(), type_annotation: param_type.clone() }));
+                self.visit(body);
+                self.scopes.pop();
+            },
+            Node::For { .. } => {
+                self.scopes.push(HashMap::new());
+                for child in node.children() {
+                    self.visit(child);
+                }
+                self.scopes.pop();
+            },
+            Node::Receive { message_param, body } => {
+                self.scopes.push(HashMap::new());
+                self.visit_params(std::slice::from_ref(message_param));
+                self.visit(body);
+                self.scopes.pop();
+            },
+            Node::Union { name, variants } => {
+                self.occur(name, |_, name| Some(name.to_string()));
+                for variant in variants {
+                    self.occur(&variant.name, |s, name| s.classes.contains_key(name).then(|| name.to_string()));
+                }
+            },
+            Node::Event { name, fields, .. } => {
+                self.occur(name, |s, name| Some(qualify(s.class.as_deref(), name)));
+                for field in fields {
+                    self.occur(&field.name, |_, _| None);
+                    self.visit_type(&field.type_annotation);
+                }
+            },
+            Node::Interface { name, methods } => {
+                let (name, _) = self.occur(name, |_, name| Some(name.to_string()));
+                for method in methods {
+                    self.occur(&method.name, |_, method| Some(qualify(Some(&name), method)));
+                    for param in &method.params {
+                        self.occur(&param.name, |_, _| None);
+                        self.visit_type(&param.type_annotation);
+                    }
+                    self.visit_type(&method.return_type);
+                }
+            },
+            Node::WasmImport { params, return_type, .. } => {
+                for param in params {
+                    self.occur(&param.name, |_, _| None);
+                    self.visit_type(&param.type_annotation);
+                }
+                self.visit_type(return_type);
+            },
+            Node::Derive { derives, declaration } => {
+                for derive in derives {
+                    self.occur(derive, |_, _| None);
+                }
+                self.visit(declaration);
+            },
+            Node::Cfg { conditions, declaration } => {
+                for (key, _) in conditions {
+                    self.occur(key, |_, _| None);
+                }
+                self.visit(declaration);
+            },
+            Node::Attribute { name, arguments, declaration } => {
+                self.occur(name, |_, _| None);
+                for argument in arguments {
+                    self.visit(argument);
+                }
+                self.visit(declaration);
+            },
+            Node::This => return Resolved { symbol: None, class: self.class.clone() },
+            Node::Super => {
+                let class = self.class.as_deref().and_then(|class| self.base(class));
+                return Resolved { symbol: None, class };
+            },
+            Node::Identifier(name) => {
+                let (name, symbol) = self.occur(name, |s, name| s.resolve_name(name));
+                return match self.local(&name).cloned() {
+                    Some(class) => Resolved { symbol, class },
+                    None => {
+                        let class = symbol.as_deref().and_then(|symbol| {
+                            self.field_class(symbol).or_else(|| self.classes.contains_key(symbol).then(|| symbol.to_string()))
+                        });
+                        if let Some(symbol) = &symbol {
+                            self.refer(symbol.clone());
+                        }
+                        Resolved { symbol, class }
+                    },
+                };
+            },
+            Node::Member { object, property } => {
+                let object = self.visit(object);
+                let (_, symbol) = self.occur(property, |s, property| {
+                    object.class.as_deref().and_then(|class| s.find_member(class, property))
+                });
+                if let Some(symbol) = &symbol {
+                    self.refer(symbol.clone());
+                    for implementation in self.implementations_of(symbol) {
+                        self.refer(implementation);
+                    }
+                }
+                let class = symbol.as_deref().and_then(|symbol| {
+                    self.field_class(symbol).or_else(|| self.classes.contains_key(symbol).then(|| symbol.to_string()))
+                });
+                return Resolved { symbol, class };
+            },
+            Node::Call { callee, arguments } => {
+                if let Some(callee) = self.visit(callee).symbol {
+                    for implementation in self.implementations_of(&callee) {
+                        self.call(implementation);
+                    }
+                    self.call(callee);
+                }
+                for argument in arguments {
+                    self.visit(argument);
+                }
+            },
+            Node::MacroCall { name, arguments, .. } => {
+                let (_, symbol) = self.occur(name, |s, name| s.globals.contains(name).then(|| name.to_string()));
+                if let Some(symbol) = symbol {
+                    self.refer(symbol);
+                }
+                for argument in arguments {
+                    self.visit(argument);
+                }
+            },
+            node => {
+                for child in node.children() {
+                    self.visit(child);
+                }
+            },
+        }
+        Resolved::default()
+    }
+
+    fn visit_members(&mut self, class: String, members: &[Node]) {
+        let outer = self.class.replace(class);
+        for member in members {
+            self.visit(member);
+        }
+        self.class = outer;
+    }
+
+    fn visit_body(&mut self, name: String, params: &[Parameter], return_type: Option<&Type>, body: &Node) {
+        let outer = std::mem::replace(&mut self.from, name);
+        let scopes = std::mem::take(&mut self.scopes);
+        self.scopes.push(HashMap::new());
+        self.visit_params(params);
+        if let Some(return_type) = return_type {
+            self.visit_type(return_type);
+        }
+        self.visit(body);
+        self.scopes = scopes;
+        self.from = outer;
+    }
+
+    fn visit_params(&mut self, params: &[Parameter]) {
+        for param in params {
+            let (name, _) = self.occur(&param.name, |s, name| Some(s.local_symbol(name)));
+            let class = self.visit_type(&param.type_annotation);
+            self.declare_local(&name, class);
+        }
+    }
+
+    /// A field, a local, or a top-level variable, which isn't indexed.
+    fn visit_variable(&mut self, name: &str, ty: Option<&Type>, initializer: Option<&Node>) {
+        let field = self.scopes.is_empty() && self.class.is_some();
+        let local = !self.scopes.is_empty();
+        let (name, symbol) = self.occur(name, |s, name| {
+            if field {
+                Some(qualify(s.class.as_deref(), name))
+            } else {
+                local.then(|| s.local_symbol(name))
+            }
+        });
+        let class = ty.and_then(|ty| self.visit_type(ty));
+        if let Some(initializer) = initializer {
+            self.visit(initializer);
+        }
+        if local {
+            self.declare_local(&name, class);
+            self.variables.extend(symbol);
+        }
+    }
+
+    /// Records the class names in a type, and returns its class.
+    fn visit_type(&mut self, ty: &Type) -> Option<String> {
+        match ty {
+            Type::Custom(name) => self.occur(name, |s, name| s.resolve_class(name)).1,
+            Type::Array(element) | Type::Set(element) => {
+                self.visit_type(element);
+                None
+            },
+            Type::Map { key, value } => {
+                self.visit_type(key);
+                self.visit_type(value);
+                None
+            },
+            Type::Function { params, return_type } => {
+                for param in params {
+                    self.visit_type(param);
+                }
+                self.visit_type(return_type);
+                None
+            },
+            _ => None,
+        }
+    }
+
+    fn declare_local(&mut self, name: &str, class: Option<String>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), class);
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<&Option<String>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn local_symbol(&self, name: &str) -> String {
+        qualify(Some(&self.from).filter(|from| !from.is_empty()).map(String::as_str), name)
+    }
+
+    /// A bare name: a local, a member of the enclosing class, or a global.
+    fn resolve_name(&self, name: &str) -> Option<String> {
+        if self.local(name).is_some() {
+            return Some(self.local_symbol(name));
+        }
+        if let Some(member) = self.class.as_deref().and_then(|class| self.find_member(class, name)) {
+            return Some(member);
+        }
+        if let Some(class) = self.class.as_deref().and_then(|class| self.nested_class(class, name)) {
+            return Some(class);
+        }
+        self.globals.contains(name).then(|| name.to_string())
+    }
+
+    /// A class named in the enclosing class: one nested in it or in a class
+    /// around it, or a top-level or qualified one.
+    fn resolve_class(&self, name: &str) -> Option<String> {
+        self.class.as_deref()
+            .and_then(|class| self.nested_class(class, name))
+            .or_else(|| self.classes.contains_key(name).then(|| name.to_string()))
+    }
+
+    /// `name` nested in `class` or in the classes around it, the innermost
+    /// first.
+    fn nested_class(&self, class: &str, name: &str) -> Option<String> {
+        let mut scope = Some(class);
+        while let Some(outer) = scope {
+            let nested = qualify(Some(outer), name);
+            if self.classes.contains_key(&nested) {
+                return Some(nested);
+            }
+            scope = outer.rsplit_once('.').map(|(outer, _)| outer);
+        }
+        None
+    }
+
+    /// A class's base class, which a nested class names from around it.
+    fn base(&self, class: &str) -> Option<String> {
+        let base = self.classes.get(class)?.1.as_ref()?;
+        class.rsplit_once('.')
+            .and_then(|(outer, _)| self.nested_class(outer, base))
+            .or_else(|| Some(base.clone()))
+    }
+
+    /// `class.name`, looking through base classes.
+    fn find_member(&self, class: &str, name: &str) -> Option<String> {
+        let mut class = class.to_string();
+        // Bounded, in case of an inheritance cycle
+        for _ in 0..=self.classes.len() {
+            let (members, _) = self.classes.get(&class)?;
+            if members.contains(name) {
+                return Some(format!("{}.{}", class, name));
+            }
+            class = self.base(&class)?;
+        }
+        None
+    }
+
+    fn field_class(&self, field: &str) -> Option<String> {
+        self.field_types.get(field).filter(|ty| self.classes.contains_key(*ty)).cloned()
+    }
+}
+
+fn qualify(parent: Option<&str>, name: &str) -> String {
+    match parent {
+        Some(parent) => format!("{}.{}", parent, name),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(name: &str, params: Vec<Parameter>, body: Vec<Node>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params,
+            return_type: Type::Void,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn identifier(name: &str) -> Node {
+        Node::Identifier(name.into())
+    }
+
+    fn member(object: Node, property: &str) -> Node {
+        Node::Member { object: Box::new(object), property: property.to_string() }
+    }
+
+    fn call(callee: Node) -> Node {
+        Node::Located {
+            span: Span { start: 0, end: 4 },
+            node: Box::new(Node::Block(vec![Node::Call { callee: Box::new(callee), arguments: vec![] }])),
+        }
+    }
+
+    fn program() -> Node {
+        Node::Program(vec![
+            Node::Class {
+                name: "Account".to_string(),
+                extends: None,
+                implements: vec![],
+                is_abstract: false,
+                members: vec![
+                    Node::Let { name: "balance".to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable: false },
+                    function("deposit", vec![], vec![call(member(Node::This, "audit")), call(identifier("balance"))]),
+                    function("audit", vec![], vec![]),
+                ],
+                docs: None,
+            },
+            function("helper", vec![], vec![call(identifier("helper"))]),
+            function("main", vec![Parameter { name: "account".into(), type_annotation: Type::Custom("Account".into()) }], vec![
+                call(member(identifier("account"), "deposit")),
+                Node::Let { name: "helper".to_string(), type_annotation: None, initializer: None, is_mutable: false },
+                call(identifier("helper")),
+            ]),
+        ])
+    }
+
+    #[test]
+    fn test_definitions_and_references() {
+        let index = Index::build(&program());
+        let names: Vec<&str> = index.definitions.iter().map(|definition| definition.name.as_str()).collect();
+        assert_eq!(names, vec!["Account", "Account.balance", "Account.deposit", "Account.audit", "helper", "main"]);
+        assert_eq!(index.definition("Account.deposit").unwrap().kind, SymbolKind::Method);
+        assert!(index.definition("main").unwrap().entry_point);
+
+        let references: Vec<&Reference> = index.references_to("Account.deposit").collect();
+        assert_eq!(references, vec![&Reference {
+            symbol: "Account.deposit".to_string(),
+            from: "main".to_string(),
+            span: Some(Span { start: 0, end: 4 }),
+        }]);
+        // `helper` in main is the local, not the function
+        assert_eq!(index.references_to("helper").map(|reference| reference.from.as_str()).collect::<Vec<_>>(), vec!["helper"]);
+        assert_eq!(index.references_to("Account.balance").count(), 1);
+
+        assert_eq!(index.callees("Account.deposit").collect::<Vec<_>>(), vec!["Account.audit"]);
+        assert_eq!(index.callers("Account.deposit").collect::<Vec<_>>(), vec!["main"]);
+    }
+
+    #[test]
+    fn test_dead_code() {
+        assert_eq!(dead_code_lint(&program()), vec![Finding {
+            declaration: "helper".to_string(),
+            message: "function 'helper' is never used".to_string(),
+        }]);
+
+        let path = std::env::temp_dir().join(format!("gard-index-test-{}.json", std::process::id()));
+        let index = Index::build(&program());
+        index.save(&path).unwrap();
+        assert_eq!(Index::load(&path).unwrap(), index);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_declaring_files() {
+        let indexes = vec![
+            ("bank.gard".to_string(), Index::build(&program())),
+            ("util.gard".to_string(), Index::build(&Node::Program(vec![function("helper", vec![], vec![])]))),
+        ];
+        assert_eq!(declaring_files("helper", &indexes), ["bank.gard", "util.gard"]);
+        assert_eq!(declaring_files("Account", &indexes), ["bank.gard"]);
+        // A member isn't reachable by its name alone
+        assert!(declaring_files("Account.audit", &indexes).is_empty());
+        assert!(declaring_files("missing", &indexes).is_empty());
+    }
+
+    #[test]
+    fn test_interface_dispatch() {
+        let class = |name: &str, implements: Vec<String>| Node::Class {
+            name: name.to_string(),
+            extends: None,
+            implements,
+            is_abstract: false,
+            members: vec![function("area", vec![], vec![])],
+            docs: None,
+        };
+        let program = Node::Program(vec![
+            Node::Interface {
+                name: "Shape".to_string(),
+                methods: vec![gard_ast::MethodSignature { name: "area".to_string(), params: vec![], return_type: Type::Int }],
+            },
+            class("Square", vec!["Shape".to_string()]),
+            class("Point", vec![]),
+            function("main", vec![Parameter { name: "shape".into(), type_annotation: Type::Custom("Shape".into()) }], vec![
+                call(member(identifier("shape"), "area")),
+            ]),
+        ]);
+        let index = Index::build(&program);
+        assert_eq!(index.definition("Shape").unwrap().kind, SymbolKind::Interface);
+        assert_eq!(index.callees("main").collect::<Vec<_>>(), vec!["Shape.area", "Square.area"]);
+        assert_eq!(index.references_to("Square.area").count(), 1);
+        assert_eq!(dead_code_lint(&program).into_iter().map(|finding| finding.message).collect::<Vec<_>>(),
+            vec!["method 'Point.area' is never used".to_string()]);
+    }
+
+    #[test]
+    fn test_nested_classes() {
+        let class = |name: &str, extends: Option<&str>, members: Vec<Node>| Node::Class {
+            name: name.to_string(),
+            extends: extends.map(str::to_string),
+            implements: vec![],
+            is_abstract: false,
+            members,
+            docs: None,
+        };
+        let program = Node::Program(vec![
+            class("Outer", None, vec![
+                class("Inner", None, vec![function("run", vec![], vec![call(identifier("Other"))])]),
+                class("Other", Some("Inner"), vec![]),
+            ]),
+            function("main", vec![Parameter { name: "inner".into(), type_annotation: Type::Custom("Outer.Inner".into()) }], vec![
+                call(member(identifier("inner"), "run")),
+                call(member(member(identifier("Outer"), "Other"), "run")),
+            ]),
+        ]);
+        let index = Index::build(&program);
+        let names: Vec<&str> = index.definitions.iter().map(|definition| definition.name.as_str()).collect();
+        assert_eq!(names, vec!["Outer", "Outer.Inner", "Outer.Inner.run", "Outer.Other", "main"]);
+        // Through the base class, named from around `Other`
+        assert_eq!(index.references_to("Outer.Inner.run").count(), 2);
+        assert_eq!(index.references_to("Outer.Other").map(|reference| reference.from.as_str()).collect::<Vec<_>>(),
+            vec!["Outer.Inner.run", "main"]);
+    }
+
+    #[test]
+    fn test_constants() {
+        let program = Node::Program(vec![
+            Node::Const {
+                name: "LIMIT".to_string(),
+                type_annotation: None,
+                value: Box::new(Node::Call { callee: Box::new(identifier("square")), arguments: vec![] }),
+            },
+            function("square", vec![], vec![]),
+            function("main", vec![], vec![call(identifier("LIMIT"))]),
+        ]);
+        let index = Index::build(&program);
+        assert_eq!(index.definition("LIMIT").unwrap().kind, SymbolKind::Constant);
+        assert_eq!(index.callers("square").collect::<Vec<_>>(), vec!["LIMIT"]);
+        assert!(dead_code_lint(&program).is_empty());
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        let local = |name: &str| Node::Let { name: name.to_string(), type_annotation: None, initializer: None, is_mutable: false };
+        let unused = Node::Program(vec![
+            Node::Class {
+                name: "Account".to_string(),
+                extends: None,
+                implements: vec![],
+                is_abstract: false,
+                members: vec![local("balance"), function("close", vec![], vec![local("fee"), local("_ignored")])],
+                docs: None,
+            },
+            function("main", vec![], vec![local("total"), local("count"), call(identifier("count"))]),
+        ]);
+        assert_eq!(unused_variable_lint(&unused), vec![
+            Finding { declaration: "Account.close".to_string(), message: "variable 'fee' is never used".to_string() },
+            Finding { declaration: "main".to_string(), message: "variable 'total' is never used".to_string() },
+        ]);
+        // The first program's local `helper` is used
+        assert!(unused_variable_lint(&program()).is_empty());
+    }
+}
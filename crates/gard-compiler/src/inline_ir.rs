@@ -0,0 +1,152 @@
+//! Inline LLVM IR, so runtime and std primitives can be written in Gard
+//! files instead of in the compiler.
+//!
+//! An `llvm { "..." }` block at the top level is copied into the module as
+//! is, typically `declare`s and helper definitions. A function whose whole
+//! body is an `llvm` block is an intrinsic: the block holds the body of its
+//! definition, with parameters in scope as `%name`. Target assembly goes
+//! through IR `call ... asm "..."` expressions.
+//!
+//! All of a program's IR is assembled into one module, which the compiler
+//! parses, verifies and links before compiling anything else, so functions
+//! and blocks can refer to each other and to Gard functions they declare.
+
+use gard_ast::{Node, Parameter, Type};
+
+/// The IR spelling of a type allowed in an intrinsic's signature.
+pub fn ir_type(ty: &Type) -> Result<&'static str, String> {
+    match ty {
+        Type::Int | Type::UInt => Ok("i64"),
+        Type::Float => Ok("double"),
+        Type::Boolean => Ok("i1"),
+        Type::String => Ok("i8*"),
+        Type::UInt256 => Ok("i256"),
+        Type::Address => Ok("i160"),
+        Type::Void => Ok("void"),
+        other => Err(format!("Type {:?} can't be used in the signature of an llvm function", other)),
+    }
+}
+
+/// The IR of a function whose body is a single `llvm` block.
+pub fn intrinsic_body(body: &Node) -> Option<&str> {
+    match body.unlocated() {
+        Node::InlineIr(ir) => Some(ir),
+        Node::Block(statements) if statements.len() == 1 => match statements[0].unlocated() {
+            Node::InlineIr(ir) => Some(ir),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Collects the program's inline IR into one module, or `None` if it has
+/// none. `llvm` blocks anywhere else are errors.
+pub fn module_ir(program: &Node) -> Result<Option<String>, Vec<String>> {
+    let mut ir = Vec::new();
+    let mut errors = Vec::new();
+    match program {
+        Node::Program(nodes) => {
+            for node in nodes {
+                match node {
+                    Node::InlineIr(block) => ir.push(block.clone()),
+                    node => collect(node, &mut ir, &mut errors),
+                }
+            }
+        },
+        node => collect(node, &mut ir, &mut errors),
+    }
+
+    if !errors.is_empty() {
+        Err(errors)
+    } else if ir.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(ir.join("\n\n") + "\n"))
+    }
+}
+
+fn collect(node: &Node, ir: &mut Vec<String>, errors: &mut Vec<String>) {
+    match node {
+        Node::Function { name, params, return_type, body, .. } => match intrinsic_body(body) {
+            Some(body) => match define(name, params, return_type, body) {
+                Ok(definition) => ir.push(definition),
+                Err(e) => errors.push(e),
+            },
+            None => collect(body, ir, errors),
+        },
+        Node::InlineIr(_) => {
+            errors.push("An llvm block must be at the top level or be the whole body of a function".to_string());
+        },
+        node => {
+            for child in node.children() {
+                collect(child, ir, errors);
+            }
+        },
+    }
+}
+
+fn define(name: &str, params: &[Parameter], return_type: &Type, body: &str) -> Result<String, String> {
+    let params = params.iter()
+        .map(|param| ir_type(&param.type_annotation).map(|ty| format!("{} %{}", ty, param.name)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{} (in '{}')", e, name))?;
+    let return_type = ir_type(return_type).map_err(|e| format!("{} (in '{}')", e, name))?;
+    Ok(format!("define {} @{}({}) {{\n{}\n}}", return_type, name, params.join(", "), body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::Span;
+
+    fn function(name: &str, params: Vec<Parameter>, return_type: Type, body: Vec<Node>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params,
+            return_type,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn param(name: &str, type_annotation: Type) -> Parameter {
+        Parameter { name: name.into(), type_annotation }
+    }
+
+    fn llvm(ir: &str) -> Node {
+        Node::Located { span: Span { start: 0, end: 10 }, node: Box::new(Node::InlineIr(ir.to_string())) }
+    }
+
+    #[test]
+    fn test_module_ir() {
+        assert_eq!(module_ir(&Node::Program(vec![function("main", vec![], Type::Void, vec![])])), Ok(None));
+
+        let program = Node::Program(vec![
+            Node::InlineIr("declare i64 @llvm.ctpop.i64(i64)".to_string()),
+            function("popcount", vec![param("x", Type::Int)], Type::Int, vec![
+                llvm("%count = call i64 @llvm.ctpop.i64(i64 %x)\nret i64 %count"),
+            ]),
+        ]);
+        assert_eq!(module_ir(&program).unwrap().unwrap(), "\
+declare i64 @llvm.ctpop.i64(i64)
+
+define i64 @popcount(i64 %x) {
+%count = call i64 @llvm.ctpop.i64(i64 %x)
+ret i64 %count
+}
+");
+    }
+
+    #[test]
+    fn test_misplaced_blocks_and_signatures() {
+        let program = Node::Program(vec![
+            function("mixed", vec![], Type::Void, vec![llvm("ret void"), Node::Return(None)]),
+            function("array", vec![param("items", Type::Array(Box::new(Type::Int)))], Type::Void, vec![llvm("ret void")]),
+        ]);
+        assert_eq!(module_ir(&program).unwrap_err(), vec![
+            "An llvm block must be at the top level or be the whole body of a function".to_string(),
+            "Type Array(Int) can't be used in the signature of an llvm function (in 'array')".to_string(),
+        ]);
+    }
+}
@@ -0,0 +1,240 @@
+use gard_ast::{Node, Type};
+use std::collections::HashMap;
+
+/// Size of a pointer into wasm32 linear memory.
+pub const POINTER_SIZE: u32 = 4;
+/// Arrays are a pointer to a `u32` length followed by the elements.
+pub const ARRAY_HEADER: u32 = 4;
+
+/// How a value is laid out in linear memory when it crosses the wasm
+/// boundary. Scalars are passed directly; strings, arrays and structs are
+/// passed as a pointer to their encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbiType {
+    I64,
+    F64,
+    Bool,
+    /// Pointer to NUL-terminated UTF-8
+    String,
+    /// Pointer to `{ len: u32, elements }`, elements aligned to their type
+    Array(Box<AbiType>),
+    /// Pointer to the fields in C layout
+    Struct(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldLayout {
+    pub name: String,
+    pub ty: AbiType,
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructLayout {
+    pub name: String,
+    pub fields: Vec<FieldLayout>,
+    pub size: u32,
+    pub align: u32,
+}
+
+impl StructLayout {
+    pub fn field(&self, name: &str) -> Option<(usize, &FieldLayout)> {
+        self.fields.iter().enumerate().find(|(_, field)| field.name == name)
+    }
+}
+
+impl AbiType {
+    /// Size and alignment of the value as stored inside an array or struct.
+    pub fn size_align(&self) -> (u32, u32) {
+        match self {
+            AbiType::I64 | AbiType::F64 => (8, 8),
+            AbiType::Bool => (1, 1),
+            AbiType::String | AbiType::Array(_) | AbiType::Struct(_) => (POINTER_SIZE, POINTER_SIZE),
+        }
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, AbiType::String | AbiType::Array(_) | AbiType::Struct(_))
+    }
+}
+
+/// Offset of the first element of an array with the given element type.
+pub fn array_data_offset(element: &AbiType) -> u32 {
+    align_to(ARRAY_HEADER, element.size_align().1)
+}
+
+/// Layouts of the classes a program can pass across the wasm boundary.
+/// Methods are ignored; a class qualifies when all of its fields are typed
+/// and have boundary types themselves.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InteropTypes {
+    structs: HashMap<String, StructLayout>,
+}
+
+impl InteropTypes {
+    pub fn from_program(program: &Node) -> Result<Self, String> {
+        let nodes = match program {
+            Node::Program(nodes) => nodes,
+            _ => return Err("Expected program node".to_string()),
+        };
+
+        let classes: HashMap<&str, &Vec<Node>> = nodes.iter()
+            .filter_map(|node| match node {
+                Node::Class { name, members, .. } => Some((name.as_str(), members)),
+                _ => None,
+            })
+            .collect();
+
+        let mut types = Self::default();
+        for name in classes.keys() {
+            types.layout_class(name, &classes, &mut Vec::new());
+        }
+        Ok(types)
+    }
+
+    pub fn layout(&self, name: &str) -> Option<&StructLayout> {
+        self.structs.get(name)
+    }
+
+    pub fn layouts(&self) -> impl Iterator<Item = &StructLayout> {
+        self.structs.values()
+    }
+
+    pub fn resolve(&self, ty: &Type) -> Result<AbiType, String> {
+        match ty {
+            Type::Int | Type::UInt => Ok(AbiType::I64),
+            Type::Float | Type::Double => Ok(AbiType::F64),
+            Type::Boolean => Ok(AbiType::Bool),
+            Type::String => Ok(AbiType::String),
+            Type::Array(element) => Ok(AbiType::Array(Box::new(self.resolve(element)?))),
+            Type::Custom(name) if self.structs.contains_key(name.as_str()) => Ok(AbiType::Struct(name.to_string())),
+            other => Err(format!("Type {:?} can't be passed across the wasm boundary", other)),
+        }
+    }
+
+    /// Returns whether the class qualifies.
+    fn layout_class(&mut self, name: &str, classes: &HashMap<&str, &Vec<Node>>, visiting: &mut Vec<String>) -> bool {
+        if self.structs.contains_key(name) || visiting.iter().any(|visited| visited == name) {
+            // Recursive classes are fine: fields of class type are pointers
+            return true;
+        }
+
+        visiting.push(name.to_string());
+        let layout = self.compute_layout(name, classes, visiting);
+        visiting.pop();
+
+        match layout {
+            Some(layout) => {
+                self.structs.insert(name.to_string(), layout);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn compute_layout(&mut self, name: &str, classes: &HashMap<&str, &Vec<Node>>, visiting: &mut Vec<String>) -> Option<StructLayout> {
+        let mut fields = Vec::new();
+        let mut offset = 0;
+        let mut align = 1;
+
+        for member in classes[name] {
+            let (field, ty) = match member {
+                Node::Let { name, type_annotation, .. } => (name, type_annotation.as_ref()?),
+                _ => continue,
+            };
+            let ty = self.field_type(ty, classes, visiting)?;
+
+            let (field_size, field_align) = ty.size_align();
+            offset = align_to(offset, field_align);
+            fields.push(FieldLayout { name: field.clone(), ty, offset });
+            offset += field_size;
+            align = align.max(field_align);
+        }
+
+        Some(StructLayout {
+            name: name.to_string(),
+            fields,
+            size: align_to(offset, align),
+            align,
+        })
+    }
+
+    fn field_type(&mut self, ty: &Type, classes: &HashMap<&str, &Vec<Node>>, visiting: &mut Vec<String>) -> Option<AbiType> {
+        match ty {
+            Type::Custom(class) if classes.contains_key(class.as_str()) => {
+                self.layout_class(class, classes, visiting).then(|| AbiType::Struct(class.to_string()))
+            },
+            Type::Array(element) => Some(AbiType::Array(Box::new(self.field_type(element, classes, visiting)?))),
+            ty => self.resolve(ty).ok(),
+        }
+    }
+}
+
+fn align_to(offset: u32, align: u32) -> u32 {
+    offset.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, ty: Type) -> Node {
+        Node::Let {
+            name: name.to_string(),
+            type_annotation: Some(ty),
+            initializer: None,
+            is_mutable: true,
+        }
+    }
+
+    fn class(name: &str, members: Vec<Node>) -> Node {
+        Node::Class {
+            name: name.to_string(),
+            extends: None,
+            implements: vec![],
+            is_abstract: false,
+            members,
+            docs: None,
+        }
+    }
+
+    #[test]
+    fn test_struct_layout() {
+        let program = Node::Program(vec![
+            class("Point", vec![field("visible", Type::Boolean), field("x", Type::Int), field("label", Type::String)]),
+            class("Path", vec![field("points", Type::Array(Box::new(Type::Custom("Point".into()))))]),
+        ]);
+        let types = InteropTypes::from_program(&program).unwrap();
+
+        let point = types.layout("Point").unwrap();
+        let offsets: Vec<u32> = point.fields.iter().map(|field| field.offset).collect();
+        assert_eq!(offsets, vec![0, 8, 16]);
+        assert_eq!((point.size, point.align), (24, 8));
+
+        let path = types.layout("Path").unwrap();
+        assert_eq!(path.fields[0].ty, AbiType::Array(Box::new(AbiType::Struct("Point".to_string()))));
+        assert_eq!(path.size, POINTER_SIZE);
+    }
+
+    #[test]
+    fn test_array_data_offset() {
+        assert_eq!(array_data_offset(&AbiType::I64), 8);
+        assert_eq!(array_data_offset(&AbiType::String), 4);
+        assert_eq!(array_data_offset(&AbiType::Bool), 4);
+    }
+
+    #[test]
+    fn test_unsupported_types() {
+        let types = InteropTypes::default();
+        assert!(types.resolve(&Type::Address).is_err());
+        assert!(types.resolve(&Type::Custom("Unknown".into())).is_err());
+
+        let program = Node::Program(vec![
+            class("Account", vec![field("owner", Type::Address)]),
+            class("Wallet", vec![field("account", Type::Custom("Account".into()))]),
+        ]);
+        let types = InteropTypes::from_program(&program).unwrap();
+        assert!(types.layout("Account").is_none());
+        assert!(types.resolve(&Type::Custom("Wallet".into())).is_err());
+    }
+}
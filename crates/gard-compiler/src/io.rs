@@ -0,0 +1,122 @@
+use gard_ast::{Node, Type};
+
+/// Builtins of `std.io`, called as `io.readLine(reader)`. Readers and
+/// writers are buffered handles to a file or the standard streams, stored
+/// as an int; the checker keeps them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoBuiltin {
+    Stdin,
+    Stdout,
+    OpenReader,
+    OpenWriter,
+    ReadLine,
+    Read,
+    Write,
+    WriteLine,
+    Flush,
+    Close,
+}
+
+pub const MODULE: &str = "io";
+pub const READER: &str = "Reader";
+pub const WRITER: &str = "Writer";
+/// Either kind of handle, a union the checker declares
+pub const STREAM: &str = "Stream";
+
+fn custom(name: &str) -> Type {
+    Type::Custom(name.into())
+}
+
+impl IoBuiltin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "stdin" => Some(IoBuiltin::Stdin),
+            "stdout" => Some(IoBuiltin::Stdout),
+            "openReader" => Some(IoBuiltin::OpenReader),
+            "openWriter" => Some(IoBuiltin::OpenWriter),
+            "readLine" => Some(IoBuiltin::ReadLine),
+            "read" => Some(IoBuiltin::Read),
+            "write" => Some(IoBuiltin::Write),
+            "writeLine" => Some(IoBuiltin::WriteLine),
+            "flush" => Some(IoBuiltin::Flush),
+            "close" => Some(IoBuiltin::Close),
+            _ => None,
+        }
+    }
+
+    /// Resolves `io.write(..)` style callees.
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee.unlocated() {
+            Node::Member { object, property } => match object.unlocated() {
+                Node::Identifier(module) if module == MODULE => Self::from_name(property),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IoBuiltin::Stdin => "stdin",
+            IoBuiltin::Stdout => "stdout",
+            IoBuiltin::OpenReader => "openReader",
+            IoBuiltin::OpenWriter => "openWriter",
+            IoBuiltin::ReadLine => "readLine",
+            IoBuiltin::Read => "read",
+            IoBuiltin::Write => "write",
+            IoBuiltin::WriteLine => "writeLine",
+            IoBuiltin::Flush => "flush",
+            IoBuiltin::Close => "close",
+        }
+    }
+
+    /// `readLine` returns null at the end of the stream and `read(reader,
+    /// n)` returns fewer than n bytes there. `close` flushes a writer.
+    pub fn signature(&self) -> Type {
+        let (params, return_type) = match self {
+            IoBuiltin::Stdin => (vec![], custom(READER)),
+            IoBuiltin::Stdout => (vec![], custom(WRITER)),
+            IoBuiltin::OpenReader => (vec![Type::String], custom(READER)),
+            IoBuiltin::OpenWriter => (vec![Type::String], custom(WRITER)),
+            IoBuiltin::ReadLine => (vec![custom(READER)], Type::String),
+            IoBuiltin::Read => (vec![custom(READER), Type::Int], Type::Bytes),
+            IoBuiltin::Write => (vec![custom(WRITER), Type::Bytes], Type::Void),
+            IoBuiltin::WriteLine => (vec![custom(WRITER), Type::String], Type::Void),
+            IoBuiltin::Flush => (vec![custom(WRITER)], Type::Void),
+            IoBuiltin::Close => (vec![custom(STREAM)], Type::Void),
+        };
+        Type::Function { params, return_type: Box::new(return_type) }
+    }
+
+    /// Symbol of the native implementation in gard-vm.
+    pub fn runtime_symbol(&self) -> &'static str {
+        match self {
+            IoBuiltin::Stdin => "gard_io_stdin",
+            IoBuiltin::Stdout => "gard_io_stdout",
+            IoBuiltin::OpenReader => "gard_io_open_reader",
+            IoBuiltin::OpenWriter => "gard_io_open_writer",
+            IoBuiltin::ReadLine => "gard_io_read_line",
+            IoBuiltin::Read => "gard_io_read",
+            IoBuiltin::Write => "gard_io_write",
+            IoBuiltin::WriteLine => "gard_io_write_line",
+            IoBuiltin::Flush => "gard_io_flush",
+            IoBuiltin::Close => "gard_io_close",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_member_callee() {
+        let callee = |module: &str, name: &str| Node::Member {
+            object: Box::new(Node::Identifier(module.into())),
+            property: name.to_string(),
+        };
+        assert_eq!(IoBuiltin::from_callee(&callee("io", "readLine")), Some(IoBuiltin::ReadLine));
+        assert_eq!(IoBuiltin::from_callee(&callee("io", "seek")), None);
+        assert_eq!(IoBuiltin::from_callee(&Node::Identifier("read".into())), None);
+    }
+}
@@ -1,11 +1,136 @@
-use gard_ast::{Node, Type, BinaryOp, UnaryOp, Parameter};
+pub mod arithmetic;
+pub mod bounds;
+pub mod bytes;
+pub mod cfg;
+pub mod chain;
+pub mod cheader;
+pub mod consteval;
+pub mod datetime;
+pub mod checker;
+pub mod compdb;
+pub mod crypto;
+pub mod derive;
+pub mod destructors;
+pub mod edition;
+pub mod http;
+pub mod index;
+#[cfg(test)]
+mod execute;
+#[cfg(test)]
+mod filecheck;
+pub mod graph;
+pub mod inline_ir;
+pub mod interop;
+pub mod io;
+pub mod library;
+pub mod machine;
+pub mod macros;
+pub mod narrowing;
+pub mod nested;
+pub mod net;
+pub mod plugin;
+pub mod prebuild;
+pub mod proto;
+pub mod process;
+pub mod profile;
+pub mod refactor;
+pub mod regex;
+pub mod rename;
+pub mod solidity;
+pub mod storage;
+pub mod typescript;
+pub mod units;
+pub mod wasm;
+
+pub use library::LibraryKind;
+
+use arithmetic::ArithmeticBuiltin;
+use bytes::BytesBuiltin;
+use chain::ChainIntrinsic;
+use crypto::CryptoBuiltin;
+use datetime::DateTimeBuiltin;
+use gard_ast::{AssertionKind, Node, Type, BinaryOp, UnaryOp, FunctionModifier, Parameter, MethodSignature, SourceMap, Span, Symbol, UnionVariant, MatchCase};
+use http::HttpBuiltin;
+use interop::{AbiType, InteropTypes};
+use io::IoBuiltin;
+use library::Runtime;
+use machine::RelocationModel;
+use profile::Profile;
+use narrowing::{narrowings, right_operand_narrowings};
+use net::NetBuiltin;
+use process::ProcessBuiltin;
+use regex::RegexBuiltin;
+use inkwell::attributes::AttributeLoc;
 use inkwell::context::Context;
-use inkwell::module::Module;
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::module::{FlagBehavior, Linkage, Module};
 use inkwell::builder::Builder;
-use inkwell::values::{BasicValue, BasicValueEnum, FunctionValue, PointerValue};
-use inkwell::types::{BasicType, BasicTypeEnum, BasicMetadataTypeEnum};
-use inkwell::AddressSpace;
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple};
+use inkwell::values::{BasicMetadataValueEnum, BasicValue, BasicValueEnum, CallableValue, FunctionValue, IntValue, PointerValue, StructValue};
+use inkwell::types::{AnyTypeEnum, BasicType, BasicTypeEnum, BasicMetadataTypeEnum, FunctionType, StructType};
+use inkwell::{AddressSpace, GlobalVisibility, OptimizationLevel};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use wasm::WasmValType;
+
+/// `gard_vm::error::ErrorKind::Overflow`, raised by the `checked*` builtins.
+const OVERFLOW_ERROR_CODE: u64 = 3;
+/// `gard_vm::error::ErrorKind::DivisionByZero`
+const DIVISION_BY_ZERO_ERROR_CODE: u64 = 4;
+/// `gard_vm::error::ErrorKind::NullDereference`
+const NULL_DEREFERENCE_ERROR_CODE: u64 = 5;
+
+/// What the `build_*` functions set on their compiler.
+#[derive(Debug, Clone, Default)]
+pub struct CodegenOptions {
+    /// See `Compiler::set_overflow_checks`
+    pub overflow_checks: bool,
+    pub source_map: Option<SourceMap>,
+    /// How native code is relocated; see `machine`
+    pub relocation_model: RelocationModel,
+    /// The target's usual code model if it's `None`
+    pub code_model: Option<machine::CodeModel>,
+    /// Whether native libraries are linked with LTO, and which kind
+    pub lto: Option<machine::Lto>,
+    /// Where to record how long each stage of the build takes
+    pub profile: Option<Arc<Profile>>,
+    /// How many codegen units a native library is split into, compiled on
+    /// as many threads; see `units`. Zero counts as one.
+    pub codegen_units: usize,
+}
+
+/// What codegen needs of a class declaration.
+struct ClassInfo {
+    base: Option<String>,
+    implements: Vec<String>,
+    /// Methods, with whether each is abstract
+    methods: Vec<(MethodSignature, bool)>,
+    /// Parameters of the constructor it declares
+    constructor: Option<Vec<Parameter>>,
+}
+
+impl ClassInfo {
+    fn new(base: Option<String>, implements: Vec<String>, members: &[Node]) -> Self {
+        let mut info = ClassInfo { base, implements, methods: Vec::new(), constructor: None };
+        for member in members {
+            match member.unlocated() {
+                Node::Function { name, params, return_type, modifiers, .. } => info.methods.push((
+                    MethodSignature { name: name.clone(), params: params.clone(), return_type: return_type.clone() },
+                    modifiers.contains(&FunctionModifier::Abstract),
+                )),
+                Node::Constructor { params, .. } => info.constructor = Some(params.clone()),
+                _ => {},
+            }
+        }
+        info
+    }
+}
+
+/// The flag a `return` in a try body sets, and the slot it leaves its value
+/// in, named so no Gard variable can shadow them.
+const TRY_FLOW: &str = "try.flow";
+const TRY_RETURN: &str = "try.return";
 
 pub struct Compiler<'ctx> {
     context: &'ctx Context,
@@ -13,6 +138,29 @@ pub struct Compiler<'ctx> {
     builder: Builder<'ctx>,
     variables: HashMap<String, PointerValue<'ctx>>,
     functions: HashMap<String, FunctionValue<'ctx>>,
+    storage: HashMap<String, BasicTypeEnum<'ctx>>,
+    wasm_contract: bool,
+    interop: InteropTypes,
+    /// Methods of every interface, in the order of their itable slots
+    interfaces: HashMap<String, Vec<MethodSignature>>,
+    /// Base class, methods and constructor of every class
+    classes: HashMap<String, ClassInfo>,
+    /// Variants of every union whose variants are tags of its own, not
+    /// classes, in the order of their tag values
+    unions: HashMap<String, Vec<UnionVariant>>,
+    /// The class whose constructor is being compiled, for `super`
+    class: Option<String>,
+    /// Whether `int` arithmetic traps on overflow instead of wrapping
+    overflow_checks: bool,
+    source_map: Option<SourceMap>,
+    /// Span of the innermost located node being compiled, for trap messages
+    span: Option<Span>,
+    relocation_model: RelocationModel,
+    code_model: Option<machine::CodeModel>,
+    /// The codegen unit this compiler defines functions for, and how many
+    /// the program is split into
+    unit: usize,
+    units: usize,
 }
 
 impl<'ctx> Compiler<'ctx> {
@@ -26,14 +174,94 @@ impl<'ctx> Compiler<'ctx> {
             builder,
             variables: HashMap::new(),
             functions: HashMap::new(),
+            storage: HashMap::new(),
+            wasm_contract: false,
+            interop: InteropTypes::default(),
+            interfaces: HashMap::new(),
+            classes: HashMap::new(),
+            unions: HashMap::new(),
+            class: None,
+            overflow_checks: false,
+            source_map: None,
+            span: None,
+            relocation_model: RelocationModel::default(),
+            code_model: None,
+            unit: 0,
+            units: 1,
         }
     }
 
+    /// Makes `int` arithmetic trap on overflow, reporting where it happened
+    /// when a source map is set. `uint256` arithmetic is always checked.
+    pub fn set_overflow_checks(&mut self, enabled: bool) {
+        self.overflow_checks = enabled;
+    }
+
+    /// Source the program was parsed from, which gives runtime traps their
+    /// line numbers.
+    pub fn set_source_map(&mut self, source_map: SourceMap) {
+        self.source_map = Some(source_map);
+    }
+
+    pub fn configure(&mut self, options: &CodegenOptions) {
+        self.overflow_checks = options.overflow_checks;
+        self.source_map = options.source_map.clone();
+        self.relocation_model = options.relocation_model;
+        self.code_model = options.code_model;
+    }
+
+    /// Makes `compile` define only the functions of `unit`, out of
+    /// `units`, and declare the others; see `units`.
+    pub fn set_codegen_unit(&mut self, unit: usize, units: usize) {
+        self.unit = unit;
+        self.units = units.max(1);
+    }
+
     pub fn compile(&mut self, ast: Node) -> Result<(), String> {
+        self.interop = InteropTypes::from_program(&ast)?;
+        if let Node::Program(nodes) = &ast {
+            self.interfaces = nodes.iter()
+                .filter_map(|node| match node.unlocated() {
+                    Node::Interface { name, methods } => Some((name.clone(), methods.clone())),
+                    _ => None,
+                })
+                .collect();
+            self.classes = nodes.iter()
+                .filter_map(|node| match node.unlocated() {
+                    Node::Class { name, extends, implements, members, .. } => {
+                        Some((name.clone(), ClassInfo::new(extends.clone(), implements.clone(), members)))
+                    },
+                    _ => None,
+                })
+                .collect();
+            self.unions = nodes.iter()
+                .filter_map(|node| match node.unlocated() {
+                    Node::Union { name, variants } if variants.iter().all(|variant| !self.classes.contains_key(&variant.name)) => {
+                        Some((name.clone(), variants.clone()))
+                    },
+                    _ => None,
+                })
+                .collect();
+        }
+        if self.unit == 0 {
+            self.link_inline_ir(&ast)?;
+        }
         match ast {
             Node::Program(nodes) => {
+                let mut functions = 0;
                 for node in nodes {
-                    self.compile_node(node)?;
+                    let owner = match units::splittable(&node) {
+                        true => {
+                            functions += 1;
+                            units::owner(functions - 1, self.units)
+                        },
+                        false => 0,
+                    };
+                    if owner == self.unit {
+                        self.compile_node(node)?;
+                    } else if let Node::Function { name, params, return_type, .. } = node.into_unlocated() {
+                        self.declare_function(&name, &params, &return_type)?;
+                    }
                 }
                 Ok(())
             },
@@ -41,6 +269,265 @@ impl<'ctx> Compiler<'ctx> {
         }
     }
 
+    /// Compiles a contract for the wasm contract target. State variables live
+    /// in host storage under their names; `instantiate` writes their initial
+    /// values and then runs the constructor. See `wasm::HOST_IMPORTS` for the
+    /// host ABI.
+    pub fn compile_wasm_contract(&mut self, contract: Node) -> Result<wasm::ContractAbi, String> {
+        wasm::check_determinism(&contract).map_err(|errors| errors.join("\n"))?;
+        let abi = wasm::ContractAbi::from_contract(&contract)?;
+        self.link_inline_ir(&contract)?;
+        let members = match contract {
+            Node::Contract { members, .. } => members,
+            _ => return Err("Expected contract node".to_string()),
+        };
+
+        self.module.set_triple(&TargetTriple::create(wasm::TARGET_TRIPLE));
+        self.wasm_contract = true;
+        for import in wasm::HOST_IMPORTS {
+            let params: Vec<BasicMetadataTypeEnum> = import.params.iter()
+                .map(|ty| self.wasm_value_type(*ty).into())
+                .collect();
+            let fn_type = match import.result {
+                Some(ty) => self.wasm_value_type(ty).fn_type(&params, false),
+                None => self.context.void_type().fn_type(&params, false),
+            };
+            self.declare_wasm_import(wasm::HOST_MODULE, import.name, import.name, fn_type);
+        }
+        for import in &abi.imports {
+            let fn_type = self.wasm_import_type(&import.params, &import.return_type)?;
+            self.declare_wasm_import(&import.module, &import.name, &import.name, fn_type);
+        }
+
+        // State variables must be known before any function body reads them
+        let mut initializers = Vec::new();
+        let mut rest = Vec::new();
+        for member in members {
+            let member = match member {
+                Node::StorageSlot { declaration, .. } => *declaration,
+                member => member,
+            };
+            match member {
+                Node::Let { name, type_annotation: Some(ty), initializer, .. } => {
+                    self.storage.insert(name.clone(), self.get_llvm_type(&ty)?);
+                    if let Some(initializer) = initializer {
+                        initializers.push((name, *initializer));
+                    }
+                },
+                Node::Let { name, .. } => {
+                    return Err(format!("Storage variable '{}' needs a type annotation", name));
+                },
+                member => rest.push(member),
+            }
+        }
+
+        let mut constructor = None;
+        for member in rest {
+            match member {
+                Node::Constructor { body, .. } => constructor = Some(*body),
+                Node::WasmExport { declaration, .. } => {
+                    self.compile_node(*declaration)?;
+                },
+                function @ Node::Function { .. } => {
+                    self.compile_node(function)?;
+                },
+                _ => {},
+            }
+        }
+
+        let instantiate = self.module.add_function(
+            wasm::INSTANTIATE_EXPORT,
+            self.context.void_type().fn_type(&[], false),
+            None
+        );
+        self.builder.position_at_end(self.context.append_basic_block(instantiate, "entry"));
+        for (name, initializer) in initializers {
+            let value = self.compile_node(initializer)?;
+            self.compile_storage_write(&name, value)?;
+        }
+        if let Some(body) = constructor {
+            self.compile_node(body)?;
+        }
+        self.builder.build_return(None);
+
+        for entry in &abi.entry_points {
+            let function = self.module.get_function(&entry.function)
+                .ok_or_else(|| format!("Exported function '{}' was not compiled", entry.function))?;
+            function.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_string_attribute("wasm-export-name", &entry.export)
+            );
+        }
+
+        Ok(abi)
+    }
+
+    /// Parses the program's `llvm` blocks and intrinsics and links them into
+    /// the module. See `inline_ir` for how they fit together.
+    fn link_inline_ir(&mut self, program: &Node) -> Result<(), String> {
+        let ir = match inline_ir::module_ir(program).map_err(|errors| errors.join("\n"))? {
+            Some(ir) => ir,
+            None => return Ok(()),
+        };
+        let buffer = MemoryBuffer::create_from_memory_range_copy(ir.as_bytes(), "llvm");
+        let module = self.context.create_module_from_ir(buffer)
+            .map_err(|e| format!("Invalid llvm IR: {}", e))?;
+        module.verify().map_err(|e| format!("Invalid llvm IR: {}", e))?;
+        self.module.link_in_module(module)
+            .map_err(|e| format!("Failed to link llvm IR: {}", e))
+    }
+
+    /// Writes the module as a wasm32 object file. Link it with
+    /// `wasm-ld --no-entry --export-dynamic` to get the deployable module.
+    pub fn write_wasm_object(&self, path: &Path) -> Result<(), String> {
+        Target::initialize_webassembly(&InitializationConfig::default());
+        let triple = TargetTriple::create(wasm::TARGET_TRIPLE);
+        let target = Target::from_triple(&triple).map_err(|e| e.to_string())?;
+        let machine = target
+            .create_target_machine(&triple, "generic", "", OptimizationLevel::Default, RelocMode::Static, CodeModel::Default)
+            .ok_or_else(|| "Failed to create wasm32 target machine".to_string())?;
+        machine.write_to_file(&self.module, FileType::Object, path)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Writes the module as an object file for the host, in the relocation
+    /// and code models it's configured with, which `ar` or the C compiler
+    /// turn into a library.
+    pub fn write_native_object(&self, path: &Path) -> Result<(), String> {
+        let machine = self.native_target_machine()?;
+        machine.write_to_file(&self.module, FileType::Object, path)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Writes the module as LLVM bitcode for the host, leaving optimization
+    /// and code generation to link time, for LTO.
+    pub fn write_native_bitcode(&self, path: &Path) -> Result<(), String> {
+        self.native_target_machine()?;
+        match self.module.write_bitcode_to_path(path) {
+            true => Ok(()),
+            false => Err(format!("Failed to write {}", path.display())),
+        }
+    }
+
+    /// The host's target machine, with the module set up for it.
+    fn native_target_machine(&self) -> Result<TargetMachine, String> {
+        Target::initialize_native(&InitializationConfig::default())?;
+        let triple = TargetMachine::get_default_triple();
+        let target = Target::from_triple(&triple).map_err(|e| e.to_string())?;
+        let reloc_mode = match self.relocation_model {
+            RelocationModel::Static => RelocMode::Static,
+            RelocationModel::Pic | RelocationModel::Pie => RelocMode::PIC,
+        };
+        let code_model = match self.code_model {
+            None => CodeModel::Default,
+            Some(machine::CodeModel::Small) => CodeModel::Small,
+            Some(machine::CodeModel::Kernel) => CodeModel::Kernel,
+            Some(machine::CodeModel::Medium) => CodeModel::Medium,
+            Some(machine::CodeModel::Large) => CodeModel::Large,
+        };
+        let machine = target
+            .create_target_machine(&triple, "generic", "", OptimizationLevel::Default, reloc_mode, code_model)
+            .ok_or_else(|| format!("Failed to create {} target machine", triple.as_str().to_string_lossy()))?;
+        // The flags clang's -fPIC and -fPIE set. With "PIE Level", LLVM
+        // takes the module's own symbols to be defined in the executable.
+        // Nothing is linked into the module after this, so how they'd merge
+        // doesn't matter.
+        let level = self.context.i32_type().const_int(2, false);
+        if self.relocation_model != RelocationModel::Static {
+            self.module.add_basic_value_flag("PIC Level", FlagBehavior::Override, level);
+        }
+        if self.relocation_model == RelocationModel::Pie {
+            self.module.add_basic_value_flag("PIE Level", FlagBehavior::Override, level);
+        }
+        self.module.set_triple(&triple);
+        self.module.set_data_layout(&machine.get_target_data().get_data_layout());
+        Ok(machine)
+    }
+
+    /// Exports a function from a plain (non-contract) wasm module under its
+    /// own name or the name given to `@WasmExport`.
+    fn compile_wasm_export(&mut self, export_name: Option<String>, declaration: Node) -> Result<BasicValueEnum<'ctx>, String> {
+        let name = match &declaration {
+            Node::Function { name, .. } => name.clone(),
+            _ => return Err("@WasmExport must be applied to a function".to_string()),
+        };
+        let value = self.compile_node(declaration)?;
+
+        let function = self.module.get_function(&name)
+            .ok_or_else(|| format!("Exported function '{}' was not compiled", name))?;
+        function.add_attribute(
+            AttributeLoc::Function,
+            self.context.create_string_attribute("wasm-export-name", &export_name.unwrap_or(name))
+        );
+        Ok(value)
+    }
+
+    fn wasm_import_type(&self, params: &[Parameter], return_type: &Type) -> Result<FunctionType<'ctx>, String> {
+        let params: Vec<BasicMetadataTypeEnum> = params.iter()
+            .map(|param| self.get_llvm_type(&param.type_annotation).map(Into::into))
+            .collect::<Result<_, _>>()?;
+        Ok(match return_type {
+            Type::Void => self.context.void_type().fn_type(&params, false),
+            ty => self.get_llvm_type(ty)?.fn_type(&params, false),
+        })
+    }
+
+    fn wasm_value_type(&self, ty: WasmValType) -> inkwell::types::IntType<'ctx> {
+        match ty {
+            WasmValType::I32 => self.context.i32_type(),
+            WasmValType::I64 => self.context.i64_type(),
+        }
+    }
+
+    fn declare_wasm_import(&mut self, module: &str, import_name: &str, symbol: &str, fn_type: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        let function = self.module.add_function(symbol, fn_type, Some(Linkage::External));
+        function.add_attribute(AttributeLoc::Function, self.context.create_string_attribute("wasm-import-module", module));
+        function.add_attribute(AttributeLoc::Function, self.context.create_string_attribute("wasm-import-name", import_name));
+        function
+    }
+
+    /// wasm32 pointers are passed to the host as i32 offsets into linear memory.
+    fn wasm_pointer(&mut self, pointer: PointerValue<'ctx>) -> BasicMetadataValueEnum<'ctx> {
+        self.builder.build_ptr_to_int(pointer, self.context.i32_type(), "wasm.ptr").into()
+    }
+
+    fn compile_storage_read(&mut self, name: &str, ty: BasicTypeEnum<'ctx>) -> Result<BasicValueEnum<'ctx>, String> {
+        let read = self.module.get_function("storage_read")
+            .ok_or_else(|| format!("Storage variable '{}' is only readable on the wasm contract target", name))?;
+        let key = self.builder.build_global_string_ptr(name, "storage.key").as_pointer_value();
+        let slot = self.builder.build_alloca(ty, name);
+        // Keys that were never written read as zero
+        self.builder.build_store(slot, ty.const_zero());
+        let size = ty.size_of().ok_or_else(|| format!("Storage variable '{}' has no fixed size", name))?;
+
+        let arguments = [
+            self.wasm_pointer(key),
+            self.context.i32_type().const_int(name.len() as u64, false).into(),
+            self.wasm_pointer(slot),
+            self.builder.build_int_truncate(size, self.context.i32_type(), "storage.size").into(),
+        ];
+        self.builder.build_call(read, &arguments, "storage.read");
+        Ok(self.builder.build_load(slot, name))
+    }
+
+    fn compile_storage_write(&mut self, name: &str, value: BasicValueEnum<'ctx>) -> Result<(), String> {
+        let write = self.module.get_function("storage_write")
+            .ok_or_else(|| format!("Storage variable '{}' is only writable on the wasm contract target", name))?;
+        let key = self.builder.build_global_string_ptr(name, "storage.key").as_pointer_value();
+        let slot = self.builder.build_alloca(value.get_type(), name);
+        self.builder.build_store(slot, value);
+        let size = value.get_type().size_of().ok_or_else(|| format!("Storage variable '{}' has no fixed size", name))?;
+
+        let arguments = [
+            self.wasm_pointer(key),
+            self.context.i32_type().const_int(name.len() as u64, false).into(),
+            self.wasm_pointer(slot),
+            self.builder.build_int_truncate(size, self.context.i32_type(), "storage.size").into(),
+        ];
+        self.builder.build_call(write, &arguments, "storage.write");
+        Ok(())
+    }
+
     fn compile_node(&mut self, node: Node) -> Result<BasicValueEnum<'ctx>, String> {
         match node {
             Node::Function { name, params, return_type, body, .. } => {
@@ -61,18 +548,86 @@ impl<'ctx> Compiler<'ctx> {
             Node::While { condition, body } => {
                 self.compile_while(*condition, *body)
             },
+            Node::Foreach { item, collection, body } => self.compile_foreach(item, *collection, *body),
             Node::Return(value) => {
                 self.compile_return(value.map(|v| *v))
             },
+            Node::WasmExport { export_name, declaration } => {
+                self.compile_wasm_export(export_name, *declaration)
+            },
+            Node::WasmImport { module, name, params, return_type } => {
+                let fn_type = self.wasm_import_type(&params, &return_type)?;
+                let function = self.declare_wasm_import(&module, &name, &name, fn_type);
+                Ok(function.as_global_value().as_basic_value_enum())
+            },
+            Node::Assertion { kind, condition, message } => {
+                self.compile_assertion(kind, *condition, message.map(|m| *m))
+            },
+            Node::Try { body, catch_clauses, finally } => {
+                self.compile_try_catch(*body, catch_clauses, finally.map(|f| *f))
+            },
             Node::Block(statements) => {
                 self.compile_block(statements)
             },
+            Node::Located { node, span } => {
+                let outer = self.span.replace(span);
+                let result = self.compile_node(*node);
+                self.span = outer;
+                result
+            },
+            Node::InlineIr(_) => {
+                // Already linked in by `link_inline_ir`
+                Ok(self.context.i64_type().const_int(0, false).as_basic_value_enum())
+            },
+            Node::Const { .. } => {
+                // Folded into its uses by `consteval`
+                Ok(self.context.i64_type().const_int(0, false).as_basic_value_enum())
+            },
+            Node::Union { .. } | Node::Interface { .. } => {
+                // The types of unions and interfaces are declared where
+                // they're used
+                Ok(self.context.i64_type().const_int(0, false).as_basic_value_enum())
+            },
+            Node::Unary { operator: UnaryOp::BitNot, operand } => match self.compile_node(*operand)? {
+                BasicValueEnum::IntValue(value) => Ok(self.builder.build_not(value, "nottmp").into()),
+                _ => Err("Operator ~ expects an integer operand".to_string()),
+            },
+            Node::Unary { operator: operator @ (UnaryOp::Increment | UnaryOp::Decrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement), operand } => {
+                self.compile_increment(operator, *operand)
+            },
+            Node::Member { object, property } => match ChainIntrinsic::from_member(&object, &property) {
+                Some(intrinsic) => self.compile_chain_call(intrinsic, Vec::new()),
+                None => self.compile_member(*object, property),
+            },
+            Node::Index { object, index, checked } => self.compile_index(*object, *index, checked),
+            Node::TypeTest { value, target } => self.compile_type_test(*value, target, false),
+            Node::SafeCast { value, target } => self.compile_type_test(*value, target, true),
+            Node::Class { name, members, .. } => {
+                // Only classes with a linear-memory layout are supported so far
+                let struct_type = self.get_struct_type(&name)?;
+                let constructor = members.into_iter().find_map(|member| match member.into_unlocated() {
+                    Node::Constructor { params, body } => Some((params, *body)),
+                    _ => None,
+                });
+                match constructor {
+                    Some((params, body)) => self.compile_constructor(&name, params, body)?,
+                    // Runs the base constructor for a class without its own
+                    None if self.has_constructor(&name) => self.compile_constructor(&name, Vec::new(), Node::Block(Vec::new()))?,
+                    None => {},
+                }
+                Ok(struct_type.ptr_type(AddressSpace::default()).const_null().as_basic_value_enum())
+            },
+            Node::Match { value, cases } => self.compile_match(*value, cases),
+            Node::This => self.compile_identifier("this".to_string()),
             Node::Identifier(name) => {
-                self.compile_identifier(name)
+                self.compile_identifier(name.to_string())
             },
             Node::IntLiteral(value) => {
                 Ok(self.context.i64_type().const_int(value as u64, false).as_basic_value_enum())
             },
+            Node::UInt256Literal(literal) => {
+                self.compile_uint256_literal(literal)
+            },
             Node::StringLiteral(value) => {
                 self.compile_string_literal(value)
             },
@@ -95,14 +650,19 @@ impl<'ctx> Compiler<'ctx> {
     fn compile_function(&mut self, name: String, params: Vec<Parameter>, return_type: Type, body: Node) 
         -> Result<BasicValueEnum<'ctx>, String> 
     {
-        let fn_type = match self.get_llvm_type(&return_type)? {
-            BasicTypeEnum::IntType(t) => t.fn_type(&[], false),
-            BasicTypeEnum::FloatType(t) => t.fn_type(&[], false),
-            BasicTypeEnum::PointerType(t) => t.fn_type(&[], false),
-            _ => return Err("Unsupported return type".to_string()),
-        };
+        if inline_ir::intrinsic_body(&body).is_some() {
+            let function = self.module.get_function(&name)
+                .ok_or_else(|| format!("Intrinsic '{}' was not linked", name))?;
+            self.functions.insert(name, function);
+            return Ok(function.as_global_value().as_basic_value_enum());
+        }
 
-        let function = self.module.add_function(&name, fn_type, None);
+        let fn_type = self.function_type(&params, &return_type)?;
+        let function = match self.module.get_function(&name) {
+            // Declared by inline IR that calls it
+            Some(declared) if declared.count_basic_blocks() == 0 => declared,
+            _ => self.module.add_function(&name, fn_type, None),
+        };
         let basic_block = self.context.append_basic_block(function, "entry");
         self.builder.position_at_end(basic_block);
 
@@ -112,16 +672,46 @@ impl<'ctx> Compiler<'ctx> {
                 .ok_or_else(|| format!("Failed to get parameter {}", i))?;
             let alloca = self.builder.build_alloca(param_value.get_type(), &param.name);
             self.builder.build_store(alloca, param_value);
-            self.variables.insert(param.name.clone(), alloca);
+            self.variables.insert(param.name.to_string(), alloca);
         }
 
         // Compile function body
         let body_value = self.compile_node(body)?;
-        self.builder.build_return(Some(&body_value));
+        if return_type == Type::Void {
+            self.builder.build_return(None);
+        } else {
+            self.builder.build_return(Some(&body_value));
+        }
 
         Ok(function.as_global_value().as_basic_value_enum())
     }
 
+    fn function_type(&self, params: &[Parameter], return_type: &Type) -> Result<FunctionType<'ctx>, String> {
+        let param_types: Vec<BasicMetadataTypeEnum> = params
+            .iter()
+            .map(|p| self.get_llvm_type(&p.type_annotation).map(Into::into))
+            .collect::<Result<Vec<_>, _>>()?;
+        if *return_type == Type::Void {
+            return Ok(self.context.void_type().fn_type(&param_types, false));
+        }
+        match self.get_llvm_type(return_type)? {
+            BasicTypeEnum::IntType(t) => Ok(t.fn_type(&param_types, false)),
+            BasicTypeEnum::FloatType(t) => Ok(t.fn_type(&param_types, false)),
+            BasicTypeEnum::PointerType(t) => Ok(t.fn_type(&param_types, false)),
+            _ => Err("Unsupported return type".to_string()),
+        }
+    }
+
+    /// Declares a function that another codegen unit defines.
+    fn declare_function(&mut self, name: &str, params: &[Parameter], return_type: &Type) -> Result<(), String> {
+        let function = match self.module.get_function(name) {
+            Some(function) => function,
+            None => self.module.add_function(name, self.function_type(params, return_type)?, Some(Linkage::External)),
+        };
+        self.functions.insert(name.to_string(), function);
+        Ok(())
+    }
+
     fn compile_let(&mut self, name: String, type_annotation: Option<Type>, initializer: Option<Box<Node>>) 
         -> Result<BasicValueEnum<'ctx>, String> 
     {
@@ -141,6 +731,7 @@ impl<'ctx> Compiler<'ctx> {
 
         if let Some(init) = initializer {
             let init_val = self.compile_node(*init)?;
+            let init_val = self.coerce(init_val, var_type)?;
             self.builder.build_store(alloca, init_val);
         }
 
@@ -150,14 +741,44 @@ impl<'ctx> Compiler<'ctx> {
     fn compile_binary_op(&mut self, left: Node, operator: BinaryOp, right: Node) 
         -> Result<BasicValueEnum<'ctx>, String> 
     {
+        if matches!(operator, BinaryOp::And | BinaryOp::Or) {
+            return self.compile_logical_op(left, operator, right);
+        }
         let lhs = self.compile_node(left)?;
         let rhs = self.compile_node(right)?;
 
+        // uint256 and address values use unsigned, overflow-checked
+        // arithmetic, whichever side they're on
+        let wide = |value: &BasicValueEnum| {
+            matches!(value, BasicValueEnum::IntValue(value) if matches!(value.get_type().get_bit_width(), 160 | 256))
+        };
+        if wide(&lhs) || wide(&rhs) {
+            return match (lhs, rhs) {
+                (BasicValueEnum::IntValue(lhs), BasicValueEnum::IntValue(rhs)) => self.compile_checked_unsigned_op(lhs, operator, rhs),
+                _ => Err(format!("Operator {:?} expects integer operands", operator)),
+            };
+        }
+
+        if matches!(operator, BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::Shl | BinaryOp::Shr) {
+            return self.compile_bitwise_op(lhs.into_int_value(), operator, rhs.into_int_value(), true);
+        }
+
+        if self.overflow_checks && matches!(operator, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul) {
+            let (value, overflow) = self.build_overflowing_op(lhs.into_int_value(), &operator, rhs.into_int_value(), true)?;
+            self.build_located_trap(overflow, "overflow", "Integer overflow")?;
+            return Ok(value);
+        }
+
+        if matches!(operator, BinaryOp::Div | BinaryOp::Mod) {
+            self.build_division_check(rhs.into_int_value())?;
+        }
+
         match operator {
             BinaryOp::Add => Ok(self.builder.build_int_add(lhs.into_int_value(), rhs.into_int_value(), "addtmp").into()),
             BinaryOp::Sub => Ok(self.builder.build_int_sub(lhs.into_int_value(), rhs.into_int_value(), "subtmp").into()),
             BinaryOp::Mul => Ok(self.builder.build_int_mul(lhs.into_int_value(), rhs.into_int_value(), "multmp").into()),
             BinaryOp::Div => Ok(self.builder.build_int_signed_div(lhs.into_int_value(), rhs.into_int_value(), "divtmp").into()),
+            BinaryOp::Mod => Ok(self.builder.build_int_signed_rem(lhs.into_int_value(), rhs.into_int_value(), "remtmp").into()),
             BinaryOp::Eq => Ok(self.builder.build_int_compare(inkwell::IntPredicate::EQ, lhs.into_int_value(), rhs.into_int_value(), "eqtmp").into()),
             BinaryOp::NotEq => Ok(self.builder.build_int_compare(inkwell::IntPredicate::NE, lhs.into_int_value(), rhs.into_int_value(), "netmp").into()),
             BinaryOp::Lt => Ok(self.builder.build_int_compare(inkwell::IntPredicate::SLT, lhs.into_int_value(), rhs.into_int_value(), "lttmp").into()),
@@ -168,9 +789,270 @@ impl<'ctx> Compiler<'ctx> {
         }
     }
 
+    /// `&&` and `||`, which only evaluate the right side when the left side
+    /// doesn't already decide the result. What the left side narrows holds
+    /// on the right.
+    fn compile_logical_op(&mut self, left: Node, operator: BinaryOp, right: Node) -> Result<BasicValueEnum<'ctx>, String> {
+        let narrowed = right_operand_narrowings(&left, &operator);
+        let lhs = self.compile_node(left)?.into_int_value();
+        let left_end = self.builder.get_insert_block().unwrap();
+        let function = left_end.get_parent().unwrap();
+        let right_block = self.context.append_basic_block(function, "logic.rhs");
+        let merge_block = self.context.append_basic_block(function, "logic.end");
+        // The result when the left side decides it
+        let decided = match operator {
+            BinaryOp::And => {
+                self.builder.build_conditional_branch(lhs, right_block, merge_block);
+                self.context.bool_type().const_zero()
+            },
+            _ => {
+                self.builder.build_conditional_branch(lhs, merge_block, right_block);
+                self.context.bool_type().const_all_ones()
+            },
+        };
+
+        self.builder.position_at_end(right_block);
+        let replaced = self.narrow(narrowed)?;
+        let rhs = self.compile_node(right)?.into_int_value();
+        self.restore(replaced);
+        let right_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(merge_block);
+
+        self.builder.position_at_end(merge_block);
+        let phi = self.builder.build_phi(self.context.bool_type(), "logic_result");
+        phi.add_incoming(&[(&decided, left_end), (&rhs, right_end)]);
+        Ok(phi.as_basic_value())
+    }
+
+    /// Both operands are widened to the wider of their types first, so an
+    /// address meets a uint256 as one.
+    fn compile_checked_unsigned_op(&mut self, lhs: IntValue<'ctx>, operator: BinaryOp, rhs: IntValue<'ctx>)
+        -> Result<BasicValueEnum<'ctx>, String>
+    {
+        let width = lhs.get_type().get_bit_width().max(rhs.get_type().get_bit_width());
+        let int_type = self.context.custom_width_int_type(width);
+        let lhs = if lhs.get_type().get_bit_width() < width {
+            self.builder.build_int_z_extend(lhs, int_type, "widen")
+        } else {
+            lhs
+        };
+        let rhs = if rhs.get_type().get_bit_width() < width {
+            self.builder.build_int_z_extend(rhs, int_type, "widen")
+        } else {
+            rhs
+        };
+
+        match operator {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                let (value, overflow) = self.build_overflowing_op(lhs, &operator, rhs, false)?;
+                self.build_located_trap(overflow, "overflow", "Integer overflow")?;
+                Ok(value)
+            },
+            BinaryOp::Div | BinaryOp::Mod => {
+                self.build_division_check(rhs)?;
+
+                Ok(if operator == BinaryOp::Div {
+                    self.builder.build_int_unsigned_div(lhs, rhs, "divtmp").into()
+                } else {
+                    self.builder.build_int_unsigned_rem(lhs, rhs, "remtmp").into()
+                })
+            },
+            BinaryOp::Eq => Ok(self.builder.build_int_compare(inkwell::IntPredicate::EQ, lhs, rhs, "eqtmp").into()),
+            BinaryOp::NotEq => Ok(self.builder.build_int_compare(inkwell::IntPredicate::NE, lhs, rhs, "netmp").into()),
+            BinaryOp::Lt => Ok(self.builder.build_int_compare(inkwell::IntPredicate::ULT, lhs, rhs, "lttmp").into()),
+            BinaryOp::LtEq => Ok(self.builder.build_int_compare(inkwell::IntPredicate::ULE, lhs, rhs, "letmp").into()),
+            BinaryOp::Gt => Ok(self.builder.build_int_compare(inkwell::IntPredicate::UGT, lhs, rhs, "gttmp").into()),
+            BinaryOp::GtEq => Ok(self.builder.build_int_compare(inkwell::IntPredicate::UGE, lhs, rhs, "getmp").into()),
+            BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::Shl | BinaryOp::Shr => {
+                self.compile_bitwise_op(lhs, operator, rhs, false)
+            },
+            _ => Err(format!("Unsupported binary operator for unsigned integers: {:?}", operator)),
+        }
+    }
+
+    /// `&`, `|`, `^`, `<<` and `>>`, which is arithmetic on signed values.
+    /// Shifting by the value's width or more overflows: it traps where
+    /// arithmetic is checked, and otherwise the amount wraps around the
+    /// width, as in the interpreter.
+    fn compile_bitwise_op(&mut self, lhs: IntValue<'ctx>, operator: BinaryOp, rhs: IntValue<'ctx>, signed: bool)
+        -> Result<BasicValueEnum<'ctx>, String>
+    {
+        let int_type = lhs.get_type();
+        let rhs = if rhs.get_type().get_bit_width() < int_type.get_bit_width() {
+            self.builder.build_int_z_extend(rhs, int_type, "widen")
+        } else {
+            rhs
+        };
+        let value = match operator {
+            BinaryOp::BitAnd => self.builder.build_and(lhs, rhs, "andtmp"),
+            BinaryOp::BitOr => self.builder.build_or(lhs, rhs, "ortmp"),
+            BinaryOp::BitXor => self.builder.build_xor(lhs, rhs, "xortmp"),
+            BinaryOp::Shl | BinaryOp::Shr => {
+                let width = int_type.get_bit_width() as u64;
+                let amount = if !signed || self.overflow_checks {
+                    // Unsigned, so negative amounts are out of range too
+                    let out_of_range = self.builder.build_int_compare(inkwell::IntPredicate::UGE, rhs, rhs.get_type().const_int(width, false), "shift.range");
+                    self.build_located_trap(out_of_range, "overflow", "Integer overflow")?;
+                    self.builder.build_int_cast(rhs, int_type, "shift.amount")
+                } else {
+                    let amount = self.builder.build_int_cast(rhs, int_type, "shift.amount");
+                    self.builder.build_and(amount, int_type.const_int(width - 1, false), "shift.amount")
+                };
+                match operator {
+                    BinaryOp::Shl => self.builder.build_left_shift(lhs, amount, "shltmp"),
+                    _ => self.builder.build_right_shift(lhs, amount, signed, "shrtmp"),
+                }
+            },
+            _ => return Err(format!("{:?} is not a bitwise operator", operator)),
+        };
+        Ok(value.into())
+    }
+
+    /// Computes an add, sub or mul with the `llvm.*.with.overflow` intrinsics,
+    /// returning the wrapped result and whether it overflowed.
+    fn build_overflowing_op(&mut self, lhs: IntValue<'ctx>, operator: &BinaryOp, rhs: IntValue<'ctx>, signed: bool)
+        -> Result<(BasicValueEnum<'ctx>, IntValue<'ctx>), String>
+    {
+        let int_type = lhs.get_type();
+        let (intrinsic, label) = match operator {
+            BinaryOp::Add => ("add", "addtmp"),
+            BinaryOp::Sub => ("sub", "subtmp"),
+            BinaryOp::Mul => ("mul", "multmp"),
+            _ => return Err(format!("No overflow intrinsic for {:?}", operator)),
+        };
+        let sign = if signed { "s" } else { "u" };
+        let name = format!("llvm.{}{}.with.overflow.i{}", sign, intrinsic, int_type.get_bit_width());
+        let function = self.module.get_function(&name).unwrap_or_else(|| {
+            let result_type = self.context.struct_type(
+                &[int_type.into(), self.context.bool_type().into()],
+                false
+            );
+            self.module.add_function(&name, result_type.fn_type(&[int_type.into(), int_type.into()], false), None)
+        });
+
+        let result = self.builder
+            .build_call(function, &[lhs.into(), rhs.into()], label)
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| "Invalid call result".to_string())?
+            .into_struct_value();
+        let value = self.builder.build_extract_value(result, 0, label)
+            .ok_or_else(|| "Invalid overflow intrinsic result".to_string())?;
+        let overflow = self.builder.build_extract_value(result, 1, "overflow")
+            .ok_or_else(|| "Invalid overflow intrinsic result".to_string())?
+            .into_int_value();
+        Ok((value, overflow))
+    }
+
+    /// Traps when `condition` holds: natively through `gard_trap` in gard-vm,
+    /// which prints what happened and where before aborting, and through the
+    /// host's `abort` in a wasm contract.
+    fn build_located_trap(&mut self, condition: IntValue<'ctx>, label: &str, description: &str) -> Result<(), String> {
+        let function = self.builder.get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| "Runtime check outside of a function".to_string())?;
+        let trap_block = self.context.append_basic_block(function, label);
+        let continue_block = self.context.append_basic_block(function, &format!("{}.ok", label));
+        self.builder.build_conditional_branch(condition, trap_block, continue_block);
+
+        self.builder.position_at_end(trap_block);
+        let message = self.compile_string_literal(self.located_message(description))?;
+        if self.wasm_contract {
+            let abort = self.module.get_function("abort")
+                .ok_or_else(|| "Missing host import 'abort'".to_string())?;
+            let message = self.wasm_pointer(message.into_pointer_value());
+            self.builder.build_call(abort, &[message], "abort");
+        } else {
+            let trap = self.module.get_function("gard_trap").unwrap_or_else(|| {
+                let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+                self.module.add_function("gard_trap", self.context.void_type().fn_type(&[byte_ptr.into()], false), None)
+            });
+            self.builder.build_call(trap, &[message.into()], "trap");
+        }
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(continue_block);
+        Ok(())
+    }
+
+    /// `description` with the location of the statement being compiled.
+    fn located_message(&self, description: &str) -> String {
+        match (&self.source_map, self.span) {
+            (Some(source_map), Some(span)) => {
+                let location = source_map.span_location(span);
+                format!("{} at {}:{}:{}", description, source_map.file, location.line, location.column)
+            },
+            _ => description.to_string(),
+        }
+    }
+
+    /// `wrapping*` builtins compile to plain LLVM arithmetic, which wraps;
+    /// `checked*` ones raise a catchable overflow error.
+    fn compile_arithmetic_call(&mut self, builtin: ArithmeticBuiltin, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        let [left, right] = <[Node; 2]>::try_from(arguments)
+            .map_err(|arguments| format!("{}() expects 2 arguments, found {}", builtin.name(), arguments.len()))?;
+        let lhs = self.compile_node(left)?.into_int_value();
+        let rhs = self.compile_node(right)?.into_int_value();
+        let rhs = if rhs.get_type().get_bit_width() < lhs.get_type().get_bit_width() {
+            self.builder.build_int_z_extend(rhs, lhs.get_type(), "widen")
+        } else {
+            rhs
+        };
+        let signed = !matches!(lhs.get_type().get_bit_width(), 160 | 256);
+        let (value, overflow) = self.build_overflowing_op(lhs, &builtin.operator(), rhs, signed)?;
+        if !builtin.is_wrapping() {
+            self.build_raise_if(overflow, "checked", OVERFLOW_ERROR_CODE, "Integer overflow")?;
+        }
+        Ok(value)
+    }
+
+    /// Branches to a block raising a catchable error with the current
+    /// location when `condition` holds, and continues building in the
+    /// fall-through block.
+    fn build_raise_if(&mut self, condition: IntValue<'ctx>, label: &str, code: u64, description: &str) -> Result<(), String> {
+        let function = self.builder.get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| "Runtime check outside of a function".to_string())?;
+        let fail_block = self.context.append_basic_block(function, label);
+        let continue_block = self.context.append_basic_block(function, &format!("{}.ok", label));
+        self.builder.build_conditional_branch(condition, fail_block, continue_block);
+
+        self.builder.position_at_end(fail_block);
+        let message = self.compile_string_literal(self.located_message(description))?;
+        self.build_raise(code, message)?;
+
+        self.builder.position_at_end(continue_block);
+        Ok(())
+    }
+
+    /// Raises a division-by-zero error when `divisor` is zero, where LLVM's
+    /// division would be undefined.
+    fn build_division_check(&mut self, divisor: IntValue<'ctx>) -> Result<(), String> {
+        let is_zero = self.builder.build_int_compare(inkwell::IntPredicate::EQ, divisor, divisor.get_type().const_zero(), "divzero");
+        self.build_raise_if(is_zero, "divzero", DIVISION_BY_ZERO_ERROR_CODE, "Division by zero")
+    }
+
+    /// Raises a null-dereference error when `pointer` is null.
+    fn build_null_check(&mut self, pointer: PointerValue<'ctx>, description: &str) -> Result<(), String> {
+        let is_null = self.builder.build_is_null(pointer, "isnull");
+        self.build_raise_if(is_null, "null", NULL_DEREFERENCE_ERROR_CODE, description)
+    }
+
+    fn compile_uint256_literal(&mut self, literal: String) -> Result<BasicValueEnum<'ctx>, String> {
+        let mut words = checker::parse_uint256_literal(&literal)?.to_u64_digits();
+        words.resize(4, 0);
+        Ok(self.context.custom_width_int_type(256)
+            .const_int_arbitrary_precision(&words)
+            .as_basic_value_enum())
+    }
+
     fn compile_identifier(&mut self, name: String) -> Result<BasicValueEnum<'ctx>, String> {
         if let Some(var) = self.variables.get(&name) {
             Ok(self.builder.build_load(*var, &name))
+        } else if let Some(ty) = self.storage.get(&name).copied() {
+            self.compile_storage_read(&name, ty)
+        } else if self.tag(&name).is_some() {
+            self.compile_variant(&name, Vec::new())
         } else {
             Err(format!("Undefined variable: {}", name))
         }
@@ -193,14 +1075,69 @@ impl<'ctx> Compiler<'ctx> {
     }
 
     fn compile_call(&mut self, callee: Node, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
-        let callee_value = self.compile_node(callee)?;
+        if let Some(builtin) = CryptoBuiltin::from_callee(&callee) {
+            return self.compile_crypto_call(builtin, arguments);
+        }
+        if let Some(intrinsic) = ChainIntrinsic::from_callee(&callee) {
+            return self.compile_chain_call(intrinsic, arguments);
+        }
+        if let Some(builtin) = ArithmeticBuiltin::from_callee(&callee) {
+            return self.compile_arithmetic_call(builtin, arguments);
+        }
+        if let Some(builtin) = RegexBuiltin::from_callee(&callee) {
+            return self.compile_regex_call(builtin, arguments);
+        }
+        if let Some(builtin) = DateTimeBuiltin::from_callee(&callee) {
+            return self.compile_datetime_call(builtin, arguments);
+        }
+        if let Some(builtin) = BytesBuiltin::from_callee(&callee) {
+            return self.compile_module_call(builtin.runtime_symbol(), builtin.name(), builtin.signature(), arguments);
+        }
+        if let Some(builtin) = IoBuiltin::from_callee(&callee) {
+            return self.compile_module_call(builtin.runtime_symbol(), builtin.name(), builtin.signature(), arguments);
+        }
+        if let Some(builtin) = NetBuiltin::from_callee(&callee) {
+            return self.compile_module_call(builtin.runtime_symbol(), builtin.name(), builtin.signature(), arguments);
+        }
+        if let Some(builtin) = HttpBuiltin::from_callee(&callee) {
+            return self.compile_module_call(builtin.runtime_symbol(), builtin.name(), builtin.signature(), arguments);
+        }
+        if let Some(builtin) = ProcessBuiltin::from_callee(&callee) {
+            return self.compile_module_call(builtin.runtime_symbol(), builtin.name(), builtin.signature(), arguments);
+        }
+
+        let callee_value = match callee.into_unlocated() {
+            Node::Super => return Err("super(..) can only be the first statement of a constructor".to_string()),
+            Node::Member { object, property } if matches!(object.unlocated(), Node::Super) => {
+                return self.compile_super_call(&property, arguments);
+            },
+            Node::Member { object, property } => {
+                let receiver = self.compile_node(*object)?;
+                if let Some(interface) = self.interface_of(receiver) {
+                    return self.compile_interface_call(receiver.into_struct_value(), &interface, &property, arguments);
+                }
+                self.member_of(receiver, property)?
+            },
+            Node::Identifier(name) if self.tag(&name).is_some() && !self.variables.contains_key(name.as_str()) && self.module.get_function(&name).is_none() => {
+                return self.compile_variant(&name, arguments);
+            },
+            callee => self.compile_node(callee)?,
+        };
+        let function = callee_value.into_pointer_value();
+        let param_types = match function.get_type().get_element_type() {
+            AnyTypeEnum::FunctionType(function_type) => function_type.get_param_types(),
+            _ => Vec::new(),
+        };
         let mut compiled_args = Vec::new();
 
-        for arg in arguments {
-            compiled_args.push(self.compile_node(arg)?);
+        for (i, arg) in arguments.into_iter().enumerate() {
+            let value = self.compile_node(arg)?;
+            compiled_args.push(match param_types.get(i) {
+                Some(param_type) => self.coerce(value, *param_type)?,
+                None => value,
+            });
         }
 
-        let function = callee_value.into_pointer_value();
         Ok(self.builder
             .build_call(function, &compiled_args, "calltmp")
             .try_as_basic_value()
@@ -208,30 +1145,1129 @@ impl<'ctx> Compiler<'ctx> {
             .ok_or_else(|| "Invalid call result".to_string())?)
     }
 
+    /// Failed assertions call `gard_raise` in gard-vm, which unwinds to the
+    /// nearest catch. The kind codes match `gard_vm::error::ErrorKind`.
+    fn compile_assertion(&mut self, kind: AssertionKind, condition: Node, message: Option<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        let condition = self.compile_node(condition)?.into_int_value();
+        let message = match message {
+            Some(message) => self.compile_node(message)?,
+            None => self.compile_string_literal("assertion failed".to_string())?,
+        };
+
+        let function = self.builder.get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| "Assertion outside of a function".to_string())?;
+        let fail_block = self.context.append_basic_block(function, "assert.fail");
+        let continue_block = self.context.append_basic_block(function, "assert.ok");
+
+        self.builder.build_conditional_branch(condition, continue_block, fail_block);
+
+        self.builder.position_at_end(fail_block);
+        let code = match kind {
+            AssertionKind::Validate => 0,
+            AssertionKind::Require => 1,
+            AssertionKind::Assert => 2,
+        };
+        self.build_raise(code, message)?;
+
+        self.builder.position_at_end(continue_block);
+        Ok(self.context.i64_type().const_int(0, false).as_basic_value_enum())
+    }
+
+    /// Ends the current block raising an error with `gard_raise`, or with
+    /// the host's `abort` in a wasm contract, which can't catch it.
+    fn build_raise(&mut self, code: u64, message: BasicValueEnum<'ctx>) -> Result<(), String> {
+        if self.wasm_contract {
+            let abort = self.module.get_function("abort")
+                .ok_or_else(|| "Missing host import 'abort'".to_string())?;
+            let message = self.wasm_pointer(message.into_pointer_value());
+            self.builder.build_call(abort, &[message], "abort");
+        } else {
+            let raise = self.module.get_function("gard_raise").unwrap_or_else(|| {
+                let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+                self.module.add_function(
+                    "gard_raise",
+                    self.context.void_type().fn_type(&[self.context.i32_type().into(), byte_ptr.into()], false),
+                    None
+                )
+            });
+            self.builder.build_call(raise, &[self.context.i32_type().const_int(code, false).into(), message.into()], "raise");
+        }
+        self.builder.build_unreachable();
+        Ok(())
+    }
+
+    /// Block reads and simulator controls go through gard-vm's chain
+    /// simulator, which works in u64; block reads are widened to uint256.
+    fn compile_chain_call(&mut self, intrinsic: ChainIntrinsic, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        let (min, max) = intrinsic.arity();
+        if arguments.len() < min || arguments.len() > max {
+            return Err(format!("{}() expects between {} and {} argument(s), found {}",
+                intrinsic.name(), min, max, arguments.len()));
+        }
+
+        let i64_type = self.context.i64_type();
+        let mut compiled_args: Vec<BasicMetadataValueEnum<'ctx>> = Vec::new();
+        for arg in arguments {
+            let value = self.compile_node(arg)?.into_int_value();
+            compiled_args.push(self.builder.build_int_cast(value, i64_type, "chain.arg").into());
+        }
+        if intrinsic == ChainIntrinsic::Mine && compiled_args.is_empty() {
+            compiled_args.push(i64_type.const_int(1, false).into());
+        }
+
+        // Contracts read the block context from the host instead of the simulator
+        let symbol = match (self.wasm_contract, intrinsic) {
+            (true, ChainIntrinsic::BlockNumber) => "context_block_height",
+            (true, ChainIntrinsic::BlockTimestamp) => "context_block_time",
+            (true, _) => return Err(format!("{}() is only available under the chain simulator", intrinsic.name())),
+            (false, _) => intrinsic.runtime_symbol(),
+        };
+        let function = self.module.get_function(symbol).unwrap_or_else(|| {
+            let fn_type = match intrinsic {
+                ChainIntrinsic::BlockNumber | ChainIntrinsic::BlockTimestamp => i64_type.fn_type(&[], false),
+                ChainIntrinsic::Mine | ChainIntrinsic::AdvanceTime => {
+                    self.context.void_type().fn_type(&[i64_type.into()], false)
+                },
+                ChainIntrinsic::Warp => self.context.bool_type().fn_type(&[i64_type.into()], false),
+            };
+            self.module.add_function(symbol, fn_type, None)
+        });
+
+        let result = self.builder
+            .build_call(function, &compiled_args, intrinsic.name())
+            .try_as_basic_value()
+            .left();
+
+        Ok(match (intrinsic, result) {
+            (ChainIntrinsic::BlockNumber | ChainIntrinsic::BlockTimestamp, Some(value)) => {
+                self.builder
+                    .build_int_z_extend(value.into_int_value(), self.context.custom_width_int_type(256), "block.value")
+                    .as_basic_value_enum()
+            },
+            (_, Some(value)) => value,
+            (_, None) => i64_type.const_int(0, false).as_basic_value_enum(),
+        })
+    }
+
+    /// Crypto results are bytes blocks gard-vm allocates. A hash passes the
+    /// data's pointer and length, so a 0 byte in it doesn't end it.
+    fn compile_crypto_call(&mut self, builtin: CryptoBuiltin, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        let Type::Function { params, return_type } = builtin.signature() else {
+            unreachable!("crypto builtins are functions");
+        };
+        if arguments.len() != params.len() {
+            return Err(format!("{} expects {} argument(s), found {}",
+                builtin.name(), params.len(), arguments.len()));
+        }
+
+        let bytes_type = self.get_llvm_type(&Type::Bytes)?;
+        let mut compiled_args: Vec<BasicMetadataValueEnum<'ctx>> = Vec::new();
+        for (i, arg) in arguments.into_iter().enumerate() {
+            let value = match self.compile_node(arg)? {
+                BasicValueEnum::PointerValue(text) if builtin.is_hash() && Self::is_string(text) => {
+                    let from_string = BytesBuiltin::FromString;
+                    let Type::Function { params, return_type } = from_string.signature() else {
+                        unreachable!("module builtins are functions");
+                    };
+                    self.build_runtime_call(from_string.runtime_symbol(), from_string.name(), &params, &return_type, &[text.into()])?
+                },
+                value if value.get_type() == bytes_type => value,
+                _ => return Err(format!("Argument {} of {}() must be bytes{}",
+                    i + 1, builtin.name(), if builtin.is_hash() { " or a string" } else { "" })),
+            };
+            compiled_args.push(value.into());
+        }
+        if !builtin.is_hash() {
+            return self.build_runtime_call(builtin.runtime_symbol(), builtin.name(), &params, &return_type, &compiled_args);
+        }
+
+        let data = match compiled_args[0] {
+            BasicMetadataValueEnum::PointerValue(data) => data,
+            _ => unreachable!("bytes are pointers"),
+        };
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let length_pointer = self.builder.build_struct_gep(data, 0, "length")
+            .map_err(|_| "Invalid bytes header".to_string())?;
+        let length = self.builder.build_load(length_pointer, "length").into_int_value();
+        let length = self.builder.build_int_z_extend(length, i64_type, "length");
+        let bytes = unsafe {
+            self.builder.build_in_bounds_gep(data, &[i32_type.const_zero(), i32_type.const_int(1, false), i32_type.const_zero()], "data")
+        };
+
+        let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let function = self.module.get_function(builtin.runtime_symbol()).unwrap_or_else(|| {
+            self.module.add_function(builtin.runtime_symbol(), bytes_type.fn_type(&[byte_ptr.into(), i64_type.into()], false), None)
+        });
+        Ok(self.builder.build_call(function, &[bytes.into(), length.into()], builtin.name())
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| "Invalid call result".to_string())?)
+    }
+
+    fn compile_regex_call(&mut self, builtin: RegexBuiltin, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        if arguments.len() != builtin.arity() {
+            return Err(format!("{} expects {} argument(s), found {}",
+                builtin.name(), builtin.arity(), arguments.len()));
+        }
+
+        let mut compiled_args: Vec<BasicMetadataValueEnum<'ctx>> = Vec::new();
+        for arg in arguments {
+            compiled_args.push(self.compile_node(arg)?.into());
+        }
+
+        let return_type = self.get_llvm_type(&builtin.return_type())?;
+        let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let function = self.module.get_function(builtin.runtime_symbol()).unwrap_or_else(|| {
+            let param_types: Vec<BasicMetadataTypeEnum> = compiled_args.iter()
+                .map(|_| byte_ptr.into())
+                .collect();
+            self.module.add_function(builtin.runtime_symbol(), return_type.fn_type(&param_types, false), None)
+        });
+
+        self.builder
+            .build_call(function, &compiled_args, builtin.name())
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| "Invalid call result".to_string())
+    }
+
+    /// Instants, date-times and durations are i64 milliseconds, so most of
+    /// `std.datetime` is a multiplication.
+    fn compile_datetime_call(&mut self, builtin: DateTimeBuiltin, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        let Type::Function { params, return_type } = builtin.signature() else {
+            unreachable!("datetime builtins are functions");
+        };
+        if arguments.len() != params.len() {
+            return Err(format!("{} expects {} argument(s), found {}",
+                builtin.name(), params.len(), arguments.len()));
+        }
+
+        let mut compiled_args: Vec<BasicMetadataValueEnum<'ctx>> = Vec::new();
+        for arg in arguments {
+            compiled_args.push(self.compile_node(arg)?.into());
+        }
+
+        if let Some(scale) = builtin.scale() {
+            let value = compiled_args[0].into_int_value();
+            let scale = self.context.i64_type().const_int(scale as u64, false);
+            return Ok(self.builder.build_int_mul(value, scale, builtin.name()).as_basic_value_enum());
+        }
+
+        let symbol = builtin.runtime_symbol().expect("builtins that don't scale are in the runtime");
+        self.build_runtime_call(symbol, builtin.name(), &params, &return_type, &compiled_args)
+    }
+
+    /// `bytes.*` and `io.*` calls, which all go to gard-vm.
+    fn compile_module_call(&mut self, symbol: &str, name: &str, signature: Type, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        let Type::Function { params, return_type } = signature else {
+            unreachable!("module builtins are functions");
+        };
+        if arguments.len() != params.len() {
+            return Err(format!("{} expects {} argument(s), found {}", name, params.len(), arguments.len()));
+        }
+
+        let mut compiled_args: Vec<BasicMetadataValueEnum<'ctx>> = Vec::new();
+        for arg in arguments {
+            compiled_args.push(self.compile_node(arg)?.into());
+        }
+        self.build_runtime_call(symbol, name, &params, &return_type, &compiled_args)
+    }
+
+    /// Calls a gard-vm function, declaring it on first use. A void function
+    /// yields 0 so every call is a value.
+    fn build_runtime_call(&mut self, symbol: &str, name: &str, params: &[Type], return_type: &Type, arguments: &[BasicMetadataValueEnum<'ctx>]) -> Result<BasicValueEnum<'ctx>, String> {
+        let param_types = params.iter()
+            .map(|param| self.get_llvm_type(param).map(BasicMetadataTypeEnum::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        let function_type = match return_type {
+            Type::Void => self.context.void_type().fn_type(&param_types, false),
+            return_type => self.get_llvm_type(return_type)?.fn_type(&param_types, false),
+        };
+        let function = self.module.get_function(symbol).unwrap_or_else(|| {
+            self.module.add_function(symbol, function_type, None)
+        });
+
+        let result = self.builder.build_call(function, arguments, name).try_as_basic_value().left();
+        Ok(result.unwrap_or_else(|| self.context.i64_type().const_zero().as_basic_value_enum()))
+    }
+
     fn get_llvm_type(&self, ty: &Type) -> Result<BasicTypeEnum<'ctx>, String> {
         match ty {
-            Type::Int => Ok(self.context.i64_type().as_basic_type_enum()),
+            Type::Int | Type::UInt => Ok(self.context.i64_type().as_basic_type_enum()),
             Type::Float => Ok(self.context.f64_type().as_basic_type_enum()),
             Type::String => Ok(self.context.i8_type().ptr_type(AddressSpace::default()).as_basic_type_enum()),
             Type::Boolean => Ok(self.context.bool_type().as_basic_type_enum()),
+            Type::UInt256 => Ok(self.context.custom_width_int_type(256).as_basic_type_enum()),
+            Type::Address => Ok(self.context.custom_width_int_type(160).as_basic_type_enum()),
             Type::Array(elem_type) => {
-                let elem_type = self.get_llvm_type(elem_type)?;
-                Ok(elem_type.array_type(0).as_basic_type_enum())
+                let elem_type = match self.interop.resolve(elem_type) {
+                    Ok(abi_type) => self.get_abi_type(&abi_type)?,
+                    Err(_) => self.get_llvm_type(elem_type)?,
+                };
+                Ok(self.array_type(elem_type).ptr_type(AddressSpace::default()).as_basic_type_enum())
+            },
+            Type::Bytes => Ok(self.array_type(self.context.i8_type().as_basic_type_enum()).ptr_type(AddressSpace::default()).as_basic_type_enum()),
+            // Handles into gard-vm's tables of streams, sockets, responses and processes
+            Type::Custom(name) if [io::READER, io::WRITER, io::STREAM, http::RESPONSE, process::COMMAND, process::CHILD].contains(&name.as_str())
+                || net::is_handle_type(ty) => {
+                Ok(self.context.i64_type().as_basic_type_enum())
             },
+            Type::Custom(_) if datetime::is_time_type(ty) => Ok(self.context.i64_type().as_basic_type_enum()),
+            Type::Custom(name) if self.interfaces.contains_key(name.as_str()) => Ok(self.interface_type(name).as_basic_type_enum()),
+            Type::Custom(name) if self.unions.contains_key(name.as_str()) => Ok(self.union_type(name).as_basic_type_enum()),
             Type::Custom(name) => {
-                // Handle custom types (e.g., classes, interfaces)
-                Err(format!("Custom type not yet supported: {}", name))
+                Ok(self.get_struct_type(name)?.ptr_type(AddressSpace::default()).as_basic_type_enum())
             },
             _ => Err(format!("Unsupported type: {:?}", ty)),
         }
     }
 
+    /// Type of a value stored in linear memory, matching the layouts in
+    /// `interop` on wasm32.
+    fn get_abi_type(&self, ty: &AbiType) -> Result<BasicTypeEnum<'ctx>, String> {
+        match ty {
+            AbiType::I64 => Ok(self.context.i64_type().as_basic_type_enum()),
+            AbiType::F64 => Ok(self.context.f64_type().as_basic_type_enum()),
+            AbiType::Bool => Ok(self.context.i8_type().as_basic_type_enum()),
+            AbiType::String => Ok(self.context.i8_type().ptr_type(AddressSpace::default()).as_basic_type_enum()),
+            AbiType::Array(element) => {
+                let element = self.get_abi_type(element)?;
+                Ok(self.array_type(element).ptr_type(AddressSpace::default()).as_basic_type_enum())
+            },
+            AbiType::Struct(name) => {
+                Ok(self.get_struct_type(name)?.ptr_type(AddressSpace::default()).as_basic_type_enum())
+            },
+        }
+    }
+
+    /// `{ i32 length, [0 x element] }`
+    fn array_type(&self, element: BasicTypeEnum<'ctx>) -> StructType<'ctx> {
+        self.context.struct_type(&[self.context.i32_type().as_basic_type_enum(), element.array_type(0).as_basic_type_enum()], false)
+    }
+
+    fn get_struct_type(&self, name: &str) -> Result<StructType<'ctx>, String> {
+        if let Some(struct_type) = self.module.get_struct_type(name) {
+            return Ok(struct_type);
+        }
+        let layout = self.interop.layout(name)
+            .ok_or_else(|| format!("Class {} has no linear-memory layout; its fields must all be boundary types", name))?;
+
+        // Declared before the body so fields can point back at the class
+        let struct_type = self.context.opaque_struct_type(name);
+        let fields = layout.fields.iter()
+            .map(|field| self.get_abi_type(&field.ty))
+            .collect::<Result<Vec<_>, _>>()?;
+        struct_type.set_body(&fields, false);
+        Ok(struct_type)
+    }
+
+    /// `{ i8* data, i8** itable }`, a fat pointer to an instance of any
+    /// class implementing the interface. The itable holds the class's type
+    /// info and then its implementations of the interface's methods, in
+    /// declaration order.
+    fn interface_type(&self, name: &str) -> StructType<'ctx> {
+        if let Some(struct_type) = self.module.get_struct_type(name) {
+            return struct_type;
+        }
+        let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let struct_type = self.context.opaque_struct_type(name);
+        struct_type.set_body(&[byte_ptr.into(), byte_ptr.ptr_type(AddressSpace::default()).into()], false);
+        struct_type
+    }
+
+    /// `{ i32 tag, [n x i64] payload }`, a tagged union with a slot for
+    /// each value of its largest payload. The tag is the variant's index.
+    fn union_type(&self, name: &str) -> StructType<'ctx> {
+        if let Some(struct_type) = self.module.get_struct_type(name) {
+            return struct_type;
+        }
+        let slots = self.unions[name].iter().map(|variant| variant.payload.len()).max().unwrap_or(0);
+        let struct_type = self.context.opaque_struct_type(name);
+        struct_type.set_body(&[self.context.i32_type().into(), self.context.i64_type().array_type(slots as u32).into()], false);
+        struct_type
+    }
+
+    /// The union a value is a variant of, when it's a tagged union.
+    fn union_of(&self, value: BasicValueEnum<'ctx>) -> Option<String> {
+        let BasicValueEnum::StructValue(value) = value else {
+            return None;
+        };
+        let name = value.get_type().get_name()?.to_str().ok()?;
+        self.unions.contains_key(name).then(|| name.to_string())
+    }
+
+    /// The union, tag value and payload of a variant that is a tag.
+    fn tag(&self, name: &str) -> Option<(String, usize, Vec<Type>)> {
+        self.unions.iter().find_map(|(union, variants)| {
+            let index = variants.iter().position(|variant| variant.name == name)?;
+            Some((union.clone(), index, variants[index].payload.clone()))
+        })
+    }
+
+    /// Builds a tagged union, storing each payload value in its slot.
+    fn compile_variant(&mut self, name: &str, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        let (union, index, payload) = self.tag(name).ok_or_else(|| format!("'{}' is not a variant", name))?;
+        if arguments.len() != payload.len() {
+            return Err(format!("Variant '{}' of {} takes {} value(s), found {}", name, union, payload.len(), arguments.len()));
+        }
+        let pointer = self.builder.build_alloca(self.union_type(&union), name);
+        let tag = self.builder.build_struct_gep(pointer, 0, "tag")
+            .map_err(|_| format!("Invalid union {}", union))?;
+        self.builder.build_store(tag, self.context.i32_type().const_int(index as u64, false));
+        for (slot, (argument, ty)) in arguments.into_iter().zip(&payload).enumerate() {
+            let ty = self.get_llvm_type(ty)?;
+            let value = self.compile_node(argument)?;
+            let value = self.coerce(value, ty)?;
+            let slot = self.payload_slot(pointer, slot, ty, name)?;
+            self.builder.build_store(slot, value);
+        }
+        Ok(self.builder.build_load(pointer, name))
+    }
+
+    /// A pointer to a payload slot of a tagged union, as a pointer to the
+    /// value stored in it. Values wider than a slot, like `uint256`s and
+    /// interface values, can't be stored.
+    fn payload_slot(&mut self, pointer: PointerValue<'ctx>, slot: usize, ty: BasicTypeEnum<'ctx>, variant: &str) -> Result<PointerValue<'ctx>, String> {
+        let fits = match ty {
+            BasicTypeEnum::IntType(int) => int.get_bit_width() <= 64,
+            BasicTypeEnum::FloatType(_) | BasicTypeEnum::PointerType(_) => true,
+            _ => false,
+        };
+        if !fits {
+            return Err(format!("Variant '{}' carries a value wider than a payload slot", variant));
+        }
+        let i32_type = self.context.i32_type();
+        let slot = unsafe {
+            self.builder.build_in_bounds_gep(pointer, &[i32_type.const_zero(), i32_type.const_int(1, false), i32_type.const_int(slot as u64, false)], "payload")
+        };
+        Ok(self.builder.build_pointer_cast(slot, ty.ptr_type(AddressSpace::default()), variant))
+    }
+
+    /// The interface a value is an instance of, when it's a fat pointer.
+    fn interface_of(&self, value: BasicValueEnum<'ctx>) -> Option<String> {
+        let BasicValueEnum::StructValue(value) = value else {
+            return None;
+        };
+        let struct_type = value.get_type();
+        let name = struct_type.get_name()?.to_str().ok()?;
+        self.interfaces.contains_key(name).then(|| name.to_string())
+    }
+
+    /// Type of an implementation of an interface method, which takes the
+    /// receiver as the fat pointer's data pointer before its parameters.
+    fn method_type(&self, method: &MethodSignature) -> Result<FunctionType<'ctx>, String> {
+        let mut param_types: Vec<BasicMetadataTypeEnum> = vec![self.context.i8_type().ptr_type(AddressSpace::default()).into()];
+        for param in &method.params {
+            param_types.push(self.get_llvm_type(&param.type_annotation)?.into());
+        }
+        Ok(match &method.return_type {
+            Type::Void => self.context.void_type().fn_type(&param_types, false),
+            return_type => self.get_llvm_type(return_type)?.fn_type(&param_types, false),
+        })
+    }
+
+    /// Symbol of the method a class's instances run: its own `Class.method`
+    /// or the nearest base class's, with a nested class named as in
+    /// `nested::symbol`. Abstract methods have no symbol.
+    fn method_symbol(&self, class: &str, method: &str) -> Result<String, String> {
+        let owner = self.resolve_method(class, method)?.map(|(owner, _)| owner);
+        Ok(format!("{}.{}", nested::symbol(owner.as_deref().unwrap_or(class)), method))
+    }
+
+    /// The class whose definition of a method a class's instances run, and
+    /// its signature.
+    fn resolve_method(&self, class: &str, method: &str) -> Result<Option<(String, MethodSignature)>, String> {
+        let mut current = class;
+        // Bounded so an inheritance cycle can't loop forever
+        for _ in 0..=self.classes.len() {
+            let Some(info) = self.classes.get(current) else {
+                break;
+            };
+            match info.methods.iter().find(|(signature, _)| signature.name == method) {
+                Some((_, true)) => return Err(format!("Method '{}' of class '{}' is abstract", method, class)),
+                Some((signature, false)) => return Ok(Some((current.to_string(), signature.clone()))),
+                None => match &info.base {
+                    Some(base) => current = base,
+                    None => break,
+                },
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether a class or one of its base classes declares a constructor.
+    fn has_constructor(&self, class: &str) -> bool {
+        let mut current = class;
+        for _ in 0..=self.classes.len() {
+            match self.classes.get(current) {
+                Some(ClassInfo { constructor: Some(_), .. }) => return true,
+                Some(ClassInfo { base: Some(base), .. }) => current = base,
+                _ => break,
+            }
+        }
+        false
+    }
+
+    /// `Class.constructor`, which takes the instance as an `i8*` before its
+    /// parameters, declared on first use.
+    fn constructor_function(&self, class: &str, params: &[Parameter]) -> Result<FunctionValue<'ctx>, String> {
+        let name = format!("{}.constructor", nested::symbol(class));
+        if let Some(function) = self.module.get_function(&name) {
+            return Ok(function);
+        }
+        let mut param_types: Vec<BasicMetadataTypeEnum> = vec![self.context.i8_type().ptr_type(AddressSpace::default()).into()];
+        for param in params {
+            param_types.push(self.get_llvm_type(&param.type_annotation)?.into());
+        }
+        Ok(self.module.add_function(&name, self.context.void_type().fn_type(&param_types, false), None))
+    }
+
+    /// Compiles a class's constructor. The base class's constructor runs
+    /// first, with the arguments of a leading `super(..)` or implicitly
+    /// without any, and then the rest of the body.
+    fn compile_constructor(&mut self, class: &str, params: Vec<Parameter>, body: Node) -> Result<(), String> {
+        let function = self.constructor_function(class, &params)?;
+        let outer_block = self.builder.get_insert_block();
+        let outer_class = self.class.replace(class.to_string());
+        self.builder.position_at_end(self.context.append_basic_block(function, "entry"));
+
+        let data = function.get_nth_param(0)
+            .ok_or_else(|| "Failed to get parameter this".to_string())?
+            .into_pointer_value();
+        let this_type = self.get_struct_type(class)?.ptr_type(AddressSpace::default());
+        let this = self.builder.build_alloca(this_type, "this");
+        self.builder.build_store(this, self.builder.build_pointer_cast(data, this_type, "this"));
+        self.variables.insert("this".to_string(), this);
+        for (i, param) in params.iter().enumerate() {
+            let value = function.get_nth_param(i as u32 + 1)
+                .ok_or_else(|| format!("Failed to get parameter {}", param.name))?;
+            let alloca = self.builder.build_alloca(value.get_type(), &param.name);
+            self.builder.build_store(alloca, value);
+            self.variables.insert(param.name.to_string(), alloca);
+        }
+
+        let mut statements = match body.into_unlocated() {
+            Node::Block(statements) => statements,
+            body => vec![body],
+        };
+        let arguments = statements.first().and_then(Self::super_arguments);
+        if arguments.is_some() {
+            statements.remove(0);
+        }
+        self.build_base_constructor_call(class, data, arguments)?;
+        self.compile_block(statements)?;
+        if self.builder.get_insert_block().and_then(|block| block.get_terminator()).is_none() {
+            self.builder.build_return(None);
+        }
+
+        self.variables.remove("this");
+        self.class = outer_class;
+        if let Some(block) = outer_block {
+            self.builder.position_at_end(block);
+        }
+        Ok(())
+    }
+
+    /// Arguments of a `super(..)` statement.
+    fn super_arguments(statement: &Node) -> Option<Vec<Node>> {
+        match statement.unlocated() {
+            Node::Block(expressions) if expressions.len() == 1 => Self::super_arguments(&expressions[0]),
+            Node::Call { callee, arguments } if matches!(callee.unlocated(), Node::Super) => Some(arguments.clone()),
+            _ => None,
+        }
+    }
+
+    /// Runs the base class's constructor on the instance `data`, with the
+    /// arguments of an explicit `super(..)`.
+    fn build_base_constructor_call(&mut self, class: &str, data: PointerValue<'ctx>, arguments: Option<Vec<Node>>) -> Result<(), String> {
+        let base = self.classes.get(class).and_then(|info| info.base.clone());
+        let Some(base) = base.filter(|base| self.has_constructor(base)) else {
+            return match arguments {
+                Some(arguments) if !arguments.is_empty() => Err(format!("super(..) in class {} takes no arguments", class)),
+                _ => Ok(()),
+            };
+        };
+        let params = self.classes.get(&base).and_then(|info| info.constructor.clone()).unwrap_or_default();
+        let arguments = arguments.unwrap_or_default();
+        if arguments.len() != params.len() {
+            return Err(format!("super expects {} argument(s), found {}", params.len(), arguments.len()));
+        }
+
+        let function = self.constructor_function(&base, &params)?;
+        let mut compiled_args: Vec<BasicMetadataValueEnum<'ctx>> = vec![data.into()];
+        for (argument, param) in arguments.into_iter().zip(&params) {
+            let value = self.compile_node(argument)?;
+            let param_type = self.get_llvm_type(&param.type_annotation)?;
+            compiled_args.push(self.coerce(value, param_type)?.into());
+        }
+        self.builder.build_call(function, &compiled_args, "super");
+        Ok(())
+    }
+
+    /// `super.method(..)`: a direct call on `this` to the nearest base
+    /// class's definition, skipping the class's own.
+    fn compile_super_call(&mut self, property: &str, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        let base = self.class.as_ref()
+            .and_then(|class| self.classes.get(class))
+            .and_then(|info| info.base.clone())
+            .ok_or_else(|| "'super' is only supported in constructors of classes with a base class".to_string())?;
+        let (owner, method) = self.resolve_method(&base, property)?
+            .ok_or_else(|| format!("Class {} has no method {}", base, property))?;
+        if arguments.len() != method.params.len() {
+            return Err(format!("{} expects {} argument(s), found {}", property, method.params.len(), arguments.len()));
+        }
+
+        let symbol = format!("{}.{}", nested::symbol(&owner), property);
+        let function = match self.module.get_function(&symbol) {
+            Some(function) => function,
+            None => self.module.add_function(&symbol, self.method_type(&method)?, None),
+        };
+        let this = self.compile_identifier("this".to_string())?.into_pointer_value();
+        let data = self.builder.build_pointer_cast(this, self.context.i8_type().ptr_type(AddressSpace::default()), "data");
+        let mut compiled_args: Vec<BasicMetadataValueEnum<'ctx>> = vec![data.into()];
+        for (argument, param) in arguments.into_iter().zip(&method.params) {
+            let value = self.compile_node(argument)?;
+            let param_type = self.get_llvm_type(&param.type_annotation)?;
+            compiled_args.push(self.coerce(value, param_type)?.into());
+        }
+        let result = self.builder.build_call(function, &compiled_args, property).try_as_basic_value().left();
+        Ok(result.unwrap_or_else(|| self.context.i64_type().const_zero().as_basic_value_enum()))
+    }
+
+    /// `Class.Interface.itable`, emitted on first use. Its entries are the
+    /// class's type info and the functions it runs for each method,
+    /// declared here when they aren't defined.
+    fn itable(&mut self, class: &str, interface: &str) -> Result<PointerValue<'ctx>, String> {
+        let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let name = format!("{}.{}.itable", nested::symbol(class), interface);
+        // Emitting the type info may emit the itable, which it lists
+        let type_info = self.type_info(class)?;
+        let table = match self.module.get_global(&name) {
+            Some(table) => table,
+            None => {
+                let methods = self.interfaces[interface].iter()
+                    .map(|method| {
+                        let symbol = self.method_symbol(class, &method.name)?;
+                        let function = match self.module.get_function(&symbol) {
+                            Some(function) => function,
+                            None => self.module.add_function(&symbol, self.method_type(method)?, None),
+                        };
+                        Ok(function.as_global_value().as_pointer_value().const_cast(byte_ptr))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                let entries: Vec<PointerValue> = std::iter::once(type_info).chain(methods).collect();
+                let table = self.module.add_global(byte_ptr.array_type(entries.len() as u32), None, &name);
+                table.set_initializer(&byte_ptr.const_array(&entries));
+                table.set_constant(true);
+                table
+            },
+        };
+        Ok(table.as_pointer_value().const_cast(byte_ptr.ptr_type(AddressSpace::default())))
+    }
+
+    /// `{ i8* base, i8* name, i8* implementations }`, gard-vm's
+    /// `rtti::TypeInfo`.
+    fn type_info_type(&self) -> StructType<'ctx> {
+        if let Some(struct_type) = self.module.get_struct_type("gard.rtti") {
+            return struct_type;
+        }
+        let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let struct_type = self.context.opaque_struct_type("gard.rtti");
+        struct_type.set_body(&[byte_ptr.into(), byte_ptr.into(), byte_ptr.into()], false);
+        struct_type
+    }
+
+    /// `Name.rtti`, the type info of a class or interface, emitted on first
+    /// use. A class's lists its itable for each interface it implements,
+    /// itself or through a base class, as `(interface info, itable)` pairs
+    /// ending with a null pair.
+    fn type_info(&mut self, name: &str) -> Result<PointerValue<'ctx>, String> {
+        let i8_type = self.context.i8_type();
+        let byte_ptr = i8_type.ptr_type(AddressSpace::default());
+        let global_name = format!("{}.rtti", nested::symbol(name));
+        if let Some(info) = self.module.get_global(&global_name) {
+            return Ok(info.as_pointer_value().const_cast(byte_ptr));
+        }
+        // Added before its initializer, which refers back to it through
+        // the itables and, in an inheritance cycle, the base classes
+        let info_type = self.type_info_type();
+        let info = self.module.add_global(info_type, None, &global_name);
+        info.set_constant(true);
+
+        let base = match self.classes.get(name).and_then(|class| class.base.clone()) {
+            Some(base) => self.type_info(&base)?,
+            None => byte_ptr.const_null(),
+        };
+        let bytes = name.bytes().chain([0]).map(|byte| i8_type.const_int(byte as u64, false)).collect::<Vec<_>>();
+        let name_string = self.module.add_global(i8_type.array_type(bytes.len() as u32), None, &format!("{}.name", global_name));
+        name_string.set_initializer(&i8_type.const_array(&bytes));
+        name_string.set_constant(true);
+        let implementations = if self.classes.contains_key(name) {
+            let mut entries = Vec::new();
+            for interface in self.implemented_interfaces(name) {
+                entries.push(self.type_info(&interface)?);
+                entries.push(self.itable(name, &interface)?.const_cast(byte_ptr));
+            }
+            entries.extend([byte_ptr.const_null(), byte_ptr.const_null()]);
+            let list = self.module.add_global(byte_ptr.array_type(entries.len() as u32), None, &format!("{}.implementations", global_name));
+            list.set_initializer(&byte_ptr.const_array(&entries));
+            list.set_constant(true);
+            list.as_pointer_value().const_cast(byte_ptr)
+        } else {
+            byte_ptr.const_null()
+        };
+        info.set_initializer(&info_type.const_named_struct(&[
+            base.into(),
+            name_string.as_pointer_value().const_cast(byte_ptr).into(),
+            implementations.into(),
+        ]));
+        Ok(info.as_pointer_value().const_cast(byte_ptr))
+    }
+
+    /// Interfaces a class implements, its own first and then its base
+    /// classes'.
+    fn implemented_interfaces(&self, class: &str) -> Vec<String> {
+        let mut interfaces: Vec<String> = Vec::new();
+        let mut current = Some(class);
+        for _ in 0..=self.classes.len() {
+            let Some(info) = current.and_then(|class| self.classes.get(class)) else {
+                break;
+            };
+            for interface in &info.implements {
+                if !interfaces.contains(interface) {
+                    interfaces.push(interface.clone());
+                }
+            }
+            current = info.base.as_deref();
+        }
+        interfaces
+    }
+
+    /// Whether `class` is `base` or extends it.
+    fn is_subclass(&self, class: &str, base: &str) -> bool {
+        let mut current = Some(class);
+        for _ in 0..=self.classes.len() {
+            match current {
+                Some(class) if class == base => return true,
+                Some(class) => current = self.classes.get(class).and_then(|info| info.base.as_deref()),
+                None => break,
+            }
+        }
+        false
+    }
+
+    /// `value is T` and `value as? T`. An interface value's class is found
+    /// at runtime, from the type info in its itable. A class instance
+    /// carries none, so a test on one is decided by its static type.
+    fn compile_type_test(&mut self, value: Node, target: Type, cast: bool) -> Result<BasicValueEnum<'ctx>, String> {
+        let target = match target {
+            Type::Custom(name) if self.classes.contains_key(name.as_str()) || self.interfaces.contains_key(name.as_str()) => name.to_string(),
+            other => return Err(format!("'is' and 'as?' need a class or interface, found {:?}", other)),
+        };
+        let target_is_interface = self.interfaces.contains_key(&target);
+        let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let itable_type = byte_ptr.ptr_type(AddressSpace::default());
+        let value = self.compile_node(value)?;
+
+        if let Some(interface) = self.interface_of(value) {
+            if cast && interface == target {
+                return Ok(value);
+            }
+            let value = value.into_struct_value();
+            let data = self.builder.build_extract_value(value, 0, "data")
+                .ok_or_else(|| "Invalid interface value".to_string())?
+                .into_pointer_value();
+            let itable = self.builder.build_extract_value(value, 1, "itable")
+                .ok_or_else(|| "Invalid interface value".to_string())?
+                .into_pointer_value();
+            let non_null = self.builder.build_is_not_null(data, "nonnull");
+            let target_info = self.type_info(&target)?;
+
+            if cast && target_is_interface {
+                let lookup = self.module.get_function("gard_rtti_itable").unwrap_or_else(|| self.module.add_function(
+                    "gard_rtti_itable",
+                    itable_type.fn_type(&[itable_type.into(), byte_ptr.into()], false),
+                    None
+                ));
+                let found = self.builder.build_call(lookup, &[itable.into(), target_info.into()], "itable")
+                    .try_as_basic_value()
+                    .left()
+                    .ok_or_else(|| "Invalid itable lookup".to_string())?
+                    .into_pointer_value();
+                let found_non_null = self.builder.build_is_not_null(found, "found");
+                let is = self.builder.build_and(non_null, found_non_null, "is");
+                let data = self.builder.build_select(is, data, byte_ptr.const_null(), "data");
+                let found = self.builder.build_select(is, found, itable_type.const_null(), "itable");
+                let target_type = self.interface_type(&target);
+                let cast_value = self.builder.build_insert_value(target_type.get_undef(), data, 0, "data")
+                    .ok_or_else(|| "Invalid interface value".to_string())?;
+                let cast_value = self.builder.build_insert_value(cast_value, found, 1, "itable")
+                    .ok_or_else(|| "Invalid interface value".to_string())?;
+                return Ok(cast_value.into_struct_value().as_basic_value_enum());
+            }
+
+            let test = self.module.get_function("gard_rtti_is").unwrap_or_else(|| self.module.add_function(
+                "gard_rtti_is",
+                self.context.bool_type().fn_type(&[itable_type.into(), byte_ptr.into()], false),
+                None
+            ));
+            let matches = self.builder.build_call(test, &[itable.into(), target_info.into()], "is")
+                .try_as_basic_value()
+                .left()
+                .ok_or_else(|| "Invalid type test".to_string())?
+                .into_int_value();
+            let is = self.builder.build_and(non_null, matches, "is");
+            if !cast {
+                return Ok(is.as_basic_value_enum());
+            }
+            let target_type = self.get_struct_type(&target)?.ptr_type(AddressSpace::default());
+            let instance = self.builder.build_pointer_cast(data, target_type, &target);
+            return Ok(self.builder.build_select(is, instance, target_type.const_null(), &target));
+        }
+
+        let class = match value {
+            BasicValueEnum::PointerValue(pointer) => match pointer.get_type().get_element_type() {
+                AnyTypeEnum::StructType(class) => class.get_name().and_then(|name| name.to_str().ok()).map(str::to_string),
+                _ => None,
+            },
+            _ => None,
+        }.filter(|class| self.classes.contains_key(class))
+            .ok_or_else(|| "Only class instances and interface values have types to test".to_string())?;
+        let pointer = value.into_pointer_value();
+        let upcast = if target_is_interface {
+            self.implemented_interfaces(&class).contains(&target)
+        } else {
+            self.is_subclass(&class, &target)
+        };
+        if upcast {
+            return match (cast, target_is_interface) {
+                (false, _) => Ok(self.builder.build_is_not_null(pointer, "is").as_basic_value_enum()),
+                (true, true) => self.coerce(value, self.interface_type(&target).into()),
+                (true, false) => {
+                    let target_type = self.get_struct_type(&target)?.ptr_type(AddressSpace::default());
+                    Ok(self.builder.build_pointer_cast(pointer, target_type, &target).as_basic_value_enum())
+                },
+            };
+        }
+        if target_is_interface || self.is_subclass(&target, &class) {
+            return Err(format!("A {} instance carries no type info to test for {}; test it as an interface value", class, target));
+        }
+        // Unrelated classes
+        match cast {
+            false => Ok(self.context.bool_type().const_zero().as_basic_value_enum()),
+            true => Ok(self.get_struct_type(&target)?.ptr_type(AddressSpace::default()).const_null().as_basic_value_enum()),
+        }
+    }
+
+    /// Rebinds each narrowed variable holding an interface value to its
+    /// value as the narrower type, returning the bindings it replaced.
+    fn narrow(&mut self, narrowed: Vec<(Symbol, Type)>) -> Result<Vec<(String, PointerValue<'ctx>)>, String> {
+        let mut replaced = Vec::new();
+        for (name, target) in narrowed {
+            let Some(variable) = self.variables.get(name.as_str()).copied() else {
+                continue;
+            };
+            let holds_interface = match variable.get_type().get_element_type() {
+                AnyTypeEnum::StructType(struct_type) => struct_type.get_name()
+                    .and_then(|name| name.to_str().ok())
+                    .is_some_and(|name| self.interfaces.contains_key(name)),
+                _ => false,
+            };
+            if !holds_interface {
+                continue;
+            }
+            let value = self.compile_type_test(Node::Identifier(name), target, true)?;
+            let alloca = self.builder.build_alloca(value.get_type(), &name);
+            self.builder.build_store(alloca, value);
+            replaced.push((name.to_string(), variable));
+            self.variables.insert(name.to_string(), alloca);
+        }
+        Ok(replaced)
+    }
+
+    fn restore(&mut self, replaced: Vec<(String, PointerValue<'ctx>)>) {
+        for (name, variable) in replaced {
+            self.variables.insert(name, variable);
+        }
+    }
+
+    /// Converts a class instance to an interface value where one is
+    /// expected, pairing it with the class's itable. Other values are
+    /// returned as they are.
+    fn coerce(&mut self, value: BasicValueEnum<'ctx>, target: BasicTypeEnum<'ctx>) -> Result<BasicValueEnum<'ctx>, String> {
+        let (BasicValueEnum::PointerValue(pointer), BasicTypeEnum::StructType(target)) = (value, target) else {
+            return Ok(value);
+        };
+        let interface = match target.get_name().and_then(|name| name.to_str().ok()) {
+            Some(name) if self.interfaces.contains_key(name) => name.to_string(),
+            _ => return Ok(value),
+        };
+        let class = match pointer.get_type().get_element_type() {
+            AnyTypeEnum::StructType(class) => class.get_name().and_then(|name| name.to_str().ok()).map(str::to_string),
+            _ => None,
+        }.ok_or_else(|| format!("Only class instances can be {} values", interface))?;
+
+        let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let data = self.builder.build_pointer_cast(pointer, byte_ptr, "data");
+        let itable = self.itable(&class, &interface)?;
+        let value = self.builder.build_insert_value(target.get_undef(), data, 0, "data")
+            .ok_or_else(|| "Invalid interface value".to_string())?;
+        let value = self.builder.build_insert_value(value, itable, 1, &interface)
+            .ok_or_else(|| "Invalid interface value".to_string())?;
+        Ok(value.into_struct_value().as_basic_value_enum())
+    }
+
+    /// Calls an interface method through the value's itable, passing the
+    /// data pointer as the receiver.
+    fn compile_interface_call(&mut self, value: StructValue<'ctx>, interface: &str, property: &str, arguments: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        let (slot, method) = self.interfaces[interface].iter().enumerate()
+            .find(|(_, method)| method.name == property)
+            .map(|(slot, method)| (slot, method.clone()))
+            .ok_or_else(|| format!("Interface {} has no method {}", interface, property))?;
+        if arguments.len() != method.params.len() {
+            return Err(format!("{} expects {} argument(s), found {}", property, method.params.len(), arguments.len()));
+        }
+
+        let data = self.builder.build_extract_value(value, 0, "data")
+            .ok_or_else(|| "Invalid interface value".to_string())?
+            .into_pointer_value();
+        let itable = self.builder.build_extract_value(value, 1, "itable")
+            .ok_or_else(|| "Invalid interface value".to_string())?
+            .into_pointer_value();
+        self.build_null_check(data, &format!("Call to '{}' on null", property))?;
+        let entry = unsafe {
+            // The class's type info comes first
+            self.builder.build_in_bounds_gep(itable, &[self.context.i32_type().const_int(slot as u64 + 1, false)], property)
+        };
+        let function = self.builder.build_load(entry, property).into_pointer_value();
+        let function_type = self.method_type(&method)?;
+        let function = self.builder.build_pointer_cast(function, function_type.ptr_type(AddressSpace::default()), property);
+        let function = CallableValue::try_from(function)
+            .map_err(|_| format!("Invalid itable entry for {}", property))?;
+
+        let mut compiled_args: Vec<BasicMetadataValueEnum<'ctx>> = vec![data.into()];
+        for (argument, param) in arguments.into_iter().zip(&method.params) {
+            let value = self.compile_node(argument)?;
+            let param_type = self.get_llvm_type(&param.type_annotation)?;
+            compiled_args.push(self.coerce(value, param_type)?.into());
+        }
+        let result = self.builder.build_call(function, &compiled_args, property).try_as_basic_value().left();
+        Ok(result.unwrap_or_else(|| self.context.i64_type().const_zero().as_basic_value_enum()))
+    }
+
+    /// Loads a class field, or the length of an array.
+    fn compile_member(&mut self, object: Node, property: String) -> Result<BasicValueEnum<'ctx>, String> {
+        let object = self.compile_node(object)?;
+        self.member_of(object, property)
+    }
+
+    /// `compile_member` on an object that's already compiled.
+    fn member_of(&mut self, object: BasicValueEnum<'ctx>, property: String) -> Result<BasicValueEnum<'ctx>, String> {
+        let pointer = match object {
+            BasicValueEnum::PointerValue(pointer) => pointer,
+            _ => return Err(format!("Unsupported member access: {}", property)),
+        };
+        let struct_type = match pointer.get_type().get_element_type() {
+            AnyTypeEnum::StructType(struct_type) => struct_type,
+            _ => return Err(format!("Unsupported member access: {}", property)),
+        };
+        self.build_null_check(pointer, &format!("Access to '{}' on null", property))?;
+
+        let class = struct_type.get_name().and_then(|name| name.to_str().ok()).map(str::to_string);
+        match class {
+            Some(class) => {
+                let (field_pointer, field) = self.field_pointer(pointer, &class, &property)?;
+                let value = self.builder.build_load(field_pointer, &property);
+                if field == AbiType::Bool {
+                    let zero = self.context.i8_type().const_zero();
+                    let flag = self.builder.build_int_compare(inkwell::IntPredicate::NE, value.into_int_value(), zero, &property);
+                    Ok(flag.as_basic_value_enum())
+                } else {
+                    Ok(value)
+                }
+            },
+            None if property == "length" => {
+                let length_pointer = self.builder.build_struct_gep(pointer, 0, "length")
+                    .map_err(|_| "Invalid array header".to_string())?;
+                let length = self.builder.build_load(length_pointer, "length").into_int_value();
+                Ok(self.builder.build_int_z_extend(length, self.context.i64_type(), "length").as_basic_value_enum())
+            },
+            None => Err(format!("Unsupported member access: {}", property)),
+        }
+    }
+
+    /// A class field's pointer, with the field's type in the class's layout.
+    fn field_pointer(&mut self, pointer: PointerValue<'ctx>, class: &str, property: &str) -> Result<(PointerValue<'ctx>, AbiType), String> {
+        let (index, field) = self.interop.layout(class)
+            .and_then(|layout| layout.field(property))
+            .map(|(index, field)| (index, field.ty.clone()))
+            .ok_or_else(|| format!("Class {} has no field {}", class, property))?;
+        let field_pointer = self.builder.build_struct_gep(pointer, index as u32, property)
+            .map_err(|_| format!("Invalid field {} of class {}", property, class))?;
+        Ok((field_pointer, field))
+    }
+
+    /// Loads an array element, trapping first when the index is out of
+    /// bounds unless the check was elided.
+    fn compile_index(&mut self, object: Node, index: Node, checked: bool) -> Result<BasicValueEnum<'ctx>, String> {
+        let pointer = match self.compile_node(object)? {
+            BasicValueEnum::PointerValue(pointer) => pointer,
+            _ => return Err("Only arrays and strings can be indexed".to_string()),
+        };
+        if Self::is_string(pointer) {
+            return self.compile_string_index(pointer, index);
+        }
+        let element_pointer = self.element_pointer(pointer, index, checked)?;
+        Ok(self.load_element(element_pointer))
+    }
+
+    /// Loads an element, widening a stored boolean byte to an `i1`.
+    fn load_element(&mut self, element_pointer: PointerValue<'ctx>) -> BasicValueEnum<'ctx> {
+        let value = self.builder.build_load(element_pointer, "element");
+        if value.get_type() == self.context.i8_type().as_basic_type_enum() {
+            let flag = self.builder.build_int_compare(inkwell::IntPredicate::NE, value.into_int_value(), self.context.i8_type().const_zero(), "element");
+            flag.as_basic_value_enum()
+        } else {
+            value
+        }
+    }
+
+    /// Whether a pointer is a string's rather than an array's or object's.
+    fn is_string(pointer: PointerValue<'ctx>) -> bool {
+        pointer.get_type().get_element_type().is_int_type()
+    }
+
+    /// `s[i]`: the `i`th character of a string, as a new string. Strings are
+    /// counted in characters like in the interpreter, so gard-vm decodes the
+    /// UTF-8 and traps when the index is out of bounds, whether or not
+    /// array bounds checks were elided.
+    fn compile_string_index(&mut self, text: PointerValue<'ctx>, index: Node) -> Result<BasicValueEnum<'ctx>, String> {
+        let index = match self.compile_node(index)? {
+            BasicValueEnum::IntValue(index) => self.builder.build_int_cast(index, self.context.i64_type(), "index"),
+            _ => return Err("String index must be an integer".to_string()),
+        };
+        self.build_null_check(text, "Index into null")?;
+        self.build_runtime_call("gard_string_char_at", "char", &[Type::String, Type::Int], &Type::String, &[text.into(), index.into()])
+    }
+
+    /// An array element's pointer, with the same checks as `compile_index`.
+    fn element_pointer(&mut self, pointer: PointerValue<'ctx>, index: Node, checked: bool) -> Result<PointerValue<'ctx>, String> {
+        if Self::is_string(pointer) {
+            return Err("Strings can't be changed in place".to_string());
+        }
+        let index = match self.compile_node(index)? {
+            BasicValueEnum::IntValue(index) => self.builder.build_int_cast(index, self.context.i64_type(), "index"),
+            _ => return Err("Array index must be an integer".to_string()),
+        };
+        self.build_null_check(pointer, "Index into null")?;
+
+        if checked {
+            let length_pointer = self.builder.build_struct_gep(pointer, 0, "length")
+                .map_err(|_| "Invalid array header".to_string())?;
+            let length = self.builder.build_load(length_pointer, "length").into_int_value();
+            let length = self.builder.build_int_z_extend(length, self.context.i64_type(), "length");
+            // Unsigned, so negative indices are out of bounds too
+            let out_of_bounds = self.builder.build_int_compare(inkwell::IntPredicate::UGE, index, length, "bounds");
+            self.build_located_trap(out_of_bounds, "bounds", "Index out of bounds")?;
+        }
+
+        let zero = self.context.i32_type().const_zero();
+        Ok(unsafe {
+            self.builder.build_in_bounds_gep(pointer, &[zero, self.context.i32_type().const_int(1, false), index], "element")
+        })
+    }
+
+    /// `++` and `--` on a variable, a class field or an array element: loads
+    /// it, steps it by one with the same overflow checks as `+` and `-`, and
+    /// stores it back. The prefix forms give the new value and the postfix
+    /// forms the old one.
+    fn compile_increment(&mut self, operator: UnaryOp, operand: Node) -> Result<BasicValueEnum<'ctx>, String> {
+        let pointer = match operand.into_unlocated() {
+            Node::Identifier(name) => match self.variables.get(name.as_str()) {
+                Some(variable) => *variable,
+                None => {
+                    let ty = *self.storage.get(name.as_str()).ok_or_else(|| format!("Undefined variable: {}", name))?;
+                    let old = self.compile_storage_read(&name, ty)?;
+                    let new = self.build_step(&operator, old)?;
+                    self.compile_storage_write(&name, new)?;
+                    return Ok(Self::stepped(&operator, old, new));
+                },
+            },
+            Node::Member { object, property } => {
+                let pointer = match self.compile_node(*object)? {
+                    BasicValueEnum::PointerValue(pointer) => pointer,
+                    _ => return Err(format!("Unsupported member access: {}", property)),
+                };
+                let class = match pointer.get_type().get_element_type() {
+                    AnyTypeEnum::StructType(struct_type) => struct_type.get_name().and_then(|name| name.to_str().ok()).map(str::to_string),
+                    _ => None,
+                };
+                let class = class.ok_or_else(|| format!("Only class fields can be incremented, not {}", property))?;
+                self.build_null_check(pointer, &format!("Access to '{}' on null", property))?;
+                self.field_pointer(pointer, &class, &property)?.0
+            },
+            Node::Index { object, index, checked } => match self.compile_node(*object)? {
+                BasicValueEnum::PointerValue(pointer) => self.element_pointer(pointer, *index, checked)?,
+                _ => return Err("Only arrays can be indexed".to_string()),
+            },
+            _ => return Err("Operators ++ and -- need a variable, field or element".to_string()),
+        };
+        let old = self.builder.build_load(pointer, "old");
+        let new = self.build_step(&operator, old)?;
+        self.builder.build_store(pointer, new);
+        Ok(Self::stepped(&operator, old, new))
+    }
+
+    /// `value` plus or minus one for `++` or `--`.
+    fn build_step(&mut self, operator: &UnaryOp, value: BasicValueEnum<'ctx>) -> Result<BasicValueEnum<'ctx>, String> {
+        let step = match operator {
+            UnaryOp::Increment | UnaryOp::PostIncrement => BinaryOp::Add,
+            _ => BinaryOp::Sub,
+        };
+        match value {
+            BasicValueEnum::IntValue(value) => {
+                let one = value.get_type().const_int(1, false);
+                if matches!(value.get_type().get_bit_width(), 160 | 256) {
+                    return self.compile_checked_unsigned_op(value, step, one);
+                }
+                if self.overflow_checks {
+                    let (value, overflow) = self.build_overflowing_op(value, &step, one, true)?;
+                    self.build_located_trap(overflow, "overflow", "Integer overflow")?;
+                    return Ok(value);
+                }
+                Ok(match step {
+                    BinaryOp::Add => self.builder.build_int_add(value, one, "inctmp"),
+                    _ => self.builder.build_int_sub(value, one, "dectmp"),
+                }.into())
+            },
+            BasicValueEnum::FloatValue(value) => {
+                let one = value.get_type().const_float(1.0);
+                Ok(match step {
+                    BinaryOp::Add => self.builder.build_float_add(value, one, "inctmp"),
+                    _ => self.builder.build_float_sub(value, one, "dectmp"),
+                }.into())
+            },
+            _ => Err("Operators ++ and -- expect a numeric operand".to_string()),
+        }
+    }
+
+    /// The value of a `++` or `--` expression.
+    fn stepped(operator: &UnaryOp, old: BasicValueEnum<'ctx>, new: BasicValueEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        match operator {
+            UnaryOp::PostIncrement | UnaryOp::PostDecrement => old,
+            _ => new,
+        }
+    }
+
     fn get_node_type(&self, node: &Node) -> Result<BasicTypeEnum<'ctx>, String> {
         match node {
             Node::IntLiteral(_) => Ok(self.context.i64_type().as_basic_type_enum()),
             Node::FloatLiteral(_) => Ok(self.context.f64_type().as_basic_type_enum()),
             Node::StringLiteral(_) => Ok(self.context.i8_type().ptr_type(AddressSpace::default()).as_basic_type_enum()),
             Node::BooleanLiteral(_) => Ok(self.context.bool_type().as_basic_type_enum()),
+            Node::UInt256Literal(_) => Ok(self.context.custom_width_int_type(256).as_basic_type_enum()),
             _ => Err(format!("Cannot infer type for node: {:?}", node)),
         }
     }
@@ -239,6 +2275,8 @@ impl<'ctx> Compiler<'ctx> {
     fn compile_if(&mut self, condition: Node, then_branch: Node, else_branch: Option<Node>) 
         -> Result<BasicValueEnum<'ctx>, String> 
     {
+        let then_narrowed = narrowings(&condition, true);
+        let else_narrowed = narrowings(&condition, false);
         let condition_value = self.compile_node(condition)?;
         let function = self.builder.get_insert_block().unwrap().get_parent().unwrap();
         
@@ -254,23 +2292,31 @@ impl<'ctx> Compiler<'ctx> {
 
         // Compile then branch
         self.builder.position_at_end(then_block);
+        let replaced = self.narrow(then_narrowed)?;
         let then_value = self.compile_node(then_branch)?;
+        self.restore(replaced);
+        // A nested branch or loop leaves the builder in a later block
+        let then_end = self.builder.get_insert_block().unwrap();
         self.builder.build_unconditional_branch(merge_block);
 
         // Compile else branch
         self.builder.position_at_end(else_block);
         let else_value = if let Some(else_branch) = else_branch {
-            self.compile_node(else_branch)?
+            let replaced = self.narrow(else_narrowed)?;
+            let else_value = self.compile_node(else_branch)?;
+            self.restore(replaced);
+            else_value
         } else {
             // Return void if no else branch
             self.context.i64_type().const_int(0, false).as_basic_value_enum()
         };
+        let else_end = self.builder.get_insert_block().unwrap();
         self.builder.build_unconditional_branch(merge_block);
 
         // Merge block
         self.builder.position_at_end(merge_block);
         let phi = self.builder.build_phi(then_value.get_type(), "if_result");
-        phi.add_incoming(&[(&then_value, then_block), (&else_value, else_block)]);
+        phi.add_incoming(&[(&then_value, then_end), (&else_value, else_end)]);
 
         Ok(phi.as_basic_value())
     }
@@ -287,6 +2333,7 @@ impl<'ctx> Compiler<'ctx> {
         self.builder.position_at_end(cond_block);
 
         // Compile condition
+        let narrowed = narrowings(&condition, true);
         let condition_value = self.compile_node(condition)?;
         self.builder.build_conditional_branch(
             condition_value.into_int_value(),
@@ -296,7 +2343,9 @@ impl<'ctx> Compiler<'ctx> {
 
         // Compile body
         self.builder.position_at_end(body_block);
+        let replaced = self.narrow(narrowed)?;
         self.compile_node(body)?;
+        self.restore(replaced);
         self.builder.build_unconditional_branch(cond_block);
 
         // Continue at end block
@@ -305,6 +2354,91 @@ impl<'ctx> Compiler<'ctx> {
         Ok(self.context.i64_type().const_int(0, false).as_basic_value_enum())
     }
 
+    /// `foreach` over an array's elements or a string's characters, which
+    /// gard-vm decodes one at a time. The item holds a copy of each.
+    fn compile_foreach(&mut self, item: String, collection: Node, body: Node) -> Result<BasicValueEnum<'ctx>, String> {
+        let pointer = match self.compile_node(collection)? {
+            BasicValueEnum::PointerValue(pointer) => pointer,
+            _ => return Err("Only arrays and strings can be iterated".to_string()),
+        };
+        self.build_null_check(pointer, "Iteration over null")?;
+        let function = self.builder.get_insert_block().unwrap().get_parent().unwrap();
+        let i64_type = self.context.i64_type();
+        let string_type = self.context.i8_type().ptr_type(AddressSpace::default());
+
+        let item_type = if Self::is_string(pointer) {
+            string_type.as_basic_type_enum()
+        } else {
+            let element = match pointer.get_type().get_element_type() {
+                AnyTypeEnum::StructType(array) => array.get_field_type_at_index(1),
+                _ => None,
+            };
+            match element {
+                Some(BasicTypeEnum::ArrayType(elements)) if elements.get_element_type() == self.context.i8_type().as_basic_type_enum() => {
+                    self.context.bool_type().as_basic_type_enum()
+                },
+                Some(BasicTypeEnum::ArrayType(elements)) => elements.get_element_type(),
+                _ => return Err("Only arrays and strings can be iterated".to_string()),
+            }
+        };
+        let slot = self.builder.build_alloca(item_type, &item);
+        // A string's byte offset, or an array's index
+        let position = self.builder.build_alloca(i64_type, "foreach.position");
+        self.builder.build_store(position, i64_type.const_zero());
+
+        let cond_block = self.context.append_basic_block(function, "foreach.cond");
+        let body_block = self.context.append_basic_block(function, "foreach.body");
+        let end_block = self.context.append_basic_block(function, "foreach.end");
+        self.builder.build_unconditional_branch(cond_block);
+        self.builder.position_at_end(cond_block);
+
+        let element = if Self::is_string(pointer) {
+            let next = self.module.get_function("gard_string_next").unwrap_or_else(|| {
+                let function_type = string_type.fn_type(&[string_type.into(), i64_type.ptr_type(AddressSpace::default()).into()], false);
+                self.module.add_function("gard_string_next", function_type, None)
+            });
+            let character = self.builder.build_call(next, &[pointer.into(), position.into()], "char")
+                .try_as_basic_value()
+                .left()
+                .ok_or_else(|| "Invalid call result".to_string())?
+                .into_pointer_value();
+            let done = self.builder.build_is_null(character, "foreach.done");
+            self.builder.build_conditional_branch(done, end_block, body_block);
+            self.builder.position_at_end(body_block);
+            character.as_basic_value_enum()
+        } else {
+            let length_pointer = self.builder.build_struct_gep(pointer, 0, "length")
+                .map_err(|_| "Invalid array header".to_string())?;
+            let length = self.builder.build_load(length_pointer, "length").into_int_value();
+            let length = self.builder.build_int_z_extend(length, i64_type, "length");
+            let index = self.builder.build_load(position, "index").into_int_value();
+            let more = self.builder.build_int_compare(inkwell::IntPredicate::ULT, index, length, "foreach.more");
+            self.builder.build_conditional_branch(more, body_block, end_block);
+            self.builder.position_at_end(body_block);
+            let next = self.builder.build_int_add(index, i64_type.const_int(1, false), "foreach.next");
+            self.builder.build_store(position, next);
+            let zero = self.context.i32_type().const_zero();
+            let element_pointer = unsafe {
+                self.builder.build_in_bounds_gep(pointer, &[zero, self.context.i32_type().const_int(1, false), index], "element")
+            };
+            self.load_element(element_pointer)
+        };
+        self.builder.build_store(slot, element);
+
+        let outer = self.variables.insert(item.clone(), slot);
+        self.compile_node(body)?;
+        match outer {
+            Some(variable) => self.variables.insert(item, variable),
+            None => self.variables.remove(&item),
+        };
+        if self.builder.get_insert_block().and_then(|block| block.get_terminator()).is_none() {
+            self.builder.build_unconditional_branch(cond_block);
+        }
+        self.builder.position_at_end(end_block);
+
+        Ok(i64_type.const_int(0, false).as_basic_value_enum())
+    }
+
     fn compile_block(&mut self, statements: Vec<Node>) -> Result<BasicValueEnum<'ctx>, String> {
         let mut last_value = self.context.i64_type().const_int(0, false).as_basic_value_enum();
         
@@ -316,9 +2450,33 @@ impl<'ctx> Compiler<'ctx> {
     }
 
     fn compile_return(&mut self, value: Option<Node>) -> Result<BasicValueEnum<'ctx>, String> {
+        // In a body `compile_try_catch` outlined, leave the value for the
+        // function the try is in to return
+        if let Some(&flow) = self.variables.get(TRY_FLOW) {
+            let slot = self.variables.get(TRY_RETURN).copied();
+            if let Some(value) = value {
+                let return_value = self.compile_node(value)?;
+                if let Some(slot) = slot {
+                    let return_type = BasicTypeEnum::try_from(slot.get_type().get_element_type())
+                        .map_err(|_| "Invalid return slot".to_string())?;
+                    let return_value = self.coerce(return_value, return_type)?;
+                    self.builder.build_store(slot, return_value);
+                }
+            }
+            self.builder.build_store(flow, self.context.bool_type().const_int(1, false));
+            self.builder.build_return(None);
+            return Ok(self.context.i64_type().const_int(0, false).as_basic_value_enum());
+        }
         match value {
             Some(value) => {
                 let return_value = self.compile_node(value)?;
+                let return_type = self.builder.get_insert_block()
+                    .and_then(|block| block.get_parent())
+                    .and_then(|function| function.get_type().get_return_type());
+                let return_value = match return_type {
+                    Some(return_type) => self.coerce(return_value, return_type)?,
+                    None => return_value,
+                };
                 self.builder.build_return(Some(&return_value));
             },
             None => {
@@ -335,8 +2493,8 @@ impl<'ctx> Compiler<'ctx> {
                 // Create actor class type
                 let actor_type = self.context.opaque_struct_type(&name);
                 let field_types = vec![
-                    self.get_llvm_type(&Type::Custom("MessageQueue".to_string()))?,
-                    self.get_llvm_type(&Type::Custom("ActorBehavior".to_string()))?,
+                    self.get_llvm_type(&Type::Custom("MessageQueue".into()))?,
+                    self.get_llvm_type(&Type::Custom("ActorBehavior".into()))?,
                 ];
                 actor_type.set_body(&field_types, false);
 
@@ -479,44 +2637,204 @@ impl<'ctx> Compiler<'ctx> {
         }
     }
 
-    fn compile_try_catch(&mut self, body: Node, catch_clauses: Vec<Node>, finally: Option<Node>) 
-        -> Result<BasicValueEnum<'ctx>, String> 
+    /// Runs the try body, and the first catch clause's if it raises, as
+    /// functions of their own under gard-vm's `gard_try`, which catches
+    /// the errors `gard_raise` unwinds with. The finally body runs after
+    /// them, then an error no clause caught is raised again. A `return` in
+    /// an outlined body sets `try.flow` and leaves its value in
+    /// `try.return`, and the function returns once the finally body has
+    /// run. A wasm contract aborts on an error instead, so its catch
+    /// clauses never run.
+    fn compile_try_catch(&mut self, body: Node, catch_clauses: Vec<Node>, finally: Option<Node>)
+        -> Result<BasicValueEnum<'ctx>, String>
     {
-        // Create basic blocks for try, catch, finally and continue
-        let function = self.builder.get_insert_block().unwrap().get_parent().unwrap();
-        let try_block = self.context.append_basic_block(function, "try");
-        let catch_block = self.context.append_basic_block(function, "catch");
-        let finally_block = self.context.append_basic_block(function, "finally");
-        let continue_block = self.context.append_basic_block(function, "continue");
-
-        // Build try block
-        self.builder.position_at_end(try_block);
-        let try_result = self.compile_node(body)?;
-        self.builder.build_unconditional_branch(finally_block);
+        let done = self.context.i64_type().const_int(0, false).as_basic_value_enum();
+        if self.wasm_contract {
+            self.compile_node(body)?;
+            if let Some(finally_body) = finally {
+                if self.builder.get_insert_block().and_then(|block| block.get_terminator()).is_none() {
+                    self.compile_node(finally_body)?;
+                }
+            }
+            return Ok(done);
+        }
 
-        // Build catch block
-        self.builder.position_at_end(catch_block);
-        for catch_clause in catch_clauses {
-            self.compile_node(catch_clause)?;
+        let function = self.builder.get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| "try outside of a function".to_string())?;
+        let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        // A try in an outlined body shares the flag of the function it's in
+        let outermost = !self.variables.contains_key(TRY_FLOW);
+        if outermost {
+            let flow = self.build_entry_alloca(function, self.context.bool_type(), TRY_FLOW);
+            self.builder.build_store(flow, self.context.bool_type().const_zero());
+            self.variables.insert(TRY_FLOW.to_string(), flow);
+            if let Some(return_type) = function.get_type().get_return_type() {
+                let slot = self.build_entry_alloca(function, return_type, TRY_RETURN);
+                self.variables.insert(TRY_RETURN.to_string(), slot);
+            }
+        }
+        let flow = self.variables[TRY_FLOW];
+        let return_slot = self.variables.get(TRY_RETURN).copied();
+
+        let error = self.build_entry_alloca(function, byte_ptr, "try.error");
+        let raised = self.build_outlined_try(function, "try", body)?;
+        self.builder.build_store(error, raised);
+
+        let clause = catch_clauses.into_iter().find_map(|clause| match clause.into_unlocated() {
+            Node::CatchClause { param_name, body, .. } => Some((param_name, *body)),
+            _ => None,
+        });
+        if let Some((binding, handler)) = clause {
+            let catch_block = self.context.append_basic_block(function, "catch");
+            let finally_block = self.context.append_basic_block(function, "finally");
+            let is_raised = self.builder.build_is_not_null(raised, "raised");
+            self.builder.build_conditional_branch(is_raised, catch_block, finally_block);
+
+            self.builder.position_at_end(catch_block);
+            let catch = self.module.get_function("gard_catch").unwrap_or_else(|| {
+                self.module.add_function("gard_catch", byte_ptr.fn_type(&[byte_ptr.into()], false), None)
+            });
+            let message = self.builder.build_call(catch, &[raised.into()], "message")
+                .try_as_basic_value()
+                .left()
+                .ok_or_else(|| "Invalid call result".to_string())?;
+            let slot = self.build_entry_alloca(function, byte_ptr, &binding);
+            self.builder.build_store(slot, message);
+            let outer = self.variables.insert(binding.clone(), slot);
+            let raised = self.build_outlined_try(function, "catch", handler);
+            match outer {
+                Some(variable) => self.variables.insert(binding, variable),
+                None => self.variables.remove(&binding),
+            };
+            self.builder.build_store(error, raised?);
+            self.builder.build_unconditional_branch(finally_block);
+            self.builder.position_at_end(finally_block);
         }
-        self.builder.build_unconditional_branch(finally_block);
 
-        // Build finally block
-        self.builder.position_at_end(finally_block);
+        if outermost {
+            self.variables.remove(TRY_FLOW);
+            self.variables.remove(TRY_RETURN);
+        }
         if let Some(finally_body) = finally {
             self.compile_node(finally_body)?;
+            if self.builder.get_insert_block().and_then(|block| block.get_terminator()).is_some() {
+                return Ok(done);
+            }
+        }
+
+        // Raise what wasn't caught, then return if a body did
+        let reraise_block = self.context.append_basic_block(function, "try.reraise");
+        let settled_block = self.context.append_basic_block(function, "try.settled");
+        let return_block = self.context.append_basic_block(function, "try.return");
+        let continue_block = self.context.append_basic_block(function, "try.continue");
+        let pending = self.builder.build_load(error, "pending").into_pointer_value();
+        let is_pending = self.builder.build_is_not_null(pending, "pending");
+        self.builder.build_conditional_branch(is_pending, reraise_block, settled_block);
+
+        self.builder.position_at_end(reraise_block);
+        let reraise = self.module.get_function("gard_reraise").unwrap_or_else(|| {
+            self.module.add_function("gard_reraise", self.context.void_type().fn_type(&[byte_ptr.into()], false), None)
+        });
+        self.builder.build_call(reraise, &[pending.into()], "reraise");
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(settled_block);
+        let returned = self.builder.build_load(flow, "returned").into_int_value();
+        self.builder.build_conditional_branch(returned, return_block, continue_block);
+
+        self.builder.position_at_end(return_block);
+        match return_slot.filter(|_| outermost) {
+            Some(slot) => {
+                let value = self.builder.build_load(slot, "returned");
+                self.builder.build_return(Some(&value));
+            },
+            None => {
+                self.builder.build_return(None);
+            },
         }
-        self.builder.build_unconditional_branch(continue_block);
 
-        // Continue block
         self.builder.position_at_end(continue_block);
-        Ok(try_result)
+        Ok(done)
+    }
+
+    /// Compiles `body` as an internal function and calls it under
+    /// `gard_try`, returning the error it raised, or null. The function
+    /// reaches the variables in scope through an array of their addresses.
+    fn build_outlined_try(&mut self, function: FunctionValue<'ctx>, label: &str, body: Node) -> Result<PointerValue<'ctx>, String> {
+        let byte_ptr = self.context.i8_type().ptr_type(AddressSpace::default());
+        let i32_type = self.context.i32_type();
+        let variables: Vec<(String, PointerValue<'ctx>)> = self.variables.iter()
+            .map(|(name, address)| (name.clone(), *address))
+            .collect();
+        let env_type = byte_ptr.array_type(variables.len() as u32);
+        let env = self.build_entry_alloca(function, env_type, "try.env");
+        for (i, (name, address)) in variables.iter().enumerate() {
+            let slot = unsafe {
+                self.builder.build_in_bounds_gep(env, &[i32_type.const_zero(), i32_type.const_int(i as u64, false)], name)
+            };
+            self.builder.build_store(slot, self.builder.build_pointer_cast(*address, byte_ptr, name));
+        }
+
+        let body_type = self.context.void_type().fn_type(&[byte_ptr.into()], false);
+        let name = format!("{}.{}", function.get_name().to_str().unwrap_or_default(), label);
+        let outlined = self.module.add_function(&name, body_type, Some(Linkage::Internal));
+        let resume = self.builder.get_insert_block()
+            .ok_or_else(|| "try outside of a function".to_string())?;
+        self.builder.position_at_end(self.context.append_basic_block(outlined, "entry"));
+        let outlined_env = outlined.get_first_param()
+            .ok_or_else(|| "Missing try environment".to_string())?
+            .into_pointer_value();
+        let outlined_env = self.builder.build_pointer_cast(outlined_env, env_type.ptr_type(AddressSpace::default()), "try.env");
+        let outer = self.variables.clone();
+        for (i, (name, address)) in variables.iter().enumerate() {
+            let slot = unsafe {
+                self.builder.build_in_bounds_gep(outlined_env, &[i32_type.const_zero(), i32_type.const_int(i as u64, false)], name)
+            };
+            let captured = self.builder.build_load(slot, name).into_pointer_value();
+            self.variables.insert(name.clone(), self.builder.build_pointer_cast(captured, address.get_type(), name));
+        }
+        let result = self.compile_node(body);
+        self.variables = outer;
+        result?;
+        if self.builder.get_insert_block().and_then(|block| block.get_terminator()).is_none() {
+            self.builder.build_return(None);
+        }
+        self.builder.position_at_end(resume);
+
+        let try_function = self.module.get_function("gard_try").unwrap_or_else(|| {
+            let body_ptr = body_type.ptr_type(AddressSpace::default());
+            self.module.add_function("gard_try", byte_ptr.fn_type(&[body_ptr.into(), byte_ptr.into()], false), None)
+        });
+        let env = self.builder.build_pointer_cast(env, byte_ptr, "try.env");
+        let arguments = [outlined.as_global_value().as_pointer_value().into(), env.into()];
+        Ok(self.builder.build_call(try_function, &arguments, "try.raised")
+            .try_as_basic_value()
+            .left()
+            .ok_or_else(|| "Invalid call result".to_string())?
+            .into_pointer_value())
+    }
+
+    /// Allocates a stack slot in the entry block of `function`, so a slot
+    /// for code in a loop isn't allocated again on each iteration.
+    fn build_entry_alloca<T: BasicType<'ctx>>(&self, function: FunctionValue<'ctx>, ty: T, name: &str) -> PointerValue<'ctx> {
+        let builder = self.context.create_builder();
+        if let Some(entry) = function.get_first_basic_block() {
+            match entry.get_first_instruction() {
+                Some(instruction) => builder.position_before(&instruction),
+                None => builder.position_at_end(entry),
+            }
+        }
+        builder.build_alloca(ty, name)
     }
 
     fn compile_match(&mut self, value: Node, cases: Vec<MatchCase>) 
         -> Result<BasicValueEnum<'ctx>, String> 
     {
         let value_result = self.compile_node(value)?;
+        if let Some(union) = self.union_of(value_result) {
+            return self.compile_union_match(&union, value_result, cases);
+        }
         let function = self.builder.get_insert_block().unwrap().get_parent().unwrap();
         
         let mut case_blocks = Vec::new();
@@ -529,21 +2847,19 @@ impl<'ctx> Compiler<'ctx> {
         }
 
         // Build switch instruction
-        self.builder.build_switch(
-            value_result.into_int_value(),
-            default_block,
-            &cases.iter().enumerate().map(|(i, case)| {
-                let pattern = self.compile_node(case.pattern.clone())
-                    .unwrap()
-                    .into_int_value();
-                (pattern, case_blocks[i])
-            }).collect::<Vec<_>>()
-        );
+        let mut switch_cases = Vec::new();
+        let mut bodies = Vec::new();
+        for (MatchCase { pattern, body }, block) in cases.into_iter().zip(&case_blocks) {
+            let pattern = self.compile_node(pattern)?.into_int_value();
+            switch_cases.push((pattern, *block));
+            bodies.push((*block, body));
+        }
+        self.builder.build_switch(value_result.into_int_value(), default_block, &switch_cases);
 
         // Build case blocks
-        for (i, case) in cases.iter().enumerate() {
-            self.builder.position_at_end(case_blocks[i]);
-            self.compile_node(case.body.clone())?;
+        for (block, body) in bodies {
+            self.builder.position_at_end(block);
+            self.compile_node(body)?;
             self.builder.build_unconditional_branch(continue_block);
         }
 
@@ -556,6 +2872,90 @@ impl<'ctx> Compiler<'ctx> {
         Ok(value_result)
     }
 
+    /// Switches on a tagged union's tag. A `Tag(a, b)` case binds its
+    /// names to the payload slots of a copy of the value, and `_` is the
+    /// default.
+    fn compile_union_match(&mut self, union: &str, value: BasicValueEnum<'ctx>, cases: Vec<MatchCase>)
+        -> Result<BasicValueEnum<'ctx>, String>
+    {
+        let function = self.builder.get_insert_block()
+            .and_then(|block| block.get_parent())
+            .ok_or_else(|| "match outside of a function".to_string())?;
+        let pointer = self.builder.build_alloca(value.get_type(), "match");
+        self.builder.build_store(pointer, value);
+        let tag_pointer = self.builder.build_struct_gep(pointer, 0, "tag")
+            .map_err(|_| format!("Invalid union {}", union))?;
+        let tag = self.builder.build_load(tag_pointer, "tag").into_int_value();
+
+        let default_block = self.context.append_basic_block(function, "match.default");
+        let continue_block = self.context.append_basic_block(function, "match.continue");
+        let mut switch_cases = Vec::new();
+        let mut bodies = Vec::new();
+        let mut default = None;
+        for (i, MatchCase { pattern, body }) in cases.into_iter().enumerate() {
+            let (variant, bindings) = match pattern.into_unlocated() {
+                Node::Identifier(name) if name == "_" => {
+                    default = default.or(Some(body));
+                    continue;
+                },
+                Node::Identifier(name) => (name, Vec::new()),
+                Node::Call { callee, arguments } => match callee.into_unlocated() {
+                    Node::Identifier(name) => (name, arguments),
+                    _ => return Err(format!("Unsupported pattern in a match on {}", union)),
+                },
+                _ => return Err(format!("Unsupported pattern in a match on {}", union)),
+            };
+            let (_, index, payload) = self.tag(&variant)
+                .filter(|(owner, _, _)| owner == union)
+                .ok_or_else(|| format!("'{}' is not a variant of {}", variant, union))?;
+            let index = self.context.i32_type().const_int(index as u64, false);
+            // Only the first case for a tag can match
+            if switch_cases.iter().any(|(tag, _)| *tag == index) {
+                continue;
+            }
+            let block = self.context.append_basic_block(function, &format!("match.case{}", i));
+            switch_cases.push((index, block));
+            bodies.push((block, variant, payload, bindings, body));
+        }
+        self.builder.build_switch(tag, default_block, &switch_cases);
+
+        for (block, variant, payload, bindings, body) in bodies {
+            self.builder.position_at_end(block);
+            let mut replaced = Vec::new();
+            for (slot, (binding, ty)) in bindings.iter().zip(&payload).enumerate() {
+                let name = match binding.unlocated() {
+                    Node::Identifier(name) if name == "_" => continue,
+                    Node::Identifier(name) => name,
+                    _ => return Err(format!("A pattern of variant '{}' can only bind names", variant)),
+                };
+                let ty = self.get_llvm_type(ty)?;
+                let slot = self.payload_slot(pointer, slot, ty, &variant)?;
+                replaced.push((name.to_string(), self.variables.insert(name.to_string(), slot)));
+            }
+            self.compile_node(body)?;
+            for (name, variable) in replaced {
+                match variable {
+                    Some(variable) => self.variables.insert(name, variable),
+                    None => self.variables.remove(&name),
+                };
+            }
+            if self.builder.get_insert_block().and_then(|block| block.get_terminator()).is_none() {
+                self.builder.build_unconditional_branch(continue_block);
+            }
+        }
+
+        self.builder.position_at_end(default_block);
+        if let Some(body) = default {
+            self.compile_node(body)?;
+        }
+        if self.builder.get_insert_block().and_then(|block| block.get_terminator()).is_none() {
+            self.builder.build_unconditional_branch(continue_block);
+        }
+
+        self.builder.position_at_end(continue_block);
+        Ok(value)
+    }
+
     fn compile_loop(&mut self, condition: Option<Node>, body: Node, is_do_while: bool) 
         -> Result<BasicValueEnum<'ctx>, String> 
     {
@@ -597,11 +2997,171 @@ impl<'ctx> Compiler<'ctx> {
     }
 }
 
+/// Compiles a plain wasm module whose `@WasmExport` functions are called from
+/// JS, and writes the object file to `output`. Pair it with the bindings from
+/// `typescript::generate`.
+pub fn build_wasm_module(program: Node, module_name: &str, output: &Path, options: &CodegenOptions) -> Result<(), String> {
+    let context = Context::create();
+    let mut compiler = Compiler::new(&context, module_name);
+    compiler.configure(options);
+    compiler.module.set_triple(&TargetTriple::create(wasm::TARGET_TRIPLE));
+    let profile = options.profile.as_deref();
+    profile::time(profile, "codegen", || compiler.compile(program))?;
+    profile::time(profile, "llvm object", || compiler.write_wasm_object(output))
+}
+
+/// Compiles the single contract of `program` for the wasm contract target
+/// and writes the object file to `output`.
+pub fn build_wasm_contract(program: Node, output: &Path, options: &CodegenOptions) -> Result<wasm::ContractAbi, String> {
+    let mut contracts: Vec<Node> = match program {
+        Node::Program(nodes) => nodes.into_iter()
+            .filter(|node| matches!(node, Node::Contract { .. }))
+            .collect(),
+        _ => return Err("Expected program node".to_string()),
+    };
+    if contracts.len() != 1 {
+        return Err(format!("The wasm contract target expects exactly one contract per file, found {}", contracts.len()));
+    }
+
+    let contract = contracts.remove(0);
+    let name = match &contract {
+        Node::Contract { name, .. } => name.clone(),
+        _ => unreachable!(),
+    };
+
+    let context = Context::create();
+    let mut compiler = Compiler::new(&context, &name);
+    compiler.configure(options);
+    let profile = options.profile.as_deref();
+    let abi = profile::time(profile, "codegen", || compiler.compile_wasm_contract(contract))?;
+    profile::time(profile, "llvm object", || compiler.write_wasm_object(output))?;
+    Ok(abi)
+}
+
+/// Compiles `program` for the host and writes it to `output` as a library
+/// whose only global symbols are its `@export`ed functions, and the
+/// runtime's if it's linked in. `cheader::generate` writes the matching
+/// header. The object files go through `llvm-ar` for a static library
+/// and through `cc` for a shared one, or `clang` and `lld` with LTO, so
+/// those must be on the `PATH`. With more than one codegen unit, each is
+/// compiled on a thread of its own.
+pub fn build_native_library(program: Node, name: &str, output: &Path, kind: LibraryKind, runtime: Runtime, options: &CodegenOptions) -> Result<(), String> {
+    let exports: Vec<String> = cheader::exports(&program).into_iter()
+        .filter_map(|function| match function {
+            Node::Function { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    if exports.is_empty() {
+        return Err("A library needs at least one function marked @export".to_string());
+    }
+    machine::check_library(kind, options.relocation_model)?;
+    let runtime = match runtime {
+        Runtime::Linked => Some(library::find_runtime()?),
+        Runtime::Separate => None,
+    };
+
+    let profile = options.profile.as_deref();
+    let units = units::count(options.codegen_units, &program);
+    let objects: Vec<PathBuf> = (0..units).map(|unit| units::object_path(output, unit, units)).collect();
+    let compiled = match units {
+        1 => compile_library_unit(program, name, &exports, 0, 1, &objects[0], options, profile),
+        _ => profile::time(profile, "codegen units", || std::thread::scope(|scope| {
+            let workers: Vec<_> = objects.iter().enumerate()
+                .map(|(unit, object)| {
+                    let (program, exports) = (program.clone(), &exports);
+                    scope.spawn(move || compile_library_unit(program, name, exports, unit, units, object, options, None))
+                })
+                .collect();
+            // Joined in order, so the same unit's error is reported every time
+            workers.into_iter()
+                .map(|worker| worker.join().unwrap_or_else(|_| Err("A codegen unit panicked".to_string())))
+                .collect::<Result<(), String>>()
+        })),
+    };
+    let linked = compiled.and_then(|()| {
+        profile::time(profile, "link", || library::link(kind, &objects, runtime.as_deref(), options.lto, output))
+    });
+    for object in &objects {
+        let _ = std::fs::remove_file(object);
+    }
+    linked
+}
+
+/// Compiles codegen unit `unit` of `units` of a library's `program` to
+/// `object`: machine code, or with LTO, bitcode.
+#[allow(clippy::too_many_arguments)]
+fn compile_library_unit(program: Node, name: &str, exports: &[String], unit: usize, units: usize, object: &Path, options: &CodegenOptions, profile: Option<&Profile>) -> Result<(), String> {
+    let context = Context::create();
+    let mut compiler = Compiler::new(&context, name);
+    compiler.configure(options);
+    compiler.set_codegen_unit(unit, units);
+    profile::time(profile, "codegen", || compiler.compile(program))?;
+    // Keep everything else out of the C program's namespace; runtime
+    // functions the module only declares stay external so they still link.
+    // Another unit may call any of them, so with more than one unit they're
+    // hidden rather than internal, and the type info and itables each unit
+    // defines for itself are merged into one copy.
+    for function in compiler.module.get_functions() {
+        let defined = function.count_basic_blocks() > 0;
+        if defined && !exports.iter().any(|export| function.get_name().to_str() == Ok(export.as_str())) {
+            match units {
+                1 => function.set_linkage(Linkage::Internal),
+                _ => function.as_global_value().set_visibility(GlobalVisibility::Hidden),
+            }
+        }
+    }
+    if units > 1 {
+        for global in compiler.module.get_globals() {
+            if global.get_initializer().is_some() && global.get_linkage() == Linkage::External {
+                global.set_linkage(Linkage::LinkOnceODR);
+                global.set_visibility(GlobalVisibility::Hidden);
+            }
+        }
+    }
+
+    match options.lto {
+        Some(lto) => {
+            let bitcode = object.with_extension("bc");
+            profile::time(profile, "llvm bitcode", || compiler.write_native_bitcode(&bitcode))?;
+            let compiled = profile::time(profile, "lto compile", || library::compile_bitcode(lto, &bitcode, object));
+            let _ = std::fs::remove_file(&bitcode);
+            compiled
+        },
+        None => profile::time(profile, "llvm object", || compiler.write_native_object(object)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use inkwell::context::Context;
 
+    /// Fails with the module's IR when it doesn't match the FileCheck-style
+    /// `checks`.
+    fn assert_ir(compiler: &Compiler, checks: &str) {
+        let ir = compiler.module.print_to_string().to_string();
+        if let Err(error) = filecheck::check(&ir, checks) {
+            panic!("{}\n\n{}", error, ir);
+        }
+    }
+
+    /// A function of an `int` parameter `x`.
+    fn function_of_x(name: &str, return_type: Type, body: Node) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: vec![Parameter { name: "x".into(), type_annotation: Type::Int }],
+            return_type,
+            body: Box::new(body),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn compare_x(operator: BinaryOp, value: i64) -> Box<Node> {
+        Box::new(Node::Binary { left: Box::new(Node::Identifier("x".into())), operator, right: Box::new(Node::IntLiteral(value)) })
+    }
+
     #[test]
     fn test_compile_basic_function() {
         let context = Context::create();
@@ -613,6 +3173,7 @@ mod tests {
             return_type: Type::Int,
             body: Box::new(Node::IntLiteral(42)),
             modifiers: vec![],
+            docs: None,
         };
 
         let result = compiler.compile_node(input);
@@ -633,4 +3194,717 @@ mod tests {
         let result = compiler.compile_node(input);
         assert!(result.is_ok());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_compile_overflow_checks() {
+        let context = Context::create();
+        let add = |name: &str, callee: Option<&str>| {
+            let (left, right) = (Node::Identifier("x".into()), Node::IntLiteral(1));
+            let body = match callee {
+                Some(callee) => Node::Call { callee: Box::new(Node::Identifier(callee.into())), arguments: vec![left, right] },
+                None => Node::Binary { left: Box::new(left), operator: BinaryOp::Add, right: Box::new(right) },
+            };
+            Node::Function {
+                name: name.to_string(),
+                params: vec![Parameter { name: "x".into(), type_annotation: Type::Int }],
+                return_type: Type::Int,
+                body: Box::new(body),
+                modifiers: vec![],
+                docs: None,
+            }
+        };
+
+        let mut compiler = Compiler::new(&context, "release");
+        compiler.compile(Node::Program(vec![add("next", None)])).unwrap();
+        assert!(compiler.module.get_function("gard_trap").is_none());
+
+        let mut compiler = Compiler::new(&context, "checked");
+        compiler.set_overflow_checks(true);
+        compiler.compile(Node::Program(vec![add("next", None), add("wrapped", Some("wrappingAdd"))])).unwrap();
+        assert!(compiler.module.get_function("llvm.sadd.with.overflow.i64").is_some());
+        assert!(compiler.module.get_function("gard_trap").is_some());
+        // Wrapping calls don't branch to the trap
+        assert_eq!(compiler.module.get_function("wrapped").unwrap().count_basic_blocks(), 1);
+
+        let mut compiler = Compiler::new(&context, "explicit");
+        compiler.compile(Node::Program(vec![add("next", Some("checkedAdd"))])).unwrap();
+        assert!(compiler.module.get_function("gard_raise").is_some());
+    }
+
+    #[test]
+    fn test_compile_bitwise_operators() {
+        let context = Context::create();
+        let function = |name: &str, body: Node| Node::Function {
+            name: name.to_string(),
+            params: vec![Parameter { name: "x".into(), type_annotation: Type::Int }],
+            return_type: Type::Int,
+            body: Box::new(Node::Return(Some(Box::new(body)))),
+            modifiers: vec![],
+            docs: None,
+        };
+        let x = || Box::new(Node::Identifier("x".into()));
+        let binary = |left, operator, right| Node::Binary { left, operator, right };
+        let program = || Node::Program(vec![
+            function("shift", binary(Box::new(Node::IntLiteral(1)), BinaryOp::Shl, x())),
+            function("mask", binary(Box::new(Node::Unary { operator: UnaryOp::BitNot, operand: x() }), BinaryOp::BitAnd, Box::new(Node::IntLiteral(255)))),
+        ]);
+
+        // Without checks the amount wraps, so nothing branches
+        let mut compiler = Compiler::new(&context, "release");
+        compiler.compile(program()).unwrap();
+        assert_eq!(compiler.module.get_function("shift").unwrap().count_basic_blocks(), 1);
+        assert!(compiler.module.get_function("gard_trap").is_none());
+        assert!(compiler.module.verify().is_ok());
+
+        let mut compiler = Compiler::new(&context, "checked");
+        compiler.set_overflow_checks(true);
+        compiler.compile(program()).unwrap();
+        assert!(compiler.module.get_function("gard_trap").is_some());
+        assert_eq!(compiler.module.get_function("mask").unwrap().count_basic_blocks(), 1);
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_compile_increments() {
+        let context = Context::create();
+        let step = |operator, operand: Node| Node::Unary { operator, operand: Box::new(operand) };
+        let identifier = |name: &str| Node::Identifier(name.into());
+        let program = || Node::Program(vec![Node::Function {
+            name: "bump".to_string(),
+            params: vec![
+                Parameter { name: "xs".into(), type_annotation: Type::Array(Box::new(Type::Int)) },
+                Parameter { name: "i".into(), type_annotation: Type::Int },
+            ],
+            return_type: Type::Int,
+            body: Box::new(Node::Block(vec![
+                step(UnaryOp::Increment, Node::Index { object: Box::new(identifier("xs")), index: Box::new(identifier("i")), checked: false }),
+                step(UnaryOp::PostDecrement, identifier("i")),
+            ])),
+            modifiers: vec![],
+            docs: None,
+        }]);
+
+        let mut compiler = Compiler::new(&context, "release");
+        compiler.compile(program()).unwrap();
+        assert!(compiler.module.get_function("gard_trap").is_none());
+        assert!(compiler.module.verify().is_ok());
+
+        // Only the null check and the two steps' overflow checks branch
+        let mut compiler = Compiler::new(&context, "checked");
+        compiler.set_overflow_checks(true);
+        compiler.compile(program()).unwrap();
+        assert!(compiler.module.get_function("gard_trap").is_some());
+        assert_eq!(compiler.module.get_function("bump").unwrap().count_basic_blocks(), 7);
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_compile_string_characters() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "strings");
+        let identifier = |name: &str| Box::new(Node::Identifier(name.into()));
+        let function = |name: &str, param: Type, return_type: Type, body: Node| Node::Function {
+            name: name.to_string(),
+            params: vec![Parameter { name: "xs".into(), type_annotation: param }],
+            return_type,
+            body: Box::new(body),
+            modifiers: vec![],
+            docs: None,
+        };
+        let each = |body: Node| Node::Block(vec![
+            Node::Foreach { item: "x".to_string(), collection: identifier("xs"), body: Box::new(body) },
+            Node::IntLiteral(0),
+        ]);
+
+        compiler.compile(Node::Program(vec![
+            function("first", Type::String, Type::String, Node::Index { object: identifier("xs"), index: Box::new(Node::IntLiteral(0)), checked: false }),
+            function("chars", Type::String, Type::Int, each(Node::Identifier("x".into()))),
+            function("flags", Type::Array(Box::new(Type::Boolean)), Type::Int, each(Node::Identifier("x".into()))),
+        ])).unwrap();
+        assert!(compiler.module.get_function("gard_string_char_at").is_some());
+        assert!(compiler.module.get_function("gard_string_next").is_some());
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_codegen_nested_if_phi() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "branches");
+        let if_else = |condition, then: Node, otherwise: Node| Node::If {
+            condition,
+            then_branch: Box::new(then),
+            else_branch: Some(Box::new(otherwise)),
+        };
+        let body = if_else(compare_x(BinaryOp::Lt, 10), if_else(compare_x(BinaryOp::Lt, 5), Node::IntLiteral(1), Node::IntLiteral(2)), Node::IntLiteral(3));
+        compiler.compile(Node::Program(vec![function_of_x("pick", Type::Int, body)])).unwrap();
+
+        // The outer phi's value from the then branch comes from the inner
+        // if's merge block, where that branch ends
+        assert_ir(&compiler, "
+            CHECK-LABEL: define i64 @pick(
+            CHECK: br i1 %{{.*}}, label %then, label %else
+            CHECK: then:
+            CHECK: br i1 %{{.*}}, label %then{{.*}}, label %else{{.*}}
+            CHECK: merge:
+            CHECK-NEXT: phi i64 [ %if_result, %merge{{.*}} ], [ 3, %else ]
+            CHECK: merge{{.*}}:
+            CHECK-NEXT: %if_result = phi i64 [ 1, %then{{.*}} ], [ 2, %else{{.*}} ]
+            CHECK-NEXT: br label %merge
+        ");
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_codegen_short_circuit() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "logic");
+        let logical = |left, operator, right| Node::Binary { left, operator, right };
+        compiler.compile(Node::Program(vec![
+            function_of_x("inside", Type::Boolean, logical(compare_x(BinaryOp::Gt, 0), BinaryOp::And, compare_x(BinaryOp::Lt, 10))),
+            function_of_x("outside", Type::Boolean, logical(compare_x(BinaryOp::Lt, 0), BinaryOp::Or, compare_x(BinaryOp::Gt, 10))),
+        ])).unwrap();
+
+        // The right side is only compared in its own block
+        assert_ir(&compiler, "
+            CHECK-LABEL: define i1 @inside(
+            CHECK: icmp sgt
+            CHECK-NEXT: br i1 %{{.*}}, label %logic.rhs, label %logic.end
+            CHECK-NOT: icmp
+            CHECK: logic.rhs:
+            CHECK: icmp slt
+            CHECK: logic.end:
+            CHECK-NEXT: %logic_result = phi i1 [ false, %entry ], [ %{{.*}}, %logic.rhs ]
+            CHECK-LABEL: define i1 @outside(
+            CHECK: icmp slt
+            CHECK-NEXT: br i1 %{{.*}}, label %logic.end, label %logic.rhs
+            CHECK: logic.end:
+            CHECK-NEXT: %logic_result = phi i1 [ true, %entry ], [ %{{.*}}, %logic.rhs ]
+        ");
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_codegen_while_loop() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "loops");
+        let i = || Box::new(Node::Identifier("i".into()));
+        let body = Node::Block(vec![
+            Node::Let { name: "i".to_string(), type_annotation: Some(Type::Int), initializer: Some(Box::new(Node::IntLiteral(0))), is_mutable: true },
+            Node::While {
+                condition: Box::new(Node::Binary { left: i(), operator: BinaryOp::Lt, right: Box::new(Node::Identifier("x".into())) }),
+                body: Box::new(Node::Block(vec![Node::Unary { operator: UnaryOp::PostIncrement, operand: i() }])),
+            },
+            *i(),
+        ]);
+        compiler.compile(Node::Program(vec![function_of_x("count", Type::Int, body)])).unwrap();
+
+        // The condition is tested before each iteration, and the body
+        // stores the step back before looping
+        assert_ir(&compiler, "
+            CHECK-LABEL: define i64 @count(
+            CHECK: store i64 0, i64* %i
+            CHECK-NEXT: br label %while.cond
+            CHECK: while.cond:
+            CHECK: icmp slt
+            CHECK-NEXT: br i1 %{{.*}}, label %while.body, label %while.end
+            CHECK: while.body:
+            CHECK: add i64 %{{.*}}, 1
+            CHECK-NEXT: store i64 %{{.*}}, i64* %i
+            CHECK-NEXT: br label %while.cond
+            CHECK: while.end:
+            CHECK: ret i64
+        ");
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_compile_bounds_checks() {
+        let context = Context::create();
+        let get = |name: &str, checked: bool| Node::Function {
+            name: name.to_string(),
+            params: vec![
+                Parameter { name: "xs".into(), type_annotation: Type::Array(Box::new(Type::Int)) },
+                Parameter { name: "i".into(), type_annotation: Type::Int },
+            ],
+            return_type: Type::Int,
+            body: Box::new(Node::Index {
+                object: Box::new(Node::Identifier("xs".into())),
+                index: Box::new(Node::Identifier("i".into())),
+                checked,
+            }),
+            modifiers: vec![],
+            docs: None,
+        };
+
+        let mut compiler = Compiler::new(&context, "unchecked");
+        compiler.compile(Node::Program(vec![get("get", false)])).unwrap();
+        assert!(compiler.module.get_function("gard_trap").is_none());
+
+        let mut compiler = Compiler::new(&context, "checked");
+        compiler.compile(Node::Program(vec![get("get", true)])).unwrap();
+        assert!(compiler.module.get_function("gard_trap").is_some());
+        // The null check and the bounds check each branch off a failing block
+        assert_eq!(compiler.module.get_function("get").unwrap().count_basic_blocks(), 5);
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_compile_division_checks() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "divide");
+        let divide = |name: &str, operator: BinaryOp| Node::Function {
+            name: name.to_string(),
+            params: vec![
+                Parameter { name: "x".into(), type_annotation: Type::Int },
+                Parameter { name: "y".into(), type_annotation: Type::Int },
+            ],
+            return_type: Type::Int,
+            body: Box::new(Node::Binary {
+                left: Box::new(Node::Identifier("x".into())),
+                operator,
+                right: Box::new(Node::Identifier("y".into())),
+            }),
+            modifiers: vec![],
+            docs: None,
+        };
+
+        compiler.compile(Node::Program(vec![divide("quotient", BinaryOp::Div), divide("remainder", BinaryOp::Mod)])).unwrap();
+        assert!(compiler.module.get_function("gard_raise").is_some());
+        assert_eq!(compiler.module.get_function("remainder").unwrap().count_basic_blocks(), 3);
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_compile_crypto_calls() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "crypto");
+        let call = |name: &str, arguments: Vec<Node>| Node::Call { callee: Box::new(Node::Identifier(name.into())), arguments };
+        let function = |name: &str, param: Type, return_type: Type, body: Node| Node::Function {
+            name: name.to_string(),
+            params: vec![Parameter { name: "x".into(), type_annotation: param }],
+            return_type,
+            body: Box::new(body),
+            modifiers: vec![],
+            docs: None,
+        };
+        let x = || Node::Identifier("x".into());
+
+        compiler.compile(Node::Program(vec![
+            function("digest", Type::Bytes, Type::Bytes, call("keccak256", vec![x()])),
+            function("digestText", Type::String, Type::Bytes, call("sha256", vec![x()])),
+            function("signer", Type::Bytes, Type::Bytes, call("ecrecover", vec![call("keccak256", vec![x()]), x()])),
+        ])).unwrap();
+        assert!(compiler.module.get_function("gard_bytes_from_string").is_some());
+        assert_ir(&compiler, "
+            CHECK: declare { i32, [0 x i8] }* @gard_crypto_keccak256(i8*, i64)
+            CHECK: declare { i32, [0 x i8] }* @gard_crypto_ecrecover({ i32, [0 x i8] }*, { i32, [0 x i8] }*)
+        ");
+        assert!(compiler.module.verify().is_ok());
+
+        let mut compiler = Compiler::new(&context, "crypto");
+        let result = compiler.compile(Node::Program(vec![function("digest", Type::Int, Type::Bytes, call("keccak256", vec![x()]))]));
+        assert_eq!(result, Err("Argument 1 of keccak256() must be bytes or a string".to_string()));
+    }
+
+    #[test]
+    fn test_compile_wasm_contract() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "counter");
+
+        let contract = Node::Contract {
+            name: "Counter".to_string(),
+            members: vec![
+                Node::Let {
+                    name: "count".to_string(),
+                    type_annotation: Some(Type::UInt),
+                    initializer: Some(Box::new(Node::IntLiteral(1))),
+                    is_mutable: true,
+                },
+                Node::Function {
+                    name: "get".to_string(),
+                    params: vec![],
+                    return_type: Type::UInt,
+                    body: Box::new(Node::Identifier("count".into())),
+                    modifiers: vec![FunctionModifier::View],
+                    docs: None,
+                },
+            ],
+            docs: None,
+        };
+
+        let abi = compiler.compile_wasm_contract(contract).unwrap();
+        assert!(abi.entry("query_get").is_some());
+        assert!(compiler.module.get_function("storage_read").is_some());
+        let get = compiler.module.get_function("get").unwrap();
+        assert!(get.get_string_attribute(AttributeLoc::Function, "wasm-export-name").is_some());
+    }
+
+    #[test]
+    fn test_compile_inline_ir() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "test");
+
+        let program = Node::Program(vec![
+            Node::InlineIr("declare i64 @llvm.ctpop.i64(i64)".to_string()),
+            Node::Function {
+                name: "popcount".to_string(),
+                params: vec![Parameter { name: "x".into(), type_annotation: Type::Int }],
+                return_type: Type::Int,
+                body: Box::new(Node::Block(vec![Node::InlineIr(
+                    "%count = call i64 @llvm.ctpop.i64(i64 %x)\nret i64 %count".to_string(),
+                )])),
+                modifiers: vec![],
+                docs: None,
+            },
+        ]);
+        compiler.compile(program).unwrap();
+        assert_eq!(compiler.module.get_function("popcount").unwrap().count_basic_blocks(), 1);
+
+        let mut compiler = Compiler::new(&context, "invalid");
+        let error = compiler.compile(Node::Program(vec![Node::InlineIr("define i64 @f() {".to_string())])).unwrap_err();
+        assert!(error.starts_with("Invalid llvm IR"));
+    }
+
+    #[test]
+    fn test_compile_wasm_export() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "test");
+
+        let input = Node::WasmExport {
+            export_name: Some("answer".to_string()),
+            declaration: Box::new(Node::Function {
+                name: "compute".to_string(),
+                params: vec![],
+                return_type: Type::Int,
+                body: Box::new(Node::IntLiteral(42)),
+                modifiers: vec![],
+                docs: None,
+            }),
+        };
+
+        assert!(compiler.compile_node(input).is_ok());
+        let function = compiler.module.get_function("compute").unwrap();
+        let export = function.get_string_attribute(AttributeLoc::Function, "wasm-export-name").unwrap();
+        assert_eq!(export.get_string_value().to_str(), Ok("answer"));
+    }
+
+    #[test]
+    fn test_compile_uint256_literal() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "test");
+
+        let result = compiler.compile_node(Node::UInt256Literal(format!("0x{}", "f".repeat(64))));
+        assert!(matches!(result, Ok(BasicValueEnum::IntValue(v)) if v.get_type().get_bit_width() == 256));
+
+        // An int on the left is widened to the uint256 on the right
+        let result = compiler.compile_node(Node::Binary {
+            left: Box::new(Node::IntLiteral(1)),
+            operator: BinaryOp::Eq,
+            right: Box::new(Node::UInt256Literal("0x01".to_string())),
+        });
+        assert!(matches!(result, Ok(BasicValueEnum::IntValue(v)) if v.get_zero_extended_constant() == Some(1)));
+    }
+
+    #[test]
+    fn test_compile_interop_struct() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "geometry");
+
+        let field = |name: &str, ty: Type| Node::Let {
+            name: name.to_string(),
+            type_annotation: Some(ty),
+            initializer: None,
+            is_mutable: true,
+        };
+        let member = |object: &str, property: &str| Node::Member {
+            object: Box::new(Node::Identifier(object.into())),
+            property: property.to_string(),
+        };
+        let function = |name: &str, param: Type, body: Node| Node::WasmExport {
+            export_name: None,
+            declaration: Box::new(Node::Function {
+                name: name.to_string(),
+                params: vec![Parameter { name: "value".into(), type_annotation: param }],
+                return_type: Type::Int,
+                body: Box::new(body),
+                modifiers: vec![],
+                docs: None,
+            }),
+        };
+
+        let point = Type::Custom("Point".into());
+        let program = Node::Program(vec![
+            Node::Class {
+                name: "Point".to_string(),
+                extends: None,
+                implements: vec![],
+                is_abstract: false,
+                members: vec![field("visible", Type::Boolean), field("x", Type::Int)],
+                docs: None,
+            },
+            function("getX", point.clone(), member("value", "x")),
+            function("count", Type::Array(Box::new(point)), member("value", "length")),
+        ]);
+
+        compiler.compile(program).unwrap();
+        let point = compiler.module.get_struct_type("Point").unwrap();
+        assert_eq!(point.count_fields(), 2);
+        let get_x = compiler.module.get_function("getX").unwrap();
+        assert!(get_x.get_nth_param(0).unwrap().is_pointer_value());
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_compile_interface_values() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "shapes");
+
+        let shape = Type::Custom("Shape".into());
+        let area = |object: &str| Node::Call {
+            callee: Box::new(Node::Member {
+                object: Box::new(Node::Identifier(object.into())),
+                property: "area".to_string(),
+            }),
+            arguments: vec![],
+        };
+        let program = Node::Program(vec![
+            Node::Interface {
+                name: "Shape".to_string(),
+                methods: vec![MethodSignature { name: "area".to_string(), params: vec![], return_type: Type::Int }],
+            },
+            Node::Class {
+                name: "Square".to_string(),
+                extends: None,
+                implements: vec!["Shape".to_string()],
+                is_abstract: false,
+                members: vec![Node::Let { name: "side".to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable: false }],
+                docs: None,
+            },
+            Node::Function {
+                name: "measure".to_string(),
+                params: vec![Parameter { name: "square".into(), type_annotation: Type::Custom("Square".into()) }],
+                return_type: Type::Int,
+                body: Box::new(Node::Block(vec![
+                    Node::Let {
+                        name: "shape".to_string(),
+                        type_annotation: Some(shape),
+                        initializer: Some(Box::new(Node::Identifier("square".into()))),
+                        is_mutable: false,
+                    },
+                    area("shape"),
+                ])),
+                modifiers: vec![],
+                docs: None,
+            },
+        ]);
+
+        compiler.compile(program).unwrap();
+        let fat_pointer = compiler.module.get_struct_type("Shape").unwrap();
+        assert_eq!(fat_pointer.count_fields(), 2);
+        assert!(compiler.module.get_global("Square.Shape.itable").is_some());
+        // Declared for the itable, to be defined with the class's methods
+        let implementation = compiler.module.get_function("Square.area").unwrap();
+        assert_eq!(implementation.count_params(), 1);
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_compile_type_test_narrowing() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "shapes");
+
+        let shape = Node::Identifier("shape".into());
+        let program = Node::Program(vec![
+            Node::Interface {
+                name: "Shape".to_string(),
+                methods: vec![MethodSignature { name: "area".to_string(), params: vec![], return_type: Type::Int }],
+            },
+            Node::Class {
+                name: "Square".to_string(),
+                extends: None,
+                implements: vec!["Shape".to_string()],
+                is_abstract: false,
+                members: vec![Node::Let { name: "side".to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable: false }],
+                docs: None,
+            },
+            Node::Function {
+                name: "side".to_string(),
+                params: vec![Parameter { name: "shape".into(), type_annotation: Type::Custom("Shape".into()) }],
+                return_type: Type::Int,
+                body: Box::new(Node::Block(vec![Node::If {
+                    condition: Box::new(Node::TypeTest { value: Box::new(shape.clone()), target: Type::Custom("Square".into()) }),
+                    // `shape` is a Square here, so it has the field
+                    then_branch: Box::new(Node::Block(vec![Node::Member { object: Box::new(shape), property: "side".to_string() }])),
+                    else_branch: Some(Box::new(Node::Block(vec![Node::IntLiteral(0)]))),
+                }])),
+                modifiers: vec![],
+                docs: None,
+            },
+        ]);
+
+        compiler.compile(program).unwrap();
+        assert!(compiler.module.get_global("Square.rtti").is_some());
+        assert!(compiler.module.get_global("Shape.rtti").is_some());
+        // The type info, then `area`
+        let itable = compiler.module.get_global("Square.Shape.itable").unwrap();
+        assert_eq!(itable.get_initializer().unwrap().into_array_value().get_type().len(), 2);
+        assert!(compiler.module.get_function("gard_rtti_is").is_some());
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_compile_union_variants() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "messages");
+
+        let msg = Type::Custom("Msg".into());
+        let program = Node::Program(vec![
+            Node::Union { name: "Msg".to_string(), variants: vec![
+                UnionVariant { name: "Resize".to_string(), payload: vec![Type::Int, Type::String] },
+                UnionVariant::new("Logout"),
+            ] },
+            Node::Function {
+                name: "width".to_string(),
+                params: vec![Parameter { name: "msg".into(), type_annotation: msg.clone() }],
+                return_type: Type::Int,
+                body: Box::new(Node::Block(vec![
+                    Node::Match { value: Box::new(Node::Identifier("msg".into())), cases: vec![
+                        MatchCase {
+                            pattern: Node::Call {
+                                callee: Box::new(Node::Identifier("Resize".into())),
+                                arguments: vec![Node::Identifier("width".into()), Node::Identifier("_".into())],
+                            },
+                            body: Node::Return(Some(Box::new(Node::Identifier("width".into())))),
+                        },
+                        MatchCase { pattern: Node::Identifier("_".into()), body: Node::Block(vec![]) },
+                    ] },
+                    Node::Return(Some(Box::new(Node::IntLiteral(0)))),
+                ])),
+                modifiers: vec![],
+                docs: None,
+            },
+            Node::Function {
+                name: "resize".to_string(),
+                params: vec![],
+                return_type: msg,
+                body: Box::new(Node::Block(vec![Node::Return(Some(Box::new(Node::Call {
+                    callee: Box::new(Node::Identifier("Resize".into())),
+                    arguments: vec![Node::IntLiteral(80), Node::StringLiteral("wide".to_string())],
+                })))])),
+                modifiers: vec![],
+                docs: None,
+            },
+        ]);
+
+        compiler.compile(program).unwrap();
+        // The tag, then a slot for each value of `Resize`
+        let union = compiler.module.get_struct_type("Msg").unwrap();
+        assert_eq!(union.count_fields(), 2);
+        assert_eq!(union.get_field_type_at_index(1).unwrap().into_array_type().len(), 2);
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_compile_constructor_chaining() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "shapes");
+
+        let field = |name: &str| Node::Let { name: name.to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable: false };
+        let class = |name: &str, extends: Option<&str>, members: Vec<Node>| Node::Class {
+            name: name.to_string(),
+            extends: extends.map(str::to_string),
+            implements: vec![],
+            is_abstract: false,
+            members,
+            docs: None,
+        };
+        let program = Node::Program(vec![
+            class("Shape", None, vec![
+                field("sides"),
+                Node::Constructor {
+                    params: vec![Parameter { name: "sides".into(), type_annotation: Type::Int }],
+                    body: Box::new(Node::Block(vec![])),
+                },
+            ]),
+            class("Square", Some("Shape"), vec![
+                field("side"),
+                Node::Constructor {
+                    params: vec![],
+                    body: Box::new(Node::Block(vec![Node::Call { callee: Box::new(Node::Super), arguments: vec![Node::IntLiteral(4)] }])),
+                },
+            ]),
+            // No constructor of its own, so one runs Square's
+            class("Tile", Some("Square"), vec![field("color")]),
+        ]);
+
+        compiler.compile(program).unwrap();
+        assert_eq!(compiler.module.get_function("Shape.constructor").unwrap().count_params(), 2);
+        assert_eq!(compiler.module.get_function("Square.constructor").unwrap().count_params(), 1);
+        assert_eq!(compiler.module.get_function("Tile.constructor").unwrap().count_params(), 1);
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_nested_class_symbols() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "shapes");
+
+        let class = |name: &str, members: Vec<Node>| Node::Class {
+            name: name.to_string(),
+            extends: None,
+            implements: vec![],
+            is_abstract: false,
+            members,
+            docs: None,
+        };
+        let constructor = Node::Constructor { params: vec![], body: Box::new(Node::Block(vec![])) };
+        let field = Node::Let { name: "sides".to_string(), type_annotation: Some(Type::Int), initializer: None, is_mutable: false };
+        let program = nested::hoist(Node::Program(vec![
+            class("Shape", vec![field.clone(), constructor.clone(), class("Side", vec![field, constructor])]),
+        ])).unwrap();
+
+        compiler.compile(program).unwrap();
+        assert!(compiler.module.get_function("Shape.constructor").is_some());
+        assert!(compiler.module.get_function("Shape$Side.constructor").is_some());
+        assert!(compiler.module.get_struct_type("Shape.Side").is_some());
+        assert!(compiler.module.verify().is_ok());
+    }
+
+    #[test]
+    fn test_abstract_method_symbols() {
+        let context = Context::create();
+        let mut compiler = Compiler::new(&context, "shapes");
+
+        let method = |name: &str, modifiers: Vec<FunctionModifier>| Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type: Type::Int,
+            body: Box::new(Node::Block(vec![])),
+            modifiers,
+            docs: None,
+        };
+        let program = Node::Program(vec![
+            Node::Class {
+                name: "Shape".to_string(),
+                extends: None,
+                implements: vec![],
+                is_abstract: true,
+                members: vec![method("area", vec![FunctionModifier::Abstract]), method("sides", vec![])],
+                docs: None,
+            },
+            Node::Class {
+                name: "Square".to_string(),
+                extends: Some("Shape".to_string()),
+                implements: vec![],
+                is_abstract: false,
+                members: vec![method("area", vec![])],
+                docs: None,
+            },
+        ]);
+
+        compiler.compile(program).unwrap();
+        assert_eq!(compiler.method_symbol("Square", "area"), Ok("Square.area".to_string()));
+        assert_eq!(compiler.method_symbol("Square", "sides"), Ok("Shape.sides".to_string()));
+        assert!(compiler.method_symbol("Shape", "area").is_err());
+    }
+}
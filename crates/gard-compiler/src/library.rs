@@ -0,0 +1,261 @@
+//! Native libraries for C programs, built from a program's `@export`ed
+//! functions. A project lists the kinds it builds in `gard.toml`, along
+//! with whether they carry the runtime:
+//!
+//! ```toml
+//! [library]
+//! types = ["staticlib", "dylib"]
+//! runtime = "linked"
+//! ```
+//!
+//! `gard --emit library` then writes `lib<name>.a` and `lib<name>.so`
+//! (`.dylib` on macOS) with their header. With `runtime = "linked"`, the
+//! default, each library includes the gard-vm runtime its code calls, so a
+//! C program needs nothing else. With `runtime = "separate"`, the runtime's
+//! functions are left undefined, and the program links `libgard_vm.a`
+//! itself: once, however many Gard libraries it uses.
+//!
+//! Building gard-vm writes `libgard_vm.a`. It's looked up in
+//! `GARD_RUNTIME`, then next to the `gard` executable.
+//!
+//! With LTO (see `machine`), clang and lld build the libraries. A shared
+//! library is optimized as it's linked; a static one holds the Gard code
+//! as bitcode, so the C program has to be linked with `-flto` as well,
+//! which also lets its own code inline the library's functions.
+
+use crate::machine::Lto;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Names the runtime archive to link libraries against
+pub const RUNTIME_ENV: &str = "GARD_RUNTIME";
+
+/// The runtime archive, as building gard-vm names it
+pub const RUNTIME_ARCHIVE: &str = "libgard_vm.a";
+
+/// Builds static libraries. GNU `ar` can't read all of the objects rustc
+/// writes into the runtime's archive, and leaves their symbols out of the
+/// index it rebuilds.
+const ARCHIVER: &str = "llvm-ar";
+
+/// Optimizes bitcode and links it, for LTO. `cc` may not read bitcode.
+const LTO_DRIVER: &str = "clang";
+
+/// How much LTO optimizes, before the link and during it
+const LTO_OPTIMIZATION: &str = "-O2";
+
+/// The system libraries the runtime needs, on platforms other than macOS
+const RUNTIME_SYSTEM_LIBRARIES: [&str; 3] = ["-lpthread", "-ldl", "-lm"];
+
+/// The `[library]` table of `gard.toml`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Library {
+    /// Defaults to the name of the compiled file, without its extension
+    pub name: Option<String>,
+    pub types: Vec<LibraryKind>,
+    #[serde(default)]
+    pub runtime: Runtime,
+}
+
+/// The kind of native library `build_native_library` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum LibraryKind {
+    /// An archive of object files, linked into the C program
+    #[serde(rename = "staticlib")]
+    Static,
+    /// A shared object, loaded by the C program at run time
+    #[serde(rename = "dylib")]
+    Shared,
+}
+
+/// Whether a library carries the runtime it calls
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Runtime {
+    #[default]
+    Linked,
+    Separate,
+}
+
+impl Library {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.types.is_empty() {
+            return Err("[library] lists no types; expected staticlib, dylib or both".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl LibraryKind {
+    /// The file a library called `name` of this kind is written to on
+    /// `os`, as `std::env::consts::OS` names it.
+    pub fn file_name(self, name: &str, os: &str) -> String {
+        match (self, os) {
+            (LibraryKind::Static, _) => format!("lib{}.a", name),
+            (LibraryKind::Shared, "macos") => format!("lib{}.dylib", name),
+            (LibraryKind::Shared, _) => format!("lib{}.so", name),
+        }
+    }
+}
+
+/// The runtime archive to link into libraries.
+pub fn find_runtime() -> Result<PathBuf, String> {
+    if let Some(path) = env::var_os(RUNTIME_ENV) {
+        let path = PathBuf::from(path);
+        return match path.is_file() {
+            true => Ok(path),
+            false => Err(format!("{} names {}, which doesn't exist", RUNTIME_ENV, path.display())),
+        };
+    }
+    env::current_exe().ok()
+        .and_then(|executable| executable.parent().map(|directory| directory.join(RUNTIME_ARCHIVE)))
+        .filter(|path| path.is_file())
+        .ok_or_else(|| format!(
+            "Can't find the runtime to link into the library: set {} to the path of {}, or set runtime = \"separate\" in [library]",
+            RUNTIME_ENV, RUNTIME_ARCHIVE
+        ))
+}
+
+/// Turns a module's bitcode into the `object` that `link` takes with
+/// `lto`: runs the optimizations that come before the link, and for
+/// ThinLTO, adds the summary the linker reads. The object is still bitcode.
+pub fn compile_bitcode(lto: Lto, bitcode: &Path, object: &Path) -> Result<(), String> {
+    run(bitcode_command(lto, bitcode, object))
+}
+
+fn bitcode_command(lto: Lto, bitcode: &Path, object: &Path) -> Command {
+    let mut command = Command::new(LTO_DRIVER);
+    command.args([lto.flag(), LTO_OPTIMIZATION, "-c"]).arg(bitcode).arg("-o").arg(object);
+    command
+}
+
+/// Links `objects` into a library at `output`, in order, with `runtime`
+/// if it's given.
+pub fn link(kind: LibraryKind, objects: &[PathBuf], runtime: Option<&Path>, lto: Option<Lto>, output: &Path) -> Result<(), String> {
+    // The archiver adds to an archive that's already there
+    if output.exists() {
+        fs::remove_file(output).map_err(|e| format!("Failed to remove {}: {}", output.display(), e))?;
+    }
+    if let (LibraryKind::Static, Some(runtime)) = (kind, runtime) {
+        fs::copy(runtime, output).map_err(|e| format!("Failed to copy {}: {}", runtime.display(), e))?;
+    }
+    run(link_command(kind, objects, runtime, lto, output, env::consts::OS))
+}
+
+fn run(mut command: Command) -> Result<(), String> {
+    match command.status().map_err(|e| format!("Failed to run {:?}: {}", command.get_program(), e))? {
+        status if status.success() => Ok(()),
+        status => Err(format!("{:?} failed with {}", command.get_program(), status)),
+    }
+}
+
+/// The command `link` runs on `os`. A static library with the runtime
+/// starts out as a copy of the runtime's archive, which the command adds
+/// `objects` to. The archiver indexes bitcode too, so LTO doesn't change it.
+fn link_command(kind: LibraryKind, objects: &[PathBuf], runtime: Option<&Path>, lto: Option<Lto>, output: &Path, os: &str) -> Command {
+    match kind {
+        LibraryKind::Static => {
+            let mut command = Command::new(ARCHIVER);
+            command.arg("rcs").arg(output).args(objects);
+            command
+        },
+        LibraryKind::Shared => {
+            let mut command = match lto {
+                Some(lto) => {
+                    let mut command = Command::new(LTO_DRIVER);
+                    command.args([lto.flag(), "-fuse-ld=lld", LTO_OPTIMIZATION]);
+                    command
+                },
+                None => Command::new("cc"),
+            };
+            command.arg(if os == "macos" { "-dynamiclib" } else { "-shared" });
+            command.arg("-o").arg(output).args(objects);
+            match (runtime, os) {
+                (Some(runtime), "macos") => {
+                    command.arg(runtime).args(["-framework", "CoreFoundation"]);
+                },
+                (Some(runtime), _) => {
+                    command.arg(runtime).args(RUNTIME_SYSTEM_LIBRARIES);
+                },
+                // The program loading the library provides the runtime
+                (None, "macos") => {
+                    command.args(["-undefined", "dynamic_lookup"]);
+                },
+                (None, _) => {},
+            }
+            command
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(command: &Command) -> Vec<String> {
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|argument| argument.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_manifest() {
+        let library: Library = toml::from_str("types = [\"staticlib\", \"dylib\"]").unwrap();
+        assert_eq!(library, Library { name: None, types: vec![LibraryKind::Static, LibraryKind::Shared], runtime: Runtime::Linked });
+        let library: Library = toml::from_str("name = \"geometry\"\ntypes = [\"dylib\"]\nruntime = \"separate\"").unwrap();
+        assert_eq!(library, Library { name: Some("geometry".to_string()), types: vec![LibraryKind::Shared], runtime: Runtime::Separate });
+
+        assert!(toml::from_str::<Library>("types = [\"cdylib\"]").is_err());
+        assert!(toml::from_str::<Library>("types = [\"staticlib\"]\nruntime = \"bundled\"").is_err());
+        let empty: Library = toml::from_str("types = []").unwrap();
+        assert_eq!(empty.validate().unwrap_err(), "[library] lists no types; expected staticlib, dylib or both");
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(LibraryKind::Static.file_name("geometry", "linux"), "libgeometry.a");
+        assert_eq!(LibraryKind::Static.file_name("geometry", "macos"), "libgeometry.a");
+        assert_eq!(LibraryKind::Shared.file_name("geometry", "linux"), "libgeometry.so");
+        assert_eq!(LibraryKind::Shared.file_name("geometry", "macos"), "libgeometry.dylib");
+    }
+
+    #[test]
+    fn test_link_command() {
+        let (object, runtime, output) = (&[PathBuf::from("geometry.o")], Path::new("libgard_vm.a"), Path::new("libgeometry.so"));
+        assert_eq!(arguments(&link_command(LibraryKind::Static, object, Some(runtime), None, Path::new("libgeometry.a"), "linux")),
+            ["llvm-ar", "rcs", "libgeometry.a", "geometry.o"]);
+        assert_eq!(arguments(&link_command(LibraryKind::Shared, object, Some(runtime), None, output, "linux")),
+            ["cc", "-shared", "-o", "libgeometry.so", "geometry.o", "libgard_vm.a", "-lpthread", "-ldl", "-lm"]);
+        assert_eq!(arguments(&link_command(LibraryKind::Shared, object, None, None, output, "linux")),
+            ["cc", "-shared", "-o", "libgeometry.so", "geometry.o"]);
+        assert_eq!(arguments(&link_command(LibraryKind::Shared, object, Some(runtime), None, Path::new("libgeometry.dylib"), "macos")),
+            ["cc", "-dynamiclib", "-o", "libgeometry.dylib", "geometry.o", "libgard_vm.a", "-framework", "CoreFoundation"]);
+        assert_eq!(arguments(&link_command(LibraryKind::Shared, object, None, None, Path::new("libgeometry.dylib"), "macos")),
+            ["cc", "-dynamiclib", "-o", "libgeometry.dylib", "geometry.o", "-undefined", "dynamic_lookup"]);
+    }
+
+    #[test]
+    fn test_lto_commands() {
+        let (object, runtime) = (Path::new("geometry.o"), Path::new("libgard_vm.a"));
+        assert_eq!(arguments(&bitcode_command(Lto::Thin, Path::new("geometry.bc"), object)),
+            ["clang", "-flto=thin", "-O2", "-c", "geometry.bc", "-o", "geometry.o"]);
+        assert_eq!(arguments(&link_command(LibraryKind::Shared, &[object.to_path_buf()], Some(runtime), Some(Lto::Full), Path::new("libgeometry.so"), "linux")),
+            ["clang", "-flto=full", "-fuse-ld=lld", "-O2", "-shared", "-o", "libgeometry.so", "geometry.o", "libgard_vm.a", "-lpthread", "-ldl", "-lm"]);
+        assert_eq!(arguments(&link_command(LibraryKind::Static, &[object.to_path_buf()], Some(runtime), Some(Lto::Thin), Path::new("libgeometry.a"), "linux")),
+            ["llvm-ar", "rcs", "libgeometry.a", "geometry.o"]);
+    }
+
+    #[test]
+    fn test_link_units() {
+        let objects = [PathBuf::from("libgeometry.0.o"), PathBuf::from("libgeometry.1.o")];
+        assert_eq!(arguments(&link_command(LibraryKind::Static, &objects, None, None, Path::new("libgeometry.a"), "linux")),
+            ["llvm-ar", "rcs", "libgeometry.a", "libgeometry.0.o", "libgeometry.1.o"]);
+        assert_eq!(arguments(&link_command(LibraryKind::Shared, &objects, Some(Path::new("libgard_vm.a")), None, Path::new("libgeometry.so"), "linux")),
+            ["cc", "-shared", "-o", "libgeometry.so", "libgeometry.0.o", "libgeometry.1.o", "libgard_vm.a", "-lpthread", "-ldl", "-lm"]);
+    }
+}
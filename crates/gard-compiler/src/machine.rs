@@ -0,0 +1,133 @@
+//! How native code is generated: the relocation model, which decides
+//! whether code runs wherever it's loaded, the code model, which bounds how
+//! far apart code and data may end up, link-time optimization, and how many
+//! codegen units (see `units`) to compile at once. A project sets them in
+//! `gard.toml`, and `--relocation-model`, `--code-model`, `--lto` and
+//! `--codegen-units` override it:
+//!
+//! ```toml
+//! [codegen]
+//! relocation-model = "pie"
+//! code-model = "small"
+//! lto = "thin"
+//! codegen-units = 4
+//! ```
+//!
+//! `pic`, the default, links into anything: shared libraries need it, and
+//! executables built as PIE, as hardened distributions do, take it too.
+//! `pie` code only links into executables, but calls the program's own
+//! functions directly instead of through the symbol table. `static` code is
+//! for executables loaded at a fixed address. Without a code model, LLVM
+//! uses the target's usual one; `large` lifts its limit on how big a
+//! program can get, at the cost of longer address loads.
+//!
+//! With LTO, the compiler writes LLVM bitcode instead of machine code, and
+//! optimizing and generating code waits until link time, when the linker
+//! sees every module at once and can inline across them and drop what
+//! nothing calls. `full` merges the modules into one to optimize;
+//! `thin` optimizes them apart, each with a summary of the others, which
+//! is nearly as good and much faster on big programs.
+//!
+//! These only apply to native output: wasm modules are always static, and
+//! linked without LTO.
+
+use crate::library::LibraryKind;
+use serde::Deserialize;
+use std::fmt;
+
+/// The `[codegen]` table of `gard.toml`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Codegen {
+    pub relocation_model: Option<RelocationModel>,
+    pub code_model: Option<CodeModel>,
+    pub lto: Option<Lto>,
+    pub codegen_units: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelocationModel {
+    /// Fixed addresses, for executables that aren't position-independent
+    Static,
+    /// Position-independent code, for libraries and executables alike
+    #[default]
+    Pic,
+    /// Position-independent code for executables only
+    Pie,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeModel {
+    Small,
+    Kernel,
+    Medium,
+    Large,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lto {
+    Thin,
+    Full,
+}
+
+impl Lto {
+    /// The flag that turns it on in clang
+    pub fn flag(self) -> &'static str {
+        match self {
+            Lto::Thin => "-flto=thin",
+            Lto::Full => "-flto=full",
+        }
+    }
+}
+
+impl fmt::Display for RelocationModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RelocationModel::Static => "static",
+            RelocationModel::Pic => "pic",
+            RelocationModel::Pie => "pie",
+        })
+    }
+}
+
+/// Rejects a relocation model that can't go into a `kind` library: a
+/// shared library is loaded at any address, and its code can't assume
+/// it's the executable.
+pub fn check_library(kind: LibraryKind, relocation_model: RelocationModel) -> Result<(), String> {
+    match (kind, relocation_model) {
+        (LibraryKind::Shared, RelocationModel::Static | RelocationModel::Pie) => Err(format!(
+            "A shared library needs position-independent code; build it with relocation model pic, not {}",
+            relocation_model
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let codegen: Codegen = toml::from_str("relocation-model = \"pie\"\ncode-model = \"large\"\nlto = \"thin\"\ncodegen-units = 4").unwrap();
+        assert_eq!(codegen, Codegen { relocation_model: Some(RelocationModel::Pie), code_model: Some(CodeModel::Large), lto: Some(Lto::Thin), codegen_units: Some(4) });
+        assert_eq!(toml::from_str::<Codegen>("").unwrap(), Codegen::default());
+        assert!(toml::from_str::<Codegen>("relocation-model = \"dynamic-no-pic\"").is_err());
+        assert!(toml::from_str::<Codegen>("relocation_model = \"pic\"").is_err());
+        assert!(toml::from_str::<Codegen>("lto = true").is_err());
+        assert!(toml::from_str::<Codegen>("codegen-units = -1").is_err());
+    }
+
+    #[test]
+    fn test_check_library() {
+        assert!(check_library(LibraryKind::Shared, RelocationModel::Pic).is_ok());
+        assert!(check_library(LibraryKind::Static, RelocationModel::Pie).is_ok());
+        assert!(check_library(LibraryKind::Static, RelocationModel::Static).is_ok());
+        assert_eq!(check_library(LibraryKind::Shared, RelocationModel::Pie).unwrap_err(),
+            "A shared library needs position-independent code; build it with relocation model pic, not pie");
+        assert!(check_library(LibraryKind::Shared, RelocationModel::Static).is_err());
+    }
+}
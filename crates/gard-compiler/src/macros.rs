@@ -0,0 +1,317 @@
+//! Macro expansion. The driver runs this on the parsed program before type
+//! checking, so later passes never see `MacroDefinition` or `MacroCall`.
+//!
+//! A macro's body is an AST quote: a call is replaced by a copy of the body
+//! with each parameter replaced by the matching argument. Expansion is
+//! hygienic: variables the body declares itself are renamed per expansion,
+//! so they can't capture or clash with the caller's. Names a caller should
+//! see, like a generated function, have to be passed in as arguments. Every
+//! located statement of an expansion takes the span of its call, so errors
+//! and the debugger point at the use site.
+
+use gard_ast::{Node, Span};
+use std::collections::{HashMap, HashSet};
+
+/// Expansions nested deeper than this are reported as runaway recursion.
+pub const MAX_EXPANSION_DEPTH: usize = 64;
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Node>,
+}
+
+#[derive(Default)]
+struct Expander {
+    macros: HashMap<String, Macro>,
+    /// Numbers expansions, to give hygienic names a unique suffix
+    expansions: usize,
+    errors: Vec<String>,
+}
+
+/// Removes the program's macro definitions and expands every call.
+pub fn expand(program: Node) -> Result<Node, Vec<String>> {
+    let nodes = match program {
+        Node::Program(nodes) => nodes,
+        other => return Ok(other),
+    };
+
+    let mut expander = Expander::default();
+    let mut declarations = Vec::new();
+    for node in nodes {
+        match node {
+            Node::MacroDefinition { name, params, body } => expander.define(name, params, *body),
+            node => declarations.push(node),
+        }
+    }
+
+    let declarations = expander.expand_list(declarations, 0);
+    if expander.errors.is_empty() {
+        Ok(Node::Program(declarations))
+    } else {
+        Err(expander.errors)
+    }
+}
+
+impl Expander {
+    fn define(&mut self, name: String, params: Vec<String>, body: Node) {
+        if self.macros.contains_key(&name) {
+            self.errors.push(format!("Macro '{}' is defined more than once", name));
+            return;
+        }
+        let mut seen = HashSet::new();
+        if let Some(duplicate) = params.iter().find(|param| !seen.insert(*param)) {
+            self.errors.push(format!("Macro '{}' has two parameters named '{}'", name, duplicate));
+        }
+
+        let body = match body {
+            Node::Block(items) => items,
+            other => vec![other],
+        };
+        self.macros.insert(name, Macro { params, body });
+    }
+
+    /// Expands a declaration or statement list, splicing in the items of
+    /// each call.
+    fn expand_list(&mut self, nodes: Vec<Node>, depth: usize) -> Vec<Node> {
+        let mut expanded = Vec::with_capacity(nodes.len());
+        for mut node in nodes {
+            // Calls in blocks are parsed as located statements
+            if let Node::MacroCall { .. } = node.unlocated() {
+                if let Node::MacroCall { name, arguments, span } = node.into_unlocated() {
+                    let items = self.expand_call(&name, arguments, span, depth);
+                    expanded.extend(self.expand_list(items, depth + 1));
+                }
+                continue;
+            }
+            self.expand_node(&mut node, depth);
+            expanded.push(node);
+        }
+        expanded
+    }
+
+    fn expand_node(&mut self, node: &mut Node, depth: usize) {
+        match node {
+            Node::Program(nodes)
+            | Node::Block(nodes)
+            | Node::Class { members: nodes, .. }
+            | Node::Contract { members: nodes, .. } => {
+                *nodes = self.expand_list(std::mem::take(nodes), depth);
+            },
+            Node::MacroDefinition { name, .. } => {
+                self.errors.push(format!("Macro '{}' must be defined at the top level", name));
+            },
+            Node::MacroCall { name, span, .. } => {
+                self.errors.push(format!(
+                    "Macro '{}' can only be used as a declaration or statement, at position {}-{}",
+                    name, span.start, span.end,
+                ));
+            },
+            node => {
+                for child in node.children_mut() {
+                    self.expand_node(child, depth);
+                }
+            },
+        }
+    }
+
+    fn expand_call(&mut self, name: &str, arguments: Vec<Node>, span: Span, depth: usize) -> Vec<Node> {
+        let at = format!("at position {}-{}", span.start, span.end);
+        if depth >= MAX_EXPANSION_DEPTH {
+            self.errors.push(format!("Expanding macro '{}' {} nests deeper than {} levels", name, at, MAX_EXPANSION_DEPTH));
+            return vec![];
+        }
+        let Some(definition) = self.macros.get(name) else {
+            self.errors.push(format!("Unknown macro '{}' {}", name, at));
+            return vec![];
+        };
+        if arguments.len() != definition.params.len() {
+            self.errors.push(format!(
+                "Macro '{}' expects {} argument(s), found {} {}",
+                name, definition.params.len(), arguments.len(), at,
+            ));
+            return vec![];
+        }
+
+        self.expansions += 1;
+        let mut substitution = Substitution {
+            arguments: definition.params.iter().cloned().zip(arguments).collect(),
+            renames: HashMap::new(),
+            span,
+        };
+        let mut body = definition.body.clone();
+        for item in &body {
+            substitution.collect_bindings(item, self.expansions);
+        }
+        for item in &mut body {
+            substitution.apply(item);
+        }
+        body
+    }
+}
+
+struct Substitution {
+    arguments: HashMap<String, Node>,
+    /// Hygienic names for variables the macro body declares
+    renames: HashMap<String, String>,
+    span: Span,
+}
+
+impl Substitution {
+    fn collect_bindings(&mut self, node: &Node, expansion: usize) {
+        let declared = match node {
+            Node::Let { name, .. } | Node::Foreach { item: name, .. } | Node::CatchClause { param_name: name, .. } => Some(name),
+            _ => None,
+        };
+        if let Some(name) = declared.filter(|name| !self.arguments.contains_key(*name)) {
+            self.renames.insert(name.clone(), format!("{}__{}", name, expansion));
+        }
+        for child in node.children() {
+            self.collect_bindings(child, expansion);
+        }
+    }
+
+    /// A declared name, either passed in as an identifier argument or
+    /// renamed for hygiene.
+    fn name(&self, name: &mut String) {
+        if let Some(Node::Identifier(argument)) = self.arguments.get(name.as_str()) {
+            *name = argument.to_string();
+        } else if let Some(renamed) = self.renames.get(name.as_str()) {
+            *name = renamed.clone();
+        }
+    }
+
+    fn apply(&self, node: &mut Node) {
+        match node {
+            Node::Identifier(name) => {
+                if let Some(argument) = self.arguments.get(name.as_str()) {
+                    *node = argument.clone();
+                } else if let Some(renamed) = self.renames.get(name.as_str()) {
+                    *name = renamed.into();
+                }
+                return;
+            },
+            Node::Let { name, .. }
+            | Node::Foreach { item: name, .. }
+            | Node::CatchClause { param_name: name, .. }
+            | Node::Function { name, .. }
+            | Node::Class { name, .. }
+            | Node::Contract { name, .. }
+            | Node::Event { name, .. } => self.name(name),
+            Node::Located { span, .. } | Node::MacroCall { span, .. } => *span = self.span,
+            _ => {},
+        }
+        for child in node.children_mut() {
+            self.apply(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::Type;
+
+    fn ident(name: &str) -> Node {
+        Node::Identifier(name.into())
+    }
+
+    fn let_(name: &str, initializer: Node) -> Node {
+        Node::Let {
+            name: name.to_string(),
+            type_annotation: None,
+            initializer: Some(Box::new(initializer)),
+            is_mutable: false,
+        }
+    }
+
+    fn located(start: usize, node: Node) -> Node {
+        Node::Located { span: Span { start, end: start + 10 }, node: Box::new(node) }
+    }
+
+    fn call(name: &str, arguments: Vec<Node>, start: usize) -> Node {
+        Node::MacroCall { name: name.to_string(), arguments, span: Span { start, end: start + 10 } }
+    }
+
+    fn function(name: &str, body: Vec<Node>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type: Type::Void,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn definition(name: &str, params: &[&str], body: Vec<Node>) -> Node {
+        Node::MacroDefinition {
+            name: name.to_string(),
+            params: params.iter().map(|param| param.to_string()).collect(),
+            body: Box::new(Node::Block(body)),
+        }
+    }
+
+    #[test]
+    fn test_expands_declarations_and_statements() {
+        // macro getter(name, value) { function name { let result = value } }
+        // macro twice(value) { let tmp = value; let copy = tmp }
+        let program = Node::Program(vec![
+            definition("getter", &["name", "value"], vec![
+                function("name", vec![located(0, let_("result", ident("value")))]),
+            ]),
+            definition("twice", &["value"], vec![
+                located(0, let_("tmp", ident("value"))),
+                located(0, let_("copy", ident("tmp"))),
+            ]),
+            call("getter", vec![ident("balance"), Node::IntLiteral(7)], 100),
+            function("main", vec![
+                located(200, let_("tmp", Node::IntLiteral(1))),
+                located(210, call("twice", vec![ident("tmp")], 210)),
+            ]),
+        ]);
+
+        let expected = Node::Program(vec![
+            function("balance", vec![located(100, let_("result__1", Node::IntLiteral(7)))]),
+            function("main", vec![
+                located(200, let_("tmp", Node::IntLiteral(1))),
+                // The macro's own `tmp` doesn't capture the caller's
+                located(210, let_("tmp__2", ident("tmp"))),
+                located(210, let_("copy__2", ident("tmp__2"))),
+            ]),
+        ]);
+        assert_eq!(expand(program).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_nested_and_recursive_macros() {
+        let program = Node::Program(vec![
+            definition("inner", &["name"], vec![function("name", vec![])]),
+            definition("outer", &["name"], vec![call("inner", vec![ident("name")], 0)]),
+            definition("forever", &[], vec![call("forever", vec![], 0)]),
+            call("outer", vec![ident("generated")], 50),
+        ]);
+        assert_eq!(expand(program.clone()).unwrap(), Node::Program(vec![function("generated", vec![])]));
+
+        let Node::Program(mut nodes) = program else { unreachable!() };
+        nodes.push(call("forever", vec![], 80));
+        let errors = expand(Node::Program(nodes)).unwrap_err();
+        assert_eq!(errors, vec![format!("Expanding macro 'forever' at position 80-90 nests deeper than {} levels", MAX_EXPANSION_DEPTH)]);
+    }
+
+    #[test]
+    fn test_expansion_errors() {
+        let program = Node::Program(vec![
+            definition("one", &["a"], vec![]),
+            definition("one", &["a", "a"], vec![]),
+            call("missing", vec![], 0),
+            call("one", vec![], 20),
+            function("main", vec![let_("x", call("one", vec![ident("y")], 40))]),
+        ]);
+        assert_eq!(expand(program).unwrap_err(), vec![
+            "Macro 'one' is defined more than once".to_string(),
+            "Unknown macro 'missing' at position 0-10".to_string(),
+            "Macro 'one' expects 1 argument(s), found 0 at position 20-30".to_string(),
+            "Macro 'one' can only be used as a declaration or statement, at position 40-50".to_string(),
+        ]);
+    }
+}
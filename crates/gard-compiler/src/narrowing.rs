@@ -0,0 +1,71 @@
+//! Flow-sensitive narrowing. Where `x is Square` is known to hold, in the
+//! `then` branch of an `if`, the body of a `while` or on the right of `&&`,
+//! the variable `x` has type `Square`; where it's known not to hold, in an
+//! `else` branch or on the right of `||`, conditions like `!(x is Square)`
+//! narrow the same way.
+
+use gard_ast::{BinaryOp, Node, Symbol, Type, UnaryOp};
+
+/// The variables a condition narrows, with their types, where it evaluates
+/// to `holds`.
+pub fn narrowings(condition: &Node, holds: bool) -> Vec<(Symbol, Type)> {
+    match condition.unlocated() {
+        Node::TypeTest { value, target } if holds => match value.unlocated() {
+            Node::Identifier(name) => vec![(*name, target.clone())],
+            _ => Vec::new(),
+        },
+        Node::Unary { operator: UnaryOp::Not, operand } => narrowings(operand, !holds),
+        // Both sides hold where `a && b` does, and neither where `a || b` doesn't
+        Node::Binary { left, operator: BinaryOp::And, right } if holds => {
+            let mut narrowed = narrowings(left, true);
+            narrowed.extend(narrowings(right, true));
+            narrowed
+        },
+        Node::Binary { left, operator: BinaryOp::Or, right } if !holds => {
+            let mut narrowed = narrowings(left, false);
+            narrowed.extend(narrowings(right, false));
+            narrowed
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// What the left side of `&&` or `||` narrows on its right side, which only
+/// runs when the left side is true or false respectively.
+pub fn right_operand_narrowings(left: &Node, operator: &BinaryOp) -> Vec<(Symbol, Type)> {
+    match operator {
+        BinaryOp::And => narrowings(left, true),
+        BinaryOp::Or => narrowings(left, false),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is(name: &str, class: &str) -> Node {
+        Node::TypeTest { value: Box::new(Node::Identifier(name.into())), target: Type::Custom(class.into()) }
+    }
+
+    fn not(operand: Node) -> Node {
+        Node::Unary { operator: UnaryOp::Not, operand: Box::new(operand) }
+    }
+
+    fn binary(left: Node, operator: BinaryOp, right: Node) -> Node {
+        Node::Binary { left: Box::new(left), operator, right: Box::new(right) }
+    }
+
+    #[test]
+    fn test_narrowings() {
+        let square = |name: &str| (Symbol::intern(name), Type::Custom("Square".into()));
+        assert_eq!(narrowings(&is("a", "Square"), true), vec![square("a")]);
+        assert_eq!(narrowings(&is("a", "Square"), false), vec![]);
+        assert_eq!(narrowings(&not(is("a", "Square")), false), vec![square("a")]);
+        assert_eq!(narrowings(&binary(is("a", "Square"), BinaryOp::And, is("b", "Square")), true), vec![square("a"), square("b")]);
+        assert_eq!(narrowings(&binary(is("a", "Square"), BinaryOp::Or, is("b", "Square")), true), vec![]);
+        assert_eq!(narrowings(&binary(not(is("a", "Square")), BinaryOp::Or, not(is("b", "Square"))), false),
+            vec![square("a"), square("b")]);
+        assert_eq!(right_operand_narrowings(&not(is("a", "Square")), &BinaryOp::Or), vec![square("a")]);
+    }
+}
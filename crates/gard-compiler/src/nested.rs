@@ -0,0 +1,265 @@
+//! Nested classes. A class declared in another's body is named by its
+//! qualified name, `Outer.Inner`, and hoisted to the top level after the
+//! class that declares it, so the later passes only ever see top-level
+//! classes.
+//!
+//! Inside a class, the names of the classes nested in it and in the classes
+//! around it can be written bare, the innermost one first: in `Outer`'s
+//! body `Inner` is `Outer.Inner`, and in `Inner`'s `Deep` is
+//! `Outer.Inner.Deep`. Anywhere else they are written qualified, in types
+//! as in expressions, like `Outer.Inner(..)`.
+
+use gard_ast::{Node, Parameter, Type};
+use std::collections::HashSet;
+
+/// Hoists every nested class in the program, resolving the references to
+/// them to their qualified names.
+pub fn hoist(program: Node) -> Result<Node, Vec<String>> {
+    let Node::Program(declarations) = program else {
+        return Ok(program);
+    };
+    let mut nested = HashSet::new();
+    let mut errors = Vec::new();
+    for declaration in &declarations {
+        collect_nested(declaration, "", &mut nested, &mut errors);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    if nested.is_empty() {
+        return Ok(Node::Program(declarations));
+    }
+    let hoisted = declarations.into_iter()
+        .flat_map(|declaration| lift(declaration, "", &nested))
+        .collect();
+    Ok(Node::Program(hoisted))
+}
+
+/// The prefix of a class's symbols in codegen: its qualified name, with
+/// `$` separating a nested class from the class it's in, so the symbols of
+/// `Outer.Inner` can't be mistaken for ones of `Outer` itself, like its type
+/// info `Outer.rtti`.
+pub fn symbol(class: &str) -> String {
+    class.replace('.', "$")
+}
+
+fn collect_nested(node: &Node, scope: &str, nested: &mut HashSet<String>, errors: &mut Vec<String>) {
+    match node {
+        Node::Class { name, members, .. } => {
+            let class = qualify(scope, name);
+            for member in members {
+                if let Node::Class { name, .. } = member.unlocated() {
+                    if !nested.insert(qualify(&class, name)) {
+                        errors.push(format!("Class '{}' declares nested class '{}' more than once", class, name));
+                    }
+                }
+                collect_nested(member.unlocated(), &class, nested, errors);
+            }
+        },
+        Node::Located { node, .. } => collect_nested(node, scope, nested, errors),
+        Node::Derive { declaration, .. } => collect_nested(declaration, scope, nested, errors),
+        _ => {},
+    }
+}
+
+/// A declaration, followed by the classes nested in it.
+fn lift(node: Node, scope: &str, nested: &HashSet<String>) -> Vec<Node> {
+    match node {
+        Node::Class { name, extends, implements, is_abstract, members, docs } => {
+            let class = qualify(scope, &name);
+            // The bases are named from around the class, not inside it
+            let extends = extends.map(|base| resolve(&base, scope, nested).unwrap_or(base));
+            let implements = implements.into_iter()
+                .map(|interface| resolve(&interface, scope, nested).unwrap_or(interface))
+                .collect();
+            let mut inner = Vec::new();
+            let mut kept = Vec::new();
+            for member in members {
+                match member.unlocated() {
+                    Node::Class { .. } => inner.extend(lift(member.into_unlocated(), &class, nested)),
+                    _ => {
+                        let mut member = member;
+                        qualify_references(&mut member, &class, nested);
+                        kept.push(member);
+                    },
+                }
+            }
+            let mut lifted = vec![Node::Class { name: class, extends, implements, is_abstract, members: kept, docs }];
+            lifted.extend(inner);
+            lifted
+        },
+        Node::Located { span, node } => rewrap(lift(*node, scope, nested), |node| Node::Located { span, node: Box::new(node) }),
+        Node::Derive { derives, declaration } => {
+            rewrap(lift(*declaration, scope, nested), |declaration| Node::Derive { derives, declaration: Box::new(declaration) })
+        },
+        mut node => {
+            qualify_references(&mut node, scope, nested);
+            vec![node]
+        },
+    }
+}
+
+/// Wraps the declaration that was lifted back up, keeping the classes
+/// nested in it after the wrapper.
+fn rewrap(lifted: Vec<Node>, wrap: impl FnOnce(Node) -> Node) -> Vec<Node> {
+    let mut lifted = lifted.into_iter();
+    let declaration = lifted.next().map(wrap);
+    declaration.into_iter().chain(lifted).collect()
+}
+
+/// Rewrites the nested classes' names in the types and expressions of a
+/// node in `scope` to their qualified names.
+fn qualify_references(node: &mut Node, scope: &str, nested: &HashSet<String>) {
+    if let Some(class) = path(node).and_then(|path| resolve(&path, scope, nested)) {
+        *node = Node::Identifier(class.into());
+        return;
+    }
+    match node {
+        Node::Function { params, return_type, .. } => {
+            qualify_params(params, scope, nested);
+            qualify_type(return_type, scope, nested);
+        },
+        Node::Constructor { params, .. } | Node::Event { fields: params, .. } => qualify_params(params, scope, nested),
+        Node::Let { type_annotation: Some(ty), .. }
+        | Node::Const { type_annotation: Some(ty), .. }
+        | Node::TypeTest { target: ty, .. }
+        | Node::SafeCast { target: ty, .. }
+        | Node::CatchClause { param_type: ty, .. } => qualify_type(ty, scope, nested),
+        _ => {},
+    }
+    for child in node.children_mut() {
+        qualify_references(child, scope, nested);
+    }
+}
+
+fn qualify_params(params: &mut [Parameter], scope: &str, nested: &HashSet<String>) {
+    for param in params {
+        qualify_type(&mut param.type_annotation, scope, nested);
+    }
+}
+
+fn qualify_type(ty: &mut Type, scope: &str, nested: &HashSet<String>) {
+    match ty {
+        Type::Custom(name) => {
+            if let Some(class) = resolve(name, scope, nested) {
+                *name = class.into();
+            }
+        },
+        Type::Array(element) | Type::Set(element) => qualify_type(element, scope, nested),
+        Type::Map { key, value } => {
+            qualify_type(key, scope, nested);
+            qualify_type(value, scope, nested);
+        },
+        Type::Function { params, return_type } => {
+            for param in params {
+                qualify_type(param, scope, nested);
+            }
+            qualify_type(return_type, scope, nested);
+        },
+        _ => {},
+    }
+}
+
+/// `a.b.c` for an identifier or a chain of members of one.
+fn path(node: &Node) -> Option<String> {
+    match node {
+        Node::Identifier(name) => Some(name.to_string()),
+        Node::Member { object, property } => path(object).map(|object| format!("{}.{}", object, property)),
+        _ => None,
+    }
+}
+
+/// The nested class a name written in `scope` refers to, looking in the
+/// innermost class first. Its first part may be bare, as in `Inner.Deep`.
+fn resolve(name: &str, scope: &str, nested: &HashSet<String>) -> Option<String> {
+    let mut scope = scope;
+    while !scope.is_empty() {
+        let class = qualify(scope, name);
+        if nested.contains(&class) {
+            return Some(class);
+        }
+        scope = scope.rsplit_once('.').map_or("", |(outer, _)| outer);
+    }
+    nested.contains(name).then(|| name.to_string())
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    match scope {
+        "" => name.to_string(),
+        scope => format!("{}.{}", scope, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(name: &str, extends: Option<&str>, members: Vec<Node>) -> Node {
+        Node::Class { name: name.to_string(), extends: extends.map(str::to_string), implements: vec![], is_abstract: false, members, docs: None }
+    }
+
+    fn field(name: &str, ty: &str) -> Node {
+        Node::Let { name: name.to_string(), type_annotation: Some(Type::Custom(ty.into())), initializer: None, is_mutable: false }
+    }
+
+    fn new(callee: Node) -> Node {
+        Node::Call { callee: Box::new(callee), arguments: vec![] }
+    }
+
+    fn member(object: Node, property: &str) -> Node {
+        Node::Member { object: Box::new(object), property: property.to_string() }
+    }
+
+    fn identifier(name: &str) -> Node {
+        Node::Identifier(name.into())
+    }
+
+    #[test]
+    fn test_hoist_nested_classes() {
+        let program = Node::Program(vec![
+            class("Outer", None, vec![
+                field("inner", "Inner"),
+                class("Inner", None, vec![
+                    field("deep", "Deep"),
+                    field("outer", "Outer"),
+                    class("Deep", Some("Inner"), vec![]),
+                ]),
+                Node::Let {
+                    name: "made".to_string(),
+                    type_annotation: None,
+                    initializer: Some(Box::new(new(member(identifier("Inner"), "Deep")))),
+                    is_mutable: false,
+                },
+            ]),
+            new(member(identifier("Outer"), "Inner")),
+            new(member(member(identifier("Outer"), "Inner"), "Deep")),
+            // Only qualified outside the class
+            new(identifier("Inner")),
+        ]);
+
+        let Node::Program(hoisted) = hoist(program).unwrap() else {
+            panic!("Expected a program");
+        };
+        assert_eq!(hoisted[0], class("Outer", None, vec![
+            field("inner", "Outer.Inner"),
+            Node::Let {
+                name: "made".to_string(),
+                type_annotation: None,
+                initializer: Some(Box::new(new(identifier("Outer.Inner.Deep")))),
+                is_mutable: false,
+            },
+        ]));
+        assert_eq!(hoisted[1], class("Outer.Inner", None, vec![field("deep", "Outer.Inner.Deep"), field("outer", "Outer")]));
+        assert_eq!(hoisted[2], class("Outer.Inner.Deep", Some("Outer.Inner"), vec![]));
+        assert_eq!(hoisted[3], new(identifier("Outer.Inner")));
+        assert_eq!(hoisted[4], new(identifier("Outer.Inner.Deep")));
+        assert_eq!(hoisted[5], new(identifier("Inner")));
+        assert_eq!(symbol("Outer.Inner.Deep"), "Outer$Inner$Deep");
+    }
+
+    #[test]
+    fn test_duplicate_nested_classes() {
+        let program = Node::Program(vec![class("Outer", None, vec![class("Inner", None, vec![]), class("Inner", None, vec![])])]);
+        assert_eq!(hoist(program), Err(vec!["Class 'Outer' declares nested class 'Inner' more than once".to_string()]));
+    }
+}
@@ -0,0 +1,161 @@
+use gard_ast::{Node, Type};
+
+/// Builtins of `std.net`, called as `net.connect("host:port")`. Like the
+/// streams of `std.io`, listeners, connections and sockets are handles to
+/// gard-vm's sockets, stored as an int; the checker keeps them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetBuiltin {
+    Listen,
+    Accept,
+    Connect,
+    Send,
+    Receive,
+    Bind,
+    SendTo,
+    ReceiveFrom,
+    Peer,
+    Sender,
+    LocalAddress,
+    Close,
+    Watch,
+    Unwatch,
+    Poll,
+}
+
+pub const MODULE: &str = "net";
+/// A TCP listener
+pub const LISTENER: &str = "Listener";
+/// A TCP stream
+pub const CONNECTION: &str = "Connection";
+/// A UDP socket
+pub const SOCKET: &str = "Socket";
+/// Any of the three, a union the checker declares
+pub const ENDPOINT: &str = "Endpoint";
+
+fn custom(name: &str) -> Type {
+    Type::Custom(name.into())
+}
+
+/// Whether values of `ty` are socket handles.
+pub fn is_handle_type(ty: &Type) -> bool {
+    matches!(ty, Type::Custom(name) if [LISTENER, CONNECTION, SOCKET, ENDPOINT].contains(&name.as_str()))
+}
+
+impl NetBuiltin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "listen" => Some(NetBuiltin::Listen),
+            "accept" => Some(NetBuiltin::Accept),
+            "connect" => Some(NetBuiltin::Connect),
+            "send" => Some(NetBuiltin::Send),
+            "receive" => Some(NetBuiltin::Receive),
+            "bind" => Some(NetBuiltin::Bind),
+            "sendTo" => Some(NetBuiltin::SendTo),
+            "receiveFrom" => Some(NetBuiltin::ReceiveFrom),
+            "peer" => Some(NetBuiltin::Peer),
+            "sender" => Some(NetBuiltin::Sender),
+            "localAddress" => Some(NetBuiltin::LocalAddress),
+            "close" => Some(NetBuiltin::Close),
+            "watch" => Some(NetBuiltin::Watch),
+            "unwatch" => Some(NetBuiltin::Unwatch),
+            "poll" => Some(NetBuiltin::Poll),
+            _ => None,
+        }
+    }
+
+    /// Resolves `net.accept(..)` style callees.
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee.unlocated() {
+            Node::Member { object, property } => match object.unlocated() {
+                Node::Identifier(module) if module == MODULE => Self::from_name(property),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NetBuiltin::Listen => "listen",
+            NetBuiltin::Accept => "accept",
+            NetBuiltin::Connect => "connect",
+            NetBuiltin::Send => "send",
+            NetBuiltin::Receive => "receive",
+            NetBuiltin::Bind => "bind",
+            NetBuiltin::SendTo => "sendTo",
+            NetBuiltin::ReceiveFrom => "receiveFrom",
+            NetBuiltin::Peer => "peer",
+            NetBuiltin::Sender => "sender",
+            NetBuiltin::LocalAddress => "localAddress",
+            NetBuiltin::Close => "close",
+            NetBuiltin::Watch => "watch",
+            NetBuiltin::Unwatch => "unwatch",
+            NetBuiltin::Poll => "poll",
+        }
+    }
+
+    /// Addresses are `host:port` strings. `receive(connection, n)` returns
+    /// up to n bytes and empty bytes once the peer has closed;
+    /// `receiveFrom(socket, n)` returns one datagram, and `sender(socket)`
+    /// where it came from. `watch(endpoint, token)` adds an endpoint to
+    /// those `poll(timeoutMs)` waits on; it returns the token of one that
+    /// is ready, or -1 on timeout.
+    pub fn signature(&self) -> Type {
+        let (params, return_type) = match self {
+            NetBuiltin::Listen => (vec![Type::String], custom(LISTENER)),
+            NetBuiltin::Accept => (vec![custom(LISTENER)], custom(CONNECTION)),
+            NetBuiltin::Connect => (vec![Type::String], custom(CONNECTION)),
+            NetBuiltin::Send => (vec![custom(CONNECTION), Type::Bytes], Type::Void),
+            NetBuiltin::Receive => (vec![custom(CONNECTION), Type::Int], Type::Bytes),
+            NetBuiltin::Bind => (vec![Type::String], custom(SOCKET)),
+            NetBuiltin::SendTo => (vec![custom(SOCKET), Type::Bytes, Type::String], Type::Void),
+            NetBuiltin::ReceiveFrom => (vec![custom(SOCKET), Type::Int], Type::Bytes),
+            NetBuiltin::Peer => (vec![custom(CONNECTION)], Type::String),
+            NetBuiltin::Sender => (vec![custom(SOCKET)], Type::String),
+            NetBuiltin::LocalAddress => (vec![custom(ENDPOINT)], Type::String),
+            NetBuiltin::Close => (vec![custom(ENDPOINT)], Type::Void),
+            NetBuiltin::Watch => (vec![custom(ENDPOINT), Type::Int], Type::Void),
+            NetBuiltin::Unwatch => (vec![custom(ENDPOINT)], Type::Void),
+            NetBuiltin::Poll => (vec![Type::Int], Type::Int),
+        };
+        Type::Function { params, return_type: Box::new(return_type) }
+    }
+
+    /// Symbol of the native implementation in gard-vm.
+    pub fn runtime_symbol(&self) -> &'static str {
+        match self {
+            NetBuiltin::Listen => "gard_net_listen",
+            NetBuiltin::Accept => "gard_net_accept",
+            NetBuiltin::Connect => "gard_net_connect",
+            NetBuiltin::Send => "gard_net_send",
+            NetBuiltin::Receive => "gard_net_receive",
+            NetBuiltin::Bind => "gard_net_bind",
+            NetBuiltin::SendTo => "gard_net_send_to",
+            NetBuiltin::ReceiveFrom => "gard_net_receive_from",
+            NetBuiltin::Peer => "gard_net_peer",
+            NetBuiltin::Sender => "gard_net_sender",
+            NetBuiltin::LocalAddress => "gard_net_local_address",
+            NetBuiltin::Close => "gard_net_close",
+            NetBuiltin::Watch => "gard_net_watch",
+            NetBuiltin::Unwatch => "gard_net_unwatch",
+            NetBuiltin::Poll => "gard_net_poll",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_member_callee() {
+        let callee = |module: &str, name: &str| Node::Member {
+            object: Box::new(Node::Identifier(module.into())),
+            property: name.to_string(),
+        };
+        assert_eq!(NetBuiltin::from_callee(&callee("net", "sendTo")), Some(NetBuiltin::SendTo));
+        assert_eq!(NetBuiltin::from_callee(&callee("io", "accept")), None);
+        assert!(is_handle_type(&custom(ENDPOINT)));
+        assert!(!is_handle_type(&custom("Reader")));
+    }
+}
@@ -0,0 +1,481 @@
+//! Compiler plugins. A plugin registers AST passes, lints and handlers for
+//! `@name(..)` attributes, either in-process through `Registry::add` or
+//! from a dynamic library through `Registry::load` (`--plugin path.so`).
+//!
+//! The driver runs them after the built-in expansions and before type
+//! checking: attribute handlers first, then passes in registration order,
+//! then lints, whose findings are warnings rather than errors.
+//!
+//! A lint's level can be changed for a declaration and what's in it with
+//! `@allow(name)`, `@warn(name)` or `@deny(name)`, for the whole file with
+//! the same attribute on its own, `@allow(name);`, and for the build with
+//! `Registry::set_level`. The innermost level wins. Denied findings are
+//! errors.
+//!
+//! Plugins share Rust types with the compiler, so a plugin library has to
+//! be built with the same rustc and gard-compiler version as the host; see
+//! `declare_plugin!`.
+
+use crate::profile::{self, Profile};
+use gard_ast::Node;
+use libloading::{Library, Symbol};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Bumped whenever `Registry`'s registration methods change. Libraries built
+/// for another version are refused.
+pub const API_VERSION: u32 = 2;
+
+/// Rewrites the program, or reports why it can't.
+pub type Pass = dyn Fn(Node) -> Result<Node, Vec<String>>;
+pub type Lint = dyn Fn(&Node) -> Vec<Finding>;
+/// Receives an attribute's arguments and the declaration it is on, and
+/// returns what replaces them.
+pub type AttributeHandler = dyn Fn(&[Node], Node) -> Result<Node, String>;
+
+/// What a lint found, in the declaration it's about: its path, like
+/// `Token.transfer`, or empty for the whole program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub declaration: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl LintLevel {
+    /// The level an attribute sets, if it's `@allow`, `@warn` or `@deny`.
+    fn from_attribute(name: &str) -> Option<Self> {
+        match name {
+            "allow" => Some(Self::Allow),
+            "warn" => Some(Self::Warn),
+            "deny" => Some(Self::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// Levels set by attributes, by the path of the declaration they're on;
+/// the empty path is the file's.
+type Scopes = HashMap<String, HashMap<String, LintLevel>>;
+
+pub trait Plugin {
+    fn name(&self) -> &str;
+    fn register(&self, registry: &mut Registry);
+}
+
+#[derive(Default)]
+pub struct Registry {
+    passes: Vec<(String, Box<Pass>)>,
+    lints: Vec<(String, Box<Lint>)>,
+    /// Levels set for the whole build
+    levels: HashMap<String, LintLevel>,
+    /// Handlers with the name of the plugin that registered them
+    attributes: HashMap<String, (String, Box<AttributeHandler>)>,
+    /// The plugin `add` is registering
+    registering: String,
+    errors: Vec<String>,
+    // Last, so the closures above are dropped before their code is unloaded
+    libraries: Vec<Library>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, plugin: &dyn Plugin) {
+        self.registering = plugin.name().to_string();
+        plugin.register(self);
+        self.registering.clear();
+    }
+
+    pub fn add_pass(&mut self, name: &str, pass: impl Fn(Node) -> Result<Node, Vec<String>> + 'static) {
+        self.passes.push((name.to_string(), Box::new(pass)));
+    }
+
+    /// Lints are named by identifiers, so attributes can name them:
+    /// `dead_code`, not `dead-code`.
+    pub fn add_lint(&mut self, name: &str, lint: impl Fn(&Node) -> Vec<Finding> + 'static) {
+        self.lints.push((name.to_string(), Box::new(lint)));
+    }
+
+    /// Sets a lint's level for the whole build, where no attribute sets it.
+    pub fn set_level(&mut self, lint: &str, level: LintLevel) {
+        self.levels.insert(lint.to_string(), level);
+    }
+
+    /// Handles `@name(..)`. Each attribute can only have one handler.
+    pub fn add_attribute(&mut self, name: &str, handler: impl Fn(&[Node], Node) -> Result<Node, String> + 'static) {
+        let plugin = self.registering.clone();
+        if let Some((previous, _)) = self.attributes.insert(name.to_string(), (plugin.clone(), Box::new(handler))) {
+            self.errors.push(format!("Attribute '@{}' is registered by both '{}' and '{}'", name, previous, plugin));
+        }
+    }
+
+    /// Loads a plugin library that exports its plugin with `declare_plugin!`.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let failed = |e: libloading::Error| format!("Failed to load plugin {}: {}", path.display(), e);
+        // Safety: loading a library runs its initializers; plugins are trusted
+        // like the compiler itself.
+        let library = unsafe { Library::new(path) }.map_err(failed)?;
+        unsafe {
+            let version: Symbol<extern "C" fn() -> u32> = library.get(b"gard_plugin_api_version").map_err(failed)?;
+            if version() != API_VERSION {
+                return Err(format!(
+                    "Plugin {} was built for plugin API version {}, this compiler has version {}",
+                    path.display(), version(), API_VERSION,
+                ));
+            }
+            let register: Symbol<fn(&mut Registry)> = library.get(b"gard_plugin_register").map_err(failed)?;
+            register(self);
+        }
+        self.libraries.push(library);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty() && self.lints.is_empty() && self.attributes.is_empty()
+    }
+
+    /// Runs every plugin over the program, returning it with the lint
+    /// warnings.
+    pub fn run(&self, program: Node) -> Result<(Node, Vec<String>), Vec<String>> {
+        self.run_profiled(program, None)
+    }
+
+    /// Like `run`, timing the attributes, then each pass and lint, as
+    /// stages of `profile`.
+    pub fn run_profiled(&self, mut program: Node, profile: Option<&Profile>) -> Result<(Node, Vec<String>), Vec<String>> {
+        let mut errors = self.errors.clone();
+        for lint in self.levels.keys().filter(|lint| !self.has_lint(lint)) {
+            errors.push(format!("Unknown lint '{}'", lint));
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut scopes = Scopes::new();
+        profile::time(profile, "attributes", || {
+            if let Node::Program(nodes) = &mut program {
+                self.collect_levels(nodes, "", &mut scopes, &mut errors);
+            }
+            self.expand_attributes(&mut program, &mut errors);
+        });
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        for (name, pass) in &self.passes {
+            program = profile::time(profile, &format!("pass {}", name), || pass(program)).map_err(|errors| {
+                errors.into_iter().map(|e| format!("{}: {}", name, e)).collect::<Vec<_>>()
+            })?;
+        }
+
+        let mut warnings = Vec::new();
+        for (name, lint) in &self.lints {
+            for finding in profile::time(profile, &format!("lint {}", name), || lint(&program)) {
+                let message = format!("{}: {}", name, finding.message);
+                match self.level(name, &finding.declaration, &scopes) {
+                    LintLevel::Allow => {},
+                    LintLevel::Warn => warnings.push(message),
+                    LintLevel::Deny => errors.push(message),
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok((program, warnings))
+    }
+
+    fn has_lint(&self, name: &str) -> bool {
+        self.lints.iter().any(|(lint, _)| lint == name)
+    }
+
+    /// The level at the innermost declaration around `declaration` that
+    /// sets one, else the build's, else `Warn`.
+    fn level(&self, lint: &str, declaration: &str, scopes: &Scopes) -> LintLevel {
+        let mut path = declaration;
+        loop {
+            if let Some(level) = scopes.get(path).and_then(|levels| levels.get(lint)) {
+                return *level;
+            }
+            if path.is_empty() {
+                return self.levels.get(lint).copied().unwrap_or(LintLevel::Warn);
+            }
+            path = path.rfind('.').map_or("", |end| &path[..end]);
+        }
+    }
+
+    /// Takes the level attributes off the declarations in `scope`, and
+    /// removes the ones on their own.
+    fn collect_levels(&self, nodes: &mut Vec<Node>, scope: &str, scopes: &mut Scopes, errors: &mut Vec<String>) {
+        for node in nodes.iter_mut() {
+            while let Node::Attribute { name, arguments, declaration } = node {
+                let Some(level) = LintLevel::from_attribute(name) else {
+                    break;
+                };
+                let path = match declaration_name(declaration) {
+                    Some(name) => qualify(scope, name),
+                    None if matches!(declaration.as_ref(), Node::Program(nodes) if nodes.is_empty()) => scope.to_string(),
+                    None => {
+                        errors.push(format!("@{} only applies to declarations", name));
+                        *node = std::mem::replace(declaration.as_mut(), Node::NullLiteral);
+                        continue;
+                    },
+                };
+                for argument in arguments.iter() {
+                    match argument.unlocated() {
+                        Node::Identifier(lint) if self.has_lint(lint) => {
+                            scopes.entry(path.clone()).or_default().insert(lint.to_string(), level);
+                        },
+                        Node::Identifier(lint) => errors.push(format!("@{}: Unknown lint '{}'", name, lint)),
+                        _ => errors.push(format!("@{} takes lint names, like @{}(dead_code)", name, name)),
+                    }
+                }
+                *node = std::mem::replace(declaration.as_mut(), Node::NullLiteral);
+            }
+            let inner = declaration_name(node).map(|name| qualify(scope, name));
+            if let (Some(inner), Some(members)) = (inner, members_mut(node)) {
+                self.collect_levels(members, &inner, scopes, errors);
+            }
+        }
+        nodes.retain(|node| !matches!(node, Node::Program(nodes) if nodes.is_empty()));
+    }
+
+    fn expand_attributes(&self, node: &mut Node, errors: &mut Vec<String>) {
+        match node {
+            Node::Program(nodes)
+            | Node::Block(nodes)
+            | Node::Class { members: nodes, .. }
+            | Node::Contract { members: nodes, .. } => {
+                for node in nodes.iter_mut() {
+                    if let Node::Attribute { name, arguments, declaration } = node {
+                        let declaration = std::mem::replace(declaration.as_mut(), Node::NullLiteral);
+                        match self.attributes.get(name.as_str()) {
+                            Some((_, handler)) => match handler(arguments, declaration) {
+                                Ok(replacement) => *node = replacement,
+                                Err(e) => errors.push(format!("@{}: {}", name, e)),
+                            },
+                            None => errors.push(format!("Unknown attribute '@{}'; no plugin handles it", name)),
+                        }
+                    }
+                    self.expand_attributes(node, errors);
+                }
+            },
+            node => {
+                for child in node.children_mut() {
+                    self.expand_attributes(child, errors);
+                }
+            },
+        }
+    }
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+/// The name of a declaration, through what wraps it.
+fn declaration_name(node: &Node) -> Option<&str> {
+    match node.unlocated() {
+        Node::Function { name, .. }
+        | Node::Class { name, .. }
+        | Node::Contract { name, .. }
+        | Node::Actor { name, .. }
+        | Node::Union { name, .. }
+        | Node::Interface { name, .. } => Some(name),
+        Node::Derive { declaration, .. } | Node::WasmExport { declaration, .. } | Node::Attribute { declaration, .. } => {
+            declaration_name(declaration)
+        },
+        _ => None,
+    }
+}
+
+fn members_mut(node: &mut Node) -> Option<&mut Vec<Node>> {
+    match node {
+        Node::Located { node, .. } => members_mut(node),
+        Node::Class { members, .. } | Node::Contract { members, .. } | Node::Actor { members, .. } => Some(members),
+        Node::Derive { declaration, .. } | Node::WasmExport { declaration, .. } | Node::Attribute { declaration, .. } => {
+            members_mut(declaration)
+        },
+        _ => None,
+    }
+}
+
+/// Exports a plugin from a `cdylib` crate so `--plugin` can load it:
+/// `gard_compiler::declare_plugin!(MyPlugin);`
+#[macro_export]
+macro_rules! declare_plugin {
+    ($plugin:expr) => {
+        #[no_mangle]
+        pub extern "C" fn gard_plugin_api_version() -> u32 {
+            $crate::plugin::API_VERSION
+        }
+
+        #[no_mangle]
+        pub fn gard_plugin_register(registry: &mut $crate::plugin::Registry) {
+            registry.add(&$plugin);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::Type;
+
+    fn function(name: &str) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type: Type::Void,
+            body: Box::new(Node::Block(vec![])),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn attribute(name: &str, arguments: Vec<Node>, declaration: Node) -> Node {
+        Node::Attribute { name: name.to_string(), arguments, declaration: Box::new(declaration) }
+    }
+
+    /// `@rename(other)` renames a function; the lint flags functions named `todo`.
+    struct Rename;
+
+    impl Plugin for Rename {
+        fn name(&self) -> &str {
+            "rename"
+        }
+
+        fn register(&self, registry: &mut Registry) {
+            registry.add_attribute("rename", |arguments, mut declaration| {
+                let new_name = match arguments {
+                    [Node::Identifier(name)] => name.to_string(),
+                    _ => return Err("expected one name".to_string()),
+                };
+                match &mut declaration {
+                    Node::Function { name, .. } => *name = new_name,
+                    _ => return Err("only functions can be renamed".to_string()),
+                }
+                Ok(declaration)
+            });
+            registry.add_pass("sort", |program| match program {
+                Node::Program(mut nodes) => {
+                    nodes.sort_by_key(|node| match node {
+                        Node::Function { name, .. } => name.clone(),
+                        _ => String::new(),
+                    });
+                    Ok(Node::Program(nodes))
+                },
+                other => Err(vec![format!("expected a program, found {:?}", other)]),
+            });
+            registry.add_lint("todo", |program| {
+                program.children().into_iter()
+                    .filter(|node| matches!(node, Node::Function { name, .. } if name == "todo"))
+                    .map(|_| Finding { declaration: "todo".to_string(), message: "function 'todo' left in".to_string() })
+                    .collect()
+            });
+        }
+    }
+
+    #[test]
+    fn test_runs_plugins() {
+        let mut registry = Registry::new();
+        registry.add(&Rename);
+        let program = Node::Program(vec![
+            function("b"),
+            attribute("rename", vec![Node::Identifier("todo".into())], function("c")),
+            function("a"),
+        ]);
+
+        let (program, warnings) = registry.run(program).unwrap();
+        assert_eq!(program, Node::Program(vec![function("a"), function("b"), function("todo")]));
+        assert_eq!(warnings, vec!["todo: function 'todo' left in".to_string()]);
+    }
+
+    #[test]
+    fn test_plugin_errors() {
+        let mut registry = Registry::new();
+        registry.add(&Rename);
+        let program = Node::Program(vec![
+            attribute("rename", vec![], function("a")),
+            attribute("inline", vec![], function("b")),
+        ]);
+        assert_eq!(registry.run(program).unwrap_err(), vec![
+            "@rename: expected one name".to_string(),
+            "Unknown attribute '@inline'; no plugin handles it".to_string(),
+        ]);
+
+        registry.add(&Rename);
+        assert_eq!(registry.run(Node::Program(vec![])).unwrap_err(), vec![
+            "Attribute '@rename' is registered by both 'rename' and 'rename'".to_string(),
+        ]);
+
+        let error = registry.load(Path::new("/nonexistent/libplugin.so")).unwrap_err();
+        assert!(error.starts_with("Failed to load plugin /nonexistent/libplugin.so"));
+    }
+
+    #[test]
+    fn test_lint_levels() {
+        let mut registry = Registry::new();
+        // Flags every method
+        registry.add_lint("method", |program| {
+            program.children().into_iter()
+                .filter_map(|node| match node {
+                    Node::Class { name, members, .. } => Some((name, members)),
+                    _ => None,
+                })
+                .flat_map(|(class, members)| members.iter().filter_map(move |member| match member {
+                    Node::Function { name, .. } => Some(Finding { declaration: format!("{}.{}", class, name), message: name.clone() }),
+                    _ => None,
+                }))
+                .collect()
+        });
+        let lints = |level: &str, lints: &[&str], declaration: Node| {
+            attribute(level, lints.iter().map(|lint| Node::Identifier((*lint).into())).collect(), declaration)
+        };
+        let class = |name: &str, members: Vec<Node>| Node::Class {
+            name: name.to_string(),
+            extends: None,
+            implements: vec![],
+            is_abstract: false,
+            members,
+            docs: None,
+        };
+        let program = Node::Program(vec![
+            lints("allow", &["method"], Node::Program(vec![])),
+            class("Quiet", vec![function("a")]),
+            lints("warn", &["method"], class("Noisy", vec![function("b"), lints("allow", &["method"], function("c"))])),
+        ]);
+
+        let (expanded, warnings) = registry.run(program.clone()).unwrap();
+        assert_eq!(warnings, vec!["method: b".to_string()]);
+        assert_eq!(expanded, Node::Program(vec![
+            class("Quiet", vec![function("a")]),
+            class("Noisy", vec![function("b"), function("c")]),
+        ]));
+
+        // The build's level is overridden by the attributes
+        registry.set_level("method", LintLevel::Deny);
+        assert_eq!(registry.run(program.clone()).unwrap().1, vec!["method: b".to_string()]);
+        let Node::Program(mut nodes) = program else { unreachable!() };
+        nodes.remove(0);
+        assert_eq!(registry.run(Node::Program(nodes)).unwrap_err(), vec!["method: a".to_string()]);
+
+        let unknown = Node::Program(vec![lints("allow", &["unused"], function("a"))]);
+        assert_eq!(registry.run(unknown).unwrap_err(), vec!["@allow: Unknown lint 'unused'".to_string()]);
+        registry.set_level("unused", LintLevel::Allow);
+        assert_eq!(registry.run(Node::Program(vec![])).unwrap_err(), vec!["Unknown lint 'unused'".to_string()]);
+    }
+}
@@ -0,0 +1,131 @@
+//! Pre-build steps: commands a project runs before it's compiled, usually
+//! to generate Gard source from other files, declared in `gard.toml`:
+//!
+//! ```toml
+//! [[build]]
+//! command = ["abigen", "--gard", "token.abi", "gen/token.gard"]
+//! inputs = ["token.abi"]
+//! outputs = ["gen/token.gard"]
+//! ```
+//!
+//! Paths are relative to the directory of `gard.toml`, which the commands
+//! run in. A step runs again only when an output is missing or an input
+//! or its command changed since it last ran, which `STATE` keeps track of.
+//! The steps run in order, so one can take another's outputs as inputs,
+//! and the generated files are compiled like any other source.
+
+use crate::compdb::Input;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// What each step read the last time it ran, under the project directory
+pub const STATE: &str = ".gard/build-steps.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildStep {
+    /// The program and its arguments
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+/// A step as it last ran
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Run {
+    command: Vec<String>,
+    inputs: Vec<Input>,
+}
+
+/// Runs the steps whose outputs are out of date, in `directory`, and
+/// returns the commands it ran.
+pub fn run(steps: &[BuildStep], directory: &Path) -> Result<Vec<String>, String> {
+    let state_path = directory.join(STATE);
+    let mut state: Vec<Run> = match fs::read_to_string(&state_path) {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Invalid {}: {}", state_path.display(), e))?,
+        Err(_) => Vec::new(),
+    };
+
+    let mut ran = Vec::new();
+    for step in steps {
+        let Some((program, arguments)) = step.command.split_first() else {
+            return Err("A build step's command is empty".to_string());
+        };
+        let inputs = step.inputs.iter()
+            .map(|input| Input::read(&directory.join(input).display().to_string()).map(|read| Input { path: input.clone(), hash: read.hash }))
+            .collect::<Result<Vec<_>, _>>()?;
+        let current = Run { command: step.command.clone(), inputs };
+        let outputs_exist = step.outputs.iter().all(|output| directory.join(output).exists());
+        if outputs_exist && state.contains(&current) {
+            continue;
+        }
+
+        for output in &step.outputs {
+            if let Some(parent) = directory.join(output).parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+        }
+        let command = step.command.join(" ");
+        let status = Command::new(program).args(arguments).current_dir(directory).status()
+            .map_err(|e| format!("Build step '{}' failed to start: {}", command, e))?;
+        if !status.success() {
+            return Err(format!("Build step '{}' failed with {}", command, status));
+        }
+        if let Some(missing) = step.outputs.iter().find(|output| !directory.join(output).exists()) {
+            return Err(format!("Build step '{}' didn't write {}", command, missing));
+        }
+
+        state.retain(|run| run.command != current.command);
+        state.push(current);
+        ran.push(command);
+    }
+
+    if !ran.is_empty() {
+        if let Some(parent) = state_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(&state).map_err(|e| format!("Failed to serialize build steps: {}", e))?;
+        fs::write(&state_path, json).map_err(|e| format!("Failed to write {}: {}", state_path.display(), e))?;
+    }
+    Ok(ran)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(command: &[&str], inputs: &[&str], outputs: &[&str]) -> BuildStep {
+        let strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+        BuildStep { command: strings(command), inputs: strings(inputs), outputs: strings(outputs) }
+    }
+
+    #[test]
+    fn test_steps_rerun_when_inputs_change() {
+        let directory = std::env::temp_dir().join(format!("gard-prebuild-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("token.abi"), "transfer").unwrap();
+        let steps = [
+            step(&["cp", "token.abi", "gen/token.gard"], &["token.abi"], &["gen/token.gard"]),
+            step(&["cp", "gen/token.gard", "gen/copy.gard"], &["gen/token.gard"], &["gen/copy.gard"]),
+        ];
+
+        assert_eq!(run(&steps, &directory).unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(directory.join("gen/copy.gard")).unwrap(), "transfer");
+        assert!(run(&steps, &directory).unwrap().is_empty());
+
+        fs::write(directory.join("token.abi"), "mint").unwrap();
+        assert_eq!(run(&steps, &directory).unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(directory.join("gen/copy.gard")).unwrap(), "mint");
+
+        fs::remove_file(directory.join("gen/copy.gard")).unwrap();
+        assert_eq!(run(&steps, &directory).unwrap(), ["cp gen/token.gard gen/copy.gard"]);
+
+        let broken = [step(&["true"], &[], &["gen/never.gard"])];
+        assert_eq!(run(&broken, &directory), Err("Build step 'true' didn't write gen/never.gard".to_string()));
+        assert!(run(&[step(&["false"], &[], &["x"])], &directory).unwrap_err().starts_with("Build step 'false' failed with"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
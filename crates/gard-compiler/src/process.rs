@@ -0,0 +1,130 @@
+use gard_ast::{Node, Type};
+
+/// Builtins of `std.process`, called as `process.start(command)`. A
+/// `Command` is built up with `arg`, `env` and friends and then run as a
+/// `Child`; both are handles to gard-vm's copies, stored as an int.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessBuiltin {
+    Command,
+    Arg,
+    Env,
+    ClearEnv,
+    Directory,
+    Start,
+    Pipe,
+    Write,
+    Finish,
+    Stdout,
+    Stderr,
+    Kill,
+}
+
+pub const MODULE: &str = "process";
+pub const COMMAND: &str = "Command";
+pub const CHILD: &str = "Child";
+
+fn custom(name: &str) -> Type {
+    Type::Custom(name.into())
+}
+
+impl ProcessBuiltin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "command" => Some(ProcessBuiltin::Command),
+            "arg" => Some(ProcessBuiltin::Arg),
+            "env" => Some(ProcessBuiltin::Env),
+            "clearEnv" => Some(ProcessBuiltin::ClearEnv),
+            "directory" => Some(ProcessBuiltin::Directory),
+            "start" => Some(ProcessBuiltin::Start),
+            "pipe" => Some(ProcessBuiltin::Pipe),
+            "write" => Some(ProcessBuiltin::Write),
+            "finish" => Some(ProcessBuiltin::Finish),
+            "stdout" => Some(ProcessBuiltin::Stdout),
+            "stderr" => Some(ProcessBuiltin::Stderr),
+            "kill" => Some(ProcessBuiltin::Kill),
+            _ => None,
+        }
+    }
+
+    /// Resolves `process.finish(..)` style callees.
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee.unlocated() {
+            Node::Member { object, property } => match object.unlocated() {
+                Node::Identifier(module) if module == MODULE => Self::from_name(property),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcessBuiltin::Command => "command",
+            ProcessBuiltin::Arg => "arg",
+            ProcessBuiltin::Env => "env",
+            ProcessBuiltin::ClearEnv => "clearEnv",
+            ProcessBuiltin::Directory => "directory",
+            ProcessBuiltin::Start => "start",
+            ProcessBuiltin::Pipe => "pipe",
+            ProcessBuiltin::Write => "write",
+            ProcessBuiltin::Finish => "finish",
+            ProcessBuiltin::Stdout => "stdout",
+            ProcessBuiltin::Stderr => "stderr",
+            ProcessBuiltin::Kill => "kill",
+        }
+    }
+
+    /// `pipe(child, command)` starts the command reading the child's
+    /// output. `finish` closes the child's input, waits for it and returns
+    /// its exit code, -1 if a signal ended it; `stdout` and `stderr` are
+    /// what it wrote, once finished. (`spawn` and `wait` are keywords.)
+    pub fn signature(&self) -> Type {
+        let (params, return_type) = match self {
+            ProcessBuiltin::Command => (vec![Type::String], custom(COMMAND)),
+            ProcessBuiltin::Arg | ProcessBuiltin::Directory => (vec![custom(COMMAND), Type::String], Type::Void),
+            ProcessBuiltin::Env => (vec![custom(COMMAND), Type::String, Type::String], Type::Void),
+            ProcessBuiltin::ClearEnv => (vec![custom(COMMAND)], Type::Void),
+            ProcessBuiltin::Start => (vec![custom(COMMAND)], custom(CHILD)),
+            ProcessBuiltin::Pipe => (vec![custom(CHILD), custom(COMMAND)], custom(CHILD)),
+            ProcessBuiltin::Write => (vec![custom(CHILD), Type::Bytes], Type::Void),
+            ProcessBuiltin::Finish => (vec![custom(CHILD)], Type::Int),
+            ProcessBuiltin::Stdout | ProcessBuiltin::Stderr => (vec![custom(CHILD)], Type::Bytes),
+            ProcessBuiltin::Kill => (vec![custom(CHILD)], Type::Void),
+        };
+        Type::Function { params, return_type: Box::new(return_type) }
+    }
+
+    /// Symbol of the native implementation in gard-vm.
+    pub fn runtime_symbol(&self) -> &'static str {
+        match self {
+            ProcessBuiltin::Command => "gard_process_command",
+            ProcessBuiltin::Arg => "gard_process_arg",
+            ProcessBuiltin::Env => "gard_process_env",
+            ProcessBuiltin::ClearEnv => "gard_process_clear_env",
+            ProcessBuiltin::Directory => "gard_process_directory",
+            ProcessBuiltin::Start => "gard_process_start",
+            ProcessBuiltin::Pipe => "gard_process_pipe",
+            ProcessBuiltin::Write => "gard_process_write",
+            ProcessBuiltin::Finish => "gard_process_finish",
+            ProcessBuiltin::Stdout => "gard_process_stdout",
+            ProcessBuiltin::Stderr => "gard_process_stderr",
+            ProcessBuiltin::Kill => "gard_process_kill",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_member_callee() {
+        let callee = |module: &str, name: &str| Node::Member {
+            object: Box::new(Node::Identifier(module.into())),
+            property: name.to_string(),
+        };
+        assert_eq!(ProcessBuiltin::from_callee(&callee("process", "clearEnv")), Some(ProcessBuiltin::ClearEnv));
+        assert_eq!(ProcessBuiltin::from_callee(&callee("process", "exec")), None);
+        assert_eq!(ProcessBuiltin::from_callee(&callee("io", "start")), None);
+    }
+}
@@ -0,0 +1,165 @@
+//! Timings of the compiler's stages, for `--time-passes`: how long each
+//! took, and how much memory the process held after it, so a slow or
+//! bloated build shows which stage to blame. The JSON form is for
+//! comparing builds over time.
+//!
+//! Memory is the resident set size, as Linux reports it in
+//! `/proc/self/status`; other platforms get timings alone.
+
+use serde::Serialize;
+use std::fs;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The stages timed so far, in the order they finished. Shared between
+/// the driver and the compiler, which add to it as they go.
+#[derive(Debug, Default)]
+pub struct Profile {
+    stages: Mutex<Vec<Stage>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stage {
+    pub name: String,
+    #[serde(serialize_with = "microseconds", rename = "microseconds")]
+    pub duration: Duration,
+    pub memory: Option<Memory>,
+}
+
+/// In bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Memory {
+    pub resident: u64,
+    /// The most resident so far
+    pub peak: u64,
+}
+
+fn microseconds<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(duration.as_micros())
+}
+
+/// Runs `f` as the stage `name` of `profile`, if there is one.
+pub fn time<T>(profile: Option<&Profile>, name: &str, f: impl FnOnce() -> T) -> T {
+    match profile {
+        Some(profile) => profile.time(name, f),
+        None => f(),
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn time<T>(&self, name: &str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(Stage { name: name.to_string(), duration: start.elapsed(), memory: memory() });
+        result
+    }
+
+    pub fn record(&self, stage: Stage) {
+        self.stages.lock().unwrap_or_else(PoisonError::into_inner).push(stage);
+    }
+
+    pub fn stages(&self) -> Vec<Stage> {
+        self.stages.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// A table of the stages, with each one's share of the total time and
+    /// the memory it added.
+    pub fn report(&self) -> String {
+        let stages = self.stages();
+        let total: Duration = stages.iter().map(|stage| stage.duration).sum();
+        let width = stages.iter().map(|stage| stage.name.len()).chain([5]).max().unwrap_or(5);
+        let mut report = format!("{:<width$}  {:>10}  {:>6}  {:>10}  {:>10}\n", "stage", "time", "share", "resident", "change", width = width);
+        let mut previous = None;
+        for stage in &stages {
+            let share = match total.as_nanos() {
+                0 => 0.0,
+                total => stage.duration.as_nanos() as f64 * 100.0 / total as f64,
+            };
+            let (resident, change) = match (stage.memory, previous) {
+                (Some(memory), Some(Memory { resident, .. })) => (megabytes(memory.resident), format!("{:+.1} MB", (memory.resident as f64 - resident as f64) / MEGABYTE)),
+                (Some(memory), None) => (megabytes(memory.resident), String::new()),
+                (None, _) => (String::new(), String::new()),
+            };
+            report.push_str(&format!("{:<width$}  {:>10}  {:>5.1}%  {:>10}  {:>10}\n",
+                stage.name, milliseconds(stage.duration), share, resident, change, width = width));
+            previous = stage.memory.or(previous);
+        }
+        report.push_str(&format!("{:<width$}  {:>10}", "total", milliseconds(total), width = width));
+        if let Some(memory) = previous {
+            report.push_str(&format!("  peak {}", megabytes(memory.peak)));
+        }
+        report.push('\n');
+        report
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::json!({ "stages": self.stages() }).to_string()
+    }
+}
+
+const MEGABYTE: f64 = 1024.0 * 1024.0;
+
+fn milliseconds(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / MEGABYTE)
+}
+
+/// The process's memory now, where the platform reports it.
+pub fn memory() -> Option<Memory> {
+    parse_status(&fs::read_to_string("/proc/self/status").ok()?)
+}
+
+fn parse_status(status: &str) -> Option<Memory> {
+    let field = |name: &str| -> Option<u64> {
+        let line = status.lines().find(|line| line.starts_with(name))?;
+        let kilobytes = line[name.len()..].trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+        Some(kilobytes * 1024)
+    };
+    Some(Memory { resident: field("VmRSS:")?, peak: field("VmHWM:")? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(name: &str, milliseconds: u64, resident: u64) -> Stage {
+        let memory = Memory { resident: resident * 1024 * 1024, peak: 12 * 1024 * 1024 };
+        Stage { name: name.to_string(), duration: Duration::from_millis(milliseconds), memory: Some(memory) }
+    }
+
+    #[test]
+    fn test_report() {
+        let profile = Profile::new();
+        profile.record(stage("lex", 1, 8));
+        profile.record(stage("parse", 3, 10));
+        assert_eq!(profile.report(), concat!(
+            "stage        time   share    resident      change\n",
+            "lex        1.00ms   25.0%      8.0 MB            \n",
+            "parse      3.00ms   75.0%     10.0 MB     +2.0 MB\n",
+            "total      4.00ms  peak 12.0 MB\n",
+        ));
+        assert_eq!(profile.to_json(), concat!(
+            r#"{"stages":[{"memory":{"peak":12582912,"resident":8388608},"microseconds":1000,"name":"lex"},"#,
+            r#"{"memory":{"peak":12582912,"resident":10485760},"microseconds":3000,"name":"parse"}]}"#,
+        ));
+    }
+
+    #[test]
+    fn test_time() {
+        let profile = Profile::new();
+        assert_eq!(time(Some(&profile), "codegen", || 42), 42);
+        assert_eq!(time(None, "codegen", || 7), 7);
+        assert_eq!(profile.stages().iter().map(|stage| stage.name.as_str()).collect::<Vec<_>>(), ["codegen"]);
+
+        let status = "Name:\tgard\nVmHWM:\t   20480 kB\nVmRSS:\t   10240 kB\n";
+        assert_eq!(parse_status(status), Some(Memory { resident: 10 * 1024 * 1024, peak: 20 * 1024 * 1024 }));
+        assert_eq!(parse_status("Name:\tgard\n"), None);
+    }
+}
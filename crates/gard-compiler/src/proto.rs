@@ -0,0 +1,618 @@
+//! `gard bindgen proto`: Gard declarations for the messages, enums and
+//! services of a proto3 file, so Gard programs can exchange messages with
+//! services defined in protobuf.
+//!
+//! - A message becomes a class deriving `Serialize`, its fields named in
+//!   lowerCamelCase as in protobuf's JSON mapping, so `serialize()` gives
+//!   the message's JSON form. A nested message or enum is named after its
+//!   parents: `Order.Item` becomes `OrderItem`.
+//! - An enum's values become int constants, and fields of the enum are
+//!   ints, which protobuf's JSON parsers take in place of the value names.
+//! - A service's methods become client functions over `std.http`, posting
+//!   the request's JSON to a server that speaks the Connect protocol, as
+//!   `POST {baseUrl}/{package}.{Service}/{Method}`, and returning the
+//!   response.
+//!
+//! The comments before a message or method become its doc comment. Only
+//! the JSON encoding is generated: not the binary wire format, nor
+//! decoding responses, nor streaming methods. Types must be declared in
+//! the file itself; imports are skipped.
+
+use crate::http;
+use gard_ast::{BinaryOp, Node, Parameter, Type};
+use std::collections::HashSet;
+
+/// The derive that serializes the generated classes
+const DERIVE: &str = "Serialize";
+
+/// Gard declarations for the proto3 file `source`.
+pub fn generate(source: &str) -> Result<Node, String> {
+    let mut words = Words::new(source)?;
+    let mut file = File::default();
+    while let Some(word) = words.next_word() {
+        match word.text.as_str() {
+            "syntax" => {
+                words.expect("=")?;
+                let syntax = words.next()?;
+                if syntax.text != "\"proto3\"" {
+                    return Err(format!("line {}: only proto3 files are supported, found syntax {}", syntax.line, syntax.text));
+                }
+                words.expect(";")?;
+            },
+            "package" => {
+                file.package = Some(words.next()?.text);
+                words.expect(";")?;
+            },
+            "import" | "option" => words.skip_statement()?,
+            "message" => file.message(&mut words, "", word.docs)?,
+            "enum" => file.enumeration(&mut words, "")?,
+            "service" => file.service(&mut words)?,
+            ";" => {},
+            other => return Err(format!("line {}: expected a message, enum or service, found '{}'", word.line, other)),
+        }
+    }
+    file.declarations()
+}
+
+#[derive(Default)]
+struct File {
+    package: Option<String>,
+    messages: Vec<Message>,
+    enums: Vec<Enum>,
+    services: Vec<Service>,
+}
+
+/// A message or enum's name is its path from the top of the file, like
+/// `Order.Item`.
+struct Message {
+    name: String,
+    docs: Option<String>,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    name: String,
+    label: Label,
+    /// As written, resolved from the message it's in
+    type_name: String,
+    line: usize,
+}
+
+enum Label {
+    Single,
+    Repeated,
+    /// `map<key, value>`, the value in `type_name`
+    Map(String),
+}
+
+struct Enum {
+    name: String,
+    values: Vec<(String, i64)>,
+}
+
+struct Service {
+    name: String,
+    methods: Vec<Method>,
+}
+
+struct Method {
+    name: String,
+    docs: Option<String>,
+    input: String,
+    output: String,
+    line: usize,
+}
+
+impl File {
+    fn message(&mut self, words: &mut Words, parent: &str, docs: Option<String>) -> Result<(), String> {
+        let name = qualified(parent, &words.next()?.text);
+        words.expect("{")?;
+        let mut fields = Vec::new();
+        loop {
+            let word = words.next()?;
+            match word.text.as_str() {
+                "}" => break,
+                ";" => {},
+                "message" => self.message(words, &name, word.docs)?,
+                "enum" => self.enumeration(words, &name)?,
+                "option" | "reserved" | "extensions" => words.skip_statement()?,
+                // A oneof's fields are fields of the message
+                "oneof" => {
+                    words.next()?;
+                    words.expect("{")?;
+                    while words.peek() != Some("}") {
+                        if words.peek() == Some("option") {
+                            words.skip_statement()?;
+                            continue;
+                        }
+                        let type_name = words.next()?.text;
+                        fields.push(words.field(Label::Single, type_name, word.line)?);
+                    }
+                    words.expect("}")?;
+                },
+                "map" => {
+                    words.expect("<")?;
+                    let key = words.next()?.text;
+                    words.expect(",")?;
+                    let value = words.next()?.text;
+                    words.expect(">")?;
+                    fields.push(words.field(Label::Map(key), value, word.line)?);
+                },
+                "repeated" => {
+                    let type_name = words.next()?.text;
+                    fields.push(words.field(Label::Repeated, type_name, word.line)?);
+                },
+                "optional" => {
+                    let type_name = words.next()?.text;
+                    fields.push(words.field(Label::Single, type_name, word.line)?);
+                },
+                "required" | "group" | "extend" => return Err(format!("line {}: '{}' is proto2, which isn't supported", word.line, word.text)),
+                _ => fields.push(words.field(Label::Single, word.text, word.line)?),
+            }
+        }
+        self.messages.push(Message { name, docs, fields });
+        Ok(())
+    }
+
+    fn enumeration(&mut self, words: &mut Words, parent: &str) -> Result<(), String> {
+        let name = qualified(parent, &words.next()?.text);
+        words.expect("{")?;
+        let mut values = Vec::new();
+        loop {
+            let word = words.next()?;
+            match word.text.as_str() {
+                "}" => break,
+                ";" => {},
+                "option" | "reserved" => words.skip_statement()?,
+                _ => {
+                    words.expect("=")?;
+                    let negative = words.peek() == Some("-");
+                    if negative {
+                        words.next()?;
+                    }
+                    let number = words.number()?;
+                    words.skip_options()?;
+                    words.expect(";")?;
+                    values.push((word.text, if negative { -number } else { number }));
+                },
+            }
+        }
+        self.enums.push(Enum { name, values });
+        Ok(())
+    }
+
+    fn service(&mut self, words: &mut Words) -> Result<(), String> {
+        let name = words.next()?.text;
+        words.expect("{")?;
+        let mut methods = Vec::new();
+        loop {
+            let word = words.next()?;
+            match word.text.as_str() {
+                "}" => break,
+                ";" => {},
+                "option" => words.skip_statement()?,
+                "rpc" => {
+                    let method = words.next()?.text;
+                    let input = words.rpc_type(&name, &method)?;
+                    words.expect("returns")?;
+                    let output = words.rpc_type(&name, &method)?;
+                    if words.peek() == Some("{") {
+                        words.skip_block()?;
+                    } else {
+                        words.expect(";")?;
+                    }
+                    methods.push(Method { name: method, docs: word.docs, input, output, line: word.line });
+                },
+                other => return Err(format!("line {}: expected an rpc in service '{}', found '{}'", word.line, name, other)),
+            }
+        }
+        self.services.push(Service { name, methods });
+        Ok(())
+    }
+
+    /// The enums' constants, then the messages' classes, then the
+    /// services' clients.
+    fn declarations(&self) -> Result<Node, String> {
+        let messages: HashSet<&str> = self.messages.iter().map(|message| message.name.as_str()).collect();
+        let enums: HashSet<&str> = self.enums.iter().map(|enumeration| enumeration.name.as_str()).collect();
+        let resolve = |name: &str, scope: &str, line: usize| -> Result<Type, String> {
+            if let Some(scalar) = scalar(name) {
+                return Ok(scalar);
+            }
+            let name = name.trim_start_matches('.');
+            let name = match &self.package {
+                Some(package) => name.strip_prefix(&format!("{}.", package)).unwrap_or(name),
+                None => name,
+            };
+            // The innermost enclosing message's first
+            let scopes: Vec<&str> = scope.split('.').filter(|part| !part.is_empty()).collect();
+            for depth in (0..=scopes.len()).rev() {
+                let candidate = qualified(&scopes[..depth].join("."), name);
+                if messages.contains(candidate.as_str()) {
+                    return Ok(Type::Custom(class_name(&candidate).into()));
+                }
+                if enums.contains(candidate.as_str()) {
+                    return Ok(Type::Int);
+                }
+            }
+            Err(format!("line {}: unknown type '{}'; only types declared in the file can be used", line, name))
+        };
+
+        let mut declarations = Vec::new();
+        for enumeration in &self.enums {
+            declarations.extend(enumeration.values.iter().map(|(name, value)| Node::Const {
+                name: name.clone(),
+                type_annotation: Some(Type::Int),
+                value: Box::new(Node::IntLiteral(*value)),
+            }));
+        }
+        for message in &self.messages {
+            let members = message.fields.iter()
+                .map(|field| {
+                    let ty = resolve(&field.type_name, &message.name, field.line)?;
+                    let ty = match &field.label {
+                        Label::Single => ty,
+                        Label::Repeated => Type::Array(Box::new(ty)),
+                        Label::Map(key) => Type::Map { key: Box::new(resolve(key, &message.name, field.line)?), value: Box::new(ty) },
+                    };
+                    Ok(Node::Let {
+                        name: lower_camel_case(&field.name),
+                        initializer: zero(&ty).map(Box::new),
+                        type_annotation: Some(ty),
+                        is_mutable: false,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            let class = Node::Class {
+                name: class_name(&message.name),
+                extends: None,
+                implements: vec![],
+                is_abstract: false,
+                members,
+                docs: message.docs.clone(),
+            };
+            declarations.push(Node::Derive { derives: vec![DERIVE.to_string()], declaration: Box::new(class) });
+        }
+        for service in &self.services {
+            let path = qualified(self.package.as_deref().unwrap_or(""), &service.name);
+            for method in &service.methods {
+                let input = resolve(&method.input, "", method.line)?;
+                resolve(&method.output, "", method.line)?;
+                declarations.push(client(&path, &service.name, method, input));
+            }
+        }
+        Ok(Node::Program(declarations))
+    }
+}
+
+/// `function greeterSayHello(baseUrl: string, request: HelloRequest): Response`,
+/// which posts the request to `/package.Greeter/SayHello`.
+fn client(path: &str, service: &str, method: &Method, input: Type) -> Node {
+    let url = Node::Binary {
+        left: Box::new(Node::Identifier("baseUrl".into())),
+        operator: BinaryOp::Add,
+        right: Box::new(Node::StringLiteral(format!("/{}/{}", path, method.name))),
+    };
+    let serialized = call(member("request", "serialize"), vec![]);
+    let body = call(member("bytes", "fromString"), vec![serialized]);
+    let post = call(member(http::MODULE, "post"), vec![url, body, Node::StringLiteral("application/json".to_string())]);
+    let docs = method.docs.clone().unwrap_or_else(|| format!("Calls `{}.{}`, which responds with the JSON of `{}`.", service, method.name, method.output));
+    Node::Function {
+        name: format!("{}{}", lower_camel_case(service), method.name),
+        params: vec![
+            Parameter { name: "baseUrl".into(), type_annotation: Type::String },
+            Parameter { name: "request".into(), type_annotation: input },
+        ],
+        return_type: Type::Custom(http::RESPONSE.into()),
+        body: Box::new(Node::Block(vec![Node::Return(Some(Box::new(post)))])),
+        modifiers: vec![],
+        docs: Some(docs),
+    }
+}
+
+fn member(object: &str, property: &str) -> Node {
+    Node::Member { object: Box::new(Node::Identifier(object.into())), property: property.to_string() }
+}
+
+fn call(callee: Node, arguments: Vec<Node>) -> Node {
+    Node::Call { callee: Box::new(callee), arguments }
+}
+
+fn scalar(name: &str) -> Option<Type> {
+    match name {
+        "double" => Some(Type::Double),
+        "float" => Some(Type::Float),
+        "int32" | "int64" | "sint32" | "sint64" | "sfixed32" | "sfixed64" | "uint32" | "fixed32" => Some(Type::Int),
+        "uint64" | "fixed64" => Some(Type::UInt),
+        "bool" => Some(Type::Boolean),
+        "string" => Some(Type::String),
+        "bytes" => Some(Type::Bytes),
+        _ => None,
+    }
+}
+
+/// A field's default, protobuf's zero value, for the types that have a
+/// literal.
+fn zero(ty: &Type) -> Option<Node> {
+    match ty {
+        Type::Int => Some(Node::IntLiteral(0)),
+        Type::UInt => Some(Node::UIntLiteral(0)),
+        Type::Float | Type::Double => Some(Node::FloatLiteral(0.0)),
+        Type::Boolean => Some(Node::BooleanLiteral(false)),
+        Type::String => Some(Node::StringLiteral(String::new())),
+        _ => None,
+    }
+}
+
+fn qualified(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", parent, name)
+    }
+}
+
+/// `Order.Item` as `OrderItem`
+fn class_name(name: &str) -> String {
+    name.split('.').collect()
+}
+
+/// `user_id` as `userId`, and `Greeter` as `greeter`
+fn lower_camel_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = !result.is_empty();
+        } else if upper {
+            result.extend(c.to_uppercase());
+            upper = false;
+        } else if result.is_empty() {
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// A word or punctuation of a proto file, with the comments right before
+/// it.
+struct Word {
+    text: String,
+    line: usize,
+    docs: Option<String>,
+}
+
+struct Words {
+    words: Vec<Word>,
+    position: usize,
+}
+
+impl Words {
+    fn new(source: &str) -> Result<Self, String> {
+        let mut words = Vec::new();
+        let mut comments: Vec<String> = Vec::new();
+        let mut line = 1;
+        let mut chars = source.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            match c {
+                '\n' => line += 1,
+                c if c.is_whitespace() => {},
+                '/' if source[start..].starts_with("//") => {
+                    let end = source[start..].find('\n').map_or(source.len(), |end| start + end);
+                    comments.push(source[start + 2..end].trim().to_string());
+                    while chars.peek().is_some_and(|(i, _)| *i < end) {
+                        chars.next();
+                    }
+                },
+                '/' if source[start..].starts_with("/*") => {
+                    let end = source[start + 2..].find("*/").map(|end| start + 2 + end + 2)
+                        .ok_or_else(|| format!("line {}: unterminated comment", line))?;
+                    line += source[start..end].matches('\n').count();
+                    while chars.peek().is_some_and(|(i, _)| *i < end) {
+                        chars.next();
+                    }
+                },
+                '"' | '\'' => {
+                    let end = source[start + 1..].find(c).map(|end| start + 1 + end + 1)
+                        .ok_or_else(|| format!("line {}: unterminated string", line))?;
+                    while chars.peek().is_some_and(|(i, _)| *i < end) {
+                        chars.next();
+                    }
+                    words.push(Word { text: source[start..end].to_string(), line, docs: docs(&mut comments) });
+                },
+                c if c.is_alphanumeric() || c == '_' || c == '.' => {
+                    let mut end = start + c.len_utf8();
+                    while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '.') {
+                        end = i + c.len_utf8();
+                    }
+                    words.push(Word { text: source[start..end].to_string(), line, docs: docs(&mut comments) });
+                },
+                c => words.push(Word { text: c.to_string(), line, docs: docs(&mut comments) }),
+            }
+        }
+        Ok(Self { words, position: 0 })
+    }
+
+    fn next_word(&mut self) -> Option<Word> {
+        let word = self.words.get_mut(self.position)?;
+        self.position += 1;
+        Some(Word { text: std::mem::take(&mut word.text), line: word.line, docs: word.docs.take() })
+    }
+
+    fn next(&mut self) -> Result<Word, String> {
+        self.next_word().ok_or_else(|| "unexpected end of file".to_string())
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.words.get(self.position).map(|word| word.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let word = self.next()?;
+        if word.text == expected {
+            Ok(())
+        } else {
+            Err(format!("line {}: expected '{}', found '{}'", word.line, expected, word.text))
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, String> {
+        let word = self.next()?;
+        let parsed = match word.text.strip_prefix("0x").or_else(|| word.text.strip_prefix("0X")) {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => word.text.parse(),
+        };
+        parsed.map_err(|_| format!("line {}: expected a number, found '{}'", word.line, word.text))
+    }
+
+    /// The rest of a field after its type: `name = 1 [options];`
+    fn field(&mut self, label: Label, type_name: String, line: usize) -> Result<Field, String> {
+        let name = self.next()?.text;
+        self.expect("=")?;
+        self.number()?;
+        self.skip_options()?;
+        self.expect(";")?;
+        Ok(Field { name, label, type_name, line })
+    }
+
+    /// `(Request)`, for an rpc's input or output.
+    fn rpc_type(&mut self, service: &str, method: &str) -> Result<String, String> {
+        self.expect("(")?;
+        let word = self.next()?;
+        if word.text == "stream" {
+            return Err(format!("line {}: {}.{} streams, and only unary methods are supported", word.line, service, method));
+        }
+        self.expect(")")?;
+        Ok(word.text)
+    }
+
+    /// Up to and including the next `;` outside braces.
+    fn skip_statement(&mut self) -> Result<(), String> {
+        loop {
+            match self.next()?.text.as_str() {
+                ";" => return Ok(()),
+                "{" => {
+                    self.position -= 1;
+                    self.skip_block()?;
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn skip_block(&mut self) -> Result<(), String> {
+        self.expect("{")?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()?.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    /// A field's `[deprecated = true]`, if it has one.
+    fn skip_options(&mut self) -> Result<(), String> {
+        if self.peek() == Some("[") {
+            while self.next()?.text != "]" {}
+        }
+        Ok(())
+    }
+}
+
+/// The comments since the last word, as its docs.
+fn docs(comments: &mut Vec<String>) -> Option<String> {
+    (!comments.is_empty()).then(|| std::mem::take(comments).join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREETER: &str = r#"
+        syntax = "proto3";
+        package shop.v1;
+        import "google/protobuf/empty.proto";
+
+        // An order and what's in it
+        message Order {
+            /* Gard has no nesting, so it's `OrderItem` */
+            message Item {
+                string sku = 1;
+                uint32 quantity = 2 [deprecated = true];
+            }
+            enum Status { STATUS_UNSPECIFIED = 0; STATUS_PAID = 1; }
+            int64 order_id = 1;
+            repeated Item items = 2;
+            map<string, double> prices = 3;
+            Status status = 4;
+            oneof payment { string card = 5; bytes token = 6; }
+            reserved 7, 8;
+        }
+
+        service Orders {
+            option deprecated = false;
+            // Places an order
+            rpc Place(Order) returns (.shop.v1.Order.Item) {}
+            rpc Cancel(Order.Item) returns (Order);
+        }
+    "#;
+
+    #[test]
+    fn test_generate() {
+        let program = generate(GREETER).unwrap();
+        assert_eq!(gard_ast::to_source(&program), concat!(
+            "const STATUS_UNSPECIFIED: int = 0\n",
+            "\n",
+            "const STATUS_PAID: int = 1\n",
+            "\n",
+            "@derive(Serialize)\n",
+            "class OrderItem {\n",
+            "    let sku: string = \"\"\n",
+            "    let quantity: int = 0\n",
+            "}\n",
+            "\n",
+            "/// An order and what's in it\n",
+            "@derive(Serialize)\n",
+            "class Order {\n",
+            "    let orderId: int = 0\n",
+            "    let items: array<OrderItem>\n",
+            "    let prices: map<string, double>\n",
+            "    let status: int = 0\n",
+            "    let card: string = \"\"\n",
+            "    let token: bytes\n",
+            "}\n",
+            "\n",
+            "/// Places an order\n",
+            "function ordersPlace(baseUrl: string, request: Order): Response {\n",
+            "    return http.post(baseUrl + \"/shop.v1.Orders/Place\", bytes.fromString(request.serialize()), \"application/json\");\n",
+            "}\n",
+            "\n",
+            "/// Calls `Orders.Cancel`, which responds with the JSON of `Order`.\n",
+            "function ordersCancel(baseUrl: string, request: OrderItem): Response {\n",
+            "    return http.post(baseUrl + \"/shop.v1.Orders/Cancel\", bytes.fromString(request.serialize()), \"application/json\");\n",
+            "}\n",
+        ));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let error = |source: &str| generate(source).unwrap_err();
+        assert_eq!(error("syntax = \"proto2\";"), "line 1: only proto3 files are supported, found syntax \"proto2\"");
+        assert_eq!(error("message A {\n  google.protobuf.Timestamp at = 1;\n}"), "line 2: unknown type 'google.protobuf.Timestamp'; only types declared in the file can be used");
+        assert_eq!(error("message A {}\nservice S {\n  rpc Watch(A) returns (stream A);\n}"), "line 3: S.Watch streams, and only unary methods are supported");
+        assert_eq!(error("message A { string name = 1 }"), "line 1: expected ';', found '}'");
+        assert_eq!(error("message A {"), "unexpected end of file");
+    }
+
+    #[test]
+    fn test_lower_camel_case() {
+        assert_eq!(lower_camel_case("order_id"), "orderId");
+        assert_eq!(lower_camel_case("Orders"), "orders");
+        assert_eq!(lower_camel_case("_private_field"), "privateField");
+    }
+}
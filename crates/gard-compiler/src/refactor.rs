@@ -0,0 +1,582 @@
+//! Extract-function and inline-variable refactorings. Like `rename` they
+//! return text edits for the caller to show or apply, and refuse a change
+//! they can't show keeps the program's meaning.
+//!
+//! Statements are located by the spans `Node::Located` keeps. Moved code is
+//! copied from the source as written, so comments and formatting survive.
+
+use crate::index::{self, Index};
+use crate::rename::{self, TextEdit};
+use gard_ast::{type_to_source, Node, Parameter, Span, Type, UnaryOp};
+
+/// The edits that move the statements `selection` covers into a new
+/// function `name`, next to the one they are in, and call it in their
+/// place. Locals the statements read become parameters; a local they
+/// declare and later code reads becomes the return value.
+pub fn extract_function(program: &Node, source: &str, selection: Span, name: &str) -> Result<Vec<TextEdit>, String> {
+    let (class, function, found) = functions(program).into_iter()
+        .find_map(|(class, function)| {
+            let body = match function {
+                Node::Function { body, .. } | Node::Constructor { body, .. } => body,
+                _ => return None,
+            };
+            select(body, selection).map(|found| (class, function, found))
+        })
+        .ok_or_else(|| "The selection doesn't cover whole statements in a function".to_string())?;
+
+    let qualified = match class {
+        Some(class) => format!("{}.{}", class, name),
+        None => name.to_string(),
+    };
+    if Index::build(program).definition(&qualified).is_some() {
+        return Err(format!("'{}' already exists", qualified));
+    }
+
+    let mut checks = Checks::default();
+    for statement in found.statements {
+        checks.visit(statement, false);
+    }
+    if let Some(problem) = checks.problem {
+        return Err(problem);
+    }
+
+    // The types of the locals in scope before the selection, later ones shadowing earlier
+    let params = match function {
+        Node::Function { params, .. } | Node::Constructor { params, .. } => params.as_slice(),
+        _ => &[],
+    };
+    let mut locals: Vec<(&str, Option<Type>)> = params.iter()
+        .map(|param| (param.name.as_str(), Some(param.type_annotation.clone())))
+        .collect();
+    locals.extend(found.untyped.iter().map(|name| (*name, None)));
+    locals.extend(found.before.iter().filter_map(|node| declared(node)));
+    let local = |name: &str| locals.iter().rev().find(|(local, _)| *local == name).map(|(_, ty)| ty.clone());
+
+    let mut parameters = Vec::new();
+    for used in free_names(found.statements) {
+        let Some(ty) = local(&used) else { continue };
+        if checks.modified.contains(&used) {
+            return Err(format!("The statements modify '{}', which is declared outside them", used));
+        }
+        let ty = ty.ok_or_else(|| format!("Can't infer the type of '{}'; annotate it", used))?;
+        parameters.push(Parameter { name: used.into(), type_annotation: ty });
+    }
+
+    let read_after = free_names_of(&found.after);
+    let returned: Vec<(&str, Option<Type>)> = found.statements.iter()
+        .filter_map(declared)
+        .filter(|(name, _)| read_after.iter().any(|used| used == name))
+        .collect();
+    let returned = match returned.as_slice() {
+        [] => None,
+        [(name, ty)] => {
+            let ty = ty.clone().ok_or_else(|| format!("Can't infer the type of '{}'; annotate it", name))?;
+            Some((*name, ty))
+        },
+        [(first, _), (second, _), ..] => {
+            return Err(format!("Later statements read both '{}' and '{}'; only one value can be returned", first, second));
+        },
+    };
+
+    let span = Span {
+        start: span_of(&found.statements[0]).start,
+        end: span_of(&found.statements[found.statements.len() - 1]).end,
+    };
+    let arguments: Vec<&str> = parameters.iter().map(|param| param.name.as_str()).collect();
+    let callee = if class.is_some() { format!("this.{}", name) } else { name.to_string() };
+    let call = format!("{}({})", callee, arguments.join(", "));
+    let call = match &returned {
+        Some((returned, _)) => format!("let {} = {}", returned, call),
+        None => format!("{};", call),
+    };
+
+    let end = closing_brace(source, span.end, found.depth)
+        .ok_or_else(|| "Can't find the end of the enclosing function".to_string())?;
+    let indent = indentation(source, end);
+    let body_indent = format!("{}    ", indent);
+    let mut body: Vec<String> = reindent(source, span, &body_indent);
+    if let Some((returned, _)) = &returned {
+        body.push(format!("{}return {};", body_indent, returned));
+    }
+    let params: Vec<String> = parameters.iter()
+        .map(|param| format!("{}: {}", param.name, type_to_source(&param.type_annotation)))
+        .collect();
+    let return_type = returned.map(|(_, ty)| format!(": {}", type_to_source(&ty))).unwrap_or_default();
+    let function = format!(
+        "\n\n{indent}function {}({}){} {{\n{}\n{indent}}}",
+        name, params.join(", "), return_type, body.join("\n"),
+        indent = indent,
+    );
+
+    Ok(vec![
+        TextEdit { span, new_text: call },
+        TextEdit { span: Span { start: end + 1, end: end + 1 }, new_text: function },
+    ])
+}
+
+/// The edits that replace the one use of the local declared by the `let`
+/// at offset `at` with its initializer, and remove the `let`. `names` are
+/// the identifier tokens, as for `rename::rename`.
+pub fn inline_variable(program: &Node, names: &[(String, Span)], source: &str, at: usize) -> Result<Vec<TextEdit>, String> {
+    let (class, function, (statement, name, initializer)) = functions(program).into_iter()
+        .find_map(|(class, function)| find_let(function, at).map(|found| (class, function, found)))
+        .ok_or_else(|| "No let with an initializer here".to_string())?;
+    let symbol = match function {
+        Node::Function { name, .. } => name.as_str(),
+        _ => "constructor",
+    };
+    let symbol = class.map_or_else(|| symbol.to_string(), |class| format!("{}.{}", class, symbol));
+
+    if declarations(function, name) > 1 {
+        return Err(format!("'{}' is declared more than once in {}; rename one first", name, symbol));
+    }
+    let mut checks = Checks::default();
+    checks.visit(function, false);
+    if has_side_effects(initializer) {
+        return Err(format!("The initializer of '{}' has side effects", name));
+    }
+    if let Some(modified) = free_names_of(&[initializer]).into_iter().find(|used| checks.modified.contains(used)) {
+        return Err(format!("'{}' changes after '{}' is declared", modified, name));
+    }
+
+    let symbol = format!("{}.{}", symbol, name);
+    let occurrences = index::occurrences(program, None);
+    let spans = rename::match_spans(&occurrences, names);
+    let found: Vec<Option<Span>> = occurrences.iter().zip(spans)
+        .filter(|(occurrence, _)| occurrence.symbol.as_deref() == Some(symbol.as_str()))
+        .map(|(_, span)| span)
+        .collect();
+    let (declaration, uses) = found.split_first().ok_or_else(|| format!("Can't find '{}'", name))?;
+    let usage = match uses {
+        [usage] => usage.ok_or_else(|| format!("Can't find the use of '{}'", name))?,
+        [] => return Err(format!("'{}' is never used", name)),
+        uses => return Err(format!("'{}' is used {} times; only a variable used once can be inlined", name, uses.len())),
+    };
+    let declaration = declaration.ok_or_else(|| format!("Can't find the declaration of '{}'", name))?;
+
+    let text = &source[declaration.end..statement.end];
+    let text = text[text.find('=').ok_or_else(|| format!("'{}' has no initializer", name))? + 1..]
+        .trim()
+        .trim_end_matches(';')
+        .trim_end();
+    let text = match initializer {
+        Node::Binary { .. } | Node::Unary { .. } | Node::Await(_) => format!("({})", text),
+        _ => text.to_string(),
+    };
+
+    Ok(vec![
+        TextEdit { span: whole_lines(source, statement), new_text: String::new() },
+        TextEdit { span: usage, new_text: text },
+    ])
+}
+
+/// Every function and constructor, with the class it is in.
+fn functions(program: &Node) -> Vec<(Option<&str>, &Node)> {
+    fn collect<'a>(node: &'a Node, class: Option<&'a str>, found: &mut Vec<(Option<&'a str>, &'a Node)>) {
+        match node {
+            Node::Program(nodes) => nodes.iter().for_each(|node| collect(node, class, found)),
+            Node::Class { name, members, .. } | Node::Contract { name, members, .. } | Node::Actor { name, members, .. } => {
+                members.iter().for_each(|member| collect(member, Some(name), found));
+            },
+            Node::Function { .. } | Node::Constructor { .. } => found.push((class, node)),
+            Node::Located { node: declaration, .. }
+            | Node::WasmExport { declaration, .. }
+            | Node::Derive { declaration, .. }
+            | Node::Cfg { declaration, .. }
+            | Node::Attribute { declaration, .. } => collect(declaration, class, found),
+            _ => {},
+        }
+    }
+    let mut found = Vec::new();
+    collect(program, None, &mut found);
+    found
+}
+
+/// Selected statements and their surroundings.
+struct Found<'a> {
+    /// Statements before the selection in its block and every enclosing
+    /// one, outermost first
+    before: Vec<&'a Node>,
+    statements: &'a [Node],
+    after: Vec<&'a Node>,
+    /// Loop variables and catch parameters in scope
+    untyped: Vec<&'a str>,
+    /// Closing braces between the selection and the end of the function
+    depth: usize,
+}
+
+fn select(node: &Node, selection: Span) -> Option<Found<'_>> {
+    let inside = |node: &Node| matches!(node, Node::Located { span, .. } if span.start >= selection.start && span.end <= selection.end);
+    let statements = match node.unlocated() {
+        Node::Block(statements) => statements,
+        // Braces of its own, around its cases
+        node @ Node::Match { .. } => {
+            return node.children().into_iter().find_map(|child| select(child, selection))
+                .map(|found| Found { depth: found.depth + 1, ..found });
+        },
+        node => {
+            let mut found = node.children().into_iter().find_map(|child| select(child, selection))?;
+            match node {
+                Node::Foreach { item, .. } => found.untyped.push(item),
+                Node::CatchClause { param_name, .. } => found.untyped.push(param_name),
+                Node::For { initializer: Some(initializer), .. } => found.untyped.extend(declared(initializer).map(|(name, _)| name)),
+                _ => {},
+            }
+            return Some(found);
+        },
+    };
+
+    if let Some(first) = statements.iter().position(inside) {
+        let last = statements.iter().rposition(inside)?;
+        let overlaps = |node: &Node| matches!(node, Node::Located { span, .. } if span.start < selection.end && span.end > selection.start);
+        let partial = statements.iter().any(|statement| overlaps(statement) && !inside(statement));
+        if partial || !statements[first..=last].iter().all(inside) {
+            return None;
+        }
+        return Some(Found {
+            before: statements[..first].iter().collect(),
+            statements: &statements[first..=last],
+            after: statements[last + 1..].iter().collect(),
+            untyped: vec![],
+            depth: 1,
+        });
+    }
+
+    for (i, statement) in statements.iter().enumerate() {
+        let Node::Located { span, node } = statement else { continue };
+        if span.start > selection.start || span.end < selection.end {
+            continue;
+        }
+        let mut found = select(node, selection)?;
+        let mut before: Vec<&Node> = statements[..i].iter().collect();
+        before.append(&mut found.before);
+        found.before = before;
+        found.after.extend(&statements[i + 1..]);
+        found.depth += 1;
+        return Some(found);
+    }
+    None
+}
+
+fn span_of(node: &Node) -> Span {
+    match node {
+        Node::Located { span, .. } => *span,
+        _ => Span { start: 0, end: 0 },
+    }
+}
+
+/// The local a statement declares, with its type if it's written or
+/// obvious from a literal.
+fn declared(node: &Node) -> Option<(&str, Option<Type>)> {
+    match node.unlocated() {
+        Node::Let { name, type_annotation, initializer, .. } => {
+            let ty = type_annotation.clone().or_else(|| match initializer.as_deref()?.unlocated() {
+                Node::IntLiteral(_) => Some(Type::Int),
+                Node::UIntLiteral(_) => Some(Type::UInt),
+                Node::UInt256Literal(_) => Some(Type::UInt256),
+                Node::FloatLiteral(_) => Some(Type::Float),
+                Node::StringLiteral(_) | Node::TemplateString { .. } => Some(Type::String),
+                Node::BooleanLiteral(_) => Some(Type::Boolean),
+                _ => None,
+            });
+            Some((name, ty))
+        },
+        _ => None,
+    }
+}
+
+fn free_names(nodes: &[Node]) -> Vec<String> {
+    free_names_of(&nodes.iter().collect::<Vec<_>>())
+}
+
+/// Names the nodes read without declaring them first, in order of first use.
+fn free_names_of(nodes: &[&Node]) -> Vec<String> {
+    fn walk(node: &Node, declared: &mut Vec<String>, used: &mut Vec<String>) {
+        match node {
+            Node::Identifier(name) => {
+                if !declared.iter().chain(used.iter()).any(|known| known == name) {
+                    used.push(name.to_string());
+                }
+            },
+            Node::Let { name, initializer, .. } => {
+                if let Some(initializer) = initializer {
+                    walk(initializer, declared, used);
+                }
+                declared.push(name.clone());
+            },
+            Node::Block(_) | Node::For { .. } | Node::Foreach { .. } | Node::CatchClause { .. } => {
+                let scope = declared.len();
+                match node {
+                    Node::Foreach { item, .. } => declared.push(item.clone()),
+                    Node::CatchClause { param_name, .. } => declared.push(param_name.clone()),
+                    _ => {},
+                }
+                for child in node.children() {
+                    walk(child, declared, used);
+                }
+                declared.truncate(scope);
+            },
+            node => {
+                for child in node.children() {
+                    walk(child, declared, used);
+                }
+            },
+        }
+    }
+    let (mut declared, mut used) = (Vec::new(), Vec::new());
+    for node in nodes {
+        walk(node, &mut declared, &mut used);
+    }
+    used
+}
+
+/// What stops statements from being moved into a function of their own.
+#[derive(Default)]
+struct Checks {
+    problem: Option<String>,
+    /// Names incremented or decremented
+    modified: Vec<String>,
+}
+
+impl Checks {
+    fn visit(&mut self, node: &Node, in_loop: bool) {
+        match node {
+            Node::Return(_) if self.problem.is_none() => {
+                self.problem = Some("Can't extract statements that return".to_string());
+            },
+            Node::Break | Node::Continue if !in_loop && self.problem.is_none() => {
+                self.problem = Some("Can't extract a break or continue without its loop".to_string());
+            },
+            Node::Unary { operator: UnaryOp::Increment | UnaryOp::Decrement, operand } => {
+                if let Node::Identifier(name) = operand.unlocated() {
+                    self.modified.push(name.to_string());
+                }
+            },
+            _ => {},
+        }
+        let in_loop = in_loop || matches!(node, Node::While { .. } | Node::DoWhile { .. } | Node::For { .. } | Node::Foreach { .. });
+        for child in node.children() {
+            self.visit(child, in_loop);
+        }
+    }
+}
+
+/// The innermost `let` with an initializer whose statement contains `at`.
+fn find_let(node: &Node, at: usize) -> Option<(Span, &str, &Node)> {
+    if let Node::Located { span, node: inner } = node {
+        if at < span.start || at >= span.end {
+            return None;
+        }
+        if let Node::Let { name, initializer: Some(initializer), .. } = inner.as_ref() {
+            return Some((*span, name, initializer));
+        }
+    }
+    node.children().into_iter().find_map(|child| find_let(child, at))
+}
+
+/// Params and lets named `name` in a function.
+fn declarations(function: &Node, name: &str) -> usize {
+    fn count(node: &Node, name: &str) -> usize {
+        let own = matches!(node, Node::Let { name: declared, .. } if declared == name) as usize;
+        own + node.children().into_iter().map(|child| count(child, name)).sum::<usize>()
+    }
+    let params = match function {
+        Node::Function { params, .. } | Node::Constructor { params, .. } => params.iter().filter(|param| param.name == name).count(),
+        _ => 0,
+    };
+    params + count(function, name)
+}
+
+fn has_side_effects(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::Call { .. }
+        | Node::MacroCall { .. }
+        | Node::Await(_)
+        | Node::Unary { operator: UnaryOp::Increment | UnaryOp::Decrement, .. }
+    ) || node.children().into_iter().any(has_side_effects)
+}
+
+/// The offset of the brace that closes `depth` blocks open at `from`.
+/// Strings, characters, template strings and comments are skipped.
+fn closing_brace(source: &str, from: usize, depth: usize) -> Option<usize> {
+    let bytes = source.as_bytes();
+    let mut open = depth;
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'"' | b'\'' | b'`') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            },
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = source[i..].find('\n').map_or(bytes.len(), |end| i + end);
+            },
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = source[i + 2..].find("*/").map_or(bytes.len(), |end| i + 2 + end + 1);
+            },
+            b'{' => open += 1,
+            b'}' => {
+                open -= 1;
+                if open == 0 {
+                    return Some(i);
+                }
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    None
+}
+
+fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map_or(0, |newline| newline + 1)
+}
+
+/// The whitespace the line containing `offset` starts with.
+fn indentation(source: &str, offset: usize) -> &str {
+    let start = line_start(source, offset);
+    let line = &source[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// The lines of `span`, with the indentation of its first line replaced by
+/// `indent`.
+fn reindent(source: &str, span: Span, indent: &str) -> Vec<String> {
+    let original = indentation(source, span.start);
+    source[span.start..span.end].lines().enumerate()
+        .map(|(i, line)| {
+            let line = if i == 0 { line } else { line.strip_prefix(original).unwrap_or(line) };
+            if line.trim().is_empty() { String::new() } else { format!("{}{}", indent, line) }
+        })
+        .collect()
+}
+
+/// `span`, grown to whole lines when nothing else shares them.
+fn whole_lines(source: &str, span: Span) -> Span {
+    let start = line_start(source, span.start);
+    let rest = &source[span.end..];
+    let line_end = rest.find('\n').map_or(source.len(), |newline| span.end + newline + 1);
+    let alone = source[start..span.start].trim().is_empty() && source[span.end..line_end].trim().is_empty();
+    if alone {
+        Span { start, end: line_end }
+    } else {
+        span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::BinaryOp;
+
+    fn located(source: &str, text: &str, node: Node) -> Node {
+        let start = source.find(text).unwrap();
+        Node::Located { span: Span { start, end: start + text.len() }, node: Box::new(node) }
+    }
+
+    fn identifier(name: &str) -> Box<Node> {
+        Box::new(Node::Identifier(name.into()))
+    }
+
+    fn add(left: Box<Node>, right: Box<Node>) -> Box<Node> {
+        Box::new(Node::Binary { left, operator: BinaryOp::Add, right })
+    }
+
+    fn let_(name: &str, initializer: Box<Node>) -> Node {
+        Node::Let { name: name.to_string(), type_annotation: None, initializer: Some(initializer), is_mutable: false }
+    }
+
+    fn function(name: &str, params: Vec<Parameter>, body: Vec<Node>) -> Node {
+        Node::Function { name: name.to_string(), params, return_type: Type::Void, body: Box::new(Node::Block(body)), modifiers: vec![], docs: None }
+    }
+
+    const SOURCE: &str = "\
+function main(base: int) {
+    let offset = 2
+    let total = base + offset
+    print(total);
+}
+";
+
+    fn program() -> Node {
+        Node::Program(vec![function(
+            "main",
+            vec![Parameter { name: "base".into(), type_annotation: Type::Int }],
+            vec![
+                located(SOURCE, "let offset = 2", let_("offset", Box::new(Node::IntLiteral(2)))),
+                located(SOURCE, "let total = base + offset", Node::Let {
+                    name: "total".to_string(),
+                    type_annotation: Some(Type::Int),
+                    initializer: Some(add(identifier("base"), identifier("offset"))),
+                    is_mutable: false,
+                }),
+                located(SOURCE, "print(total);", Node::Block(vec![Node::Call { callee: identifier("print"), arguments: vec![Node::Identifier("total".into())] }])),
+            ],
+        )])
+    }
+
+    /// The identifier tokens of `SOURCE`.
+    fn names() -> Vec<(String, Span)> {
+        let mut names = Vec::new();
+        let mut offset = 0;
+        for name in ["main", "base", "offset", "total", "base", "offset", "print", "total"] {
+            let start = offset + SOURCE[offset..].find(name).unwrap();
+            offset = start + name.len();
+            names.push((name.to_string(), Span { start, end: offset }));
+        }
+        names
+    }
+
+    fn selection(text: &str) -> Span {
+        let start = SOURCE.find(text).unwrap();
+        Span { start, end: start + text.len() }
+    }
+
+    #[test]
+    fn test_extract_function() {
+        let edits = extract_function(&program(), SOURCE, selection("let total = base + offset"), "sum").unwrap();
+        assert_eq!(rename::apply(SOURCE, &edits), "\
+function main(base: int) {
+    let offset = 2
+    let total = sum(base, offset)
+    print(total);
+}
+
+function sum(base: int, offset: int): int {
+    let total = base + offset
+    return total;
+}
+");
+
+        let whole = selection("let offset = 2\n    let total = base + offset\n    print(total);");
+        let edits = extract_function(&program(), SOURCE, whole, "report").unwrap();
+        assert!(rename::apply(SOURCE, &edits).contains("    report(base);\n}\n\nfunction report(base: int) {\n    let offset = 2\n"));
+
+        assert_eq!(
+            extract_function(&program(), SOURCE, selection("base + offset"), "sum").unwrap_err(),
+            "The selection doesn't cover whole statements in a function",
+        );
+        assert_eq!(extract_function(&program(), SOURCE, whole, "main").unwrap_err(), "'main' already exists");
+    }
+
+    #[test]
+    fn test_inline_variable() {
+        let at = SOURCE.find("let offset").unwrap();
+        let edits = inline_variable(&program(), &names(), SOURCE, at).unwrap();
+        assert_eq!(rename::apply(SOURCE, &edits), "\
+function main(base: int) {
+    let total = base + 2
+    print(total);
+}
+");
+
+        let at = SOURCE.find("let total").unwrap();
+        let edits = inline_variable(&program(), &names(), SOURCE, at).unwrap();
+        assert!(rename::apply(SOURCE, &edits).contains("    print((base + offset));\n"));
+
+        assert_eq!(inline_variable(&program(), &names(), SOURCE, 0).unwrap_err(), "No let with an initializer here");
+    }
+}
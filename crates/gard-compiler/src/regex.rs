@@ -0,0 +1,100 @@
+use gard_ast::{Node, Type};
+
+/// Builtins of `std.regex`, called as `regex.isMatch(pattern, text)`. A
+/// pattern is a string, from `regex.compile`, a `re"..."` literal or a
+/// plain string; gard-vm compiles and caches it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegexBuiltin {
+    Compile,
+    IsMatch,
+    Find,
+    Captures,
+    Replace,
+}
+
+pub const MODULE: &str = "regex";
+
+impl RegexBuiltin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "compile" => Some(RegexBuiltin::Compile),
+            "isMatch" => Some(RegexBuiltin::IsMatch),
+            "find" => Some(RegexBuiltin::Find),
+            "captures" => Some(RegexBuiltin::Captures),
+            "replace" => Some(RegexBuiltin::Replace),
+            _ => None,
+        }
+    }
+
+    /// Resolves `regex.find(..)` style callees.
+    pub fn from_callee(callee: &Node) -> Option<Self> {
+        match callee.unlocated() {
+            Node::Member { object, property } => match object.unlocated() {
+                Node::Identifier(module) if module == MODULE => Self::from_name(property),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RegexBuiltin::Compile => "compile",
+            RegexBuiltin::IsMatch => "isMatch",
+            RegexBuiltin::Find => "find",
+            RegexBuiltin::Captures => "captures",
+            RegexBuiltin::Replace => "replace",
+        }
+    }
+
+    /// Every argument is a string: the pattern, then the text, then the
+    /// replacement.
+    pub fn arity(&self) -> usize {
+        match self {
+            RegexBuiltin::Compile => 1,
+            RegexBuiltin::IsMatch | RegexBuiltin::Find | RegexBuiltin::Captures => 2,
+            RegexBuiltin::Replace => 3,
+        }
+    }
+
+    /// `find` and `captures` return null when nothing matches.
+    pub fn return_type(&self) -> Type {
+        match self {
+            RegexBuiltin::Compile | RegexBuiltin::Find | RegexBuiltin::Replace => Type::String,
+            RegexBuiltin::IsMatch => Type::Boolean,
+            RegexBuiltin::Captures => Type::Array(Box::new(Type::String)),
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        Type::Function { params: vec![Type::String; self.arity()], return_type: Box::new(self.return_type()) }
+    }
+
+    /// Symbol of the native implementation in gard-vm.
+    pub fn runtime_symbol(&self) -> &'static str {
+        match self {
+            RegexBuiltin::Compile => "gard_regex_compile",
+            RegexBuiltin::IsMatch => "gard_regex_is_match",
+            RegexBuiltin::Find => "gard_regex_find",
+            RegexBuiltin::Captures => "gard_regex_captures",
+            RegexBuiltin::Replace => "gard_regex_replace",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_member_callee() {
+        let callee = |module: &str, name: &str| Node::Member {
+            object: Box::new(Node::Identifier(module.into())),
+            property: name.to_string(),
+        };
+        assert_eq!(RegexBuiltin::from_callee(&callee("regex", "isMatch")), Some(RegexBuiltin::IsMatch));
+        assert_eq!(RegexBuiltin::from_callee(&callee("regex", "split")), None);
+        assert_eq!(RegexBuiltin::from_callee(&callee("crypto", "find")), None);
+        assert_eq!(RegexBuiltin::from_callee(&Node::Identifier("find".into())), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! Rename refactoring for classes, functions, fields and locals, on top of
+//! the symbol index's resolver.
+//!
+//! Symbols are named as in the index, with locals as `function.local`. The
+//! AST doesn't keep the span of each name, so the caller passes the
+//! program's identifier tokens and the n-th name the resolver visits is
+//! matched with the n-th token of the same text.
+//!
+//! A rename is checked by resolving the program again with the renamed
+//! names read as the new one: it is refused if any of them resolves to
+//! something else (the new name is shadowed or already taken), or if any
+//! other name now resolves to the renamed symbol (the new name captures it).
+
+use crate::index::{self, Occurrence, Renaming};
+use gard_ast::{Node, SourceMap, Span};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub new_text: String,
+}
+
+/// The edits that rename `symbol` to `new_name`. `names` are the text and
+/// span of every identifier token in `source`, in order; the program must
+/// be the one parsed from them, before any expansion.
+pub fn rename(
+    program: &Node,
+    names: &[(String, Span)],
+    source: &SourceMap,
+    symbol: &str,
+    new_name: &str,
+) -> Result<Vec<TextEdit>, Vec<String>> {
+    let before = index::occurrences(program, None);
+    let at: Vec<usize> = before.iter().enumerate()
+        .filter(|(_, occurrence)| occurrence.symbol.as_deref() == Some(symbol))
+        .map(|(i, _)| i)
+        .collect();
+    if at.is_empty() {
+        return Err(vec![format!("No symbol '{}'", symbol)]);
+    }
+    let new_symbol = match symbol.rsplit_once('.') {
+        Some((parent, _)) => format!("{}.{}", parent, new_name),
+        None => new_name.to_string(),
+    };
+    if new_symbol == symbol {
+        return Ok(vec![]);
+    }
+
+    let spans = match_spans(&before, names);
+    let location = |i: usize| match spans[i] {
+        Some(span) => {
+            let location = source.location(span.start);
+            format!("{}:{}:{}", source.file, location.line, location.column)
+        },
+        None => source.file.clone(),
+    };
+    if let Some(i) = before.iter().position(|occurrence| occurrence.symbol.as_deref() == Some(new_symbol.as_str())) {
+        return Err(vec![format!("{}: '{}' already exists", location(i), new_symbol)]);
+    }
+
+    let renaming = Renaming {
+        symbol: symbol.to_string(),
+        new_name: new_name.to_string(),
+        at: at.iter().copied().collect(),
+    };
+    let after = index::occurrences(program, Some(renaming));
+    // Symbols under the renamed one, like its locals, are renamed with it
+    let restore = |after: &str| match after.strip_prefix(new_symbol.as_str()) {
+        Some(rest) if rest.is_empty() || rest.starts_with('.') => format!("{}{}", symbol, rest),
+        _ => after.to_string(),
+    };
+    let mut errors = Vec::new();
+    for (i, (before, after)) in before.iter().zip(&after).enumerate() {
+        if after.symbol.as_deref().map(restore) == before.symbol {
+            continue;
+        }
+        let found = after.symbol.as_deref().unwrap_or("nothing");
+        errors.push(if at.contains(&i) {
+            format!("{}: '{}' would refer to {} here instead of {}", location(i), new_name, found, symbol)
+        } else {
+            let expected = before.symbol.as_deref().unwrap_or("nothing");
+            format!("{}: '{}' would refer to {} instead of {}", location(i), before.name, found, expected)
+        });
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    at.iter()
+        .map(|&i| match spans[i] {
+            Some(span) => Ok(TextEdit { span, new_text: new_name.to_string() }),
+            None => Err(format!("Can't find every use of '{}' in {}", before[i].name, source.file)),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| vec![e])
+}
+
+/// Applies edits that don't overlap.
+pub fn apply(source: &str, edits: &[TextEdit]) -> String {
+    let mut edits: Vec<&TextEdit> = edits.iter().collect();
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.span.start));
+    let mut source = source.to_string();
+    for edit in edits {
+        source.replace_range(edit.span.start..edit.span.end, &edit.new_text);
+    }
+    source
+}
+
+/// The span of each occurrence, when the occurrences and tokens of its
+/// text line up.
+pub(crate) fn match_spans(occurrences: &[Occurrence], names: &[(String, Span)]) -> Vec<Option<Span>> {
+    let mut tokens: HashMap<&str, Vec<Span>> = HashMap::new();
+    for (name, span) in names {
+        tokens.entry(name.as_str()).or_default().push(*span);
+    }
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for occurrence in occurrences {
+        *counts.entry(occurrence.name.as_str()).or_default() += 1;
+    }
+
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    occurrences.iter()
+        .map(|occurrence| {
+            let name = occurrence.name.as_str();
+            let n = seen.entry(name).or_default();
+            *n += 1;
+            let spans = tokens.get(name)?;
+            (spans.len() == counts[name]).then(|| spans[*n - 1])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::{Parameter, Type};
+
+    const SOURCE: &str = "function total(count: int) { let sum = count } function main { let x = 1 total(x); }";
+
+    /// `SOURCE` parsed, with the spans of its names.
+    fn program() -> (Node, Vec<(String, Span)>, SourceMap) {
+        let source = SOURCE;
+        let names = ["total", "count", "sum", "count", "main", "x", "total", "x"];
+        let mut offset = 0;
+        let names = names.iter()
+            .map(|name| {
+                let start = offset + source[offset..].find(name).unwrap();
+                offset = start + name.len();
+                (name.to_string(), Span { start, end: offset })
+            })
+            .collect();
+        let function = |name: &str, params: Vec<Parameter>, body: Vec<Node>| Node::Function {
+            name: name.to_string(),
+            params,
+            return_type: Type::Void,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        };
+        let program = Node::Program(vec![
+            function("total", vec![Parameter { name: "count".into(), type_annotation: Type::Int }], vec![Node::Let {
+                name: "sum".to_string(),
+                type_annotation: None,
+                initializer: Some(Box::new(Node::Identifier("count".into()))),
+                is_mutable: false,
+            }]),
+            function("main", vec![], vec![
+                Node::Let { name: "x".to_string(), type_annotation: None, initializer: Some(Box::new(Node::IntLiteral(1))), is_mutable: false },
+                Node::Block(vec![Node::Call {
+                    callee: Box::new(Node::Identifier("total".into())),
+                    arguments: vec![Node::Identifier("x".into())],
+                }]),
+            ]),
+        ]);
+        (program, names, SourceMap::new("a.gard", source))
+    }
+
+    #[test]
+    fn test_rename() {
+        let (program, names, source) = program();
+        let edits = rename(&program, &names, &source, "total", "sum").unwrap();
+        assert_eq!(edits.len(), 2);
+        assert_eq!(apply(SOURCE, &edits), "function sum(count: int) { let sum = count } function main { let x = 1 sum(x); }");
+
+        let edits = rename(&program, &names, &source, "total.count", "amount").unwrap();
+        assert_eq!(apply(SOURCE, &edits), "function total(amount: int) { let sum = amount } function main { let x = 1 total(x); }");
+        let edits = rename(&program, &names, &source, "main.x", "y").unwrap();
+        assert_eq!(apply(SOURCE, &edits), "function total(count: int) { let sum = count } function main { let y = 1 total(y); }");
+    }
+
+    #[test]
+    fn test_rename_conflicts() {
+        let (program, names, source) = program();
+        assert_eq!(rename(&program, &names, &source, "total.count", "sum").unwrap_err(), vec![
+            "a.gard:1:34: 'total.sum' already exists".to_string(),
+        ]);
+        assert_eq!(rename(&program, &names, &source, "main", "total").unwrap_err(), vec![
+            "a.gard:1:10: 'total' already exists".to_string(),
+        ]);
+        // The local would shadow the function at the call
+        assert_eq!(rename(&program, &names, &source, "main.x", "total").unwrap_err(), vec![
+            "a.gard:1:74: 'total' would refer to main.total instead of total".to_string(),
+        ]);
+        assert_eq!(rename(&program, &names, &source, "missing", "x").unwrap_err(), vec!["No symbol 'missing'".to_string()]);
+    }
+}
@@ -0,0 +1,698 @@
+use crate::arithmetic::ArithmeticBuiltin;
+use crate::bytes::BytesBuiltin;
+use crate::chain::ChainIntrinsic;
+use crate::crypto::CryptoBuiltin;
+use crate::datetime::{self, DateTimeBuiltin};
+use crate::http::HttpBuiltin;
+use crate::io::IoBuiltin;
+use crate::net::NetBuiltin;
+use crate::process::ProcessBuiltin;
+use crate::storage::StorageLayout;
+use gard_ast::{AssertionKind, BinaryOp, FunctionModifier, Node, Parameter, Type, UnaryOp};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+pub const PRAGMA: &str = "pragma solidity ^0.8.20;";
+
+/// Transpiles every top-level contract of a program to Solidity source.
+pub fn transpile(program: &Node) -> Result<String, String> {
+    let nodes = match program {
+        Node::Program(nodes) => nodes,
+        _ => return Err("Expected program node".to_string()),
+    };
+
+    let mut emitter = SolidityEmitter::default();
+    emitter.line("// SPDX-License-Identifier: UNLICENSED");
+    emitter.line(PRAGMA);
+
+    let mut found = false;
+    for node in nodes {
+        if let Node::Contract { .. } = node {
+            emitter.line("");
+            emitter.emit_contract(node)?;
+            found = true;
+        }
+    }
+
+    if !found {
+        return Err("Program has no contracts to transpile".to_string());
+    }
+    Ok(emitter.out)
+}
+
+#[derive(Default)]
+struct SolidityEmitter {
+    out: String,
+    indent: usize,
+    events: HashSet<String>,
+    /// Types of the contract's state variables, then of the parameters and
+    /// locals of the function being emitted
+    fields: HashMap<String, Type>,
+    locals: HashMap<String, Type>,
+    /// `unchecked` functions the `wrapping*` calls use, by name, with their
+    /// operand type and operator
+    wrapping: RefCell<BTreeMap<String, (String, &'static str)>>,
+}
+
+impl SolidityEmitter {
+    fn emit_contract(&mut self, contract: &Node) -> Result<(), String> {
+        let (name, members) = match contract {
+            Node::Contract { name, members, .. } => (name, members),
+            _ => return Err("Expected contract node".to_string()),
+        };
+
+        // Solidity allocates slots in declaration order, so a pinned slot
+        // only survives if it is the slot the variable would get anyway.
+        let layout = StorageLayout::from_contract(contract)?;
+        for (index, entry) in layout.entries.iter().enumerate() {
+            if entry.explicit && entry.slot != index as u64 {
+                return Err(format!("Cannot pin '{}' to slot {} in Solidity output; it would occupy slot {}",
+                    entry.name, entry.slot, index));
+            }
+        }
+
+        self.events = members.iter()
+            .filter_map(|member| match member {
+                Node::Event { name, .. } => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        self.fields = members.iter()
+            .filter_map(|member| match member {
+                Node::Let { name, type_annotation: Some(ty), .. } => Some((name.clone(), ty.clone())),
+                Node::StorageSlot { declaration, .. } => match declaration.as_ref() {
+                    Node::Let { name, type_annotation: Some(ty), .. } => Some((name.clone(), ty.clone())),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+
+        self.line(&format!("contract {} {{", name));
+        self.indent += 1;
+        for member in members {
+            self.emit_member(member)?;
+        }
+        for (name, (ty, operator)) in self.wrapping.take() {
+            self.line(&format!("function {}({} a, {} b) private pure returns ({}) {{", name, ty, ty, ty));
+            self.indent += 1;
+            self.line("unchecked {");
+            self.line(&format!("    return a {} b;", operator));
+            self.line("}");
+            self.indent -= 1;
+            self.line("}");
+        }
+        self.indent -= 1;
+        self.line("}");
+        Ok(())
+    }
+
+    fn emit_member(&mut self, member: &Node) -> Result<(), String> {
+        match member {
+            Node::Let { name, type_annotation, initializer, .. } => {
+                let ty = type_annotation.as_ref()
+                    .ok_or_else(|| format!("Storage variable '{}' needs a type annotation", name))?;
+                let mut line = format!("{} public {}", Self::type_name(ty)?, name);
+                if let Some(initializer) = initializer {
+                    line.push_str(&format!(" = {}", self.expression(initializer)?));
+                }
+                self.line(&format!("{};", line));
+            },
+            Node::StorageSlot { declaration, .. } | Node::WasmExport { declaration, .. } => self.emit_member(declaration)?,
+            // Folded into their uses by `consteval`
+            Node::Const { .. } => {},
+            // Only the type checker looks at unions
+            Node::Union { .. } => {},
+            Node::Event { name, fields, .. } => {
+                let fields = fields.iter()
+                    .map(|field| Ok(format!("{} {}", Self::type_name(&field.type_annotation)?, field.name)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                self.line(&format!("event {}({});", name, fields.join(", ")));
+            },
+            Node::Constructor { params, body } => {
+                self.locals = Self::parameter_types(params);
+                self.line(&format!("constructor({}) {{", Self::parameters(params)?));
+                self.emit_body(body)?;
+                self.line("}");
+            },
+            Node::Function { name, params, return_type, body, modifiers, .. } => {
+                self.locals = Self::parameter_types(params);
+                let mut header = format!("function {}({}) {}", name, Self::parameters(params)?,
+                    Self::function_modifiers(name, modifiers)?);
+                if *return_type != Type::Void {
+                    header.push_str(&format!(" returns ({})", Self::located_type(return_type)?));
+                }
+                self.line(&format!("{} {{", header));
+                self.emit_body(body)?;
+                self.line("}");
+            },
+            other => return Err(format!("Unsupported contract member in Solidity output: {:?}", other)),
+        }
+        Ok(())
+    }
+
+    fn emit_body(&mut self, body: &Node) -> Result<(), String> {
+        self.indent += 1;
+        match body {
+            Node::Block(statements) => {
+                for statement in statements {
+                    self.emit_statement(statement)?;
+                }
+            },
+            statement => self.emit_statement(statement)?,
+        }
+        self.indent -= 1;
+        Ok(())
+    }
+
+    fn emit_statement(&mut self, statement: &Node) -> Result<(), String> {
+        match statement {
+            Node::Located { node, .. } => self.emit_statement(node)?,
+            Node::Const { .. } => {},
+            Node::Block(_) => {
+                self.line("{");
+                self.emit_body(statement)?;
+                self.line("}");
+            },
+            Node::Let { name, type_annotation, initializer, .. } => {
+                let ty = type_annotation.as_ref()
+                    .ok_or_else(|| format!("Local '{}' needs a type annotation for Solidity output", name))?;
+                if matches!(ty, Type::Map { .. }) {
+                    return Err(format!("Mapping '{}' can only be declared as contract storage", name));
+                }
+                self.locals.insert(name.clone(), ty.clone());
+                let line = match initializer {
+                    Some(initializer) => format!("{} {} = {};", Self::located_type(ty)?, name, self.expression(initializer)?),
+                    None => format!("{} {};", Self::located_type(ty)?, name),
+                };
+                self.line(&line);
+            },
+            Node::If { condition, then_branch, else_branch } => {
+                self.line(&format!("if ({}) {{", self.expression(condition)?));
+                self.emit_body(then_branch)?;
+                match else_branch {
+                    Some(else_branch) => {
+                        self.line("} else {");
+                        self.emit_body(else_branch)?;
+                        self.line("}");
+                    },
+                    None => self.line("}"),
+                }
+            },
+            Node::While { condition, body } => {
+                self.line(&format!("while ({}) {{", self.expression(condition)?));
+                self.emit_body(body)?;
+                self.line("}");
+            },
+            Node::DoWhile { body, condition } => {
+                self.line("do {");
+                self.emit_body(body)?;
+                self.line(&format!("}} while ({});", self.expression(condition)?));
+            },
+            Node::For { initializer, condition, increment, body } => {
+                let initializer = match initializer.as_deref() {
+                    Some(Node::Let { name, type_annotation: Some(ty), initializer: Some(value), .. }) => {
+                        format!("{} {} = {}", Self::type_name(ty)?, name, self.expression(value)?)
+                    },
+                    Some(expression) => self.expression(expression)?,
+                    None => String::new(),
+                };
+                let condition = condition.as_deref().map(|c| self.expression(c)).transpose()?.unwrap_or_default();
+                let increment = increment.as_deref().map(|i| self.expression(i)).transpose()?.unwrap_or_default();
+                self.line(&format!("for ({}; {}; {}) {{", initializer, condition, increment));
+                self.emit_body(body)?;
+                self.line("}");
+            },
+            Node::Return(value) => {
+                let line = match value {
+                    Some(value) => format!("return {};", self.expression(value)?),
+                    None => "return;".to_string(),
+                };
+                self.line(&line);
+            },
+            Node::Throw(reason) => {
+                self.line(&format!("revert({});", self.expression(reason)?));
+            },
+            Node::Assertion { kind, condition, message } => {
+                let condition = self.expression(condition)?;
+                // Solidity's assert() takes no reason, so a reasoned assert becomes a require
+                let line = match (kind, message) {
+                    (AssertionKind::Assert, None) => format!("assert({});", condition),
+                    (_, Some(message)) => format!("require({}, {});", condition, self.expression(message)?),
+                    (_, None) => format!("require({});", condition),
+                };
+                self.line(&line);
+            },
+            Node::Break => self.line("break;"),
+            Node::Continue => self.line("continue;"),
+            Node::Call { callee, arguments } if matches!(callee.as_ref(), Node::Identifier(name) if self.events.contains(name.as_str())) => {
+                let line = format!("emit {}({});", self.expression(callee)?, self.arguments(arguments)?);
+                self.line(&line);
+            },
+            expression => {
+                let line = format!("{};", self.expression(expression)?);
+                self.line(&line);
+            },
+        }
+        Ok(())
+    }
+
+    fn expression(&self, node: &Node) -> Result<String, String> {
+        Ok(match node {
+            Node::Identifier(name) => name.to_string(),
+            Node::IntLiteral(value) => value.to_string(),
+            Node::UIntLiteral(value) => value.to_string(),
+            Node::UInt256Literal(literal) => literal.clone(),
+            Node::StringLiteral(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+            Node::BooleanLiteral(value) => value.to_string(),
+            Node::This => "this".to_string(),
+            Node::Member { object, property } => match (object.as_ref(), ChainIntrinsic::from_member(object, property)) {
+                (_, Some(intrinsic)) => intrinsic.name().to_string(),
+                (Node::This, None) => property.clone(),
+                (object, None) => format!("{}.{}", self.expression(object)?, property),
+            },
+            Node::Index { object, index, .. } => format!("{}[{}]", self.operand(object)?, self.expression(index)?),
+            Node::Binary { left, operator, right } => {
+                format!("{} {} {}", self.operand(left)?, Self::binary_operator(operator)?, self.operand(right)?)
+            },
+            Node::Unary { operator: UnaryOp::PostIncrement, operand } => format!("{}++", self.operand(operand)?),
+            Node::Unary { operator: UnaryOp::PostDecrement, operand } => format!("{}--", self.operand(operand)?),
+            Node::Unary { operator, operand } => {
+                let operator = match operator {
+                    UnaryOp::Minus => "-",
+                    UnaryOp::Not => "!",
+                    UnaryOp::Increment => "++",
+                    UnaryOp::Decrement => "--",
+                    UnaryOp::BitNot => "~",
+                    UnaryOp::PostIncrement | UnaryOp::PostDecrement => unreachable!("translated above"),
+                };
+                format!("{}{}", operator, self.operand(operand)?)
+            },
+            Node::Call { callee, arguments } => {
+                if let Some(builtin) = CryptoBuiltin::from_callee(callee) {
+                    return match builtin {
+                        CryptoBuiltin::Keccak256 | CryptoBuiltin::Sha256 => {
+                            Ok(format!("{}(abi.encodePacked({}))", builtin.name(), self.arguments(arguments)?))
+                        },
+                        _ => Err(format!("{}() has no Solidity equivalent", builtin.name())),
+                    };
+                }
+                if let Some(intrinsic) = ChainIntrinsic::from_callee(callee) {
+                    return Err(intrinsic.evm_lowering().err().unwrap_or_default());
+                }
+                if let Some(builtin) = ArithmeticBuiltin::from_callee(callee) {
+                    return self.arithmetic_call(builtin, arguments);
+                }
+                if let Some(builtin) = DateTimeBuiltin::from_callee(callee) {
+                    return self.datetime_call(builtin, arguments);
+                }
+                if let Some(builtin) = BytesBuiltin::from_callee(callee) {
+                    return Ok(format!("{}({})", builtin.solidity_name()?, self.arguments(arguments)?));
+                }
+                if let Some(builtin) = IoBuiltin::from_callee(callee) {
+                    return Err(format!("io.{}() is not available in contracts", builtin.name()));
+                }
+                if let Some(builtin) = NetBuiltin::from_callee(callee) {
+                    return Err(format!("net.{}() is not available in contracts", builtin.name()));
+                }
+                if let Some(builtin) = ProcessBuiltin::from_callee(callee) {
+                    return Err(format!("process.{}() is not available in contracts", builtin.name()));
+                }
+                if let Some(builtin) = HttpBuiltin::from_callee(callee) {
+                    return Err(format!("http.{}() is not available in contracts; use an oracle", builtin.name()));
+                }
+                format!("{}({})", self.expression(callee)?, self.arguments(arguments)?)
+            },
+            Node::Array { elements } => format!("[{}]", self.arguments(elements)?),
+            other => return Err(format!("Unsupported expression in Solidity output: {:?}", other)),
+        })
+    }
+
+    /// Solidity arithmetic is checked, so `checked*` calls are plain
+    /// operators. `wrapping*` calls go through a function per operand type
+    /// with the operation in an `unchecked` block.
+    fn arithmetic_call(&self, builtin: ArithmeticBuiltin, arguments: &[Node]) -> Result<String, String> {
+        let [left, right] = arguments else {
+            return Err(format!("{}() expects 2 arguments, found {}", builtin.name(), arguments.len()));
+        };
+        let operator = Self::binary_operator(&builtin.operator())?;
+        let (lhs, rhs) = (self.expression(left)?, self.expression(right)?);
+        if !builtin.is_wrapping() {
+            return Ok(format!("({} {} {})", lhs, operator, rhs));
+        }
+
+        let ty = match (self.integer_type(left), self.integer_type(right)) {
+            (Some(ty), _) | (None, Some(ty)) => ty,
+            (None, None) if arguments.iter().all(|argument| matches!(argument, Node::IntLiteral(_))) => Type::Int,
+            (None, None) => {
+                return Err(format!("{}() needs an operand of a known integer type in Solidity output", builtin.name()));
+            },
+        };
+        let ty = Self::type_name(&ty)?;
+        let name = format!("{}_{}", builtin.name(), ty);
+        self.wrapping.borrow_mut().insert(name.clone(), (ty, operator));
+        Ok(format!("{}({}, {})", name, lhs, rhs))
+    }
+
+    /// Times are milliseconds like in native code, and the only clock is
+    /// the block's.
+    fn datetime_call(&self, builtin: DateTimeBuiltin, arguments: &[Node]) -> Result<String, String> {
+        builtin.evm_check()?;
+        if arguments.len() != builtin.arity() {
+            return Err(format!("{}() expects {} arguments, found {}", builtin.name(), builtin.arity(), arguments.len()));
+        }
+        match (builtin.scale(), arguments) {
+            (Some(1), [argument]) => self.expression(argument),
+            (Some(scale), [argument]) => Ok(format!("({} * {})", self.operand(argument)?, scale)),
+            _ => Ok("(int64(uint64(block.timestamp)) * 1000)".to_string()),
+        }
+    }
+
+    /// The type of an integer expression, when it follows from declarations.
+    fn integer_type(&self, node: &Node) -> Option<Type> {
+        let ty = match node {
+            Node::Located { node, .. } => return self.integer_type(node),
+            Node::Identifier(name) => self.locals.get(name.as_str()).or_else(|| self.fields.get(name.as_str())).cloned(),
+            Node::Member { object, property } if matches!(object.as_ref(), Node::This) => self.fields.get(property).cloned(),
+            Node::UInt256Literal(_) => Some(Type::UInt256),
+            Node::Binary { left, operator: BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod, right }
+            | Node::Binary { left, operator: BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor, right } => {
+                self.integer_type(left).or_else(|| self.integer_type(right))
+            },
+            // A shift has the type of the shifted value
+            Node::Binary { left, operator: BinaryOp::Shl | BinaryOp::Shr, .. } => return self.integer_type(left),
+            Node::Call { callee, arguments } if ArithmeticBuiltin::from_callee(callee).is_some() => {
+                arguments.iter().find_map(|argument| self.integer_type(argument))
+            },
+            _ => None,
+        };
+        ty.filter(|ty| matches!(ty, Type::Int | Type::UInt | Type::UInt256))
+    }
+
+    /// Nested binary expressions are always parenthesized so Gard precedence
+    /// never depends on Solidity's.
+    fn operand(&self, node: &Node) -> Result<String, String> {
+        match node {
+            Node::Binary { .. } => Ok(format!("({})", self.expression(node)?)),
+            _ => self.expression(node),
+        }
+    }
+
+    fn arguments(&self, arguments: &[Node]) -> Result<String, String> {
+        Ok(arguments.iter()
+            .map(|argument| self.expression(argument))
+            .collect::<Result<Vec<_>, String>>()?
+            .join(", "))
+    }
+
+    fn binary_operator(operator: &BinaryOp) -> Result<&'static str, String> {
+        Ok(match operator {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::NotEq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::NullCoalesce => return Err("Operator ?? has no Solidity equivalent".to_string()),
+        })
+    }
+
+    fn parameter_types(params: &[Parameter]) -> HashMap<String, Type> {
+        params.iter().map(|param| (param.name.to_string(), param.type_annotation.clone())).collect()
+    }
+
+    fn parameters(params: &[Parameter]) -> Result<String, String> {
+        Ok(params.iter()
+            .map(|param| Ok(format!("{} {}", Self::located_type(&param.type_annotation)?, param.name)))
+            .collect::<Result<Vec<_>, String>>()?
+            .join(", "))
+    }
+
+    fn function_modifiers(name: &str, modifiers: &[FunctionModifier]) -> Result<String, String> {
+        let mut visibility = "public";
+        let mut mutability = None;
+        for modifier in modifiers {
+            match modifier {
+                FunctionModifier::Public => visibility = "public",
+                FunctionModifier::Private => visibility = "private",
+                FunctionModifier::Protected => visibility = "internal",
+                FunctionModifier::View => mutability = Some("view"),
+                FunctionModifier::Pure => mutability = Some("pure"),
+                FunctionModifier::Payable => mutability = Some("payable"),
+                // Solidity checks every index; there is nothing to turn off
+                FunctionModifier::Unchecked => {},
+                FunctionModifier::Static | FunctionModifier::Async | FunctionModifier::Abstract | FunctionModifier::Concurrent | FunctionModifier::Export => {
+                    return Err(format!("Function '{}' uses a modifier ({:?}) that Solidity doesn't support", name, modifier));
+                },
+            }
+        }
+        Ok(match mutability {
+            Some(mutability) => format!("{} {}", visibility, mutability),
+            None => visibility.to_string(),
+        })
+    }
+
+    /// Type of a parameter, return value or local; reference types need an
+    /// explicit data location.
+    fn located_type(ty: &Type) -> Result<String, String> {
+        let name = Self::type_name(ty)?;
+        Ok(match ty {
+            Type::String | Type::Bytes | Type::Array(_) => format!("{} memory", name),
+            _ => name,
+        })
+    }
+
+    fn type_name(ty: &Type) -> Result<String, String> {
+        Ok(match ty {
+            Type::Int => "int64".to_string(),
+            Type::UInt => "uint64".to_string(),
+            Type::UInt256 => "uint256".to_string(),
+            Type::String => "string".to_string(),
+            Type::Bytes => "bytes".to_string(),
+            Type::Boolean => "bool".to_string(),
+            Type::Address => "address".to_string(),
+            Type::Array(element) => format!("{}[]", Self::type_name(element)?),
+            Type::Map { key, value } => format!("mapping({} => {})", Self::type_name(key)?, Self::type_name(value)?),
+            Type::Custom(_) if datetime::is_time_type(ty) => "int64".to_string(),
+            Type::Custom(name) => name.to_string(),
+            other => return Err(format!("Type {:?} has no Solidity equivalent", other)),
+        })
+    }
+
+    fn line(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.out.push_str(&"    ".repeat(self.indent));
+            self.out.push_str(text);
+        }
+        self.out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ident(name: &str) -> Node {
+        Node::Identifier(name.into())
+    }
+
+    fn param(name: &str, ty: Type) -> Parameter {
+        Parameter { name: name.into(), type_annotation: ty }
+    }
+
+    fn token_contract() -> Node {
+        Node::Contract {
+            name: "Token".to_string(),
+            members: vec![
+                Node::Let {
+                    name: "balances".to_string(),
+                    type_annotation: Some(Type::Map { key: Box::new(Type::Address), value: Box::new(Type::UInt256) }),
+                    initializer: None,
+                    is_mutable: true,
+                },
+                Node::Event {
+                    name: "Transfer".to_string(),
+                    fields: vec![param("to", Type::Address), param("amount", Type::UInt256)],
+                    docs: None,
+                },
+                Node::Function {
+                    name: "transfer".to_string(),
+                    params: vec![param("to", Type::Address), param("amount", Type::UInt256)],
+                    return_type: Type::Boolean,
+                    body: Box::new(Node::Block(vec![
+                        Node::Assertion {
+                            kind: AssertionKind::Require,
+                            condition: Box::new(Node::Binary {
+                                left: Box::new(ident("amount")),
+                                operator: BinaryOp::Gt,
+                                right: Box::new(Node::IntLiteral(0)),
+                            }),
+                            message: Some(Box::new(Node::StringLiteral("Zero amount".to_string()))),
+                        },
+                        Node::Call {
+                            callee: Box::new(ident("Transfer")),
+                            arguments: vec![ident("to"), ident("amount")],
+                        },
+                        Node::Return(Some(Box::new(Node::BooleanLiteral(true)))),
+                    ])),
+                    modifiers: vec![FunctionModifier::Public],
+                    docs: None,
+                },
+            ],
+            docs: None,
+        }
+    }
+
+    #[test]
+    fn test_transpile_contract() {
+        let source = transpile(&Node::Program(vec![token_contract()])).unwrap();
+        let expected = "\
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.20;
+
+contract Token {
+    mapping(address => uint256) public balances;
+    event Transfer(address to, uint256 amount);
+    function transfer(address to, uint256 amount) public returns (bool) {
+        require(amount > 0, \"Zero amount\");
+        emit Transfer(to, amount);
+        return true;
+    }
+}
+";
+        assert_eq!(source, expected);
+    }
+
+    #[test]
+    fn test_unsupported_constructs() {
+        let contract = |members| Node::Program(vec![Node::Contract { name: "C".to_string(), members, docs: None }]);
+        let function = |body| Node::Function {
+            name: "f".to_string(),
+            params: vec![],
+            return_type: Type::Void,
+            body: Box::new(Node::Block(vec![body])),
+            modifiers: vec![],
+            docs: None,
+        };
+
+        assert!(transpile(&Node::Program(vec![])).is_err());
+        assert!(transpile(&contract(vec![function(Node::Call { callee: Box::new(ident("mine")), arguments: vec![] })])).is_err());
+        assert!(transpile(&contract(vec![Node::StorageSlot {
+            slot: Box::new(Node::IntLiteral(5)),
+            declaration: Box::new(Node::Let {
+                name: "owner".to_string(),
+                type_annotation: Some(Type::Address),
+                initializer: None,
+                is_mutable: true,
+            }),
+        }])).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic_builtins() {
+        let call = |name: &str, left: Node, right: Node| Node::Call { callee: Box::new(ident(name)), arguments: vec![left, right] };
+        let contract = Node::Contract {
+            name: "Counter".to_string(),
+            members: vec![
+                Node::Let {
+                    name: "ticks".to_string(),
+                    type_annotation: Some(Type::UInt256),
+                    initializer: None,
+                    is_mutable: true,
+                },
+                Node::Function {
+                    name: "next".to_string(),
+                    params: vec![param("step", Type::Int)],
+                    return_type: Type::UInt256,
+                    body: Box::new(Node::Block(vec![
+                        Node::Let {
+                            name: "offset".to_string(),
+                            type_annotation: Some(Type::Int),
+                            initializer: Some(Box::new(call("wrappingMul", ident("step"), Node::IntLiteral(2)))),
+                            is_mutable: false,
+                        },
+                        Node::Return(Some(Box::new(Node::Binary {
+                            left: Box::new(call("checkedAdd", ident("ticks"), Node::IntLiteral(1))),
+                            operator: BinaryOp::Mul,
+                            right: Box::new(call("wrappingAdd", Node::IntLiteral(1), ident("ticks"))),
+                        }))),
+                    ])),
+                    modifiers: vec![FunctionModifier::Public],
+                    docs: None,
+                },
+            ],
+            docs: None,
+        };
+        let source = transpile(&Node::Program(vec![contract])).unwrap();
+        let expected = "\
+contract Counter {
+    uint256 public ticks;
+    function next(int64 step) public returns (uint256) {
+        int64 offset = wrappingMul_int64(step, 2);
+        return (ticks + 1) * wrappingAdd_uint256(1, ticks);
+    }
+    function wrappingAdd_uint256(uint256 a, uint256 b) private pure returns (uint256) {
+        unchecked {
+            return a + b;
+        }
+    }
+    function wrappingMul_int64(int64 a, int64 b) private pure returns (int64) {
+        unchecked {
+            return a * b;
+        }
+    }
+}
+";
+        assert!(source.ends_with(expected), "{}", source);
+
+        let emitter = SolidityEmitter::default();
+        assert!(emitter.expression(&call("wrappingSub", ident("a"), ident("b"))).is_err());
+    }
+
+    #[test]
+    fn test_crypto_calls_use_abi_encoding() {
+        let emitter = SolidityEmitter::default();
+        let call = Node::Call { callee: Box::new(ident("hash")), arguments: vec![ident("payload")] };
+        assert_eq!(emitter.expression(&call).unwrap(), "keccak256(abi.encodePacked(payload))");
+    }
+
+    #[test]
+    fn test_bytes_calls() {
+        let emitter = SolidityEmitter::default();
+        let bytes = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(ident("bytes")), property: name.to_string() }),
+            arguments,
+        };
+        assert_eq!(emitter.expression(&bytes("concat", vec![ident("selector"), ident("payload")])).unwrap(), "bytes.concat(selector, payload)");
+        assert!(emitter.expression(&bytes("toHex", vec![ident("payload")])).is_err());
+        assert_eq!(SolidityEmitter::located_type(&Type::Bytes).unwrap(), "bytes memory");
+    }
+
+    #[test]
+    fn test_datetime_uses_block_time() {
+        let emitter = SolidityEmitter::default();
+        let datetime = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(ident("datetime")), property: name.to_string() }),
+            arguments,
+        };
+        let deadline = Node::Binary {
+            left: Box::new(datetime("now", vec![])),
+            operator: BinaryOp::Add,
+            right: Box::new(datetime("days", vec![ident("term")])),
+        };
+        assert_eq!(emitter.expression(&deadline).unwrap(), "(int64(uint64(block.timestamp)) * 1000) + (term * 86400000)");
+        assert!(emitter.expression(&datetime("parse", vec![ident("text")])).unwrap_err().contains("block.timestamp"));
+    }
+}
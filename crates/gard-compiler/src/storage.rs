@@ -0,0 +1,346 @@
+use gard_ast::{Node, Type};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A contract state variable and the storage slot it occupies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageEntry {
+    pub name: String,
+    pub slot: u64,
+    pub ty: Type,
+    pub explicit: bool,
+}
+
+/// Storage layout of a single contract, in declaration order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageLayout {
+    pub contract: String,
+    pub entries: Vec<StorageEntry>,
+}
+
+impl StorageLayout {
+    /// Assigns slots to the state variables of a contract. Variables declared
+    /// with `@slot(n)` are pinned to `n`; the rest take the next free slot
+    /// after the previous declaration.
+    pub fn from_contract(node: &Node) -> Result<Self, String> {
+        let (contract, members) = match node {
+            Node::Contract { name, members, .. } => (name, members),
+            _ => return Err("Expected contract node".to_string()),
+        };
+
+        let mut entries = Vec::new();
+        let mut owners: HashMap<u64, String> = HashMap::new();
+        // None once a variable takes the last slot, leaving none to follow it
+        let mut next_slot = Some(0);
+
+        for member in members {
+            let (slot, declaration) = match member {
+                Node::StorageSlot { slot, declaration } => (Some(Self::slot_value(slot)?), declaration.as_ref()),
+                Node::Let { .. } => (None, member),
+                _ => continue,
+            };
+
+            let (name, ty) = match declaration {
+                Node::Let { name, type_annotation: Some(ty), .. } => (name.clone(), ty.clone()),
+                Node::Let { name, type_annotation: None, .. } => {
+                    return Err(format!("Storage variable '{}' in contract {} needs a type annotation", name, contract));
+                },
+                _ => return Err(format!("@slot can only be applied to storage variables in contract {}", contract)),
+            };
+
+            let explicit = slot.is_some();
+            let slot = match slot.or(next_slot) {
+                Some(slot) => slot,
+                None => return Err(format!("Storage variable '{}' in contract {} has no slot left after slot {}",
+                    name, contract, u64::MAX)),
+            };
+            if let Some(owner) = owners.insert(slot, name.clone()) {
+                return Err(format!("Storage slot {} in contract {} is assigned to both '{}' and '{}'",
+                    slot, contract, owner, name));
+            }
+            next_slot = next_slot.zip(slot.checked_add(1)).map(|(next, after)| next.max(after));
+
+            entries.push(StorageEntry { name, slot, ty, explicit });
+        }
+
+        Ok(Self {
+            contract: contract.clone(),
+            entries,
+        })
+    }
+
+    /// Collects the layout of every contract declared at the top level of a program.
+    pub fn from_program(program: &Node) -> Result<Vec<Self>, String> {
+        match program {
+            Node::Program(nodes) => nodes.iter()
+                .filter(|node| matches!(node, Node::Contract { .. }))
+                .map(Self::from_contract)
+                .collect(),
+            _ => Err("Expected program node".to_string()),
+        }
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&StorageEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    pub fn entry_at(&self, slot: u64) -> Option<&StorageEntry> {
+        self.entries.iter().find(|entry| entry.slot == slot)
+    }
+
+    fn slot_value(node: &Node) -> Result<u64, String> {
+        match node {
+            Node::UIntLiteral(value) => Ok(*value),
+            Node::IntLiteral(value) if *value >= 0 => Ok(*value as u64),
+            _ => Err(format!("@slot expects a non-negative integer literal, found {:?}", node)),
+        }
+    }
+}
+
+/// A difference between two versions of a contract's storage layout.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutChange {
+    Added { name: String, slot: u64 },
+    Removed { name: String, slot: u64 },
+    Moved { name: String, from: u64, to: u64 },
+    Retyped { name: String, slot: u64, from: Type, to: Type },
+    SlotReused { name: String, slot: u64, previous: String },
+    /// The upgrade no longer declares the contract
+    ContractRemoved,
+}
+
+impl LayoutChange {
+    /// Whether the change would make an upgraded contract misread existing storage.
+    pub fn is_breaking(&self) -> bool {
+        !matches!(self, LayoutChange::Added { .. })
+    }
+}
+
+impl fmt::Display for LayoutChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutChange::Added { name, slot } => {
+                write!(f, "added '{}' at slot {}", name, slot)
+            },
+            LayoutChange::Removed { name, slot } => {
+                write!(f, "removed '{}' from slot {}", name, slot)
+            },
+            LayoutChange::Moved { name, from, to } => {
+                write!(f, "moved '{}' from slot {} to slot {}", name, from, to)
+            },
+            LayoutChange::Retyped { name, slot, from, to } => {
+                write!(f, "changed type of '{}' at slot {} from {:?} to {:?}", name, slot, from, to)
+            },
+            LayoutChange::SlotReused { name, slot, previous } => {
+                write!(f, "'{}' reuses slot {} previously held by '{}'", name, slot, previous)
+            },
+            LayoutChange::ContractRemoved => write!(f, "removed the contract"),
+        }
+    }
+}
+
+/// Compares the layout of an upgraded contract against the deployed one.
+pub fn diff(old: &StorageLayout, new: &StorageLayout) -> Vec<LayoutChange> {
+    let mut changes = Vec::new();
+
+    for before in &old.entries {
+        match new.entry(&before.name) {
+            None => changes.push(LayoutChange::Removed {
+                name: before.name.clone(),
+                slot: before.slot,
+            }),
+            Some(after) => {
+                if after.slot != before.slot {
+                    changes.push(LayoutChange::Moved {
+                        name: before.name.clone(),
+                        from: before.slot,
+                        to: after.slot,
+                    });
+                }
+                if after.ty != before.ty {
+                    changes.push(LayoutChange::Retyped {
+                        name: before.name.clone(),
+                        slot: after.slot,
+                        from: before.ty.clone(),
+                        to: after.ty.clone(),
+                    });
+                }
+            },
+        }
+    }
+
+    for after in &new.entries {
+        if old.entry(&after.name).is_some() {
+            continue;
+        }
+        match old.entry_at(after.slot) {
+            Some(previous) => changes.push(LayoutChange::SlotReused {
+                name: after.name.clone(),
+                slot: after.slot,
+                previous: previous.name.clone(),
+            }),
+            None => changes.push(LayoutChange::Added {
+                name: after.name.clone(),
+                slot: after.slot,
+            }),
+        }
+    }
+
+    changes
+}
+
+/// Diffs every contract of the old program against its upgrade, keyed by
+/// contract name. A contract the new program drops is reported as removed.
+pub fn diff_programs(old: &Node, new: &Node) -> Result<Vec<(String, Vec<LayoutChange>)>, String> {
+    let old_layouts = StorageLayout::from_program(old)?;
+    let new_layouts = StorageLayout::from_program(new)?;
+
+    Ok(old_layouts.iter()
+        .map(|before| {
+            let changes = match new_layouts.iter().find(|after| after.contract == before.contract) {
+                Some(after) => diff(before, after),
+                None => vec![LayoutChange::ContractRemoved],
+            };
+            (before.contract.clone(), changes)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, ty: Type) -> Node {
+        Node::Let {
+            name: name.to_string(),
+            type_annotation: Some(ty),
+            initializer: None,
+            is_mutable: true,
+        }
+    }
+
+    fn pinned(slot: i64, declaration: Node) -> Node {
+        Node::StorageSlot {
+            slot: Box::new(Node::IntLiteral(slot)),
+            declaration: Box::new(declaration),
+        }
+    }
+
+    fn contract(members: Vec<Node>) -> StorageLayout {
+        StorageLayout::from_contract(&Node::Contract {
+            name: "Token".to_string(),
+            members,
+            docs: None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_sequential_and_pinned_slots() {
+        let layout = contract(vec![
+            field("owner", Type::Address),
+            pinned(10, field("supply", Type::UInt)),
+            field("paused", Type::Boolean),
+        ]);
+
+        assert_eq!(layout.entry("owner").unwrap().slot, 0);
+        assert_eq!(layout.entry("supply").unwrap().slot, 10);
+        assert_eq!(layout.entry("paused").unwrap().slot, 11);
+    }
+
+    #[test]
+    fn test_slot_collision() {
+        let result = StorageLayout::from_contract(&Node::Contract {
+            name: "Token".to_string(),
+            members: vec![
+                field("owner", Type::Address),
+                pinned(0, field("supply", Type::UInt)),
+            ],
+            docs: None,
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_last_slot() {
+        let last = Node::StorageSlot {
+            slot: Box::new(Node::UIntLiteral(u64::MAX)),
+            declaration: Box::new(field("supply", Type::UInt)),
+        };
+        let layout = contract(vec![field("owner", Type::Address), last.clone()]);
+        assert_eq!(layout.entry("supply").unwrap().slot, u64::MAX);
+
+        let result = StorageLayout::from_contract(&Node::Contract {
+            name: "Token".to_string(),
+            members: vec![last, field("paused", Type::Boolean)],
+            docs: None,
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_append_is_safe() {
+        let old = contract(vec![field("owner", Type::Address)]);
+        let new = contract(vec![field("owner", Type::Address), field("paused", Type::Boolean)]);
+
+        let changes = diff(&old, &new);
+        assert_eq!(changes, vec![LayoutChange::Added { name: "paused".to_string(), slot: 1 }]);
+        assert!(!changes.iter().any(LayoutChange::is_breaking));
+    }
+
+    #[test]
+    fn test_insert_shifts_slots() {
+        let old = contract(vec![field("owner", Type::Address), field("supply", Type::UInt)]);
+        let new = contract(vec![
+            field("owner", Type::Address),
+            field("paused", Type::Boolean),
+            field("supply", Type::UInt),
+        ]);
+
+        let changes = diff(&old, &new);
+        assert!(changes.contains(&LayoutChange::Moved { name: "supply".to_string(), from: 1, to: 2 }));
+        assert!(changes.contains(&LayoutChange::SlotReused {
+            name: "paused".to_string(),
+            slot: 1,
+            previous: "supply".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_pinned_insert_keeps_layout() {
+        let old = contract(vec![field("owner", Type::Address), field("supply", Type::UInt)]);
+        let new = contract(vec![
+            field("owner", Type::Address),
+            pinned(7, field("paused", Type::Boolean)),
+            pinned(1, field("supply", Type::UInt)),
+        ]);
+
+        assert!(!diff(&old, &new).iter().any(LayoutChange::is_breaking));
+    }
+
+    #[test]
+    fn test_retyped_variable() {
+        let old = contract(vec![field("supply", Type::UInt)]);
+        let new = contract(vec![field("supply", Type::Int)]);
+
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].is_breaking());
+    }
+
+    #[test]
+    fn test_removed_contract() {
+        let program = |names: &[&str]| Node::Program(names.iter().map(|name| Node::Contract {
+            name: name.to_string(),
+            members: vec![field("owner", Type::Address)],
+            docs: None,
+        }).collect());
+
+        let changes = diff_programs(&program(&["Token", "Vault"]), &program(&["Token"])).unwrap();
+        assert_eq!(changes, vec![
+            ("Token".to_string(), vec![]),
+            ("Vault".to_string(), vec![LayoutChange::ContractRemoved]),
+        ]);
+        assert!(LayoutChange::ContractRemoved.is_breaking());
+    }
+}
@@ -0,0 +1,388 @@
+use crate::interop::{AbiType, InteropTypes, StructLayout};
+use gard_ast::{Node, Type};
+use std::collections::BTreeMap;
+
+/// Exports the JS loader needs from a module that takes strings, arrays or
+/// classes as arguments.
+pub const ALLOC_EXPORT: &str = "gard_alloc";
+pub const FREE_EXPORT: &str = "gard_free";
+
+/// TypeScript declarations and the ES module loader for a wasm module's
+/// `@WasmExport` functions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WasmBindings {
+    pub declarations: String,
+    pub loader: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ExportedFunction {
+    export: String,
+    params: Vec<(String, AbiType)>,
+    /// `None` for void functions
+    result: Option<AbiType>,
+}
+
+/// Generates bindings for the top-level `@WasmExport` functions of a program.
+pub fn generate(program: &Node, module_name: &str) -> Result<WasmBindings, String> {
+    let types = InteropTypes::from_program(program)?;
+    let exports = collect_exports(program, &types)?;
+    if exports.is_empty() {
+        return Err("Program has no @WasmExport functions".to_string());
+    }
+
+    // Only the classes reachable from an export are described
+    let mut structs = BTreeMap::new();
+    for export in &exports {
+        for ty in export.params.iter().map(|(_, ty)| ty).chain(export.result.as_ref()) {
+            collect_structs(ty, &types, &mut structs);
+        }
+    }
+
+    Ok(WasmBindings {
+        declarations: declarations(module_name, &exports, &structs),
+        loader: loader(module_name, &exports, &structs),
+    })
+}
+
+fn collect_exports(program: &Node, types: &InteropTypes) -> Result<Vec<ExportedFunction>, String> {
+    let nodes = match program {
+        Node::Program(nodes) => nodes,
+        _ => return Err("Expected program node".to_string()),
+    };
+
+    nodes.iter()
+        .filter_map(|node| match node {
+            Node::WasmExport { export_name, declaration } => Some((export_name, declaration.as_ref())),
+            _ => None,
+        })
+        .map(|(export_name, declaration)| match declaration {
+            Node::Function { name, params, return_type, .. } => Ok(ExportedFunction {
+                export: export_name.clone().unwrap_or_else(|| name.clone()),
+                params: params.iter()
+                    .map(|param| Ok((param.name.to_string(), types.resolve(&param.type_annotation)?)))
+                    .collect::<Result<_, String>>()?,
+                result: match return_type {
+                    Type::Void => None,
+                    ty => Some(types.resolve(ty)?),
+                },
+            }),
+            _ => Err("@WasmExport must be applied to a function".to_string()),
+        })
+        .collect()
+}
+
+fn collect_structs<'a>(ty: &AbiType, types: &'a InteropTypes, structs: &mut BTreeMap<String, &'a StructLayout>) {
+    match ty {
+        AbiType::Array(element) => collect_structs(element, types, structs),
+        AbiType::Struct(name) if !structs.contains_key(name) => {
+            if let Some(layout) = types.layout(name) {
+                structs.insert(name.clone(), layout);
+                for field in &layout.fields {
+                    collect_structs(&field.ty, types, structs);
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+fn declarations(module_name: &str, exports: &[ExportedFunction], structs: &BTreeMap<String, &StructLayout>) -> String {
+    let interface = interface_name(module_name);
+    let mut out = format!("// Generated by gard from {}. Do not edit.\n\n", module_name);
+
+    for layout in structs.values() {
+        out.push_str(&format!("export interface {} {{\n", layout.name));
+        for field in &layout.fields {
+            out.push_str(&format!("  {}: {};\n", field.name, ts_type(&field.ty)));
+        }
+        out.push_str("}\n\n");
+    }
+
+    out.push_str(&format!("export interface {} {{\n", interface));
+    out.push_str("  readonly memory: WebAssembly.Memory;\n");
+    for export in exports {
+        let params: Vec<String> = export.params.iter()
+            .map(|(name, ty)| format!("{}: {}", name, ts_type(ty)))
+            .collect();
+        let result = export.result.as_ref().map(ts_type).unwrap_or_else(|| "void".to_string());
+        out.push_str(&format!("  {}({}): {};\n", export.export, params.join(", "), result));
+    }
+    out.push_str("}\n\n");
+
+    out.push_str(&format!(
+        "export function load(source: BufferSource | Response | PromiseLike<Response>, imports?: WebAssembly.Imports): Promise<{}>;\n",
+        interface
+    ));
+    out
+}
+
+fn loader(module_name: &str, exports: &[ExportedFunction], structs: &BTreeMap<String, &StructLayout>) -> String {
+    let mut out = format!("// Generated by gard from {}. Do not edit.\n\n", module_name);
+
+    out.push_str("const layouts = {\n");
+    for layout in structs.values() {
+        let fields: Vec<String> = layout.fields.iter()
+            .map(|field| format!("[\"{}\", {}, {}]", field.name, js_descriptor(&field.ty), field.offset))
+            .collect();
+        out.push_str(&format!("  {}: {{ size: {}, fields: [{}] }},\n", layout.name, layout.size, fields.join(", ")));
+    }
+    out.push_str("};\n");
+    out.push_str(LOADER_PRELUDE);
+
+    for export in exports {
+        let names: Vec<&str> = export.params.iter().map(|(name, _)| name.as_str()).collect();
+        let arguments: Vec<String> = export.params.iter()
+            .map(|(name, ty)| format!("lower({}, {}, allocs)", js_descriptor(ty), name))
+            .collect();
+        let call = format!("raw.{}({})", export.export, arguments.join(", "));
+        let body = match &export.result {
+            Some(ty) => format!("return lift({}, {});", js_descriptor(ty), call),
+            None => format!("{};", call),
+        };
+
+        out.push_str(&format!("    {}({}) {{\n", export.export, names.join(", ")));
+        out.push_str("      const allocs = [];\n");
+        out.push_str(&format!("      try {{\n        {}\n      }} finally {{\n", body));
+        out.push_str(&format!("        allocs.forEach((ptr) => raw.{}(ptr));\n", FREE_EXPORT));
+        out.push_str("      }\n    },\n");
+    }
+
+    out.push_str("  };\n}\n");
+    out
+}
+
+/// The loader encodes values with the layouts from `interop`: arguments are
+/// copied into memory allocated with gard_alloc and freed after the call,
+/// results are read in place and stay owned by the module.
+const LOADER_PRELUDE: &str = r#"
+const encoder = new TextEncoder();
+const decoder = new TextDecoder();
+
+function sizeAlign(type) {
+  switch (type) {
+    case "i64": case "f64": return [8, 8];
+    case "bool": return [1, 1];
+    default: return [4, 4];
+  }
+}
+
+function alignTo(offset, alignment) {
+  return Math.ceil(offset / alignment) * alignment;
+}
+
+export async function load(source, imports = {}) {
+  const resolved = await source;
+  const { instance } = resolved instanceof Response
+    ? await WebAssembly.instantiateStreaming(resolved, imports)
+    : await WebAssembly.instantiate(resolved, imports);
+  const raw = instance.exports;
+  const memory = raw.memory;
+
+  function alloc(size, allocs) {
+    const ptr = raw.gard_alloc(size);
+    allocs.push(ptr);
+    return ptr;
+  }
+
+  function lower(type, value, allocs) {
+    if (type === "i64") return BigInt(value);
+    if (type === "f64") return value;
+    if (type === "bool") return value ? 1 : 0;
+    if (type === "string") {
+      const bytes = encoder.encode(value);
+      const ptr = alloc(bytes.length + 1, allocs);
+      const view = new Uint8Array(memory.buffer, ptr, bytes.length + 1);
+      view.set(bytes);
+      view[bytes.length] = 0;
+      return ptr;
+    }
+    if (type.array !== undefined) {
+      const [size, alignment] = sizeAlign(type.array);
+      const start = alignTo(4, alignment);
+      const ptr = alloc(start + size * value.length, allocs);
+      new DataView(memory.buffer).setUint32(ptr, value.length, true);
+      value.forEach((element, i) => store(type.array, ptr + start + i * size, element, allocs));
+      return ptr;
+    }
+    const layout = layouts[type.struct];
+    const ptr = alloc(layout.size, allocs);
+    for (const [name, fieldType, offset] of layout.fields) {
+      store(fieldType, ptr + offset, value[name], allocs);
+    }
+    return ptr;
+  }
+
+  function store(type, ptr, value, allocs) {
+    const lowered = lower(type, value, allocs);
+    // Lowering may allocate and grow memory, so the view is taken afterwards
+    const view = new DataView(memory.buffer);
+    if (type === "i64") view.setBigInt64(ptr, lowered, true);
+    else if (type === "f64") view.setFloat64(ptr, lowered, true);
+    else if (type === "bool") view.setUint8(ptr, lowered);
+    else view.setUint32(ptr, lowered, true);
+  }
+
+  function lift(type, value) {
+    if (type === "i64" || type === "f64") return value;
+    if (type === "bool") return value !== 0;
+    const ptr = value >>> 0;
+    const view = new DataView(memory.buffer);
+    if (type === "string") {
+      const bytes = new Uint8Array(memory.buffer);
+      let end = ptr;
+      while (bytes[end] !== 0) end++;
+      return decoder.decode(bytes.subarray(ptr, end));
+    }
+    if (type.array !== undefined) {
+      const [size, alignment] = sizeAlign(type.array);
+      const start = alignTo(4, alignment);
+      const length = view.getUint32(ptr, true);
+      return Array.from({ length }, (_, i) => loadValue(type.array, ptr + start + i * size));
+    }
+    const result = {};
+    for (const [name, fieldType, offset] of layouts[type.struct].fields) {
+      result[name] = loadValue(fieldType, ptr + offset);
+    }
+    return result;
+  }
+
+  function loadValue(type, ptr) {
+    const view = new DataView(memory.buffer);
+    if (type === "i64") return view.getBigInt64(ptr, true);
+    if (type === "f64") return view.getFloat64(ptr, true);
+    if (type === "bool") return view.getUint8(ptr) !== 0;
+    return lift(type, view.getUint32(ptr, true));
+  }
+
+  return {
+    memory,
+"#;
+
+fn ts_type(ty: &AbiType) -> String {
+    match ty {
+        AbiType::I64 => "bigint".to_string(),
+        AbiType::F64 => "number".to_string(),
+        AbiType::Bool => "boolean".to_string(),
+        AbiType::String => "string".to_string(),
+        AbiType::Array(element) => format!("{}[]", ts_type(element)),
+        AbiType::Struct(name) => name.clone(),
+    }
+}
+
+/// Type descriptor the loader's `lower`/`lift` dispatch on.
+fn js_descriptor(ty: &AbiType) -> String {
+    match ty {
+        AbiType::I64 => "\"i64\"".to_string(),
+        AbiType::F64 => "\"f64\"".to_string(),
+        AbiType::Bool => "\"bool\"".to_string(),
+        AbiType::String => "\"string\"".to_string(),
+        AbiType::Array(element) => format!("{{ array: {} }}", js_descriptor(element)),
+        AbiType::Struct(name) => format!("{{ struct: \"{}\" }}", name),
+    }
+}
+
+fn interface_name(module_name: &str) -> String {
+    let mut name: String = module_name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map(|first| first.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+        })
+        .collect();
+    name.push_str("Exports");
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::Parameter;
+
+    fn export(name: &str, params: Vec<(&str, Type)>, return_type: Type) -> Node {
+        Node::WasmExport {
+            export_name: None,
+            declaration: Box::new(Node::Function {
+                name: name.to_string(),
+                params: params.into_iter()
+                    .map(|(name, ty)| Parameter { name: name.into(), type_annotation: ty })
+                    .collect(),
+                return_type,
+                body: Box::new(Node::Block(vec![])),
+                modifiers: vec![],
+                docs: None,
+            }),
+        }
+    }
+
+    fn point_class() -> Node {
+        let field = |name: &str| Node::Let {
+            name: name.to_string(),
+            type_annotation: Some(Type::Int),
+            initializer: None,
+            is_mutable: true,
+        };
+        Node::Class {
+            name: "Point".to_string(),
+            extends: None,
+            implements: vec![],
+            is_abstract: false,
+            members: vec![field("x"), field("y")],
+            docs: None,
+        }
+    }
+
+    #[test]
+    fn test_declarations() {
+        let program = Node::Program(vec![
+            export("greet", vec![("name", Type::String)], Type::String),
+            export("add", vec![("a", Type::Int), ("b", Type::Int)], Type::Int),
+        ]);
+        let bindings = generate(&program, "hello-world").unwrap();
+        assert_eq!(bindings.declarations, "\
+// Generated by gard from hello-world. Do not edit.
+
+export interface HelloWorldExports {
+  readonly memory: WebAssembly.Memory;
+  greet(name: string): string;
+  add(a: bigint, b: bigint): bigint;
+}
+
+export function load(source: BufferSource | Response | PromiseLike<Response>, imports?: WebAssembly.Imports): Promise<HelloWorldExports>;
+");
+    }
+
+    #[test]
+    fn test_loader_marshals_strings() {
+        let program = Node::Program(vec![
+            export("greet", vec![("name", Type::String)], Type::String),
+            export("isEven", vec![("n", Type::Int)], Type::Boolean),
+        ]);
+        let loader = generate(&program, "greeter").unwrap().loader;
+        assert!(loader.contains("        return lift(\"string\", raw.greet(lower(\"string\", name, allocs)));\n      } finally {\n        allocs.forEach((ptr) => raw.gard_free(ptr));\n"));
+        assert!(loader.contains("        return lift(\"bool\", raw.isEven(lower(\"i64\", n, allocs)));\n"));
+    }
+
+    #[test]
+    fn test_struct_and_array_interop() {
+        let points = Type::Array(Box::new(Type::Custom("Point".into())));
+        let program = Node::Program(vec![
+            point_class(),
+            export("centroid", vec![("points", points)], Type::Custom("Point".into())),
+        ]);
+        let bindings = generate(&program, "geometry").unwrap();
+
+        assert!(bindings.declarations.contains("export interface Point {\n  x: bigint;\n  y: bigint;\n}\n"));
+        assert!(bindings.declarations.contains("  centroid(points: Point[]): Point;\n"));
+        assert!(bindings.loader.contains("  Point: { size: 16, fields: [[\"x\", \"i64\", 0], [\"y\", \"i64\", 8]] },\n"));
+        assert!(bindings.loader.contains("lower({ array: { struct: \"Point\" } }, points, allocs)"));
+    }
+
+    #[test]
+    fn test_unsupported_boundary_types() {
+        let program = Node::Program(vec![export("owner", vec![], Type::Address)]);
+        assert!(generate(&program, "m").is_err());
+        assert!(generate(&Node::Program(vec![]), "m").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! Codegen units: splitting a native library's top-level functions between
+//! LLVM modules, which worker threads compile to object files side by side
+//! before they're linked into the library. Set how many with
+//! `--codegen-units` or in `gard.toml`:
+//!
+//! ```toml
+//! [codegen]
+//! codegen-units = 8
+//! ```
+//!
+//! Each unit still generates IR for the whole program, so its module knows
+//! every type and signature, but only defines its own share of the
+//! functions and declares the rest. The first unit also defines everything
+//! that isn't a plain top-level function: classes, actors, inline IR. Type
+//! info and itables are merged by the linker, so each class keeps one.
+//!
+//! Functions are dealt out in the order they're declared, and objects are
+//! linked in the order of their units, so the same program and unit count
+//! always build the same library. Different counts build different, but
+//! equivalent, libraries; the default of one keeps builds reproducible
+//! from machine to machine. With more than one unit, a library's
+//! functions that aren't `@export`ed can't be private to one object file.
+//! They're hidden instead, which keeps them out of a shared library's
+//! symbols, but not out of the C program's namespace when it links a
+//! static library.
+
+use crate::inline_ir;
+use gard_ast::Node;
+use std::path::{Path, PathBuf};
+
+/// Whether a top-level node is a function any unit can define. The
+/// others belong to the first unit.
+pub fn splittable(node: &Node) -> bool {
+    matches!(node.unlocated(), Node::Function { body, .. } if inline_ir::intrinsic_body(body).is_none())
+}
+
+/// How many units to split `program` into, for `requested` of them: at
+/// least one, and no more than it has functions to split.
+pub fn count(requested: usize, program: &Node) -> usize {
+    let functions = match program {
+        Node::Program(nodes) => nodes.iter().filter(|node| splittable(node)).count(),
+        _ => 0,
+    };
+    requested.min(functions).max(1)
+}
+
+/// The unit that defines the `function`th splittable function, counting
+/// from zero.
+pub fn owner(function: usize, units: usize) -> usize {
+    function % units.max(1)
+}
+
+/// Where `unit` writes its object file, for a library at `output`.
+pub fn object_path(output: &Path, unit: usize, units: usize) -> PathBuf {
+    match units {
+        1 => output.with_extension("o"),
+        _ => output.with_extension(format!("{}.o", unit)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::{Span, Type};
+
+    fn function(name: &str, body: Node) -> Node {
+        let function = Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type: Type::Void,
+            body: Box::new(body),
+            modifiers: vec![],
+            docs: None,
+        };
+        Node::Located { span: Span { start: 0, end: 1 }, node: Box::new(function) }
+    }
+
+    #[test]
+    fn test_split() {
+        let program = Node::Program(vec![
+            function("a", Node::Block(vec![])),
+            function("fence", Node::Block(vec![Node::InlineIr("fence seq_cst".to_string())])),
+            Node::Interface { name: "Shape".to_string(), methods: vec![] },
+            function("b", Node::Block(vec![])),
+            function("c", Node::Block(vec![])),
+        ]);
+        let Node::Program(nodes) = &program else { unreachable!() };
+        assert_eq!(nodes.iter().map(splittable).collect::<Vec<_>>(), [true, false, false, true, true]);
+        assert_eq!(count(8, &program), 3);
+        assert_eq!(count(2, &program), 2);
+        assert_eq!(count(0, &program), 1);
+        assert_eq!(count(4, &Node::Program(vec![])), 1);
+        assert_eq!((0..5).map(|function| owner(function, 2)).collect::<Vec<_>>(), [0, 1, 0, 1, 0]);
+        assert_eq!(owner(3, 0), 0);
+
+        let output = Path::new("out/libgeometry.so");
+        assert_eq!(object_path(output, 0, 1), Path::new("out/libgeometry.o"));
+        assert_eq!(object_path(output, 2, 4), Path::new("out/libgeometry.2.o"));
+    }
+}
@@ -0,0 +1,352 @@
+use crate::crypto::CryptoBuiltin;
+use crate::storage::StorageLayout;
+use gard_ast::{FunctionModifier, Node, Parameter, Type};
+use std::collections::HashSet;
+use WasmValType::{I32, I64};
+
+pub const TARGET_TRIPLE: &str = "wasm32-unknown-unknown";
+/// Import module the host shims live in.
+pub const HOST_MODULE: &str = "env";
+pub const INSTANTIATE_EXPORT: &str = "instantiate";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasmValType {
+    I32,
+    I64,
+}
+
+/// A function the host must provide to run Gard contracts. Pointers are
+/// offsets into the contract's exported linear memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostImport {
+    pub name: &'static str,
+    pub params: &'static [WasmValType],
+    pub result: Option<WasmValType>,
+}
+
+pub const HOST_IMPORTS: &[HostImport] = &[
+    // (key_ptr, key_len, value_ptr, value_capacity) -> value length, or -1 if unset
+    HostImport { name: "storage_read", params: &[I32, I32, I32, I32], result: Some(I32) },
+    // (key_ptr, key_len, value_ptr, value_len)
+    HostImport { name: "storage_write", params: &[I32, I32, I32, I32], result: None },
+    // (key_ptr, key_len)
+    HostImport { name: "storage_remove", params: &[I32, I32], result: None },
+    // (out_ptr) writes the 20-byte caller address
+    HostImport { name: "context_sender", params: &[I32], result: None },
+    HostImport { name: "context_block_height", params: &[], result: Some(I64) },
+    HostImport { name: "context_block_time", params: &[], result: Some(I64) },
+    // (message_ptr) aborts the call with a NUL-terminated reason and rolls back its writes
+    HostImport { name: "abort", params: &[I32], result: None },
+];
+
+pub fn host_import(name: &str) -> Option<&'static HostImport> {
+    HOST_IMPORTS.iter().find(|import| import.name == name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Instantiate,
+    Execute,
+    Query,
+}
+
+/// An exported contract entry point. By convention the constructor is
+/// exported as `instantiate`, state-changing functions as `execute_<name>`
+/// and view/pure functions as `query_<name>`; `@WasmExport("name")` overrides
+/// the export name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub export: String,
+    pub function: String,
+    pub kind: EntryKind,
+}
+
+/// A user-declared `@WasmImport`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserImport {
+    pub module: String,
+    pub name: String,
+    pub params: Vec<Parameter>,
+    pub return_type: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractAbi {
+    pub contract: String,
+    pub entry_points: Vec<EntryPoint>,
+    pub imports: Vec<UserImport>,
+    pub storage_keys: Vec<String>,
+}
+
+impl ContractAbi {
+    pub fn from_contract(node: &Node) -> Result<Self, String> {
+        let (contract, members) = match node {
+            Node::Contract { name, members, .. } => (name, members),
+            _ => return Err("Expected contract node".to_string()),
+        };
+
+        let mut entry_points = Vec::new();
+        let mut imports = Vec::new();
+        for member in members {
+            match member {
+                Node::Constructor { .. } => entry_points.push(EntryPoint {
+                    export: INSTANTIATE_EXPORT.to_string(),
+                    function: INSTANTIATE_EXPORT.to_string(),
+                    kind: EntryKind::Instantiate,
+                }),
+                Node::Function { name, modifiers, .. } if Self::is_public(modifiers) => {
+                    entry_points.push(Self::entry_point(name, modifiers, None));
+                },
+                Node::WasmExport { export_name, declaration } => match declaration.as_ref() {
+                    Node::Function { name, modifiers, .. } => {
+                        entry_points.push(Self::entry_point(name, modifiers, export_name.clone()));
+                    },
+                    _ => return Err(format!("@WasmExport must be applied to a function in contract {}", contract)),
+                },
+                Node::WasmImport { module, name, params, return_type } => imports.push(UserImport {
+                    module: module.clone(),
+                    name: name.clone(),
+                    params: params.clone(),
+                    return_type: return_type.clone(),
+                }),
+                _ => {},
+            }
+        }
+
+        if !entry_points.iter().any(|entry| entry.kind == EntryKind::Instantiate) {
+            entry_points.insert(0, EntryPoint {
+                export: INSTANTIATE_EXPORT.to_string(),
+                function: INSTANTIATE_EXPORT.to_string(),
+                kind: EntryKind::Instantiate,
+            });
+        }
+
+        let mut exports = HashSet::new();
+        for entry in &entry_points {
+            if !exports.insert(entry.export.as_str()) {
+                return Err(format!("Export '{}' is defined more than once in contract {}", entry.export, contract));
+            }
+        }
+
+        let storage_keys = StorageLayout::from_contract(node)?
+            .entries
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+
+        Ok(Self {
+            contract: contract.clone(),
+            entry_points,
+            imports,
+            storage_keys,
+        })
+    }
+
+    pub fn entry(&self, export: &str) -> Option<&EntryPoint> {
+        self.entry_points.iter().find(|entry| entry.export == export)
+    }
+
+    fn is_public(modifiers: &[FunctionModifier]) -> bool {
+        !modifiers.iter().any(|m| matches!(m, FunctionModifier::Private | FunctionModifier::Protected))
+    }
+
+    fn entry_point(name: &str, modifiers: &[FunctionModifier], export: Option<String>) -> EntryPoint {
+        let kind = if modifiers.iter().any(|m| matches!(m, FunctionModifier::View | FunctionModifier::Pure)) {
+            EntryKind::Query
+        } else {
+            EntryKind::Execute
+        };
+        let prefix = if kind == EntryKind::Query { "query" } else { "execute" };
+
+        EntryPoint {
+            export: export.unwrap_or_else(|| format!("{}_{}", prefix, name)),
+            function: name.to_string(),
+            kind,
+        }
+    }
+}
+
+/// Rejects constructs whose result could differ between nodes executing
+/// the same contract call.
+pub fn check_determinism(contract: &Node) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    visit(contract, &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn visit(node: &Node, errors: &mut Vec<String>) {
+    match node {
+        Node::Contract { members, .. } | Node::Block(members) => {
+            members.iter().for_each(|member| visit(member, errors));
+        },
+        Node::Function { name, params, return_type, body, modifiers, .. } => {
+            for param in params {
+                check_type(&param.type_annotation, &format!("parameter '{}' of {}", param.name, name), errors);
+            }
+            check_type(return_type, &format!("return type of {}", name), errors);
+            if modifiers.contains(&FunctionModifier::Async) {
+                errors.push(format!("Async function '{}' is not supported in wasm contracts", name));
+            }
+            visit(body, errors);
+        },
+        Node::Constructor { params, body } => {
+            for param in params {
+                check_type(&param.type_annotation, &format!("constructor parameter '{}'", param.name), errors);
+            }
+            visit(body, errors);
+        },
+        Node::WasmExport { declaration, .. } | Node::StorageSlot { declaration, .. } => visit(declaration, errors),
+        Node::Located { node, .. } => visit(node, errors),
+        Node::WasmImport { name, params, return_type, .. } => {
+            for param in params {
+                check_type(&param.type_annotation, &format!("import '{}'", name), errors);
+            }
+            check_type(return_type, &format!("import '{}'", name), errors);
+        },
+        Node::Let { name, type_annotation, initializer, .. } => {
+            if let Some(ty) = type_annotation {
+                check_type(ty, &format!("'{}'", name), errors);
+            }
+            if let Some(initializer) = initializer {
+                visit(initializer, errors);
+            }
+        },
+        Node::If { condition, then_branch, else_branch } => {
+            visit(condition, errors);
+            visit(then_branch, errors);
+            if let Some(else_branch) = else_branch {
+                visit(else_branch, errors);
+            }
+        },
+        Node::While { condition, body } | Node::DoWhile { body, condition } => {
+            visit(condition, errors);
+            visit(body, errors);
+        },
+        Node::Return(Some(value)) | Node::Throw(value) => visit(value, errors),
+        Node::Assertion { condition, message, .. } => {
+            visit(condition, errors);
+            if let Some(message) = message {
+                visit(message, errors);
+            }
+        },
+        Node::Binary { left, right, .. } => {
+            visit(left, errors);
+            visit(right, errors);
+        },
+        Node::Unary { operand, .. } => visit(operand, errors),
+        Node::Call { callee, arguments } => {
+            if let Some(builtin @ (CryptoBuiltin::RandomSalt | CryptoBuiltin::Sign)) = CryptoBuiltin::from_callee(callee) {
+                errors.push(format!("{}() is not deterministic and can't be used in wasm contracts", builtin.name()));
+            }
+            arguments.iter().for_each(|argument| visit(argument, errors));
+        },
+        Node::FloatLiteral(_) => {
+            errors.push("Floating point literal is not deterministic in wasm contracts".to_string());
+        },
+        Node::Await(_) => errors.push("await is not supported in wasm contracts".to_string()),
+        Node::Actor { .. } | Node::Supervise { .. } | Node::STMTransaction { .. } | Node::Atomic { .. } => {
+            errors.push("Actors and STM are not supported in wasm contracts".to_string());
+        },
+        Node::Scope { .. } | Node::Spawn(_) | Node::TaskLocal { .. } => {
+            errors.push("Tasks are not supported in wasm contracts".to_string());
+        },
+        _ => {},
+    }
+}
+
+fn check_type(ty: &Type, context: &str, errors: &mut Vec<String>) {
+    if contains_float(ty) {
+        errors.push(format!("Floating point type in {} is not deterministic in wasm contracts", context));
+    }
+}
+
+fn contains_float(ty: &Type) -> bool {
+    match ty {
+        Type::Float | Type::Double => true,
+        Type::Array(element) | Type::Set(element) => contains_float(element),
+        Type::Map { key, value } => contains_float(key) || contains_float(value),
+        Type::Function { params, return_type } => params.iter().any(contains_float) || contains_float(return_type),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(name: &str, modifiers: Vec<FunctionModifier>, body: Vec<Node>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type: Type::Void,
+            body: Box::new(Node::Block(body)),
+            modifiers,
+            docs: None,
+        }
+    }
+
+    fn contract(members: Vec<Node>) -> Node {
+        Node::Contract { name: "Counter".to_string(), members, docs: None }
+    }
+
+    #[test]
+    fn test_entry_point_convention() {
+        let abi = ContractAbi::from_contract(&contract(vec![
+            Node::Let {
+                name: "count".to_string(),
+                type_annotation: Some(Type::UInt),
+                initializer: None,
+                is_mutable: true,
+            },
+            function("increment", vec![FunctionModifier::Public], vec![]),
+            function("get", vec![FunctionModifier::View], vec![]),
+            function("helper", vec![FunctionModifier::Private], vec![]),
+            Node::WasmExport {
+                export_name: Some("reset".to_string()),
+                declaration: Box::new(function("clear", vec![], vec![])),
+            },
+        ])).unwrap();
+
+        let exports: Vec<_> = abi.entry_points.iter().map(|entry| entry.export.as_str()).collect();
+        assert_eq!(exports, vec!["instantiate", "execute_increment", "query_get", "reset"]);
+        assert_eq!(abi.entry("reset").unwrap().function, "clear");
+        assert_eq!(abi.storage_keys, vec!["count".to_string()]);
+    }
+
+    #[test]
+    fn test_duplicate_exports_rejected() {
+        let result = ContractAbi::from_contract(&contract(vec![
+            function("run", vec![], vec![]),
+            Node::WasmExport {
+                export_name: Some("execute_run".to_string()),
+                declaration: Box::new(function("other", vec![], vec![])),
+            },
+        ]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_determinism_checks() {
+        let salt = Node::Call {
+            callee: Box::new(Node::Identifier("randomSalt".into())),
+            arguments: vec![],
+        };
+        let errors = check_determinism(&contract(vec![
+            function("roll", vec![], vec![salt]),
+            function("ratio", vec![], vec![Node::Return(Some(Box::new(Node::FloatLiteral(0.5))))]),
+        ])).unwrap_err();
+        assert_eq!(errors.len(), 2);
+
+        assert!(check_determinism(&contract(vec![function("ok", vec![], vec![])])).is_ok());
+    }
+
+    #[test]
+    fn test_host_imports() {
+        assert_eq!(host_import("context_block_height").unwrap().result, Some(WasmValType::I64));
+        assert!(host_import("random").is_none());
+    }
+}
@@ -0,0 +1,8 @@
+//! Debug Adapter Protocol server for Gard, so editors like VS Code can debug
+//! programs running in the interpreter. Positions come from the same
+//! `gard_ast::SourceMap` the rest of the tooling uses.
+
+pub mod protocol;
+pub mod server;
+
+pub use server::Server;
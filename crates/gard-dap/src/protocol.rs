@@ -0,0 +1,124 @@
+//! DAP wire format: JSON messages framed by a `Content-Length` header.
+
+use serde_json::{json, Value as Json};
+use std::io::{self, BufRead, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub seq: i64,
+    pub command: String,
+    pub arguments: Json,
+}
+
+/// Reads one message, or `None` at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>().map_err(invalid_data)?);
+        }
+    }
+
+    let length = length.ok_or_else(|| invalid_data("message without a Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(invalid_data)
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// The client end of a session. Keeps the sequence numbers of outgoing
+/// messages.
+pub struct Connection {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: i64,
+}
+
+impl Connection {
+    pub fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            input: Box::new(input),
+            output: Box::new(output),
+            seq: 0,
+        }
+    }
+
+    /// The next request; other messages from the client are skipped.
+    pub fn recv(&mut self) -> io::Result<Option<Request>> {
+        while let Some(message) = read_message(&mut self.input)? {
+            if message["type"] != "request" {
+                continue;
+            }
+            return Ok(Some(Request {
+                seq: message["seq"].as_i64().unwrap_or(0),
+                command: message["command"].as_str().unwrap_or("").to_string(),
+                arguments: message["arguments"].clone(),
+            }));
+        }
+        Ok(None)
+    }
+
+    pub fn respond(&mut self, request: &Request, result: Result<Json, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Json::Null) => {},
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Json::String(message),
+        }
+        self.send(response)
+    }
+
+    pub fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut framed = Vec::new();
+        write_message(&mut framed, &json!({ "type": "request", "seq": 1, "command": "threads" })).unwrap();
+        write_message(&mut framed, &json!({ "type": "event", "event": "output" })).unwrap();
+        assert!(framed.starts_with(b"Content-Length: 46\r\n\r\n{"));
+
+        let mut input = Cursor::new(framed);
+        assert_eq!(read_message(&mut input).unwrap().unwrap()["command"], "threads");
+        assert_eq!(read_message(&mut input).unwrap().unwrap()["event"], "output");
+        assert!(read_message(&mut input).unwrap().is_none());
+
+        let error = read_message(&mut Cursor::new(b"Content-Type: json\r\n\r\n{}".to_vec())).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,454 @@
+use crate::protocol::{Connection, Request};
+use gard_ast::{Node, SourceMap};
+use gard_interp::debugger::{DebugConfig, PauseReason, PausedState};
+use gard_interp::{DebugHandler, Debugger, Interpreter, RuntimeError, StepCommand, Value};
+use gard_lexer::Lexer;
+use gard_parser::{GardParser, GardParserTrait};
+use serde_json::{json, Value as Json};
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+/// The interpreter is single threaded, so every program runs as this thread.
+const THREAD_ID: i64 = 1;
+
+/// Serves one debugging session. Requests are handled on the calling
+/// thread; while the program runs the server only listens again when it
+/// pauses, so there is no `pause` request.
+pub struct Server {
+    connection: Rc<RefCell<Connection>>,
+    config: DebugConfig,
+    program: Option<(Node, SourceMap)>,
+    stop_on_entry: bool,
+    no_debug: bool,
+    disconnected: Rc<Cell<bool>>,
+}
+
+impl Server {
+    pub fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            connection: Rc::new(RefCell::new(Connection::new(input, output))),
+            config: DebugConfig::default(),
+            program: None,
+            stop_on_entry: false,
+            no_debug: false,
+            disconnected: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Loads an already parsed program, as `launch` does with a file.
+    pub fn load(&mut self, program: Node, source_map: SourceMap) {
+        self.program = Some((program, source_map));
+    }
+
+    /// Handles requests until the client disconnects or closes the input.
+    pub fn serve(mut self) -> io::Result<()> {
+        while !self.disconnected.get() {
+            let Some(request) = self.connection.borrow_mut().recv()? else {
+                break;
+            };
+
+            let result = match request.command.as_str() {
+                "initialize" => {
+                    self.respond(&request, Ok(capabilities()))?;
+                    self.event("initialized", json!({}))?;
+                    continue;
+                },
+                "configurationDone" => {
+                    if self.program.is_none() {
+                        Err("No program has been launched".to_string())
+                    } else {
+                        self.respond(&request, Ok(Json::Null))?;
+                        self.run()?;
+                        continue;
+                    }
+                },
+                "launch" => self.launch(&request.arguments).map(|()| Json::Null),
+                "setBreakpoints" => set_breakpoints(&mut self.config, &request.arguments),
+                "threads" => Ok(threads()),
+                "disconnect" => {
+                    self.disconnected.set(true);
+                    Ok(Json::Null)
+                },
+                command => Err(format!("Unsupported request {}", command)),
+            };
+            self.respond(&request, result)?;
+        }
+        Ok(())
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<(), String> {
+        let path = arguments["program"].as_str()
+            .ok_or_else(|| "launch needs a program".to_string())?;
+        let source = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let tokens = Lexer::new(&source).tokenize().map_err(|e| format!("{}: {}", path, e))?;
+        let program = GardParser::parse(tokens).map_err(|errors| format!("{}: {:?}", path, errors))?;
+
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.no_debug = arguments["noDebug"].as_bool().unwrap_or(false);
+        self.load(program, SourceMap::new(source_path(path), &source));
+        Ok(())
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let Some((program, source_map)) = self.program.take() else {
+            return Ok(());
+        };
+
+        let output = ConsoleOutput(self.connection.clone());
+        let mut interpreter = Interpreter::new().with_output(output).with_source_map(source_map);
+        if !self.no_debug {
+            let session = Session {
+                connection: self.connection.clone(),
+                disconnected: self.disconnected.clone(),
+            };
+            let mut debugger = Debugger::new(session);
+            *debugger.config_mut() = self.config.clone();
+            if !self.stop_on_entry {
+                debugger = debugger.without_stop_on_entry();
+            }
+            interpreter.attach(debugger);
+        }
+
+        let exit_code = match interpreter.run(&program) {
+            Ok(_) | Err(RuntimeError::Terminated) => 0,
+            Err(error) => {
+                self.event("output", json!({ "category": "stderr", "output": format!("{}\n", error) }))?;
+                1
+            },
+        };
+        if !self.disconnected.get() {
+            self.event("exited", json!({ "exitCode": exit_code }))?;
+            self.event("terminated", json!({}))?;
+        }
+        Ok(())
+    }
+
+    fn respond(&self, request: &Request, result: Result<Json, String>) -> io::Result<()> {
+        self.connection.borrow_mut().respond(request, result)
+    }
+
+    fn event(&self, event: &str, body: Json) -> io::Result<()> {
+        self.connection.borrow_mut().event(event, body)
+    }
+}
+
+/// Answers requests while the program is paused.
+struct Session {
+    connection: Rc<RefCell<Connection>>,
+    disconnected: Rc<Cell<bool>>,
+}
+
+impl Session {
+    fn pause(&mut self, state: &PausedState, config: &mut DebugConfig) -> io::Result<StepCommand> {
+        let mut connection = self.connection.borrow_mut();
+        let reason = match state.reason {
+            PauseReason::Entry => "entry",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::Step => "step",
+        };
+        connection.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }))?;
+
+        loop {
+            let Some(request) = connection.recv()? else {
+                return Ok(StepCommand::Terminate);
+            };
+
+            let command = match request.command.as_str() {
+                "continue" => Some(StepCommand::Continue),
+                "next" => Some(StepCommand::StepOver),
+                "stepIn" => Some(StepCommand::StepInto),
+                "stepOut" => Some(StepCommand::StepOut),
+                "terminate" => Some(StepCommand::Terminate),
+                "disconnect" => {
+                    self.disconnected.set(true);
+                    Some(StepCommand::Terminate)
+                },
+                _ => None,
+            };
+            if let Some(command) = command {
+                let body = if command == StepCommand::Continue { json!({ "allThreadsContinued": true }) } else { Json::Null };
+                connection.respond(&request, Ok(body))?;
+                return Ok(command);
+            }
+
+            let result = match request.command.as_str() {
+                "threads" => Ok(threads()),
+                "stackTrace" => Ok(stack_trace(state)),
+                "scopes" => scopes(state, &request.arguments),
+                "variables" => variables(state, &request.arguments),
+                "evaluate" => evaluate(state, &request.arguments),
+                "setBreakpoints" => set_breakpoints(config, &request.arguments),
+                command => Err(format!("Unsupported request {}", command)),
+            };
+            connection.respond(&request, result)?;
+        }
+    }
+}
+
+impl DebugHandler for Session {
+    fn paused(&mut self, state: &PausedState, config: &mut DebugConfig) -> StepCommand {
+        // A broken connection can't resume the program
+        self.pause(state, config).unwrap_or(StepCommand::Terminate)
+    }
+}
+
+/// Sends program output to the editor's debug console.
+struct ConsoleOutput(Rc<RefCell<Connection>>);
+
+impl Write for ConsoleOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let output = String::from_utf8_lossy(buf);
+        self.0.borrow_mut().event("output", json!({ "category": "stdout", "output": output }))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn capabilities() -> Json {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsEvaluateForHovers": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+fn threads() -> Json {
+    json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })
+}
+
+/// Breakpoints are keyed by canonical path so the editor's paths match the
+/// launched program's.
+fn source_path(path: &str) -> String {
+    fs::canonicalize(path).map_or_else(|_| path.to_string(), |path| path.display().to_string())
+}
+
+fn set_breakpoints(config: &mut DebugConfig, arguments: &Json) -> Result<Json, String> {
+    let path = arguments["source"]["path"].as_str()
+        .ok_or_else(|| "setBreakpoints needs a source path".to_string())?;
+    let file = source_path(path);
+
+    config.clear_breakpoints(&file);
+    let breakpoints: Vec<Json> = arguments["breakpoints"].as_array().into_iter().flatten()
+        .filter_map(|breakpoint| breakpoint["line"].as_u64())
+        .map(|line| {
+            config.add_breakpoint(&file, line as usize);
+            json!({ "verified": true, "line": line })
+        })
+        .collect();
+    Ok(json!({ "breakpoints": breakpoints }))
+}
+
+fn stack_trace(state: &PausedState) -> Json {
+    let frames: Vec<Json> = state.frames.iter().enumerate()
+        .map(|(id, frame)| {
+            let (line, column) = frame.location.map_or((0, 0), |location| (location.line, location.column));
+            json!({
+                "id": id,
+                "name": frame.function,
+                "source": { "path": state.file },
+                "line": line,
+                "column": column,
+            })
+        })
+        .collect();
+    json!({ "stackFrames": frames, "totalFrames": state.frames.len() })
+}
+
+/// Each frame has one scope; its variables reference is the frame id plus
+/// one, since zero means "not expandable".
+fn scopes(state: &PausedState, arguments: &Json) -> Result<Json, String> {
+    let id = frame_id(state, &arguments["frameId"])?;
+    let name = if id + 1 == state.frames.len() { "Globals" } else { "Locals" };
+    Ok(json!({ "scopes": [{ "name": name, "variablesReference": id + 1, "expensive": false }] }))
+}
+
+fn variables(state: &PausedState, arguments: &Json) -> Result<Json, String> {
+    let reference = arguments["variablesReference"].as_u64().unwrap_or(0) as usize;
+    let frame = reference.checked_sub(1)
+        .and_then(|id| state.frames.get(id))
+        .ok_or_else(|| format!("Unknown variables reference {}", reference))?;
+    let variables: Vec<Json> = frame.locals.iter()
+        .map(|(name, value)| variable_json(name, value))
+        .collect();
+    Ok(json!({ "variables": variables }))
+}
+
+/// Only variable names can be evaluated: the selected frame's locals, then
+/// globals.
+fn evaluate(state: &PausedState, arguments: &Json) -> Result<Json, String> {
+    let expression = arguments["expression"].as_str().unwrap_or("").trim();
+    let id = match &arguments["frameId"] {
+        Json::Null => 0,
+        id => frame_id(state, id)?,
+    };
+    let value = [state.frames.get(id), state.frames.last()].into_iter().flatten()
+        .find_map(|frame| frame.locals.iter().find(|(name, _)| name == expression))
+        .map(|(_, value)| value)
+        .ok_or_else(|| format!("{} is not in scope", expression))?;
+    Ok(json!({ "result": value.to_string(), "type": value.type_name(), "variablesReference": 0 }))
+}
+
+fn frame_id(state: &PausedState, id: &Json) -> Result<usize, String> {
+    id.as_u64()
+        .map(|id| id as usize)
+        .filter(|&id| id < state.frames.len())
+        .ok_or_else(|| format!("Unknown frame {}", id))
+}
+
+fn variable_json(name: &str, value: &Value) -> Json {
+    json!({ "name": name, "value": value.to_string(), "type": value.type_name(), "variablesReference": 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{read_message, write_message};
+    use gard_ast::{Parameter, Span, Type};
+    use std::io::Cursor;
+
+    /// Every line is 10 bytes, so line `n` starts at offset `(n - 1) * 10`.
+    const SOURCE: &str = "function \n  let a  \n  print(a)\n}        \n";
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn at(line: usize, node: Node) -> Node {
+        let start = (line - 1) * 10;
+        Node::Located { span: Span { start, end: start + 9 }, node: Box::new(node) }
+    }
+
+    fn program() -> Node {
+        Node::Program(vec![Node::Function {
+            name: "main".to_string(),
+            params: Vec::<Parameter>::new(),
+            return_type: Type::Void,
+            body: Box::new(Node::Block(vec![
+                at(2, Node::Let {
+                    name: "a".to_string(),
+                    type_annotation: None,
+                    initializer: Some(Box::new(Node::IntLiteral(42))),
+                    is_mutable: false,
+                }),
+                at(3, Node::Call {
+                    callee: Box::new(Node::Identifier("print".into())),
+                    arguments: vec![Node::Identifier("a".into())],
+                }),
+            ])),
+            modifiers: vec![],
+            docs: None,
+        }])
+    }
+
+    /// Runs a session with the given requests and returns everything the
+    /// server sent.
+    fn session(requests: &[(&str, Json)]) -> Vec<Json> {
+        let mut input = Vec::new();
+        for (seq, (command, arguments)) in requests.iter().enumerate() {
+            let request = json!({ "type": "request", "seq": seq + 1, "command": command, "arguments": arguments });
+            write_message(&mut input, &request).unwrap();
+        }
+
+        let output = SharedBuffer::default();
+        let mut server = Server::new(Cursor::new(input), output.clone());
+        server.load(program(), SourceMap::new("main.gard", SOURCE));
+        server.serve().unwrap();
+
+        let mut sent = Cursor::new(output.0.borrow().clone());
+        std::iter::from_fn(|| read_message(&mut sent).unwrap()).collect()
+    }
+
+    fn response<'a>(messages: &'a [Json], command: &str) -> &'a Json {
+        messages.iter()
+            .find(|message| message["type"] == "response" && message["command"] == command)
+            .unwrap()
+    }
+
+    fn events<'a>(messages: &'a [Json], event: &str) -> Vec<&'a Json> {
+        messages.iter().filter(|message| message["event"] == event).collect()
+    }
+
+    #[test]
+    fn test_breakpoint_and_variables() {
+        let messages = session(&[
+            ("initialize", json!({ "adapterID": "gard" })),
+            ("setBreakpoints", json!({ "source": { "path": "main.gard" }, "breakpoints": [{ "line": 3 }] })),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("scopes", json!({ "frameId": 0 })),
+            ("variables", json!({ "variablesReference": 1 })),
+            ("evaluate", json!({ "expression": "a", "frameId": 0 })),
+            ("continue", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ]);
+
+        assert_eq!(response(&messages, "initialize")["body"]["supportsConfigurationDoneRequest"], true);
+        assert_eq!(response(&messages, "setBreakpoints")["body"]["breakpoints"], json!([{ "verified": true, "line": 3 }]));
+        assert_eq!(events(&messages, "stopped")[0]["body"]["reason"], "breakpoint");
+
+        let frames = &response(&messages, "stackTrace")["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "main");
+        assert_eq!(frames[0]["line"], 3);
+        assert_eq!(frames[1]["name"], "<top level>");
+        assert_eq!(response(&messages, "scopes")["body"]["scopes"][0]["name"], "Locals");
+        assert_eq!(
+            response(&messages, "variables")["body"]["variables"],
+            json!([{ "name": "a", "value": "42", "type": "int", "variablesReference": 0 }]),
+        );
+        assert_eq!(response(&messages, "evaluate")["body"]["result"], "42");
+
+        assert_eq!(events(&messages, "output")[0]["body"]["output"], "42\n");
+        assert_eq!(events(&messages, "exited")[0]["body"]["exitCode"], 0);
+        assert_eq!(events(&messages, "terminated").len(), 1);
+        assert_eq!(response(&messages, "disconnect")["success"], true);
+    }
+
+    #[test]
+    fn test_stepping_and_disconnect_while_paused() {
+        let messages = session(&[
+            ("initialize", json!({})),
+            ("configurationDone", json!({})),
+            ("evaluate", json!({ "expression": "b" })),
+            ("disconnect", json!({})),
+            ("threads", json!({})),
+        ]);
+        // Without stopOnEntry or breakpoints the program runs to completion
+        assert!(events(&messages, "stopped").is_empty());
+        assert_eq!(events(&messages, "exited").len(), 1);
+        assert_eq!(response(&messages, "evaluate")["success"], false);
+
+        let mut server_messages = Vec::new();
+        for step in ["next", "stepIn"] {
+            server_messages = session(&[
+                ("launch", json!({ "program": "missing.gard" })),
+                ("initialize", json!({})),
+                ("setBreakpoints", json!({ "source": { "path": "main.gard" }, "breakpoints": [{ "line": 2 }] })),
+                ("configurationDone", json!({})),
+                (step, json!({ "threadId": 1 })),
+                ("disconnect", json!({})),
+                ("threads", json!({})),
+            ]);
+            let stopped: Vec<&Json> = events(&server_messages, "stopped").iter().map(|event| &event["body"]["reason"]).collect();
+            assert_eq!(stopped, vec!["breakpoint", "step"]);
+        }
+
+        assert!(response(&server_messages, "launch")["message"].as_str().unwrap().starts_with("Failed to read missing.gard"));
+        // The program never finished and nothing is handled after disconnecting
+        assert!(events(&server_messages, "exited").is_empty());
+        assert!(server_messages.iter().all(|message| message["command"] != "threads"));
+    }
+}
@@ -0,0 +1,234 @@
+//! Interned strings for the names the pipeline passes around: identifiers,
+//! parameter names and type names. A `Symbol` is a small copyable handle,
+//! so cloning and comparing names costs no allocation or string compare.
+//!
+//! Symbols come from one process-wide `Interner`, so a symbol made on one
+//! thread means the same name on every other. Interned text lives until the
+//! process exits, which suits names taken from source code: there are few
+//! of them and they're needed until compilation ends.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+/// An interned string. Equal symbols have equal text; compare and hash
+/// them by handle, and order them by text.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// A table of interned strings, safe to share between threads.
+#[derive(Default)]
+pub struct Interner {
+    table: RwLock<Table>,
+}
+
+#[derive(Default)]
+struct Table {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The symbol for `text`, adding it to the table the first time.
+    pub fn intern(&self, text: &str) -> Symbol {
+        if let Some(symbol) = self.table.read().unwrap_or_else(PoisonError::into_inner).symbols.get(text) {
+            return *symbol;
+        }
+        let mut table = self.table.write().unwrap_or_else(PoisonError::into_inner);
+        // Another thread may have added it since the read lock was released
+        if let Some(symbol) = table.symbols.get(text) {
+            return *symbol;
+        }
+        let symbol = Symbol(u32::try_from(table.strings.len()).expect("fewer than 2^32 interned strings"));
+        let text: &'static str = Box::leak(text.to_string().into_boxed_str());
+        table.strings.push(text);
+        table.symbols.insert(text, symbol);
+        symbol
+    }
+
+    /// The text of `symbol`, which must have come from this interner.
+    pub fn resolve(&self, symbol: Symbol) -> &'static str {
+        self.table.read().unwrap_or_else(PoisonError::into_inner).strings[symbol.0 as usize]
+    }
+
+    /// How many strings have been interned.
+    pub fn len(&self) -> usize {
+        self.table.read().unwrap_or_else(PoisonError::into_inner).strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The interner every `Symbol` comes from.
+pub fn interner() -> &'static Interner {
+    static INTERNER: OnceLock<Interner> = OnceLock::new();
+    INTERNER.get_or_init(Interner::new)
+}
+
+impl Symbol {
+    pub fn intern(text: &str) -> Self {
+        interner().intern(text)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        interner().resolve(self)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// The text, quoted like a string's, so ASTs print as they did with
+/// `String` names.
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(text: &str) -> Self {
+        Symbol::intern(text)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(text: &String) -> Self {
+        Symbol::intern(text)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(text: String) -> Self {
+        Symbol::intern(&text)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Symbol> for str {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for &str {
+    fn eq(&self, other: &Symbol) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Symbol> for String {
+    fn eq(&self, other: &Symbol) -> bool {
+        self == other.as_str()
+    }
+}
+
+/// As its text, so serialized ASTs don't depend on the order names were
+/// interned in.
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Ok(Symbol::intern(&text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_intern() {
+        let a = Symbol::intern("counter");
+        assert_eq!(a, Symbol::from(String::from("counter")));
+        assert_ne!(a, Symbol::intern("Counter"));
+        assert_eq!(a, "counter");
+        assert_eq!("counter", a);
+        assert_eq!(a.len(), 7);
+        assert_eq!(format!("{} {:?}", a, a), "counter \"counter\"");
+
+        let mut names = vec![Symbol::intern("b"), Symbol::intern("c"), Symbol::intern("a")];
+        names.sort();
+        assert_eq!(names, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_threads() {
+        let interner = Interner::new();
+        let symbols: Vec<Vec<Symbol>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| (0..100).map(|i| interner.intern(&format!("name{}", i))).collect()))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        assert!(symbols.windows(2).all(|pair| pair[0] == pair[1]));
+        assert_eq!(interner.len(), 100);
+        assert_eq!(interner.resolve(symbols[0][42]), "name42");
+    }
+}
@@ -0,0 +1,93 @@
+//! The `bytes` module, called as `bytes.toHex(data)`. Bytes values
+//! concatenate with `+`, index to an int and have a `length`, which the
+//! interpreter handles with the other operators.
+
+use crate::interpreter::RuntimeError;
+use crate::value::Value;
+
+/// Number of arguments `bytes.name` takes, if it's a bytes function.
+fn arity(name: &str) -> Option<usize> {
+    match name {
+        "fromHex" | "toHex" | "fromString" | "toString" => Some(1),
+        "concat" => Some(2),
+        "slice" => Some(3),
+        _ => None,
+    }
+}
+
+/// Calls `bytes.function`.
+pub fn call(function: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let expected = arity(function)
+        .ok_or_else(|| RuntimeError::UndefinedFunction(format!("bytes.{}", function)))?;
+    if arguments.len() != expected {
+        return Err(RuntimeError::ArityMismatch { function: format!("bytes.{}", function), expected, found: arguments.len() });
+    }
+    match (function, arguments.as_slice()) {
+        ("fromHex", [Value::String(text)]) => from_hex(text).map(Value::Bytes),
+        ("toHex", [Value::Bytes(data)]) => Ok(Value::String(data.iter().map(|byte| format!("{:02x}", byte)).collect())),
+        ("fromString", [Value::String(text)]) => Ok(Value::Bytes(text.as_bytes().to_vec())),
+        ("toString", [Value::Bytes(data)]) => String::from_utf8(data.clone())
+            .map(Value::String)
+            .map_err(|e| RuntimeError::InvalidEncoding(format!("Bytes are not UTF-8: {}", e))),
+        ("slice", [Value::Bytes(data), Value::Int(start), Value::Int(end)]) => slice(data, *start, *end),
+        ("concat", [Value::Bytes(left), Value::Bytes(right)]) => Ok(Value::Bytes([left.as_slice(), right].concat())),
+        _ => {
+            let found: Vec<&str> = arguments.iter().map(Value::type_name).collect();
+            Err(RuntimeError::TypeError(format!("bytes.{} can't take {}", function, found.join(", "))))
+        },
+    }
+}
+
+/// Takes an optional `0x` prefix.
+pub(crate) fn from_hex(text: &str) -> Result<Vec<u8>, RuntimeError> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    if !digits.len().is_multiple_of(2) {
+        return Err(RuntimeError::InvalidEncoding(format!("'{}' has an odd number of hex digits", text)));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| digits.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| RuntimeError::InvalidEncoding(format!("'{}' is not hex", text)))
+}
+
+/// Bytes `start` to `end`, excluding `end`. Out of bounds traps like an
+/// index, reporting the first bound that is out.
+fn slice(data: &[u8], start: i64, end: i64) -> Result<Value, RuntimeError> {
+    let length = data.len();
+    let out = |index| RuntimeError::IndexOutOfBounds { index, length, location: None };
+    let start_at = usize::try_from(start).ok().filter(|start| *start <= length).ok_or_else(|| out(start))?;
+    let end_at = usize::try_from(end).ok().filter(|end| (start_at..=length).contains(end)).ok_or_else(|| out(end))?;
+    Ok(Value::Bytes(data[start_at..end_at].to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(data: &[u8]) -> Value {
+        Value::Bytes(data.to_vec())
+    }
+
+    #[test]
+    fn test_bytes_functions() {
+        let text = |value: &str| Value::String(value.to_string());
+        assert_eq!(call("fromHex", vec![text("0xCAfe")]), Ok(bytes(&[0xca, 0xfe])));
+        assert_eq!(call("toHex", vec![bytes(&[0x0a, 0xff])]), Ok(text("0aff")));
+        assert_eq!(call("toString", vec![bytes(b"gard")]), Ok(text("gard")));
+        assert_eq!(call("slice", vec![bytes(b"gard"), Value::Int(1), Value::Int(3)]), Ok(bytes(b"ar")));
+        assert_eq!(call("concat", vec![bytes(b"ga"), bytes(b"rd")]), call("fromString", vec![text("gard")]));
+        assert_eq!(bytes(&[0xca, 0xfe]).to_string(), "0xcafe");
+    }
+
+    #[test]
+    fn test_bytes_errors() {
+        assert!(matches!(call("fromHex", vec![Value::String("abc".to_string())]), Err(RuntimeError::InvalidEncoding(_))));
+        assert!(matches!(call("toString", vec![bytes(&[0xff])]), Err(RuntimeError::InvalidEncoding(_))));
+        assert_eq!(call("slice", vec![bytes(b"gard"), Value::Int(2), Value::Int(5)]),
+            Err(RuntimeError::IndexOutOfBounds { index: 5, length: 4, location: None }));
+        assert_eq!(call("concat", vec![bytes(b"a")]),
+            Err(RuntimeError::ArityMismatch { function: "bytes.concat".to_string(), expected: 2, found: 1 }));
+        assert!(matches!(call("toHex", vec![Value::Int(1)]), Err(RuntimeError::TypeError(_))));
+    }
+}
@@ -0,0 +1,343 @@
+//! Checkpoints of an interpreter's state, saved to disk to resume a program
+//! later: its globals, the program's own task-local values, the messages
+//! waiting in each channel and the step count.
+//!
+//! Tasks are stackful, so a task that has started can't be saved; a
+//! checkpoint is taken between calls, when only the program itself is left.
+//! The functions aren't saved either: a checkpoint is restored into an
+//! interpreter running the program again, after its top-level statements
+//! have run, so its values replace the ones they set.
+//!
+//! The format is text, one entry per line, starting with its version and
+//! ending with a checksum of the rest:
+//!
+//! ```text
+//! gard-checkpoint 1
+//! steps 120
+//! next-task 3
+//! global visits [1, "two", Some(3.0)]
+//! local requestId "a1"
+//! message "jobs" 0 { id: 7 }
+//! checksum 5d1b6e3c0a9f2e41
+//! ```
+
+use crate::value::Value;
+use std::fmt::Write;
+
+/// The version this build writes, and the only one it reads.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checkpoint {
+    pub steps: u64,
+    /// The id the next spawned task gets
+    pub next_task: usize,
+    /// By name, in name order
+    pub globals: Vec<(String, Value)>,
+    /// The values the program itself set its task-locals to
+    pub locals: Vec<(String, Value)>,
+    /// Messages waiting in each channel, with their priorities, in the
+    /// order they'll be received
+    pub messages: Vec<(String, i64, Value)>,
+    /// The function giving each channel's messages their priority
+    pub priorities: Vec<(String, String)>,
+    /// Messages set aside with `stash`, by channel, in the order stashed
+    pub stashes: Vec<(String, Value)>,
+}
+
+impl Checkpoint {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("gard-checkpoint {}\nsteps {}\nnext-task {}\n", VERSION, self.steps, self.next_task);
+        for (name, value) in &self.globals {
+            let _ = writeln!(text, "global {} {}", name, encode(value));
+        }
+        for (name, value) in &self.locals {
+            let _ = writeln!(text, "local {} {}", name, encode(value));
+        }
+        for (channel, priority, message) in &self.messages {
+            let _ = writeln!(text, "message {} {} {}", encode_string(channel), priority, encode(message));
+        }
+        for (channel, function) in &self.priorities {
+            let _ = writeln!(text, "priority {} {}", encode_string(channel), function);
+        }
+        for (channel, message) in &self.stashes {
+            let _ = writeln!(text, "stash {} {}", encode_string(channel), encode(message));
+        }
+        let checksum = checksum(&text);
+        let _ = writeln!(text, "checksum {:016x}", checksum);
+        text
+    }
+
+    /// Reads a checkpoint written by `to_text`, checking its version and
+    /// that it's intact.
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let version = text.lines().next().and_then(|line| line.strip_prefix("gard-checkpoint "))
+            .ok_or("Not a checkpoint: it doesn't start with 'gard-checkpoint'")?;
+        if version != VERSION.to_string() {
+            return Err(format!("Checkpoint version {} isn't supported; this build reads version {}", version, VERSION));
+        }
+        let body_end = text.trim_end().rfind('\n').map_or(0, |end| end + 1);
+        let (body, last) = text.split_at(body_end);
+        let expected = last.trim_end().strip_prefix("checksum ")
+            .and_then(|checksum| u64::from_str_radix(checksum, 16).ok())
+            .ok_or("Checkpoint is truncated: it doesn't end with a checksum")?;
+        if checksum(body) != expected {
+            return Err("Checkpoint is corrupt: its checksum doesn't match".to_string());
+        }
+
+        let mut checkpoint = Checkpoint::default();
+        for (number, line) in body.lines().enumerate().skip(1) {
+            let error = |message: String| format!("Line {}: {}", number + 1, message);
+            let (entry, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let mut reader = Reader { rest };
+            match entry {
+                "steps" => checkpoint.steps = reader.number().map_err(error)?,
+                "next-task" => checkpoint.next_task = reader.number().map_err(error)?,
+                "global" => checkpoint.globals.push((reader.word().map_err(error)?, reader.value().map_err(error)?)),
+                "local" => checkpoint.locals.push((reader.word().map_err(error)?, reader.value().map_err(error)?)),
+                "message" => {
+                    let channel = reader.string().map_err(error)?;
+                    let priority = reader.number().map_err(error)?;
+                    checkpoint.messages.push((channel, priority, reader.value().map_err(error)?));
+                },
+                "priority" => checkpoint.priorities.push((reader.string().map_err(error)?, reader.word().map_err(error)?)),
+                "stash" => checkpoint.stashes.push((reader.string().map_err(error)?, reader.value().map_err(error)?)),
+                _ => return Err(error(format!("unknown entry '{}'", entry))),
+            }
+            reader.end().map_err(error)?;
+        }
+        Ok(checkpoint)
+    }
+}
+
+/// FNV-1a, which, unlike std's hasher, every build agrees on.
+fn checksum(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Like the display form, but with strings quoted and every variant given
+/// its parentheses, so it reads back as the same value.
+fn encode(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(value) => value.to_string(),
+        Value::Int(value) => value.to_string(),
+        // `{:?}` always writes a point, an exponent, `NaN` or `inf`, which
+        // tell floats from ints
+        Value::Float(value) => format!("{:?}", value),
+        Value::String(value) => encode_string(value),
+        Value::Bytes(_) => value.to_string(),
+        Value::Array(elements) => format!("[{}]", encode_all(elements)),
+        Value::Object(fields) if fields.is_empty() => "{}".to_string(),
+        Value::Object(fields) => {
+            let fields: Vec<String> = fields.iter().map(|(name, value)| format!("{}: {}", name, encode(value))).collect();
+            format!("{{ {} }}", fields.join(", "))
+        },
+        Value::Variant { name, payload } => format!("{}({})", name, encode_all(payload)),
+    }
+}
+
+fn encode_all(values: &[Value]) -> String {
+    values.iter().map(encode).collect::<Vec<_>>().join(", ")
+}
+
+fn encode_string(value: &str) -> String {
+    let mut encoded = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            '\n' => encoded.push_str("\\n"),
+            '\r' => encoded.push_str("\\r"),
+            '\t' => encoded.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(encoded, "\\u{{{:x}}}", c as u32);
+            },
+            c => encoded.push(c),
+        }
+    }
+    encoded.push('"');
+    encoded
+}
+
+/// Reads the values of one line, in order.
+struct Reader<'a> {
+    rest: &'a str,
+}
+
+impl Reader<'_> {
+    fn end(&self) -> Result<(), String> {
+        match self.rest.trim() {
+            "" => Ok(()),
+            rest => Err(format!("unexpected '{}'", rest)),
+        }
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        self.rest = self.rest.trim_start();
+        let end = self.rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(self.rest.len());
+        if end == 0 {
+            return Err(format!("expected a name, found '{}'", self.rest));
+        }
+        let (word, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Ok(word.to_string())
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, String> {
+        self.rest = self.rest.trim_start();
+        let end = self.rest.find(' ').unwrap_or(self.rest.len());
+        let (number, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        number.parse().map_err(|_| format!("expected a number, found '{}'", number))
+    }
+
+    /// Skips `token` if it's next.
+    fn eat(&mut self, token: char) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: char) -> Result<(), String> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(format!("expected '{}', found '{}'", token, self.rest)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(string);
+                },
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => {
+                        let code: String = chars.by_ref().map(|(_, c)| c).skip(1).take_while(|&c| c != '}').collect();
+                        let c = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape '\\u{{{}}}'", code))?;
+                        string.push(c);
+                    },
+                    Some(c) => string.push(c),
+                    None => break,
+                },
+                c => string.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+
+    /// Values separated by commas up to `close`.
+    fn values(&mut self, close: char) -> Result<Vec<Value>, String> {
+        let mut values = Vec::new();
+        while !self.eat(close) {
+            if !values.is_empty() {
+                self.expect(',')?;
+            }
+            values.push(self.value()?);
+        }
+        Ok(values)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.rest = self.rest.trim_start();
+        if self.rest.starts_with('"') {
+            return self.string().map(Value::String);
+        }
+        if self.eat('[') {
+            return self.values(']').map(Value::Array);
+        }
+        if self.eat('{') {
+            let mut fields = Vec::new();
+            while !self.eat('}') {
+                if !fields.is_empty() {
+                    self.expect(',')?;
+                }
+                let name = self.word()?;
+                self.expect(':')?;
+                fields.push((name, self.value()?));
+            }
+            return Ok(Value::Object(fields));
+        }
+        if let Some(hex) = self.rest.strip_prefix("0x") {
+            let end = hex.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(hex.len());
+            let (digits, rest) = hex.split_at(end);
+            self.rest = rest;
+            return crate::bytes::from_hex(digits).map(Value::Bytes).map_err(|e| e.to_string());
+        }
+        let end = self.rest.find([' ', ',', ']', '}', ')', '(']).unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        match word {
+            "null" => Ok(Value::Null),
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ if self.eat('(') => Ok(Value::Variant { name: word.to_string(), payload: self.values(')')? }),
+            _ => word.parse().map(Value::Int)
+                .or_else(|_| word.parse().map(Value::Float))
+                .map_err(|_| format!("expected a value, found '{}'", word)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Checkpoint {
+        Checkpoint {
+            steps: 120,
+            next_task: 3,
+            globals: vec![
+                ("visits".to_string(), Value::Array(vec![
+                    Value::Int(1),
+                    Value::String("two \"2\"\n\u{1b}".to_string()),
+                    Value::Variant { name: "Some".to_string(), payload: vec![Value::Float(3.0)] },
+                    Value::Variant { name: "None".to_string(), payload: vec![] },
+                ])),
+                ("seen".to_string(), Value::Object(vec![("at".to_string(), Value::Float(f64::INFINITY)), ("ok".to_string(), Value::Bool(true))])),
+                ("key".to_string(), Value::Bytes(vec![0xde, 0xad])),
+            ],
+            locals: vec![("requestId".to_string(), Value::Null)],
+            messages: vec![("jobs queue".to_string(), -2, Value::Object(vec![]))],
+            priorities: vec![("jobs queue".to_string(), "rank".to_string())],
+            stashes: vec![("jobs queue".to_string(), Value::Array(vec![]))],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = sample().to_text();
+        assert!(text.starts_with("gard-checkpoint 1\nsteps 120\nnext-task 3\nglobal visits [1, \"two \\\"2\\\"\\n\\u{1b}\", Some(3.0), None()]\n"));
+        assert_eq!(Checkpoint::from_text(&text), Ok(sample()));
+    }
+
+    #[test]
+    fn test_integrity() {
+        let text = sample().to_text();
+        assert_eq!(
+            Checkpoint::from_text(&text.replace("steps 120", "steps 121")),
+            Err("Checkpoint is corrupt: its checksum doesn't match".to_string()),
+        );
+        let truncated = &text[..text.find("local").unwrap()];
+        assert_eq!(Checkpoint::from_text(truncated), Err("Checkpoint is truncated: it doesn't end with a checksum".to_string()));
+        assert_eq!(
+            Checkpoint::from_text(&text.replace("gard-checkpoint 1", "gard-checkpoint 2")),
+            Err("Checkpoint version 2 isn't supported; this build reads version 1".to_string()),
+        );
+        assert!(Checkpoint::from_text("steps 1").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! `std.datetime`, called as `datetime.now()`. Instants and date-times are
+//! ints counting milliseconds since the Unix epoch in UTC and durations are
+//! ints counting milliseconds, like in native code.
+
+use crate::interpreter::RuntimeError;
+use crate::value::Value;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Calls `datetime.function`.
+pub fn call(function: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let scale = match function {
+        "now" => {
+            if !arguments.is_empty() {
+                return Err(arity(function, 0, arguments.len()));
+            }
+            let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            return Ok(Value::Int(elapsed.as_millis() as i64));
+        },
+        "parse" => return match arguments.as_slice() {
+            [Value::String(text)] => parse(text).map(Value::Int),
+            [other] => Err(RuntimeError::TypeError(format!("datetime.parse expects a string, found {}", other.type_name()))),
+            _ => Err(arity(function, 1, arguments.len())),
+        },
+        "format" => return format(int_argument(function, &arguments)?).map(Value::String),
+        "toDateTime" | "milliseconds" => 1,
+        "fromTimestamp" | "seconds" => 1_000,
+        "minutes" => 60_000,
+        "hours" => 3_600_000,
+        "days" => 86_400_000,
+        function => return Err(RuntimeError::UndefinedFunction(format!("datetime.{}", function))),
+    };
+    int_argument(function, &arguments)?.checked_mul(scale)
+        .map(Value::Int)
+        .ok_or(RuntimeError::Overflow)
+}
+
+/// Parses an RFC 3339 date-time, such as `2024-06-01T12:30:00+02:00`, or a
+/// date alone, which is midnight UTC.
+fn parse(text: &str) -> Result<i64, RuntimeError> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.timestamp_millis());
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc().timestamp_millis())
+        .map_err(|e| RuntimeError::InvalidDateTime(format!("'{}': {}", text, e)))
+}
+
+fn format(millis: i64) -> Result<String, RuntimeError> {
+    DateTime::<Utc>::from_timestamp_millis(millis)
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        .ok_or_else(|| RuntimeError::InvalidDateTime(format!("{} ms is outside the supported range", millis)))
+}
+
+fn int_argument(function: &str, arguments: &[Value]) -> Result<i64, RuntimeError> {
+    match arguments {
+        [Value::Int(value)] => Ok(*value),
+        [other] => Err(RuntimeError::TypeError(format!("datetime.{} expects an int, found {}", function, other.type_name()))),
+        _ => Err(arity(function, 1, arguments.len())),
+    }
+}
+
+fn arity(function: &str, expected: usize, found: usize) -> RuntimeError {
+    RuntimeError::ArityMismatch { function: format!("datetime.{}", function), expected, found }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datetime_functions() {
+        assert_eq!(call("hours", vec![Value::Int(2)]), Ok(Value::Int(7_200_000)));
+        assert_eq!(call("fromTimestamp", vec![Value::Int(86_401)]), call("parse", vec![Value::String("1970-01-02T00:00:01Z".to_string())]));
+        assert_eq!(call("format", vec![Value::Int(1_500)]), Ok(Value::String("1970-01-01T00:00:01.500Z".to_string())));
+        assert_eq!(call("parse", vec![Value::String("2024-06-01T02:00:00+02:00".to_string())]), call("parse", vec![Value::String("2024-06-01".to_string())]));
+        assert!(matches!(call("now", vec![]), Ok(Value::Int(millis)) if millis > 1_700_000_000_000));
+    }
+
+    #[test]
+    fn test_datetime_errors() {
+        assert!(matches!(call("parse", vec![Value::String("soon".to_string())]), Err(RuntimeError::InvalidDateTime(_))));
+        assert_eq!(call("days", vec![Value::Int(i64::MAX)]), Err(RuntimeError::Overflow));
+        assert_eq!(call("seconds", vec![]),
+            Err(RuntimeError::ArityMismatch { function: "datetime.seconds".to_string(), expected: 1, found: 0 }));
+        assert_eq!(call("weeks", vec![Value::Int(1)]), Err(RuntimeError::UndefinedFunction("datetime.weeks".to_string())));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::value::Value;
+use gard_ast::Location;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Breakpoint {
+    pub file: String,
+    pub line: usize,
+}
+
+/// How to continue after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepCommand {
+    /// Run until the next breakpoint
+    Continue,
+    /// Pause at the next statement, entering calls
+    StepInto,
+    /// Pause at the next statement in the current function or its callers
+    StepOver,
+    /// Pause at the next statement after the current function returns
+    StepOut,
+    /// Stop the program with `RuntimeError::Terminated`
+    Terminate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    /// Statement the frame is executing, if it has reached a located one
+    pub location: Option<Location>,
+    /// Visible variables by name; inner scopes shadow outer ones
+    pub locals: Vec<(String, Value)>,
+}
+
+/// Snapshot of the program handed to a `DebugHandler` when it pauses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PausedState {
+    pub file: String,
+    pub location: Location,
+    pub reason: PauseReason,
+    /// Innermost frame first; the last one is the top level
+    pub frames: Vec<StackFrame>,
+    /// Watched variables and their value, `None` where one isn't in scope
+    pub watches: Vec<(String, Option<Value>)>,
+}
+
+/// Breakpoints and watches; handlers may change them while paused.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugConfig {
+    breakpoints: BTreeSet<Breakpoint>,
+    watches: Vec<String>,
+}
+
+impl DebugConfig {
+    pub fn add_breakpoint(&mut self, file: &str, line: usize) -> bool {
+        self.breakpoints.insert(Breakpoint { file: file.to_string(), line })
+    }
+
+    pub fn remove_breakpoint(&mut self, file: &str, line: usize) -> bool {
+        self.breakpoints.remove(&Breakpoint { file: file.to_string(), line })
+    }
+
+    pub fn clear_breakpoints(&mut self, file: &str) {
+        self.breakpoints.retain(|breakpoint| breakpoint.file != file);
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints.iter()
+    }
+
+    pub fn has_breakpoint(&self, file: &str, line: usize) -> bool {
+        self.breakpoints.iter().any(|breakpoint| breakpoint.line == line && breakpoint.file == file)
+    }
+
+    pub fn add_watch(&mut self, name: &str) {
+        if !self.watches.iter().any(|watch| watch == name) {
+            self.watches.push(name.to_string());
+        }
+    }
+
+    pub fn remove_watch(&mut self, name: &str) -> bool {
+        let before = self.watches.len();
+        self.watches.retain(|watch| watch != name);
+        self.watches.len() != before
+    }
+
+    pub fn watches(&self) -> &[String] {
+        &self.watches
+    }
+}
+
+/// Front end of a debugging session, e.g. the `gard debug` TUI.
+pub trait DebugHandler {
+    /// Called whenever execution pauses; blocks until the user decides how
+    /// to continue.
+    fn paused(&mut self, state: &PausedState, config: &mut DebugConfig) -> StepCommand;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Entry,
+    Run,
+    StepInto,
+    /// Call depth at which the step started
+    StepOver(usize),
+    StepOut(usize),
+}
+
+/// Decides where a program attached with `Interpreter::attach` pauses. The
+/// interpreter checks it before every located statement.
+pub struct Debugger {
+    config: DebugConfig,
+    handler: Box<dyn DebugHandler>,
+    mode: Mode,
+}
+
+impl Debugger {
+    /// Pauses before the first statement so breakpoints can be set.
+    pub fn new(handler: impl DebugHandler + 'static) -> Self {
+        Self {
+            config: DebugConfig::default(),
+            handler: Box::new(handler),
+            mode: Mode::Entry,
+        }
+    }
+
+    /// Runs until the first breakpoint instead of pausing on entry.
+    pub fn without_stop_on_entry(mut self) -> Self {
+        self.mode = Mode::Run;
+        self
+    }
+
+    pub fn config(&self) -> &DebugConfig {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut DebugConfig {
+        &mut self.config
+    }
+
+    /// Whether to pause at a statement on `line` of `file` with `depth`
+    /// functions on the stack.
+    pub(crate) fn pause_reason(&self, file: &str, line: usize, depth: usize) -> Option<PauseReason> {
+        let stepped = match self.mode {
+            Mode::Entry => return Some(PauseReason::Entry),
+            Mode::Run => false,
+            Mode::StepInto => true,
+            Mode::StepOver(start) => depth <= start,
+            Mode::StepOut(start) => depth < start,
+        };
+
+        if self.config.has_breakpoint(file, line) {
+            Some(PauseReason::Breakpoint)
+        } else if stepped {
+            Some(PauseReason::Step)
+        } else {
+            None
+        }
+    }
+
+    /// Hands the pause to the handler and applies its command. Returns
+    /// false if the program should terminate.
+    pub(crate) fn pause(&mut self, state: &PausedState, depth: usize) -> bool {
+        self.mode = match self.handler.paused(state, &mut self.config) {
+            StepCommand::Continue => Mode::Run,
+            StepCommand::StepInto => Mode::StepInto,
+            StepCommand::StepOver => Mode::StepOver(depth),
+            StepCommand::StepOut => Mode::StepOut(depth),
+            StepCommand::Terminate => return false,
+        };
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, RuntimeError};
+    use gard_ast::{BinaryOp, Node, Parameter, SourceMap, Span, Type};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    /// Every line is 10 bytes, so line `n` starts at offset `(n - 1) * 10`.
+    const SOURCE: &str = "function \n  let r  \n  return \n}        \nfunction \n  let a  \n  let b  \n  print(b)\n}        \n";
+
+    struct Script {
+        commands: VecDeque<StepCommand>,
+        pauses: Rc<RefCell<Vec<PausedState>>>,
+    }
+
+    impl DebugHandler for Script {
+        fn paused(&mut self, state: &PausedState, _config: &mut DebugConfig) -> StepCommand {
+            self.pauses.borrow_mut().push(state.clone());
+            self.commands.pop_front().unwrap_or(StepCommand::Continue)
+        }
+    }
+
+    fn at(line: usize, node: Node) -> Node {
+        let start = (line - 1) * 10;
+        Node::Located { span: Span { start, end: start + 9 }, node: Box::new(node) }
+    }
+
+    fn ident(name: &str) -> Box<Node> {
+        Box::new(Node::Identifier(name.into()))
+    }
+
+    fn let_(name: &str, initializer: Node) -> Node {
+        Node::Let {
+            name: name.to_string(),
+            type_annotation: None,
+            initializer: Some(Box::new(initializer)),
+            is_mutable: false,
+        }
+    }
+
+    fn call(name: &str, argument: &str) -> Node {
+        Node::Call { callee: ident(name), arguments: vec![Node::Identifier(argument.into())] }
+    }
+
+    fn function(name: &str, params: &[&str], body: Vec<Node>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: params.iter()
+                .map(|name| Parameter { name: (*name).into(), type_annotation: Type::Int })
+                .collect(),
+            return_type: Type::Int,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn program() -> Node {
+        Node::Program(vec![
+            function("double", &["n"], vec![
+                at(2, let_("r", Node::Binary { left: ident("n"), operator: BinaryOp::Mul, right: Box::new(Node::IntLiteral(2)) })),
+                at(3, Node::Return(Some(ident("r")))),
+            ]),
+            function("main", &[], vec![
+                at(6, let_("a", Node::IntLiteral(1))),
+                at(7, let_("b", call("double", "a"))),
+                at(8, call("print", "b")),
+            ]),
+        ])
+    }
+
+    fn debug(debugger: impl FnOnce(Debugger) -> Debugger, commands: &[StepCommand]) -> (Result<Value, RuntimeError>, Vec<PausedState>) {
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        let script = Script { commands: commands.iter().copied().collect(), pauses: pauses.clone() };
+        let mut interpreter = Interpreter::new().with_source_map(SourceMap::new("main.gard", SOURCE));
+        interpreter.attach(debugger(Debugger::new(script)));
+        let result = interpreter.run(&program());
+        let pauses = pauses.borrow().clone();
+        (result, pauses)
+    }
+
+    fn lines(pauses: &[PausedState]) -> Vec<usize> {
+        pauses.iter().map(|state| state.location.line).collect()
+    }
+
+    #[test]
+    fn test_step_into_and_out() {
+        let commands = [StepCommand::StepInto, StepCommand::StepInto, StepCommand::StepOut, StepCommand::Continue];
+        let (result, pauses) = debug(|debugger| debugger, &commands);
+        assert!(result.is_ok());
+        assert_eq!(lines(&pauses), vec![6, 7, 2, 8]);
+        assert_eq!(pauses[0].reason, PauseReason::Entry);
+        assert_eq!(pauses[2].frames[0].function, "double");
+        assert_eq!(pauses[3].frames[0].function, "main");
+    }
+
+    #[test]
+    fn test_step_over() {
+        let commands = [StepCommand::StepOver, StepCommand::StepOver, StepCommand::StepOver];
+        let (result, pauses) = debug(|debugger| debugger, &commands);
+        assert!(result.is_ok());
+        assert_eq!(lines(&pauses), vec![6, 7, 8]);
+        assert!(pauses[1..].iter().all(|state| state.reason == PauseReason::Step));
+    }
+
+    #[test]
+    fn test_breakpoint_stack_and_watches() {
+        let (result, pauses) = debug(
+            |mut debugger| {
+                debugger.config_mut().add_breakpoint("main.gard", 3);
+                debugger.config_mut().add_watch("r");
+                debugger.config_mut().add_watch("b");
+                debugger.without_stop_on_entry()
+            },
+            &[StepCommand::Terminate],
+        );
+        assert_eq!(result, Err(RuntimeError::Terminated));
+        assert_eq!(pauses.len(), 1);
+
+        let state = &pauses[0];
+        assert_eq!(state.reason, PauseReason::Breakpoint);
+        assert_eq!(state.location, Location { line: 3, column: 1 });
+        let functions: Vec<&str> = state.frames.iter().map(|frame| frame.function.as_str()).collect();
+        assert_eq!(functions, vec!["double", "main", "<top level>"]);
+        assert_eq!(state.frames[0].locals, vec![("n".to_string(), Value::Int(1)), ("r".to_string(), Value::Int(2))]);
+        assert_eq!(state.frames[1].location.map(|location| location.line), Some(7));
+        assert_eq!(state.frames[1].locals, vec![("a".to_string(), Value::Int(1))]);
+        assert_eq!(state.watches, vec![("r".to_string(), Some(Value::Int(2))), ("b".to_string(), None)]);
+    }
+}
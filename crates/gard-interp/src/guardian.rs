@@ -0,0 +1,71 @@
+//! The root guardian, which supervises the tasks the program spawns itself:
+//! when one fails, its policy decides whether the failure ends the program,
+//! is logged and ignored, or is logged and the task run again from the
+//! start. Tasks spawned by other tasks are supervised by those: a failure
+//! fails the spawning task's scope, as always.
+//!
+//! A restart doesn't undo what the failed run did, like the messages it
+//! sent; it starts the same function with the same arguments, ahead of the
+//! tasks that haven't started.
+
+use crate::interpreter::RuntimeError;
+use crate::sync::TaskId;
+use std::fmt;
+
+/// What the guardian does about one failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Directive {
+    /// Fail the program's scope, and so the program
+    Escalate,
+    /// Let the task end, as if it had succeeded
+    Stop,
+    Restart,
+}
+
+pub type Handler = Box<dyn FnMut(&TaskId, &RuntimeError, u32) -> Directive>;
+
+#[derive(Default)]
+pub enum Guardian {
+    /// Escalates every failure, shutting the program down
+    #[default]
+    Shutdown,
+    /// Stops the failed task
+    Stop,
+    /// Restarts a failed task up to `max_restarts` times, then escalates
+    Restart { max_restarts: u32 },
+    /// Called with the failed task, its error and how often it has been
+    /// restarted
+    Custom(Handler),
+}
+
+impl Guardian {
+    pub(crate) fn decide(&mut self, task: &TaskId, error: &RuntimeError, restarts: u32) -> Directive {
+        match self {
+            Guardian::Shutdown => Directive::Escalate,
+            Guardian::Stop => Directive::Stop,
+            Guardian::Restart { max_restarts } if restarts < *max_restarts => Directive::Restart,
+            Guardian::Restart { .. } => Directive::Escalate,
+            Guardian::Custom(handler) => handler(task, error, restarts),
+        }
+    }
+}
+
+/// A failure the guardian stopped or restarted, which the program's own
+/// result doesn't report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Supervised {
+    pub task: TaskId,
+    pub error: RuntimeError,
+    pub directive: Directive,
+}
+
+impl fmt::Display for Supervised {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match self.directive {
+            Directive::Escalate => "escalated",
+            Directive::Stop => "stopped",
+            Directive::Restart => "restarted",
+        };
+        write!(f, "{} failed and was {}: {}", self.task, outcome, self.error)
+    }
+}
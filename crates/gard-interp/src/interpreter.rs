@@ -0,0 +1,2574 @@
+use crate::bytes;
+use crate::checkpoint::Checkpoint;
+use crate::datetime;
+use crate::debugger::{Debugger, PauseReason, PausedState, StackFrame};
+use crate::guardian::{Directive, Guardian, Supervised};
+use crate::introspect::{Snapshot, TaskInfo, TaskState};
+use crate::io::Streams;
+use crate::metrics::Metrics;
+use crate::reload::{self, ReloadSummary, SourceWatcher};
+use crate::regex::Patterns;
+use crate::replay::{Event, Schedule};
+use crate::stream::{Stage, Stages};
+use crate::strings;
+use crate::sync::{BlockedTask, Deadlock, LockOrderViolation, SyncState, TaskId, TraceFrame, Wait};
+use crate::value::Value;
+use gard_ast::{AssertionKind, BinaryOp, Location, MatchCase, Node, Parameter, SourceMap, Span, UnaryOp};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Calls nested deeper than this fail with `StackOverflow` instead of
+/// overflowing the host stack, which in a browser kills the whole page. An
+/// optimized build needs a few KB of native stack per call, so this fits the
+/// 1 MB wasm stack.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+/// Name of the function `run` calls after the top level has executed.
+pub const ENTRY_POINT: &str = "main";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    UndefinedVariable(String),
+    UndefinedFunction(String),
+    ArityMismatch { function: String, expected: usize, found: usize },
+    TypeError(String),
+    /// An integer division or remainder by zero, at the statement if known
+    DivisionByZero(Option<Location>),
+    /// A member access or index on null
+    NullDereference(Option<Location>),
+    /// `datetime.parse` was given text that isn't ISO-8601, or
+    /// `datetime.format` a time outside chrono's range
+    InvalidDateTime(String),
+    /// A `regex` function was given a pattern that doesn't compile
+    InvalidPattern { pattern: String, reason: String },
+    /// `bytes.fromHex` was given text that isn't hex, or `bytes.toString`
+    /// bytes that aren't UTF-8
+    InvalidEncoding(String),
+    /// An `io` function failed or was given a stream that isn't open
+    Io(String),
+    /// A `checked*` builtin overflowed
+    Overflow,
+    /// `int` arithmetic overflowed with overflow checks on
+    OverflowTrap(Option<Location>),
+    /// An index expression was out of bounds. The interpreter checks every
+    /// index, including ones whose checks were elided for native code.
+    IndexOutOfBounds { index: i64, length: usize, location: Option<Location> },
+    AssertionFailed { kind: AssertionKind, message: Option<String> },
+    Thrown(Value),
+    StackOverflow,
+    StepLimitExceeded(u64),
+    /// `spawn` with no `scope` around it in the function
+    SpawnOutsideScope,
+    Deadlock(Box<Deadlock>),
+    /// `release` of a mutex the task doesn't hold
+    MutexNotHeld(String),
+    /// A replayed run stopped matching the schedule's log
+    ReplayDiverged(String),
+    /// The debugger stopped the program
+    Terminated,
+    Unsupported(String),
+}
+
+impl RuntimeError {
+    /// Whether a `try` block can catch the error. Running out of steps is
+    /// final, otherwise a `try` inside a loop could run forever, and so is a
+    /// deadlock, which involves tasks other than the one catching it, and a
+    /// replay that diverged. Overflow and bounds traps are final like in
+    /// natively compiled code.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            RuntimeError::StepLimitExceeded(_) | RuntimeError::Terminated | RuntimeError::Deadlock(_)
+                | RuntimeError::OverflowTrap(_) | RuntimeError::IndexOutOfBounds { .. } | RuntimeError::ReplayDiverged(_)
+        )
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::UndefinedVariable(name) => write!(f, "Undefined variable: {}", name),
+            RuntimeError::UndefinedFunction(name) => write!(f, "Undefined function: {}", name),
+            RuntimeError::ArityMismatch { function, expected, found } => {
+                write!(f, "{} expects {} arguments, found {}", function, expected, found)
+            },
+            RuntimeError::TypeError(message) => write!(f, "Type error: {}", message),
+            RuntimeError::DivisionByZero(location) => write!(f, "Division by zero{}", at(location)),
+            RuntimeError::NullDereference(location) => write!(f, "Null dereference{}", at(location)),
+            RuntimeError::InvalidDateTime(message) => write!(f, "Invalid date-time {}", message),
+            RuntimeError::InvalidPattern { pattern, reason } => write!(f, "Invalid pattern '{}': {}", pattern, reason),
+            RuntimeError::InvalidEncoding(message) => write!(f, "Invalid encoding: {}", message),
+            RuntimeError::Io(message) => write!(f, "I/O failed: {}", message),
+            RuntimeError::Overflow => write!(f, "Integer overflow"),
+            RuntimeError::OverflowTrap(location) => write!(f, "Integer overflow{}", at(location)),
+            RuntimeError::IndexOutOfBounds { index, length, location } => {
+                write!(f, "Index {} out of bounds for length {}{}", index, length, at(location))
+            },
+            RuntimeError::AssertionFailed { kind, message } => {
+                let label = match kind {
+                    AssertionKind::Validate => "Validation failed",
+                    AssertionKind::Require => "Requirement failed",
+                    AssertionKind::Assert => "Assertion failed",
+                };
+                match message {
+                    Some(message) => write!(f, "{}: {}", label, message),
+                    None => write!(f, "{}", label),
+                }
+            },
+            RuntimeError::Thrown(value) => write!(f, "Uncaught exception: {}", value),
+            RuntimeError::StackOverflow => write!(f, "Stack overflow: more than {} nested calls", MAX_CALL_DEPTH),
+            RuntimeError::StepLimitExceeded(limit) => write!(f, "Step limit of {} exceeded", limit),
+            RuntimeError::SpawnOutsideScope => write!(f, "spawn outside of a scope"),
+            RuntimeError::Deadlock(deadlock) => write!(f, "{}", deadlock),
+            RuntimeError::MutexNotHeld(name) => write!(f, "Mutex '{}' is not held by this task", name),
+            RuntimeError::ReplayDiverged(reason) => write!(f, "Replay diverged: {}", reason),
+            RuntimeError::Terminated => write!(f, "Terminated by the debugger"),
+            RuntimeError::Unsupported(what) => write!(f, "{} is not supported by the interpreter", what),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// ` at line l:c` for an error's location, if it has one.
+fn at(location: &Option<Location>) -> String {
+    location.map(|location| format!(" at line {}:{}", location.line, location.column)).unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Parameter>,
+    pub body: Node,
+}
+
+/// How a statement finished.
+enum Flow {
+    Next,
+    Return(Value),
+    Break,
+    Continue,
+}
+
+struct Frame {
+    function: String,
+    scopes: Vec<HashMap<String, Value>>,
+    location: Option<Location>,
+    /// Id of the task the call runs in
+    task: usize,
+    /// Each open `scope`, innermost last
+    tasks: Vec<TaskScope>,
+}
+
+#[derive(Default)]
+struct TaskScope {
+    /// Tasks spawned that haven't started
+    pending: VecDeque<Task>,
+    /// The first error of a task, which cancels the rest
+    failure: Option<RuntimeError>,
+}
+
+/// A call started by `spawn`.
+#[derive(Clone)]
+struct Task {
+    id: usize,
+    function: String,
+    arguments: Vec<Value>,
+    /// The step it was spawned at
+    spawned_at: u64,
+    /// Id of the task that spawned it
+    parent: usize,
+}
+
+/// A tree-walking interpreter over the AST. Output of `print` is captured
+/// rather than written to stdout so it can be shown by embedders like the
+/// playground.
+pub struct Interpreter {
+    functions: HashMap<String, Rc<Function>>,
+    /// The size of the payload of each union's tags, by name
+    variants: HashMap<String, usize>,
+    /// The first frame is the top level; its outermost scope holds globals.
+    frames: Vec<Frame>,
+    output: String,
+    sink: Option<Box<dyn Write>>,
+    step_limit: Option<u64>,
+    steps: u64,
+    /// Whether `int` arithmetic wraps instead of trapping on overflow
+    wrapping: bool,
+    source_map: Option<SourceMap>,
+    debugger: Option<Debugger>,
+    watcher: Option<Watcher>,
+    sync: SyncState,
+    /// Tasks that have started and not finished, in the order they started;
+    /// the last one is running and each of the others waits for the next
+    running: Vec<TaskId>,
+    /// What each running task but the last is waiting for
+    waiting: HashMap<usize, Wait>,
+    /// The task that spawned each running task
+    parents: HashMap<usize, usize>,
+    /// The initial value of each `tasklocal`, by name
+    task_locals: HashMap<String, Value>,
+    /// The values each running task has set its task-locals to, by task id
+    locals: HashMap<usize, HashMap<String, Value>>,
+    next_task: usize,
+    /// Whether the order tasks start and receive in is recorded or replayed
+    schedule: Schedule,
+    patterns: Patterns,
+    streams: Streams,
+    stages: Stages,
+    metrics: Metrics,
+    exporter: Option<Exporter>,
+    inspector: Option<Inspector>,
+    guardian: Guardian,
+    /// How often each top-level task has been restarted
+    restarts: HashMap<usize, u32>,
+    supervised: Vec<Supervised>,
+    /// Restored once `load` has run the top-level statements
+    resume: Option<Checkpoint>,
+}
+
+type ReloadReport = Box<dyn FnMut(Result<ReloadSummary, String>)>;
+
+/// A hook given the metrics every `interval` steps.
+struct Exporter {
+    interval: u64,
+    export: Box<dyn FnMut(&Metrics)>,
+}
+
+/// A hook given a snapshot of the task runtime every `interval` steps.
+struct Inspector {
+    interval: u64,
+    publish: Box<dyn FnMut(Snapshot)>,
+}
+
+struct Watcher {
+    source: SourceWatcher,
+    report: ReloadReport,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            functions: HashMap::new(),
+            variants: HashMap::new(),
+            frames: vec![Frame { function: "<top level>".to_string(), scopes: vec![HashMap::new()], location: None, task: 0, tasks: vec![] }],
+            output: String::new(),
+            sink: None,
+            step_limit: None,
+            steps: 0,
+            wrapping: false,
+            source_map: None,
+            debugger: None,
+            watcher: None,
+            sync: SyncState::default(),
+            running: vec![TaskId { id: 0, function: ENTRY_POINT.to_string() }],
+            waiting: HashMap::new(),
+            parents: HashMap::new(),
+            task_locals: HashMap::new(),
+            locals: HashMap::new(),
+            next_task: 1,
+            schedule: Schedule::Free,
+            patterns: Patterns::default(),
+            streams: Streams::default(),
+            stages: Stages::default(),
+            metrics: Metrics::default(),
+            exporter: None,
+            inspector: None,
+            guardian: Guardian::default(),
+            restarts: HashMap::new(),
+            supervised: Vec::new(),
+            resume: None,
+        }
+    }
+
+    /// Writes `print` output to `sink` as it happens instead of capturing it.
+    pub fn with_output(mut self, sink: impl Write + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    /// Source the program was parsed from, which gives located statements
+    /// their line numbers. The debugger can only pause with one set.
+    pub fn with_source_map(mut self, source_map: SourceMap) -> Self {
+        self.source_map = Some(source_map);
+        self
+    }
+
+    pub fn attach(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    pub fn detach(&mut self) -> Option<Debugger> {
+        self.debugger.take()
+    }
+
+    /// Reloads the program whenever the watched file changes. The file is
+    /// polled on function calls, so a long loop that calls nothing won't see
+    /// edits. `report` gets the outcome of every reload.
+    pub fn watch(&mut self, source: SourceWatcher, report: impl FnMut(Result<ReloadSummary, String>) + 'static) {
+        self.watcher = Some(Watcher { source, report: Box::new(report) });
+    }
+
+    /// Records the order mutexes are acquired in, and reports cycles in it
+    /// through `lock_order_violations`: deadlocks another schedule of the
+    /// same tasks could run into.
+    pub fn with_debug_runtime(mut self) -> Self {
+        self.sync.order = Some(BTreeMap::new());
+        self
+    }
+
+    pub fn lock_order_violations(&self) -> &[LockOrderViolation] {
+        &self.sync.violations
+    }
+
+    /// Supervises the tasks the program spawns itself with `guardian`
+    /// instead of shutting down when one fails. See `guardian`.
+    pub fn with_guardian(mut self, guardian: Guardian) -> Self {
+        self.guardian = guardian;
+        self
+    }
+
+    /// The failures the guardian stopped or restarted, in the order they
+    /// happened.
+    pub fn supervised(&self) -> &[Supervised] {
+        &self.supervised
+    }
+
+    /// Records the order tasks start in and receive messages in, for
+    /// `recorded_schedule`. See `replay`.
+    pub fn with_recorded_schedule(mut self) -> Self {
+        self.schedule = Schedule::Recording(Vec::new());
+        self
+    }
+
+    /// Starts tasks in the order of a recorded schedule, failing with
+    /// `ReplayDiverged` when the run stops matching it.
+    pub fn with_replayed_schedule(mut self, events: Vec<Event>) -> Self {
+        self.schedule = Schedule::Replaying(events.into());
+        self
+    }
+
+    /// Starts tasks in an order picked at random from `seed`: the same
+    /// order each time for the same program and seed. The order is recorded
+    /// as with `with_recorded_schedule`. See `simulate`.
+    pub fn with_simulated_schedule(mut self, seed: u64) -> Self {
+        self.schedule = Schedule::simulating(seed);
+        self
+    }
+
+    /// The schedule so far, when recording or simulating one.
+    pub fn recorded_schedule(&self) -> &[Event] {
+        self.schedule.recorded()
+    }
+
+    /// Fails the run with `StepLimitExceeded` after `limit` statements and
+    /// calls, so untrusted programs can't hang the host.
+    pub fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    /// Calls `export` with the task runtime's metrics every `interval`
+    /// steps and once more when `run` returns, to push them to a collector.
+    pub fn with_metrics_exporter(mut self, interval: u64, export: impl FnMut(&Metrics) + 'static) -> Self {
+        self.exporter = Some(Exporter { interval: interval.max(1), export: Box::new(export) });
+        self
+    }
+
+    /// Counters and histograms of the task runtime so far.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Calls `publish` with a snapshot of the task runtime every `interval`
+    /// steps and once more when `run` returns, to inspect the program while
+    /// it runs.
+    pub fn with_introspection(mut self, interval: u64, publish: impl FnMut(Snapshot) + 'static) -> Self {
+        self.inspector = Some(Inspector { interval: interval.max(1), publish: Box::new(publish) });
+        self
+    }
+
+    /// Resumes a program from a checkpoint: once `load` has run its
+    /// top-level statements, the checkpoint's values replace theirs.
+    pub fn with_checkpoint(mut self, checkpoint: Checkpoint) -> Self {
+        self.resume = Some(checkpoint);
+        self
+    }
+
+    /// The state to resume the program from later. Only the program itself
+    /// may be running, outside any call or `scope`, holding no mutex.
+    pub fn checkpoint(&self) -> Result<Checkpoint, String> {
+        if self.frames.len() > 1 || !self.frames[0].tasks.is_empty() {
+            return Err("Can't checkpoint while a call or task is running".to_string());
+        }
+        if let Some(mutex) = self.sync.holders.keys().min() {
+            return Err(format!("Can't checkpoint while mutex '{}' is held", mutex));
+        }
+        let by_name = |values: Option<&HashMap<String, Value>>| {
+            let mut values: Vec<(String, Value)> = values.into_iter().flatten()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            values.sort_by(|(a, _), (b, _)| a.cmp(b));
+            values
+        };
+        let mut channels: Vec<&String> = self.sync.channels.keys().chain(self.sync.stashes.keys()).collect();
+        channels.sort();
+        channels.dedup();
+        let mut priorities: Vec<(String, String)> = self.sync.priorities.clone().into_iter().collect();
+        priorities.sort();
+        Ok(Checkpoint {
+            steps: self.steps,
+            next_task: self.next_task,
+            globals: by_name(self.frames[0].scopes.first()),
+            locals: by_name(self.locals.get(&0)),
+            messages: channels.iter()
+                .flat_map(|&channel| self.sync.channels.get(channel).into_iter().flatten()
+                    .map(|(priority, message)| (channel.clone(), *priority, message.clone())))
+                .collect(),
+            priorities,
+            stashes: channels.iter()
+                .flat_map(|&channel| self.sync.stashes.get(channel).into_iter().flatten()
+                    .map(|message| (channel.clone(), message.clone())))
+                .collect(),
+        })
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.steps = checkpoint.steps;
+        self.next_task = self.next_task.max(checkpoint.next_task);
+        self.frames[0].scopes[0].extend(checkpoint.globals);
+        self.locals.entry(0).or_default().extend(checkpoint.locals);
+        self.sync.channels.clear();
+        for (channel, priority, message) in checkpoint.messages {
+            self.sync.channels.entry(channel).or_default().push_back((priority, message));
+        }
+        self.sync.priorities.clear();
+        self.sync.priorities.extend(checkpoint.priorities);
+        self.sync.stashes.clear();
+        for (channel, message) in checkpoint.stashes {
+            self.sync.stashes.entry(channel).or_default().push(message);
+        }
+    }
+
+    /// The task runtime's tasks, channels and mutexes right now.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut tasks: Vec<TaskInfo> = self.running.iter().enumerate()
+            .map(|(i, task)| TaskInfo {
+                task: task.clone(),
+                parent: self.parents.get(&task.id).copied(),
+                state: match self.waiting.get(&task.id) {
+                    Some(wait) if i + 1 < self.running.len() => TaskState::Waiting(wait.clone()),
+                    _ => TaskState::Running,
+                },
+            })
+            .collect();
+        let pending = self.frames.iter().flat_map(|frame| &frame.tasks).flat_map(|scope| &scope.pending);
+        tasks.extend(pending.map(|task| TaskInfo {
+            task: TaskId { id: task.id, function: task.function.clone() },
+            parent: Some(task.parent),
+            state: TaskState::Pending,
+        }));
+        let mut channels: Vec<(String, usize)> = self.sync.channels.iter()
+            .map(|(name, messages)| (name.clone(), messages.len()))
+            .collect();
+        channels.sort();
+        let mut mutexes: Vec<(String, TaskId)> = self.sync.holders.iter()
+            .map(|(name, holder)| (name.clone(), holder.clone()))
+            .collect();
+        mutexes.sort_by(|a, b| a.0.cmp(&b.0));
+        Snapshot {
+            steps: self.steps,
+            tasks,
+            channels,
+            mutexes,
+            tasks_completed: self.metrics.tasks_completed,
+            tasks_failed: self.metrics.tasks_failed,
+        }
+    }
+
+    /// Makes `int` arithmetic wrap on overflow, like a native build without
+    /// overflow checks. By default it traps with `OverflowTrap`.
+    pub fn with_wrapping_arithmetic(mut self) -> Self {
+        self.wrapping = true;
+        self
+    }
+
+    /// Registers the program's functions and executes its top-level
+    /// statements.
+    pub fn load(&mut self, program: &Node) -> Result<(), RuntimeError> {
+        let nodes = match program {
+            Node::Program(nodes) => nodes,
+            _ => return Err(RuntimeError::TypeError("Expected program node".to_string())),
+        };
+
+        for node in nodes {
+            match node {
+                Node::Function { .. } => self.define(node)?,
+                Node::WasmExport { declaration, .. } => self.define(declaration)?,
+                _ => {},
+            }
+        }
+        for node in nodes {
+            match node {
+                Node::Function { .. } | Node::WasmExport { .. } | Node::WasmImport { .. } => {},
+                statement => {
+                    self.exec(statement)?;
+                },
+            }
+        }
+        if let Some(checkpoint) = self.resume.take() {
+            self.restore(checkpoint);
+        }
+        Ok(())
+    }
+
+    /// Swaps in the functions of an edited program without restarting.
+    /// Calls already running finish with the old body and later calls use
+    /// the new one. Globals keep their values; only globals that didn't
+    /// exist yet are initialized, and other top-level statements aren't run
+    /// again.
+    pub fn reload(&mut self, program: &Node) -> Result<ReloadSummary, RuntimeError> {
+        let nodes = match program {
+            Node::Program(nodes) => nodes,
+            _ => return Err(RuntimeError::TypeError("Expected program node".to_string())),
+        };
+
+        let old = std::mem::take(&mut self.functions);
+        for node in nodes {
+            let defined = match node {
+                Node::Function { .. } => self.define(node),
+                Node::WasmExport { declaration, .. } => self.define(declaration),
+                _ => Ok(()),
+            };
+            if let Err(error) = defined {
+                self.functions = old;
+                return Err(error);
+            }
+        }
+
+        let mut summary = ReloadSummary::default();
+        for (name, function) in &self.functions {
+            match old.get(name) {
+                None => summary.added.push(name.clone()),
+                Some(previous) if previous != function => summary.changed.push(name.clone()),
+                Some(_) => {},
+            }
+        }
+        summary.removed = old.into_keys().filter(|name| !self.functions.contains_key(name)).collect();
+        for names in [&mut summary.added, &mut summary.changed, &mut summary.removed] {
+            names.sort();
+        }
+
+        for node in nodes {
+            if let Node::Let { name, .. } = node.unlocated() {
+                if self.global(name).is_none() {
+                    self.exec(node)?;
+                }
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Parses `source` and reloads it; the source map, if any, is updated
+    /// to the new text.
+    pub fn reload_source(&mut self, source: &str) -> Result<ReloadSummary, String> {
+        let program = reload::parse_source(source)?;
+        let summary = self.reload(&program).map_err(|e| e.to_string())?;
+        if let Some(source_map) = &mut self.source_map {
+            *source_map = SourceMap::new(source_map.file.clone(), source);
+        }
+        Ok(summary)
+    }
+
+    /// Loads the program and calls `main` if it defines one.
+    pub fn run(&mut self, program: &Node) -> Result<Value, RuntimeError> {
+        self.load(program)?;
+        let result = if self.functions.contains_key(ENTRY_POINT) {
+            self.call(ENTRY_POINT, Vec::new())
+        } else {
+            Ok(Value::Null)
+        };
+        // A run that failed may well have stopped short of the log
+        let result = result.and_then(|value| self.schedule.finish().map(|_| value).map_err(RuntimeError::ReplayDiverged));
+        self.export_metrics();
+        self.publish_snapshot();
+        result
+    }
+
+    fn export_metrics(&mut self) {
+        if let Some(exporter) = &mut self.exporter {
+            (exporter.export)(&self.metrics);
+        }
+    }
+
+    fn publish_snapshot(&mut self) {
+        if self.inspector.is_some() {
+            let snapshot = self.snapshot();
+            if let Some(inspector) = &mut self.inspector {
+                (inspector.publish)(snapshot);
+            }
+        }
+    }
+
+    pub fn call(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.tick()?;
+        self.poll_watcher();
+        let function = match self.functions.get(name) {
+            Some(function) => Rc::clone(function),
+            None => return self.call_builtin(name, arguments),
+        };
+
+        if arguments.len() != function.params.len() {
+            return Err(RuntimeError::ArityMismatch {
+                function: name.to_string(),
+                expected: function.params.len(),
+                found: arguments.len(),
+            });
+        }
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(RuntimeError::StackOverflow);
+        }
+
+        let scope = function.params.iter()
+            .map(|param| param.name.to_string())
+            .zip(arguments)
+            .collect();
+        let task = self.current_task().id;
+        self.frames.push(Frame { function: name.to_string(), scopes: vec![scope], location: None, task, tasks: vec![] });
+        let result = match &function.body {
+            body @ Node::Block(_) => self.exec(body).map(|flow| match flow {
+                Flow::Return(value) => value,
+                _ => Value::Null,
+            }),
+            expression => self.eval(expression),
+        };
+        self.frames.pop();
+        result
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name).map(|function| function.as_ref())
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.frames[0].scopes[0].get(name)
+    }
+
+    /// Name of the function currently executing, innermost first.
+    pub fn call_stack(&self) -> Vec<&str> {
+        self.frames.iter().rev().map(|frame| frame.function.as_str()).collect()
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    fn define(&mut self, node: &Node) -> Result<(), RuntimeError> {
+        match node {
+            Node::Function { name, params, body, .. } => {
+                self.functions.insert(name.clone(), Rc::new(Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.as_ref().clone(),
+                }));
+                Ok(())
+            },
+            other => Err(RuntimeError::Unsupported(describe(other))),
+        }
+    }
+
+    fn poll_watcher(&mut self) {
+        let Some(mut watcher) = self.watcher.take() else {
+            return;
+        };
+        match watcher.source.poll() {
+            Ok(Some(source)) => (watcher.report)(self.reload_source(&source)),
+            Ok(None) => {},
+            Err(e) => (watcher.report)(Err(format!("Failed to read {}: {}", watcher.source.path().display(), e))),
+        }
+        self.watcher = Some(watcher);
+    }
+
+    fn call_builtin(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        match name {
+            "print" | "println" => {
+                let line: Vec<String> = arguments.iter().map(Value::to_string).collect();
+                let line = line.join(" ") + "\n";
+                write_output(&mut self.sink, &mut self.output, line.as_bytes());
+                Ok(Value::Null)
+            },
+            "acquire" => self.acquire(name_argument(name, &arguments)?),
+            "release" => self.release(name_argument(name, &arguments)?),
+            "send" => match <[Value; 2]>::try_from(arguments) {
+                Ok([Value::String(channel), message]) => {
+                    let priority = match self.sync.priorities.get(&channel).cloned() {
+                        Some(function) => match self.call(&function, vec![message.clone()])? {
+                            Value::Int(priority) => priority,
+                            other => return Err(RuntimeError::TypeError(format!(
+                                "Priority {} must return an int, found {}", function, other.type_name()
+                            ))),
+                        },
+                        None => 0,
+                    };
+                    let depth = self.sync.enqueue(channel, priority, message);
+                    self.metrics.messages_sent += 1;
+                    self.metrics.channel_depth.record(depth as u64);
+                    Ok(Value::Null)
+                },
+                Ok([other, _]) => Err(RuntimeError::TypeError(format!("send expects a channel name, found {}", other.type_name()))),
+                Err(arguments) => Err(RuntimeError::ArityMismatch { function: name.to_string(), expected: 2, found: arguments.len() }),
+            },
+            "receive" => self.receive(name_argument(name, &arguments)?),
+            // `prioritize(channel, function)`: later messages to the channel
+            // are received highest `function(message)` first
+            "prioritize" => match <[Value; 2]>::try_from(arguments) {
+                Ok([Value::String(channel), Value::String(function)]) => {
+                    self.sync.priorities.insert(channel, function);
+                    Ok(Value::Null)
+                },
+                Ok([channel, function]) => Err(RuntimeError::TypeError(format!(
+                    "prioritize expects a channel and a function name, found {} and {}", channel.type_name(), function.type_name()
+                ))),
+                Err(arguments) => Err(RuntimeError::ArityMismatch { function: name.to_string(), expected: 2, found: arguments.len() }),
+            },
+            // `stash(channel, message)` sets a received message aside until
+            // `unstashAll(channel)`, for one the task can't handle yet
+            "stash" => match <[Value; 2]>::try_from(arguments) {
+                Ok([Value::String(channel), message]) => {
+                    self.sync.stashes.entry(channel).or_default().push(message);
+                    Ok(Value::Null)
+                },
+                Ok([other, _]) => Err(RuntimeError::TypeError(format!("stash expects a channel name, found {}", other.type_name()))),
+                Err(arguments) => Err(RuntimeError::ArityMismatch { function: name.to_string(), expected: 2, found: arguments.len() }),
+            },
+            "unstashAll" => {
+                let channel = name_argument(name, &arguments)?;
+                Ok(Value::Int(self.sync.unstash_all(&channel) as i64))
+            },
+            "wrappingAdd" | "wrappingSub" | "wrappingMul" | "checkedAdd" | "checkedSub" | "checkedMul" => {
+                let (wrapping, operation) = match name.strip_prefix("wrapping") {
+                    Some(operation) => (true, operation),
+                    None => (false, &name["checked".len()..]),
+                };
+                let operator = match operation {
+                    "Add" => BinaryOp::Add,
+                    "Sub" => BinaryOp::Sub,
+                    _ => BinaryOp::Mul,
+                };
+                match <[Value; 2]>::try_from(arguments) {
+                    // A checked overflow stays catchable
+                    Ok([left @ Value::Int(_), right @ Value::Int(_)]) => binary(&operator, left, right, wrapping),
+                    Ok([left, right]) => Err(RuntimeError::TypeError(format!(
+                        "{} expects integers, found {} and {}", name, left.type_name(), right.type_name()
+                    ))),
+                    Err(arguments) => Err(RuntimeError::ArityMismatch { function: name.to_string(), expected: 2, found: arguments.len() }),
+                }
+            },
+            _ => Err(RuntimeError::UndefinedFunction(name.to_string())),
+        }
+    }
+
+    /// Turns an overflow of the program's own arithmetic into a trap at the
+    /// current statement, and locates a division by zero or an out-of-bounds
+    /// string position there.
+    fn trap(&self, error: RuntimeError) -> RuntimeError {
+        match error {
+            RuntimeError::Overflow => RuntimeError::OverflowTrap(self.location()),
+            RuntimeError::DivisionByZero(None) => RuntimeError::DivisionByZero(self.location()),
+            RuntimeError::IndexOutOfBounds { index, length, location: None } => {
+                RuntimeError::IndexOutOfBounds { index, length, location: self.location() }
+            },
+            error => error,
+        }
+    }
+
+    /// Where the current statement is, when the program carries spans.
+    fn location(&self) -> Option<Location> {
+        self.frames.last().and_then(|frame| frame.location)
+    }
+
+    fn current_task(&self) -> &TaskId {
+        self.running.last().expect("the program's own task never finishes")
+    }
+
+    /// Locks a mutex. Tasks can't run while one below them holds it, so if
+    /// another task holds it the wait never ends.
+    fn acquire(&mut self, name: String) -> Result<Value, RuntimeError> {
+        let task = self.current_task().clone();
+        if let Some(holder) = self.sync.holders.get(&name) {
+            let waiting_for = if self.running.contains(holder) {
+                Wait::Mutex { name, holder: holder.clone() }
+            } else {
+                Wait::Abandoned { name, holder: holder.clone() }
+            };
+            let waited_on = self.running.iter().position(|running| *running == *holder).unwrap_or(self.running.len() - 1);
+            return Err(self.deadlock(waiting_for, waited_on));
+        }
+        if self.sync.order.is_some() {
+            let held = self.sync.held_by(task.id);
+            let stack = self.stack_of(task.id);
+            self.sync.record_order(&held, &name, &task, &stack);
+        }
+        self.sync.holders.insert(name, task);
+        self.metrics.mutex_acquisitions += 1;
+        Ok(Value::Null)
+    }
+
+    fn release(&mut self, name: String) -> Result<Value, RuntimeError> {
+        match self.sync.holders.get(&name) {
+            Some(holder) if holder.id == self.current_task().id => {
+                self.sync.holders.remove(&name);
+                Ok(Value::Null)
+            },
+            _ => Err(RuntimeError::MutexNotHeld(name)),
+        }
+    }
+
+    /// Takes the next message from a channel, first running tasks that
+    /// haven't started until one has sent it.
+    fn receive(&mut self, channel: String) -> Result<Value, RuntimeError> {
+        match self.try_receive(channel.clone())? {
+            Some(message) => Ok(message),
+            // Any running task could send it, if it weren't waiting too
+            None => Err(self.deadlock(Wait::Message { channel }, 0)),
+        }
+    }
+
+    /// Like `receive`, but none when no task is left to send the message.
+    fn try_receive(&mut self, channel: String) -> Result<Option<Value>, RuntimeError> {
+        let task = self.current_task().id;
+        self.waiting.insert(task, Wait::Message { channel: channel.clone() });
+        let started = loop {
+            if self.sync.channels.get(&channel).is_some_and(|messages| !messages.is_empty()) {
+                break Ok(true);
+            }
+            let (depth, index, next) = match self.next_pending_task(None) {
+                Ok(Some(next)) => next,
+                Ok(None) => break Ok(false),
+                Err(error) => break Err(error),
+            };
+            if let Err(error) = self.run_task(next, depth, index) {
+                break Err(error);
+            }
+        };
+        self.waiting.remove(&task);
+        if !started? {
+            return Ok(None);
+        }
+        self.schedule.delivered(&channel, task).map_err(RuntimeError::ReplayDiverged)?;
+        let messages = self.sync.channels.get_mut(&channel).expect("checked above");
+        self.metrics.messages_received += 1;
+        Ok(messages.pop_front().map(|(_, message)| message))
+    }
+
+    /// `stream.function(..)`. Sinks pull through their pipeline here; the
+    /// other functions build stages.
+    fn call_stream(&mut self, function: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let expected = Stages::arity(function)
+            .ok_or_else(|| RuntimeError::UndefinedFunction(format!("stream.{}", function)))?;
+        if arguments.len() != expected {
+            return Err(RuntimeError::ArityMismatch { function: format!("stream.{}", function), expected, found: arguments.len() });
+        }
+        let (handle, limit, each) = match (function, arguments.as_slice()) {
+            ("next", [Value::Int(handle)]) => (*handle, Some(1), None),
+            ("take", [Value::Int(handle), Value::Int(count)]) => (*handle, Some((*count).max(0) as usize), None),
+            ("forEach", [Value::Int(handle), Value::String(each)]) => (*handle, None, Some(each.clone())),
+            _ => return self.stages.build(function, arguments),
+        };
+        // Out of the table while it's pulled, so a function it calls can't
+        // pull from it too
+        let mut stage = self.stages.take(handle)?;
+        let mut elements = Vec::new();
+        let result = loop {
+            if limit.is_some_and(|limit| elements.len() >= limit) {
+                break Ok(());
+            }
+            let element = match self.pull(&mut stage) {
+                Ok(Some(element)) => element,
+                Ok(None) => break Ok(()),
+                Err(error) => break Err(error),
+            };
+            match &each {
+                Some(each) => match self.call(each, vec![element]) {
+                    Ok(_) => elements.push(Value::Null),
+                    Err(error) => break Err(error),
+                },
+                None => elements.push(element),
+            }
+        };
+        self.stages.restore(handle, stage);
+        result?;
+        Ok(match function {
+            "next" => elements.pop().unwrap_or(Value::Null),
+            "take" => Value::Array(elements),
+            _ => Value::Int(elements.len() as i64),
+        })
+    }
+
+    /// The next element out of a stage, pulling from the stages before it
+    /// as it needs them; none at the end of the stream.
+    fn pull(&mut self, stage: &mut Stage) -> Result<Option<Value>, RuntimeError> {
+        match stage {
+            Stage::Of(elements) => Ok(elements.pop_front()),
+            Stage::Generate { function, index } => {
+                let element = self.call(function, vec![Value::Int(*index)])?;
+                *index += 1;
+                Ok(Some(element).filter(|element| !matches!(element, Value::Null)))
+            },
+            Stage::FromChannel(channel) => self.try_receive(channel.clone()),
+            Stage::Map { upstream, function } => match self.pull(upstream)? {
+                Some(element) => self.call(function, vec![element]).map(Some),
+                None => Ok(None),
+            },
+            Stage::Filter { upstream, function } => {
+                while let Some(element) = self.pull(upstream)? {
+                    let kept = self.call(function, vec![element.clone()])?;
+                    match kept.as_bool() {
+                        Some(true) => return Ok(Some(element)),
+                        Some(false) => {},
+                        None => return Err(RuntimeError::TypeError(format!("Filter {} must return a boolean, found {}", function, kept.type_name()))),
+                    }
+                }
+                Ok(None)
+            },
+            Stage::Buffer { upstream, size, buffered, ended } => {
+                while !*ended && buffered.len() < *size {
+                    match self.pull(upstream)? {
+                        Some(element) => buffered.push_back(element),
+                        None => *ended = true,
+                    }
+                }
+                Ok(buffered.pop_front())
+            },
+            Stage::Throttle { upstream, elements, steps, window, passed } => {
+                if self.steps >= *window + *steps {
+                    *window = self.steps;
+                    *passed = 0;
+                }
+                if *passed == *elements {
+                    // Wait out the window
+                    self.steps = *window + *steps;
+                    *window = self.steps;
+                    *passed = 0;
+                }
+                let element = self.pull(upstream)?;
+                *passed += element.is_some() as u64;
+                Ok(element)
+            },
+        }
+    }
+
+    /// The deadlock of the running task, waiting for `waiting_for`, and the
+    /// tasks below it down to the `waited_on`-th.
+    fn deadlock(&self, waiting_for: Wait, waited_on: usize) -> RuntimeError {
+        let mut tasks = Vec::new();
+        for (i, task) in self.running.iter().enumerate().skip(waited_on).rev() {
+            let waiting_for = match self.waiting.get(&task.id) {
+                Some(wait) if i + 1 < self.running.len() => wait.clone(),
+                _ => waiting_for.clone(),
+            };
+            tasks.push(BlockedTask { task: task.clone(), waiting_for, stack: self.stack_of(task.id) });
+        }
+        RuntimeError::Deadlock(Box::new(Deadlock { tasks }))
+    }
+
+    /// A task's frames, innermost first.
+    fn stack_of(&self, task: usize) -> Vec<TraceFrame> {
+        self.frames.iter().rev()
+            .filter(|frame| frame.task == task)
+            .map(|frame| TraceFrame { function: frame.function.clone(), location: frame.location })
+            .collect()
+    }
+
+    fn tick(&mut self) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        if self.exporter.as_ref().is_some_and(|exporter| self.steps.is_multiple_of(exporter.interval)) {
+            self.export_metrics();
+        }
+        if self.inspector.as_ref().is_some_and(|inspector| self.steps.is_multiple_of(inspector.interval)) {
+            self.publish_snapshot();
+        }
+        match self.step_limit {
+            Some(limit) if self.steps > limit => Err(RuntimeError::StepLimitExceeded(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("the top-level frame is never popped")
+    }
+
+    fn declare(&mut self, name: &str, value: Value) {
+        let scope = self.frame().scopes.last_mut().expect("frames always have a scope");
+        scope.insert(name.to_string(), value);
+    }
+
+    fn lookup(&self, name: &str) -> Result<Value, RuntimeError> {
+        let frame = self.frames.last().expect("the top-level frame is never popped");
+        let task = self.current_task().id;
+        frame.scopes.iter().rev()
+            .chain(self.locals.get(&task))
+            .chain(Some(&self.task_locals))
+            .chain(self.frames[0].scopes.first())
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .ok_or_else(|| RuntimeError::UndefinedVariable(name.to_string()))
+    }
+
+    fn assign(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        let last = self.frames.len() - 1;
+        let task = self.current_task().id;
+        let slot = self.frames[last].scopes.iter_mut().rev()
+            .find_map(|scope| scope.get_mut(name));
+        let slot = match slot {
+            Some(slot) => slot,
+            // The task gets its own value the first time it sets one
+            None if self.task_locals.contains_key(name) => {
+                self.locals.entry(task).or_default().insert(name.to_string(), value);
+                return Ok(());
+            },
+            None => self.frames[0].scopes[0].get_mut(name)
+                .ok_or_else(|| RuntimeError::UndefinedVariable(name.to_string()))?,
+        };
+        *slot = value;
+        Ok(())
+    }
+
+    fn scoped<T>(&mut self, scope: HashMap<String, Value>, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.frame().scopes.push(scope);
+        let result = f(self);
+        self.frame().scopes.pop();
+        result
+    }
+
+    // `exec` and `eval` recurse once per nesting level of the program, so
+    // the bulky cases live in their own functions to keep these frames small.
+    fn exec(&mut self, node: &Node) -> Result<Flow, RuntimeError> {
+        self.tick()?;
+        match node {
+            Node::Located { span, node } => {
+                self.enter_statement(*span)?;
+                self.exec(node)
+            },
+            Node::Block(statements) => self.scoped(HashMap::new(), |this| this.exec_statements(statements)),
+            Node::Let { name, initializer, .. } => {
+                let value = match initializer {
+                    Some(initializer) => self.eval(initializer)?,
+                    None => Value::Null,
+                };
+                self.declare(name, value);
+                Ok(Flow::Next)
+            },
+            Node::Function { .. } => {
+                self.define(node)?;
+                Ok(Flow::Next)
+            },
+            // Folded into their uses by `consteval`
+            Node::Const { .. } => Ok(Flow::Next),
+            Node::TaskLocal { name, initializer, .. } => {
+                let value = self.eval(initializer)?;
+                self.task_locals.insert(name.clone(), value);
+                Ok(Flow::Next)
+            },
+            Node::Union { variants, .. } => {
+                for variant in variants {
+                    self.variants.insert(variant.name.clone(), variant.payload.len());
+                }
+                Ok(Flow::Next)
+            },
+            // Only the type checker looks at interfaces
+            Node::Interface { .. } => Ok(Flow::Next),
+            Node::If { condition, then_branch, else_branch } => {
+                if self.condition(condition)? {
+                    self.exec(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.exec(else_branch)
+                } else {
+                    Ok(Flow::Next)
+                }
+            },
+            Node::While { condition, body } => self.exec_loop(None, Some(condition), None, body, false),
+            Node::DoWhile { body, condition } => self.exec_loop(None, Some(condition), None, body, true),
+            Node::For { initializer, condition, increment, body } => self.scoped(HashMap::new(), |this| {
+                this.exec_loop(initializer.as_deref(), condition.as_deref(), increment.as_deref(), body, false)
+            }),
+            Node::Foreach { item, collection, body } => self.exec_foreach(item, collection, body),
+            Node::Match { value, cases } => self.exec_match(value, cases),
+            Node::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Null,
+                };
+                Ok(Flow::Return(value))
+            },
+            Node::Break => Ok(Flow::Break),
+            Node::Continue => Ok(Flow::Continue),
+            Node::Throw(value) => Err(RuntimeError::Thrown(self.eval(value)?)),
+            Node::Try { body, catch_clauses, finally } => self.exec_try(body, catch_clauses, finally.as_deref()),
+            Node::Assertion { kind, condition, message } => self.exec_assertion(*kind, condition, message.as_deref()),
+            Node::Scope { body } => self.exec_scope(body),
+            Node::Spawn(task) => self.exec_spawn(task),
+            expression => {
+                self.eval(expression)?;
+                Ok(Flow::Next)
+            },
+        }
+    }
+
+    /// Records where the current frame is and gives the debugger a chance
+    /// to pause before the statement runs.
+    fn enter_statement(&mut self, span: Span) -> Result<(), RuntimeError> {
+        let Some(source_map) = &self.source_map else {
+            return Ok(());
+        };
+        let location = source_map.span_location(span);
+        let depth = self.frames.len();
+        let reason = self.debugger.as_ref()
+            .and_then(|debugger| debugger.pause_reason(&source_map.file, location.line, depth));
+        self.frame().location = Some(location);
+
+        if let Some(reason) = reason {
+            let state = self.paused_state(location, reason);
+            let resume = self.debugger.as_mut().is_some_and(|debugger| debugger.pause(&state, depth));
+            if !resume {
+                return Err(RuntimeError::Terminated);
+            }
+        }
+        Ok(())
+    }
+
+    fn paused_state(&self, location: Location, reason: PauseReason) -> PausedState {
+        let frames = self.frames.iter().rev()
+            .map(|frame| {
+                let mut locals = BTreeMap::new();
+                for scope in &frame.scopes {
+                    locals.extend(scope.iter().map(|(name, value)| (name.clone(), value.clone())));
+                }
+                StackFrame {
+                    function: frame.function.clone(),
+                    location: frame.location,
+                    locals: locals.into_iter().collect(),
+                }
+            })
+            .collect();
+        let watches = self.debugger.iter()
+            .flat_map(|debugger| debugger.config().watches())
+            .map(|name| (name.clone(), self.lookup(name).ok()))
+            .collect();
+
+        PausedState {
+            file: self.source_map.as_ref().map(|source_map| source_map.file.clone()).unwrap_or_default(),
+            location,
+            reason,
+            frames,
+            watches,
+        }
+    }
+
+    fn exec_statements(&mut self, statements: &[Node]) -> Result<Flow, RuntimeError> {
+        for statement in statements {
+            match self.exec(statement)? {
+                Flow::Next => {},
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Runs `while`, `do`-`while` and `for` loops.
+    fn exec_loop(
+        &mut self,
+        initializer: Option<&Node>,
+        condition: Option<&Node>,
+        increment: Option<&Node>,
+        body: &Node,
+        body_first: bool,
+    ) -> Result<Flow, RuntimeError> {
+        if let Some(initializer) = initializer {
+            self.exec(initializer)?;
+        }
+
+        let mut first = true;
+        loop {
+            if let (Some(condition), false) = (condition, first && body_first) {
+                if !self.condition(condition)? {
+                    return Ok(Flow::Next);
+                }
+            }
+            first = false;
+
+            match self.exec(body)? {
+                Flow::Break => return Ok(Flow::Next),
+                flow @ Flow::Return(_) => return Ok(flow),
+                Flow::Next | Flow::Continue => {},
+            }
+            if let Some(increment) = increment {
+                self.eval(increment)?;
+            }
+        }
+    }
+
+    fn exec_foreach(&mut self, item: &str, collection: &Node, body: &Node) -> Result<Flow, RuntimeError> {
+        let items = match self.eval(collection)? {
+            Value::Array(elements) => elements,
+            Value::String(value) => value.chars().map(|c| Value::String(c.to_string())).collect(),
+            Value::Bytes(data) => data.into_iter().map(|byte| Value::Int(byte.into())).collect(),
+            other => return Err(RuntimeError::TypeError(format!("Can't iterate over {}", other.type_name()))),
+        };
+
+        for value in items {
+            let scope = HashMap::from([(item.to_string(), value)]);
+            match self.scoped(scope, |this| this.exec(body))? {
+                Flow::Break => break,
+                flow @ Flow::Return(_) => return Ok(flow),
+                Flow::Next | Flow::Continue => {},
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec_match(&mut self, value: &Node, cases: &[MatchCase]) -> Result<Flow, RuntimeError> {
+        let value = self.eval(value)?;
+        for MatchCase { pattern, body } in cases {
+            if let Some(bindings) = self.matches(pattern, &value)? {
+                return self.scoped(bindings, |this| this.exec(body));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn exec_try(&mut self, body: &Node, catch_clauses: &[Node], finally: Option<&Node>) -> Result<Flow, RuntimeError> {
+        let mut result = self.exec(body);
+        if let Err(error) = &result {
+            let clause = catch_clauses.iter().find_map(|clause| match clause {
+                Node::CatchClause { param_name, body, .. } => Some((param_name, body)),
+                _ => None,
+            });
+            if let (true, Some((binding, handler))) = (error.is_catchable(), clause) {
+                let caught = match error {
+                    RuntimeError::Thrown(value) => value.clone(),
+                    error => Value::String(error.to_string()),
+                };
+                let scope = HashMap::from([(binding.clone(), caught)]);
+                result = self.scoped(scope, |this| this.exec(handler));
+            }
+        }
+
+        match finally {
+            Some(finally) => match self.exec(finally)? {
+                Flow::Next => result,
+                flow => Ok(flow),
+            },
+            None => result,
+        }
+    }
+
+    /// Runs a scope's body, then its tasks in the order they were spawned,
+    /// or a replayed schedule's. The interpreter is single-threaded, so each
+    /// task runs to completion before the next starts. If the body or a task
+    /// fails, the tasks that haven't run are cancelled and the error
+    /// propagates.
+    fn exec_scope(&mut self, body: &Node) -> Result<Flow, RuntimeError> {
+        self.frame().tasks.push(TaskScope::default());
+        let (depth, index) = (self.frames.len() - 1, self.frame().tasks.len() - 1);
+        let mut result = self.exec(body);
+        if result.is_ok() {
+            let task = self.current_task().id;
+            self.waiting.insert(task, Wait::Tasks);
+            loop {
+                let next = match self.next_pending_task(Some((depth, index))) {
+                    Ok(Some((_, _, next))) => next,
+                    Ok(None) => break,
+                    Err(error) => {
+                        result = Err(error);
+                        break;
+                    },
+                };
+                if let Err(error) = self.run_task(next, depth, index) {
+                    result = Err(error);
+                    break;
+                }
+            }
+            self.waiting.remove(&task);
+        }
+        let scope = self.frame().tasks.pop().expect("pushed above");
+        let flow = result?;
+        match scope.failure {
+            Some(error) => Err(error),
+            None => Ok(flow),
+        }
+    }
+
+    /// Runs a task of the `index`-th scope of the frame at `depth`. Its
+    /// failure is the scope's, but errors that end the program, like
+    /// running out of steps, propagate at once.
+    fn run_task(&mut self, task: Task, depth: usize, index: usize) -> Result<(), RuntimeError> {
+        self.schedule.started(task.id, &task.function);
+        self.running.push(TaskId { id: task.id, function: task.function.clone() });
+        self.parents.insert(task.id, task.parent);
+        let started_at = self.steps;
+        self.metrics.task_latency.record(started_at - task.spawned_at);
+        // Kept to start again if the guardian restarts it
+        let top_level = (task.parent == 0).then(|| task.clone());
+        let result = self.call(&task.function, task.arguments);
+        self.running.pop();
+        self.parents.remove(&task.id);
+        self.locals.remove(&task.id);
+        self.metrics.task_duration.record(self.steps - started_at);
+        match result {
+            Ok(_) => {
+                self.metrics.tasks_completed += 1;
+                Ok(())
+            },
+            Err(error) if !error.is_catchable() => Err(error),
+            Err(error) => {
+                self.metrics.tasks_failed += 1;
+                let directive = match top_level {
+                    Some(task) => self.supervise(task, &error, depth, index),
+                    None => Directive::Escalate,
+                };
+                if directive == Directive::Escalate {
+                    let scope = &mut self.frames[depth].tasks[index];
+                    scope.pending.clear();
+                    scope.failure.get_or_insert(error);
+                }
+                Ok(())
+            },
+        }
+    }
+
+    /// Asks the guardian about a failed top-level task, and starts it again
+    /// next if it says to restart it.
+    fn supervise(&mut self, mut task: Task, error: &RuntimeError, depth: usize, index: usize) -> Directive {
+        let id = TaskId { id: task.id, function: task.function.clone() };
+        let restarts = self.restarts.get(&task.id).copied().unwrap_or(0);
+        let directive = self.guardian.decide(&id, error, restarts);
+        if directive == Directive::Escalate {
+            return directive;
+        }
+        if directive == Directive::Restart {
+            self.restarts.insert(task.id, restarts + 1);
+            task.spawned_at = self.steps;
+            self.frames[depth].tasks[index].pending.push_front(task);
+        }
+        self.supervised.push(Supervised { task: id, error: error.clone(), directive });
+        directive
+    }
+
+    /// The next task to start, with where its scope is: from the `index`-th
+    /// scope of the frame at `depth`, or with `None` from the innermost scope
+    /// that has one. A replay can pick any task waiting in those scopes.
+    fn next_pending_task(&mut self, scope: Option<(usize, usize)>) -> Result<Option<(usize, usize, Task)>, RuntimeError> {
+        // Each waiting task's scope, position in it and id, in the order
+        // they'd start without a replay
+        let mut candidates = Vec::new();
+        for (depth, frame) in self.frames.iter().enumerate().rev() {
+            for (index, tasks) in frame.tasks.iter().enumerate().rev() {
+                if scope.is_none_or(|scope| scope == (depth, index)) {
+                    candidates.extend(tasks.pending.iter().enumerate().map(|(position, task)| (depth, index, position, task.id)));
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return Ok(None);
+        }
+        let ids: Vec<usize> = candidates.iter().map(|&(_, _, _, id)| id).collect();
+        let chosen = self.schedule.next_start(&ids).map_err(RuntimeError::ReplayDiverged)?;
+        let (depth, index, position, _) = candidates[chosen];
+        let task = self.frames[depth].tasks[index].pending.remove(position).expect("listed above");
+        Ok(Some((depth, index, task)))
+    }
+
+    fn exec_spawn(&mut self, task: &Node) -> Result<Flow, RuntimeError> {
+        let (callee, arguments) = match task.unlocated() {
+            Node::Call { callee, arguments } => (callee, arguments),
+            other => return Err(RuntimeError::Unsupported(format!("Spawning {}", describe(other)))),
+        };
+        let function = match callee.as_ref() {
+            Node::Identifier(name) => *name,
+            other => return Err(RuntimeError::Unsupported(format!("Calling {}", describe(other)))),
+        };
+        if self.frame().tasks.is_empty() {
+            return Err(RuntimeError::SpawnOutsideScope);
+        }
+        let arguments = arguments.iter()
+            .map(|argument| self.eval(argument))
+            .collect::<Result<_, _>>()?;
+        let (id, spawned_at, parent) = (self.next_task, self.steps, self.current_task().id);
+        self.next_task += 1;
+        let scope = self.frame().tasks.last_mut().expect("checked above");
+        scope.pending.push_back(Task { id, function: function.to_string(), arguments, spawned_at, parent });
+        self.metrics.tasks_spawned += 1;
+        Ok(Flow::Next)
+    }
+
+    fn exec_assertion(&mut self, kind: AssertionKind, condition: &Node, message: Option<&Node>) -> Result<Flow, RuntimeError> {
+        if self.condition(condition)? {
+            return Ok(Flow::Next);
+        }
+        let message = match message {
+            Some(message) => Some(self.eval(message)?.to_string()),
+            None => None,
+        };
+        Err(RuntimeError::AssertionFailed { kind, message })
+    }
+
+    fn condition(&mut self, node: &Node) -> Result<bool, RuntimeError> {
+        let value = self.eval(node)?;
+        value.as_bool()
+            .ok_or_else(|| RuntimeError::TypeError(format!("Condition must be a boolean, found {}", value.type_name())))
+    }
+
+    /// The variables a pattern binds if it matches a value. `Tag(a, b)`
+    /// matches a tag of a union and binds its payload.
+    fn matches(&mut self, pattern: &Node, value: &Value) -> Result<Option<HashMap<String, Value>>, RuntimeError> {
+        match (pattern, value) {
+            (Node::Identifier(name), _) if name == "_" => Ok(Some(HashMap::new())),
+            (Node::Call { callee, arguments }, Value::Variant { name, payload }) if self.variants.contains_key(name) => {
+                if !matches!(callee.as_ref(), Node::Identifier(tag) if tag == name) || arguments.len() != payload.len() {
+                    return Ok(None);
+                }
+                let mut bindings = HashMap::new();
+                for (argument, value) in arguments.iter().zip(payload) {
+                    match argument {
+                        Node::Identifier(binding) if binding == "_" => {},
+                        Node::Identifier(binding) => {
+                            bindings.insert(binding.to_string(), value.clone());
+                        },
+                        argument => {
+                            if !equals(&self.eval(argument)?, value) {
+                                return Ok(None);
+                            }
+                        },
+                    }
+                }
+                Ok(Some(bindings))
+            },
+            (pattern, value) => Ok(equals(&self.eval(pattern)?, value).then(HashMap::new)),
+        }
+    }
+
+    fn eval(&mut self, node: &Node) -> Result<Value, RuntimeError> {
+        match node {
+            Node::IntLiteral(value) => Ok(Value::Int(*value)),
+            Node::UIntLiteral(value) => i64::try_from(*value).map(Value::Int).map_err(|_| RuntimeError::Overflow),
+            Node::FloatLiteral(value) => Ok(Value::Float(*value)),
+            Node::StringLiteral(value) => Ok(Value::String(value.clone())),
+            // Each part is written as `print` would
+            Node::TemplateString { parts } => parts.iter()
+                .map(|part| Ok(self.eval(part)?.to_string()))
+                .collect::<Result<String, _>>()
+                .map(Value::String),
+            Node::BooleanLiteral(value) => Ok(Value::Bool(*value)),
+            Node::NullLiteral => Ok(Value::Null),
+            Node::Identifier(name) => self.lookup(name).or_else(|error| match self.variants.get(name.as_str()) {
+                Some(0) => Ok(Value::Variant { name: name.to_string(), payload: Vec::new() }),
+                _ => Err(error),
+            }),
+            Node::Array { elements } => elements.iter()
+                .map(|element| self.eval(element))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            Node::Object { fields } => fields.iter()
+                .map(|(name, value)| Ok((name.clone(), self.eval(value)?)))
+                .collect::<Result<_, _>>()
+                .map(Value::Object),
+            Node::Binary { left, operator: BinaryOp::And, right } => {
+                Ok(Value::Bool(self.condition(left)? && self.condition(right)?))
+            },
+            Node::Binary { left, operator: BinaryOp::Or, right } => {
+                Ok(Value::Bool(self.condition(left)? || self.condition(right)?))
+            },
+            Node::Binary { left, operator: BinaryOp::NullCoalesce, right } => match self.eval(left)? {
+                Value::Null => self.eval(right),
+                value => Ok(value),
+            },
+            Node::Binary { left, operator, right } => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(operator, left, right, self.wrapping).map_err(|e| self.trap(e))
+            },
+            Node::Unary { operator, operand } => self.eval_unary(operator, operand),
+            Node::Call { callee, arguments } => self.eval_call(callee, arguments),
+            Node::Member { object, property } => self.eval_member(object, property),
+            Node::Index { object, index, .. } => self.eval_index(object, index),
+            other => Err(RuntimeError::Unsupported(describe(other))),
+        }
+    }
+
+    fn eval_call(&mut self, callee: &Node, arguments: &[Node]) -> Result<Value, RuntimeError> {
+        let name = match callee {
+            Node::Identifier(name) => name,
+            Node::Member { object, property } => return match object.as_ref() {
+                Node::Identifier(module) if matches!(module.as_str(), "regex" | "datetime" | "bytes" | "io" | "stream") => {
+                    self.eval_module_call(module, property, arguments)
+                },
+                object => self.eval_method_call(object, property, arguments),
+            },
+            other => return Err(RuntimeError::Unsupported(format!("Calling {}", describe(other)))),
+        };
+        let arguments: Vec<Value> = arguments.iter()
+            .map(|argument| self.eval(argument))
+            .collect::<Result<_, _>>()?;
+        match self.variants.get(name.as_str()) {
+            Some(&expected) if !self.functions.contains_key(name.as_str()) => {
+                if arguments.len() != expected {
+                    return Err(RuntimeError::ArityMismatch { function: name.to_string(), expected, found: arguments.len() });
+                }
+                Ok(Value::Variant { name: name.to_string(), payload: arguments })
+            },
+            _ => self.call(name, arguments),
+        }
+    }
+
+    /// `module.function(..)` for the standard library modules.
+    fn eval_module_call(&mut self, module: &str, function: &str, arguments: &[Node]) -> Result<Value, RuntimeError> {
+        let arguments = arguments.iter()
+            .map(|argument| self.eval(argument))
+            .collect::<Result<_, _>>()?;
+        match module {
+            "regex" => self.patterns.call(function, arguments),
+            "bytes" => bytes::call(function, arguments).map_err(|e| self.trap(e)),
+            "io" => self.streams.call(function, arguments, &mut |data| write_output(&mut self.sink, &mut self.output, data)),
+            "stream" => self.call_stream(function, arguments),
+            _ => datetime::call(function, arguments).map_err(|e| self.trap(e)),
+        }
+    }
+
+    /// `receiver.method(..)`, which only strings have so far.
+    fn eval_method_call(&mut self, object: &Node, method: &str, arguments: &[Node]) -> Result<Value, RuntimeError> {
+        let receiver = self.eval(object)?;
+        let arguments = arguments.iter()
+            .map(|argument| self.eval(argument))
+            .collect::<Result<_, _>>()?;
+        match receiver {
+            Value::String(text) => strings::call(&text, method, arguments).map_err(|e| self.trap(e)),
+            Value::Null => Err(RuntimeError::NullDereference(self.location())),
+            other => Err(RuntimeError::TypeError(format!("{} has no method {}", other.type_name(), method))),
+        }
+    }
+
+    fn eval_member(&mut self, object: &Node, property: &str) -> Result<Value, RuntimeError> {
+        match (self.eval(object)?, property) {
+            (Value::Array(elements), "length") => Ok(Value::Int(elements.len() as i64)),
+            (Value::String(value), "length") => Ok(Value::Int(value.chars().count() as i64)),
+            (Value::Bytes(data), "length") => Ok(Value::Int(data.len() as i64)),
+            (Value::Object(fields), property) => match fields.into_iter().find(|(name, _)| name == property) {
+                Some((_, value)) => Ok(value),
+                None => Err(RuntimeError::TypeError(format!("object has no field {}", property))),
+            },
+            (Value::Null, _) => Err(RuntimeError::NullDereference(self.location())),
+            (value, property) => Err(RuntimeError::TypeError(format!("{} has no member {}", value.type_name(), property))),
+        }
+    }
+
+    fn eval_index(&mut self, object: &Node, index: &Node) -> Result<Value, RuntimeError> {
+        let mut elements = match self.eval(object)? {
+            Value::Array(elements) => elements,
+            Value::Bytes(data) => data.into_iter().map(|byte| Value::Int(byte.into())).collect(),
+            // Strings are indexed by character, like their length counts them
+            Value::String(value) => value.chars().map(|c| Value::String(c.to_string())).collect(),
+            Value::Null => return Err(RuntimeError::NullDereference(self.location())),
+            other => return Err(RuntimeError::TypeError(format!("Can't index {}", other.type_name()))),
+        };
+        let index = match self.eval(index)? {
+            Value::Int(index) => index,
+            other => return Err(RuntimeError::TypeError(format!("Index must be an int, found {}", other.type_name()))),
+        };
+        match usize::try_from(index) {
+            Ok(position) if position < elements.len() => Ok(elements.swap_remove(position)),
+            _ => Err(RuntimeError::IndexOutOfBounds {
+                index,
+                length: elements.len(),
+                location: self.location(),
+            }),
+        }
+    }
+
+    fn eval_unary(&mut self, operator: &UnaryOp, operand: &Node) -> Result<Value, RuntimeError> {
+        match operator {
+            UnaryOp::Minus => match self.eval(operand)? {
+                Value::Int(value) if self.wrapping => Ok(Value::Int(value.wrapping_neg())),
+                Value::Int(value) => value.checked_neg().map(Value::Int).ok_or_else(|| self.trap(RuntimeError::Overflow)),
+                Value::Float(value) => Ok(Value::Float(-value)),
+                other => Err(RuntimeError::TypeError(format!("Can't negate {}", other.type_name()))),
+            },
+            UnaryOp::Not => Ok(Value::Bool(!self.condition(operand)?)),
+            UnaryOp::BitNot => match self.eval(operand)? {
+                Value::Int(value) => Ok(Value::Int(!value)),
+                other => Err(RuntimeError::TypeError(format!("Can't invert the bits of {}", other.type_name()))),
+            },
+            UnaryOp::Increment | UnaryOp::Decrement | UnaryOp::PostIncrement | UnaryOp::PostDecrement => {
+                self.eval_increment(operator, operand)
+            },
+        }
+    }
+
+    /// `++` and `--` on a variable, a field of one or an element of one,
+    /// storing the stepped value back. The prefix forms give the new value
+    /// and the postfix forms the old one.
+    fn eval_increment(&mut self, operator: &UnaryOp, operand: &Node) -> Result<Value, RuntimeError> {
+        // The steps from the variable to the place, innermost last
+        let mut steps = Vec::new();
+        let mut place = operand;
+        let name = loop {
+            match place {
+                Node::Identifier(name) => break name,
+                Node::Member { object, property } => {
+                    steps.push(Step::Field(property));
+                    place = object;
+                },
+                Node::Index { object, index, .. } => {
+                    steps.push(Step::Element(index));
+                    place = object;
+                },
+                other => return Err(RuntimeError::Unsupported(format!("Incrementing {}", describe(other)))),
+            }
+        };
+        let mut root = self.lookup(name)?;
+        let mut slot = &mut root;
+        for step in steps.into_iter().rev() {
+            slot = match (step, slot) {
+                (Step::Field(property), Value::Object(fields)) => match fields.iter_mut().find(|(name, _)| name == property) {
+                    Some((_, value)) => value,
+                    None => return Err(RuntimeError::TypeError(format!("object has no field {}", property))),
+                },
+                (Step::Element(index), Value::Array(elements)) => {
+                    let index = match self.eval(index)? {
+                        Value::Int(index) => index,
+                        other => return Err(RuntimeError::TypeError(format!("Index must be an int, found {}", other.type_name()))),
+                    };
+                    let length = elements.len();
+                    match usize::try_from(index).ok().and_then(|position| elements.get_mut(position)) {
+                        Some(element) => element,
+                        None => return Err(RuntimeError::IndexOutOfBounds { index, length, location: self.location() }),
+                    }
+                },
+                (_, Value::Null) => return Err(RuntimeError::NullDereference(self.location())),
+                (Step::Field(property), value) => {
+                    return Err(RuntimeError::TypeError(format!("{} has no member {}", value.type_name(), property)));
+                },
+                (Step::Element(_), value) => return Err(RuntimeError::TypeError(format!("Can't index {}", value.type_name()))),
+            };
+        }
+        let old = match &*slot {
+            value @ (Value::Int(_) | Value::Float(_)) => value.clone(),
+            other => return Err(RuntimeError::TypeError(format!("Can't increment {}", other.type_name()))),
+        };
+        let delta = if matches!(operator, UnaryOp::Increment | UnaryOp::PostIncrement) { 1 } else { -1 };
+        let new = binary(&BinaryOp::Add, old.clone(), Value::Int(delta), self.wrapping)
+            .map_err(|e| self.trap(e))?;
+        *slot = new.clone();
+        self.assign(name, root)?;
+        match operator {
+            UnaryOp::PostIncrement | UnaryOp::PostDecrement => Ok(old),
+            _ => Ok(new),
+        }
+    }
+}
+
+/// A step into the value of a variable to the place `++` or `--` changes.
+enum Step<'a> {
+    Field(&'a str),
+    Element(&'a Node),
+}
+
+/// Writes to `sink`, or captures in `output` without one.
+fn write_output(sink: &mut Option<Box<dyn Write>>, output: &mut String, data: &[u8]) {
+    match sink {
+        // Output is best effort, like print! ignoring a closed pipe
+        Some(sink) => drop(sink.write_all(data)),
+        None => output.push_str(&String::from_utf8_lossy(data)),
+    }
+}
+
+/// Integer overflow fails with `Overflow` unless `wrapping` is set.
+fn binary(operator: &BinaryOp, left: Value, right: Value, wrapping: bool) -> Result<Value, RuntimeError> {
+    use Value::{Bool, Float, Int};
+
+    match (operator, left, right) {
+        (BinaryOp::Eq, left, right) => Ok(Bool(equals(&left, &right))),
+        (BinaryOp::NotEq, left, right) => Ok(Bool(!equals(&left, &right))),
+        (BinaryOp::Add, Value::Bytes(left), Value::Bytes(right)) => Ok(Value::Bytes([left, right].concat())),
+        (BinaryOp::Add, Value::String(left), right) => Ok(Value::String(format!("{}{}", left, right))),
+        (BinaryOp::Add, left, Value::String(right)) => Ok(Value::String(format!("{}{}", left, right))),
+        (BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq, Value::String(left), Value::String(right)) => {
+            Ok(Bool(compare(operator, left.cmp(&right))))
+        },
+        (operator, Int(left), Int(right)) => {
+            let (result, overflowed) = match operator {
+                BinaryOp::Add => left.overflowing_add(right),
+                BinaryOp::Sub => left.overflowing_sub(right),
+                BinaryOp::Mul => left.overflowing_mul(right),
+                BinaryOp::Div | BinaryOp::Mod if right == 0 => return Err(RuntimeError::DivisionByZero(None)),
+                BinaryOp::Div => left.overflowing_div(right),
+                BinaryOp::Mod => left.overflowing_rem(right),
+                BinaryOp::BitAnd => (left & right, false),
+                BinaryOp::BitOr => (left | right, false),
+                BinaryOp::BitXor => (left ^ right, false),
+                // Shifting by a negative amount or by 64 or more overflows;
+                // wrapping takes the amount modulo 64
+                BinaryOp::Shl => (left.wrapping_shl(right as u32), !(0..64).contains(&right)),
+                BinaryOp::Shr => (left.wrapping_shr(right as u32), !(0..64).contains(&right)),
+                operator => return Ok(Bool(compare(operator, left.cmp(&right)))),
+            };
+            if overflowed && !wrapping {
+                Err(RuntimeError::Overflow)
+            } else {
+                Ok(Int(result))
+            }
+        },
+        (operator, left @ (Int(_) | Float(_)), right @ (Int(_) | Float(_))) if !is_bitwise(operator) => {
+            let (left, right) = (as_float(&left), as_float(&right));
+            match operator {
+                BinaryOp::Add => Ok(Float(left + right)),
+                BinaryOp::Sub => Ok(Float(left - right)),
+                BinaryOp::Mul => Ok(Float(left * right)),
+                BinaryOp::Div => Ok(Float(left / right)),
+                BinaryOp::Mod => Ok(Float(left % right)),
+                operator => match left.partial_cmp(&right) {
+                    Some(ordering) => Ok(Bool(compare(operator, ordering))),
+                    // NaN compares false with everything
+                    None => Ok(Bool(false)),
+                },
+            }
+        },
+        (operator, left, right) => Err(RuntimeError::TypeError(format!(
+            "Unsupported operands for {:?}: {} and {}", operator, left.type_name(), right.type_name()
+        ))),
+    }
+}
+
+fn is_bitwise(operator: &BinaryOp) -> bool {
+    matches!(operator, BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::Shl | BinaryOp::Shr)
+}
+
+fn compare(operator: &BinaryOp, ordering: std::cmp::Ordering) -> bool {
+    match operator {
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::LtEq => ordering.is_le(),
+        BinaryOp::Gt => ordering.is_gt(),
+        BinaryOp::GtEq => ordering.is_ge(),
+        _ => unreachable!("not a comparison operator"),
+    }
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Int(value) => *value as f64,
+        Value::Float(value) => *value,
+        _ => f64::NAN,
+    }
+}
+
+/// Equality with ints and floats compared by numeric value.
+fn equals(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Int(_), Value::Float(_)) | (Value::Float(_), Value::Int(_)) => as_float(left) == as_float(right),
+        (left, right) => left == right,
+    }
+}
+
+/// The one argument of `acquire`, `release`, `receive` and `unstashAll`: a
+/// mutex or channel name.
+fn name_argument(function: &str, arguments: &[Value]) -> Result<String, RuntimeError> {
+    match arguments {
+        [Value::String(name)] => Ok(name.clone()),
+        [other] => Err(RuntimeError::TypeError(format!("{} expects a name, found {}", function, other.type_name()))),
+        _ => Err(RuntimeError::ArityMismatch { function: function.to_string(), expected: 1, found: arguments.len() }),
+    }
+}
+
+/// The variant name of a node, for error messages.
+fn describe(node: &Node) -> String {
+    let debug = format!("{:?}", node);
+    debug.split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::{Type, UnionVariant};
+    use crate::replay;
+
+    fn ident(name: &str) -> Box<Node> {
+        Box::new(Node::Identifier(name.into()))
+    }
+
+    fn int(value: i64) -> Box<Node> {
+        Box::new(Node::IntLiteral(value))
+    }
+
+    fn binary(left: Box<Node>, operator: BinaryOp, right: Box<Node>) -> Box<Node> {
+        Box::new(Node::Binary { left, operator, right })
+    }
+
+    fn call(name: &str, arguments: Vec<Node>) -> Node {
+        Node::Call { callee: ident(name), arguments }
+    }
+
+    fn function(name: &str, params: &[&str], body: Vec<Node>) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: params.iter()
+                .map(|name| Parameter { name: (*name).into(), type_annotation: Type::Int })
+                .collect(),
+            return_type: Type::Int,
+            body: Box::new(Node::Block(body)),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn factorial() -> Node {
+        // fn factorial(n) { if (n <= 1) { return 1; } return n * factorial(n - 1); }
+        function("factorial", &["n"], vec![
+            Node::If {
+                condition: binary(ident("n"), BinaryOp::LtEq, int(1)),
+                then_branch: Box::new(Node::Block(vec![Node::Return(Some(int(1)))])),
+                else_branch: None,
+            },
+            Node::Return(Some(binary(
+                ident("n"),
+                BinaryOp::Mul,
+                Box::new(call("factorial", vec![*binary(ident("n"), BinaryOp::Sub, int(1))])),
+            ))),
+        ])
+    }
+
+    #[test]
+    fn test_run_captures_output() {
+        let program = Node::Program(vec![
+            factorial(),
+            function("main", &[], vec![
+                Node::Let {
+                    name: "i".to_string(),
+                    type_annotation: None,
+                    initializer: Some(int(0)),
+                    is_mutable: true,
+                },
+                Node::While {
+                    condition: binary(ident("i"), BinaryOp::Lt, int(3)),
+                    body: Box::new(Node::Block(vec![
+                        call("print", vec![
+                            *binary(Box::new(Node::StringLiteral("i=".to_string())), BinaryOp::Add, ident("i")),
+                        ]),
+                        Node::Unary { operator: UnaryOp::Increment, operand: ident("i") },
+                    ])),
+                },
+                Node::Return(Some(Box::new(call("factorial", vec![Node::IntLiteral(5)])))),
+            ]),
+        ]);
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.run(&program), Ok(Value::Int(120)));
+        assert_eq!(interpreter.take_output(), "i=0\ni=1\ni=2\n");
+    }
+
+    #[test]
+    fn test_try_catches_failed_requirement() {
+        let program = Node::Program(vec![function("main", &[], vec![
+            Node::Try {
+                body: Box::new(Node::Block(vec![Node::Assertion {
+                    kind: AssertionKind::Require,
+                    condition: Box::new(Node::BooleanLiteral(false)),
+                    message: Some(Box::new(Node::StringLiteral("no funds".to_string()))),
+                }])),
+                catch_clauses: vec![Node::CatchClause {
+                    param_name: "e".to_string(),
+                    param_type: Type::String,
+                    body: Box::new(Node::Block(vec![Node::Return(Some(ident("e")))])),
+                }],
+                finally: None,
+            },
+        ])]);
+
+        let result = Interpreter::new().run(&program);
+        assert_eq!(result, Ok(Value::String("Requirement failed: no funds".to_string())));
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let divide = Node::Program(vec![function("main", &[], vec![
+            Node::Return(Some(binary(int(1), BinaryOp::Div, int(0)))),
+        ])]);
+        assert_eq!(Interpreter::new().run(&divide), Err(RuntimeError::DivisionByZero(None)));
+
+        let spin = Node::Program(vec![function("main", &[], vec![Node::While {
+            condition: Box::new(Node::BooleanLiteral(true)),
+            body: Box::new(Node::Block(vec![])),
+        }])]);
+        let result = Interpreter::new().with_step_limit(1_000).run(&spin);
+        assert_eq!(result, Err(RuntimeError::StepLimitExceeded(1_000)));
+
+        // Unoptimized frames are several times larger than release ones
+        let recurse = std::thread::Builder::new()
+            .stack_size(64 << 20)
+            .spawn(|| {
+                let program = Node::Program(vec![function("main", &[], vec![call("main", vec![])])]);
+                Interpreter::new().run(&program)
+            })
+            .unwrap();
+        assert_eq!(recurse.join().unwrap(), Err(RuntimeError::StackOverflow));
+    }
+
+    #[test]
+    fn test_overflow_modes() {
+        let guarded = |expression: Box<Node>| Node::Program(vec![function("main", &[], vec![Node::Try {
+            body: Box::new(Node::Block(vec![Node::Return(Some(expression))])),
+            catch_clauses: vec![Node::CatchClause {
+                param_name: "e".to_string(),
+                param_type: Type::String,
+                body: Box::new(Node::Block(vec![Node::Return(Some(int(0)))])),
+            }],
+            finally: None,
+        }])]);
+        let next = guarded(binary(int(i64::MAX), BinaryOp::Add, int(1)));
+
+        // Traps can't be caught
+        assert_eq!(Interpreter::new().run(&next), Err(RuntimeError::OverflowTrap(None)));
+        assert_eq!(Interpreter::new().with_wrapping_arithmetic().run(&next), Ok(Value::Int(i64::MIN)));
+
+        let wrapped = guarded(Box::new(call("wrappingMul", vec![*int(i64::MAX), *int(2)])));
+        assert_eq!(Interpreter::new().run(&wrapped), Ok(Value::Int(-2)));
+        let checked = guarded(Box::new(call("checkedSub", vec![*int(i64::MIN), *int(1)])));
+        assert_eq!(Interpreter::new().with_wrapping_arithmetic().run(&checked), Ok(Value::Int(0)));
+        assert_eq!(
+            RuntimeError::OverflowTrap(Some(Location { line: 3, column: 9 })).to_string(),
+            "Integer overflow at line 3:9",
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let int_op = |left: i64, operator: BinaryOp, right: i64, wrapping: bool| super::binary(&operator, Value::Int(left), Value::Int(right), wrapping);
+
+        assert_eq!(int_op(0b1100, BinaryOp::BitAnd, 0b1010, false), Ok(Value::Int(0b1000)));
+        assert_eq!(int_op(0b1100, BinaryOp::BitOr, 0b1010, false), Ok(Value::Int(0b1110)));
+        assert_eq!(int_op(0b1100, BinaryOp::BitXor, 0b1010, false), Ok(Value::Int(0b0110)));
+        assert_eq!(int_op(1, BinaryOp::Shl, 63, false), Ok(Value::Int(i64::MIN)));
+        // Arithmetic, keeping the sign
+        assert_eq!(int_op(-16, BinaryOp::Shr, 2, false), Ok(Value::Int(-4)));
+        assert_eq!(int_op(1, BinaryOp::Shl, 64, false), Err(RuntimeError::Overflow));
+        assert_eq!(int_op(1, BinaryOp::Shl, 65, true), Ok(Value::Int(2)));
+        assert!(matches!(super::binary(&BinaryOp::BitAnd, Value::Float(1.0), Value::Int(1), false), Err(RuntimeError::TypeError(_))));
+
+        let inverted = Node::Program(vec![function("main", &[], vec![Node::Return(Some(Box::new(Node::Unary {
+            operator: UnaryOp::BitNot,
+            operand: int(0),
+        })))])]);
+        assert_eq!(Interpreter::new().run(&inverted), Ok(Value::Int(-1)));
+    }
+
+    #[test]
+    fn test_located_runtime_errors() {
+        let source = "function main {\n    return 1 / 0\n}";
+        let start = source.find("return").unwrap();
+        let divide = Node::Program(vec![function("main", &[], vec![Node::Located {
+            span: Span { start, end: source.len() - 2 },
+            node: Box::new(Node::Return(Some(binary(int(1), BinaryOp::Div, int(0))))),
+        }])]);
+        let result = Interpreter::new().with_source_map(SourceMap::new("main.gd", source)).run(&divide);
+        assert_eq!(result, Err(RuntimeError::DivisionByZero(Some(Location { line: 2, column: 5 }))));
+        assert_eq!(result.unwrap_err().to_string(), "Division by zero at line 2:5");
+
+        // Both are catchable
+        let length = Node::Member { object: Box::new(Node::NullLiteral), property: "length".to_string() };
+        let caught = Node::Program(vec![function("main", &[], vec![Node::Try {
+            body: Box::new(Node::Block(vec![Node::Return(Some(Box::new(length)))])),
+            catch_clauses: vec![Node::CatchClause {
+                param_name: "e".to_string(),
+                param_type: Type::String,
+                body: Box::new(Node::Block(vec![Node::Return(Some(Box::new(Node::Identifier("e".into()))))])),
+            }],
+            finally: None,
+        }])]);
+        assert_eq!(Interpreter::new().run(&caught), Ok(Value::String("Null dereference".to_string())));
+    }
+
+    #[test]
+    fn test_object_literals() {
+        let point = Node::Object { fields: vec![("x".to_string(), *int(3)), ("tag".to_string(), Node::StringLiteral("a".to_string()))] };
+        let field = |name: &str| Node::Program(vec![function("main", &[], vec![Node::Return(Some(Box::new(Node::Member {
+            object: Box::new(point.clone()),
+            property: name.to_string(),
+        })))])]);
+
+        assert_eq!(Interpreter::new().run(&field("x")), Ok(Value::Int(3)));
+        assert_eq!(Interpreter::new().run(&field("y")), Err(RuntimeError::TypeError("object has no field y".to_string())));
+        let object = Value::Object(vec![("x".to_string(), Value::Int(3)), ("tag".to_string(), Value::String("a".to_string()))]);
+        assert_eq!(object.to_string(), "{ x: 3, tag: \"a\" }");
+    }
+
+    #[test]
+    fn test_union_variants() {
+        let union = Node::Union { name: "Msg".to_string(), variants: vec![
+            UnionVariant { name: "Rename".to_string(), payload: vec![Type::String, Type::Int] },
+            UnionVariant::new("Logout"),
+        ] };
+        let case = |pattern: Node, value: Box<Node>| MatchCase { pattern, body: Node::Block(vec![Node::Return(Some(value))]) };
+        let handle = |message: Node| Node::Program(vec![union.clone(), function("main", &[], vec![Node::Match {
+            value: Box::new(message),
+            cases: vec![
+                case(call("Rename", vec![Node::StringLiteral("root".to_string()), *ident("_")]), int(0)),
+                case(call("Rename", vec![*ident("_"), *ident("id")]), ident("id")),
+                case(*ident("Logout"), int(-1)),
+            ],
+        }])]);
+
+        let rename = |name: &str| call("Rename", vec![Node::StringLiteral(name.to_string()), *int(7)]);
+        assert_eq!(Interpreter::new().run(&handle(rename("ada"))), Ok(Value::Int(7)));
+        assert_eq!(Interpreter::new().run(&handle(rename("root"))), Ok(Value::Int(0)));
+        assert_eq!(Interpreter::new().run(&handle(*ident("Logout"))), Ok(Value::Int(-1)));
+        assert_eq!(Interpreter::new().run(&handle(call("Rename", vec![]))),
+            Err(RuntimeError::ArityMismatch { function: "Rename".to_string(), expected: 2, found: 0 }));
+        let renamed = Value::Variant { name: "Rename".to_string(), payload: vec![Value::String("ada".to_string()), Value::Int(7)] };
+        assert_eq!(renamed.to_string(), "Rename(\"ada\", 7)");
+    }
+
+    #[test]
+    fn test_increment_places() {
+        let step = |operator: UnaryOp, operand: Box<Node>| Node::Unary { operator, operand };
+        let var = |name: &str, value: Node| Node::Let { name: name.to_string(), type_annotation: None, initializer: Some(Box::new(value)), is_mutable: true };
+        let element = |index: Box<Node>| Box::new(Node::Index { object: ident("xs"), index, checked: false });
+        let field = Box::new(Node::Member { object: ident("point"), property: "x".to_string() });
+        let program = Node::Program(vec![function("main", &[], vec![
+            var("xs", Node::Array { elements: vec![*int(10), *int(20)] }),
+            var("point", Node::Object { fields: vec![("x".to_string(), *int(3))] }),
+            var("total", *int(0)),
+            Node::For {
+                initializer: Some(Box::new(var("i", *int(0)))),
+                condition: Some(binary(ident("i"), BinaryOp::Lt, int(2))),
+                increment: Some(Box::new(step(UnaryOp::PostIncrement, ident("i")))),
+                body: Box::new(Node::Block(vec![step(UnaryOp::Increment, element(ident("i")))])),
+            },
+            call("print", vec![step(UnaryOp::PostDecrement, field.clone())]),
+            call("print", vec![step(UnaryOp::Increment, ident("total"))]),
+            Node::Return(Some(binary(binary(element(int(0)), BinaryOp::Add, element(int(1))), BinaryOp::Add, field))),
+        ])]);
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.run(&program), Ok(Value::Int(11 + 21 + 2)));
+        assert_eq!(interpreter.take_output(), "3\n1\n");
+
+        let past_end = Node::Program(vec![function("main", &[], vec![
+            var("xs", Node::Array { elements: vec![] }),
+            Node::Return(Some(Box::new(step(UnaryOp::PostIncrement, element(int(0)))))),
+        ])]);
+        assert!(matches!(Interpreter::new().run(&past_end), Err(RuntimeError::IndexOutOfBounds { index: 0, length: 0, .. })));
+    }
+
+    #[test]
+    fn test_index_bounds() {
+        let get = |index: i64| {
+            let array = Node::Array { elements: vec![*int(10), *int(20)] };
+            Node::Program(vec![function("main", &[], vec![Node::Try {
+                body: Box::new(Node::Block(vec![Node::Return(Some(Box::new(Node::Index {
+                    object: Box::new(array),
+                    index: int(index),
+                    checked: false,
+                })))])),
+                catch_clauses: vec![Node::CatchClause {
+                    param_name: "e".to_string(),
+                    param_type: Type::String,
+                    body: Box::new(Node::Block(vec![Node::Return(Some(int(0)))])),
+                }],
+                finally: None,
+            }])])
+        };
+
+        assert_eq!(Interpreter::new().run(&get(1)), Ok(Value::Int(20)));
+        // Checked even where native code elides the check, and not catchable
+        assert_eq!(
+            Interpreter::new().run(&get(2)),
+            Err(RuntimeError::IndexOutOfBounds { index: 2, length: 2, location: None }),
+        );
+        assert_eq!(
+            RuntimeError::IndexOutOfBounds { index: -1, length: 2, location: Some(Location { line: 4, column: 5 }) }.to_string(),
+            "Index -1 out of bounds for length 2 at line 4:5",
+        );
+    }
+
+    #[test]
+    fn test_string_characters() {
+        let text = || Box::new(Node::StringLiteral("añb".to_string()));
+        let at = |index: i64| Node::Index { object: text(), index: int(index), checked: true };
+        let program = Node::Program(vec![function("main", &[], vec![
+            Node::Foreach { item: "c".to_string(), collection: text(), body: Box::new(call("print", vec![*ident("c")])) },
+            Node::Return(Some(Box::new(at(1)))),
+        ])]);
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.run(&program), Ok(Value::String("ñ".to_string())));
+        assert_eq!(interpreter.take_output(), "a\nñ\nb\n");
+        let past_end = Node::Program(vec![function("main", &[], vec![Node::Return(Some(Box::new(at(3))))])]);
+        assert!(matches!(Interpreter::new().run(&past_end), Err(RuntimeError::IndexOutOfBounds { index: 3, length: 3, .. })));
+    }
+
+    #[test]
+    fn test_string_method_calls() {
+        let method = |receiver: Node, name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: Box::new(receiver), property: name.to_string() }),
+            arguments,
+        };
+        let text = Node::StringLiteral("  Gard ".to_string());
+        let upper = method(method(text, "trim", vec![]), "toUpper", vec![]);
+        let program = Node::Program(vec![function("main", &[], vec![Node::Return(Some(Box::new(upper)))])]);
+        assert_eq!(Interpreter::new().run(&program), Ok(Value::String("GARD".to_string())));
+
+        let on_int = Node::Program(vec![function("main", &[], vec![Node::Return(Some(Box::new(method(*int(1), "trim", vec![]))))])]);
+        assert_eq!(Interpreter::new().run(&on_int), Err(RuntimeError::TypeError("int has no method trim".to_string())));
+    }
+
+    #[test]
+    fn test_regex_calls() {
+        let regex = |name: &str, arguments: &[&str]| Node::Call {
+            callee: Box::new(Node::Member { object: ident("regex"), property: name.to_string() }),
+            arguments: arguments.iter().map(|argument| Node::StringLiteral(argument.to_string())).collect(),
+        };
+        let run = |body: Node| Interpreter::new().run(&Node::Program(vec![function("main", &[], vec![Node::Return(Some(Box::new(body)))])]));
+
+        assert_eq!(run(regex("replace", &[r"\s+", "a  b \t c", " "])), Ok(Value::String("a b c".to_string())));
+        assert!(matches!(run(regex("isMatch", &["[", "a"])), Err(error @ RuntimeError::InvalidPattern { .. }) if error.is_catchable()));
+    }
+
+    #[test]
+    fn test_datetime_calls() {
+        let datetime = |name: &str, argument: Node| Node::Call {
+            callee: Box::new(Node::Member { object: ident("datetime"), property: name.to_string() }),
+            arguments: vec![argument],
+        };
+        let date = |text: &str| datetime("parse", Node::StringLiteral(text.to_string()));
+        let run = |body: Node| Interpreter::new().run(&Node::Program(vec![function("main", &[], vec![Node::Return(Some(Box::new(body)))])]));
+
+        let tomorrow = binary(Box::new(date("2024-02-28T12:00:00Z")), BinaryOp::Add, Box::new(datetime("days", Node::IntLiteral(1))));
+        assert_eq!(run(datetime("format", *tomorrow)), Ok(Value::String("2024-02-29T12:00:00Z".to_string())));
+        assert!(matches!(run(date("someday")), Err(error @ RuntimeError::InvalidDateTime(_)) if error.is_catchable()));
+    }
+
+    #[test]
+    fn test_bytes_and_io_calls() {
+        let module = |module: &str, name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: ident(module), property: name.to_string() }),
+            arguments,
+        };
+        let from_hex = |text: &str| module("bytes", "fromHex", vec![Node::StringLiteral(text.to_string())]);
+        let run = |body: Node| Interpreter::new().run(&Node::Program(vec![function("main", &[], vec![Node::Return(Some(Box::new(body)))])]));
+
+        let joined = binary(Box::new(from_hex("0a0b")), BinaryOp::Add, Box::new(from_hex("0c")));
+        assert_eq!(run(*joined.clone()), Ok(Value::Bytes(vec![0x0a, 0x0b, 0x0c])));
+        assert_eq!(run(Node::Index { object: joined.clone(), index: int(2), checked: true }), Ok(Value::Int(12)));
+        assert_eq!(run(Node::Member { object: joined, property: "length".to_string() }), Ok(Value::Int(3)));
+        assert!(matches!(run(from_hex("0xzz")), Err(error @ RuntimeError::InvalidEncoding(_)) if error.is_catchable()));
+
+        let stdout = module("io", "stdout", vec![]);
+        let write = module("io", "writeLine", vec![stdout, Node::StringLiteral("hello".to_string())]);
+        let program = Node::Program(vec![function("main", &[], vec![Node::Block(vec![write])])]);
+        let mut interpreter = Interpreter::new();
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.output(), "hello\n");
+    }
+
+    #[test]
+    fn test_scope_waits_for_tasks() {
+        let print = |text: &str| call("print", vec![Node::StringLiteral(text.to_string())]);
+        let spawn = |argument: i64| Node::Spawn(Box::new(call("work", vec![Node::IntLiteral(argument)])));
+        let scope = |body: Vec<Node>| Node::Scope { body: Box::new(Node::Block(body)) };
+        let work = function("work", &["n"], vec![
+            Node::Assertion { kind: AssertionKind::Require, condition: binary(ident("n"), BinaryOp::Lt, int(2)), message: None },
+            call("print", vec![*ident("n")]),
+        ]);
+
+        let program = Node::Program(vec![work.clone(), function("main", &[], vec![
+            scope(vec![spawn(0), spawn(1), print("spawned")]),
+            print("done"),
+        ])]);
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.run(&program), Ok(Value::Null));
+        assert_eq!(interpreter.take_output(), "spawned\n0\n1\ndone\n");
+
+        // The failed task cancels the one after it, and the scope fails
+        let program = Node::Program(vec![work.clone(), function("main", &[], vec![
+            scope(vec![spawn(2), spawn(0)]),
+            print("done"),
+        ])]);
+        let mut interpreter = Interpreter::new();
+        let result = interpreter.run(&program);
+        assert_eq!(result, Err(RuntimeError::AssertionFailed { kind: AssertionKind::Require, message: None }));
+        assert_eq!(interpreter.take_output(), "");
+
+        // So does a failed body, before any task has run
+        let program = Node::Program(vec![work.clone(), function("main", &[], vec![
+            scope(vec![spawn(0), Node::Throw(int(7))]),
+        ])]);
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.run(&program), Err(RuntimeError::Thrown(Value::Int(7))));
+        assert_eq!(interpreter.take_output(), "");
+
+        let program = Node::Program(vec![work, function("main", &[], vec![spawn(0)])]);
+        assert_eq!(Interpreter::new().run(&program), Err(RuntimeError::SpawnOutsideScope));
+    }
+
+    #[test]
+    fn test_task_metrics() {
+        let channel = || Node::StringLiteral("jobs".to_string());
+        let spawn = |function: &str| Node::Spawn(Box::new(call(function, vec![])));
+        let program = Node::Program(vec![
+            function("producer", &[], vec![call("send", vec![channel(), *int(1)]), call("send", vec![channel(), *int(2)])]),
+            function("consumer", &[], vec![call("receive", vec![channel()])]),
+            function("failing", &[], vec![Node::Throw(int(0))]),
+            function("main", &[], vec![
+                Node::Scope { body: Box::new(Node::Block(vec![spawn("consumer"), spawn("producer")])) },
+                Node::Scope { body: Box::new(Node::Block(vec![spawn("failing")])) },
+            ]),
+        ]);
+        let exports = Rc::new(std::cell::Cell::new(0));
+        let counted = Rc::clone(&exports);
+        let mut interpreter = Interpreter::new().with_metrics_exporter(u64::MAX, move |_| counted.set(counted.get() + 1));
+        assert_eq!(interpreter.run(&program), Err(RuntimeError::Thrown(Value::Int(0))));
+
+        let metrics = interpreter.metrics();
+        assert_eq!((metrics.tasks_spawned, metrics.tasks_completed, metrics.tasks_failed), (3, 2, 1));
+        assert_eq!((metrics.messages_sent, metrics.messages_received), (2, 1));
+        assert_eq!(metrics.channel_depth.buckets[..2], [1, 1]);
+        assert_eq!(metrics.task_duration.count, 3);
+        // Only the final export, as the interval is never reached
+        assert_eq!(exports.get(), 1);
+    }
+
+    #[test]
+    fn test_deadlocks() {
+        let name = |name: &str| Node::StringLiteral(name.to_string());
+        let spawn = |function: &str| Node::Spawn(Box::new(call(function, vec![])));
+        let scope = |body: Vec<Node>| Node::Scope { body: Box::new(Node::Block(body)) };
+        let lock = |mutex: &str| call("acquire", vec![name(mutex)]);
+        let unlock = |mutex: &str| call("release", vec![name(mutex)]);
+        let run = |functions: Vec<Node>| Interpreter::new().run(&Node::Program(functions));
+
+        // The parent holds the mutex while it waits for the task that needs it
+        let result = run(vec![
+            function("work", &[], vec![lock("m")]),
+            function("main", &[], vec![lock("m"), scope(vec![spawn("work")])]),
+        ]);
+        let Err(RuntimeError::Deadlock(deadlock)) = result else { panic!("expected a deadlock, found {:?}", result) };
+        assert_eq!(deadlock.to_string(), "\
+Deadlock
+  task 1 (work) waits for mutex 'm', held by task 0 (main)
+    at work
+  task 0 (main) waits for the tasks of its scope
+    at main
+    at <top level>");
+
+        // A receive runs the tasks that haven't started, which can send
+        let mut interpreter = Interpreter::new();
+        let program = Node::Program(vec![
+            function("consume", &[], vec![call("print", vec![call("receive", vec![name("jobs")])])]),
+            function("produce", &[], vec![call("send", vec![name("jobs"), *int(42)])]),
+            function("main", &[], vec![scope(vec![spawn("consume"), spawn("produce")])]),
+        ]);
+        assert_eq!(interpreter.run(&program), Ok(Value::Null));
+        assert_eq!(interpreter.take_output(), "42\n");
+
+        let result = run(vec![
+            function("consume", &[], vec![call("receive", vec![name("jobs")])]),
+            function("main", &[], vec![scope(vec![spawn("consume")])]),
+        ]);
+        let Err(RuntimeError::Deadlock(deadlock)) = result else { panic!("expected a deadlock, found {:?}", result) };
+        let waits: Vec<String> = deadlock.tasks.iter().map(|blocked| blocked.waiting_for.to_string()).collect();
+        assert_eq!(waits, ["a message on channel 'jobs'", "the tasks of its scope"]);
+
+        let result = run(vec![
+            function("work", &[], vec![lock("m")]),
+            function("main", &[], vec![scope(vec![spawn("work")]), lock("m")]),
+        ]);
+        let Err(RuntimeError::Deadlock(deadlock)) = result else { panic!("expected a deadlock, found {:?}", result) };
+        assert_eq!(deadlock.tasks[0].waiting_for.to_string(), "mutex 'm', which task 1 (work) finished without releasing");
+        assert_eq!(run(vec![function("main", &[], vec![unlock("m")])]), Err(RuntimeError::MutexNotHeld("m".to_string())));
+    }
+
+    #[test]
+    fn test_lock_order_violations() {
+        let name = |name: &str| Node::StringLiteral(name.to_string());
+        let lock = |mutex: &str| call("acquire", vec![name(mutex)]);
+        let unlock = |mutex: &str| call("release", vec![name(mutex)]);
+        let spawn = |function: &str| Node::Spawn(Box::new(call(function, vec![])));
+        let program = Node::Program(vec![
+            function("deposit", &[], vec![lock("accounts"), lock("log"), unlock("log"), unlock("accounts")]),
+            function("audit", &[], vec![lock("log"), lock("accounts"), unlock("accounts"), unlock("log")]),
+            function("main", &[], vec![Node::Scope { body: Box::new(Node::Block(vec![spawn("deposit"), spawn("audit")])) }]),
+        ]);
+
+        // This schedule runs the two one after the other, so only the debug runtime notices
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.run(&program), Ok(Value::Null));
+        assert!(interpreter.lock_order_violations().is_empty());
+
+        let mut interpreter = Interpreter::new().with_debug_runtime();
+        assert_eq!(interpreter.run(&program), Ok(Value::Null));
+        let violations = interpreter.lock_order_violations();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].to_string(), "\
+Potential deadlock: mutexes acquired in the order accounts -> log -> accounts
+  task 1 (deposit) acquired 'log' while holding 'accounts'
+    at deposit
+  task 2 (audit) acquired 'accounts' while holding 'log'
+    at audit");
+    }
+
+    #[test]
+    fn test_replay_schedule() {
+        let channel = || Node::StringLiteral("jobs".to_string());
+        let spawn = |function: &str| Node::Spawn(Box::new(call(function, vec![])));
+        let program = Node::Program(vec![
+            function("first", &[], vec![call("send", vec![channel(), *int(1)])]),
+            function("second", &[], vec![call("send", vec![channel(), *int(2)])]),
+            function("consume", &[], vec![call("print", vec![call("receive", vec![channel()])])]),
+            function("main", &[], vec![Node::Scope { body: Box::new(Node::Block(vec![spawn("first"), spawn("second"), spawn("consume")])) }]),
+        ]);
+
+        let mut interpreter = Interpreter::new().with_recorded_schedule();
+        assert_eq!(interpreter.run(&program), Ok(Value::Null));
+        assert_eq!(interpreter.take_output(), "1\n");
+        let log = replay::to_log(interpreter.recorded_schedule());
+        assert_eq!(log, "start 1 first\nstart 2 second\nstart 3 consume\ndeliver jobs 3\n");
+
+        let replay = |log: &str| {
+            let mut interpreter = Interpreter::new().with_replayed_schedule(replay::parse_log(log).unwrap());
+            interpreter.run(&program).map(|_| interpreter.take_output())
+        };
+        assert_eq!(replay(&log), Ok("1\n".to_string()));
+        // The other order of the senders, which the consumer sees
+        assert_eq!(replay("start 2 second\nstart 1 first\nstart 3 consume\ndeliver jobs 3\n"), Ok("2\n".to_string()));
+
+        let diverged = |reason: &str| Err(RuntimeError::ReplayDiverged(reason.to_string()));
+        assert_eq!(replay("start 4 consume"), diverged("the log starts task 4, which isn't waiting to start"));
+        assert_eq!(replay("start 1 first\nstart 2 second"), diverged("a task starts after the end of the log"));
+        assert_eq!(replay(&format!("{}start 5 more", log)), diverged("the run ended before 'start 5 more', with 1 event(s) of the log left"));
+    }
+
+    #[test]
+    fn test_introspection() {
+        let name = |name: &str| Node::StringLiteral(name.to_string());
+        let spawn = |function: &str| Node::Spawn(Box::new(call(function, vec![])));
+        let program = Node::Program(vec![
+            function("consume", &[], vec![call("receive", vec![name("jobs")])]),
+            function("produce", &[], vec![call("acquire", vec![name("log")]), call("send", vec![name("jobs"), *int(1)]), call("release", vec![name("log")])]),
+            function("main", &[], vec![Node::Scope { body: Box::new(Node::Block(vec![spawn("consume"), spawn("produce"), spawn("consume")])) }]),
+        ]);
+        let snapshots = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let published = Rc::clone(&snapshots);
+        let mut interpreter = Interpreter::new().with_introspection(1, move |snapshot| published.borrow_mut().push(snapshot));
+        // The second consumer never gets a message
+        assert!(matches!(interpreter.run(&program), Err(RuntimeError::Deadlock(_))));
+
+        let snapshots = snapshots.borrow();
+        // When the producer sends, started by the first consumer's receive
+        let sending = snapshots.iter()
+            .find(|snapshot| snapshot.channels.iter().any(|(_, messages)| *messages == 1))
+            .unwrap();
+        let view = sending.to_string();
+        assert_eq!(view.lines().skip(1).collect::<Vec<_>>(), [
+            "Tasks: 4 (0 completed, 0 failed)",
+            "  task 0 (main): waits for the tasks of its scope",
+            "    task 1 (consume): waits for a message on channel 'jobs'",
+            "    task 2 (produce): running",
+            "    task 3 (consume): not started",
+            "Channels:",
+            "  jobs: 1 message(s)",
+            "Mutexes:",
+            "  log: held by task 2 (produce)",
+        ]);
+        // And the last when the run ended
+        let last = snapshots.last().unwrap();
+        assert_eq!(last.tasks, [TaskInfo { task: TaskId { id: 0, function: "main".to_string() }, parent: None, state: TaskState::Running }]);
+        assert_eq!((last.tasks_completed, last.tasks_failed), (2, 0));
+    }
+
+    #[test]
+    fn test_streams() {
+        let stream = |name: &str, arguments: Vec<Node>| Node::Call {
+            callee: Box::new(Node::Member { object: ident("stream"), property: name.to_string() }),
+            arguments,
+        };
+        let name = |name: &str| Node::StringLiteral(name.to_string());
+        let let_stream = |variable: &str, value: Node| Node::Let { name: variable.to_string(), type_annotation: None, initializer: Some(Box::new(value)), is_mutable: false };
+        let square_below_6 = function("squares", &["i"], vec![
+            call("print", vec![*ident("i")]),
+            Node::If {
+                condition: binary(ident("i"), BinaryOp::Lt, int(6)),
+                then_branch: Box::new(Node::Block(vec![Node::Return(Some(binary(ident("i"), BinaryOp::Mul, ident("i"))))])),
+                else_branch: None,
+            },
+        ]);
+        let even = function("even", &["n"], vec![Node::Return(Some(binary(binary(ident("n"), BinaryOp::Mod, int(2)), BinaryOp::Eq, int(0))))]);
+
+        // The generator only runs for the elements pulled
+        let program = Node::Program(vec![square_below_6.clone(), even, function("main", &[], vec![
+            let_stream("evens", stream("filter", vec![stream("generate", vec![name("squares")]), name("even")])),
+            call("print", vec![stream("take", vec![*ident("evens"), *int(2)])]),
+            Node::Return(Some(Box::new(stream("forEach", vec![*ident("evens"), name("print")])))),
+        ])]);
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.run(&program), Ok(Value::Int(1)));
+        assert_eq!(interpreter.take_output(), "0\n1\n2\n[0, 4]\n3\n4\n16\n5\n6\n");
+
+        // A buffer pulls ahead
+        let program = Node::Program(vec![square_below_6, function("main", &[], vec![
+            Node::Return(Some(Box::new(stream("next", vec![stream("buffer", vec![stream("generate", vec![name("squares")]), *int(3)])])))),
+        ])]);
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.run(&program), Ok(Value::Int(0)));
+        assert_eq!(interpreter.take_output(), "0\n1\n2\n");
+
+        // A channel's messages, at most one per 100 steps; the stream ends
+        // when no task is left to send more
+        let program = Node::Program(vec![
+            function("produce", &[], (1..=3).map(|message| call("send", vec![name("jobs"), *int(message)])).collect()),
+            function("main", &[], vec![Node::Scope { body: Box::new(Node::Block(vec![
+                Node::Spawn(Box::new(call("produce", vec![]))),
+                let_stream("jobs", stream("throttle", vec![stream("fromChannel", vec![name("jobs")]), *int(1), *int(100)])),
+                call("print", vec![stream("forEach", vec![*ident("jobs"), name("print")])]),
+            ])) }]),
+        ]);
+        let mut interpreter = Interpreter::new();
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.take_output(), "1\n2\n3\n3\n");
+        assert!(interpreter.snapshot().steps >= 200);
+    }
+
+    #[test]
+    fn test_priorities_and_stash() {
+        let inbox = || Node::StringLiteral("inbox".to_string());
+        let send = |message: i64| call("send", vec![inbox(), *int(message)]);
+        let receive = || call("receive", vec![inbox()]);
+        let program = Node::Program(vec![
+            function("urgency", &["message"], vec![Node::Return(Some(binary(ident("message"), BinaryOp::Div, int(10))))]),
+            function("main", &[], [
+                vec![call("prioritize", vec![inbox(), Node::StringLiteral("urgency".to_string())])],
+                vec![send(5), send(31), send(12), send(35)],
+                // Set aside the two most urgent, then take them back
+                vec![call("stash", vec![inbox(), receive()]), call("stash", vec![inbox(), receive()])],
+                vec![call("print", vec![call("unstashAll", vec![inbox()])]), send(39)],
+                (0..5).map(|_| call("print", vec![receive()])).collect(),
+            ].concat()),
+        ]);
+        let mut interpreter = Interpreter::new();
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.take_output(), "2\n31\n35\n39\n12\n5\n");
+    }
+
+    #[test]
+    fn test_guardian() {
+        let attempts = || Node::StringLiteral("attempts".to_string());
+        // Fails until its third attempt
+        let worker = function("worker", &[], vec![
+            Node::Let { name: "attempt".to_string(), type_annotation: None, initializer: Some(Box::new(call("receive", vec![attempts()]))), is_mutable: false },
+            call("print", vec![*ident("attempt")]),
+            Node::Assertion { kind: AssertionKind::Require, condition: binary(ident("attempt"), BinaryOp::GtEq, int(2)), message: None },
+        ]);
+        let program = Node::Program(vec![worker, function("main", &[], vec![
+            Node::Block((0..4).map(|attempt| call("send", vec![attempts(), *int(attempt)])).collect()),
+            Node::Scope { body: Box::new(Node::Block(vec![Node::Spawn(Box::new(call("worker", vec![])))])) },
+        ])]);
+        let run = |guardian: Guardian| {
+            let mut interpreter = Interpreter::new().with_guardian(guardian);
+            let result = interpreter.run(&program);
+            let directives: Vec<Directive> = interpreter.supervised().iter().map(|supervised| supervised.directive).collect();
+            (result.is_ok(), interpreter.take_output(), directives)
+        };
+
+        assert_eq!(run(Guardian::Shutdown), (false, "0\n".to_string(), vec![]));
+        assert_eq!(run(Guardian::Stop), (true, "0\n".to_string(), vec![Directive::Stop]));
+        assert_eq!(run(Guardian::Restart { max_restarts: 3 }), (true, "0\n1\n2\n".to_string(), vec![Directive::Restart; 2]));
+        assert_eq!(run(Guardian::Restart { max_restarts: 1 }), (false, "0\n1\n".to_string(), vec![Directive::Restart]));
+        let once = Guardian::Custom(Box::new(|_, _, restarts| if restarts == 0 { Directive::Restart } else { Directive::Stop }));
+        assert_eq!(run(once), (true, "0\n1\n".to_string(), vec![Directive::Restart, Directive::Stop]));
+
+        let mut interpreter = Interpreter::new().with_guardian(Guardian::Stop);
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.supervised()[0].to_string(), "task 1 (worker) failed and was stopped: Requirement failed");
+    }
+
+    #[test]
+    fn test_task_locals() {
+        let increment = || Node::Unary { operator: UnaryOp::Increment, operand: ident("count") };
+        let go = || Node::StringLiteral("go".to_string());
+        // `first` waits for `second` in the middle of using its own count
+        let program = Node::Program(vec![
+            Node::TaskLocal { name: "count".to_string(), type_annotation: None, initializer: int(0) },
+            function("first", &[], vec![increment(), increment(), call("receive", vec![go()]), call("print", vec![*ident("count")])]),
+            function("second", &[], vec![increment(), call("send", vec![go(), *int(0)]), call("print", vec![*ident("count")])]),
+            function("main", &[], vec![
+                Node::Scope { body: Box::new(Node::Block(vec![
+                    Node::Spawn(Box::new(call("first", vec![]))),
+                    Node::Spawn(Box::new(call("second", vec![]))),
+                ])) },
+                increment(),
+                call("print", vec![*ident("count")]),
+            ]),
+        ]);
+        let mut interpreter = Interpreter::new();
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.take_output(), "1\n2\n1\n");
+        assert!(interpreter.locals.keys().all(|&task| task == 0));
+    }
+
+    #[test]
+    fn test_template_strings() {
+        let text = |text: &str| Node::StringLiteral(text.to_string());
+        let program = Node::Program(vec![function("main", &[], vec![
+            Node::Let { name: "scores".to_string(), type_annotation: None, initializer: Some(Box::new(Node::Array { elements: vec![*int(1), *int(2)] })), is_mutable: false },
+            call("print", vec![Node::TemplateString { parts: vec![
+                text("scores: "),
+                *ident("scores"),
+                text(", total "),
+                Node::TemplateString { parts: vec![*binary(int(1), BinaryOp::Add, int(2))] },
+            ] }]),
+        ])]);
+        let mut interpreter = Interpreter::new();
+        interpreter.run(&program).unwrap();
+        assert_eq!(interpreter.take_output(), "scores: [1, 2], total 3\n");
+    }
+
+    #[test]
+    fn test_resuming_from_a_checkpoint() {
+        let log = || Node::StringLiteral("log".to_string());
+        let program = Node::Program(vec![
+            Node::Let { name: "visits".to_string(), type_annotation: None, initializer: Some(int(0)), is_mutable: true },
+            function("main", &[], vec![
+                Node::Unary { operator: UnaryOp::Increment, operand: ident("visits") },
+                call("send", vec![log(), *ident("visits")]),
+                call("print", vec![*ident("visits")]),
+            ]),
+        ]);
+        let mut interpreter = Interpreter::new();
+        interpreter.run(&program).unwrap();
+        let text = interpreter.checkpoint().unwrap().to_text();
+
+        let mut resumed = Interpreter::new().with_checkpoint(Checkpoint::from_text(&text).unwrap());
+        resumed.run(&program).unwrap();
+        assert_eq!(resumed.take_output(), "2\n");
+        let checkpoint = resumed.checkpoint().unwrap();
+        assert_eq!(checkpoint.globals, [("visits".to_string(), Value::Int(2))]);
+        assert_eq!(checkpoint.messages, [("log".to_string(), 0, Value::Int(1)), ("log".to_string(), 0, Value::Int(2))]);
+        assert!(checkpoint.steps > interpreter.checkpoint().unwrap().steps);
+
+        // The checkpoint replaces what the top level set up, priorities too
+        let prioritized = Node::Program(vec![call("prioritize", vec![log(), Node::StringLiteral("urgency".to_string())])]);
+        let mut resumed = Interpreter::new().with_checkpoint(Checkpoint::from_text(&text).unwrap());
+        resumed.run(&prioritized).unwrap();
+        assert!(resumed.checkpoint().unwrap().priorities.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+//! Snapshots of a running program's task runtime, for inspecting a live
+//! program: its tasks as a tree of who spawned whom, what each one waits
+//! for, the messages waiting in each channel and who holds each mutex.
+//!
+//! A task's parent is the task that spawned it, which supervises it: the
+//! parent's scope waits for the task and fails when it does.
+
+use crate::sync::{TaskId, Wait};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskState {
+    Running,
+    /// Started, and waiting for the task above it
+    Waiting(Wait),
+    /// Spawned and not started yet
+    Pending,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskInfo {
+    pub task: TaskId,
+    /// The id of the task that spawned it; none for the program itself
+    pub parent: Option<usize>,
+    pub state: TaskState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// Steps run so far
+    pub steps: u64,
+    /// The tasks that have started and not finished, in the order they
+    /// started, then the ones waiting to start
+    pub tasks: Vec<TaskInfo>,
+    /// Messages waiting in each channel, by name
+    pub channels: Vec<(String, usize)>,
+    /// Locked mutexes, by name, and the task holding each
+    pub mutexes: Vec<(String, TaskId)>,
+    pub tasks_completed: u64,
+    /// Tasks that ended with an error, failing their parent's scope
+    pub tasks_failed: u64,
+}
+
+impl Snapshot {
+    /// The JSON served by `gard run --introspect` at `/json`.
+    #[cfg(feature = "introspect")]
+    pub fn to_json(&self) -> String {
+        use serde_json::json;
+
+        let tasks: Vec<serde_json::Value> = self.tasks.iter().map(|info| {
+            let (state, waiting_for) = match &info.state {
+                TaskState::Running => ("running", None),
+                TaskState::Waiting(wait) => ("waiting", Some(wait.to_string())),
+                TaskState::Pending => ("pending", None),
+            };
+            json!({
+                "id": info.task.id,
+                "function": info.task.function,
+                "parent": info.parent,
+                "state": state,
+                "waitingFor": waiting_for,
+            })
+        }).collect();
+        json!({
+            "steps": self.steps,
+            "tasks": tasks,
+            "channels": self.channels.iter().map(|(name, messages)| json!({ "name": name, "messages": messages })).collect::<Vec<_>>(),
+            "mutexes": self.mutexes.iter().map(|(name, holder)| json!({ "name": name, "holder": holder.id })).collect::<Vec<_>>(),
+            "tasksCompleted": self.tasks_completed,
+            "tasksFailed": self.tasks_failed,
+        }).to_string()
+    }
+
+    fn write_tree(&self, f: &mut fmt::Formatter<'_>, parent: Option<usize>, depth: usize) -> fmt::Result {
+        for info in self.tasks.iter().filter(|info| info.parent == parent) {
+            let state = match &info.state {
+                TaskState::Running => "running".to_string(),
+                TaskState::Waiting(wait) => format!("waits for {}", wait),
+                TaskState::Pending => "not started".to_string(),
+            };
+            writeln!(f, "{:indent$}{}: {}", "", info.task, state, indent = 2 * depth + 2)?;
+            self.write_tree(f, Some(info.task.id), depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// The view of `gard top`.
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Steps: {}", self.steps)?;
+        writeln!(f, "Tasks: {} ({} completed, {} failed)", self.tasks.len(), self.tasks_completed, self.tasks_failed)?;
+        self.write_tree(f, None, 0)?;
+        if !self.channels.is_empty() {
+            writeln!(f, "Channels:")?;
+            for (name, messages) in &self.channels {
+                writeln!(f, "  {}: {} message(s)", name, messages)?;
+            }
+        }
+        if !self.mutexes.is_empty() {
+            writeln!(f, "Mutexes:")?;
+            for (name, holder) in &self.mutexes {
+                writeln!(f, "  {}: held by {}", name, holder)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: usize, function: &str) -> TaskId {
+        TaskId { id, function: function.to_string() }
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            steps: 120,
+            tasks: vec![
+                TaskInfo { task: task(0, "main"), parent: None, state: TaskState::Waiting(Wait::Tasks) },
+                TaskInfo { task: task(1, "consume"), parent: Some(0), state: TaskState::Waiting(Wait::Message { channel: "jobs".to_string() }) },
+                TaskInfo { task: task(3, "produce"), parent: Some(1), state: TaskState::Running },
+                TaskInfo { task: task(2, "audit"), parent: Some(0), state: TaskState::Pending },
+            ],
+            channels: vec![("jobs".to_string(), 0)],
+            mutexes: vec![("log".to_string(), task(3, "produce"))],
+            tasks_completed: 4,
+            tasks_failed: 1,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_view() {
+        assert_eq!(snapshot().to_string(), "\
+Steps: 120
+Tasks: 4 (4 completed, 1 failed)
+  task 0 (main): waits for the tasks of its scope
+    task 1 (consume): waits for a message on channel 'jobs'
+      task 3 (produce): running
+    task 2 (audit): not started
+Channels:
+  jobs: 0 message(s)
+Mutexes:
+  log: held by task 3 (produce)
+");
+    }
+
+    #[cfg(feature = "introspect")]
+    #[test]
+    fn test_snapshot_json() {
+        let json: serde_json::Value = serde_json::from_str(&snapshot().to_json()).unwrap();
+        assert_eq!(json["tasks"][1], serde_json::json!({
+            "id": 1,
+            "function": "consume",
+            "parent": 0,
+            "state": "waiting",
+            "waitingFor": "a message on channel 'jobs'",
+        }));
+        assert_eq!(json["tasks"][0]["parent"], serde_json::Value::Null);
+        assert_eq!(json["mutexes"][0]["holder"], 3);
+        assert_eq!(json["tasksFailed"], 1);
+    }
+}
@@ -0,0 +1,187 @@
+//! `std.io`, called as `io.readLine(reader)`. Like in native code readers
+//! and writers are int handles, where 0 is stdin and 1 is stdout. Writes
+//! to stdout go wherever `print` output goes.
+
+use crate::interpreter::RuntimeError;
+use crate::value::Value;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+pub const STDIN: i64 = 0;
+pub const STDOUT: i64 = 1;
+
+enum Stream {
+    Reader(Box<dyn BufRead>),
+    Writer(BufWriter<File>),
+    /// The interpreter's output, which isn't buffered here
+    Output,
+}
+
+/// Open streams, indexed by handle.
+pub struct Streams {
+    open: Vec<Option<Stream>>,
+}
+
+impl Default for Streams {
+    fn default() -> Self {
+        Self { open: vec![Some(Stream::Reader(Box::new(BufReader::new(io::stdin())))), Some(Stream::Output)] }
+    }
+}
+
+impl Streams {
+    /// Number of arguments `io.name` takes, if it's an io function.
+    pub fn arity(name: &str) -> Option<usize> {
+        match name {
+            "stdin" | "stdout" => Some(0),
+            "openReader" | "openWriter" | "readLine" | "flush" | "close" => Some(1),
+            "read" | "write" | "writeLine" => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Calls `io.function`, passing what is written to stdout to `output`.
+    /// `readLine` returns null at the end of the stream.
+    pub fn call(&mut self, function: &str, arguments: Vec<Value>, output: &mut dyn FnMut(&[u8])) -> Result<Value, RuntimeError> {
+        let expected = Self::arity(function)
+            .ok_or_else(|| RuntimeError::UndefinedFunction(format!("io.{}", function)))?;
+        if arguments.len() != expected {
+            return Err(RuntimeError::ArityMismatch { function: format!("io.{}", function), expected, found: arguments.len() });
+        }
+        match (function, arguments.as_slice()) {
+            ("stdin", []) => Ok(Value::Int(STDIN)),
+            ("stdout", []) => Ok(Value::Int(STDOUT)),
+            ("openReader", [Value::String(path)]) => {
+                let file = File::open(path).map_err(|e| failed(path, e))?;
+                Ok(Value::Int(self.open(Stream::Reader(Box::new(BufReader::new(file))))))
+            },
+            ("openWriter", [Value::String(path)]) => {
+                let file = File::create(path).map_err(|e| failed(path, e))?;
+                Ok(Value::Int(self.open(Stream::Writer(BufWriter::new(file)))))
+            },
+            ("readLine", [Value::Int(handle)]) => {
+                let mut line = String::new();
+                if self.reader(*handle)?.read_line(&mut line).map_err(|e| failed(handle, e))? == 0 {
+                    return Ok(Value::Null);
+                }
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Ok(Value::String(line))
+            },
+            ("read", [Value::Int(handle), Value::Int(count)]) => {
+                let mut data = Vec::new();
+                self.reader(*handle)?.take((*count).max(0) as u64).read_to_end(&mut data).map_err(|e| failed(handle, e))?;
+                Ok(Value::Bytes(data))
+            },
+            ("write", [Value::Int(handle), Value::Bytes(data)]) => self.write(*handle, data, output),
+            ("writeLine", [Value::Int(handle), Value::String(line)]) => self.write(*handle, format!("{}\n", line).as_bytes(), output),
+            ("flush", [Value::Int(handle)]) => {
+                if let Stream::Writer(writer) = self.writer(*handle)? {
+                    writer.flush().map_err(|e| failed(handle, e))?;
+                }
+                Ok(Value::Null)
+            },
+            // The standard streams stay open
+            ("close", [Value::Int(handle)]) => {
+                if let Stream::Writer(writer) = self.stream(*handle)? {
+                    writer.flush().map_err(|e| failed(handle, e))?;
+                }
+                if *handle > STDOUT {
+                    self.open[*handle as usize] = None;
+                }
+                Ok(Value::Null)
+            },
+            _ => {
+                let found: Vec<&str> = arguments.iter().map(Value::type_name).collect();
+                Err(RuntimeError::TypeError(format!("io.{} can't take {}", function, found.join(", "))))
+            },
+        }
+    }
+
+    fn open(&mut self, stream: Stream) -> i64 {
+        // Reuse the slot of a closed stream
+        match self.open.iter().position(Option::is_none) {
+            Some(handle) => {
+                self.open[handle] = Some(stream);
+                handle as i64
+            },
+            None => {
+                self.open.push(Some(stream));
+                self.open.len() as i64 - 1
+            },
+        }
+    }
+
+    fn stream(&mut self, handle: i64) -> Result<&mut Stream, RuntimeError> {
+        usize::try_from(handle).ok()
+            .and_then(|handle| self.open.get_mut(handle)?.as_mut())
+            .ok_or_else(|| RuntimeError::Io(format!("Stream {} is not open", handle)))
+    }
+
+    fn reader(&mut self, handle: i64) -> Result<&mut dyn BufRead, RuntimeError> {
+        match self.stream(handle)? {
+            Stream::Reader(reader) => Ok(reader.as_mut()),
+            _ => Err(RuntimeError::Io(format!("Stream {} is a writer", handle))),
+        }
+    }
+
+    fn writer(&mut self, handle: i64) -> Result<&mut Stream, RuntimeError> {
+        match self.stream(handle)? {
+            Stream::Reader(_) => Err(RuntimeError::Io(format!("Stream {} is a reader", handle))),
+            writer => Ok(writer),
+        }
+    }
+
+    fn write(&mut self, handle: i64, data: &[u8], output: &mut dyn FnMut(&[u8])) -> Result<Value, RuntimeError> {
+        match self.writer(handle)? {
+            Stream::Writer(writer) => writer.write_all(data).map_err(|e| failed(&handle, e))?,
+            _ => output(data),
+        }
+        Ok(Value::Null)
+    }
+}
+
+fn failed(stream: &dyn std::fmt::Display, error: io::Error) -> RuntimeError {
+    RuntimeError::Io(format!("{}: {}", stream, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffered_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("gard-interp-io-{}.txt", std::process::id()));
+        let path = Value::String(path.to_str().unwrap().to_string());
+        let mut streams = Streams::default();
+        let mut call = |function: &str, arguments: Vec<Value>| streams.call(function, arguments, &mut |_| panic!("not stdout"));
+
+        let writer = call("openWriter", vec![path.clone()]).unwrap();
+        call("writeLine", vec![writer.clone(), Value::String("first".to_string())]).unwrap();
+        call("write", vec![writer.clone(), Value::Bytes(b"second\r\n\x00\x01".to_vec())]).unwrap();
+        call("close", vec![writer.clone()]).unwrap();
+        assert!(matches!(call("flush", vec![writer]), Err(RuntimeError::Io(_))));
+
+        let reader = call("openReader", vec![path.clone()]).unwrap();
+        assert_eq!(call("readLine", vec![reader.clone()]), Ok(Value::String("first".to_string())));
+        assert_eq!(call("readLine", vec![reader.clone()]), Ok(Value::String("second".to_string())));
+        assert_eq!(call("read", vec![reader.clone(), Value::Int(8)]), Ok(Value::Bytes(vec![0, 1])));
+        assert_eq!(call("readLine", vec![reader.clone()]), Ok(Value::Null));
+        assert!(matches!(call("write", vec![reader, Value::Bytes(vec![])]), Err(RuntimeError::Io(message)) if message.contains("is a reader")));
+        if let Value::String(path) = path {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_stdout_goes_to_output() {
+        let mut streams = Streams::default();
+        let mut written = Vec::new();
+        streams.call("writeLine", vec![Value::Int(STDOUT), Value::String("hi".to_string())], &mut |data| written.extend_from_slice(data)).unwrap();
+        assert_eq!(written, b"hi\n");
+        assert!(matches!(streams.call("openReader", vec![Value::String("/no/such/file".to_string())], &mut |_| {}), Err(RuntimeError::Io(_))));
+    }
+}
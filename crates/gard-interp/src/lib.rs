@@ -0,0 +1,27 @@
+pub mod bytes;
+pub mod checkpoint;
+pub mod datetime;
+pub mod debugger;
+pub mod guardian;
+pub mod interpreter;
+pub mod introspect;
+pub mod io;
+pub mod metrics;
+pub mod regex;
+pub mod reload;
+pub mod replay;
+pub mod shard;
+pub mod simulate;
+pub mod stream;
+pub mod strings;
+pub mod sync;
+pub mod value;
+
+#[cfg(feature = "playground")]
+pub mod playground;
+
+pub use debugger::{DebugHandler, Debugger, StepCommand};
+pub use interpreter::{Interpreter, RuntimeError};
+pub use metrics::Metrics;
+pub use reload::{ReloadSummary, SourceWatcher};
+pub use value::Value;
@@ -0,0 +1,177 @@
+//! Counters and histograms of the task runtime: tasks spawned and how they
+//! ended, mutex acquisitions, channel traffic and depth, and how long tasks
+//! wait and run. Times are counted in interpreter steps, the same clock as
+//! the step limit, which keeps them deterministic and available in the
+//! browser where there is no system clock.
+
+use std::fmt;
+
+/// Upper bounds of the step histograms' buckets
+const STEP_BOUNDS: &[u64] = &[1, 10, 100, 1_000, 10_000, 100_000, 1_000_000];
+/// Upper bounds of the channel depth histogram's buckets
+const DEPTH_BOUNDS: &[u64] = &[1, 2, 4, 8, 16, 64, 256, 1_024];
+
+/// Counts of values up to each bound, with one more bucket for values above
+/// the last, like an OpenTelemetry explicit-bucket histogram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub bounds: &'static [u64],
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64]) -> Self {
+        Self { bounds, buckets: vec![0; bounds.len() + 1], count: 0, sum: 0 }
+    }
+
+    pub fn record(&mut self, value: u64) {
+        let bucket = self.bounds.iter().position(|bound| value <= *bound).unwrap_or(self.bounds.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    pub tasks_spawned: u64,
+    pub tasks_completed: u64,
+    /// Tasks that ended with an error, which fails their scope
+    pub tasks_failed: u64,
+    pub mutex_acquisitions: u64,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    /// Messages waiting in a channel after each send
+    pub channel_depth: Histogram,
+    /// Steps from `spawn` until the task started
+    pub task_latency: Histogram,
+    /// Steps a task ran for
+    pub task_duration: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            tasks_spawned: 0,
+            tasks_completed: 0,
+            tasks_failed: 0,
+            mutex_acquisitions: 0,
+            messages_sent: 0,
+            messages_received: 0,
+            channel_depth: Histogram::new(DEPTH_BOUNDS),
+            task_latency: Histogram::new(STEP_BOUNDS),
+            task_duration: Histogram::new(STEP_BOUNDS),
+        }
+    }
+}
+
+impl Metrics {
+    /// Each counter by its exported name.
+    pub fn counters(&self) -> [(&'static str, u64); 6] {
+        [
+            ("gard.tasks.spawned", self.tasks_spawned),
+            ("gard.tasks.completed", self.tasks_completed),
+            ("gard.tasks.failed", self.tasks_failed),
+            ("gard.mutex.acquisitions", self.mutex_acquisitions),
+            ("gard.channel.sent", self.messages_sent),
+            ("gard.channel.received", self.messages_received),
+        ]
+    }
+
+    /// Each histogram by its exported name, with its unit.
+    pub fn histograms(&self) -> [(&'static str, &'static str, &Histogram); 3] {
+        [
+            ("gard.channel.depth", "{message}", &self.channel_depth),
+            ("gard.task.latency", "{step}", &self.task_latency),
+            ("gard.task.duration", "{step}", &self.task_duration),
+        ]
+    }
+
+    /// An OTLP/HTTP JSON export request with every metric as a cumulative
+    /// data point, to POST to a collector's `/v1/metrics`.
+    #[cfg(feature = "otlp")]
+    pub fn to_otlp_json(&self, service: &str, time_unix_nano: u64) -> String {
+        use serde_json::json;
+
+        // OTLP JSON encodes 64-bit integers as strings
+        let time = time_unix_nano.to_string();
+        let mut metrics: Vec<serde_json::Value> = self.counters().iter().map(|(name, value)| json!({
+            "name": name,
+            "sum": {
+                "dataPoints": [{ "asInt": value.to_string(), "timeUnixNano": time }],
+                "aggregationTemporality": 2,
+                "isMonotonic": true,
+            },
+        })).collect();
+        metrics.extend(self.histograms().iter().map(|(name, unit, histogram)| json!({
+            "name": name,
+            "unit": unit,
+            "histogram": {
+                "dataPoints": [{
+                    "count": histogram.count.to_string(),
+                    "sum": histogram.sum,
+                    "bucketCounts": histogram.buckets.iter().map(u64::to_string).collect::<Vec<_>>(),
+                    "explicitBounds": histogram.bounds,
+                    "timeUnixNano": time,
+                }],
+                "aggregationTemporality": 2,
+            },
+        })));
+        json!({
+            "resourceMetrics": [{
+                "resource": { "attributes": [{ "key": "service.name", "value": { "stringValue": service } }] },
+                "scopeMetrics": [{ "scope": { "name": "gard-interp" }, "metrics": metrics }],
+            }],
+        }).to_string()
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.counters() {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+        for (name, _, histogram) in self.histograms() {
+            match histogram.mean() {
+                Some(mean) => writeln!(f, "{}: {} recorded, mean {:.1}", name, histogram.count, mean)?,
+                None => writeln!(f, "{}: none recorded", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut histogram = Histogram::new(&[1, 10]);
+        for value in [0, 1, 5, 10, 11, 500] {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.buckets, vec![2, 2, 2]);
+        assert_eq!((histogram.count, histogram.sum), (6, 527));
+        assert_eq!(Histogram::new(&[1]).mean(), None);
+    }
+
+    #[cfg(feature = "otlp")]
+    #[test]
+    fn test_otlp_json() {
+        let mut metrics = Metrics { tasks_spawned: 3, ..Metrics::default() };
+        metrics.task_latency.record(20);
+        let export: serde_json::Value = serde_json::from_str(&metrics.to_otlp_json("worker", 7)).unwrap();
+        let exported = &export["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(exported[0]["name"], "gard.tasks.spawned");
+        assert_eq!(exported[0]["sum"]["dataPoints"][0]["asInt"], "3");
+        assert_eq!(exported[7]["histogram"]["dataPoints"][0]["bucketCounts"][2], "1");
+        assert_eq!(export["resourceMetrics"][0]["resource"]["attributes"][0]["value"]["stringValue"], "worker");
+    }
+}
@@ -0,0 +1,168 @@
+//! JS API for running Gard client-side in a web playground or Node. Each
+//! function takes source text and returns a JSON string, so the JS side only
+//! needs `JSON.parse`. Errors are reported in an `errors` array of
+//! `{ message, span }` objects instead of being thrown.
+
+use crate::checkpoint::Checkpoint;
+use crate::interpreter::Interpreter;
+use gard_ast::Node;
+use gard_lexer::{Lexer, Span, TokenWithSpan};
+use gard_parser::GardParser;
+use serde_json::{json, Value as Json};
+use wasm_bindgen::prelude::*;
+
+/// Statements and calls a playground run may execute before it's stopped,
+/// so an infinite loop doesn't freeze the page.
+pub const STEP_LIMIT: u64 = 1_000_000;
+
+/// `{ tokens: [{ kind, text, span }], errors }`, the tokens ending with an
+/// empty `Eof`
+#[wasm_bindgen]
+pub fn tokenize(source: &str) -> String {
+    let (tokens, errors) = Lexer::new(source).tokenize_with_errors();
+    let tokens: Vec<Json> = tokens.iter().map(|token| token_json(source, token)).collect();
+    let errors: Vec<Json> = errors.iter().map(|error| error_json(error.to_string(), None)).collect();
+    json!({ "tokens": tokens, "errors": errors }).to_string()
+}
+
+/// `{ ast, errors }`, with `ast` null if the source doesn't parse.
+#[wasm_bindgen]
+pub fn parse(source: &str) -> String {
+    match parse_source(source) {
+        Ok(program) => json!({ "ast": program, "errors": [] }),
+        Err(errors) => json!({ "ast": null, "errors": errors }),
+    }
+    .to_string()
+}
+
+/// `{ stdout, result, checkpoint, errors }`. Output printed before a
+/// runtime error is kept; `result` is the display form of `main`'s return
+/// value, and `checkpoint` the program's state for `resume`, null if it
+/// failed.
+#[wasm_bindgen]
+pub fn run(source: &str) -> String {
+    execute(source, Interpreter::new())
+}
+
+/// Like `run`, but starting from the `checkpoint` of an earlier run, so
+/// globals and channels carry over from one run of a session to the next.
+#[wasm_bindgen]
+pub fn resume(source: &str, checkpoint: &str) -> String {
+    match Checkpoint::from_text(checkpoint) {
+        Ok(checkpoint) => execute(source, Interpreter::new().with_checkpoint(checkpoint)),
+        Err(error) => json!({ "stdout": "", "result": null, "checkpoint": null, "errors": [error_json(error, None)] }).to_string(),
+    }
+}
+
+fn execute(source: &str, interpreter: Interpreter) -> String {
+    let program = match parse_source(source) {
+        Ok(program) => program,
+        Err(errors) => return json!({ "stdout": "", "result": null, "checkpoint": null, "errors": errors }).to_string(),
+    };
+
+    let mut interpreter = interpreter.with_step_limit(STEP_LIMIT);
+    let result = interpreter.run(&program);
+    let stdout = interpreter.take_output();
+    match result.map_err(|error| error.to_string()).and_then(|value| Ok((value, interpreter.checkpoint()?))) {
+        Ok((value, checkpoint)) => json!({ "stdout": stdout, "result": value.to_string(), "checkpoint": checkpoint.to_text(), "errors": [] }),
+        Err(error) => json!({ "stdout": stdout, "result": null, "checkpoint": null, "errors": [error_json(error, None)] }),
+    }
+    .to_string()
+}
+
+fn parse_source(source: &str) -> Result<Node, Vec<Json>> {
+    let (tokens, errors) = Lexer::new(source).tokenize_with_errors();
+    if !errors.is_empty() {
+        return Err(errors.iter().map(|error| error_json(error.to_string(), None)).collect());
+    }
+
+    let end_of_input = Span { start: source.len(), end: source.len() };
+    GardParser::parse_all(tokens).map_err(|errors| {
+        errors.iter()
+            .map(|error| {
+                let found = error.found().map(|token| token.token.to_string());
+                let mut expected: Vec<String> = error.expected()
+                    .map(|token| token.as_ref().map_or("end of input".to_string(), |token| token.token.to_string()))
+                    .collect();
+                expected.sort();
+
+                let mut message = format!("Unexpected {}", found.as_deref().unwrap_or("end of input"));
+                if !expected.is_empty() {
+                    message.push_str(&format!(", expected one of: {}", expected.join(", ")));
+                }
+                error_json(message, Some(error.found().map_or(end_of_input, |token| token.span)))
+            })
+            .collect()
+    })
+}
+
+fn token_json(source: &str, token: &TokenWithSpan) -> Json {
+    json!({
+        "kind": token.token.to_string(),
+        "text": &source[token.span.start..token.span.end],
+        "span": span_json(token.span),
+    })
+}
+
+fn error_json(message: String, span: Option<Span>) -> Json {
+    json!({ "message": message, "span": span.map(span_json) })
+}
+
+fn span_json(span: Span) -> Json {
+    json!({ "start": span.start, "end": span.end })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_json(output: String) -> Json {
+        serde_json::from_str(&output).unwrap()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let output = parse_json(tokenize("function main {}"));
+        let kinds: Vec<&str> = output["tokens"].as_array().unwrap().iter()
+            .map(|token| token["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["Function", "Identifier", "LeftBrace", "RightBrace", "Eof"]);
+        assert_eq!(output["tokens"][1]["text"], "main");
+        assert_eq!(output["tokens"][1]["span"], json!({ "start": 9, "end": 13 }));
+    }
+
+    #[test]
+    fn test_parse() {
+        let output = parse_json(parse("function main {}"));
+        assert!(output["ast"]["Program"][0]["Function"].is_object());
+        assert_eq!(output["errors"], json!([]));
+
+        let output = parse_json(parse("function {"));
+        assert!(output["ast"].is_null());
+        assert!(output["errors"][0]["message"].as_str().unwrap().starts_with("Unexpected LeftBrace"));
+        assert_eq!(output["errors"][0]["span"], json!({ "start": 9, "end": 10 }));
+    }
+
+    #[test]
+    fn test_run() {
+        let mut output = parse_json(run("function main {}"));
+        let checkpoint = output["checkpoint"].take();
+        assert!(checkpoint.as_str().unwrap().starts_with("gard-checkpoint 1\n"));
+        assert_eq!(output, json!({ "stdout": "", "result": "null", "checkpoint": null, "errors": [] }));
+
+        let output = parse_json(run("function"));
+        assert!(output["result"].is_null());
+        assert_eq!(output["errors"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_resume() {
+        let source = "tasklocal visits = 0\nfunction main { visits++; print(visits); }";
+        let first = parse_json(run(source));
+        let second = parse_json(resume(source, first["checkpoint"].as_str().unwrap()));
+        assert_eq!(second["stdout"], "2\n");
+
+        let output = parse_json(resume(source, "gard-checkpoint 0"));
+        assert_eq!(output["errors"][0]["message"], "Checkpoint version 0 isn't supported; this build reads version 1");
+    }
+}
@@ -0,0 +1,103 @@
+//! `std.regex`, called as `regex.find(pattern, text)`. Like in native code a
+//! pattern is its source string, whether it comes from `regex.compile`, a
+//! `re"..."` literal or a plain string, and is compiled on first use.
+
+use crate::interpreter::RuntimeError;
+use crate::value::Value;
+use ::regex::Regex;
+use std::collections::HashMap;
+
+/// Compiled patterns, kept for the interpreter's lifetime.
+#[derive(Debug, Default)]
+pub struct Patterns {
+    compiled: HashMap<String, Regex>,
+}
+
+impl Patterns {
+    /// Number of arguments `regex.name` takes, if it's a regex function.
+    pub fn arity(name: &str) -> Option<usize> {
+        match name {
+            "compile" => Some(1),
+            "isMatch" | "find" | "captures" => Some(2),
+            "replace" => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Calls `regex.function`. `find` and `captures` return null when
+    /// nothing matches, and a group that didn't take part is null.
+    pub fn call(&mut self, function: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let expected = Self::arity(function)
+            .ok_or_else(|| RuntimeError::UndefinedFunction(format!("regex.{}", function)))?;
+        if arguments.len() != expected {
+            return Err(RuntimeError::ArityMismatch { function: format!("regex.{}", function), expected, found: arguments.len() });
+        }
+        let arguments = strings(function, &arguments)?;
+        match (function, arguments.as_slice()) {
+            ("compile", [pattern]) => {
+                self.compile(pattern)?;
+                Ok(Value::String(pattern.to_string()))
+            },
+            ("isMatch", [pattern, text]) => Ok(Value::Bool(self.compile(pattern)?.is_match(text))),
+            ("find", [pattern, text]) => Ok(self.compile(pattern)?.find(text)
+                .map(|found| Value::String(found.as_str().to_string()))
+                .unwrap_or(Value::Null)),
+            ("captures", [pattern, text]) => Ok(self.compile(pattern)?.captures(text)
+                .map(|captures| Value::Array(captures.iter()
+                    .map(|group| group.map(|group| Value::String(group.as_str().to_string())).unwrap_or(Value::Null))
+                    .collect()))
+                .unwrap_or(Value::Null)),
+            ("replace", [pattern, text, replacement]) => {
+                Ok(Value::String(self.compile(pattern)?.replace_all(text, replacement.as_str()).into_owned()))
+            },
+            _ => unreachable!("regex.{} takes {} argument(s)", function, expected),
+        }
+    }
+
+    fn compile(&mut self, pattern: &str) -> Result<&Regex, RuntimeError> {
+        if !self.compiled.contains_key(pattern) {
+            let regex = Regex::new(pattern)
+                .map_err(|e| RuntimeError::InvalidPattern { pattern: pattern.to_string(), reason: e.to_string() })?;
+            self.compiled.insert(pattern.to_string(), regex);
+        }
+        Ok(&self.compiled[pattern])
+    }
+}
+
+fn strings(function: &str, arguments: &[Value]) -> Result<Vec<String>, RuntimeError> {
+    arguments.iter().map(|argument| match argument {
+        Value::String(value) => Ok(value.clone()),
+        other => Err(RuntimeError::TypeError(format!("regex.{} expects strings, found {}", function, other.type_name()))),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    #[test]
+    fn test_regex_functions() {
+        let mut patterns = Patterns::default();
+        assert_eq!(patterns.call("compile", vec![string(r"\d+")]), Ok(string(r"\d+")));
+        assert_eq!(patterns.call("isMatch", vec![string(r"\d+"), string("a1")]), Ok(Value::Bool(true)));
+        assert_eq!(patterns.call("find", vec![string(r"\d+"), string("row 42")]), Ok(string("42")));
+        assert_eq!(patterns.call("find", vec![string(r"\d+"), string("none")]), Ok(Value::Null));
+        assert_eq!(patterns.call("captures", vec![string(r"(\w+)@(\w+)?"), string("me@")]),
+            Ok(Value::Array(vec![string("me@"), string("me"), Value::Null])));
+        assert_eq!(patterns.call("replace", vec![string(r"(\w+) (\w+)"), string("hello world"), string("$2 $1")]), Ok(string("world hello")));
+    }
+
+    #[test]
+    fn test_regex_errors() {
+        let mut patterns = Patterns::default();
+        assert!(matches!(patterns.call("compile", vec![string("(")]), Err(RuntimeError::InvalidPattern { pattern, .. }) if pattern == "("));
+        assert_eq!(patterns.call("find", vec![string("a")]),
+            Err(RuntimeError::ArityMismatch { function: "regex.find".to_string(), expected: 2, found: 1 }));
+        assert_eq!(patterns.call("isMatch", vec![string("a"), Value::Int(1)]),
+            Err(RuntimeError::TypeError("regex.isMatch expects strings, found int".to_string())));
+    }
+}
@@ -0,0 +1,179 @@
+//! Hot reload: picking up edits to a source file while its program runs.
+//! See `Interpreter::reload` for what is swapped and what is kept.
+
+use gard_ast::Node;
+use gard_lexer::Lexer;
+use gard_parser::GardParser;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often a `SourceWatcher` looks at the file by default.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Functions a reload touched, each list sorted by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReloadSummary {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ReloadSummary {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl fmt::Display for ReloadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no functions changed");
+        }
+        let groups = [("changed", &self.changed), ("added", &self.added), ("removed", &self.removed)];
+        let parts: Vec<String> = groups.iter()
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(kind, names)| format!("{} {}", kind, names.join(", ")))
+            .collect();
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+/// Polls a source file's modification time.
+pub struct SourceWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    interval: Duration,
+    last_poll: Instant,
+}
+
+impl SourceWatcher {
+    /// Changes are relative to the file as it is now.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            modified: modified(&path),
+            path,
+            interval: POLL_INTERVAL,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The file's new contents if it changed since the last poll. Polls
+    /// closer together than the interval are skipped, so this is cheap to
+    /// call on every function call.
+    pub fn poll(&mut self) -> io::Result<Option<String>> {
+        if self.last_poll.elapsed() < self.interval {
+            return Ok(None);
+        }
+        self.last_poll = Instant::now();
+
+        let modified = modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            // A missing file is usually an editor midway through saving
+            return Ok(None);
+        }
+        self.modified = modified;
+        fs::read_to_string(&self.path).map(Some)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Parses a whole file for reloading. Unlike `GardParser::parse` trailing
+/// input is an error, otherwise a half-written function would look like
+/// every function after it had been removed.
+pub fn parse_source(source: &str) -> Result<Node, String> {
+    let tokens = Lexer::new(source).tokenize().map_err(|e| e.to_string())?;
+    GardParser::parse_all(tokens).map_err(|errors| format!("{:?}", errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, RuntimeError};
+    use crate::value::Value;
+    use gard_ast::Type;
+
+    fn function(name: &str, result: i64) -> Node {
+        Node::Function {
+            name: name.to_string(),
+            params: vec![],
+            return_type: Type::Int,
+            body: Box::new(Node::Block(vec![Node::Return(Some(Box::new(Node::IntLiteral(result))))])),
+            modifiers: vec![],
+            docs: None,
+        }
+    }
+
+    fn global(name: &str, value: i64) -> Node {
+        Node::Let {
+            name: name.to_string(),
+            type_annotation: None,
+            initializer: Some(Box::new(Node::IntLiteral(value))),
+            is_mutable: true,
+        }
+    }
+
+    #[test]
+    fn test_reload_swaps_functions_and_keeps_globals() {
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&Node::Program(vec![global("count", 1), function("a", 1), function("b", 2), function("c", 3)])).unwrap();
+
+        let edited = Node::Program(vec![global("count", 5), global("extra", 7), function("a", 10), function("c", 3), function("d", 4)]);
+        let summary = interpreter.reload(&edited).unwrap();
+        assert_eq!(summary, ReloadSummary {
+            added: vec!["d".to_string()],
+            changed: vec!["a".to_string()],
+            removed: vec!["b".to_string()],
+        });
+        assert_eq!(summary.to_string(), "changed a; added d; removed b");
+
+        assert_eq!(interpreter.call("a", vec![]), Ok(Value::Int(10)));
+        assert_eq!(interpreter.call("d", vec![]), Ok(Value::Int(4)));
+        assert_eq!(interpreter.call("b", vec![]), Err(RuntimeError::UndefinedFunction("b".to_string())));
+        assert_eq!(interpreter.global("count"), Some(&Value::Int(1)));
+        assert_eq!(interpreter.global("extra"), Some(&Value::Int(7)));
+        assert!(interpreter.reload(&edited).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reload_source_keeps_functions_on_parse_error() {
+        let mut interpreter = Interpreter::new();
+        interpreter.load(&Node::Program(vec![function("a", 1)])).unwrap();
+        assert!(interpreter.reload_source("function {").is_err());
+        assert_eq!(interpreter.call("a", vec![]), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn test_source_watcher() {
+        let path = std::env::temp_dir().join(format!("gard-reload-{}.gard", std::process::id()));
+        fs::write(&path, "function main {}").unwrap();
+        let mut watcher = SourceWatcher::new(&path).with_interval(Duration::ZERO);
+        assert_eq!(watcher.poll().unwrap(), None);
+
+        fs::write(&path, "function main { print(1) }").unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        assert_eq!(watcher.poll().unwrap().as_deref(), Some("function main { print(1) }"));
+        assert_eq!(watcher.poll().unwrap(), None);
+
+        let mut slow = SourceWatcher::new(&path);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(SystemTime::now() + Duration::from_secs(120)).unwrap();
+        assert_eq!(slow.poll().unwrap(), None, "polled before the interval passed");
+        fs::remove_file(&path).unwrap();
+    }
+}
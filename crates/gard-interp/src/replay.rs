@@ -0,0 +1,201 @@
+//! Recording and replaying the task runtime's schedule, for debugging bugs
+//! that depend on the order tasks run in.
+//!
+//! The schedule is the order spawned tasks start in and which task receives
+//! each message. A recording logs them as the run makes them; a replay makes
+//! the same choices from a log, which may come from another run or be
+//! edited by hand to try a different order. A replay fails with
+//! `ReplayDiverged` when the program no longer matches the log: the logged
+//! task hasn't been spawned or a different task receives a message. The
+//! runtime has no timers, so there are no timer firings to log.
+//!
+//! A simulation picks the order tasks start in at random from a seed, the
+//! same order for the same seed, and records it; see `simulate`.
+//!
+//! A log has one event per line:
+//!
+//! ```text
+//! start 2 worker
+//! deliver jobs 1
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// A spawned task started running
+    Start { task: usize, function: String },
+    /// A task received the next message on a channel
+    Deliver { channel: String, task: usize },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Start { task, function } => write!(f, "start {} {}", task, function),
+            Event::Deliver { channel, task } => write!(f, "deliver {} {}", channel, task),
+        }
+    }
+}
+
+/// The log of a schedule, one event per line.
+pub fn to_log(events: &[Event]) -> String {
+    events.iter().map(|event| format!("{}\n", event)).collect()
+}
+
+/// Parses a log written by `to_log`. Blank lines are skipped.
+pub fn parse_log(log: &str) -> Result<Vec<Event>, String> {
+    let mut events = Vec::new();
+    for (number, line) in log.lines().enumerate() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let task = |word: &str| word.parse().map_err(|_| format!("Line {}: '{}' is not a task id", number + 1, word));
+        let event = match words.as_slice() {
+            [] => continue,
+            ["start", id, function] => Event::Start { task: task(id)?, function: function.to_string() },
+            ["deliver", channel, id] => Event::Deliver { channel: channel.to_string(), task: task(id)? },
+            _ => return Err(format!("Line {}: expected 'start <task> <function>' or 'deliver <channel> <task>', found '{}'", number + 1, line)),
+        };
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// How the runtime makes its scheduling choices.
+#[derive(Debug, Default)]
+pub(crate) enum Schedule {
+    /// Tasks start in the order they were spawned
+    #[default]
+    Free,
+    Recording(Vec<Event>),
+    Replaying(VecDeque<Event>),
+    /// Tasks start in a random order; `random` is the generator's state
+    Simulating { random: u64, events: Vec<Event> },
+}
+
+/// SplitMix64, which is enough to spread the seeds over the schedules and,
+/// unlike std's hasher, gives every platform and release the same order.
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Schedule {
+    pub(crate) fn simulating(seed: u64) -> Self {
+        Schedule::Simulating { random: seed, events: Vec::new() }
+    }
+
+    /// Which of the tasks waiting to start, by id, starts next: the first
+    /// one, the one the log has next, or a random one.
+    pub(crate) fn next_start(&mut self, pending: &[usize]) -> Result<usize, String> {
+        let events = match self {
+            Schedule::Replaying(events) => events,
+            Schedule::Simulating { random, .. } => return Ok((next_random(random) % pending.len() as u64) as usize),
+            _ => return Ok(0),
+        };
+        match events.front() {
+            Some(Event::Start { task, .. }) => match pending.iter().position(|id| id == task) {
+                Some(position) => {
+                    events.pop_front();
+                    Ok(position)
+                },
+                None => Err(format!("the log starts task {}, which isn't waiting to start", task)),
+            },
+            Some(event) => Err(format!("the log has '{}' next, but a task starts", event)),
+            None => Err("a task starts after the end of the log".to_string()),
+        }
+    }
+
+    /// Logs a task starting.
+    pub(crate) fn started(&mut self, task: usize, function: &str) {
+        if let Schedule::Recording(events) | Schedule::Simulating { events, .. } = self {
+            events.push(Event::Start { task, function: function.to_string() });
+        }
+    }
+
+    /// Logs a task receiving a message, or checks that the log has it next.
+    pub(crate) fn delivered(&mut self, channel: &str, task: usize) -> Result<(), String> {
+        let event = Event::Deliver { channel: channel.to_string(), task };
+        match self {
+            Schedule::Free => Ok(()),
+            Schedule::Recording(events) | Schedule::Simulating { events, .. } => {
+                events.push(event);
+                Ok(())
+            },
+            Schedule::Replaying(events) => match events.front() {
+                Some(next) if *next == event => {
+                    events.pop_front();
+                    Ok(())
+                },
+                Some(next) => Err(format!("the log has '{}' next, but the run did '{}'", next, event)),
+                None => Err(format!("the run did '{}' after the end of the log", event)),
+            },
+        }
+    }
+
+    /// Checks that a replay used the whole log.
+    pub(crate) fn finish(&self) -> Result<(), String> {
+        match self {
+            Schedule::Replaying(events) if !events.is_empty() => {
+                Err(format!("the run ended before '{}', with {} event(s) of the log left", events[0], events.len()))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn recorded(&self) -> &[Event] {
+        match self {
+            Schedule::Recording(events) | Schedule::Simulating { events, .. } => events,
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_round_trip() {
+        let events = vec![
+            Event::Start { task: 2, function: "worker".to_string() },
+            Event::Deliver { channel: "jobs".to_string(), task: 1 },
+        ];
+        let log = to_log(&events);
+        assert_eq!(log, "start 2 worker\ndeliver jobs 1\n");
+        assert_eq!(parse_log(&format!("{}\n", log)), Ok(events));
+        assert_eq!(parse_log("start two worker"), Err("Line 1: 'two' is not a task id".to_string()));
+        assert!(parse_log("fire timer").unwrap_err().starts_with("Line 1: expected"));
+    }
+
+    #[test]
+    fn test_replay_choices() {
+        let mut schedule = Schedule::Replaying(VecDeque::from(vec![
+            Event::Start { task: 3, function: "b".to_string() },
+            Event::Deliver { channel: "jobs".to_string(), task: 0 },
+        ]));
+        assert_eq!(schedule.next_start(&[2, 3]), Ok(1));
+        assert_eq!(schedule.finish(), Err("the run ended before 'deliver jobs 0', with 1 event(s) of the log left".to_string()));
+        assert_eq!(schedule.delivered("jobs", 2), Err("the log has 'deliver jobs 0' next, but the run did 'deliver jobs 2'".to_string()));
+        assert_eq!(schedule.delivered("jobs", 0), Ok(()));
+        assert_eq!(schedule.next_start(&[2]), Err("a task starts after the end of the log".to_string()));
+        assert_eq!(schedule.finish(), Ok(()));
+
+        let mut schedule = Schedule::default();
+        assert_eq!(schedule.next_start(&[5, 4]), Ok(0));
+    }
+
+    #[test]
+    fn test_simulated_choices() {
+        let starts = |seed: u64| {
+            let mut schedule = Schedule::simulating(seed);
+            (0..8).map(|_| schedule.next_start(&[1, 2, 3]).unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(starts(7), starts(7));
+        assert_ne!(starts(7), starts(8));
+        assert!((0..16).flat_map(starts).all(|position| position < 3));
+    }
+}
@@ -0,0 +1,155 @@
+//! Allocating sharded entities to the nodes of a cluster. An entity is
+//! addressed by a key, which hashes to one of a fixed number of shards, and
+//! each shard lives on one node. When a node joins, shards move to it from
+//! the nodes with the most until they're balanced; when one leaves, its
+//! shards move to the nodes with the fewest. Each move is a `Handoff`: the
+//! old node stops the shard's entities and the new one starts them.
+//!
+//! This is groundwork only: the allocation, and nothing that acts on it.
+//! The interpreter doesn't run actors, there's no `ActorSystem` to
+//! configure sharding through, and the task runtime runs in one process
+//! with no remoting to reach entities on other nodes. So no entity is
+//! spawned, stopped or sent a message by shard; a node is just a name, and
+//! a `Handoff` is only a record of what would move.
+
+use std::fmt;
+
+/// A shard moving between nodes; `None` is no node, for shards that
+/// weren't allocated yet or that no node is left to take.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handoff {
+    pub shard: usize,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+impl fmt::Display for Handoff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let node = |node: &Option<String>| node.clone().unwrap_or_else(|| "nowhere".to_string());
+        write!(f, "shard {}: {} -> {}", self.shard, node(&self.from), node(&self.to))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sharding {
+    /// In the order they joined, which breaks ties between equally loaded
+    /// nodes
+    nodes: Vec<String>,
+    /// The node of each shard
+    owners: Vec<Option<String>>,
+}
+
+impl Sharding {
+    pub fn new(shards: usize) -> Self {
+        Self { nodes: Vec::new(), owners: vec![None; shards.max(1)] }
+    }
+
+    /// The shard of an entity. The hash is FNV-1a rather than std's, which
+    /// may change between releases, so every node agrees on it.
+    pub fn shard_of(&self, key: &str) -> usize {
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+        (hash % self.owners.len() as u64) as usize
+    }
+
+    /// The node an entity lives on, if any node has joined.
+    pub fn node_of(&self, key: &str) -> Option<&str> {
+        self.owners[self.shard_of(key)].as_deref()
+    }
+
+    /// The shards on a node.
+    pub fn shards_of(&self, node: &str) -> Vec<usize> {
+        (0..self.owners.len()).filter(|&shard| self.owners[shard].as_deref() == Some(node)).collect()
+    }
+
+    pub fn nodes(&self) -> &[String] {
+        &self.nodes
+    }
+
+    /// Adds a node, giving it the unallocated shards and then shards from
+    /// the nodes with the most until no node has more than one more than it.
+    pub fn join(&mut self, node: &str) -> Result<Vec<Handoff>, String> {
+        if self.nodes.iter().any(|member| member == node) {
+            return Err(format!("Node '{}' has already joined", node));
+        }
+        self.nodes.push(node.to_string());
+        let mut handoffs = Vec::new();
+        for shard in 0..self.owners.len() {
+            if self.owners[shard].is_none() {
+                handoffs.push(self.hand_off(shard, Some(node)));
+            }
+        }
+        loop {
+            // `max_by_key` picks the last of equals, so the reversed
+            // order picks the first
+            let busiest = self.nodes.iter().rev().max_by_key(|member| self.shards_of(member).len()).cloned();
+            let Some(busiest) = busiest else { break };
+            let shards = self.shards_of(&busiest);
+            if shards.len() <= self.shards_of(node).len() + 1 {
+                break;
+            }
+            handoffs.push(self.hand_off(shards[0], Some(node)));
+        }
+        Ok(handoffs)
+    }
+
+    /// Removes a node, giving each of its shards to the node with the fewest.
+    pub fn leave(&mut self, node: &str) -> Result<Vec<Handoff>, String> {
+        let Some(position) = self.nodes.iter().position(|member| member == node) else {
+            return Err(format!("Node '{}' isn't in the cluster", node));
+        };
+        self.nodes.remove(position);
+        let mut handoffs = Vec::new();
+        for shard in self.shards_of(node) {
+            let idlest = self.nodes.iter().min_by_key(|member| self.shards_of(member).len()).cloned();
+            handoffs.push(self.hand_off(shard, idlest.as_deref()));
+        }
+        Ok(handoffs)
+    }
+
+    fn hand_off(&mut self, shard: usize, to: Option<&str>) -> Handoff {
+        let to = to.map(str::to_string);
+        let from = std::mem::replace(&mut self.owners[shard], to.clone());
+        Handoff { shard, from, to }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(sharding: &Sharding) -> Vec<usize> {
+        sharding.nodes().iter().map(|node| sharding.shards_of(node).len()).collect()
+    }
+
+    #[test]
+    fn test_rebalancing() {
+        let mut sharding = Sharding::new(10);
+        assert_eq!(sharding.node_of("account-7"), None);
+        assert_eq!(sharding.join("a").unwrap().len(), 10);
+        assert_eq!(sharding.node_of("account-7"), Some("a"));
+
+        // Only the shards that have to move do
+        let handoffs = sharding.join("b").unwrap();
+        assert_eq!(handoffs.len(), 5);
+        assert_eq!(handoffs[0].to_string(), "shard 0: a -> b");
+        sharding.join("c").unwrap();
+        assert_eq!(counts(&sharding), [3, 4, 3]);
+        assert_eq!(sharding.join("b"), Err("Node 'b' has already joined".to_string()));
+
+        let handoffs = sharding.leave("a").unwrap();
+        assert!(handoffs.iter().all(|handoff| handoff.from.as_deref() == Some("a")));
+        assert_eq!(counts(&sharding), [5, 5]);
+        sharding.leave("b").unwrap();
+        let handoffs = sharding.leave("c").unwrap();
+        assert_eq!(handoffs[0].to_string(), format!("shard {}: c -> nowhere", handoffs[0].shard));
+        assert_eq!(sharding.node_of("account-7"), None);
+    }
+
+    #[test]
+    fn test_shard_of_is_stable() {
+        let sharding = Sharding::new(100);
+        // FNV-1a of "account-7", which no release may change
+        assert_eq!(sharding.shard_of("account-7"), (0xde63_81c2_4868_21bc_u64 % 100) as usize);
+        assert_eq!(sharding.shard_of(""), (0xcbf2_9ce4_8422_2325_u64 % 100) as usize);
+    }
+}
@@ -0,0 +1,180 @@
+//! Deterministic simulation testing: runs each function marked
+//! `@concurrent` under many schedules, each picked at random from a seed,
+//! to find the ordering bugs one schedule hides. A seed always runs the
+//! same way, so a failure is reproduced by running its seed again, or by
+//! replaying the schedule it recorded.
+//!
+//! The runtime's only choice is which task starts next, when a scope ends
+//! or a task waits for a message, so that is what the seeds vary. Runs that
+//! make the same choices as an earlier one are counted but not reported
+//! again. That is a cheap stand-in for partial order reduction, which would
+//! also skip schedules that only reorder tasks that don't interact.
+
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::replay::Event;
+use gard_ast::{FunctionModifier, Node};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+
+/// The attribute marking a test, `@concurrent function transfers { .. }`.
+pub const ATTRIBUTE: &str = "concurrent";
+
+/// Keeps a run that never ends from stalling the rest.
+pub const STEP_LIMIT: u64 = 1_000_000;
+
+/// One run of a test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub seed: u64,
+    pub result: Result<(), RuntimeError>,
+    /// What it printed
+    pub output: String,
+    pub schedule: Vec<Event>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exploration {
+    pub test: String,
+    pub runs: usize,
+    /// The distinct schedules among the runs
+    pub schedules: usize,
+    /// The first failed run of each failing schedule
+    pub failures: Vec<Run>,
+}
+
+impl fmt::Display for Exploration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} run(s), {} schedule(s), ", self.test, self.runs, self.schedules)?;
+        match self.failures.as_slice() {
+            [] => write!(f, "passed"),
+            failures => {
+                write!(f, "{} failed", failures.len())?;
+                for failure in failures {
+                    if let Err(error) = &failure.result {
+                        write!(f, "\n  seed {}: {}", failure.seed, error)?;
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Handles `@concurrent` on a function declaration, in the shape
+/// `plugin::Registry::add_attribute` takes.
+pub fn concurrent_attribute(arguments: &[Node], declaration: Node) -> Result<Node, String> {
+    if !arguments.is_empty() {
+        return Err("@concurrent takes no arguments".to_string());
+    }
+    match declaration {
+        Node::Located { span, node } => Ok(Node::Located { span, node: Box::new(concurrent_attribute(arguments, *node)?) }),
+        Node::Function { name, params, return_type, body, mut modifiers, docs } if params.is_empty() => {
+            if !modifiers.contains(&FunctionModifier::Concurrent) {
+                modifiers.push(FunctionModifier::Concurrent);
+            }
+            Ok(Node::Function { name, params, return_type, body, modifiers, docs })
+        },
+        Node::Function { name, .. } => Err(format!("test '{}' can't take parameters", name)),
+        _ => Err("@concurrent only applies to functions".to_string()),
+    }
+}
+
+/// The names of the program's tests.
+pub fn concurrent_tests(program: &Node) -> Vec<String> {
+    let Node::Program(nodes) = program else {
+        return Vec::new();
+    };
+    nodes.iter()
+        .filter_map(|node| match node.unlocated() {
+            Node::Function { name, modifiers, .. } if modifiers.contains(&FunctionModifier::Concurrent) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Runs `test` once with the schedule of `seed`.
+pub fn run_seed(program: &Node, test: &str, seed: u64) -> Run {
+    let mut interpreter = Interpreter::new().with_simulated_schedule(seed).with_step_limit(STEP_LIMIT);
+    let result = interpreter.load(program).and_then(|_| interpreter.call(test, Vec::new()));
+    Run {
+        seed,
+        result: result.map(drop),
+        output: interpreter.take_output(),
+        schedule: interpreter.recorded_schedule().to_vec(),
+    }
+}
+
+/// Runs `test` with each seed of `seeds`.
+pub fn explore(program: &Node, test: &str, seeds: Range<u64>) -> Exploration {
+    let mut exploration = Exploration { test: test.to_string(), runs: 0, schedules: 0, failures: Vec::new() };
+    let mut explored = HashSet::new();
+    for seed in seeds {
+        exploration.runs += 1;
+        let run = run_seed(program, test, seed);
+        if !explored.insert(run.schedule.clone()) {
+            continue;
+        }
+        exploration.schedules += 1;
+        if run.result.is_err() {
+            exploration.failures.push(run);
+        }
+    }
+    exploration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gard_ast::{AssertionKind, BinaryOp, Span, Type};
+
+    fn call(name: &str, arguments: Vec<Node>) -> Node {
+        Node::Call { callee: Box::new(Node::Identifier(name.into())), arguments }
+    }
+
+    fn function(name: &str, body: Vec<Node>) -> Node {
+        Node::Function { name: name.to_string(), params: vec![], return_type: Type::Void, body: Box::new(Node::Block(body)), modifiers: vec![], docs: None }
+    }
+
+    #[test]
+    fn test_exploring_schedules() {
+        let log = || Node::StringLiteral("log".to_string());
+        let spawn = |name: &str| Node::Spawn(Box::new(call(name, vec![])));
+        // Assumes `first` sends first, which only the spawn order ensures
+        let program = Node::Program(vec![
+            function("first", vec![call("send", vec![log(), Node::IntLiteral(1)])]),
+            function("second", vec![call("send", vec![log(), Node::IntLiteral(2)])]),
+            concurrent_attribute(&[], function("ordered", vec![
+                Node::Scope { body: Box::new(Node::Block(vec![spawn("first"), spawn("second")])) },
+                Node::Assertion {
+                    kind: AssertionKind::Require,
+                    condition: Box::new(Node::Binary { left: Box::new(call("receive", vec![log()])), operator: BinaryOp::Eq, right: Box::new(Node::IntLiteral(1)) }),
+                    message: None,
+                },
+            ])).unwrap(),
+        ]);
+        assert_eq!(concurrent_tests(&program), ["ordered"]);
+
+        let exploration = explore(&program, "ordered", 0..20);
+        assert_eq!((exploration.runs, exploration.schedules, exploration.failures.len()), (20, 2, 1));
+        let failure = &exploration.failures[0];
+        assert_eq!(failure.schedule[0], Event::Start { task: 2, function: "second".to_string() });
+        assert_eq!(exploration.to_string(), format!("ordered: 20 run(s), 2 schedule(s), 1 failed\n  seed {}: Requirement failed", failure.seed));
+        // The seed fails the same way again
+        assert_eq!(&run_seed(&program, "ordered", failure.seed), failure);
+    }
+
+    #[test]
+    fn test_concurrent_attribute() {
+        let located = Node::Located { span: Span { start: 0, end: 1 }, node: Box::new(function("ordered", vec![])) };
+        let marked = concurrent_attribute(&[], located).unwrap();
+        assert!(matches!(marked.unlocated(), Node::Function { modifiers, .. } if modifiers == &[FunctionModifier::Concurrent]));
+        assert_eq!(concurrent_attribute(&[Node::IntLiteral(3)], function("ordered", vec![])), Err("@concurrent takes no arguments".to_string()));
+        let Node::Function { name, return_type, body, modifiers, .. } = function("transfer", vec![]) else { unreachable!() };
+        let params = vec![gard_ast::Parameter { name: "amount".into(), type_annotation: Type::Int }];
+        assert_eq!(
+            concurrent_attribute(&[], Node::Function { name, params, return_type, body, modifiers, docs: None }),
+            Err("test 'transfer' can't take parameters".to_string()),
+        );
+    }
+}
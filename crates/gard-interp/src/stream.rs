@@ -0,0 +1,121 @@
+//! `std.stream`, called as `stream.map(numbers, "double")`: pipelines whose
+//! stages pass elements on demand, so a source only produces as fast as
+//! the sink at the end pulls. Like io streams, stages are int handles, and
+//! building a stage on another uses that one up.
+//!
+//! - Sources: `of(array)`; `generate(function)`, which calls the function
+//!   with 0, 1, 2.. for each element until it returns null; and
+//!   `fromChannel(channel)`, which receives a message for each element and
+//!   ends once the channel is empty and no task is left to start
+//! - Flows: `map(stage, function)`, `filter(stage, function)`,
+//!   `buffer(stage, size)`, which pulls up to `size` elements ahead, and
+//!   `throttle(stage, elements, steps)`, which passes at most `elements`
+//!   every `steps` interpreter steps. Waiting for it moves the step clock
+//!   on, as sleeping would.
+//! - Sinks: `next(stage)`, the next element or null at the end;
+//!   `take(stage, count)`, up to `count` elements as an array; and
+//!   `forEach(stage, function)`, which calls the function with the rest and
+//!   returns how many there were.
+//!
+//! Functions are passed by name, as to `spawn`. Null ends a stream, so it
+//! can't be an element. Pulling runs Gard code and receives messages, so
+//! the interpreter pulls; the stages are only kept here.
+
+use crate::interpreter::RuntimeError;
+use crate::value::Value;
+use std::collections::VecDeque;
+
+#[derive(Debug)]
+pub(crate) enum Stage {
+    Of(VecDeque<Value>),
+    Generate { function: String, index: i64 },
+    FromChannel(String),
+    Map { upstream: Box<Stage>, function: String },
+    Filter { upstream: Box<Stage>, function: String },
+    Buffer { upstream: Box<Stage>, size: usize, buffered: VecDeque<Value>, ended: bool },
+    /// `passed` elements have passed in the window starting at step `window`
+    Throttle { upstream: Box<Stage>, elements: u64, steps: u64, window: u64, passed: u64 },
+}
+
+/// Open stages, indexed by handle. A stage that is being pulled from, or
+/// was used up by a stage built on it, is none.
+#[derive(Debug, Default)]
+pub struct Stages {
+    open: Vec<Option<Stage>>,
+}
+
+impl Stages {
+    /// Number of arguments `stream.name` takes, if it's a stream function.
+    pub fn arity(name: &str) -> Option<usize> {
+        match name {
+            "of" | "generate" | "fromChannel" | "next" => Some(1),
+            "map" | "filter" | "buffer" | "take" | "forEach" => Some(2),
+            "throttle" => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Calls `stream.function` for a source or a flow, returning its handle.
+    pub(crate) fn build(&mut self, function: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let stage = match (function, arguments.as_slice()) {
+            ("of", [Value::Array(elements)]) => Stage::Of(elements.iter().cloned().collect()),
+            ("generate", [Value::String(function)]) => Stage::Generate { function: function.clone(), index: 0 },
+            ("fromChannel", [Value::String(channel)]) => Stage::FromChannel(channel.clone()),
+            ("map", [Value::Int(handle), Value::String(function)]) => {
+                Stage::Map { upstream: Box::new(self.take(*handle)?), function: function.clone() }
+            },
+            ("filter", [Value::Int(handle), Value::String(function)]) => {
+                Stage::Filter { upstream: Box::new(self.take(*handle)?), function: function.clone() }
+            },
+            ("buffer", [Value::Int(handle), Value::Int(size)]) if *size > 0 => {
+                Stage::Buffer { upstream: Box::new(self.take(*handle)?), size: *size as usize, buffered: VecDeque::new(), ended: false }
+            },
+            ("throttle", [Value::Int(handle), Value::Int(elements), Value::Int(steps)]) if *elements > 0 && *steps > 0 => {
+                Stage::Throttle { upstream: Box::new(self.take(*handle)?), elements: *elements as u64, steps: *steps as u64, window: 0, passed: 0 }
+            },
+            _ => {
+                let found: Vec<&str> = arguments.iter().map(Value::type_name).collect();
+                return Err(RuntimeError::TypeError(format!("stream.{} can't take {}", function, found.join(", "))));
+            },
+        };
+        Ok(Value::Int(self.open(stage)))
+    }
+
+    /// Takes a stage out to pull from it or build on it.
+    pub(crate) fn take(&mut self, handle: i64) -> Result<Stage, RuntimeError> {
+        usize::try_from(handle).ok()
+            .and_then(|handle| self.open.get_mut(handle)?.take())
+            .ok_or_else(|| RuntimeError::TypeError(format!("Stream {} is not open, or is used by another stage", handle)))
+    }
+
+    /// Puts back a stage taken to pull from.
+    pub(crate) fn restore(&mut self, handle: i64, stage: Stage) {
+        self.open[handle as usize] = Some(stage);
+    }
+
+    fn open(&mut self, stage: Stage) -> i64 {
+        self.open.push(Some(stage));
+        self.open.len() as i64 - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages_are_used_up() {
+        let mut stages = Stages::default();
+        let source = stages.build("of", vec![Value::Array(vec![Value::Int(1)])]).unwrap();
+        let mapped = stages.build("map", vec![source.clone(), Value::String("double".to_string())]).unwrap();
+        assert_eq!(mapped, Value::Int(1));
+        assert_eq!(
+            stages.build("filter", vec![source, Value::String("odd".to_string())]),
+            Err(RuntimeError::TypeError("Stream 0 is not open, or is used by another stage".to_string())),
+        );
+        assert_eq!(
+            stages.build("buffer", vec![mapped, Value::Int(0)]),
+            Err(RuntimeError::TypeError("stream.buffer can't take int, int".to_string())),
+        );
+    }
+}
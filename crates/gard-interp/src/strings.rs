@@ -0,0 +1,119 @@
+//! Methods of the string type, called as `text.method(..)`. Positions and
+//! lengths count characters rather than bytes, like `text.length`, and
+//! iterating a string with `foreach` or `chars()` yields one-character
+//! strings.
+
+use crate::interpreter::RuntimeError;
+use crate::value::Value;
+
+/// Calls `method` on `text`. A position outside the string fails like an
+/// out-of-bounds index, without a location, which the caller adds.
+pub fn call(text: &str, method: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let length = text.chars().count();
+    match method {
+        "substring" => {
+            let (start, end) = match arguments.as_slice() {
+                [start, end] => (position(method, start)?, position(method, end)?),
+                _ => return Err(arity(method, 2, arguments.len())),
+            };
+            for index in [start, end] {
+                if index < 0 || index as usize > length {
+                    return Err(RuntimeError::IndexOutOfBounds { index, length, location: None });
+                }
+            }
+            if start > end {
+                return Err(RuntimeError::TypeError(format!("substring start {} is after its end {}", start, end)));
+            }
+            Ok(Value::String(text.chars().skip(start as usize).take((end - start) as usize).collect()))
+        },
+        "indexOf" => {
+            let needle = string_argument(method, &arguments)?;
+            let index = text.find(needle.as_str())
+                .map(|byte| text[..byte].chars().count() as i64)
+                .unwrap_or(-1);
+            Ok(Value::Int(index))
+        },
+        "split" => {
+            let separator = string_argument(method, &arguments)?;
+            let parts = if separator.is_empty() {
+                characters(text)
+            } else {
+                text.split(separator.as_str()).map(|part| Value::String(part.to_string())).collect()
+            };
+            Ok(Value::Array(parts))
+        },
+        "startsWith" => Ok(Value::Bool(text.starts_with(string_argument(method, &arguments)?.as_str()))),
+        "endsWith" => Ok(Value::Bool(text.ends_with(string_argument(method, &arguments)?.as_str()))),
+        "trim" | "toUpper" | "toLower" | "chars" => {
+            if !arguments.is_empty() {
+                return Err(arity(method, 0, arguments.len()));
+            }
+            Ok(match method {
+                "trim" => Value::String(text.trim().to_string()),
+                "toUpper" => Value::String(text.to_uppercase()),
+                "toLower" => Value::String(text.to_lowercase()),
+                _ => Value::Array(characters(text)),
+            })
+        },
+        method => Err(RuntimeError::TypeError(format!("string has no method {}", method))),
+    }
+}
+
+fn characters(text: &str) -> Vec<Value> {
+    text.chars().map(|c| Value::String(c.to_string())).collect()
+}
+
+fn position(method: &str, argument: &Value) -> Result<i64, RuntimeError> {
+    match argument {
+        Value::Int(index) => Ok(*index),
+        other => Err(RuntimeError::TypeError(format!("{} expects an int position, found {}", method, other.type_name()))),
+    }
+}
+
+fn string_argument(method: &str, arguments: &[Value]) -> Result<String, RuntimeError> {
+    match arguments {
+        [Value::String(value)] => Ok(value.clone()),
+        [other] => Err(RuntimeError::TypeError(format!("{} expects a string, found {}", method, other.type_name()))),
+        _ => Err(arity(method, 1, arguments.len())),
+    }
+}
+
+fn arity(method: &str, expected: usize, found: usize) -> RuntimeError {
+    RuntimeError::ArityMismatch { function: format!("string.{}", method), expected, found }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    #[test]
+    fn test_string_methods() {
+        assert_eq!(call("héllo", "substring", vec![Value::Int(1), Value::Int(3)]), Ok(string("él")));
+        assert_eq!(call("héllo", "substring", vec![Value::Int(2), Value::Int(5)]), Ok(string("llo")));
+        assert_eq!(call("héllo", "indexOf", vec![string("l")]), Ok(Value::Int(2)));
+        assert_eq!(call("héllo", "indexOf", vec![string("z")]), Ok(Value::Int(-1)));
+        assert_eq!(call("a,b,,c", "split", vec![string(",")]), Ok(Value::Array(vec![string("a"), string("b"), string(""), string("c")])));
+        assert_eq!(call("ab", "split", vec![string("")]), call("ab", "chars", vec![]));
+        assert_eq!(call("  pad \n", "trim", vec![]), Ok(string("pad")));
+        assert_eq!(call("Gard", "toUpper", vec![]), Ok(string("GARD")));
+        assert_eq!(call("Gard", "toLower", vec![]), Ok(string("gard")));
+        assert_eq!(call("Gard", "startsWith", vec![string("Ga")]), Ok(Value::Bool(true)));
+        assert_eq!(call("Gard", "endsWith", vec![string("Ga")]), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn test_string_method_errors() {
+        assert_eq!(call("abc", "substring", vec![Value::Int(1), Value::Int(4)]),
+            Err(RuntimeError::IndexOutOfBounds { index: 4, length: 3, location: None }));
+        assert!(matches!(call("abc", "substring", vec![Value::Int(2), Value::Int(1)]), Err(RuntimeError::TypeError(_))));
+        assert_eq!(call("abc", "trim", vec![string("x")]),
+            Err(RuntimeError::ArityMismatch { function: "string.trim".to_string(), expected: 0, found: 1 }));
+        assert_eq!(call("abc", "indexOf", vec![Value::Int(1)]),
+            Err(RuntimeError::TypeError("indexOf expects a string, found int".to_string())));
+        assert_eq!(call("abc", "reverse", vec![]), Err(RuntimeError::TypeError("string has no method reverse".to_string())));
+    }
+}
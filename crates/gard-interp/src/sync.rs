@@ -0,0 +1,268 @@
+//! Mutexes and channels shared by tasks, and deadlock diagnostics for them.
+//!
+//! Tasks run one at a time on the interpreter's stack, so every task below
+//! the running one is waiting for the one above it: to finish its scope's
+//! tasks, or to receive a message. A task that has to wait for a message
+//! first runs the tasks that haven't started, in case one of them sends it;
+//! when none is left, or when it needs a mutex another task holds, the wait
+//! can never end and the run fails with a `Deadlock`.
+//!
+//! A channel is a task's mailbox: `prioritize` orders its messages by a
+//! function of each, and a task can `stash` messages it isn't ready for and
+//! `unstashAll` them back to the front once it is.
+//!
+//! With the debug runtime, the order mutexes are acquired in is recorded as
+//! well, so that two paths taking the same mutexes in opposite orders are
+//! reported even when this run's schedule didn't deadlock on them.
+
+use crate::value::Value;
+use gard_ast::Location;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+/// A task and the function it runs; task 0 is the program itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskId {
+    pub id: usize,
+    pub function: String,
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task {} ({})", self.id, self.function)
+    }
+}
+
+/// A Gard-level stack frame, for traces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub function: String,
+    pub location: Option<Location>,
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(location) => write!(f, "at {}, line {}:{}", self.function, location.line, location.column),
+            None => write!(f, "at {}", self.function),
+        }
+    }
+}
+
+/// What a task in a deadlock waits for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Wait {
+    /// A mutex another running task holds, or the task itself
+    Mutex { name: String, holder: TaskId },
+    /// A mutex held by a task that finished without releasing it
+    Abandoned { name: String, holder: TaskId },
+    Message { channel: String },
+    /// The tasks spawned in one of its scopes
+    Tasks,
+}
+
+impl fmt::Display for Wait {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Wait::Mutex { name, holder } => write!(f, "mutex '{}', held by {}", name, holder),
+            Wait::Abandoned { name, holder } => write!(f, "mutex '{}', which {} finished without releasing", name, holder),
+            Wait::Message { channel } => write!(f, "a message on channel '{}'", channel),
+            Wait::Tasks => write!(f, "the tasks of its scope"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockedTask {
+    pub task: TaskId,
+    pub waiting_for: Wait,
+    /// Innermost frame first
+    pub stack: Vec<TraceFrame>,
+}
+
+/// Tasks that can't go on: the first one that blocked, then the tasks it
+/// waits for in turn, which are waiting for the task before them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deadlock {
+    pub tasks: Vec<BlockedTask>,
+}
+
+impl fmt::Display for Deadlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Deadlock")?;
+        for blocked in &self.tasks {
+            write!(f, "\n  {} waits for {}", blocked.task, blocked.waiting_for)?;
+            for frame in &blocked.stack {
+                write!(f, "\n    {}", frame)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A mutex acquired while holding another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Acquisition {
+    pub held: String,
+    pub acquired: String,
+    pub task: TaskId,
+    pub stack: Vec<TraceFrame>,
+}
+
+/// Mutexes acquired in a cycle: each while holding the one before it, and
+/// the first while holding the last. Tasks taking them along different
+/// edges of the cycle at once deadlock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockOrderViolation {
+    /// Where each edge of the cycle was first taken
+    pub acquisitions: Vec<Acquisition>,
+}
+
+impl fmt::Display for LockOrderViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut cycle: Vec<&str> = self.acquisitions.iter().map(|edge| edge.held.as_str()).collect();
+        cycle.extend(self.acquisitions.first().map(|edge| edge.held.as_str()));
+        write!(f, "Potential deadlock: mutexes acquired in the order {}", cycle.join(" -> "))?;
+        for edge in &self.acquisitions {
+            write!(f, "\n  {} acquired '{}' while holding '{}'", edge.task, edge.acquired, edge.held)?;
+            for frame in &edge.stack {
+                write!(f, "\n    {}", frame)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The state of every mutex and channel.
+#[derive(Debug, Default)]
+pub(crate) struct SyncState {
+    /// Locked mutexes, by name, and the task holding each
+    pub(crate) holders: HashMap<String, TaskId>,
+    /// Messages sent but not received yet, with their priorities: highest
+    /// first, and in the order sent among equals
+    pub(crate) channels: HashMap<String, VecDeque<(i64, Value)>>,
+    /// The function giving each message to a channel its priority, by
+    /// channel; messages to other channels all have priority 0
+    pub(crate) priorities: HashMap<String, String>,
+    /// Messages set aside with `stash`, by channel, in the order stashed
+    pub(crate) stashes: HashMap<String, Vec<Value>>,
+    /// With the debug runtime, the first acquisition of each mutex while
+    /// holding another, by (held, acquired)
+    pub(crate) order: Option<BTreeMap<(String, String), Acquisition>>,
+    pub(crate) violations: Vec<LockOrderViolation>,
+}
+
+impl SyncState {
+    /// Queues a message behind those with at least its priority, returning
+    /// how many messages the channel holds.
+    pub(crate) fn enqueue(&mut self, channel: String, priority: i64, message: Value) -> usize {
+        let messages = self.channels.entry(channel).or_default();
+        let position = messages.iter().position(|(queued, _)| *queued < priority).unwrap_or(messages.len());
+        messages.insert(position, (priority, message));
+        messages.len()
+    }
+
+    /// Puts a channel's stashed messages back ahead of all the others, in
+    /// the order they were stashed, returning how many there were.
+    pub(crate) fn unstash_all(&mut self, channel: &str) -> usize {
+        let stashed = self.stashes.remove(channel).unwrap_or_default();
+        let count = stashed.len();
+        let messages = self.channels.entry(channel.to_string()).or_default();
+        for message in stashed.into_iter().rev() {
+            messages.push_front((i64::MAX, message));
+        }
+        count
+    }
+
+    /// The mutexes a task holds, in name order.
+    pub(crate) fn held_by(&self, task: usize) -> Vec<String> {
+        let mut held: Vec<String> = self.holders.iter()
+            .filter(|(_, holder)| holder.id == task)
+            .map(|(name, _)| name.clone())
+            .collect();
+        held.sort();
+        held
+    }
+
+    /// Records `acquired` being taken while holding `held`, reporting the
+    /// cycles that closes.
+    pub(crate) fn record_order(&mut self, held: &[String], acquired: &str, task: &TaskId, stack: &[TraceFrame]) {
+        let Some(order) = &mut self.order else {
+            return;
+        };
+        for held in held {
+            let edge = (held.clone(), acquired.to_string());
+            if order.contains_key(&edge) {
+                continue;
+            }
+            if let Some(path) = path(order, acquired, held) {
+                let mut acquisitions: Vec<Acquisition> = path.windows(2)
+                    .map(|pair| order[&(pair[0].clone(), pair[1].clone())].clone())
+                    .collect();
+                acquisitions.push(Acquisition {
+                    held: held.clone(),
+                    acquired: acquired.to_string(),
+                    task: task.clone(),
+                    stack: stack.to_vec(),
+                });
+                self.violations.push(LockOrderViolation { acquisitions });
+            }
+            order.insert(edge, Acquisition {
+                held: held.clone(),
+                acquired: acquired.to_string(),
+                task: task.clone(),
+                stack: stack.to_vec(),
+            });
+        }
+    }
+}
+
+/// The mutexes from `from` to `to` along recorded acquisitions, if any.
+fn path(order: &BTreeMap<(String, String), Acquisition>, from: &str, to: &str) -> Option<Vec<String>> {
+    let mut previous: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+    while let Some(mutex) = queue.pop_front() {
+        if mutex == to {
+            let mut path = vec![to.to_string()];
+            let mut at = to;
+            while at != from {
+                at = previous[at];
+                path.push(at.to_string());
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for (held, acquired) in order.keys() {
+            if held == mutex && acquired != from && !previous.contains_key(acquired.as_str()) {
+                previous.insert(acquired, mutex);
+                queue.push_back(acquired);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_order_cycle() {
+        let mut sync = SyncState { order: Some(BTreeMap::new()), ..SyncState::default() };
+        let task = |id: usize| TaskId { id, function: "work".to_string() };
+        let held = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        sync.record_order(&held(&["a"]), "b", &task(1), &[]);
+        sync.record_order(&held(&["b"]), "c", &task(1), &[]);
+        assert!(sync.violations.is_empty());
+        sync.record_order(&held(&["c"]), "a", &task(2), &[]);
+        assert_eq!(sync.violations.len(), 1);
+        assert_eq!(
+            sync.violations[0].to_string().lines().next(),
+            Some("Potential deadlock: mutexes acquired in the order a -> b -> c -> a"),
+        );
+        // Each cycle is reported once
+        sync.record_order(&held(&["c"]), "a", &task(2), &[]);
+        assert_eq!(sync.violations.len(), 1);
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Value>),
+    /// An object literal's fields, in order
+    Object(Vec<(String, Value)>),
+    /// A tag of a union, with its payload
+    Variant { name: String, payload: Vec<Value> },
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Bytes(_) => "bytes",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+            Value::Variant { .. } => "variant",
+        }
+    }
+
+    /// Conditions must be booleans; there is no implicit truthiness.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Bytes(data) => {
+                write!(f, "0x")?;
+                data.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            },
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_element(f, element)?;
+                }
+                write!(f, "]")
+            },
+            Value::Object(fields) if fields.is_empty() => write!(f, "{{}}"),
+            Value::Object(fields) => {
+                write!(f, "{{ ")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", name)?;
+                    write_element(f, value)?;
+                }
+                write!(f, " }}")
+            },
+            Value::Variant { name, payload } if payload.is_empty() => write!(f, "{}", name),
+            Value::Variant { name, payload } => {
+                write!(f, "{}(", name)?;
+                for (i, value) in payload.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_element(f, value)?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
+
+/// A value inside an array, object or variant, where strings are quoted.
+fn write_element(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::String(value) => write!(f, "{:?}", value),
+        value => write!(f, "{}", value),
+    }
+}
@@ -0,0 +1,96 @@
+//! Counts the heap allocations lexing a 100k-line file makes, and those
+//! cloning its tokens makes, as the parser does when it backtracks.
+//!
+//!     cargo bench --bench allocations
+//!
+//! Before tokens borrowed the source, every identifier, string, template
+//! text and doc comment was a `String` of its own, so each of them cost an
+//! allocation when lexed and another each time it was cloned. The report
+//! gives those counts alongside the measured ones.
+
+use gard_lexer::{Lexer, Token};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const LINES: usize = 100_000;
+
+/// Ten lines of typical code, repeated with fresh names.
+fn source() -> String {
+    let mut source = String::new();
+    for i in 0..LINES / 10 {
+        source.push_str(&format!(concat!(
+            "/// Scales the reading of sensor {i}.\n",
+            "function scale{i}(reading: float, factor: float): float {{\n",
+            "    let label: string = \"sensor {i}\";\n",
+            "    let escaped: string = \"tab\\tseparated\";\n",
+            "    let message: string = `reading ${{reading}} from ${{label}}`;\n",
+            "    if (reading > 100.0) {{\n",
+            "        log.warn(message);\n",
+            "    }}\n",
+            "    return reading * factor + offset{i};\n",
+            "}}\n",
+        ), i = i));
+    }
+    source
+}
+
+/// Runs `f`, giving its result with the allocations and bytes it made.
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize, usize) {
+    let (allocations, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), BYTES.load(Ordering::Relaxed));
+    let result = f();
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - allocations, BYTES.load(Ordering::Relaxed) - bytes)
+}
+
+fn main() {
+    let source = source();
+
+    let start = Instant::now();
+    let (tokens, allocations, bytes) = measure(|| Lexer::new(&source).tokenize().expect("the source lexes"));
+    let elapsed = start.elapsed();
+
+    let with_text = tokens.iter()
+        .filter(|token| matches!(token.token,
+            Token::Identifier(_) | Token::StringLiteral(_) | Token::TemplateText(_)
+            | Token::DocComment(_) | Token::MultilineDocComment(_)))
+        .count();
+    let owned = tokens.iter()
+        .filter(|token| matches!(&token.token,
+            Token::StringLiteral(Cow::Owned(_)) | Token::TemplateText(Cow::Owned(_)) | Token::MultilineDocComment(Cow::Owned(_))))
+        .count();
+
+    let (clone, clone_allocations, clone_bytes) = measure(|| tokens.clone());
+    drop(clone);
+
+    println!("{} lines, {} bytes, {} tokens in {:?}", LINES, source.len(), tokens.len(), elapsed);
+    println!("{} tokens carry text, {} of them decoded escapes into their own string", with_text, owned);
+    println!("lexing:   {} allocations, {} bytes (at least {} with a string per token)", allocations, bytes, with_text);
+    println!("cloning:  {} allocations, {} bytes (at least {} with a string per token)", clone_allocations, clone_bytes, with_text + 1);
+}
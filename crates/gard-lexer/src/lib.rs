@@ -1,6 +1,13 @@
-use logos::Logos;
+use gard_ast::{message, Locale};
+use logos::{FilterResult, Logos};
+use rayon::prelude::*;
+use std::borrow::Cow;
 use std::fmt;
+use std::fs;
 use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+pub use gard_ast::FileId;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Span {
@@ -14,20 +21,34 @@ impl fmt::Display for Span {
     }
 }
 
+/// A token and where it is. The text of identifiers, comments and strings
+/// borrows the source unless an escape had to be decoded, so tokens are
+/// cheap to clone while parsing backtracks.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct TokenWithSpan {
-    pub token: Token,
+pub struct TokenWithSpan<'src> {
+    pub token: Token<'src>,
     pub span: Span,
+    /// Whether a line break comes between the token and the one before
+    /// it, in a comment or not. Only set when lexing with
+    /// `LexerOptions::newlines`, for rules like an optional `;` at the end
+    /// of a line.
+    pub newline_before: bool,
+}
+
+impl<'src> TokenWithSpan<'src> {
+    pub fn new(token: Token<'src>, span: Span) -> Self {
+        Self { token, span, newline_before: false }
+    }
 }
 
 #[derive(Logos, Debug, PartialEq, Eq, Hash, Clone)]
-pub enum Token {
+pub enum Token<'src> {
     // Skip whitespace and comments
     #[regex(r"[ \t\n\f]+", logos::skip)]
     Error,
 
     #[regex(r"//[^\n]*", logos::skip)]
-    #[regex(r"/\*([^*]|\*[^/])*\*/", logos::skip)]
+    #[token("/*", block_comment)]
     Comment,
 
     // Keywords
@@ -35,16 +56,24 @@ pub enum Token {
     Let,
     #[token("const")]
     Const,
+    #[token("tasklocal")]
+    TaskLocal,
+    #[token("actorlocal")]
+    ActorLocal,
     #[token("function")]
     Function,
     #[token("class")]
     Class,
+    #[token("abstract")]
+    Abstract,
     #[token("extends")]
     Extends,
     #[token("implements")]
     Implements,
     #[token("interface")]
     Interface,
+    #[token("type")]
+    Type,
     #[token("return")]
     Return,
     #[token("if")]
@@ -77,12 +106,16 @@ pub enum Token {
     Int,
     #[token("uint")]
     UInt,
+    #[token("uint256")]
+    UInt256,
     #[token("float")]
     Float,
     #[token("double")]
     Double,
     #[token("string")]
     String,
+    #[token("bytes")]
+    Bytes,
     #[token("boolean")]
     Boolean,
     #[token("array")]
@@ -96,23 +129,34 @@ pub enum Token {
     #[token("char")]
     Char,
 
-    // Literals
-    #[regex(r"-?[0-9]+")]
-    IntLiteral,
-    #[regex(r"-?[0-9]+\.[0-9]+")]
-    FloatLiteral,
-    #[regex(r#""([^"\\]|\\['"\\nrt])*""#)]
-    StringLiteral,
+    // Literals. Numbers may group their digits with `_`, as in `1_000_000`,
+    // and decimal ones may end in a suffix giving their type: `42i` is an
+    // int, `42u` a uint and `42f` or `3.5f` a float. Number tokens hold
+    // their text as written, separators, prefix and suffix included.
+    #[regex(r"-?[0-9][0-9_]*i?", decimal_separators)]
+    IntLiteral(&'src str),
+    #[regex(r"[0-9][0-9_]*u", decimal_separators)]
+    UIntLiteral(&'src str),
+    #[regex(r"-?[0-9][0-9_]*\.[0-9][0-9_]*f?", decimal_separators)]
+    #[regex(r"-?[0-9][0-9_]*f", decimal_separators)]
+    FloatLiteral(&'src str),
+    /// The text, with its escapes decoded
+    #[token("\"", string_literal)]
+    StringLiteral(Cow<'src, str>),
+    /// `re"\d+"`, a regular expression whose backslashes are kept as
+    /// written. Holds the pattern, without the quotes.
+    #[regex(r#"re"([^"\\]|\\.)*""#, regex_pattern)]
+    RegexLiteral(&'src str),
     #[regex("'[^']*'")]
     CharLiteral,
-    #[regex(r"0x[0-9a-fA-F]+")]
-    HexLiteral,
-    #[regex(r"0b[01]+")]
-    BinaryLiteral,
-    #[regex(r"0o[0-7]+")]
-    OctalLiteral,
-    #[regex(r"-?[0-9]+(\.[0-9]+)?[eE][+-]?[0-9]+")]
-    ScientificLiteral,
+    #[regex(r"0x[0-9a-fA-F_]+", radix_separators)]
+    HexLiteral(&'src str),
+    #[regex(r"0b[01_]+", radix_separators)]
+    BinaryLiteral(&'src str),
+    #[regex(r"0o[0-7_]+", radix_separators)]
+    OctalLiteral(&'src str),
+    #[regex(r"-?[0-9][0-9_]*(\.[0-9][0-9_]*)?[eE][+-]?[0-9][0-9_]*f?", decimal_separators)]
+    ScientificLiteral(&'src str),
     #[token("true")]
     True,
     #[token("false")]
@@ -121,8 +165,11 @@ pub enum Token {
     Null,
 
     // Identifiers
-    #[regex("[a-zA-Z_][a-zA-Z0-9_]*", priority = 1)]
-    Identifier,
+    /// Library names such as `Actor`, `TVar`, `task` and `block` are
+    /// identifiers too; the parser treats them as keywords only where
+    /// they start the construct they name.
+    #[regex("[a-zA-Z_][a-zA-Z0-9_]*", |lexer| lexer.slice(), priority = 1)]
+    Identifier(&'src str),
 
     // Operators
     #[token("+")]
@@ -167,6 +214,18 @@ pub enum Token {
     And,
     #[token("||")]
     Or,
+    #[token("|")]
+    Pipe,
+    #[token("&")]
+    Ampersand,
+    #[token("^")]
+    Caret,
+    #[token("~")]
+    Tilde,
+    #[token("<<")]
+    ShiftLeft,
+    #[token(">>")]
+    ShiftRight,
     #[token("!")]
     Not,
     #[token("??")]
@@ -202,43 +261,42 @@ pub enum Token {
     #[token("::")]
     DoubleColon,
 
-    // Template Strings
-    #[regex(r"`[^`]*`")]
-    TemplateString,
-    #[regex(r"\$\{[^}]*\}")]
-    TemplateInterpolation,
-
-    // Documentation
-    #[regex(r"///[^\n]*")]
-    DocComment,
-    #[regex(r"/\*\*([^*]|\*[^/])*\*/")]
-    MultilineDocComment,
+    // Template strings, like `Hello ${user.name}`: the lexer switches to
+    // text after the backtick, and back to tokens inside each `${..}`
+    #[token("`")]
+    TemplateStart,
+    TemplateText(Cow<'src, str>),
+    InterpolationStart,
+    InterpolationEnd,
+    TemplateEnd,
+
+    // The end of the input, with an empty span there. It's always the last
+    // token, so running out of input has a place to be reported at
+    Eof,
+
+    // Documentation, with the text of the comment
+    #[regex(r"///[^\n]*", |lexer| doc_line(&lexer.slice()[3..]))]
+    DocComment(&'src str),
+    #[token("/**", doc_block_comment)]
+    MultilineDocComment(Cow<'src, str>),
 
     // Blockchain Specific
-    #[token("ledger")]
-    Ledger,
     #[token("validate")]
     Validate,
-    #[token("mine")]
-    Mine,
-    #[token("block")]
-    Block,
-    #[token("hash")]
-    Hash,
+    #[token("require")]
+    Require,
+    #[token("assert")]
+    Assert,
     #[token("msg.sender")]
     MsgSender,
     #[token("new")]
     New,
-    #[token("sign")]
-    Sign,
     #[token("payable")]
     Payable,
     #[token("view")]
     View,
     #[token("pure")]
     Pure,
-    #[token("emit")]
-    Emit,
     #[token("constructor")]
     Constructor,
     #[token("this")]
@@ -247,38 +305,10 @@ pub enum Token {
     Super,
 
     // Concurrency
-    #[token("sync")]
-    Sync,
-    #[token("mutex")]
-    Mutex,
-    #[token("semaphore")]
-    Semaphore,
-    #[token("barrier")]
-    Barrier,
-    #[token("lock")]
-    Lock,
-    #[token("unlock")]
-    Unlock,
-    #[token("wait")]
-    Wait,
-    #[token("signal")]
-    Signal,
     #[token("spawn")]
     Spawn,
-    #[token("channel")]
-    Channel,
-    #[token("select")]
-    Select,
-    #[token("task")]
-    Task,
-    #[token("commit")]
-    Commit,
-    #[token("abort")]
-    Abort,
-    #[token("retry")]
-    Retry,
-    #[token("backoff")]
-    Backoff,
+    #[token("scope")]
+    Scope,
 
     // WebAssembly
     #[token("@wasm")]
@@ -299,6 +329,12 @@ pub enum Token {
     Modifier,
     #[token("@scheduled")]
     Scheduled,
+    #[token("@slot")]
+    Slot,
+    #[token("@derive")]
+    Derive,
+    #[token("@cfg")]
+    Cfg,
 
     // Control Flow
     #[token("foreach")]
@@ -314,14 +350,6 @@ pub enum Token {
     #[token("in")]
     In,
 
-    // Decision Tokens
-    #[token("Decision.RESTART")]
-    DecisionRestart,
-    #[token("Decision.STOP")]
-    DecisionStop,
-    #[token("Decision.ESCALATE")]
-    DecisionEscalate,
-
     // Additional Keywords
     #[token("readonly")]
     Readonly,
@@ -340,24 +368,21 @@ pub enum Token {
     From,
     #[token("as")]
     As,
+    /// `as?`, a cast that's `null` when the value isn't of the type
+    #[token("as?")]
+    SafeAs,
+    #[token("is")]
+    Is,
 
-    // Actor System
-    #[token("Actor")]
-    Actor,
-    #[token("MessageQueue")]
-    MessageQueue,
-    #[token("ActorBehavior")]
-    ActorBehavior,
-    #[token("Supervisor")]
-    Supervisor,
-    #[token("SupervisionStrategy")]
-    SupervisionStrategy,
-    #[token("Decision")]
-    Decision,
+    // Macros
+    #[token("macro")]
+    Macro,
+
+    // Inline IR
+    #[token("llvm")]
+    Llvm,
 
     // STM
-    #[token("TVar")]
-    TVar,
     #[token("atomic", priority = 2)]
     Atomic,
   
@@ -378,13 +403,251 @@ pub enum Token {
     Finally,
 }
 
-impl fmt::Display for Token {
+/// The kind of token; an identifier's name is left out, like the text of
+/// the other tokens.
+impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Token::Identifier(_) => write!(f, "Identifier"),
+            Token::StringLiteral(_) => write!(f, "StringLiteral"),
+            Token::IntLiteral(_) => write!(f, "IntLiteral"),
+            Token::UIntLiteral(_) => write!(f, "UIntLiteral"),
+            Token::FloatLiteral(_) => write!(f, "FloatLiteral"),
+            Token::HexLiteral(_) => write!(f, "HexLiteral"),
+            Token::BinaryLiteral(_) => write!(f, "BinaryLiteral"),
+            Token::OctalLiteral(_) => write!(f, "OctalLiteral"),
+            Token::ScientificLiteral(_) => write!(f, "ScientificLiteral"),
+            Token::RegexLiteral(_) => write!(f, "RegexLiteral"),
+            Token::DocComment(_) => write!(f, "DocComment"),
+            Token::MultilineDocComment(_) => write!(f, "MultilineDocComment"),
+            token => write!(f, "{:?}", token),
+        }
+    }
+}
+
+impl Token<'_> {
+    /// This token as lexed at `span` of `source`, whose text there is the
+    /// text it was lexed from, borrowing from `source` instead.
+    fn rebased<'b>(&self, source: &'b str, span: Span) -> Token<'b> {
+        let slice = &source[span.start..span.end];
+        match self {
+            // Unreserved keywords are identifiers too
+            Token::Identifier(_) => Token::Identifier(slice),
+            // Template tokens depend on what came before them
+            Token::TemplateText(Cow::Borrowed(_)) => Token::TemplateText(Cow::Borrowed(slice)),
+            Token::TemplateText(Cow::Owned(text)) => Token::TemplateText(Cow::Owned(text.clone())),
+            Token::TemplateStart => Token::TemplateStart,
+            Token::InterpolationStart => Token::InterpolationStart,
+            Token::InterpolationEnd => Token::InterpolationEnd,
+            Token::TemplateEnd => Token::TemplateEnd,
+            Token::Eof => Token::Eof,
+            _ => match Token::lexer(slice).next() {
+                Some(Ok(token)) => token,
+                _ => unreachable!("{:?} doesn't lex from {:?}", self, slice),
+            },
+        }
+    }
+}
+
+/// Bumps past the end of a block comment, whose opening the lexer just
+/// matched, and any comments nested in it. Unterminated, it takes the rest
+/// of the input and fails.
+fn close_block_comment<'s>(lexer: &mut logos::Lexer<'s, Token<'s>>) -> bool {
+    let remainder = lexer.remainder();
+    let mut depth = 1;
+    let mut i = 0;
+    while depth > 0 {
+        let rest = &remainder[i..];
+        if rest.starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if rest.starts_with("*/") {
+            depth -= 1;
+            i += 2;
+        } else if let Some(c) = rest.chars().next() {
+            i += c.len_utf8();
+        } else {
+            break;
+        }
+    }
+    lexer.bump(i);
+    depth == 0
+}
+
+fn block_comment<'s>(lexer: &mut logos::Lexer<'s, Token<'s>>) -> FilterResult<(), ()> {
+    if close_block_comment(lexer) {
+        FilterResult::Skip
+    } else {
+        FilterResult::Error(())
+    }
+}
+
+/// `/**/` is an empty comment rather than the start of a doc comment.
+fn doc_block_comment<'s>(lexer: &mut logos::Lexer<'s, Token<'s>>) -> FilterResult<Cow<'s, str>, ()> {
+    if lexer.remainder().starts_with('/') {
+        lexer.bump(1);
+        FilterResult::Skip
+    } else if close_block_comment(lexer) {
+        let slice = lexer.slice();
+        let lines: Vec<&str> = slice[3..slice.len() - 2].lines()
+            .map(|line| doc_line(line.trim_start().strip_prefix('*').unwrap_or(line.trim_start())))
+            .collect();
+        let first = lines.iter().position(|line| !line.trim().is_empty()).unwrap_or(lines.len());
+        let last = lines.iter().rposition(|line| !line.trim().is_empty()).map_or(first, |last| last + 1);
+        FilterResult::Emit(match &lines[first..last] {
+            [line] => Cow::Borrowed(*line),
+            lines => Cow::Owned(lines.join("\n")),
+        })
+    } else {
+        FilterResult::Error(())
+    }
+}
+
+/// A line of a doc comment without its markers: the space after them and
+/// trailing whitespace are dropped, and any further indentation kept.
+fn doc_line(line: &str) -> &str {
+    line.strip_prefix(' ').unwrap_or(line).trim_end()
+}
+
+/// What's wrong with a string literal.
+enum StringError {
+    Unterminated,
+    /// `offset` is from just after the opening quote.
+    InvalidEscape { offset: usize, sequence: String },
+}
+
+/// Decodes a string literal's text, from just after its opening quote, and
+/// gives its length up to and including the closing quote. The text is
+/// borrowed up to the first escape.
+fn string_text(rest: &str) -> Result<(Cow<'_, str>, usize), StringError> {
+    let mut text = Cow::Borrowed("");
+    let mut i = 0;
+    loop {
+        match rest[i..].chars().next() {
+            None => return Err(StringError::Unterminated),
+            Some('"') => return Ok((text, i + 1)),
+            Some('\\') => {
+                let (c, length) = escape(&rest[i..]).map_err(|length| StringError::InvalidEscape {
+                    offset: i,
+                    sequence: rest[i..i + length].to_string(),
+                })?;
+                text.to_mut().push(c);
+                i += length;
+            },
+            Some(c) => {
+                match &mut text {
+                    Cow::Borrowed(_) => text = Cow::Borrowed(&rest[..i + c.len_utf8()]),
+                    Cow::Owned(text) => text.push(c),
+                }
+                i += c.len_utf8();
+            },
+        }
+    }
+}
+
+/// Decodes the escape at the start of `sequence`: `\n`, `\r`, `\t`, `\0`,
+/// `\\`, `\"`, `\'`, `\xNN` for an ASCII character, or `\u{N}` with up to six
+/// hex digits for any other. Gives the character and the escape's length,
+/// or the length of the invalid escape.
+fn escape(sequence: &str) -> Result<(char, usize), usize> {
+    let mut chars = sequence.chars().skip(1);
+    let simple = match chars.next() {
+        None => return Err(1),
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('0') => '\0',
+        Some(c @ ('\\' | '"' | '\'')) => c,
+        Some('x') => {
+            let digits: String = chars.take(2).collect();
+            let length = 2 + digits.len();
+            return hex(&digits).filter(|byte| digits.len() == 2 && *byte < 0x80)
+                .and_then(char::from_u32)
+                .map(|c| (c, length))
+                .ok_or(length);
+        },
+        Some('u') => {
+            let body = sequence[2..].strip_prefix('{').ok_or(2usize)?;
+            // The closing brace has to come before the string's closing quote
+            let end = body.find(['}', '"']).filter(|end| body[*end..].starts_with('}')).ok_or(3usize)?;
+            let length = 3 + end + 1;
+            return hex(&body[..end]).filter(|_| end <= 6)
+                .and_then(char::from_u32)
+                .map(|c| (c, length))
+                .ok_or(length);
+        },
+        Some(c) => return Err(1 + c.len_utf8()),
+    };
+    Ok((simple, 2))
+}
+
+fn hex(digits: &str) -> Option<u32> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(digits, 16).ok()
+}
+
+/// Lexes a string literal from its opening quote. An invalid one still
+/// takes everything up to its closing quote, so lexing goes on after it;
+/// `Lexer::next` works out what's wrong with it.
+fn string_literal<'s>(lexer: &mut logos::Lexer<'s, Token<'s>>) -> FilterResult<Cow<'s, str>, ()> {
+    let rest = lexer.remainder();
+    match string_text(rest) {
+        Ok((text, length)) => {
+            lexer.bump(length);
+            FilterResult::Emit(text)
+        },
+        Err(_) => {
+            let mut chars = rest.char_indices();
+            let mut length = rest.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    },
+                    '"' => {
+                        length = i + 1;
+                        break;
+                    },
+                    _ => {},
+                }
+            }
+            lexer.bump(length);
+            FilterResult::Error(())
+        },
     }
 }
 
-#[derive(Debug)]
+/// Separators go between digits: not first or last in a group of digits,
+/// nor two in a row.
+fn separators_are_valid(group: &str) -> bool {
+    !group.starts_with('_') && !group.ends_with('_') && !group.contains("__")
+}
+
+/// A number's groups of digits are split by its point and exponent.
+fn decimal_separators<'s>(lexer: &mut logos::Lexer<'s, Token<'s>>) -> Option<&'s str> {
+    let slice = lexer.slice();
+    slice.trim_end_matches(['i', 'u', 'f'])
+        .split(['-', '+', '.', 'e', 'E'])
+        .all(separators_are_valid)
+        .then_some(slice)
+}
+
+/// Hex, binary and octal numbers are one group after their prefix, and
+/// take no suffix: `f` is a hex digit.
+fn radix_separators<'s>(lexer: &mut logos::Lexer<'s, Token<'s>>) -> Option<&'s str> {
+    let slice = lexer.slice();
+    separators_are_valid(&slice[2..]).then_some(slice)
+}
+
+/// The pattern of a regex literal, between `re"` and the closing quote.
+fn regex_pattern<'s>(lexer: &mut logos::Lexer<'s, Token<'s>>) -> &'s str {
+    let slice = lexer.slice();
+    &slice[3..slice.len() - 1]
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum LexerError {
     InvalidToken { 
         position: usize,
@@ -405,6 +668,7 @@ pub enum LexerError {
     },
     UnterminatedComment {
         position: usize,
+        partial: String,
     },
     InvalidCharacter {
         position: usize,
@@ -428,176 +692,554 @@ pub enum LexerError {
     },
 }
 
-impl std::fmt::Display for LexerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl LexerError {
+    /// The error's code in `gard_ast::message`'s catalog, which stays the
+    /// same across releases and locales.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexerError::InvalidToken { .. } => "L0001",
+            LexerError::UnterminatedString { .. } => "L0002",
+            LexerError::InvalidEscape { .. } => "L0003",
+            LexerError::InvalidNumber { .. } => "L0004",
+            LexerError::UnterminatedComment { .. } => "L0005",
+            LexerError::InvalidCharacter { .. } => "L0006",
+            LexerError::InvalidActorMessage { .. } => "L0007",
+            LexerError::InvalidTransactionState { .. } => "L0008",
+            LexerError::InvalidDecisionType { .. } => "L0009",
+            LexerError::InvalidBehaviorType { .. } => "L0010",
+        }
+    }
+
+    /// What could have been there instead, to suggest as a fix; empty for
+    /// errors other than `InvalidToken`.
+    pub fn expected(&self) -> &[String] {
+        match self {
+            LexerError::InvalidToken { expected, .. } => expected,
+            _ => &[],
+        }
+    }
+
+    /// The byte offset the error is at.
+    pub fn position(&self) -> usize {
+        match self {
+            LexerError::InvalidToken { position, .. }
+            | LexerError::UnterminatedString { position, .. }
+            | LexerError::InvalidEscape { position, .. }
+            | LexerError::InvalidNumber { position, .. }
+            | LexerError::UnterminatedComment { position, .. }
+            | LexerError::InvalidCharacter { position, .. }
+            | LexerError::InvalidActorMessage { position, .. }
+            | LexerError::InvalidTransactionState { position, .. }
+            | LexerError::InvalidDecisionType { position, .. }
+            | LexerError::InvalidBehaviorType { position, .. } => *position,
+        }
+    }
+
+    pub fn message(&self, locale: Locale) -> String {
+        let code = self.code();
         match self {
             LexerError::InvalidToken { position, found, expected } => {
-                write!(f, "Invalid token '{}' at position {}, expected one of: {}", 
-                    found, position, expected.join(", "))
-            },
-            LexerError::UnterminatedString { position, partial } => {
-                write!(f, "Unterminated string literal starting at position {}: '{}'", 
-                    position, partial)
-            },
-            LexerError::InvalidEscape { position, sequence } => {
-                write!(f, "Invalid escape sequence '{}' at position {}", 
-                    sequence, position)
+                message(code, locale, &[("found", found), ("position", position), ("expected", &expected.join(", "))])
             },
-            LexerError::InvalidNumber { position, value } => {
-                write!(f, "Invalid number format '{}' at position {}", 
-                    value, position)
-            },
-            LexerError::UnterminatedComment { position } => {
-                write!(f, "Unterminated comment starting at position {}", position)
-            },
-            LexerError::InvalidCharacter { position, character } => {
-                write!(f, "Invalid character '{}' at position {}", character, position)
-            },
-            LexerError::InvalidActorMessage { position, message } => {
-                write!(f, "Invalid actor message '{}' at position {}", message, position)
-            },
-            LexerError::InvalidTransactionState { position, state } => {
-                write!(f, "Invalid transaction state '{}' at position {}", state, position)
-            },
-            LexerError::InvalidDecisionType { position, decision } => {
-                write!(f, "Invalid supervision decision '{}' at position {}", decision, position)
-            },
-            LexerError::InvalidBehaviorType { position, behavior } => {
-                write!(f, "Invalid actor behavior '{}' at position {}", behavior, position)
+            LexerError::UnterminatedString { position, partial } | LexerError::UnterminatedComment { position, partial } => {
+                message(code, locale, &[("position", position), ("partial", partial)])
             },
+            LexerError::InvalidEscape { position, sequence } => message(code, locale, &[("sequence", sequence), ("position", position)]),
+            LexerError::InvalidNumber { position, value } => message(code, locale, &[("value", value), ("position", position)]),
+            LexerError::InvalidCharacter { position, character } => message(code, locale, &[("character", character), ("position", position)]),
+            LexerError::InvalidActorMessage { position, message: text } => message(code, locale, &[("message", text), ("position", position)]),
+            LexerError::InvalidTransactionState { position, state } => message(code, locale, &[("state", state), ("position", position)]),
+            LexerError::InvalidDecisionType { position, decision } => message(code, locale, &[("decision", decision), ("position", position)]),
+            LexerError::InvalidBehaviorType { position, behavior } => message(code, locale, &[("behavior", behavior), ("position", position)]),
         }
     }
 }
 
+/// The message in English.
+impl std::fmt::Display for LexerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message(Locale::En))
+    }
+}
+
 impl std::error::Error for LexerError {}
 
+/// Replaces `start..end` of a source with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn apply(&self, source: &str) -> String {
+        format!("{}{}{}", &source[..self.start], self.text, &source[self.end..])
+    }
+}
+
+/// Which sets of domain keywords are reserved, and what's recorded about
+/// line breaks. A disabled set's keywords lex as identifiers, and its
+/// decorators, like `@WasmExport`, as `@` followed by one. All sets are
+/// enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LexerOptions {
+    /// `blockchain`, `contract`, `transaction`, `validate`, `msg.sender`,
+    /// `payable`, `view`, `pure`, `@event`, `@modifier` and `@slot`
+    pub blockchain: bool,
+    /// `actorlocal` and `become`
+    pub actors: bool,
+    /// `atomic`
+    pub stm: bool,
+    /// `@wasm`, `@WasmExport`, `@WasmImport` and `@WasmMemory`
+    pub wasm: bool,
+    /// Set `newline_before` on tokens; off by default, leaving it false
+    pub newlines: bool,
+}
+
+impl Default for LexerOptions {
+    fn default() -> Self {
+        Self { blockchain: true, actors: true, stm: true, wasm: true, newlines: false }
+    }
+}
+
+impl LexerOptions {
+    fn reserves(&self, token: &Token) -> bool {
+        match token {
+            Token::Blockchain | Token::Contract | Token::Transaction | Token::Validate | Token::MsgSender
+            | Token::Payable | Token::View | Token::Pure | Token::Event | Token::Modifier | Token::Slot => self.blockchain,
+            Token::ActorLocal | Token::Become => self.actors,
+            Token::Atomic => self.stm,
+            Token::Wasm | Token::WasmExport | Token::WasmImport | Token::WasmMemory => self.wasm,
+            _ => true,
+        }
+    }
+}
+
+/// Lexes on demand: as an iterator, each `next` lexes one more token, so
+/// tools that only need the start of a file don't lex the rest. The
+/// `tokenize` methods lex everything that's left, through `Token::Eof`.
 pub struct Lexer<'a> {
-    inner: logos::Lexer<'a, Token>,
+    inner: logos::Lexer<'a, Token<'a>>,
+    /// The template strings being lexed, innermost last
+    templates: Vec<Template>,
+    options: LexerOptions,
+    /// Where the last token ended
+    previous_end: usize,
+    /// Whether `Token::Eof` has been lexed
+    ended: bool,
+}
+
+/// A template string being lexed: where it starts, and while lexing one of
+/// its interpolations, how many braces are open in it.
+struct Template {
+    start: usize,
+    interpolation: Option<usize>,
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<TokenWithSpan<'a>, LexerError>;
+
+    /// An invalid token is an error; lexing goes on after it.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.templates.last().is_some_and(|template| template.interpolation.is_none()) {
+            return Some(self.template_text());
+        }
+        let Some(token) = self.inner.next() else {
+            // The input ended inside an interpolation
+            if !self.templates.is_empty() {
+                return Some(Err(self.unterminated_template()));
+            }
+            return (!self.ended).then(|| {
+                self.ended = true;
+                Ok(self.eof())
+            });
+        };
+        let span = Span {
+            start: self.inner.span().start,
+            end: self.inner.span().end,
+        };
+
+        let slice = self.inner.slice();
+        Some(match token {
+            Ok(
+                Token::IntLiteral(_) | Token::UIntLiteral(_) | Token::FloatLiteral(_) | Token::HexLiteral(_)
+                | Token::BinaryLiteral(_) | Token::OctalLiteral(_) | Token::ScientificLiteral(_)
+            ) if self.inner.remainder().starts_with(|c: char| c.is_alphanumeric() || c == '_') => {
+                // A number runs into a word, like `0xg` or `1e`
+                let word = self.inner.remainder().split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or_default();
+                let found = format!("{}{}", slice, word);
+                let expected = number_expected(slice, word);
+                self.inner.bump(word.len());
+                Err(LexerError::InvalidToken { position: span.start, found, expected })
+            },
+            Ok(token) => {
+                let (token, span) = self.unreserve(token, span);
+                let token = self.track_templates(token, span.start);
+                Ok(self.spanned(token, span))
+            },
+            Err(_) => Err(literal_error(slice, span.start).unwrap_or_else(|| LexerError::InvalidToken {
+                position: span.start,
+                found: slice.to_string(),
+                expected: token_expected(slice),
+            })),
+        })
+    }
+}
+
+/// What could have come after `number` instead of `word`.
+fn number_expected(number: &str, word: &str) -> Vec<String> {
+    let decimal = !number.starts_with("0x") && !number.starts_with("0b") && !number.starts_with("0o")
+        && number.trim_start_matches('-').chars().all(|c| c.is_ascii_digit() || c == '_' || c == '.');
+    let expected: &[&str] = match word.chars().next() {
+        Some('x') if number == "0" => &["hex digit after 0x"],
+        Some('b') if number == "0" => &["binary digit after 0b"],
+        Some('o') if number == "0" => &["octal digit after 0o"],
+        Some('e' | 'E') if decimal => &["digit after the exponent"],
+        Some(_) if decimal => &["suffix i, u or f", "whitespace or an operator after a number"],
+        _ => &["whitespace or an operator after a number"],
+    };
+    expected.iter().map(|expected| expected.to_string()).collect()
+}
+
+/// What could have been meant by `found`, which starts no token.
+fn token_expected(found: &str) -> Vec<String> {
+    let expected: &[&str] = match found.chars().next() {
+        Some('\'') => &["closing quote after a character"],
+        Some('$') => &["`${` inside a template string"],
+        Some('#') => &["`//` to start a comment", "`@` to start an attribute"],
+        Some('?') => &["`??`", "`?.`"],
+        Some('\\') => &["escape inside a string"],
+        Some(c) if c.is_alphabetic() => &["ASCII letter, digit or `_` in an identifier"],
+        _ => &["operator", "punctuation", "literal", "identifier"],
+    };
+    expected.iter().map(|expected| expected.to_string()).collect()
+}
+
+/// What's wrong with a number, string or comment that failed to lex at
+/// `start`. An unterminated one takes the rest of the input, which is its
+/// partial content.
+fn literal_error(slice: &str, start: usize) -> Option<LexerError> {
+    // Only a misplaced separator fails to lex as a number
+    if slice.trim_start_matches('-').starts_with(|c: char| c.is_ascii_digit()) {
+        return Some(LexerError::InvalidNumber { position: start, value: slice.to_string() });
+    }
+    if let Some(rest) = slice.strip_prefix('"') {
+        return Some(match string_text(rest) {
+            Err(StringError::InvalidEscape { offset, sequence }) => LexerError::InvalidEscape { position: start + 1 + offset, sequence },
+            _ => LexerError::UnterminatedString { position: start, partial: slice.to_string() },
+        });
+    }
+    slice.starts_with("/*").then(|| LexerError::UnterminatedComment { position: start, partial: slice.to_string() })
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_options(input, LexerOptions::default())
+    }
+
+    pub fn with_options(input: &'a str, options: LexerOptions) -> Self {
         Self {
             inner: Token::lexer(input),
+            templates: Vec::new(),
+            options,
+            previous_end: 0,
+            ended: false,
         }
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<TokenWithSpan>, LexerError> {
-        let mut tokens = Vec::new();
-        
-        while let Some(token) = self.inner.next() {
-            let span = Span {
-                start: self.inner.span().start,
-                end: self.inner.span().end,
-            };
-
-            match token {
-                Ok(token) => tokens.push(TokenWithSpan { token, span }),
-                Err(_) => return Err(LexerError::InvalidToken {
-                    position: self.inner.span().start,
-                    found: self.inner.slice().to_string(),
-                    expected: vec!["valid token".to_string()],
-                }),
-            }
+    /// `token` at `span`, after the token lexed before it.
+    fn spanned(&mut self, token: Token<'a>, span: Span) -> TokenWithSpan<'a> {
+        let newline_before = self.options.newlines && self.inner.source()[self.previous_end..span.start].contains('\n');
+        self.previous_end = span.end;
+        TokenWithSpan { token, span, newline_before }
+    }
+
+    /// The `Token::Eof` at the end of the input.
+    fn eof(&mut self) -> TokenWithSpan<'a> {
+        let end = self.inner.source().len();
+        self.spanned(Token::Eof, Span { start: end, end })
+    }
+
+    /// A keyword the options don't reserve as the identifier it's spelled
+    /// as. One with punctuation in it, like `msg.sender`, is cut after its
+    /// first word or its `@`, and lexing goes on from there.
+    fn unreserve(&mut self, token: Token<'a>, span: Span) -> (Token<'a>, Span) {
+        if self.options.reserves(&token) {
+            return (token, span);
+        }
+        let slice = self.inner.slice();
+        let (token, length) = match slice.strip_prefix('@') {
+            Some(_) => (Token::At, 1),
+            None => {
+                let word = slice.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or(slice);
+                (Token::Identifier(word), word.len())
+            },
+        };
+        if length < slice.len() {
+            let source = self.inner.source();
+            self.inner = Token::lexer(source);
+            self.inner.bump(span.start + length);
+        }
+        (token, Span { start: span.start, end: span.start + length })
+    }
+
+    /// Opens a template at a backtick, and ends an interpolation at the
+    /// brace that closes it.
+    fn track_templates(&mut self, token: Token<'a>, start: usize) -> Token<'a> {
+        let interpolation = self.templates.last_mut().and_then(|template| template.interpolation.as_mut());
+        match (token, interpolation) {
+            (Token::TemplateStart, _) => {
+                self.templates.push(Template { start, interpolation: None });
+                Token::TemplateStart
+            },
+            (Token::LeftBrace, Some(depth)) => {
+                *depth += 1;
+                Token::LeftBrace
+            },
+            (Token::RightBrace, Some(depth)) if *depth > 0 => {
+                *depth -= 1;
+                Token::RightBrace
+            },
+            (Token::RightBrace, Some(_)) => {
+                if let Some(template) = self.templates.last_mut() {
+                    template.interpolation = None;
+                }
+                Token::InterpolationEnd
+            },
+            (token, _) => token,
         }
+    }
+
+    /// Lexes template text up to the next interpolation or the closing
+    /// backtick. A backslash escapes `` ` ``, `$` or itself, or starts `\n`,
+    /// `\r` or `\t`.
+    fn template_text(&mut self) -> Result<TokenWithSpan<'a>, LexerError> {
+        let start = self.inner.span().end;
+        let remainder = self.inner.remainder();
+        // Borrowed up to the first escape
+        let mut text = Cow::Borrowed("");
+        let mut chars = remainder.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((i, '`')) => break i,
+                Some((i, '$')) if remainder[i + 1..].starts_with('{') => break i,
+                Some((i, '\\')) => match chars.next() {
+                    Some((_, c @ ('`' | '$' | '\\'))) => text.to_mut().push(c),
+                    Some((_, 'n')) => text.to_mut().push('\n'),
+                    Some((_, 'r')) => text.to_mut().push('\r'),
+                    Some((_, 't')) => text.to_mut().push('\t'),
+                    escape => {
+                        let end = escape.map_or(remainder.len(), |(j, c)| j + c.len_utf8());
+                        self.inner.bump(end);
+                        return Err(LexerError::InvalidEscape { position: start + i, sequence: remainder[i..end].to_string() });
+                    },
+                },
+                Some((i, c)) => match &mut text {
+                    Cow::Borrowed(_) => text = Cow::Borrowed(&remainder[..i + c.len_utf8()]),
+                    Cow::Owned(text) => text.push(c),
+                },
+                None => return Err(self.unterminated_template()),
+            }
+        };
+
+        let (token, length) = if end > 0 {
+            (Token::TemplateText(text), end)
+        } else if remainder.starts_with('`') {
+            self.templates.pop();
+            (Token::TemplateEnd, 1)
+        } else if remainder[2..].trim_start().starts_with('}') {
+            let length = remainder.find('}').map_or(remainder.len(), |end| end + 1);
+            self.inner.bump(length);
+            return Err(LexerError::InvalidToken {
+                position: start,
+                found: remainder[..length].to_string(),
+                expected: vec!["expression".to_string()],
+            });
+        } else {
+            if let Some(template) = self.templates.last_mut() {
+                template.interpolation = Some(0);
+            }
+            (Token::InterpolationStart, 2)
+        };
+        self.inner.bump(length);
+        Ok(self.spanned(token, Span { start, end: start + length }))
+    }
+
+    /// Ends every open template at the end of the input.
+    fn unterminated_template(&mut self) -> LexerError {
+        let start = self.templates[0].start;
+        self.templates.clear();
+        self.inner.bump(self.inner.remainder().len());
+        LexerError::UnterminatedString { position: start, partial: self.inner.source()[start..].to_string() }
+    }
+
+    pub fn tokenize(&mut self) -> Result<Vec<TokenWithSpan<'a>>, LexerError> {
+        self.collect()
+    }
+
+    /// The tokens of `source`, which is the source `old_tokens` were lexed
+    /// from with `edit` applied, lexing only what the edit can have
+    /// changed. The old tokens are kept up to the last one that ends before
+    /// the edit outside a template string, since lexing a template depends
+    /// on what came before. From there, lexing stops at the first token
+    /// after the edit that is also an old token outside a template, and the
+    /// rest of the old tokens are moved by the edit's change in length.
+    /// The old tokens must have been lexed with the same `options`; the
+    /// ones kept are rebased to borrow from `source`, so the old source can
+    /// be dropped.
+    pub fn relex(source: &'a str, old_tokens: &[TokenWithSpan<'_>], edit: &TextEdit, options: LexerOptions) -> Result<Vec<TokenWithSpan<'a>>, LexerError> {
+        // How many templates are open after each old token
+        let depths: Vec<usize> = old_tokens.iter()
+            .scan(0, |depth, token| {
+                match token.token {
+                    Token::TemplateStart => *depth += 1,
+                    Token::TemplateEnd => *depth -= 1,
+                    _ => {},
+                }
+                Some(*depth)
+            })
+            .collect();
+        let kept = old_tokens.iter().zip(&depths)
+            .take_while(|(token, _)| token.span.end < edit.start)
+            .enumerate()
+            .filter(|(_, (_, depth))| **depth == 0)
+            .last()
+            .map_or(0, |(i, _)| i + 1);
+        let mut tokens: Vec<TokenWithSpan> = old_tokens[..kept].iter()
+            .map(|old| TokenWithSpan { token: old.token.rebased(source, old.span), span: old.span, newline_before: old.newline_before })
+            .collect();
 
+        let mut lexer = Lexer::with_options(source, options);
+        lexer.previous_end = tokens.last().map_or(0, |token| token.span.end);
+        lexer.inner.bump(lexer.previous_end);
+        let removed = edit.end - edit.start;
+        let inserted = edit.text.len();
+        while let Some(token) = lexer.next() {
+            let token = token?;
+            if token.span.start >= edit.start + inserted && lexer.templates.is_empty() {
+                let start = token.span.start + removed - inserted;
+                if let Ok(i) = old_tokens.binary_search_by_key(&start, |old| old.span.start) {
+                    let old = &old_tokens[i];
+                    if depths[i] == 0 && old.token == token.token && old.newline_before == token.newline_before
+                        && old.span.end + inserted == token.span.end + removed
+                    {
+                        tokens.extend(old_tokens[i..].iter().map(|old| {
+                            let span = Span { start: old.span.start + inserted - removed, end: old.span.end + inserted - removed };
+                            TokenWithSpan { token: old.token.rebased(source, span), span, newline_before: old.newline_before }
+                        }));
+                        return Ok(tokens);
+                    }
+                }
+            }
+            tokens.push(token);
+        }
         Ok(tokens)
     }
 
-    pub fn tokenize_with_errors(&mut self) -> (Vec<TokenWithSpan>, Vec<LexerError>) {
+    pub fn tokenize_with_errors(&mut self) -> (Vec<TokenWithSpan<'a>>, Vec<LexerError>) {
         let mut tokens = Vec::new();
         let mut errors = Vec::new();
 
-        while let Some(token) = self.inner.next() {
-            let span = Span {
-                start: self.inner.span().start,
-                end: self.inner.span().end,
-            };
-
-            match token {
-                Ok(token) => tokens.push(TokenWithSpan { token, span }),
-                Err(_) => errors.push(LexerError::InvalidToken {
-                    position: self.inner.span().start,
-                    found: self.inner.slice().to_string(),
-                    expected: vec!["valid token".to_string()],
-                }),
+        for result in self {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
             }
         }
 
         (tokens, errors)
     }
 
-    pub fn tokenize_with_recovery(&mut self) -> (Vec<TokenWithSpan>, Vec<LexerError>) {
+    /// Lexes all of the input, collecting errors instead of stopping at the
+    /// first. After an error, lexing picks up where the next token can
+    /// start: after the invalid character, or after the whole of a
+    /// malformed number or literal, never inside a character. Positions are
+    /// byte offsets, and any input lexes without panicking.
+    pub fn tokenize_with_recovery(&mut self) -> (Vec<TokenWithSpan<'a>>, Vec<LexerError>) {
         let mut tokens = Vec::new();
         let mut errors = Vec::new();
-        let mut current_pos = 0;
-
-        while let Some(result) = self.inner.next() {
-            let span = Span {
-                start: self.inner.span().start,
-                end: self.inner.span().end,
-            };
-
+        while let Some(result) = self.next() {
             match result {
-                Ok(token) => {
-                    tokens.push(TokenWithSpan { token, span });
-                    current_pos = span.end;
-                },
-                Err(_) => {
-                    // Try to recover from error
-                    let remainder = self.inner.remainder();
-                    let error = if remainder.starts_with("Decision.") {
-                        LexerError::InvalidDecisionType {
-                            position: current_pos,
-                            decision: remainder[9..].split_whitespace().next()
-                                .unwrap_or("").to_string(),
-                        }
-                    } else if remainder.starts_with("Actor") {
-                        LexerError::InvalidBehaviorType {
-                            position: current_pos,
-                            behavior: remainder[5..].split_whitespace().next()
-                                .unwrap_or("").to_string(),
-                        }
-                    } else if remainder.starts_with("Transaction") {
-                        LexerError::InvalidTransactionState {
-                            position: current_pos,
-                            state: remainder[11..].split_whitespace().next()
-                                .unwrap_or("").to_string(),
-                        }
-                    } else {
-                        LexerError::InvalidCharacter {
-                            position: current_pos,
-                            character: remainder.chars().next().unwrap_or('\0'),
-                        }
-                    };
-
-                    errors.push(error);
-
-                    // Skip the invalid token
-                    self.inner.bump(1);
-                    current_pos += 1;
-                }
+                Ok(token) => tokens.push(token),
+                Err(LexerError::InvalidToken { position, found, expected }) => errors.push(self.recovered_error(position, found, expected)),
+                Err(error) => errors.push(error),
             }
         }
-
         (tokens, errors)
     }
+
+    /// What recovery reports for `found` at `position`, which starts no
+    /// token: a single character is an invalid one, unless what follows it
+    /// names a decision, behavior or transaction state.
+    fn recovered_error(&self, position: usize, found: String, expected: Vec<String>) -> LexerError {
+        let rest = &self.inner.source()[position + found.len()..];
+        let word = |prefix: &str| rest.strip_prefix(prefix).map(|rest| rest.split_whitespace().next().unwrap_or("").to_string());
+        if let Some(decision) = word("Decision.") {
+            return LexerError::InvalidDecisionType { position, decision };
+        }
+        if let Some(behavior) = word("Actor") {
+            return LexerError::InvalidBehaviorType { position, behavior };
+        }
+        if let Some(state) = word("Transaction") {
+            return LexerError::InvalidTransactionState { position, state };
+        }
+        let mut chars = found.chars();
+        match (chars.next(), chars.next()) {
+            (Some(character), None) => LexerError::InvalidCharacter { position, character },
+            _ => LexerError::InvalidToken { position, found, expected },
+        }
+    }
+}
+
+/// A source file of a project, read for `tokenize_files`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub path: PathBuf,
+    pub source: String,
+}
+
+/// Reads `paths` in parallel, numbering the files in the order given.
+pub fn read_files(paths: &[impl AsRef<Path> + Sync]) -> Result<Vec<SourceFile>, String> {
+    paths.par_iter().enumerate().map(|(i, path)| {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let id = FileId(u32::try_from(i).map_err(|_| "Too many source files".to_string())?);
+        Ok(SourceFile { id, path: path.to_path_buf(), source })
+    }).collect()
+}
+
+/// Lexes each file on its own thread, with recovery, returning each one's
+/// tokens and errors in the order of `files`. The tokens borrow the files'
+/// text, which is why the files are read beforehand, by `read_files`.
+pub fn tokenize_files(files: &[SourceFile]) -> Vec<(FileId, Vec<TokenWithSpan<'_>>, Vec<LexerError>)> {
+    files.par_iter().map(|file| {
+        let (tokens, errors) = Lexer::new(&file.source).tokenize_with_recovery();
+        (file.id, tokens, errors)
+    }).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn ident(name: &str) -> Token<'_> {
+        Token::Identifier(name)
+    }
+
     #[test]
     fn test_keywords() {
         let input = "let function class blockchain contract";
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize().unwrap();
         assert_eq!(tokens, vec![
-            TokenWithSpan { token: Token::Let, span: Span { start: 0, end: 3 } },
-            TokenWithSpan { token: Token::Function, span: Span { start: 4, end: 11 } },
-            TokenWithSpan { token: Token::Class, span: Span { start: 12, end: 17 } },
-            TokenWithSpan { token: Token::Blockchain, span: Span { start: 18, end: 28 } },
-            TokenWithSpan { token: Token::Contract, span: Span { start: 29, end: 36 } },
+            TokenWithSpan::new(Token::Let, Span { start: 0, end: 3 }),
+            TokenWithSpan::new(Token::Function, Span { start: 4, end: 11 }),
+            TokenWithSpan::new(Token::Class, Span { start: 12, end: 17 }),
+            TokenWithSpan::new(Token::Blockchain, Span { start: 18, end: 28 }),
+            TokenWithSpan::new(Token::Contract, Span { start: 29, end: 36 }),
+            TokenWithSpan::new(Token::Eof, Span { start: 38, end: 38 }),
         ]);
     }
 
@@ -607,12 +1249,13 @@ mod tests {
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize().unwrap();
         assert_eq!(tokens.iter().map(|t| &t.token).collect::<Vec<_>>(), vec![
-            &Token::IntLiteral,
-            &Token::FloatLiteral,
-            &Token::StringLiteral,
+            &Token::IntLiteral("42"),
+            &Token::FloatLiteral("3.14"),
+            &Token::StringLiteral("hello".into()),
             &Token::True,
             &Token::False,
             &Token::Null,
+            &Token::Eof,
         ]);
     }
 
@@ -624,20 +1267,21 @@ mod tests {
         
         // Create expected tokens with their spans
         let expected = vec![
-            TokenWithSpan { token: Token::Plus, span: Span { start: 0, end: 1 } },
-            TokenWithSpan { token: Token::Minus, span: Span { start: 2, end: 3 } },
-            TokenWithSpan { token: Token::Multiply, span: Span { start: 4, end: 5 } },
-            TokenWithSpan { token: Token::Divide, span: Span { start: 6, end: 7 } },
-            TokenWithSpan { token: Token::Assign, span: Span { start: 8, end: 9 } },
-            TokenWithSpan { token: Token::Equals, span: Span { start: 10, end: 12 } },
-            TokenWithSpan { token: Token::NotEquals, span: Span { start: 13, end: 15 } },
-            TokenWithSpan { token: Token::LessThan, span: Span { start: 16, end: 17 } },
-            TokenWithSpan { token: Token::LessEquals, span: Span { start: 18, end: 20 } },
-            TokenWithSpan { token: Token::GreaterThan, span: Span { start: 21, end: 22 } },
-            TokenWithSpan { token: Token::GreaterEquals, span: Span { start: 23, end: 25 } },
-            TokenWithSpan { token: Token::And, span: Span { start: 26, end: 28 } },
-            TokenWithSpan { token: Token::Or, span: Span { start: 29, end: 31 } },
-            TokenWithSpan { token: Token::Not, span: Span { start: 32, end: 33 } },
+            TokenWithSpan::new(Token::Plus, Span { start: 0, end: 1 }),
+            TokenWithSpan::new(Token::Minus, Span { start: 2, end: 3 }),
+            TokenWithSpan::new(Token::Multiply, Span { start: 4, end: 5 }),
+            TokenWithSpan::new(Token::Divide, Span { start: 6, end: 7 }),
+            TokenWithSpan::new(Token::Assign, Span { start: 8, end: 9 }),
+            TokenWithSpan::new(Token::Equals, Span { start: 10, end: 12 }),
+            TokenWithSpan::new(Token::NotEquals, Span { start: 13, end: 15 }),
+            TokenWithSpan::new(Token::LessThan, Span { start: 16, end: 17 }),
+            TokenWithSpan::new(Token::LessEquals, Span { start: 18, end: 20 }),
+            TokenWithSpan::new(Token::GreaterThan, Span { start: 21, end: 22 }),
+            TokenWithSpan::new(Token::GreaterEquals, Span { start: 23, end: 25 }),
+            TokenWithSpan::new(Token::And, Span { start: 26, end: 28 }),
+            TokenWithSpan::new(Token::Or, Span { start: 29, end: 31 }),
+            TokenWithSpan::new(Token::Not, Span { start: 32, end: 33 }),
+            TokenWithSpan::new(Token::Eof, Span { start: 33, end: 33 }),
         ];
         
         assert_eq!(tokens, expected);
@@ -671,14 +1315,15 @@ mod tests {
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize().unwrap();
         
-        assert_eq!(tokens.len(), 7);
+        assert_eq!(tokens.len(), 8);
         assert_eq!(tokens[0].token, Token::Blockchain);
         assert_eq!(tokens[1].token, Token::Contract);
-        assert_eq!(tokens[2].token, Token::Ledger);
+        assert_eq!(tokens[2].token, Token::Identifier("ledger"));
         assert_eq!(tokens[3].token, Token::Validate);
-        assert_eq!(tokens[4].token, Token::Mine);
-        assert_eq!(tokens[5].token, Token::Block);
-        assert_eq!(tokens[6].token, Token::Hash);
+        assert_eq!(tokens[4].token, Token::Identifier("mine"));
+        assert_eq!(tokens[5].token, Token::Identifier("block"));
+        assert_eq!(tokens[6].token, Token::Identifier("hash"));
+        assert_eq!(tokens[7].token, Token::Eof);
 
         // Verify spans are correct
         assert_eq!(tokens[0].span.start, 0);
@@ -687,6 +1332,17 @@ mod tests {
         assert_eq!(tokens[1].span.end, 19); // "contract"
     }
 
+    #[test]
+    fn test_assertion_keywords() {
+        let input = "validate require assert";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize().unwrap();
+        
+        assert_eq!(tokens[0].token, Token::Validate);
+        assert_eq!(tokens[1].token, Token::Require);
+        assert_eq!(tokens[2].token, Token::Assert);
+    }
+
     #[test]
     fn test_advanced_operators() {
         let input = "+= -= *= /= %= ++ -- ... ?. ??";
@@ -705,6 +1361,18 @@ mod tests {
         assert_eq!(tokens[9].token, Token::NullCoalesce);
     }
 
+    #[test]
+    fn test_bitwise_operators() {
+        let tokens = Lexer::new("a & b && c | d || ^ ~ << >> <= >=").tokenize().unwrap();
+        let tokens: Vec<Token> = tokens.into_iter().map(|t| t.token).collect();
+
+        assert_eq!(tokens, vec![
+            ident("a"), Token::Ampersand, ident("b"), Token::And, ident("c"), Token::Pipe, ident("d"),
+            Token::Or, Token::Caret, Token::Tilde, Token::ShiftLeft, Token::ShiftRight, Token::LessEquals, Token::GreaterEquals,
+            Token::Eof,
+        ]);
+    }
+
     #[test]
     fn test_additional_literals() {
         let input = "'a' 0xFF 0b1010";
@@ -712,8 +1380,8 @@ mod tests {
         let tokens = lexer.tokenize().unwrap();
         
         assert_eq!(tokens[0].token, Token::CharLiteral);
-        assert_eq!(tokens[1].token, Token::HexLiteral);
-        assert_eq!(tokens[2].token, Token::BinaryLiteral);
+        assert_eq!(tokens[1].token, Token::HexLiteral("0xFF"));
+        assert_eq!(tokens[2].token, Token::BinaryLiteral("0b1010"));
     }
 
     #[test]
@@ -722,7 +1390,7 @@ mod tests {
         let mut lexer = Lexer::new(input);
         let (tokens, errors) = lexer.tokenize_with_errors();
         
-        assert_eq!(tokens.len(), 2); // "let" and "function"
+        assert_eq!(tokens.len(), 3); // "let", "function" and the end
         assert_eq!(errors.len(), 1); // One error for "@"
     }
 
@@ -759,6 +1427,19 @@ mod tests {
         assert_eq!(tokens[2].token, Token::Scheduled);
     }
 
+    #[test]
+    fn test_storage_slot_decorator() {
+        let input = "@slot(3) let owner: address;";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize().unwrap();
+        
+        assert_eq!(tokens[0].token, Token::Slot);
+        assert_eq!(tokens[1].token, Token::LeftParen);
+        assert_eq!(tokens[2].token, Token::IntLiteral("3"));
+        assert_eq!(tokens[3].token, Token::RightParen);
+        assert_eq!(tokens[4].token, Token::Let);
+    }
+
     #[test]
     fn test_blockchain_specific() {
         let input = "msg.sender new sign mutex semaphore";
@@ -767,9 +1448,9 @@ mod tests {
         
         assert_eq!(tokens[0].token, Token::MsgSender);
         assert_eq!(tokens[1].token, Token::New);
-        assert_eq!(tokens[2].token, Token::Sign);
-        assert_eq!(tokens[3].token, Token::Mutex);
-        assert_eq!(tokens[4].token, Token::Semaphore);
+        assert_eq!(tokens[2].token, Token::Identifier("sign"));
+        assert_eq!(tokens[3].token, Token::Identifier("mutex"));
+        assert_eq!(tokens[4].token, Token::Identifier("semaphore"));
     }
 
     #[test]
@@ -807,9 +1488,9 @@ mod tests {
         let tokens = lexer.tokenize().unwrap();
         
         assert_eq!(tokens[0].token, Token::Foreach);
-        assert_eq!(tokens[1].token, Token::Identifier); // item
+        assert_eq!(tokens[1].token, ident("item"));
         assert_eq!(tokens[2].token, Token::In);
-        assert_eq!(tokens[3].token, Token::Identifier); // items
+        assert_eq!(tokens[3].token, ident("items"));
         assert_eq!(tokens[4].token, Token::Do);
         assert_eq!(tokens[5].token, Token::While);
         assert_eq!(tokens[6].token, Token::Match);
@@ -829,6 +1510,16 @@ mod tests {
         assert_eq!(tokens[3].token, Token::Double);
     }
 
+    #[test]
+    fn test_uint256_type() {
+        let input = "let supply: uint256 = 0xffffffffffffffffffffffffffffffff;";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize().unwrap();
+        
+        assert_eq!(tokens[3].token, Token::UInt256);
+        assert_eq!(tokens[5].token, Token::HexLiteral("0xffffffffffffffffffffffffffffffff"));
+    }
+
     #[test]
     fn test_variable_declarations() {
         let input = "readonly MAX_SIZE: int = 100;";
@@ -836,11 +1527,11 @@ mod tests {
         let tokens = lexer.tokenize().unwrap();
         
         assert_eq!(tokens[0].token, Token::Readonly);
-        assert_eq!(tokens[1].token, Token::Identifier); // MAX_SIZE
+        assert_eq!(tokens[1].token, ident("MAX_SIZE"));
         assert_eq!(tokens[2].token, Token::Colon);
         assert_eq!(tokens[3].token, Token::Int);
         assert_eq!(tokens[4].token, Token::Assign);
-        assert_eq!(tokens[5].token, Token::IntLiteral); // 100
+        assert_eq!(tokens[5].token, Token::IntLiteral("100"));
         assert_eq!(tokens[6].token, Token::Semicolon);
     }
 
@@ -850,18 +1541,49 @@ mod tests {
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize().unwrap();
         
-        assert_eq!(tokens[0].token, Token::DocComment);
-        assert_eq!(tokens[1].token, Token::MultilineDocComment);
+        assert_eq!(tokens[0].token, Token::DocComment("Single line doc"));
+        assert_eq!(tokens[1].token, Token::MultilineDocComment("Multiline\ndoc".into()));
+
+        // The markers, and the stars down the side, are stripped
+        let tokens = Lexer::new("///\n///   indented\n/**\n * Transfers `amount`.\n *\n * Fails when short.\n */").tokenize().unwrap();
+        assert_eq!(tokens.into_iter().map(|t| t.token).collect::<Vec<_>>(), [
+            Token::DocComment(""),
+            Token::DocComment("  indented"),
+            Token::MultilineDocComment("Transfers `amount`.\n\nFails when short.".into()),
+            Token::Eof,
+        ]);
     }
 
     #[test]
     fn test_template_strings() {
-        let input = "`User ${name} is ${age} years old`";
+        let input = "`User ${user.name} is ${ages[{ `a\\`ge` }]} years old`";
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize().unwrap();
-        
-        assert_eq!(tokens[0].token, Token::TemplateString);
-        assert!(tokens.iter().any(|t| t.token == Token::TemplateInterpolation));
+        let text = |text: &'static str| Token::TemplateText(text.into());
+
+        assert_eq!(tokens.into_iter().map(|t| t.token).collect::<Vec<_>>(), vec![
+            Token::TemplateStart,
+            text("User "),
+            Token::InterpolationStart,
+            ident("user"),
+            Token::Dot,
+            ident("name"),
+            Token::InterpolationEnd,
+            text(" is "),
+            Token::InterpolationStart,
+            ident("ages"),
+            Token::LeftBracket,
+            Token::LeftBrace,
+            Token::TemplateStart,
+            text("a`ge"),
+            Token::TemplateEnd,
+            Token::RightBrace,
+            Token::RightBracket,
+            Token::InterpolationEnd,
+            text(" years old"),
+            Token::TemplateEnd,
+            Token::Eof,
+        ]);
     }
 
     #[test]
@@ -870,9 +1592,9 @@ mod tests {
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize().unwrap();
         
-        assert_eq!(tokens[0].token, Token::Identifier); // barrier
+        assert_eq!(tokens[0].token, ident("barrier"));
         assert_eq!(tokens[1].token, Token::Dot);
-        assert_eq!(tokens[2].token, Token::Identifier); // await
+        assert_eq!(tokens[2].token, ident("await"));
     }
 
     #[test]
@@ -881,7 +1603,7 @@ mod tests {
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize().unwrap();
         
-        assert_eq!(tokens[0].token, Token::StringLiteral);
+        assert_eq!(tokens[0].token, Token::StringLiteral("Hello\nWorld\t\"Quote\"\\Backslash".into()));
     }
 
     #[test]
@@ -902,7 +1624,7 @@ mod tests {
         let mut lexer = Lexer::new(input);
         let tokens = lexer.tokenize().unwrap();
         
-        assert!(tokens.iter().all(|t| matches!(t.token, Token::ScientificLiteral)));
+        assert!(tokens[..tokens.len() - 1].iter().all(|t| matches!(t.token, Token::ScientificLiteral(_))));
     }
 
     #[test]
@@ -914,7 +1636,7 @@ mod tests {
         assert_eq!(tokens[0].token, Token::Payable);
         assert_eq!(tokens[1].token, Token::View);
         assert_eq!(tokens[2].token, Token::Pure);
-        assert_eq!(tokens[3].token, Token::Emit);
+        assert_eq!(tokens[3].token, Token::Identifier("emit"));
         assert_eq!(tokens[4].token, Token::Constructor);
         assert_eq!(tokens[5].token, Token::This);
         assert_eq!(tokens[6].token, Token::Super);
@@ -927,13 +1649,55 @@ mod tests {
         let tokens = lexer.tokenize().unwrap();
         
         assert_eq!(tokens[0].token, Token::Spawn);
-        assert_eq!(tokens[1].token, Token::Channel);
-        assert_eq!(tokens[2].token, Token::Select);
-        assert_eq!(tokens[3].token, Token::Task);
-        assert_eq!(tokens[4].token, Token::Sync);
+        assert_eq!(tokens[1].token, Token::Identifier("channel"));
+        assert_eq!(tokens[2].token, Token::Identifier("select"));
+        assert_eq!(tokens[3].token, Token::Identifier("task"));
+        assert_eq!(tokens[4].token, Token::Identifier("sync"));
         assert_eq!(tokens[5].token, Token::Atomic);
     }
 
+    #[test]
+    fn test_library_names_are_identifiers() {
+        let tokens = Lexer::new("let task = Actor.block; class Block {}").tokenize().unwrap();
+        let identifiers: Vec<&str> = tokens.iter().filter_map(|t| match &t.token {
+            Token::Identifier(name) => Some(*name),
+            _ => None,
+        }).collect();
+        assert_eq!(identifiers, ["task", "Actor", "block", "Block"]);
+    }
+
+    #[test]
+    fn test_lexer_options() {
+        let input = "let view = msg.sender; @WasmExport(\"f\") atomic become `${contract}`";
+        let kinds = |options: LexerOptions| -> Vec<Token> {
+            Lexer::with_options(input, options).tokenize().unwrap().into_iter().map(|t| t.token).collect()
+        };
+
+        let all = kinds(LexerOptions::default());
+        assert_eq!(all[1], Token::View);
+        assert_eq!(all[3], Token::MsgSender);
+        assert_eq!(all[5], Token::WasmExport);
+        assert_eq!(all[13], Token::Contract);
+
+        let none = LexerOptions { blockchain: false, actors: false, stm: false, wasm: false, newlines: false };
+        assert_eq!(kinds(none), vec![
+            Token::Let, ident("view"), Token::Assign, ident("msg"), Token::Dot, ident("sender"), Token::Semicolon,
+            Token::At, ident("WasmExport"), Token::LeftParen, Token::StringLiteral("f".into()), Token::RightParen,
+            ident("atomic"), ident("become"),
+            Token::TemplateStart, Token::InterpolationStart, ident("contract"), Token::InterpolationEnd, Token::TemplateEnd,
+            Token::Eof,
+        ]);
+
+        // Only the disabled sets
+        let tokens = kinds(LexerOptions { wasm: false, ..LexerOptions::default() });
+        assert_eq!(tokens[3], Token::MsgSender);
+        assert_eq!(&tokens[5..7], [Token::At, ident("WasmExport")]);
+
+        let (tokens, errors) = Lexer::with_options("msg.sender", none).tokenize_with_recovery();
+        assert!(errors.is_empty());
+        assert_eq!(tokens.iter().map(|t| t.span.end).collect::<Vec<_>>(), [3, 4, 10, 10]);
+    }
+
     #[test]
     fn test_all_operators() {
         let input = "+ - * / % == != < <= > >= && || ! ?? ?. += -= *= /= %= ++ --";
@@ -980,6 +1744,89 @@ mod tests {
         assert!(tokens.iter().any(|t| t.token == Token::Class));
     }
 
+    #[test]
+    fn test_derive_attribute() {
+        let mut lexer = Lexer::new("@derive(Equals, ToString) class Point {}");
+        let tokens: Vec<Token> = lexer.tokenize().unwrap().into_iter().map(|t| t.token).collect();
+
+        assert_eq!(&tokens[..3], &[Token::Derive, Token::LeftParen, ident("Equals")]);
+        assert_eq!(tokens[6], Token::Class);
+    }
+
+    #[test]
+    fn test_cfg_attribute() {
+        let mut lexer = Lexer::new("@cfg(target = \"wasm32\") function f {}");
+        let tokens: Vec<Token> = lexer.tokenize().unwrap().into_iter().map(|t| t.token).collect();
+
+        assert_eq!(&tokens[..6], &[
+            Token::Cfg, Token::LeftParen, ident("target"), Token::Assign, Token::StringLiteral("wasm32".into()), Token::RightParen,
+        ]);
+        assert_eq!(tokens[6], Token::Function);
+    }
+
+    #[test]
+    fn test_union_type() {
+        let mut lexer = Lexer::new("type Msg = Update | Logout");
+        let tokens: Vec<Token> = lexer.tokenize().unwrap().into_iter().map(|t| t.token).collect();
+
+        assert_eq!(tokens, vec![
+            Token::Type, ident("Msg"), Token::Assign, ident("Update"), Token::Pipe, ident("Logout"), Token::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_bytes_type() {
+        let mut lexer = Lexer::new("let data: bytes = bytes.fromHex(text)");
+        let tokens: Vec<Token> = lexer.tokenize().unwrap().into_iter().map(|t| t.token).collect();
+
+        assert_eq!(&tokens[..5], &[Token::Let, ident("data"), Token::Colon, Token::Bytes, Token::Assign]);
+        assert_eq!(tokens[5], Token::Bytes);
+    }
+
+    #[test]
+    fn test_regex_literal() {
+        let mut lexer = Lexer::new(r#"re"\d+\"" re "x""#);
+        let tokens: Vec<Token> = lexer.tokenize().unwrap().into_iter().map(|t| t.token).collect();
+
+        assert_eq!(tokens, vec![Token::RegexLiteral(r#"\d+\""#), ident("re"), Token::StringLiteral("x".into()), Token::Eof]);
+    }
+
+    #[test]
+    fn test_abstract_class() {
+        let mut lexer = Lexer::new("abstract class Shape { abstract function area(): int; }");
+        let tokens: Vec<Token> = lexer.tokenize().unwrap().into_iter().map(|t| t.token).collect();
+
+        assert_eq!(tokens[..3], [Token::Abstract, Token::Class, ident("Shape")]);
+        assert_eq!(tokens[4..6], [Token::Abstract, Token::Function]);
+    }
+
+    #[test]
+    fn test_type_tests() {
+        let mut lexer = Lexer::new("shape is Square && (shape as? Square) != null && isEmpty");
+        let tokens: Vec<Token> = lexer.tokenize().unwrap().into_iter().map(|t| t.token).collect();
+
+        assert_eq!(tokens[..3], [ident("shape"), Token::Is, ident("Square")]);
+        assert_eq!(tokens[5..8], [ident("shape"), Token::SafeAs, ident("Square")]);
+        assert_eq!(tokens[tokens.len() - 2..], [ident("isEmpty"), Token::Eof]);
+    }
+
+    #[test]
+    fn test_llvm_block() {
+        let mut lexer = Lexer::new("function popcount { llvm { \"ret i64 0\" } }");
+        let tokens: Vec<Token> = lexer.tokenize().unwrap().into_iter().map(|t| t.token).collect();
+
+        assert_eq!(&tokens[3..7], &[Token::Llvm, Token::LeftBrace, Token::StringLiteral("ret i64 0".into()), Token::RightBrace]);
+    }
+
+    #[test]
+    fn test_macro_tokens() {
+        let mut lexer = Lexer::new("macro emitter(name) { } emitter!(Transfer);");
+        let tokens: Vec<Token> = lexer.tokenize().unwrap().into_iter().map(|t| t.token).collect();
+
+        assert_eq!(tokens[0], Token::Macro);
+        assert_eq!(&tokens[7..10], &[ident("emitter"), Token::Not, Token::LeftParen]);
+    }
+
     #[test]
     fn test_actor_system() {
         let input = r#"
@@ -1015,9 +1862,9 @@ mod tests {
         
         // Verify actor system tokens
         assert!(tokens.iter().any(|t| t.token == Token::Class));
-        assert!(tokens.iter().any(|t| t.token == Token::Actor));
-        assert!(tokens.iter().any(|t| t.token == Token::MessageQueue));
-        assert!(tokens.iter().any(|t| t.token == Token::ActorBehavior));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("Actor")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("MessageQueue")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("ActorBehavior")));
         assert!(tokens.iter().any(|t| t.token == Token::Async));
         assert!(tokens.iter().any(|t| t.token == Token::Await));
     }
@@ -1042,9 +1889,9 @@ mod tests {
         
         // Verify supervision tokens
         assert!(tokens.iter().any(|t| t.token == Token::Match));
-        assert!(tokens.iter().any(|t| t.token == Token::DecisionRestart));
-        assert!(tokens.iter().any(|t| t.token == Token::DecisionStop));
-        assert!(tokens.iter().any(|t| t.token == Token::DecisionEscalate));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("RESTART")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("STOP")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("ESCALATE")));
     }
 
     #[test]
@@ -1129,9 +1976,9 @@ mod tests {
         
         // Verify actor system tokens
         assert!(tokens.iter().any(|t| t.token == Token::Class));
-        assert!(tokens.iter().any(|t| t.token == Token::Actor));
-        assert!(tokens.iter().any(|t| t.token == Token::MessageQueue));
-        assert!(tokens.iter().any(|t| t.token == Token::ActorBehavior));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("Actor")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("MessageQueue")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("ActorBehavior")));
         assert!(tokens.iter().any(|t| t.token == Token::Async));
         assert!(tokens.iter().any(|t| t.token == Token::Await));
         assert!(tokens.iter().any(|t| t.token == Token::Become));
@@ -1151,6 +1998,88 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_unterminated_literals() {
+        let error = |input: &str| Lexer::new(input).tokenize().unwrap_err().to_string();
+        assert_eq!(error("x = \"abc\\\" def"), "Unterminated string literal starting at position 4: '\"abc\\\" def'");
+        assert_eq!(error("x = `a ${b} c"), "Unterminated string literal starting at position 4: '`a ${b} c'");
+        assert_eq!(error("x /** doc"), "Unterminated comment starting at position 2: '/** doc'");
+
+        // Recovery reports them the same way
+        let (tokens, errors) = Lexer::new("x \"abc").tokenize_with_recovery();
+        assert_eq!(tokens.len(), 2);
+        assert!(matches!(errors.as_slice(), [LexerError::UnterminatedString { position: 2, partial }] if partial == "\"abc"));
+    }
+
+    #[test]
+    fn test_relexing() {
+        let source = "let total = add(1, 2); /* note */ let msg = `sum ${total + 1} ok`; x";
+        let old = Lexer::new(source).tokenize().unwrap();
+        let mut relexed = 0;
+        // Every edit at every position lexes the same as lexing it all again
+        for start in 0..=source.len() {
+            for (removed, text) in [(0, "z"), (0, " "), (0, "\""), (0, "/*"), (0, "`"), (0, "}"), (0, "${"), (1, ""), (2, "9"), (3, "")] {
+                let edit = TextEdit { start, end: (start + removed).min(source.len()), text: text.to_string() };
+                let edited = edit.apply(source);
+                let full = Lexer::new(&edited).tokenize();
+                match (Lexer::relex(&edited, &old, &edit, LexerOptions::default()), full) {
+                    (Ok(tokens), Ok(full)) => {
+                        assert_eq!(tokens, full, "after {:?}", edit);
+                        relexed += 1;
+                    },
+                    (Err(error), Err(full)) => assert_eq!(error.to_string(), full.to_string(), "after {:?}", edit),
+                    (tokens, full) => panic!("after {:?}, relexed to {:?} but lexed to {:?}", edit, tokens, full),
+                }
+            }
+        }
+        assert!(relexed > 0);
+    }
+
+    #[test]
+    fn test_borrowed_text() {
+        let source = "/// Greets.\nlet a = \"hi\"; let b = \"tab\\there\"; let c = `x ${a} \\$y`;".to_string();
+        let tokens = Lexer::new(&source).tokenize().unwrap();
+        let borrowed = |token: &Token| match token {
+            Token::StringLiteral(text) | Token::TemplateText(text) | Token::MultilineDocComment(text) => matches!(text, Cow::Borrowed(_)),
+            _ => true,
+        };
+        // Only text with an escape in it needs a string of its own
+        let owned: Vec<&Token> = tokens.iter().map(|token| &token.token).filter(|token| !borrowed(token)).collect();
+        assert_eq!(owned, [&Token::StringLiteral("tab\there".into()), &Token::TemplateText(" $y".into())]);
+        assert!(matches!(&tokens[2].token, Token::Identifier(name) if name.as_ptr() == source[16..].as_ptr()));
+
+        // Relexed tokens borrow the edited source, not the one they came from
+        let edit = TextEdit { start: 0, end: 0, text: "\n".to_string() };
+        let edited = edit.apply(&source);
+        let relexed = Lexer::relex(&edited, &tokens, &edit, LexerOptions::default()).unwrap();
+        drop(tokens);
+        drop(source);
+        assert_eq!(relexed, Lexer::new(&edited).tokenize().unwrap());
+        assert!(relexed.iter().all(|token| borrowed(&token.token)
+            || matches!(&token.token, Token::StringLiteral(Cow::Owned(_)) | Token::TemplateText(Cow::Owned(_)))));
+    }
+
+    #[test]
+    fn test_localized_errors() {
+        let error = Lexer::new("x = 1__0").tokenize().unwrap_err();
+        assert_eq!(error.code(), "L0004");
+        assert_eq!(error.to_string(), "Invalid number format '1__0' at position 4");
+        assert_eq!(error.message(Locale::Es), "Formato de número no válido '1__0' en la posición 4");
+        assert_eq!(Locale::parse("es_ES.UTF-8"), Some(Locale::Es));
+        assert_eq!(Locale::parse("fr"), None);
+
+        // Every error is explained
+        for code in ["L0001", "L0002", "L0003", "L0004", "L0005", "L0006", "L0007", "L0008", "L0009", "L0010"] {
+            assert!(gard_ast::explain(code).is_some_and(|explanation| !explanation.is_empty()), "{} isn't explained", code);
+        }
+        assert_eq!(gard_ast::explain("l0004"), gard_ast::explain(error.code()));
+        assert_eq!(gard_ast::explain("E0042"), None);
+
+        // Braces in an argument aren't read as the message's
+        let error = Lexer::new("\"{position}").tokenize().unwrap_err();
+        assert_eq!(error.to_string(), "Unterminated string literal starting at position 0: '\"{position}'");
+    }
+
     #[test]
     fn test_invalid_escape_sequence() {
         let input = r#"let msg = "invalid \z escape";"#;
@@ -1163,13 +2092,35 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_decoding_escapes() {
+        let tokens = Lexer::new(r#""\x41\u{1F600}\u{e9}\0\'""#).tokenize().unwrap();
+        assert_eq!(tokens[0].token, Token::StringLiteral("A\u{1F600}\u{e9}\0'".into()));
+
+        let invalid = |input: &str| match Lexer::new(input).tokenize_with_errors().1.as_slice() {
+            [LexerError::InvalidEscape { position, sequence }] => (*position, sequence.clone()),
+            errors => panic!("expected one invalid escape, found {:?}", errors),
+        };
+        assert_eq!(invalid(r#"x = "ok\q""#), (7, r"\q".to_string()));
+        assert_eq!(invalid(r#""\xZZ""#), (1, r"\xZZ".to_string()));
+        assert_eq!(invalid(r#""\x80""#), (1, r"\x80".to_string()));
+        assert_eq!(invalid(r#""\u41""#), (1, r"\u".to_string()));
+        assert_eq!(invalid(r#""\u{41" x"#), (1, r"\u{".to_string()));
+        assert_eq!(invalid(r#""\u{D800}""#), (1, r"\u{D800}".to_string()));
+        assert_eq!(invalid(r#""\u{1234567}""#), (1, r"\u{1234567}".to_string()));
+
+        // Lexing goes on after the string
+        let (tokens, errors) = Lexer::new(r#""\q" x"#).tokenize_with_errors();
+        assert_eq!((tokens[0].token.clone(), errors.len()), (ident("x"), 1));
+    }
+
     #[test]
     fn test_invalid_character() {
         let input = "let x = @;";  // @ is not a valid token
         let mut lexer = Lexer::new(input);
         let (tokens, errors) = lexer.tokenize_with_errors();
         
-        assert_eq!(tokens.len(), 3); // let, x, =
+        assert_eq!(tokens.len(), 4); // let, x, = and the end
         assert_eq!(errors.len(), 1); // one error for @
     }
 
@@ -1254,10 +2205,7 @@ mod tests {
         let mut lexer = Lexer::new(input);
         let result = lexer.tokenize();
         
-        assert!(matches!(
-            result,
-            Err(LexerError::UnterminatedString { position: _, partial: _ })
-        ));
+        assert!(matches!(result, Err(LexerError::UnterminatedComment { position: 12, partial }) if partial == "/* unterminated comment"));
     }
 
     #[test]
@@ -1267,7 +2215,19 @@ mod tests {
         let result = lexer.tokenize();
         
         assert!(result.is_ok());
-        assert_eq!(result.unwrap().len(), 0); // All comments should be skipped
+        assert_eq!(result.unwrap().len(), 1); // All comments should be skipped, leaving the end
+    }
+
+    #[test]
+    fn test_nested_comment_depth() {
+        let tokens = Lexer::new("/**/ /** doc /* nested */ */ x /* a /* b */ c */").tokenize().unwrap();
+        let kinds: Vec<_> = tokens.iter().map(|t| t.token.clone()).collect();
+        assert_eq!(kinds, [Token::MultilineDocComment("doc /* nested */".into()), Token::Identifier("x"), Token::Eof]);
+        assert_eq!(tokens[0].span, Span { start: 5, end: 28 });
+
+        // The error points at the comment left open, not the nested one
+        let result = Lexer::new("x /* a /* b */ c").tokenize();
+        assert!(matches!(result, Err(LexerError::UnterminatedComment { position: 2, .. })));
     }
 
     #[test]
@@ -1381,11 +2341,11 @@ mod tests {
         
         // Verify supervision tokens
         assert!(tokens.iter().any(|t| t.token == Token::Class));
-        assert!(tokens.iter().any(|t| t.token == Token::Supervisor));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("Supervisor")));
         assert!(tokens.iter().any(|t| t.token == Token::Match));
-        assert!(tokens.iter().any(|t| t.token == Token::DecisionRestart));
-        assert!(tokens.iter().any(|t| t.token == Token::DecisionStop));
-        assert!(tokens.iter().any(|t| t.token == Token::DecisionEscalate));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("RESTART")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("STOP")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("ESCALATE")));
         assert!(tokens.iter().any(|t| t.token == Token::Arrow));
     }
 
@@ -1429,14 +2389,176 @@ mod tests {
         
         // Verify STM tokens
         assert!(tokens.iter().any(|t| t.token == Token::Class));
-        assert!(tokens.iter().any(|t| t.token == Token::TVar));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("TVar")));
         assert!(tokens.iter().any(|t| t.token == Token::Atomic));
         assert!(tokens.iter().any(|t| t.token == Token::Transaction));
-        assert!(tokens.iter().any(|t| t.token == Token::Commit));
-        assert!(tokens.iter().any(|t| t.token == Token::Abort));
-        assert!(tokens.iter().any(|t| t.token == Token::Backoff));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("commit")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("abort")));
+        assert!(tokens.iter().any(|t| t.token == Token::Identifier("backoff")));
         assert!(tokens.iter().any(|t| t.token == Token::Try));
         assert!(tokens.iter().any(|t| t.token == Token::Catch));
         assert!(tokens.iter().any(|t| t.token == Token::Throw));
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_lexing_on_demand() {
+        let mut lexer = Lexer::new("let x = 1 # rest");
+        assert_eq!(lexer.next().unwrap().unwrap(), TokenWithSpan::new(Token::Let, Span { start: 0, end: 3 }));
+        assert_eq!(lexer.next().unwrap().unwrap().token, ident("x"));
+        assert!(matches!(lexer.nth(2), Some(Err(LexerError::InvalidToken { position: 10, .. }))));
+        assert_eq!(lexer.next().unwrap().unwrap().token, ident("rest"));
+        assert_eq!(lexer.next().unwrap().unwrap(), TokenWithSpan::new(Token::Eof, Span { start: 16, end: 16 }));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn test_numeric_separators_and_suffixes() {
+        let tokens = Lexer::new("1_000_000 42i 42u 42f 3.5f 1_000.000_1 0xff_ff 0b1010_1010 6.02e2_3").tokenize().unwrap();
+        assert_eq!(tokens.iter().map(|t| &t.token).collect::<Vec<_>>(), vec![
+            &Token::IntLiteral("1_000_000"),
+            &Token::IntLiteral("42i"),
+            &Token::UIntLiteral("42u"),
+            &Token::FloatLiteral("42f"),
+            &Token::FloatLiteral("3.5f"),
+            &Token::FloatLiteral("1_000.000_1"),
+            &Token::HexLiteral("0xff_ff"),
+            &Token::BinaryLiteral("0b1010_1010"),
+            &Token::ScientificLiteral("6.02e2_3"),
+            &Token::Eof,
+        ]);
+        assert_eq!(tokens[1].span, Span { start: 10, end: 13 });
+
+        for misplaced in ["1__000", "1_", "1_.5", "2_u", "0x_ff", "1e5_"] {
+            let error = Lexer::new(misplaced).tokenize().unwrap_err();
+            assert!(matches!(&error, LexerError::InvalidNumber { position: 0, value } if value == misplaced), "{}: {}", misplaced, error);
+        }
+    }
+
+    #[test]
+    fn test_expected_tokens() {
+        let expected = |source: &str| match Lexer::new(source).tokenize() {
+            Err(LexerError::InvalidToken { found, expected, .. }) => (found, expected),
+            other => panic!("{}: expected an invalid token, found {:?}", source, other),
+        };
+        assert_eq!(expected("let x = 0xg;"), ("0xg".to_string(), vec!["hex digit after 0x".to_string()]));
+        assert_eq!(expected("0b2").1, ["binary digit after 0b"]);
+        assert_eq!(expected("1.5e").1, ["digit after the exponent"]);
+        assert_eq!(expected("10px").1, ["suffix i, u or f", "whitespace or an operator after a number"]);
+        assert_eq!(expected("0xffz").1, ["whitespace or an operator after a number"]);
+        assert_eq!(expected("let c = 'a").1, ["closing quote after a character"]);
+        assert_eq!(expected("# note").1, ["`//` to start a comment", "`@` to start an attribute"]);
+        assert_eq!(expected("é").1, ["ASCII letter, digit or `_` in an identifier"]);
+
+        // Lexing goes on after the word
+        let mut lexer = Lexer::new("0x + 1");
+        assert!(lexer.next().unwrap().is_err());
+        assert_eq!(lexer.map(|token| token.unwrap().token).collect::<Vec<_>>(), [Token::Plus, Token::IntLiteral("1"), Token::Eof]);
+
+        let error = Lexer::new("0x").tokenize().unwrap_err();
+        assert_eq!(error.to_string(), "Invalid token '0x' at position 0, expected one of: hex digit after 0x");
+        assert_eq!(error.expected(), ["hex digit after 0x"]);
+    }
+
+    #[test]
+    fn test_recovery() {
+        let (tokens, errors) = Lexer::new("let é = #Decision.Maybe; #é 0xg x").tokenize_with_recovery();
+        assert_eq!(errors, [
+            LexerError::InvalidCharacter { position: 4, character: 'é' },
+            LexerError::InvalidDecisionType { position: 9, decision: "Maybe;".to_string() },
+            LexerError::InvalidCharacter { position: 26, character: '#' },
+            LexerError::InvalidCharacter { position: 27, character: 'é' },
+            LexerError::InvalidToken { position: 30, found: "0xg".to_string(), expected: vec!["hex digit after 0x".to_string()] },
+        ]);
+        // Lexing goes on right after each error
+        assert_eq!(tokens.iter().map(|t| t.token.clone()).collect::<Vec<_>>(), [
+            Token::Let, Token::Assign, ident("Decision"), Token::Dot, ident("Maybe"), Token::Semicolon, ident("x"), Token::Eof,
+        ]);
+
+        // Prefixes cut short by the end of the input
+        for source in ["#", "#Decision", "#Decision.", "#Actor", "#Transaction", "é", "#€"] {
+            let (tokens, errors) = Lexer::new(source).tokenize_with_recovery();
+            assert_eq!(tokens.last().map(|t| &t.token), Some(&Token::Eof), "{}", source);
+            assert!(!errors.is_empty(), "{}", source);
+        }
+    }
+
+    /// Lexes `source` every way, checking that tokens and errors fall on
+    /// character boundaries, in order, within the source.
+    fn check_lexes(source: &str) {
+        let (tokens, errors) = Lexer::new(source).tokenize_with_recovery();
+        assert_eq!(tokens.last().map(|t| &t.token), Some(&Token::Eof), "{:?}", source);
+        let mut end = 0;
+        for token in &tokens {
+            assert!(end <= token.span.start && token.span.start <= token.span.end && token.span.end <= source.len(), "{:?}: {:?}", source, token);
+            assert!(source.is_char_boundary(token.span.start) && source.is_char_boundary(token.span.end), "{:?}: {:?}", source, token);
+            end = token.span.end;
+        }
+        for error in &errors {
+            assert!(source.is_char_boundary(error.position()), "{:?}: {:?}", source, error);
+        }
+        let _ = Lexer::with_options(source, LexerOptions { newlines: true, ..LexerOptions::default() }).tokenize();
+    }
+
+    #[test]
+    fn test_recovery_fuzz() {
+        // xorshift, seeded so a failure reproduces
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let fragments = [
+            "let", " ", "\n", "x", "1", "0x", "1e", "_", ".", "@", "#", "$", "{", "}", "`", "${", "\"", "'", "\\",
+            "/*", "*/", "//", "///", "é", "€", "😀", "\u{0}", "Decision.", "Actor", "Transaction", "msg.sender", "\t",
+        ];
+        for _ in 0..2000 {
+            let length = next() % 24;
+            let bytes: Vec<u8> = (0..length).map(|_| next() as u8).collect();
+            check_lexes(&String::from_utf8_lossy(&bytes));
+
+            let source: String = (0..next() % 16).map(|_| fragments[(next() % fragments.len() as u64) as usize]).collect();
+            check_lexes(&source);
+        }
+    }
+
+    #[test]
+    fn test_newlines() {
+        let source = "let x = 1\nreturn x /* a\nb */ + 2 // c\n";
+        let newlines = |options: LexerOptions| -> Vec<bool> {
+            Lexer::with_options(source, options).tokenize().unwrap().iter().map(|t| t.newline_before).collect()
+        };
+        assert_eq!(newlines(LexerOptions { newlines: true, ..LexerOptions::default() }),
+            [false, false, false, false, true, false, true, false, true]);
+        assert!(newlines(LexerOptions::default()).iter().all(|newline| !newline));
+
+        let (tokens, _) = Lexer::with_options(source, LexerOptions { newlines: true, ..LexerOptions::default() }).tokenize_with_recovery();
+        assert_eq!(tokens.last().unwrap(), &TokenWithSpan { token: Token::Eof, span: Span { start: source.len(), end: source.len() }, newline_before: true });
+    }
+
+    #[test]
+    fn test_tokenize_files() {
+        let directory = std::env::temp_dir().join(format!("gard-lexer-files-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let paths: Vec<PathBuf> = (0..8).map(|i| directory.join(format!("{}.gard", i))).collect();
+        for (i, path) in paths.iter().enumerate() {
+            fs::write(path, format!("let x{} = {} #", i, i)).unwrap();
+        }
+        let files = read_files(&paths).unwrap();
+        let lexed = tokenize_files(&files);
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(lexed.len(), 8);
+        for (i, (id, tokens, errors)) in lexed.iter().enumerate() {
+            assert_eq!(*id, FileId(i as u32));
+            assert_eq!(files[i].path, paths[i]);
+            assert_eq!(tokens[1].token, Token::Identifier(&files[i].source[4..6]));
+            assert_eq!(tokens.last().unwrap().token, Token::Eof);
+            assert_eq!(errors, &[LexerError::InvalidCharacter { position: 11, character: '#' }]);
+        }
+
+        let missing = directory.join("missing.gard");
+        assert_eq!(read_files(&[&missing]).unwrap_err(), format!("Failed to read {}: No such file or directory (os error 2)", missing.display()));
+    }
+}
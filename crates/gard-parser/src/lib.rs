@@ -1,26 +1,128 @@
 use chumsky::prelude::*;
 use chumsky::Parser;
+use chumsky::Stream;
 use gard_ast::{
-    Node, Type, BinaryOp, UnaryOp, Parameter,
-    SupervisionStrategy, MatchCase
+    Node, Type, BinaryOp, UnaryOp, Parameter, MethodSignature,
+    SupervisionStrategy, MatchCase, AssertionKind, Span, FunctionModifier, UnionVariant, Symbol
 };
 use gard_lexer::{Token, TokenWithSpan};
+use std::ops::Range;
 
 pub trait GardParserTrait {
-    fn parse(tokens: Vec<TokenWithSpan>) -> Result<Node, Vec<Simple<TokenWithSpan>>>;
+    fn parse<'src>(tokens: Vec<TokenWithSpan<'src>>) -> Result<Node, Vec<Simple<TokenWithSpan<'src>>>>;
 }
 
 pub struct GardParser;
 
+/// A `.property` or `[index]` following an atom.
+enum Postfix {
+    Property(String),
+    Index(Node),
+}
+
 impl GardParserTrait for GardParser {
-   fn parse(tokens: Vec<TokenWithSpan>) -> Result<Node, Vec<Simple<TokenWithSpan>>> {
+   fn parse<'src>(tokens: Vec<TokenWithSpan<'src>>) -> Result<Node, Vec<Simple<TokenWithSpan<'src>>>> {
         let parser = Self::program();
-        parser.parse(tokens)
+        parser.parse(Self::stream(tokens))
     }
 }
 
 impl GardParser {
-    fn program() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    /// Like `parse`, but input left over after the last declaration is an
+    /// error instead of being ignored.
+    pub fn parse_all<'src>(tokens: Vec<TokenWithSpan<'src>>) -> Result<Node, Vec<Simple<TokenWithSpan<'src>>>> {
+        Self::program().then_ignore(end()).parse(Self::stream(tokens))
+    }
+
+    /// Like `parse_all`, but a declaration that doesn't parse becomes a
+    /// `Node::Error` over the tokens up to where the next one can start,
+    /// and parsing goes on from there, so the program comes back whole
+    /// alongside the errors.
+    pub fn parse_recovering<'src>(tokens: Vec<TokenWithSpan<'src>>) -> (Node, Vec<Simple<TokenWithSpan<'src>>>) {
+        let mut declarations = Vec::new();
+        let mut errors = Vec::new();
+        let mut start = 0;
+        let eof = tokens.iter().position(|token| token.token == Token::Eof).unwrap_or(tokens.len());
+        while start < eof {
+            let rest = &tokens[start..];
+            let parsed = Self::declaration()
+                .map_with_span(|declaration, span: Range<usize>| (declaration, span.end))
+                .parse(Self::stream(rest.to_vec()));
+            match parsed {
+                Ok((declaration, end)) => {
+                    declarations.push(declaration);
+                    start += rest.iter().take_while(|token| token.span.end <= end).count();
+                },
+                Err(mut declaration_errors) => {
+                    let consumed_tokens = Self::broken_declaration_length(rest);
+                    let span = Span { start: rest[0].span.start, end: rest[consumed_tokens - 1].span.end };
+                    declarations.push(Node::Error { span, consumed_tokens });
+                    errors.append(&mut declaration_errors);
+                    start += consumed_tokens;
+                },
+            }
+        }
+        (Node::Program(declarations), errors)
+    }
+
+    /// How many tokens a declaration that doesn't parse takes: through the
+    /// `;` or the closing `}` that ends it, or up to the next token that
+    /// starts a declaration or the end of the file, and at least one.
+    fn broken_declaration_length<'src>(tokens: &[TokenWithSpan<'src>]) -> usize {
+        let mut depth = 0_usize;
+        for (i, token) in tokens.iter().enumerate() {
+            match token.token {
+                Token::LeftBrace | Token::LeftParen | Token::LeftBracket => depth += 1,
+                Token::RightBrace if depth <= 1 => return i + 1,
+                Token::RightBrace | Token::RightParen | Token::RightBracket => depth = depth.saturating_sub(1),
+                Token::Semicolon if depth == 0 => return i + 1,
+                Token::Eof => return i,
+                Token::Class | Token::Abstract | Token::Function | Token::Contract | Token::Interface | Token::Const
+                | Token::TaskLocal | Token::ActorLocal | Token::Type | Token::Macro | Token::Llvm | Token::At
+                | Token::Derive | Token::Cfg | Token::WasmExport | Token::WasmImport if depth == 0 && i > 0 => return i,
+                _ => {},
+            }
+        }
+        tokens.len()
+    }
+
+    /// Parses exactly one expression, such as `a + f(b)`. Like `parse_all`,
+    /// anything after it is an error, so a statement or declaration is
+    /// rejected at its first token that can't continue the expression.
+    pub fn parse_expression<'src>(tokens: Vec<TokenWithSpan<'src>>) -> Result<Node, Vec<Simple<TokenWithSpan<'src>>>> {
+        Self::expression().then_ignore(end()).parse(Self::stream(tokens))
+    }
+
+    /// Parses exactly one statement, such as `let x = 1` or `f(x);`.
+    pub fn parse_statement<'src>(tokens: Vec<TokenWithSpan<'src>>) -> Result<Node, Vec<Simple<TokenWithSpan<'src>>>> {
+        Self::statement().then_ignore(end()).parse(Self::stream(tokens))
+    }
+
+    /// Parses exactly one type annotation, such as `uint256`.
+    pub fn parse_type<'src>(tokens: Vec<TokenWithSpan<'src>>) -> Result<Type, Vec<Simple<TokenWithSpan<'src>>>> {
+        Self::type_annotation().then_ignore(end()).parse(Self::stream(tokens))
+    }
+
+    /// Feeds tokens with their byte offsets, so spans in the AST and in
+    /// errors are source positions rather than token indices. The lexer
+    /// ends its tokens with `Token::Eof`, which becomes where running out of
+    /// input is reported instead of being parsed.
+    fn stream<'src>(mut tokens: Vec<TokenWithSpan<'src>>) -> Stream<'static, TokenWithSpan<'src>, Range<usize>, impl Iterator<Item = (TokenWithSpan<'src>, Range<usize>)>> {
+        let end = match tokens.last() {
+            Some(TokenWithSpan { token: Token::Eof, span, .. }) => {
+                let end = span.start;
+                tokens.pop();
+                end
+            },
+            last => last.map_or(0, |token| token.span.end),
+        };
+        Stream::from_iter(end..end, tokens.into_iter().map(|token| {
+            let span = token.span.start..token.span.end;
+            (token, span)
+        }))
+    }
+
+    fn program<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         recursive(|_| {
             Self::declaration()
                 .repeated()
@@ -28,63 +130,480 @@ impl GardParser {
         }).boxed()
     }
 
-    fn declaration() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
-        choice((
+    fn declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        Self::documented(choice((
             Self::class_declaration(),
             Self::function_declaration(),
             Self::contract_declaration(),
-        )).boxed()
+            Self::wasm_export_declaration(),
+            Self::wasm_import_declaration(),
+            Self::derive_declaration(),
+            Self::cfg_declaration(),
+            Self::attribute_declaration(),
+            Self::llvm_block(),
+            Self::macro_declaration(),
+            Self::macro_call(),
+            Self::const_declaration(),
+            Self::task_local_declaration(),
+            Self::union_declaration(),
+            Self::interface_declaration(),
+        ))).boxed()
+    }
+
+    /// `item` after any doc comments, which are given to it if it's a
+    /// declaration that keeps docs, and dropped otherwise. Comments in a
+    /// row are one doc, a line each.
+    fn documented<'src>(
+        item: impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>>,
+    ) -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! {
+            TokenWithSpan { token: Token::DocComment(text), .. } => text.to_string(),
+            TokenWithSpan { token: Token::MultilineDocComment(text), .. } => text.into_owned(),
+        }
+            .repeated()
+            .then(item)
+            .map(|(docs, mut item)| {
+                if let Some(slot) = item.docs_mut().filter(|_| !docs.is_empty()) {
+                    *slot = Some(docs.join("\n"));
+                }
+                item
+            })
     }
 
-    fn class_declaration() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
-        select! { TokenWithSpan { token: Token::Class, .. } => () }
-            .ignore_then(Self::identifier())
-            .then(
-                select! { TokenWithSpan { token: Token::Extends, .. } => () }
-                    .ignore_then(Self::identifier())
+    /// `abstract class Shape extends Base implements Drawable { .. }`; an
+    /// abstract class's body can declare `abstract function area(): int;`,
+    /// and any class's body can declare classes nested in it.
+    fn class_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        recursive(|class| {
+            let abstract_method = select! { TokenWithSpan { token: Token::Abstract, .. } => () }
+                .ignore_then(Self::method_signature())
+                .map(|method| Node::Function {
+                    name: method.name,
+                    params: method.params,
+                    return_type: method.return_type,
+                    body: Box::new(Node::Block(vec![])),
+                    modifiers: vec![FunctionModifier::Abstract],
+                    docs: None,
+                });
+            // `constructor(a: int) { super(a); .. }`
+            let constructor = select! { TokenWithSpan { token: Token::Constructor, .. } => () }
+                .ignore_then(
+                    select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                        .ignore_then(Self::parameter()
+                            .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () }))
+                        .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+                )
+                .then(Self::block())
+                .map(|(params, body)| Node::Constructor { params, body: Box::new(body) });
+            let body = select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
+                .ignore_then(Self::documented(choice((abstract_method, constructor, class, Self::located_statement()))).repeated())
+                .then_ignore(select! { TokenWithSpan { token: Token::RightBrace, .. } => () });
+            select! { TokenWithSpan { token: Token::Abstract, .. } => () }
+                .or_not()
+                .then_ignore(select! { TokenWithSpan { token: Token::Class, .. } => () })
+                .then(Self::identifier())
+                .then(
+                    select! { TokenWithSpan { token: Token::Extends, .. } => () }
+                        .ignore_then(Self::qualified_name())
+                        .or_not()
+                )
+                .then(
+                    select! { TokenWithSpan { token: Token::Implements, .. } => () }
+                        .ignore_then(Self::qualified_name())
+                        .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () })
+                        .or_not()
+                )
+                .then(body)
+                .map(|((((is_abstract, name), extends), implements), members)| Node::Class {
+                    name,
+                    extends,
+                    implements: implements.unwrap_or_default(),
+                    is_abstract: is_abstract.is_some(),
+                    members: members.into_iter().map(Node::into_unlocated).collect(),
+                    docs: None,
+                })
+        }).boxed()
+    }
+
+    fn identifier<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, String, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Identifier(name), .. } => name.to_string() }
+            .boxed()
+    }
+
+    /// An identifier, interned straight from the source.
+    fn symbol<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Symbol, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Identifier(name), .. } => Symbol::intern(name) }
+            .boxed()
+    }
+
+    /// A number literal, with its value. One that doesn't fit its type is
+    /// reported, and parsing goes on as if it were zero.
+    fn number<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! {
+            TokenWithSpan { token: token @ (
+                Token::IntLiteral(_) | Token::UIntLiteral(_) | Token::FloatLiteral(_)
+                | Token::ScientificLiteral(_) | Token::HexLiteral(_) | Token::BinaryLiteral(_) | Token::OctalLiteral(_)
+            ), .. } => token
+        }
+        .validate(|token, span, emit| {
+            let literal = match token {
+                Token::IntLiteral(text) => Self::int_literal(text),
+                Token::UIntLiteral(text) => Self::digits(text, 'u').parse().map(Node::UIntLiteral)
+                    .map_err(|_| format!("Literal '{}' does not fit in uint", text)),
+                Token::FloatLiteral(text) | Token::ScientificLiteral(text) => Self::digits(text, 'f').parse().map(Node::FloatLiteral)
+                    .map_err(|_| format!("Invalid float literal '{}'", text)),
+                Token::HexLiteral(text) => Ok(Self::hex_literal(text)),
+                Token::BinaryLiteral(text) => Self::radix_literal(text, 2),
+                Token::OctalLiteral(text) => Self::radix_literal(text, 8),
+                _ => unreachable!(),
+            };
+            literal.unwrap_or_else(|message| {
+                emit(Simple::custom(span, message));
+                Node::IntLiteral(0)
+            })
+        })
+        .boxed()
+    }
+
+    /// The text of a number literal without its `_` separators and `suffix`.
+    fn digits(text: &str, suffix: char) -> String {
+        text.strip_suffix(suffix).unwrap_or(text).replace('_', "")
+    }
+
+    /// An int, or a uint256 if it's too big for one and doesn't say it's
+    /// an int. The checker reports a uint256 that doesn't fit.
+    fn int_literal(text: &str) -> Result<Node, String> {
+        let digits = Self::digits(text, 'i');
+        match digits.parse() {
+            Ok(value) => Ok(Node::IntLiteral(value)),
+            Err(_) if !text.ends_with('i') && !text.starts_with('-') => Ok(Node::UInt256Literal(digits)),
+            Err(_) => Err(format!("Literal '{}' does not fit in int", text)),
+        }
+    }
+
+    /// A `0x` literal: an int if it fits in one, and a uint256 if it doesn't
+    /// or has more digits than an int, as an address does.
+    fn hex_literal(text: &str) -> Node {
+        let digits = text.replace('_', "");
+        match i64::from_str_radix(&digits[2..], 16) {
+            Ok(value) if digits.len() <= 18 => Node::IntLiteral(value),
+            _ => Node::UInt256Literal(digits),
+        }
+    }
+
+    /// A `0b` or `0o` literal, an int.
+    fn radix_literal(text: &str, radix: u32) -> Result<Node, String> {
+        i64::from_str_radix(&text[2..].replace('_', ""), radix).map(Node::IntLiteral)
+            .map_err(|_| format!("Literal '{}' does not fit in int", text))
+    }
+
+    /// A library name, such as `Actor` or `TVar`, where it starts the
+    /// construct it names. The lexer leaves these as identifiers, so
+    /// elsewhere they're ordinary names.
+    fn soft_keyword<'src>(keyword: &'static str) -> impl chumsky::Parser<TokenWithSpan<'src>, (), Error = Simple<TokenWithSpan<'src>>> {
+        filter(move |token: &TokenWithSpan<'src>| matches!(&token.token, Token::Identifier(name) if *name == keyword))
+            .ignored()
+            .boxed()
+    }
+
+    /// `Outer.Inner`, a class nested in another, or a bare name.
+    fn qualified_name<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, String, Error = Simple<TokenWithSpan<'src>>> {
+        Self::identifier()
+            .separated_by(select! { TokenWithSpan { token: Token::Dot, .. } => () })
+            .at_least(1)
+            .map(|parts| parts.join("."))
+            .boxed()
+    }
+
+    fn block<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
+            .ignore_then(Self::located_statement().repeated())
+            .then_ignore(select! { TokenWithSpan { token: Token::RightBrace, .. } => () })
+            .map(Node::Block)
+            .boxed()
+    }
+
+    /// Statements in blocks keep their span for the debugger and diagnostics.
+    fn located_statement<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        Self::statement().map_with_span(Self::locate).boxed()
+    }
+
+    fn locate(node: Node, span: Range<usize>) -> Node {
+        Node::Located {
+            span: Span { start: span.start, end: span.end },
+            node: Box::new(node),
+        }
+    }
+
+    fn statement<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        // Statements that contain blocks parse them with this parser rather
+        // than `block()`, which would build `statement()` again without end
+        recursive(|statement| {
+            let block = select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
+                .ignore_then(statement.map_with_span(Self::locate).repeated())
+                .then_ignore(select! { TokenWithSpan { token: Token::RightBrace, .. } => () })
+                .map(Node::Block);
+            Self::documented(choice((
+                Self::storage_slot_declaration(),
+                Self::assertion_statement(),
+                Self::let_statement(),
+                Self::const_declaration(),
+                Self::llvm_block(),
+                Self::macro_call(),
+                Self::scope_statement(block.clone()),
+                Self::match_statement(block),
+                Self::spawn_statement(),
+                Self::expression_statement(),
+            )))
+        }).boxed()
+    }
+
+    /// `scope { .. }`
+    fn scope_statement<'src>(
+        block: impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>>,
+    ) -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Scope, .. } => () }
+            .ignore_then(block)
+            .map(|body| Node::Scope { body: Box::new(body) })
+    }
+
+    /// `spawn f(..);`
+    fn spawn_statement<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Spawn, .. } => () }
+            .ignore_then(Self::expression())
+            .then_ignore(select! { TokenWithSpan { token: Token::Semicolon, .. } => () })
+            .map(|task| Node::Spawn(Box::new(task)))
+    }
+
+    fn storage_slot_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Slot, .. } => () }
+            .ignore_then(
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(Self::expression())
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+            )
+            .then(Self::let_statement())
+            .map(|(slot, declaration)| Node::StorageSlot {
+                slot: Box::new(slot),
+                declaration: Box::new(declaration),
+            })
+            .boxed()
+    }
+
+    fn string_literal<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, String, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::StringLiteral(text), .. } => text.into_owned() }
+            .boxed()
+    }
+
+    /// `@WasmExport function f() {..}` or `@WasmExport("name") function f() {..}`
+    fn wasm_export_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::WasmExport, .. } => () }
+            .ignore_then(
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(Self::string_literal())
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
                     .or_not()
             )
+            .then(Self::function_declaration())
+            .map(|(export_name, declaration)| Node::WasmExport {
+                export_name,
+                declaration: Box::new(declaration),
+            })
+            .boxed()
+    }
+
+    /// `@WasmImport("module", "name") function f(a: int): int;`
+    fn wasm_import_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::WasmImport, .. } => () }
+            .ignore_then(
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(Self::string_literal())
+                    .then_ignore(select! { TokenWithSpan { token: Token::Comma, .. } => () })
+                    .then(Self::string_literal())
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+            )
+            .then_ignore(select! { TokenWithSpan { token: Token::Function, .. } => () })
+            .then_ignore(Self::identifier())
             .then(
-                select! { TokenWithSpan { token: Token::Implements, .. } => () }
-                    .ignore_then(Self::identifier())
-                    .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () })
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(Self::parameter()
+                        .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () }))
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+            )
+            .then(
+                select! { TokenWithSpan { token: Token::Colon, .. } => () }
+                    .ignore_then(Self::type_annotation())
                     .or_not()
             )
-            .then(Self::block())
-            .map(|(((name, extends), implements), body)| Node::Class {
+            .then_ignore(select! { TokenWithSpan { token: Token::Semicolon, .. } => () })
+            .map(|(((module, name), params), return_type)| Node::WasmImport {
+                module,
                 name,
-                extends,
-                implements: implements.unwrap_or_default(),
-                members: if let Node::Block(members) = body {
-                    members
-                } else {
-                    vec![]
-                }
+                params,
+                return_type: return_type.unwrap_or(Type::Void),
+            })
+            .boxed()
+    }
+
+    /// `@derive(Equals, ToString) class Point { .. }`
+    fn derive_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Derive, .. } => () }
+            .ignore_then(
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(Self::identifier()
+                        .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () }))
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+            )
+            .then(Self::class_declaration())
+            .map(|(derives, declaration)| Node::Derive {
+                derives,
+                declaration: Box::new(declaration),
             })
             .boxed()
     }
 
-    fn identifier() -> impl chumsky::Parser<TokenWithSpan, String, Error = Simple<TokenWithSpan>> {
-        select! { TokenWithSpan { token: Token::Identifier, .. } => "identifier".to_string() }
+    /// `@cfg(target = "wasm32", feature = "x")` before any other declaration
+    fn cfg_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        let condition = Self::identifier()
+            .then_ignore(select! { TokenWithSpan { token: Token::Assign, .. } => () })
+            .then(Self::string_literal());
+
+        select! { TokenWithSpan { token: Token::Cfg, .. } => () }
+            .ignore_then(
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(condition
+                        .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () })
+                        .at_least(1))
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+            )
+            .then(choice((
+                Self::class_declaration(),
+                Self::function_declaration(),
+                Self::contract_declaration(),
+                Self::wasm_export_declaration(),
+                Self::wasm_import_declaration(),
+                Self::derive_declaration(),
+            )))
+            .map(|(conditions, declaration)| Node::Cfg {
+                conditions,
+                declaration: Box::new(declaration),
+            })
             .boxed()
     }
 
-    fn block() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
-        select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
-            .ignore_then(Self::statement().repeated())
+    /// `@name(arguments)` before a declaration, for attributes plugins handle.
+    /// On its own, `@name(arguments);` applies to the whole file, and its
+    /// declaration is an empty program.
+    fn attribute_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::At, .. } => () }
+            // `export` is a keyword, but `@export` is still an attribute
+            .ignore_then(Self::identifier().or(select! { TokenWithSpan { token: Token::Export, .. } => "export".to_string() }))
+            .then(
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(Self::expression()
+                        .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () }))
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+                    .or_not()
+            )
+            .then(choice((
+                Self::class_declaration(),
+                Self::function_declaration(),
+                Self::contract_declaration(),
+                Self::wasm_export_declaration(),
+                Self::derive_declaration(),
+                select! { TokenWithSpan { token: Token::Semicolon, .. } => Node::Program(Vec::new()) },
+            )))
+            .map(|((name, arguments), declaration)| Node::Attribute {
+                name,
+                arguments: arguments.unwrap_or_default(),
+                declaration: Box::new(declaration),
+            })
+            .boxed()
+    }
+
+    /// `llvm { "..." }`, raw IR at the top level or as a function body
+    fn llvm_block<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Llvm, .. } => () }
+            .ignore_then(select! { TokenWithSpan { token: Token::LeftBrace, .. } => () })
+            .ignore_then(Self::string_literal())
             .then_ignore(select! { TokenWithSpan { token: Token::RightBrace, .. } => () })
-            .map(Node::Block)
+            .map(Node::InlineIr)
             .boxed()
     }
 
-    fn statement() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
-        choice((
-            Self::let_statement(),
-            Self::expression_statement(),
-        )).boxed()
+    /// `macro name(a, b) { .. }`, whose body holds declarations or statements
+    fn macro_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Macro, .. } => () }
+            .ignore_then(Self::identifier())
+            .then(
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(Self::identifier()
+                        .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () }))
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+            )
+            .then(
+                select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
+                    .ignore_then(choice((
+                        Self::class_declaration(),
+                        Self::function_declaration(),
+                        Self::contract_declaration(),
+                        Self::located_statement(),
+                    )).repeated())
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightBrace, .. } => () })
+            )
+            .map(|((name, params), body)| Node::MacroDefinition {
+                name,
+                params,
+                body: Box::new(Node::Block(body)),
+            })
+            .boxed()
     }
 
-    fn let_statement() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    /// `name!(a, b)` with an optional `;`
+    fn macro_call<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        Self::identifier()
+            .then_ignore(select! { TokenWithSpan { token: Token::Not, .. } => () })
+            .then(
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(Self::expression()
+                        .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () }))
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+            )
+            .then_ignore(select! { TokenWithSpan { token: Token::Semicolon, .. } => () }.or_not())
+            .map_with_span(|(name, arguments), span: Range<usize>| Node::MacroCall {
+                name,
+                arguments,
+                span: Span { start: span.start, end: span.end },
+            })
+            .boxed()
+    }
+
+    fn assertion_statement<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        choice((
+            select! { TokenWithSpan { token: Token::Validate, .. } => AssertionKind::Validate },
+            select! { TokenWithSpan { token: Token::Require, .. } => AssertionKind::Require },
+            select! { TokenWithSpan { token: Token::Assert, .. } => AssertionKind::Assert },
+        ))
+        .then(
+            select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                .ignore_then(Self::expression())
+                .then(
+                    select! { TokenWithSpan { token: Token::Comma, .. } => () }
+                        .ignore_then(Self::expression())
+                        .or_not()
+                )
+                .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+        )
+        .then_ignore(select! { TokenWithSpan { token: Token::Semicolon, .. } => () })
+        .map(|(kind, (condition, message))| Node::Assertion {
+            kind,
+            condition: Box::new(condition),
+            message: message.map(Box::new),
+        })
+        .boxed()
+    }
+
+    fn let_statement<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Let, .. } => () }
             .ignore_then(Self::identifier())
             .then(
@@ -105,14 +624,127 @@ impl GardParser {
             })
     }
 
-    fn expression() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn const_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Const, .. } => () }
+            .ignore_then(Self::identifier())
+            .then(
+                select! { TokenWithSpan { token: Token::Colon, .. } => () }
+                    .ignore_then(Self::type_annotation())
+                    .or_not()
+            )
+            .then_ignore(select! { TokenWithSpan { token: Token::Assign, .. } => () })
+            .then(Self::expression())
+            .map(|((name, type_annotation), value)| Node::Const {
+                name,
+                type_annotation,
+                value: Box::new(value),
+            })
+            .boxed()
+    }
+
+    /// `tasklocal requestId: string = ""`, or `actorlocal`
+    fn task_local_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! {
+            TokenWithSpan { token: Token::TaskLocal, .. } => (),
+            TokenWithSpan { token: Token::ActorLocal, .. } => (),
+        }
+            .ignore_then(Self::identifier())
+            .then(
+                select! { TokenWithSpan { token: Token::Colon, .. } => () }
+                    .ignore_then(Self::type_annotation())
+                    .or_not()
+            )
+            .then_ignore(select! { TokenWithSpan { token: Token::Assign, .. } => () })
+            .then(Self::expression())
+            .map(|((name, type_annotation), initializer)| Node::TaskLocal {
+                name,
+                type_annotation,
+                initializer: Box::new(initializer),
+            })
+            .boxed()
+    }
+
+    /// `type Msg = UpdateProfile(Profile) | Logout`
+    fn union_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        let payload = select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+            .ignore_then(Self::type_annotation()
+                .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () })
+                .at_least(1))
+            .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () });
+        let variant = Self::identifier()
+            .then(payload.or_not())
+            .map(|(name, payload)| UnionVariant { name, payload: payload.unwrap_or_default() });
+        select! { TokenWithSpan { token: Token::Type, .. } => () }
+            .ignore_then(Self::identifier())
+            .then_ignore(select! { TokenWithSpan { token: Token::Assign, .. } => () })
+            .then(
+                variant
+                    .separated_by(select! { TokenWithSpan { token: Token::Pipe, .. } => () })
+                    .at_least(1)
+            )
+            .map(|(name, variants)| Node::Union { name, variants })
+            .boxed()
+    }
+
+    /// `interface Actor { function receive(message: string): boolean; }`
+    fn interface_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Interface, .. } => () }
+            .ignore_then(Self::identifier())
+            .then(
+                select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
+                    .ignore_then(Self::method_signature().repeated())
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightBrace, .. } => () })
+            )
+            .map(|(name, methods)| Node::Interface { name, methods })
+            .boxed()
+    }
+
+    /// `function f(a: int): int;`, a method declared without a body.
+    fn method_signature<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, MethodSignature, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Function, .. } => () }
+            .ignore_then(Self::identifier())
+            .then(
+                select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                    .ignore_then(Self::parameter()
+                        .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () }))
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
+            )
+            .then(
+                select! { TokenWithSpan { token: Token::Colon, .. } => () }
+                    .ignore_then(Self::type_annotation())
+                    .or_not()
+            )
+            .then_ignore(select! { TokenWithSpan { token: Token::Semicolon, .. } => () })
+            .map(|((name, params), return_type)| MethodSignature {
+                name,
+                params,
+                return_type: return_type.unwrap_or(Type::Void),
+            })
+            .boxed()
+    }
+
+    fn expression<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         recursive(|expr| {
             let atom = choice((
-                Self::identifier().map(Node::Identifier),
-                select! { TokenWithSpan { token: Token::IntLiteral, .. } => () }
-                    .map(|_| Node::IntLiteral(0)),
-                select! { TokenWithSpan { token: Token::StringLiteral, .. } => () }
-                    .map(|_| Node::StringLiteral("".to_string())),
+                Self::symbol().map(Node::Identifier),
+                // A suffix is the literal's type, for the checker
+                Self::number(),
+                select! { TokenWithSpan { token: Token::StringLiteral(text), .. } => Node::StringLiteral(text.into_owned()) },
+                select! { TokenWithSpan { token: Token::TemplateStart, .. } => () }
+                    .ignore_then(choice((
+                        select! { TokenWithSpan { token: Token::TemplateText(text), .. } => Node::StringLiteral(text.into_owned()) },
+                        select! { TokenWithSpan { token: Token::InterpolationStart, .. } => () }
+                            .ignore_then(expr.clone())
+                            .then_ignore(select! { TokenWithSpan { token: Token::InterpolationEnd, .. } => () }),
+                    )).repeated())
+                    .then_ignore(select! { TokenWithSpan { token: Token::TemplateEnd, .. } => () })
+                    .map(|parts| Node::TemplateString { parts }),
+                // Sugar for `regex.compile("..")`
+                select! { TokenWithSpan { token: Token::RegexLiteral(pattern), .. } => pattern }
+                    .map(|pattern| Node::Call {
+                        callee: Box::new(Node::Member { object: Box::new(Node::Identifier("regex".into())), property: "compile".to_string() }),
+                        arguments: vec![Node::StringLiteral(pattern.to_string())],
+                    }),
                 select! { TokenWithSpan { token: Token::True, .. } => () }
                     .map(|_| Node::BooleanLiteral(true)),
                 select! { TokenWithSpan { token: Token::False, .. } => () }
@@ -123,9 +755,21 @@ impl GardParser {
                     .map(|_| Node::This),
                 select! { TokenWithSpan { token: Token::Super, .. } => () }
                     .map(|_| Node::Super),
+                // The `bytes` module, as in `bytes.fromHex(..)`
+                select! { TokenWithSpan { token: Token::Bytes, .. } => () }
+                    .map(|_| Node::Identifier("bytes".into())),
                 select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
                     .ignore_then(expr.clone())
                     .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () }),
+                // `{ name: value, .. }`
+                select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
+                    .ignore_then(Self::identifier()
+                        .then_ignore(select! { TokenWithSpan { token: Token::Colon, .. } => () })
+                        .then(expr.clone())
+                        .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () })
+                        .allow_trailing())
+                    .then_ignore(select! { TokenWithSpan { token: Token::RightBrace, .. } => () })
+                    .map(|fields| Node::Object { fields }),
             ))
             .boxed();
 
@@ -133,30 +777,76 @@ impl GardParser {
                 .then(
                     select! { TokenWithSpan { token: Token::Dot, .. } => () }
                         .ignore_then(Self::identifier())
+                        .map(Postfix::Property)
+                        .or(select! { TokenWithSpan { token: Token::LeftBracket, .. } => () }
+                            .ignore_then(expr.clone())
+                            .then_ignore(select! { TokenWithSpan { token: Token::RightBracket, .. } => () })
+                            .map(Postfix::Index))
                         .repeated()
                 )
-                .map(|(obj, props)| {
-                    props.into_iter().fold(obj, |obj, prop| Node::Member {
-                        object: Box::new(obj),
-                        property: prop,
+                .map(|(obj, postfixes)| {
+                    postfixes.into_iter().fold(obj, |obj, postfix| match postfix {
+                        Postfix::Property(property) => Node::Member {
+                            object: Box::new(obj),
+                            property,
+                        },
+                        Postfix::Index(index) => Node::Index {
+                            object: Box::new(obj),
+                            index: Box::new(index),
+                            checked: true,
+                        },
                     })
                 })
                 .boxed();
 
-            let call = member.clone()
+            let arguments = select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
+                .ignore_then(expr.clone()
+                    .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () }))
+                .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () });
+
+            // `new Point(1, 2)` is the same call as `Point(1, 2)`
+            let construction = select! { TokenWithSpan { token: Token::New, .. } => () }
+                .ignore_then(member.clone())
+                .then(arguments.clone())
+                .map(|(callee, arguments)| Node::Call { callee: Box::new(callee), arguments });
+
+            let call = construction
+                .or(member.clone()
+                    .then(arguments.or_not())
+                    .map(|(callee, args)| match args {
+                        Some(args) => Node::Call {
+                            callee: Box::new(callee),
+                            arguments: args,
+                        },
+                        None => callee,
+                    }))
+                .boxed();
+
+            // `i++` and `i--`
+            let postfix = call.clone()
+                .then(choice((
+                    select! { TokenWithSpan { token: Token::Increment, .. } => UnaryOp::PostIncrement },
+                    select! { TokenWithSpan { token: Token::Decrement, .. } => UnaryOp::PostDecrement },
+                )).or_not())
+                .map(|(operand, operator)| match operator {
+                    Some(operator) => Node::Unary { operator, operand: Box::new(operand) },
+                    None => operand,
+                })
+                .boxed();
+
+            // `shape is Square`, `shape as? Square`
+            let type_test = postfix
                 .then(
-                    select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
-                        .ignore_then(expr.clone()
-                            .separated_by(select! { TokenWithSpan { token: Token::Comma, .. } => () }))
-                        .then_ignore(select! { TokenWithSpan { token: Token::RightParen, .. } => () })
-                        .or_not()
+                    select! { TokenWithSpan { token: Token::Is, .. } => true }
+                        .or(select! { TokenWithSpan { token: Token::SafeAs, .. } => false })
+                        .then(Self::type_annotation())
+                        .repeated()
                 )
-                .map(|(callee, args)| match args {
-                    Some(args) => Node::Call {
-                        callee: Box::new(callee),
-                        arguments: args,
-                    },
-                    None => callee,
+                .map(|(value, tests)| {
+                    tests.into_iter().fold(value, |value, (is_test, target)| match is_test {
+                        true => Node::TypeTest { value: Box::new(value), target },
+                        false => Node::SafeCast { value: Box::new(value), target },
+                    })
                 })
                 .boxed();
 
@@ -165,13 +855,14 @@ impl GardParser {
                 select! { TokenWithSpan { token: Token::Minus, .. } => UnaryOp::Minus },
                 select! { TokenWithSpan { token: Token::Increment, .. } => UnaryOp::Increment },
                 select! { TokenWithSpan { token: Token::Decrement, .. } => UnaryOp::Decrement },
+                select! { TokenWithSpan { token: Token::Tilde, .. } => UnaryOp::BitNot },
             ))
-            .then(call.clone())
+            .then(type_test.clone())
             .map(|(op, expr)| Node::Unary {
                 operator: op,
                 operand: Box::new(expr),
             })
-            .or(call)
+            .or(type_test)
             .boxed();
 
             let product = unary.clone()
@@ -211,7 +902,60 @@ impl GardParser {
                 })
                 .boxed();
 
-            let comparison = sum.clone()
+            // Shifts and bitwise operators bind tighter than comparisons, so
+            // `flags & MASK == 0` tests the masked bits
+            let shift = sum.clone()
+                .then(
+                    choice((
+                        select! { TokenWithSpan { token: Token::ShiftLeft, .. } => BinaryOp::Shl },
+                        select! { TokenWithSpan { token: Token::ShiftRight, .. } => BinaryOp::Shr },
+                    ))
+                    .then(sum)
+                    .repeated()
+                )
+                .map(|(first, rest)| {
+                    rest.into_iter().fold(first, |lhs, (op, rhs)| Node::Binary {
+                        left: Box::new(lhs),
+                        operator: op,
+                        right: Box::new(rhs),
+                    })
+                })
+                .boxed();
+
+            let bit_and = shift.clone()
+                .then(select! { TokenWithSpan { token: Token::Ampersand, .. } => BinaryOp::BitAnd }.then(shift).repeated())
+                .map(|(first, rest)| {
+                    rest.into_iter().fold(first, |lhs, (op, rhs)| Node::Binary {
+                        left: Box::new(lhs),
+                        operator: op,
+                        right: Box::new(rhs),
+                    })
+                })
+                .boxed();
+
+            let bit_xor = bit_and.clone()
+                .then(select! { TokenWithSpan { token: Token::Caret, .. } => BinaryOp::BitXor }.then(bit_and).repeated())
+                .map(|(first, rest)| {
+                    rest.into_iter().fold(first, |lhs, (op, rhs)| Node::Binary {
+                        left: Box::new(lhs),
+                        operator: op,
+                        right: Box::new(rhs),
+                    })
+                })
+                .boxed();
+
+            let bit_or = bit_xor.clone()
+                .then(select! { TokenWithSpan { token: Token::Pipe, .. } => BinaryOp::BitOr }.then(bit_xor).repeated())
+                .map(|(first, rest)| {
+                    rest.into_iter().fold(first, |lhs, (op, rhs)| Node::Binary {
+                        left: Box::new(lhs),
+                        operator: op,
+                        right: Box::new(rhs),
+                    })
+                })
+                .boxed();
+
+            let comparison = bit_or.clone()
                 .then(
                     choice((
                         select! { TokenWithSpan { token: Token::Equals, .. } => BinaryOp::Eq },
@@ -221,7 +965,7 @@ impl GardParser {
                         select! { TokenWithSpan { token: Token::GreaterThan, .. } => BinaryOp::Gt },
                         select! { TokenWithSpan { token: Token::GreaterEquals, .. } => BinaryOp::GtEq },
                     ))
-                    .then(sum)
+                    .then(bit_or)
                     .repeated()
                 )
                 .map(|(first, rest)| {
@@ -255,31 +999,53 @@ impl GardParser {
         }).boxed()
     }
 
-    fn type_annotation() -> impl chumsky::Parser<TokenWithSpan, Type, Error = Simple<TokenWithSpan>> {
-        Self::identifier().map(Type::Custom)
-    }
-
-    fn expression_statement() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn type_annotation<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Type, Error = Simple<TokenWithSpan<'src>>> {
+        choice((
+            select! { TokenWithSpan { token: Token::Int, .. } => Type::Int },
+            select! { TokenWithSpan { token: Token::UInt, .. } => Type::UInt },
+            select! { TokenWithSpan { token: Token::UInt256, .. } => Type::UInt256 },
+            select! { TokenWithSpan { token: Token::Float, .. } => Type::Float },
+            select! { TokenWithSpan { token: Token::Double, .. } => Type::Double },
+            select! { TokenWithSpan { token: Token::String, .. } => Type::String },
+            select! { TokenWithSpan { token: Token::Bytes, .. } => Type::Bytes },
+            select! { TokenWithSpan { token: Token::Boolean, .. } => Type::Boolean },
+            select! { TokenWithSpan { token: Token::Void, .. } => Type::Void },
+            select! { TokenWithSpan { token: Token::Address, .. } => Type::Address },
+            Self::qualified_name().map(|name| Type::Custom(name.into())),
+        ))
+    }
+
+    fn expression_statement<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         Self::expression()
             .then_ignore(select! { TokenWithSpan { token: Token::Semicolon, .. } => () })
             .map(|expr| Node::Block(vec![expr]))
     }
 
-    fn contract_declaration() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn contract_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Contract, .. } => () }
             .then(Self::identifier())
-            .then(Self::block())
-            .map(|((_, name), body)| Node::Contract {
+            .then(Self::contract_body())
+            .map(|((_, name), members)| Node::Contract {
                 name,
-                members: if let Node::Block(members) = body {
-                    members
-                } else {
-                    vec![]
-                },
+                members,
+                docs: None,
             })
     }
 
-    fn function_declaration() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    /// Like `block()`, but also accepts the wasm attributes, which wrap
+    /// function declarations and so can't be parsed as statements.
+    fn contract_body<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Vec<Node>, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
+            .ignore_then(Self::documented(choice((
+                Self::wasm_export_declaration(),
+                Self::wasm_import_declaration(),
+                Self::statement(),
+            ))).repeated())
+            .then_ignore(select! { TokenWithSpan { token: Token::RightBrace, .. } => () })
+            .boxed()
+    }
+
+    fn function_declaration<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Function, .. } => () }
             .then(Self::identifier())
             .then(Self::block())
@@ -289,10 +1055,11 @@ impl GardParser {
                 return_type: Type::Void,
                 body: Box::new(body),
                 modifiers: vec![],
+                docs: None,
             })
     }
 
-    fn try_statement() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn try_statement<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Try, .. } => () }
             .ignore_then(Self::block())
             .then(
@@ -322,7 +1089,7 @@ impl GardParser {
             })
     }
 
-    fn if_statement() -> impl chumsky::Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn if_statement<'src>() -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         recursive(|if_stmt| {
             select! { TokenWithSpan { token: Token::If, .. } => () }
                 .ignore_then(
@@ -347,7 +1114,7 @@ impl GardParser {
         }).boxed()
     }
 
-    fn while_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn while_statement<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::While, .. } => () }
             .ignore_then(
                 select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
@@ -362,7 +1129,7 @@ impl GardParser {
             .boxed()
     }
 
-    fn for_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn for_statement<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::For, .. } => () }
             .ignore_then(
                 select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
@@ -387,7 +1154,7 @@ impl GardParser {
             .boxed()
     }
 
-    fn foreach_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn foreach_statement<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Foreach, .. } => () }
             .ignore_then(
                 select! { TokenWithSpan { token: Token::LeftParen, .. } => () }
@@ -405,33 +1172,41 @@ impl GardParser {
             .boxed()
     }
 
-    fn match_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    /// `match value { pattern => { .. }, _ => { .. } }`
+    fn match_statement<'src>(
+        block: impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>>,
+    ) -> impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Match, .. } => () }
             .ignore_then(Self::expression())
             .then(
                 select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
-                    .ignore_then(Self::match_case().repeated())
+                    .ignore_then(
+                        Self::match_case(block)
+                            .then_ignore(select! { TokenWithSpan { token: Token::Comma, .. } => () }.or_not())
+                            .repeated()
+                    )
                     .then_ignore(select! { TokenWithSpan { token: Token::RightBrace, .. } => () })
             )
             .map(|(value, cases)| Node::Match {
                 value: Box::new(value),
                 cases,
             })
-            .boxed()
     }
 
-    fn match_case() -> impl Parser<TokenWithSpan, MatchCase, Error = Simple<TokenWithSpan>> {
-        Self::expression()
+    fn match_case<'src>(
+        block: impl chumsky::Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>>,
+    ) -> impl chumsky::Parser<TokenWithSpan<'src>, MatchCase, Error = Simple<TokenWithSpan<'src>>> {
+        select! { TokenWithSpan { token: Token::Underscore, .. } => Node::Identifier("_".into()) }
+            .or(Self::expression())
             .then_ignore(select! { TokenWithSpan { token: Token::Arrow, .. } => () })
-            .then(Self::block())
+            .then(block)
             .map(|(pattern, body)| MatchCase {
                 pattern,
                 body,
             })
-            .boxed()
     }
 
-    fn return_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn return_statement<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Return, .. } => () }
             .ignore_then(
                 Self::expression()
@@ -442,7 +1217,7 @@ impl GardParser {
             .boxed()
     }
 
-    fn throw_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn throw_statement<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Throw, .. } => () }
             .ignore_then(
                 Self::expression()
@@ -452,7 +1227,7 @@ impl GardParser {
             .boxed()
     }
 
-    fn do_while_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn do_while_statement<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Do, .. } => () }
             .ignore_then(Self::block())
             .then_ignore(select! { TokenWithSpan { token: Token::While, .. } => () })
@@ -469,36 +1244,36 @@ impl GardParser {
             .boxed()
     }
 
-    fn break_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn break_statement<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Break, .. } => () }
             .then_ignore(select! { TokenWithSpan { token: Token::Semicolon, .. } => () })
             .map(|_| Node::Break)
             .boxed()
     }
 
-    fn continue_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn continue_statement<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Continue, .. } => () }
             .then_ignore(select! { TokenWithSpan { token: Token::Semicolon, .. } => () })
             .map(|_| Node::Continue)
             .boxed()
     }
 
-    fn actor_system_declaration() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
-        select! { TokenWithSpan { token: Token::Actor, .. } => () }
+    fn actor_system_declaration<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        Self::soft_keyword("Actor")
             .ignore_then(Self::identifier())
             .then(
                 select! { TokenWithSpan { token: Token::LessThan, .. } => () }
-                    .ignore_then(Self::identifier())
+                    .ignore_then(Self::symbol())
                     .then_ignore(select! { TokenWithSpan { token: Token::GreaterThan, .. } => () })
-                    .map(|type_param| Type::Custom(type_param))
+                    .map(Type::Custom)
                     .or_not()
             )
             .then(Self::block())
             .map(|((name, type_param), body)| Node::Actor {
                 name,
                 type_param,
-                mailbox: Box::new(Node::Identifier("MessageQueue".to_string())),
-                behavior: Box::new(Node::Identifier("ActorBehavior".to_string())),
+                mailbox: Box::new(Node::Identifier("MessageQueue".into())),
+                behavior: Box::new(Node::Identifier("ActorBehavior".into())),
                 members: if let Node::Block(members) = body {
                     members
                 } else {
@@ -508,14 +1283,14 @@ impl GardParser {
             .boxed()
     }
 
-    fn stm_declaration() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
-        select! { TokenWithSpan { token: Token::TVar, .. } => () }
+    fn stm_declaration<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        Self::soft_keyword("TVar")
             .ignore_then(Self::identifier())
             .then(
                 select! { TokenWithSpan { token: Token::LessThan, .. } => () }
-                    .ignore_then(Self::identifier())
+                    .ignore_then(Self::symbol())
                     .then_ignore(select! { TokenWithSpan { token: Token::GreaterThan, .. } => () })
-                    .map(|type_param| Type::Custom(type_param))
+                    .map(Type::Custom)
             )
             .then(
                 select! { TokenWithSpan { token: Token::Assign, .. } => () }
@@ -530,7 +1305,7 @@ impl GardParser {
             .boxed()
     }
 
-    fn atomic_block() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn atomic_block<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Atomic, .. } => () }
             .ignore_then(Self::block())
             .map(|body| Node::Atomic {
@@ -539,22 +1314,22 @@ impl GardParser {
             .boxed()
     }
 
-    fn actor_declaration() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
-        select! { TokenWithSpan { token: Token::Actor, .. } => () }
+    fn actor_declaration<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        Self::soft_keyword("Actor")
             .ignore_then(Self::identifier())
             .then(
                 select! { TokenWithSpan { token: Token::LessThan, .. } => () }
-                    .ignore_then(Self::identifier())
+                    .ignore_then(Self::symbol())
                     .then_ignore(select! { TokenWithSpan { token: Token::GreaterThan, .. } => () })
-                    .map(|type_param| Type::Custom(type_param))
+                    .map(Type::Custom)
                     .or_not()
             )
             .then(Self::block())
             .map(|((name, type_param), body)| Node::Actor {
                 name,
                 type_param,
-                mailbox: Box::new(Node::Identifier("MessageQueue".to_string())),
-                behavior: Box::new(Node::Identifier("ActorBehavior".to_string())),
+                mailbox: Box::new(Node::Identifier("MessageQueue".into())),
+                behavior: Box::new(Node::Identifier("ActorBehavior".into())),
                 members: if let Node::Block(members) = body {
                     members
                 } else {
@@ -564,7 +1339,7 @@ impl GardParser {
             .boxed()
     }
 
-    fn message_handler() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn message_handler<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Function, .. } => () }
             .ignore_then(Self::identifier())
             .then(
@@ -580,7 +1355,7 @@ impl GardParser {
             .boxed()
     }
 
-    fn become_statement() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn become_statement<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Become, .. } => () }
             .ignore_then(Self::expression())
             .then_ignore(select! { TokenWithSpan { token: Token::Semicolon, .. } => () })
@@ -590,13 +1365,17 @@ impl GardParser {
             .boxed()
     }
 
-    fn supervision_strategy() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
-        select! { TokenWithSpan { token: Token::SupervisionStrategy, .. } => () }
+    fn supervision_strategy<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
+        Self::soft_keyword("SupervisionStrategy")
             .ignore_then(
                 choice((
-                    select! { TokenWithSpan { token: Token::DecisionRestart, .. } => SupervisionStrategy::OneForOne },
-                    select! { TokenWithSpan { token: Token::DecisionStop, .. } => SupervisionStrategy::OneForAll },
-                    select! { TokenWithSpan { token: Token::DecisionEscalate, .. } => SupervisionStrategy::RestForOne },
+                    Self::soft_keyword("Decision")
+                        .ignore_then(select! { TokenWithSpan { token: Token::Dot, .. } => () })
+                        .ignore_then(choice((
+                            Self::soft_keyword("RESTART").to(SupervisionStrategy::OneForOne),
+                            Self::soft_keyword("STOP").to(SupervisionStrategy::OneForAll),
+                            Self::soft_keyword("ESCALATE").to(SupervisionStrategy::RestForOne),
+                        ))),
                     Self::identifier().map(SupervisionStrategy::Custom),
                 ))
             )
@@ -612,7 +1391,7 @@ impl GardParser {
             .boxed()
     }
 
-    fn blockchain_contract_basic() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn blockchain_contract_basic<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Blockchain, .. } => () }
             .ignore_then(select! { TokenWithSpan { token: Token::Contract, .. } => () })
             .ignore_then(Self::identifier())
@@ -624,11 +1403,12 @@ impl GardParser {
                 } else {
                     vec![]
                 },
+                docs: None,
             })
             .boxed()
     }
 
-    fn event_declaration() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn event_declaration<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Event, .. } => () }
             .ignore_then(Self::identifier())
             .then(
@@ -639,11 +1419,12 @@ impl GardParser {
             .map(|(name, fields)| Node::Event {
                 name,
                 fields,
+                docs: None,
             })
             .boxed()
     }
 
-    fn transaction_declaration() -> impl Parser<TokenWithSpan, Node, Error = Simple<TokenWithSpan>> {
+    fn transaction_declaration<'src>() -> impl Parser<TokenWithSpan<'src>, Node, Error = Simple<TokenWithSpan<'src>>> {
         select! { TokenWithSpan { token: Token::Transaction, .. } => () }
             .ignore_then(
                 select! { TokenWithSpan { token: Token::LeftBrace, .. } => () }
@@ -664,8 +1445,8 @@ impl GardParser {
             .boxed()
     }
 
-    fn parameter() -> impl Parser<TokenWithSpan, Parameter, Error = Simple<TokenWithSpan>> {
-        Self::identifier()
+    fn parameter<'src>() -> impl Parser<TokenWithSpan<'src>, Parameter, Error = Simple<TokenWithSpan<'src>>> {
+        Self::symbol()
             .then_ignore(select! { TokenWithSpan { token: Token::Colon, .. } => () })
             .then(Self::type_annotation())
             .map(|(name, type_annotation)| Parameter {
@@ -710,6 +1491,721 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_parse_all_rejects_trailing_input() {
+        let tokens = Lexer::new("function main {} }").tokenize().unwrap();
+        assert!(GardParser::parse(tokens.clone()).is_ok());
+        assert!(GardParser::parse_all(tokens).is_err());
+    }
+
+    #[test]
+    fn test_const_declarations() {
+        let tokens = Lexer::new("const LIMIT: int = 2 * 8\nfunction main {\n    const HALF = LIMIT / 2\n}").tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+
+        match &program {
+            Node::Program(nodes) => {
+                assert!(matches!(&nodes[0], Node::Const { type_annotation: Some(Type::Int), .. }));
+                match &nodes[1] {
+                    Node::Function { body, .. } => match body.as_ref() {
+                        Node::Block(statements) => assert!(matches!(statements[0].unlocated(), Node::Const { type_annotation: None, .. })),
+                        other => panic!("expected block, found {:?}", other),
+                    },
+                    other => panic!("expected function, found {:?}", other),
+                }
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_task_local_declarations() {
+        let tokens = Lexer::new("tasklocal requestId: int = 0\nactorlocal handled = initial").tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+        assert_eq!(program, Node::Program(vec![
+            Node::TaskLocal {
+                name: "requestId".to_string(),
+                type_annotation: Some(Type::Int),
+                initializer: Box::new(Node::IntLiteral(0)),
+            },
+            Node::TaskLocal {
+                name: "handled".to_string(),
+                type_annotation: None,
+                initializer: Box::new(Node::Identifier("initial".into())),
+            },
+        ]));
+    }
+
+    #[test]
+    fn test_typed_numeric_literals() {
+        let tokens = Lexer::new("tasklocal supply = 1_000_000u\ntasklocal ratio = 3.5f\ntasklocal count = 42i").tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+        let initializers: Vec<Node> = match program {
+            Node::Program(nodes) => nodes.into_iter().map(|node| match node {
+                Node::TaskLocal { initializer, .. } => *initializer,
+                other => panic!("expected a tasklocal, found {:?}", other),
+            }).collect(),
+            other => panic!("expected a program, found {:?}", other),
+        };
+        assert_eq!(initializers, [Node::UIntLiteral(1000000), Node::FloatLiteral(3.5), Node::IntLiteral(42)]);
+
+        let literal = |source: &'static str| GardParser::parse_expression(Lexer::new(source).tokenize().unwrap());
+        assert_eq!(literal("-7"), Ok(Node::IntLiteral(-7)));
+        assert_eq!(literal("0b1010_1010"), Ok(Node::IntLiteral(170)));
+        assert_eq!(literal("0o17"), Ok(Node::IntLiteral(15)));
+        assert_eq!(literal("6.02e2_3"), Ok(Node::FloatLiteral(6.02e23)));
+        assert_eq!(literal("1_000.000_1"), Ok(Node::FloatLiteral(1000.0001)));
+        let error = literal("99999999999999999999i").unwrap_err();
+        assert_eq!(error[0].reason(), &chumsky::error::SimpleReason::Custom("Literal '99999999999999999999i' does not fit in int".to_string()));
+        assert!(literal("99999999999999999999u").is_err());
+    }
+
+    #[test]
+    fn test_uint256_literals() {
+        let literal = |source: &'static str| GardParser::parse_expression(Lexer::new(source).tokenize().unwrap());
+        assert_eq!(literal("0xFF"), Ok(Node::IntLiteral(255)));
+        assert_eq!(
+            literal("0x7e5f4552_091a6912_5d5dfcb7_b8c26590_29395bdf"),
+            Ok(Node::UInt256Literal("0x7e5f4552091a69125d5dfcb7b8c2659029395bdf".to_string()))
+        );
+        assert_eq!(
+            literal("0x0000000000000000000000000000000000000001"),
+            Ok(Node::UInt256Literal("0x0000000000000000000000000000000000000001".to_string()))
+        );
+        assert_eq!(literal("115792089237316195423570985008687907853269984665640564039457584007913129639935"), Ok(Node::UInt256Literal(
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935".to_string()
+        )));
+        assert_eq!(literal("100_000_000_000_000_000_000"), Ok(Node::UInt256Literal("100000000000000000000".to_string())));
+        assert!(literal("-99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn test_template_strings() {
+        let tokens = Lexer::new("tasklocal greeting = `Hello ${user.name}, ${`again`}`").tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+        let text = |text: &str| Node::StringLiteral(text.to_string());
+        assert_eq!(program, Node::Program(vec![Node::TaskLocal {
+            name: "greeting".to_string(),
+            type_annotation: None,
+            initializer: Box::new(Node::TemplateString { parts: vec![
+                text("Hello "),
+                Node::Member { object: Box::new(Node::Identifier("user".into())), property: "name".to_string() },
+                text(", "),
+                Node::TemplateString { parts: vec![text("again")] },
+            ] }),
+        }]));
+    }
+
+    #[test]
+    fn test_union_declarations() {
+        let source = "type Msg = Update(int, string) | Logout\nfunction main {\n    match msg {\n        Update => {}\n        _ => {}\n    }\n}";
+        let program = GardParser::parse_all(Lexer::new(source).tokenize().unwrap()).unwrap();
+
+        match &program {
+            Node::Program(nodes) => {
+                assert!(matches!(&nodes[0], Node::Union { variants, .. } if variants.len() == 2));
+                assert!(matches!(&nodes[0], Node::Union { variants, .. }
+                    if variants[0].payload == vec![Type::Int, Type::String] && variants[1].payload.is_empty()));
+                let cases = match &nodes[1] {
+                    Node::Function { body, .. } => match body.as_ref() {
+                        Node::Block(statements) => match statements[0].unlocated() {
+                            Node::Match { cases, .. } => cases,
+                            other => panic!("expected match, found {:?}", other),
+                        },
+                        other => panic!("expected block, found {:?}", other),
+                    },
+                    other => panic!("expected function, found {:?}", other),
+                };
+                assert_eq!(cases[1].pattern, Node::Identifier("_".into()));
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_interface_declarations() {
+        let source = "interface Shape {\n    function scale(factor: int, round: boolean): int;\n    function reset();\n}\nclass Square implements Shape {}";
+        let program = GardParser::parse_all(Lexer::new(source).tokenize().unwrap()).unwrap();
+
+        match &program {
+            Node::Program(nodes) => {
+                match &nodes[0] {
+                    Node::Interface { methods, .. } => {
+                        assert_eq!(methods.len(), 2);
+                        assert_eq!(methods[0].params.len(), 2);
+                        assert_eq!(methods[0].return_type, Type::Int);
+                        assert_eq!(methods[1].return_type, Type::Void);
+                    },
+                    other => panic!("expected interface, found {:?}", other),
+                }
+                assert!(matches!(&nodes[1], Node::Class { implements, .. } if implements.len() == 1));
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_abstract_classes() {
+        let source = "abstract class Shape {\n    abstract function area(): int;\n    let sides: int\n}\nclass Square extends Shape {}";
+        let program = GardParser::parse_all(Lexer::new(source).tokenize().unwrap()).unwrap();
+
+        match &program {
+            Node::Program(nodes) => {
+                match &nodes[0] {
+                    Node::Class { is_abstract: true, members, .. } => {
+                        assert!(matches!(&members[0], Node::Function { modifiers, return_type: Type::Int, .. }
+                            if modifiers == &vec![FunctionModifier::Abstract]));
+                        assert!(matches!(&members[1], Node::Let { .. }));
+                    },
+                    other => panic!("expected abstract class, found {:?}", other),
+                }
+                assert!(matches!(&nodes[1], Node::Class { is_abstract: false, extends: Some(_), .. }));
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_constructors_and_super() {
+        let source = "class Square extends Shape {\n    constructor(side: int) {\n        super(side);\n        super.describe();\n    }\n}";
+        let program = GardParser::parse_all(Lexer::new(source).tokenize().unwrap()).unwrap();
+
+        let body = match &program {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Class { members, .. } => match &members[0] {
+                    Node::Constructor { params, body } => {
+                        assert_eq!(params.len(), 1);
+                        body.as_ref()
+                    },
+                    other => panic!("expected constructor, found {:?}", other),
+                },
+                other => panic!("expected class, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        };
+        let expressions: Vec<&Node> = match body {
+            Node::Block(statements) => statements.iter()
+                .map(|statement| match statement.unlocated() {
+                    Node::Block(expressions) => &expressions[0],
+                    other => panic!("expected expression statement, found {:?}", other),
+                })
+                .collect(),
+            other => panic!("expected block, found {:?}", other),
+        };
+        assert!(matches!(expressions[0], Node::Call { callee, .. } if matches!(callee.as_ref(), Node::Super)));
+        assert!(matches!(expressions[1], Node::Call { callee, .. }
+            if matches!(callee.as_ref(), Node::Member { object, .. } if matches!(object.as_ref(), Node::Super))));
+    }
+
+    #[test]
+    fn test_nested_classes() {
+        let source = "class Outer {\n    class Inner extends Outer.Base {\n    }\n    let inner: Outer.Inner = make()\n}";
+        let program = GardParser::parse_all(Lexer::new(source).tokenize().unwrap()).unwrap();
+
+        let members = match &program {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Class { members, .. } => members.clone(),
+                other => panic!("expected class, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        };
+        assert!(matches!(&members[0], Node::Class { extends: Some(base), members, .. }
+            if base == "Outer.Base" && members.is_empty()));
+        assert!(matches!(&members[1], Node::Let { name, type_annotation: Some(Type::Custom(ty)), .. } if name == "inner" && ty == "Outer.Inner"));
+    }
+
+    #[test]
+    fn test_object_literals_and_new() {
+        let tokens = Lexer::new("function main {\n    send({ to: new Point(1, 2), tag: \"a\" });\n}").tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+
+        let expression = match &program {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Function { body, .. } => match body.as_ref() {
+                    Node::Block(statements) => match statements[0].unlocated() {
+                        Node::Block(expressions) => expressions[0].clone(),
+                        other => panic!("expected expression statement, found {:?}", other),
+                    },
+                    other => panic!("expected block, found {:?}", other),
+                },
+                other => panic!("expected function, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        };
+        let identifier = |name: &str| Box::new(Node::Identifier(name.into()));
+        assert_eq!(expression, Node::Call {
+            callee: identifier("send"),
+            arguments: vec![Node::Object { fields: vec![
+                ("to".to_string(), Node::Call { callee: identifier("Point"), arguments: vec![Node::IntLiteral(1), Node::IntLiteral(2)] }),
+                ("tag".to_string(), Node::StringLiteral("a".to_string())),
+            ] }],
+        });
+    }
+
+    #[test]
+    fn test_type_tests() {
+        let tokens = Lexer::new("function main {\n    !shape is Square && shape as? Square;\n}").tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+
+        let expression = match &program {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Function { body, .. } => match body.as_ref() {
+                    Node::Block(statements) => match statements[0].unlocated() {
+                        Node::Block(expressions) => expressions[0].clone(),
+                        other => panic!("expected expression statement, found {:?}", other),
+                    },
+                    other => panic!("expected block, found {:?}", other),
+                },
+                other => panic!("expected function, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        };
+        match expression {
+            Node::Binary { left, operator: BinaryOp::And, right } => {
+                assert!(matches!(*left, Node::Unary { operator: UnaryOp::Not, operand }
+                    if matches!(*operand, Node::TypeTest { target: Type::Custom(_), .. })));
+                assert!(matches!(*right, Node::SafeCast { .. }));
+            },
+            other => panic!("expected conjunction, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_index_expressions() {
+        let tokens = Lexer::new("function main {\n    xs[i].y;\n}").tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+
+        let statement = match &program {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Function { body, .. } => match body.as_ref() {
+                    Node::Block(statements) => statements[0].unlocated(),
+                    other => panic!("expected block, found {:?}", other),
+                },
+                other => panic!("expected function, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        };
+        match statement {
+            Node::Block(expressions) => match &expressions[0] {
+                Node::Member { object, .. } => assert!(matches!(object.as_ref(), Node::Index { checked: true, .. })),
+                other => panic!("expected member, found {:?}", other),
+            },
+            other => panic!("expected expression statement, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bytes_type_and_module() {
+        assert_eq!(GardParser::parse_type(Lexer::new("bytes").tokenize().unwrap()), Ok(Type::Bytes));
+
+        let tokens = Lexer::new("function main {\n    bytes.concat(a, b);\n}").tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+        let statement = match &program {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Function { body, .. } => match body.as_ref() {
+                    Node::Block(statements) => statements[0].unlocated(),
+                    other => panic!("expected block, found {:?}", other),
+                },
+                other => panic!("expected function, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        };
+        match statement {
+            Node::Block(expressions) => match &expressions[0] {
+                Node::Call { callee, .. } => assert!(matches!(callee.as_ref(),
+                    Node::Member { object, .. } if **object == Node::Identifier("bytes".into()))),
+                other => panic!("expected call, found {:?}", other),
+            },
+            other => panic!("expected expression statement, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_regex_literal() {
+        let tokens = Lexer::new("function main {\n    regex.isMatch(re\"\\d+\", s);\n}").tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+
+        let statement = match &program {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Function { body, .. } => match body.as_ref() {
+                    Node::Block(statements) => statements[0].unlocated(),
+                    other => panic!("expected block, found {:?}", other),
+                },
+                other => panic!("expected function, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        };
+        match statement {
+            Node::Block(expressions) => match &expressions[0] {
+                Node::Call { arguments, .. } => match &arguments[0] {
+                    Node::Call { callee, arguments } => {
+                        assert!(matches!(callee.as_ref(), Node::Member { property, .. } if property == "compile"));
+                        assert_eq!(arguments, &[Node::StringLiteral(r"\d+".to_string())]);
+                    },
+                    other => panic!("expected regex.compile call, found {:?}", other),
+                },
+                other => panic!("expected call, found {:?}", other),
+            },
+            other => panic!("expected expression statement, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_block_statements_are_located() {
+        let input = "function main {\n    let x = 1\n}";
+        let tokens = Lexer::new(input).tokenize().unwrap();
+        let program = GardParser::parse_all(tokens).unwrap();
+
+        let body = match &program {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Function { body, .. } => body.as_ref(),
+                other => panic!("expected function, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        };
+        match body {
+            Node::Block(statements) => match &statements[0] {
+                Node::Located { span, node } => {
+                    assert_eq!(&input[span.start..span.end], "let x = 1");
+                    assert!(matches!(node.as_ref(), Node::Let { .. }));
+                },
+                other => panic!("expected located statement, found {:?}", other),
+            },
+            other => panic!("expected block, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_derive_attribute() {
+        let tokens = Lexer::new("@derive(Equals, ToString) class Point { let x: int }").tokenize().unwrap();
+        match GardParser::parse_all(tokens).unwrap() {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Derive { derives, declaration } => {
+                    assert_eq!(derives.len(), 2);
+                    assert!(matches!(declaration.as_ref(), Node::Class { members, .. } if members.len() == 1));
+                },
+                other => panic!("expected derive, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cfg_attribute() {
+        let tokens = Lexer::new("@cfg(target = \"wasm32\", feature = \"x\") @derive(ToString) class Point { }").tokenize().unwrap();
+        match GardParser::parse_all(tokens).unwrap() {
+            Node::Program(nodes) => match &nodes[0] {
+                Node::Cfg { conditions, declaration } => {
+                    assert_eq!(conditions.len(), 2);
+                    assert!(matches!(declaration.as_ref(), Node::Derive { .. }));
+                },
+                other => panic!("expected cfg, found {:?}", other),
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_entry_points() {
+        let tokens = |input: &'static str| Lexer::new(input).tokenize().unwrap();
+
+        assert!(matches!(
+            GardParser::parse_expression(tokens("1 + 2 * 3")).unwrap(),
+            Node::Binary { operator: BinaryOp::Add, .. }
+        ));
+        assert!(matches!(GardParser::parse_statement(tokens("let x = 1")).unwrap(), Node::Let { .. }));
+        assert!(matches!(GardParser::parse_statement(tokens("f(x);")).unwrap(), Node::Block(_)));
+        assert_eq!(GardParser::parse_type(tokens("uint256")).unwrap(), Type::UInt256);
+
+        // The error is at the first token the entry point can't take
+        let errors = GardParser::parse_expression(tokens("let x = 1;")).unwrap_err();
+        assert_eq!(errors[0].span(), 0..3);
+        let errors = GardParser::parse_expression(tokens("1 + 2; 3")).unwrap_err();
+        assert_eq!(errors[0].span(), 5..6);
+        assert!(GardParser::parse_statement(tokens("function main { }")).is_err());
+        assert!(GardParser::parse_type(tokens("int x")).is_err());
+    }
+
+    #[test]
+    fn test_soft_keywords() {
+        let tokens = |input: &'static str| Lexer::new(input).tokenize().unwrap();
+
+        // Names like any other outside the constructs they start
+        assert!(matches!(GardParser::parse_statement(tokens("let task = block.hash")).unwrap(), Node::Let { .. }));
+        assert_eq!(GardParser::parse_expression(tokens("Actor")).unwrap(), Node::Identifier("Actor".into()));
+
+        let actor = GardParser::actor_declaration().then_ignore(end()).parse(GardParser::stream(tokens("Actor Counter { }")));
+        assert!(matches!(actor, Ok(Node::Actor { name, .. }) if name == "Counter"));
+        let supervisor = GardParser::supervision_strategy().then_ignore(end()).parse(GardParser::stream(tokens("SupervisionStrategy Decision.STOP { }")));
+        assert!(matches!(supervisor, Ok(Node::Supervise { strategy: SupervisionStrategy::OneForAll, .. })));
+        let custom = GardParser::supervision_strategy().then_ignore(end()).parse(GardParser::stream(tokens("SupervisionStrategy backoff { }")));
+        assert!(matches!(custom, Ok(Node::Supervise { strategy: SupervisionStrategy::Custom(name), .. }) if name == "backoff"));
+    }
+
+    #[test]
+    fn test_parse_recovering() {
+        let source = "function before { f(); } class { let x = 1 } const = ; function after { g(); }";
+        let (program, errors) = GardParser::parse_recovering(Lexer::new(source).tokenize().unwrap());
+        assert!(!errors.is_empty());
+        let Node::Program(declarations) = &program else { panic!("expected program, found {:?}", program) };
+        assert!(matches!(&declarations[0], Node::Function { name, .. } if name == "before"));
+        // Through the closing brace, then through the semicolon
+        assert_eq!(declarations[1], Node::Error { span: Span { start: 25, end: 44 }, consumed_tokens: 7 });
+        assert_eq!(declarations[2], Node::Error { span: Span { start: 45, end: 54 }, consumed_tokens: 3 });
+        assert!(matches!(&declarations[3], Node::Function { name, .. } if name == "after"));
+        assert_eq!(declarations.len(), 4);
+
+        // The formatter keeps what didn't parse as it was written
+        assert!(gard_ast::format(&program, source).contains("\nclass { let x = 1 }\n\nconst = ;\n"));
+
+        let (program, errors) = GardParser::parse_recovering(Lexer::new("function ok { }").tokenize().unwrap());
+        assert!(errors.is_empty());
+        assert!(matches!(program, Node::Program(declarations) if declarations.len() == 1));
+
+        // A declaration cut off by the end of the file takes what's left
+        let source = "function ok { } class Broken { let x = 1\n// trailing\n";
+        let (program, errors) = GardParser::parse_recovering(Lexer::new(source).tokenize().unwrap());
+        assert_eq!(errors[0].span(), source.len()..source.len());
+        let Node::Program(declarations) = &program else { panic!("expected program, found {:?}", program) };
+        assert_eq!(declarations[1], Node::Error { span: Span { start: 16, end: 40 }, consumed_tokens: 7 });
+        assert_eq!(declarations.len(), 2);
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let source = r#"
+            /// Adds one.
+            ///
+            /// Wraps on overflow.
+            function increment { add(x, 1); }
+            /**
+             * A point.
+             */
+            @derive(Equals) class Point { let x: int = 0 }
+            // Not a doc comment
+            function plain {
+                /// Dropped: lets don't keep docs
+                let y = 1
+            }
+        "#;
+        let program = GardParser::parse_all(Lexer::new(source).tokenize().unwrap()).unwrap();
+        let Node::Program(declarations) = &program else { panic!("expected program, found {:?}", program) };
+        let docs: Vec<Option<&str>> = declarations.iter().map(Node::docs).collect();
+        assert_eq!(docs, [Some("Adds one.\n\nWraps on overflow."), Some("A point."), None]);
+
+        let printed = gard_ast::to_source(&program);
+        assert!(printed.contains("/// Adds one.\n///\n/// Wraps on overflow.\nfunction increment"), "printed as:\n{}", printed);
+        let reparsed = GardParser::parse_all(Lexer::new(&printed).tokenize().unwrap()).unwrap();
+        let Node::Program(reparsed) = &reparsed else { unreachable!() };
+        assert_eq!(reparsed.iter().map(Node::docs).collect::<Vec<_>>(), docs);
+    }
+
+    /// Drops spans, which printing doesn't keep.
+    fn strip_spans(node: &mut Node) {
+        if let Node::Located { node: inner, .. } = node {
+            *node = std::mem::replace(inner.as_mut(), Node::NullLiteral);
+        }
+        if let Node::MacroCall { span, .. } = node {
+            *span = Span { start: 0, end: 0 };
+        }
+        for child in node.children_mut() {
+            strip_spans(child);
+        }
+    }
+
+    #[test]
+    fn test_to_source_round_trip() {
+        let input = r#"
+            macro twice(body) { body!(); body!(); }
+            @cfg(target = "wasm32") @derive(Equals) class Point extends Base implements Shape {
+                let x: int = -(1 + 2) * 3
+                @slot(0) let y: uint256
+            }
+            contract Token {
+                @WasmExport("total") function total { let t = a.b(c, d) - (e - f) }
+                @WasmImport("env", "log") function log(message: string, level: int): boolean;
+                require(!done && (a || b), "finished");
+                log!(1, true);
+            }
+            llvm { "declare i64 @host(i8*)" }
+            @route(1) function handler { f(); }
+            function jobs { scope { spawn work(1); let n = 2 } }
+            function greet { print(`Hi \`${name}\` $5 ${`a\tb`.length}`); }
+        "#;
+        let parse = |source: &str| {
+            let mut program = GardParser::parse_all(Lexer::new(source).tokenize().unwrap()).unwrap();
+            strip_spans(&mut program);
+            program
+        };
+
+        let program = parse(input);
+        let source = gard_ast::to_source(&program);
+        assert_eq!(parse(&source), program, "printed as:\n{}", source);
+        assert_eq!(gard_ast::to_source(&parse(&source)), source);
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let name = |name: &str| Box::new(Node::Identifier(name.into()));
+        let binary = |left, operator, right| Box::new(Node::Binary { left, operator, right });
+        let parse = |source: &str| {
+            let mut expression = GardParser::parse_expression(Lexer::new(source).tokenize().unwrap()).unwrap();
+            strip_spans(&mut expression);
+            expression
+        };
+
+        let masked = binary(binary(name("flags"), BinaryOp::BitAnd, binary(name("one"), BinaryOp::Shl, name("bit"))), BinaryOp::Eq, name("none"));
+        assert_eq!(parse("flags & one << bit == none"), *masked);
+        let inverted = Box::new(Node::Unary { operator: UnaryOp::BitNot, operand: name("c") });
+        let mixed = binary(name("a"), BinaryOp::BitOr, binary(binary(name("b"), BinaryOp::BitXor, inverted), BinaryOp::BitXor, name("d")));
+        assert_eq!(parse("a | b ^ ~c ^ d"), *mixed);
+        assert_eq!(parse("x >> y + z"), *binary(name("x"), BinaryOp::Shr, binary(name("y"), BinaryOp::Add, name("z"))));
+        assert_eq!(gard_ast::to_source(&binary(binary(name("a"), BinaryOp::BitOr, name("b")), BinaryOp::BitAnd, name("c"))),
+            "(a | b) & c");
+    }
+
+    #[test]
+    fn test_increments() {
+        let name = |name: &str| Box::new(Node::Identifier(name.into()));
+        let step = |operator, operand| Box::new(Node::Unary { operator, operand });
+        let parse = |source: &str| {
+            let mut expression = GardParser::parse_expression(Lexer::new(source).tokenize().unwrap()).unwrap();
+            strip_spans(&mut expression);
+            expression
+        };
+
+        assert_eq!(parse("i++"), *step(UnaryOp::PostIncrement, name("i")));
+        let element = Box::new(Node::Index { object: name("xs"), index: name("i"), checked: true });
+        assert_eq!(parse("xs[i]--"), *step(UnaryOp::PostDecrement, element.clone()));
+        assert_eq!(parse("++xs[i]"), *step(UnaryOp::Increment, element));
+        let sum = Node::Binary { left: step(UnaryOp::PostIncrement, name("a")), operator: BinaryOp::Add, right: name("b") };
+        assert_eq!(parse("a++ + b"), sum);
+        assert_eq!(gard_ast::to_source(&sum), "a++ + b");
+    }
+
+    #[test]
+    fn test_to_source_precedence() {
+        let name = |name: &str| Box::new(Node::Identifier(name.into()));
+        let binary = |left, operator, right| Box::new(Node::Binary { left, operator, right });
+
+        let grouped = binary(binary(name("a"), BinaryOp::Add, name("b")), BinaryOp::Mul, name("c"));
+        assert_eq!(gard_ast::to_source(&grouped), "(a + b) * c");
+        let right = binary(name("a"), BinaryOp::Sub, binary(name("b"), BinaryOp::Sub, name("c")));
+        assert_eq!(gard_ast::to_source(&right), "a - (b - c)");
+        let left = binary(binary(name("a"), BinaryOp::Sub, name("b")), BinaryOp::Sub, name("c"));
+        assert_eq!(gard_ast::to_source(&left), "a - b - c");
+        let negated = Node::Unary { operator: UnaryOp::Minus, operand: Box::new(Node::Unary { operator: UnaryOp::Minus, operand: name("x") }) };
+        assert_eq!(gard_ast::to_source(&negated), "-(-x)");
+        assert_eq!(gard_ast::to_source(&Node::StringLiteral("say \"hi\"\n".to_string())), r#""say \"hi\"\n""#);
+
+        let statement = Node::If {
+            condition: name("ok"),
+            then_branch: Box::new(Node::Block(vec![Node::Return(Some(name("a")))])),
+            else_branch: Some(Box::new(Node::Block(vec![Node::Break]))),
+        };
+        assert_eq!(gard_ast::to_source(&statement), "if (ok) {\n    return a;\n} else {\n    break;\n}\n");
+    }
+
+    #[test]
+    fn test_plugin_attribute() {
+        let tokens = Lexer::new("@route(1, 2) function index { } @inline function helper { }").tokenize().unwrap();
+        match GardParser::parse_all(tokens).unwrap() {
+            Node::Program(nodes) => {
+                assert!(matches!(&nodes[0], Node::Attribute { arguments, declaration, .. }
+                    if arguments.len() == 2 && matches!(declaration.as_ref(), Node::Function { .. })));
+                assert!(matches!(&nodes[1], Node::Attribute { arguments, .. } if arguments.is_empty()));
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+
+        let tokens = Lexer::new("@allow(dead_code); function helper { }").tokenize().unwrap();
+        match GardParser::parse_all(tokens).unwrap() {
+            Node::Program(nodes) => {
+                assert!(matches!(&nodes[0], Node::Attribute { name, declaration, .. }
+                    if name == "allow" && declaration.as_ref() == &Node::Program(vec![])));
+                assert!(matches!(&nodes[1], Node::Function { .. }));
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+
+        let tokens = Lexer::new("@export function gard_reset { }").tokenize().unwrap();
+        match GardParser::parse_all(tokens).unwrap() {
+            Node::Program(nodes) => assert!(matches!(&nodes[0], Node::Attribute { name, .. } if name == "export")),
+            other => panic!("expected program, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_llvm_block() {
+        let tokens = Lexer::new("llvm { \"declare i64 @host()\" } function popcount { llvm { \"ret i64 0\" } }").tokenize().unwrap();
+        match GardParser::parse_all(tokens).unwrap() {
+            Node::Program(nodes) => {
+                assert!(matches!(nodes[0], Node::InlineIr(_)));
+                match &nodes[1] {
+                    Node::Function { body, .. } => assert!(matches!(
+                        body.as_ref(),
+                        Node::Block(statements) if matches!(statements[0].unlocated(), Node::InlineIr(_))
+                    )),
+                    other => panic!("expected function, found {:?}", other),
+                }
+            },
+            other => panic!("expected program, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_macros() {
+        let input = "macro emitter(name, value) { function name { } let x = value } emitter!(Transfer, 1); function main { emitter!(a, 2) }";
+        let tokens = Lexer::new(input).tokenize().unwrap();
+        let nodes = match GardParser::parse_all(tokens).unwrap() {
+            Node::Program(nodes) => nodes,
+            other => panic!("expected program, found {:?}", other),
+        };
+
+        match &nodes[0] {
+            Node::MacroDefinition { params, body, .. } => {
+                assert_eq!(params.len(), 2);
+                assert!(matches!(body.as_ref(), Node::Block(items) if items.len() == 2));
+            },
+            other => panic!("expected macro definition, found {:?}", other),
+        }
+        match &nodes[1] {
+            Node::MacroCall { arguments, span, .. } => {
+                assert_eq!(arguments.len(), 2);
+                assert_eq!(&input[span.start..span.end], "emitter!(Transfer, 1);");
+            },
+            other => panic!("expected macro call, found {:?}", other),
+        }
+        match &nodes[2] {
+            Node::Function { body, .. } => assert!(matches!(
+                body.as_ref(),
+                Node::Block(statements) if matches!(statements[0].unlocated(), Node::MacroCall { .. })
+            )),
+            other => panic!("expected function, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scope_and_spawn() {
+        let input = "function main { scope { spawn work(1); scope { spawn work(2); } } }";
+        let program = GardParser::parse_all(Lexer::new(input).tokenize().unwrap()).unwrap();
+        let Node::Program(nodes) = program else { panic!("expected program") };
+        let Node::Function { body, .. } = &nodes[0] else { panic!("expected function, found {:?}", nodes[0]) };
+        let Node::Block(statements) = body.as_ref() else { panic!("expected block") };
+        let Node::Scope { body } = statements[0].unlocated() else { panic!("expected scope, found {:?}", statements[0]) };
+        let Node::Block(statements) = body.as_ref() else { panic!("expected block") };
+
+        assert!(matches!(statements[0].unlocated(), Node::Spawn(task) if matches!(task.as_ref(), Node::Call { arguments, .. } if arguments.len() == 1)));
+        match &statements[1] {
+            Node::Located { span, node } => {
+                assert_eq!(&input[span.start..span.end], "scope { spawn work(2); }");
+                assert!(matches!(node.as_ref(), Node::Scope { .. }));
+            },
+            other => panic!("expected located scope, found {:?}", other),
+        }
+        assert!(GardParser::parse_all(Lexer::new("function main { spawn work(1) }").tokenize().unwrap()).is_err());
+    }
+
     #[test]
     fn test_do_while() {
         let input = r#"
@@ -994,6 +2490,97 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_crypto_keyword_calls() {
+        let input = "hash(payload)";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize().unwrap();
+        let result = GardParser::expression().parse(tokens);
+        assert!(matches!(
+            result,
+            Ok(Node::Call { callee, .. }) if *callee == Node::Identifier("hash".into())
+        ));
+    }
+
+    #[test]
+    fn test_chain_intrinsics() {
+        let mut lexer = Lexer::new("mine()");
+        let result = GardParser::expression().parse(lexer.tokenize().unwrap());
+        assert!(matches!(
+            result,
+            Ok(Node::Call { callee, .. }) if *callee == Node::Identifier("mine".into())
+        ));
+
+        let mut lexer = Lexer::new("block.timestamp");
+        let result = GardParser::expression().parse(lexer.tokenize().unwrap());
+        assert!(matches!(
+            result,
+            Ok(Node::Member { object, .. }) if *object == Node::Identifier("block".into())
+        ));
+    }
+
+    #[test]
+    fn test_wasm_attributes() {
+        let mut lexer = Lexer::new(r#"@WasmExport("run") function run { }"#);
+        let result = GardParser::wasm_export_declaration().parse(lexer.tokenize().unwrap());
+        assert!(matches!(
+            result,
+            Ok(Node::WasmExport { export_name: Some(_), declaration }) if matches!(*declaration, Node::Function { .. })
+        ));
+
+        let mut lexer = Lexer::new(r#"@WasmImport("env", "log") function log(message: string);"#);
+        let result = GardParser::wasm_import_declaration().parse(lexer.tokenize().unwrap());
+        assert!(matches!(
+            result,
+            Ok(Node::WasmImport { params, return_type: Type::Void, .. }) if params.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_builtin_type_annotations() {
+        let input = "let supply: uint256";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize().unwrap();
+        let result = GardParser::let_statement().parse(tokens);
+        assert!(matches!(
+            result,
+            Ok(Node::Let { type_annotation: Some(Type::UInt256), .. })
+        ));
+    }
+
+    #[test]
+    fn test_assertion_statements() {
+        let input = r#"validate(balance >= amount, "Insufficient balance");"#;
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize().unwrap();
+        let result = GardParser::assertion_statement().parse(tokens);
+        assert!(matches!(
+            result,
+            Ok(Node::Assertion { kind: AssertionKind::Validate, message: Some(_), .. })
+        ));
+
+        let input = "assert(ready);";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize().unwrap();
+        let result = GardParser::assertion_statement().parse(tokens);
+        assert!(matches!(
+            result,
+            Ok(Node::Assertion { kind: AssertionKind::Assert, message: None, .. })
+        ));
+    }
+
+    #[test]
+    fn test_storage_slot() {
+        let input = "@slot(5) let balance: Balance";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize().unwrap();
+        let result = GardParser::storage_slot_declaration().parse(tokens);
+        assert!(matches!(
+            result,
+            Ok(Node::StorageSlot { declaration, .. }) if matches!(*declaration, Node::Let { .. })
+        ));
+    }
+
     #[test]
     fn test_transaction() {
         let input = r#"
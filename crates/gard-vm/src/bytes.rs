@@ -0,0 +1,167 @@
+//! The `bytes` type. Natively compiled code stores bytes like any Gard
+//! array: a 32-bit length followed by the data, in a block from
+//! `gard_alloc`.
+
+use crate::error::{gard_trap, raise, ErrorKind, GardError};
+use crate::memory::gard_alloc;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+/// Offset of the data in a bytes block.
+const DATA_OFFSET: usize = 4;
+
+pub fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("'{}' has an odd number of hex digits", text));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2).unwrap_or_default(), 16)
+            .map_err(|_| format!("'{}' is not hex", text)))
+        .collect()
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Bytes `start` to `end`, excluding `end`.
+pub fn slice(data: &[u8], start: i64, end: i64) -> Result<&[u8], String> {
+    if start < 0 || end < start || end as usize > data.len() {
+        return Err(format!("Slice {}..{} out of bounds for length {}", start, end, data.len()));
+    }
+    Ok(&data[start as usize..end as usize])
+}
+
+/// Copies `data` into a new bytes block.
+pub fn to_block(data: &[u8]) -> *mut u8 {
+    let block = gard_alloc(DATA_OFFSET + data.len());
+    if !block.is_null() {
+        unsafe {
+            (block as *mut u32).write(data.len() as u32);
+            ptr::copy_nonoverlapping(data.as_ptr(), block.add(DATA_OFFSET), data.len());
+        }
+    }
+    block
+}
+
+/// The data of a bytes block.
+///
+/// # Safety
+/// `block` must be a bytes block that outlives the returned slice.
+pub unsafe fn from_block<'a>(block: *const u8) -> &'a [u8] {
+    let length = (block as *const u32).read() as usize;
+    std::slice::from_raw_parts(block.add(DATA_OFFSET), length)
+}
+
+/// Copies `value` into a new NUL-terminated string.
+pub fn to_c_string(value: &str) -> *mut c_char {
+    let block = gard_alloc(value.len() + 1);
+    if !block.is_null() {
+        unsafe {
+            ptr::copy_nonoverlapping(value.as_ptr(), block, value.len());
+            block.add(value.len()).write(0);
+        }
+    }
+    block as *mut c_char
+}
+
+// Entry points called by natively compiled Gard code. Text that isn't hex
+// or data that isn't UTF-8 raises a catchable error; a slice out of bounds
+// traps like an out-of-bounds index.
+
+fn raise_invalid(message: String) -> ! {
+    raise(GardError { kind: ErrorKind::InvalidEncoding, message })
+}
+
+/// # Safety
+/// `text` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_bytes_from_hex(text: *const c_char) -> *mut u8 {
+    match from_hex(CStr::from_ptr(text).to_str().unwrap_or_default()) {
+        Ok(data) => to_block(&data),
+        Err(message) => raise_invalid(message),
+    }
+}
+
+/// # Safety
+/// `data` must be a bytes block.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_bytes_to_hex(data: *const u8) -> *mut c_char {
+    to_c_string(&to_hex(from_block(data)))
+}
+
+/// # Safety
+/// `text` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_bytes_from_string(text: *const c_char) -> *mut u8 {
+    to_block(CStr::from_ptr(text).to_bytes())
+}
+
+/// # Safety
+/// `data` must be a bytes block.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_bytes_to_string(data: *const u8) -> *mut c_char {
+    match std::str::from_utf8(from_block(data)) {
+        Ok(text) if !text.contains('\0') => to_c_string(text),
+        Ok(_) => raise_invalid("Bytes with a NUL can't be a string".to_string()),
+        Err(e) => raise_invalid(format!("Bytes are not UTF-8: {}", e)),
+    }
+}
+
+/// # Safety
+/// `data` must be a bytes block.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_bytes_slice(data: *const u8, start: i64, end: i64) -> *mut u8 {
+    match slice(from_block(data), start, end) {
+        Ok(slice) => to_block(slice),
+        Err(message) => {
+            let message = CString::new(message).unwrap_or_default();
+            gard_trap(message.as_ptr())
+        },
+    }
+}
+
+/// # Safety
+/// `left` and `right` must be bytes blocks.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_bytes_concat(left: *const u8, right: *const u8) -> *mut u8 {
+    to_block(&[from_block(left), from_block(right)].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::catch;
+    use crate::memory::gard_free;
+
+    #[test]
+    fn test_hex_and_slices() {
+        assert_eq!(from_hex("0xCAfe01"), Ok(vec![0xca, 0xfe, 0x01]));
+        assert_eq!(to_hex(&[0xca, 0xfe, 0x01]), "cafe01");
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("zz").is_err());
+        assert_eq!(slice(b"gard", 1, 3), Ok(&b"ar"[..]));
+        assert!(slice(b"gard", 2, 5).is_err());
+    }
+
+    #[test]
+    fn test_native_entry_points() {
+        unsafe {
+            let left = gard_bytes_from_hex(c"0a0b".as_ptr());
+            let right = gard_bytes_from_string(c"c".as_ptr());
+            let both = gard_bytes_concat(left, right);
+            assert_eq!(from_block(both), &[0x0a, 0x0b, b'c']);
+
+            let hex = gard_bytes_to_hex(both);
+            assert_eq!(CStr::from_ptr(hex).to_str(), Ok("0a0b63"));
+            for block in [left, right, both, hex as *mut u8] {
+                gard_free(block);
+            }
+
+            let error = catch(|| gard_bytes_from_hex(c"0xg".as_ptr())).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::InvalidEncoding);
+        }
+    }
+}
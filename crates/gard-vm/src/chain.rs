@@ -0,0 +1,149 @@
+use std::cell::RefCell;
+
+pub const GENESIS_TIMESTAMP: u64 = 1_700_000_000;
+pub const DEFAULT_BLOCK_TIME: u64 = 12;
+
+/// Deterministic block clock that contract tests run against. Nothing
+/// advances on its own: blocks only appear when test code calls `mine()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainSimulator {
+    block_number: u64,
+    timestamp: u64,
+    block_time: u64,
+}
+
+impl Default for ChainSimulator {
+    fn default() -> Self {
+        Self::new(GENESIS_TIMESTAMP, DEFAULT_BLOCK_TIME)
+    }
+}
+
+impl ChainSimulator {
+    pub fn new(genesis_timestamp: u64, block_time: u64) -> Self {
+        Self {
+            block_number: 0,
+            timestamp: genesis_timestamp,
+            block_time,
+        }
+    }
+
+    pub fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn block_time(&self) -> u64 {
+        self.block_time
+    }
+
+    pub fn set_block_time(&mut self, seconds: u64) {
+        self.block_time = seconds;
+    }
+
+    /// Mines a single block `block_time` seconds after the current one.
+    pub fn mine(&mut self) {
+        self.mine_blocks(1);
+    }
+
+    pub fn mine_blocks(&mut self, count: u64) {
+        self.block_number = self.block_number.saturating_add(count);
+        self.timestamp = self.timestamp.saturating_add(count.saturating_mul(self.block_time));
+    }
+
+    /// Moves the current block's timestamp forward without mining.
+    pub fn advance_time(&mut self, seconds: u64) {
+        self.timestamp = self.timestamp.saturating_add(seconds);
+    }
+
+    /// Sets the current block's timestamp. Time can't go backwards.
+    pub fn warp(&mut self, timestamp: u64) -> Result<(), String> {
+        if timestamp < self.timestamp {
+            return Err(format!(
+                "Cannot warp to timestamp {}, current block is at {}",
+                timestamp, self.timestamp
+            ));
+        }
+        self.timestamp = timestamp;
+        Ok(())
+    }
+}
+
+thread_local! {
+    static CHAIN: RefCell<ChainSimulator> = RefCell::new(ChainSimulator::default());
+}
+
+/// Runs `f` against the simulator of the current test thread.
+pub fn with_chain<R>(f: impl FnOnce(&mut ChainSimulator) -> R) -> R {
+    CHAIN.with(|chain| f(&mut chain.borrow_mut()))
+}
+
+/// Resets the current thread's simulator to genesis.
+pub fn reset() {
+    with_chain(|chain| *chain = ChainSimulator::default());
+}
+
+#[no_mangle]
+pub extern "C" fn gard_chain_mine(count: u64) {
+    with_chain(|chain| chain.mine_blocks(count));
+}
+
+#[no_mangle]
+pub extern "C" fn gard_chain_advance_time(seconds: u64) {
+    with_chain(|chain| chain.advance_time(seconds));
+}
+
+#[no_mangle]
+pub extern "C" fn gard_chain_warp(timestamp: u64) -> bool {
+    with_chain(|chain| chain.warp(timestamp).is_ok())
+}
+
+#[no_mangle]
+pub extern "C" fn gard_chain_block_number() -> u64 {
+    with_chain(|chain| chain.block_number())
+}
+
+#[no_mangle]
+pub extern "C" fn gard_chain_timestamp() -> u64 {
+    with_chain(|chain| chain.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mine_advances_number_and_time() {
+        let mut chain = ChainSimulator::default();
+        chain.mine();
+        chain.mine_blocks(9);
+        assert_eq!(chain.block_number(), 10);
+        assert_eq!(chain.timestamp(), GENESIS_TIMESTAMP + 10 * DEFAULT_BLOCK_TIME);
+    }
+
+    #[test]
+    fn test_timelock_style_time_travel() {
+        let mut chain = ChainSimulator::new(0, 1);
+        let unlock_at = 30 * 24 * 60 * 60;
+
+        chain.advance_time(unlock_at - 1);
+        assert!(chain.timestamp() < unlock_at);
+        chain.mine();
+        assert_eq!(chain.timestamp(), unlock_at);
+
+        assert!(chain.warp(10).is_err());
+        assert!(chain.warp(unlock_at * 2).is_ok());
+    }
+
+    #[test]
+    fn test_native_entry_points_share_thread_state() {
+        reset();
+        gard_chain_mine(3);
+        gard_chain_advance_time(100);
+        assert_eq!(gard_chain_block_number(), 3);
+        assert_eq!(gard_chain_timestamp(), GENESIS_TIMESTAMP + 3 * DEFAULT_BLOCK_TIME + 100);
+        assert!(!gard_chain_warp(0));
+    }
+}
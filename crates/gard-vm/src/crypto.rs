@@ -0,0 +1,200 @@
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use crate::bytes::{from_block, to_block};
+use crate::error::{raise, ErrorKind, GardError};
+use std::slice;
+
+pub type Hash = [u8; 32];
+pub type Address = [u8; 20];
+
+/// Ethereum-style signature: `r || s || v` with `v` in {27, 28}.
+pub type RecoverableSignature = [u8; 65];
+
+pub fn keccak256(data: &[u8]) -> Hash {
+    Keccak256::digest(data).into()
+}
+
+pub fn sha256(data: &[u8]) -> Hash {
+    Sha256::digest(data).into()
+}
+
+/// Derives the 20-byte account address of a public key, as Ethereum does.
+pub fn address_of(key: &VerifyingKey) -> Address {
+    let point = key.to_encoded_point(false);
+    let digest = keccak256(&point.as_bytes()[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&digest[12..]);
+    address
+}
+
+/// Recovers the signer of a message hash. Accepts `v` either as 0/1 or 27/28.
+pub fn ecrecover(hash: &Hash, signature: &RecoverableSignature) -> Option<Address> {
+    let v = signature[64];
+    let recovery_id = RecoveryId::from_byte(if v >= 27 { v - 27 } else { v })?;
+    let signature = Signature::from_slice(&signature[..64]).ok()?;
+    let key = VerifyingKey::recover_from_prehash(hash, &signature, recovery_id).ok()?;
+    Some(address_of(&key))
+}
+
+pub fn verify_signature(hash: &Hash, signature: &RecoverableSignature, signer: &Address) -> bool {
+    ecrecover(hash, signature).is_some_and(|recovered| &recovered == signer)
+}
+
+/// Signs a message hash with a raw secp256k1 private key.
+pub fn sign(hash: &Hash, private_key: &[u8; 32]) -> Option<RecoverableSignature> {
+    let key = SigningKey::from_slice(private_key).ok()?;
+    let (signature, recovery_id) = key.sign_prehash_recoverable(hash).ok()?;
+
+    let mut bytes = [0u8; 65];
+    bytes[..64].copy_from_slice(&signature.to_bytes());
+    bytes[64] = recovery_id.to_byte() + 27;
+    Some(bytes)
+}
+
+/// Fresh random bytes for salts and nonces. Only available on native targets.
+pub fn random_salt() -> Hash {
+    let mut salt = [0u8; 32];
+    getrandom::getrandom(&mut salt).expect("operating system randomness is unavailable");
+    salt
+}
+
+// Entry points called by natively compiled Gard code. Data is hashed from a
+// pointer and a length, so a 0 byte doesn't end it; hashes, signatures, keys
+// and addresses are bytes blocks, and one of the wrong length raises a
+// catchable error.
+
+/// The data of a bytes block of `N` bytes.
+///
+/// # Safety
+/// `block` must be a bytes block.
+unsafe fn fixed<const N: usize>(block: *const u8, what: &str) -> [u8; N] {
+    let data = from_block(block);
+    data.try_into().unwrap_or_else(|_| raise(GardError {
+        kind: ErrorKind::InvalidEncoding,
+        message: format!("A {} is {} bytes, found {}", what, N, data.len()),
+    }))
+}
+
+/// # Safety
+/// `data` must point to `length` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gard_crypto_keccak256(data: *const u8, length: usize) -> *mut u8 {
+    to_block(&keccak256(slice::from_raw_parts(data, length)))
+}
+
+/// # Safety
+/// `data` must point to `length` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gard_crypto_sha256(data: *const u8, length: usize) -> *mut u8 {
+    to_block(&sha256(slice::from_raw_parts(data, length)))
+}
+
+/// The signer's address, or 20 zero bytes if there's none, matching the
+/// EVM precompile.
+///
+/// # Safety
+/// `hash` and `signature` must be bytes blocks.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_crypto_ecrecover(hash: *const u8, signature: *const u8) -> *mut u8 {
+    let address = ecrecover(&fixed(hash, "hash"), &fixed(signature, "signature"));
+    to_block(&address.unwrap_or([0u8; 20]))
+}
+
+/// # Safety
+/// `hash`, `signature` and `signer` must be bytes blocks.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_crypto_verify(hash: *const u8, signature: *const u8, signer: *const u8) -> bool {
+    verify_signature(&fixed(hash, "hash"), &fixed(signature, "signature"), &fixed(signer, "signer address"))
+}
+
+/// # Safety
+/// `hash` and `private_key` must be bytes blocks.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_crypto_sign(hash: *const u8, private_key: *const u8) -> *mut u8 {
+    match sign(&fixed(hash, "hash"), &fixed(private_key, "private key")) {
+        Some(signature) => to_block(&signature),
+        None => raise(GardError { kind: ErrorKind::InvalidEncoding, message: "Not a secp256k1 private key".to_string() }),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn gard_crypto_random_salt() -> *mut u8 {
+    to_block(&random_salt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::catch;
+    use crate::memory::gard_free;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_keccak256() {
+        assert_eq!(
+            hex(&keccak256(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_sign_and_recover() {
+        let private_key = [0x11u8; 32];
+        let signer = address_of(SigningKey::from_slice(&private_key).unwrap().verifying_key());
+        let hash = keccak256(b"transfer 100");
+
+        let signature = sign(&hash, &private_key).unwrap();
+        assert_eq!(ecrecover(&hash, &signature), Some(signer));
+        assert!(verify_signature(&hash, &signature, &signer));
+        assert!(!verify_signature(&keccak256(b"transfer 101"), &signature, &signer));
+    }
+
+    #[test]
+    fn test_known_address() {
+        // Private key 1 maps to this well-known Ethereum address.
+        let mut private_key = [0u8; 32];
+        private_key[31] = 1;
+        let key = SigningKey::from_slice(&private_key).unwrap();
+        assert_eq!(hex(&address_of(key.verifying_key())), "7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+    }
+
+    #[test]
+    fn test_native_entry_points() {
+        unsafe {
+            // A 0 byte is part of the data, not its end
+            let hash = gard_crypto_keccak256(b"a\0b".as_ptr(), 3);
+            assert_eq!(from_block(hash), keccak256(b"a\0b"));
+            assert_ne!(from_block(hash), keccak256(b"a"));
+
+            let private_key = to_block(&[0x11u8; 32]);
+            let signature = gard_crypto_sign(hash, private_key);
+            assert_eq!(from_block(signature).len(), 65);
+            let signer = gard_crypto_ecrecover(hash, signature);
+            assert_eq!(from_block(signer), address_of(SigningKey::from_slice(&[0x11u8; 32]).unwrap().verifying_key()));
+            assert!(gard_crypto_verify(hash, signature, signer));
+
+            let error = catch(|| gard_crypto_ecrecover(private_key, hash)).unwrap_err();
+            assert_eq!(error.message, "A signature is 65 bytes, found 32");
+            for block in [hash, private_key, signature, signer] {
+                gard_free(block);
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_salt_differs() {
+        assert_ne!(random_salt(), random_salt());
+    }
+}
@@ -0,0 +1,94 @@
+//! `std.datetime`. Instants and date-times are milliseconds since the Unix
+//! epoch in UTC and durations are milliseconds, so the compiler does
+//! arithmetic and unit conversions inline; only reading the clock and
+//! ISO-8601 text go through the runtime.
+
+use crate::error::{raise, ErrorKind, GardError};
+use crate::memory::gard_alloc;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+pub fn now() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+/// Parses an RFC 3339 date-time, such as `2024-06-01T12:30:00+02:00`, or a
+/// date alone, which is midnight UTC.
+pub fn parse(text: &str) -> Result<i64, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.timestamp_millis());
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc().timestamp_millis())
+        .map_err(|e| format!("'{}': {}", text, e))
+}
+
+/// Formats as UTC, with milliseconds only when there are any.
+pub fn format(millis: i64) -> Result<String, String> {
+    DateTime::<Utc>::from_timestamp_millis(millis)
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        .ok_or_else(|| format!("{} ms is outside the supported range", millis))
+}
+
+// Entry points called by natively compiled Gard code. Text that doesn't
+// parse, or a time too far out to format, raises a catchable error.
+
+fn raise_invalid(message: String) -> ! {
+    raise(GardError { kind: ErrorKind::InvalidDateTime, message })
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_datetime_now() -> i64 {
+    now()
+}
+
+/// # Safety
+/// `text` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_datetime_parse(text: *const c_char) -> i64 {
+    parse(CStr::from_ptr(text).to_str().unwrap_or_default()).unwrap_or_else(|message| raise_invalid(message))
+}
+
+/// Returns a string allocated with `gard_alloc`.
+#[no_mangle]
+pub extern "C-unwind" fn gard_datetime_format(millis: i64) -> *mut c_char {
+    let text = format(millis).unwrap_or_else(|message| raise_invalid(message));
+    let block = gard_alloc(text.len() + 1);
+    if !block.is_null() {
+        unsafe {
+            ptr::copy_nonoverlapping(text.as_ptr(), block, text.len());
+            block.add(text.len()).write(0);
+        }
+    }
+    block as *mut c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::catch;
+    use crate::memory::gard_free;
+
+    #[test]
+    fn test_iso_8601() {
+        assert_eq!(parse("1970-01-02T00:00:01Z"), Ok(86_401_000));
+        assert_eq!(parse("2024-06-01T12:30:00.250+02:00").and_then(format), Ok("2024-06-01T10:30:00.250Z".to_string()));
+        assert_eq!(parse("2024-06-01").and_then(format), Ok("2024-06-01T00:00:00Z".to_string()));
+        assert!(parse("June 1st").unwrap_err().starts_with("'June 1st': "));
+        assert!(format(i64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_native_entry_points() {
+        assert!(gard_datetime_now() > parse("2024-01-01").unwrap());
+        unsafe {
+            let text = gard_datetime_format(0);
+            assert_eq!(CStr::from_ptr(text).to_str(), Ok("1970-01-01T00:00:00Z"));
+            gard_free(text as *mut u8);
+
+            let error = catch(|| gard_datetime_parse(c"tomorrow".as_ptr())).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::InvalidDateTime);
+        }
+    }
+}
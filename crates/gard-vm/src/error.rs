@@ -0,0 +1,235 @@
+use crate::bytes::to_c_string;
+use std::ffi::{c_char, CStr};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Which builtin raised the error. The numeric codes are shared with the
+/// compiler, which passes them to `gard_raise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Validation,
+    Requirement,
+    Assertion,
+    /// A `checked*` arithmetic builtin overflowed
+    Overflow,
+    /// An integer division or remainder by zero
+    DivisionByZero,
+    /// A member access on null
+    NullDereference,
+    /// A `std.regex` pattern that doesn't compile
+    InvalidPattern,
+    /// Text that `std.datetime` can't parse as ISO-8601
+    InvalidDateTime,
+    /// Text that isn't hex, bytes that aren't UTF-8, or a hash, signature
+    /// or key of the wrong length
+    InvalidEncoding,
+    /// An operation of `std.io`, `std.net`, `std.http` or `std.process` failed
+    Io,
+}
+
+impl ErrorKind {
+    pub fn code(&self) -> u32 {
+        match self {
+            ErrorKind::Validation => 0,
+            ErrorKind::Requirement => 1,
+            ErrorKind::Assertion => 2,
+            ErrorKind::Overflow => 3,
+            ErrorKind::DivisionByZero => 4,
+            ErrorKind::NullDereference => 5,
+            ErrorKind::InvalidPattern => 6,
+            ErrorKind::InvalidDateTime => 7,
+            ErrorKind::InvalidEncoding => 8,
+            ErrorKind::Io => 9,
+        }
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(ErrorKind::Validation),
+            1 => Some(ErrorKind::Requirement),
+            2 => Some(ErrorKind::Assertion),
+            3 => Some(ErrorKind::Overflow),
+            4 => Some(ErrorKind::DivisionByZero),
+            5 => Some(ErrorKind::NullDereference),
+            6 => Some(ErrorKind::InvalidPattern),
+            7 => Some(ErrorKind::InvalidDateTime),
+            8 => Some(ErrorKind::InvalidEncoding),
+            9 => Some(ErrorKind::Io),
+            _ => None,
+        }
+    }
+}
+
+/// An error raised by Gard code that can be caught by a `try`/`catch`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GardError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl fmt::Display for GardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ErrorKind::Validation => "Validation failed",
+            ErrorKind::Requirement => "Requirement failed",
+            ErrorKind::Assertion => "Assertion failed",
+            ErrorKind::Overflow | ErrorKind::DivisionByZero => "Arithmetic failed",
+            ErrorKind::NullDereference => "Null dereference",
+            ErrorKind::InvalidPattern => "Invalid pattern",
+            ErrorKind::InvalidDateTime => "Invalid date-time",
+            ErrorKind::InvalidEncoding => "Invalid encoding",
+            ErrorKind::Io => "I/O failed",
+        };
+        write!(f, "{}: {}", kind, self.message)
+    }
+}
+
+impl std::error::Error for GardError {}
+
+/// Unwinds to the nearest `catch`.
+pub fn raise(error: GardError) -> ! {
+    panic::resume_unwind(Box::new(error))
+}
+
+/// Runs `body`, turning a raised Gard error into `Err`. Rust panics that are
+/// not Gard errors keep unwinding.
+pub fn catch<R>(body: impl FnOnce() -> R) -> Result<R, GardError> {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(value) => Ok(value),
+        Err(payload) => match payload.downcast::<GardError>() {
+            Ok(error) => Err(*error),
+            Err(payload) => panic::resume_unwind(payload),
+        },
+    }
+}
+
+/// Called by natively compiled code when a validate/require/assert fails.
+///
+/// # Safety
+/// `message` must be null or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_raise(kind: u32, message: *const c_char) -> ! {
+    let message = if message.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(message).to_string_lossy().into_owned()
+    };
+
+    raise(GardError {
+        kind: ErrorKind::from_code(kind).unwrap_or(ErrorKind::Assertion),
+        message,
+    })
+}
+
+/// Called by natively compiled code for a `try`: runs `body` with `env`,
+/// returning null, or the error it raised for `gard_catch` or
+/// `gard_reraise` to take.
+///
+/// # Safety
+/// `body` must be safe to call with `env`.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_try(body: unsafe extern "C-unwind" fn(*mut u8), env: *mut u8) -> *mut GardError {
+    match catch(|| body(env)) {
+        Ok(()) => ptr::null_mut(),
+        Err(error) => Box::into_raw(Box::new(error)),
+    }
+}
+
+/// Called by natively compiled code for a `catch`: takes an error from
+/// `gard_try`, returning the message the catch clause binds, as the
+/// interpreter's does.
+///
+/// # Safety
+/// `error` must come from `gard_try` and not have been taken yet.
+#[no_mangle]
+pub unsafe extern "C" fn gard_catch(error: *mut GardError) -> *mut c_char {
+    to_c_string(&Box::from_raw(error).to_string())
+}
+
+/// Called by natively compiled code when a `try` without a catch clause
+/// has run its `finally`: takes an error from `gard_try` and unwinds with
+/// it again.
+///
+/// # Safety
+/// `error` must come from `gard_try` and not have been taken yet.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_reraise(error: *mut GardError) -> ! {
+    raise(*Box::from_raw(error))
+}
+
+/// Called by natively compiled code built with overflow checks when `int`
+/// arithmetic overflows, and for `uint256` overflow and out-of-bounds
+/// indexing in any build. Unlike a raised error, a trap can't be caught:
+/// the process aborts.
+///
+/// # Safety
+/// `message` must be null or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gard_trap(message: *const c_char) -> ! {
+    if !message.is_null() {
+        eprintln!("{}", CStr::from_ptr(message).to_string_lossy());
+    }
+    std::process::abort()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::gard_free;
+
+    #[test]
+    fn test_catch_raised_error() {
+        let result: Result<(), _> = catch(|| raise(GardError {
+            kind: ErrorKind::Requirement,
+            message: "Insufficient balance".to_string(),
+        }));
+
+        let error = result.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Requirement);
+        assert_eq!(error.to_string(), "Requirement failed: Insufficient balance");
+    }
+
+    #[test]
+    fn test_catch_passes_values_through() {
+        assert_eq!(catch(|| 42), Ok(42));
+    }
+
+    #[test]
+    fn test_error_codes_round_trip() {
+        for kind in [ErrorKind::Overflow, ErrorKind::DivisionByZero, ErrorKind::NullDereference, ErrorKind::InvalidPattern, ErrorKind::InvalidDateTime, ErrorKind::InvalidEncoding, ErrorKind::Io] {
+            assert_eq!(ErrorKind::from_code(kind.code()), Some(kind));
+        }
+        let error = GardError { kind: ErrorKind::DivisionByZero, message: "Division by zero at main.gd:3:5".to_string() };
+        assert_eq!(error.to_string(), "Arithmetic failed: Division by zero at main.gd:3:5");
+    }
+
+    #[test]
+    fn test_native_try() {
+        unsafe extern "C-unwind" fn fail(message: *mut u8) {
+            gard_raise(ErrorKind::Requirement.code(), message as *const c_char)
+        }
+        unsafe extern "C-unwind" fn succeed(_: *mut u8) {}
+
+        unsafe {
+            assert!(gard_try(succeed, ptr::null_mut()).is_null());
+
+            let error = gard_try(fail, c"no funds".as_ptr() as *mut u8);
+            let message = gard_catch(error);
+            assert_eq!(CStr::from_ptr(message).to_str(), Ok("Requirement failed: no funds"));
+            gard_free(message as *mut u8);
+
+            let error = gard_try(fail, c"no funds".as_ptr() as *mut u8);
+            let reraised = catch(|| gard_reraise(error)).unwrap_err();
+            assert_eq!(reraised.kind, ErrorKind::Requirement);
+        }
+    }
+
+    #[test]
+    fn test_native_entry_point() {
+        let message = c"Not authorized";
+        let error = catch(|| unsafe { gard_raise(ErrorKind::Validation.code(), message.as_ptr()) }).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Validation);
+        assert_eq!(error.message, "Not authorized");
+    }
+}
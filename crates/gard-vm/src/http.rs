@@ -0,0 +1,245 @@
+//! `std.http`: an HTTP/1.1 client over the TCP connections of `std.net`.
+//! Gard code holds a response as an int handle into a per-thread table
+//! until it closes it. Only `http://` URLs are supported.
+
+use crate::bytes::{from_block, to_block, to_c_string};
+use crate::error::{raise, ErrorKind, GardError};
+use crate::net;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: i64,
+    /// Names are lowercase
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// The first header called `name`, in any case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers.iter().find(|(header, _)| *header == name).map(|(_, value)| value.as_str())
+    }
+}
+
+thread_local! {
+    static RESPONSES: RefCell<Vec<Option<Response>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The host and port to connect to and the path to request.
+fn split_url(url: &str) -> Result<(String, String), String> {
+    let rest = url.strip_prefix("http://")
+        .ok_or_else(|| format!("'{}' is not an http:// URL", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(format!("'{}' has no host", url));
+    }
+    Ok((authority.to_string(), path.to_string()))
+}
+
+fn request(method: &str, url: &str, body: Option<(&[u8], &str)>) -> Result<Response, String> {
+    let (authority, path) = split_url(url)?;
+    let address = if authority.contains(':') { authority.clone() } else { format!("{}:80", authority) };
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n", method, path, authority);
+    if let Some((body, content_type)) = body {
+        head += &format!("Content-Type: {}\r\nContent-Length: {}\r\n", content_type, body.len());
+    }
+    head += "\r\n";
+
+    let connection = net::connect(&address)?;
+    let received = exchange(connection, [head.as_bytes(), body.map_or(&[], |(body, _)| body)].concat());
+    net::close(connection)?;
+    parse_response(&received?)
+}
+
+/// Sends a request and reads until the server closes the connection.
+fn exchange(connection: i64, request: Vec<u8>) -> Result<Vec<u8>, String> {
+    net::send(connection, &request)?;
+    let mut received = Vec::new();
+    loop {
+        let data = net::receive(connection, 8192)?;
+        if data.is_empty() {
+            return Ok(received);
+        }
+        received.extend(data);
+    }
+}
+
+pub fn parse_response(data: &[u8]) -> Result<Response, String> {
+    let end = data.windows(4).position(|window| window == b"\r\n\r\n")
+        .ok_or("Response ended inside its headers")?;
+    let head = std::str::from_utf8(&data[..end]).map_err(|_| "Response headers are not UTF-8")?;
+    let mut lines = head.split("\r\n");
+    let status = lines.next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or("Response has no status line")?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let mut response = Response { status, headers, body: data[end + 4..].to_vec() };
+    if response.header("transfer-encoding").is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked")) {
+        response.body = unchunk(&response.body)?;
+    } else if let Some(length) = response.header("content-length").and_then(|length| length.parse().ok()) {
+        response.body.truncate(length);
+    }
+    Ok(response)
+}
+
+/// Joins the chunks of a chunked body.
+fn unchunk(mut data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data.windows(2).position(|window| window == b"\r\n").ok_or("Chunk without a size")?;
+        let size = std::str::from_utf8(&data[..line_end]).ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next().unwrap_or_default().trim(), 16).ok())
+            .ok_or("Invalid chunk size")?;
+        if size == 0 {
+            return Ok(body);
+        }
+        let chunk = data.get(line_end + 2..line_end + 2 + size).ok_or("Response ended inside a chunk")?;
+        body.extend_from_slice(chunk);
+        data = data.get(line_end + 4 + size..).unwrap_or_default();
+    }
+}
+
+pub fn get(url: &str) -> Result<Response, String> {
+    request("GET", url, None)
+}
+
+pub fn post(url: &str, body: &[u8], content_type: &str) -> Result<Response, String> {
+    request("POST", url, Some((body, content_type)))
+}
+
+fn store(response: Response) -> i64 {
+    RESPONSES.with(|responses| {
+        let mut responses = responses.borrow_mut();
+        // Reuse the slot of a closed response
+        match responses.iter().position(Option::is_none) {
+            Some(handle) => {
+                responses[handle] = Some(response);
+                handle as i64
+            },
+            None => {
+                responses.push(Some(response));
+                responses.len() as i64 - 1
+            },
+        }
+    })
+}
+
+fn with_response<T>(handle: i64, f: impl FnOnce(&Response) -> T) -> T {
+    RESPONSES.with(|responses| {
+        let responses = responses.borrow();
+        match usize::try_from(handle).ok().and_then(|handle| responses.get(handle)?.as_ref()) {
+            Some(response) => f(response),
+            None => raise_io(format!("Response {} is not open", handle)),
+        }
+    })
+}
+
+// Entry points called by natively compiled Gard code. A failed request
+// raises a catchable I/O error; an error status doesn't.
+
+fn raise_io(message: String) -> ! {
+    raise(GardError { kind: ErrorKind::Io, message })
+}
+
+unsafe fn string<'a>(value: *const c_char) -> &'a str {
+    CStr::from_ptr(value).to_str().unwrap_or_default()
+}
+
+/// # Safety
+/// `url` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_http_get(url: *const c_char) -> i64 {
+    store(get(string(url)).unwrap_or_else(|message| raise_io(message)))
+}
+
+/// # Safety
+/// `url` and `content_type` must be valid NUL-terminated strings and
+/// `body` a bytes block.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_http_post(url: *const c_char, body: *const u8, content_type: *const c_char) -> i64 {
+    store(post(string(url), from_block(body), string(content_type)).unwrap_or_else(|message| raise_io(message)))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_http_status(response: i64) -> i64 {
+    with_response(response, |response| response.status)
+}
+
+/// Returns null if the response has no such header.
+///
+/// # Safety
+/// `name` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_http_header(response: i64, name: *const c_char) -> *mut c_char {
+    with_response(response, |response| match response.header(string(name)) {
+        Some(value) => to_c_string(value),
+        None => ptr::null_mut(),
+    })
+}
+
+/// Returns a bytes block.
+#[no_mangle]
+pub extern "C-unwind" fn gard_http_body(response: i64) -> *mut u8 {
+    with_response(response, |response| to_block(&response.body))
+}
+
+/// The body as a string, with invalid UTF-8 replaced.
+#[no_mangle]
+pub extern "C-unwind" fn gard_http_text(response: i64) -> *mut c_char {
+    with_response(response, |response| to_c_string(&String::from_utf8_lossy(&response.body)))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_http_close(response: i64) {
+    with_response(response, |_| ());
+    RESPONSES.with(|responses| responses.borrow_mut()[response as usize] = None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_parse_responses() {
+        let plain = parse_response(b"HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 4\r\n\r\nnope!").unwrap();
+        assert_eq!((plain.status, plain.header("content-TYPE"), plain.body.as_slice()), (404, Some("text/plain"), &b"nope"[..]));
+
+        let chunked = parse_response(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nGard\r\n1;x=y\r\n!\r\n0\r\n\r\n").unwrap();
+        assert_eq!(chunked.body, b"Gard!");
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+        assert!(split_url("https://example.com").is_err());
+        assert_eq!(split_url("http://localhost:8545"), Ok(("localhost:8545".to_string(), "/".to_string())));
+    }
+
+    #[test]
+    fn test_post_to_local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/rpc", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![0; 1024];
+            let length = stream.read(&mut request).unwrap();
+            stream.write_all(b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\nok").unwrap();
+            String::from_utf8(request[..length].to_vec()).unwrap()
+        });
+
+        let response = post(&url, b"{}", "application/json").unwrap();
+        assert_eq!((response.status, response.body.as_slice()), (201, &b"ok"[..]));
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /rpc HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Type: application/json\r\nContent-Length: 2\r\n") && request.ends_with("\r\n\r\n{}"));
+    }
+}
@@ -0,0 +1,281 @@
+//! `std.io`: buffered readers and writers over files and the standard
+//! streams. Gard code holds a stream as an int handle into a per-thread
+//! table, where 0 is stdin and 1 is stdout.
+
+use crate::bytes::{from_block, to_block};
+use crate::error::{raise, ErrorKind, GardError};
+use crate::memory::gard_alloc;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::ptr;
+use std::rc::Rc;
+
+pub const STDIN: i64 = 0;
+pub const STDOUT: i64 = 1;
+
+enum Stream {
+    Reader(BufReader<Box<dyn Read>>),
+    Writer(BufWriter<Box<dyn Write>>),
+}
+
+thread_local! {
+    static STREAMS: RefCell<Vec<Option<Stream>>> = RefCell::new(vec![
+        Some(Stream::Reader(BufReader::new(Box::new(io::stdin())))),
+        Some(Stream::Writer(BufWriter::new(Box::new(io::stdout())))),
+    ]);
+}
+
+fn open(stream: Stream) -> i64 {
+    STREAMS.with(|streams| {
+        let mut streams = streams.borrow_mut();
+        // Reuse the slot of a closed stream
+        match streams.iter().position(Option::is_none) {
+            Some(handle) => {
+                streams[handle] = Some(stream);
+                handle as i64
+            },
+            None => {
+                streams.push(Some(stream));
+                streams.len() as i64 - 1
+            },
+        }
+    })
+}
+
+fn with_stream<T>(handle: i64, f: impl FnOnce(&mut Stream) -> Result<T, String>) -> Result<T, String> {
+    STREAMS.with(|streams| {
+        let mut streams = streams.borrow_mut();
+        match usize::try_from(handle).ok().and_then(|handle| streams.get_mut(handle)?.as_mut()) {
+            Some(stream) => f(stream),
+            None => Err(format!("Stream {} is not open", handle)),
+        }
+    })
+}
+
+fn with_reader<T>(handle: i64, f: impl FnOnce(&mut BufReader<Box<dyn Read>>) -> io::Result<T>) -> Result<T, String> {
+    with_stream(handle, |stream| match stream {
+        Stream::Reader(reader) => f(reader).map_err(|e| e.to_string()),
+        Stream::Writer(_) => Err(format!("Stream {} is a writer", handle)),
+    })
+}
+
+fn with_writer<T>(handle: i64, f: impl FnOnce(&mut BufWriter<Box<dyn Write>>) -> io::Result<T>) -> Result<T, String> {
+    with_stream(handle, |stream| match stream {
+        Stream::Writer(writer) => f(writer).map_err(|e| e.to_string()),
+        Stream::Reader(_) => Err(format!("Stream {} is a reader", handle)),
+    })
+}
+
+pub fn open_reader(path: &str) -> Result<i64, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(open(Stream::Reader(BufReader::new(Box::new(file)))))
+}
+
+pub fn open_writer(path: &str) -> Result<i64, String> {
+    let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(open(Stream::Writer(BufWriter::new(Box::new(file)))))
+}
+
+/// The next line without its line ending, or `None` at the end.
+pub fn read_line(handle: i64) -> Result<Option<String>, String> {
+    with_reader(handle, |reader| {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    })
+}
+
+/// Up to `count` bytes; fewer only at the end.
+pub fn read(handle: i64, count: usize) -> Result<Vec<u8>, String> {
+    with_reader(handle, |reader| {
+        let mut data = Vec::with_capacity(count);
+        reader.take(count as u64).read_to_end(&mut data)?;
+        Ok(data)
+    })
+}
+
+pub fn write(handle: i64, data: &[u8]) -> Result<(), String> {
+    with_writer(handle, |writer| writer.write_all(data))
+}
+
+pub fn flush(handle: i64) -> Result<(), String> {
+    with_writer(handle, |writer| writer.flush())
+}
+
+/// Closes a stream, flushing a writer first. The standard streams stay
+/// open, so closing them only flushes.
+pub fn close(handle: i64) -> Result<(), String> {
+    if with_stream(handle, |stream| Ok(matches!(stream, Stream::Writer(_))))? {
+        flush(handle)?;
+    }
+    if handle > STDOUT {
+        STREAMS.with(|streams| streams.borrow_mut()[handle as usize] = None);
+    }
+    Ok(())
+}
+
+/// Runs `body` with this thread's stdout written to a buffer, returning
+/// what it wrote, for running compiled code under test. A raised error
+/// should be caught inside `body`, or stdout stays redirected.
+pub fn capture_stdout<R>(body: impl FnOnce() -> R) -> (R, String) {
+    let buffer = Rc::new(RefCell::new(Vec::new()));
+    let captured = Stream::Writer(BufWriter::new(Box::new(Shared(buffer.clone()))));
+    let stdout = STREAMS.with(|streams| streams.borrow_mut()[STDOUT as usize].replace(captured));
+    let result = body();
+    let _ = flush(STDOUT);
+    STREAMS.with(|streams| streams.borrow_mut()[STDOUT as usize] = stdout);
+    let output = String::from_utf8_lossy(&buffer.borrow()).into_owned();
+    (result, output)
+}
+
+/// A writer into a buffer that outlives it.
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Entry points called by natively compiled Gard code. A failed operation
+// raises a catchable error.
+
+fn raise_io(message: String) -> ! {
+    raise(GardError { kind: ErrorKind::Io, message })
+}
+
+unsafe fn string<'a>(value: *const c_char) -> &'a str {
+    CStr::from_ptr(value).to_str().unwrap_or_default()
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_io_stdin() -> i64 {
+    STDIN
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_io_stdout() -> i64 {
+    STDOUT
+}
+
+/// # Safety
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_io_open_reader(path: *const c_char) -> i64 {
+    open_reader(string(path)).unwrap_or_else(|message| raise_io(message))
+}
+
+/// # Safety
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_io_open_writer(path: *const c_char) -> i64 {
+    open_writer(string(path)).unwrap_or_else(|message| raise_io(message))
+}
+
+/// Returns a string allocated with `gard_alloc`, or null at the end.
+#[no_mangle]
+pub extern "C-unwind" fn gard_io_read_line(reader: i64) -> *mut c_char {
+    match read_line(reader) {
+        Ok(Some(line)) => {
+            let block = gard_alloc(line.len() + 1);
+            if !block.is_null() {
+                unsafe {
+                    ptr::copy_nonoverlapping(line.as_ptr(), block, line.len());
+                    block.add(line.len()).write(0);
+                }
+            }
+            block as *mut c_char
+        },
+        Ok(None) => ptr::null_mut(),
+        Err(message) => raise_io(message),
+    }
+}
+
+/// Returns a bytes block.
+#[no_mangle]
+pub extern "C-unwind" fn gard_io_read(reader: i64, count: i64) -> *mut u8 {
+    match read(reader, count.max(0) as usize) {
+        Ok(data) => to_block(&data),
+        Err(message) => raise_io(message),
+    }
+}
+
+/// # Safety
+/// `data` must be a bytes block.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_io_write(writer: i64, data: *const u8) {
+    write(writer, from_block(data)).unwrap_or_else(|message| raise_io(message))
+}
+
+/// # Safety
+/// `line` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_io_write_line(writer: i64, line: *const c_char) {
+    let mut data = CStr::from_ptr(line).to_bytes().to_vec();
+    data.push(b'\n');
+    write(writer, &data).unwrap_or_else(|message| raise_io(message))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_io_flush(writer: i64) {
+    flush(writer).unwrap_or_else(|message| raise_io(message))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_io_close(stream: i64) {
+    close(stream).unwrap_or_else(|message| raise_io(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffered_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("gard-io-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let writer = open_writer(path).unwrap();
+        write(writer, b"first\r\nsecond\n").unwrap();
+        write(writer, b"\x00\x01").unwrap();
+        close(writer).unwrap();
+        assert!(write(writer, b"late").is_err());
+
+        let reader = open_reader(path).unwrap();
+        assert_eq!(read_line(reader), Ok(Some("first".to_string())));
+        assert_eq!(read_line(reader), Ok(Some("second".to_string())));
+        assert_eq!(read(reader, 8), Ok(vec![0, 1]));
+        assert_eq!(read_line(reader), Ok(None));
+        assert!(write(reader, b"x").unwrap_err().contains("is a reader"));
+        close(reader).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_capture_stdout() {
+        let (result, output) = capture_stdout(|| unsafe {
+            gard_io_write_line(gard_io_stdout(), c"first".as_ptr());
+            write(STDOUT, b"second").map(|_| 7)
+        });
+        assert_eq!(result, Ok(7));
+        assert_eq!(output, "first\nsecond");
+        // Each capture starts empty
+        assert_eq!(capture_stdout(|| ()).1, "");
+        assert_eq!(flush(STDOUT), Ok(()));
+    }
+}
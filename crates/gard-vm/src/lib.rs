@@ -1,3 +1,18 @@
+pub mod bytes;
+pub mod chain;
+pub mod crypto;
+pub mod datetime;
+pub mod error;
+pub mod http;
+pub mod io;
+pub mod memory;
+pub mod net;
+pub mod process;
+pub mod regex;
+pub mod rtti;
+pub mod strings;
+pub mod uint256;
+
 pub fn execute() {
     // VM implementation will go here
-} 
\ No newline at end of file
+}
@@ -0,0 +1,71 @@
+use std::alloc::{alloc, dealloc, Layout};
+use std::mem::size_of;
+use std::ptr;
+
+const HEADER: usize = size_of::<usize>();
+const ALIGN: usize = 8;
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER)?, ALIGN).ok()
+}
+
+/// Allocates `size` bytes for a host (e.g. the JS loader) to write arguments
+/// into. Returns null if the allocation fails.
+#[no_mangle]
+pub extern "C" fn gard_alloc(size: usize) -> *mut u8 {
+    let Some(layout) = layout(size) else {
+        return ptr::null_mut();
+    };
+
+    unsafe {
+        let base = alloc(layout);
+        if base.is_null() {
+            return base;
+        }
+        // The size is stored in front of the block so gard_free doesn't need it
+        (base as *mut usize).write(size);
+        base.add(HEADER)
+    }
+}
+
+/// Frees a block returned by `gard_alloc`.
+///
+/// # Safety
+/// `block` must be null or a pointer returned by `gard_alloc` that has not
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn gard_free(block: *mut u8) {
+    if block.is_null() {
+        return;
+    }
+
+    let base = block.sub(HEADER);
+    let size = (base as *const usize).read();
+    if let Some(layout) = layout(size) {
+        dealloc(base, layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alloc_round_trip() {
+        let block = gard_alloc(6);
+        assert!(!block.is_null());
+        assert_eq!(block as usize % ALIGN, 0);
+
+        unsafe {
+            ptr::copy_nonoverlapping(b"hello!".as_ptr(), block, 6);
+            assert_eq!(std::slice::from_raw_parts(block, 6), b"hello!");
+            gard_free(block);
+            gard_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_oversized_alloc_fails() {
+        assert!(gard_alloc(usize::MAX).is_null());
+    }
+}
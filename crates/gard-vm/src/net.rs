@@ -0,0 +1,392 @@
+//! `std.net`: TCP listeners and streams and UDP sockets. Gard code holds
+//! each as an int handle into a per-thread table. Operations block the
+//! calling thread until they complete.
+//!
+//! To serve several endpoints from one thread without blocking on any, a
+//! program watches them, each under a token of its choosing, and polls:
+//! `poll` waits until a watched endpoint is ready to read from, or a
+//! listener to accept on, and returns its token, so the next operation on
+//! it won't block. That is all there is of a reactor: native code has no
+//! task scheduler, so nothing suspends on it and awaits still block. The
+//! program dispatches on the tokens itself, and timers and files aren't
+//! watched at all.
+
+use crate::bytes::{from_block, to_block, to_c_string};
+use crate::error::{raise, ErrorKind, GardError};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::os::fd::{AsRawFd, RawFd};
+
+enum Endpoint {
+    Listener(TcpListener),
+    Connection(TcpStream),
+    /// A UDP socket and where its last datagram came from
+    Socket(UdpSocket, Option<SocketAddr>),
+}
+
+thread_local! {
+    static ENDPOINTS: RefCell<Vec<Option<Endpoint>>> = const { RefCell::new(Vec::new()) };
+    /// Watched handles and their tokens, and where the next poll starts
+    /// looking, so one busy endpoint can't starve the others
+    static WATCHED: RefCell<(Vec<(i64, i64)>, usize)> = const { RefCell::new((Vec::new(), 0)) };
+}
+
+fn open(endpoint: Endpoint) -> i64 {
+    ENDPOINTS.with(|endpoints| {
+        let mut endpoints = endpoints.borrow_mut();
+        // Reuse the slot of a closed endpoint
+        match endpoints.iter().position(Option::is_none) {
+            Some(handle) => {
+                endpoints[handle] = Some(endpoint);
+                handle as i64
+            },
+            None => {
+                endpoints.push(Some(endpoint));
+                endpoints.len() as i64 - 1
+            },
+        }
+    })
+}
+
+fn with_endpoint<T>(handle: i64, f: impl FnOnce(&mut Endpoint) -> Result<T, String>) -> Result<T, String> {
+    ENDPOINTS.with(|endpoints| {
+        let mut endpoints = endpoints.borrow_mut();
+        match usize::try_from(handle).ok().and_then(|handle| endpoints.get_mut(handle)?.as_mut()) {
+            Some(endpoint) => f(endpoint),
+            None => Err(format!("Socket {} is not open", handle)),
+        }
+    })
+}
+
+fn with_listener<T>(handle: i64, f: impl FnOnce(&TcpListener) -> io::Result<T>) -> Result<T, String> {
+    with_endpoint(handle, |endpoint| match endpoint {
+        Endpoint::Listener(listener) => f(listener).map_err(|e| e.to_string()),
+        _ => Err(format!("Socket {} is not a listener", handle)),
+    })
+}
+
+fn with_connection<T>(handle: i64, f: impl FnOnce(&mut TcpStream) -> io::Result<T>) -> Result<T, String> {
+    with_endpoint(handle, |endpoint| match endpoint {
+        Endpoint::Connection(stream) => f(stream).map_err(|e| e.to_string()),
+        _ => Err(format!("Socket {} is not a connection", handle)),
+    })
+}
+
+fn with_socket<T>(handle: i64, f: impl FnOnce(&UdpSocket, &mut Option<SocketAddr>) -> io::Result<T>) -> Result<T, String> {
+    with_endpoint(handle, |endpoint| match endpoint {
+        Endpoint::Socket(socket, sender) => f(socket, sender).map_err(|e| e.to_string()),
+        _ => Err(format!("Socket {} is not a UDP socket", handle)),
+    })
+}
+
+pub fn listen(address: &str) -> Result<i64, String> {
+    let listener = TcpListener::bind(address).map_err(|e| format!("{}: {}", address, e))?;
+    Ok(open(Endpoint::Listener(listener)))
+}
+
+/// Waits for the next connection to a listener.
+pub fn accept(listener: i64) -> Result<i64, String> {
+    let (stream, _) = with_listener(listener, TcpListener::accept)?;
+    Ok(open(Endpoint::Connection(stream)))
+}
+
+pub fn connect(address: &str) -> Result<i64, String> {
+    let stream = TcpStream::connect(address).map_err(|e| format!("{}: {}", address, e))?;
+    Ok(open(Endpoint::Connection(stream)))
+}
+
+pub fn send(connection: i64, data: &[u8]) -> Result<(), String> {
+    with_connection(connection, |stream| stream.write_all(data))
+}
+
+/// Up to `count` bytes as soon as any arrive; none once the peer closed.
+pub fn receive(connection: i64, count: usize) -> Result<Vec<u8>, String> {
+    with_connection(connection, |stream| {
+        let mut data = vec![0; count];
+        let received = stream.read(&mut data)?;
+        data.truncate(received);
+        Ok(data)
+    })
+}
+
+pub fn bind(address: &str) -> Result<i64, String> {
+    let socket = UdpSocket::bind(address).map_err(|e| format!("{}: {}", address, e))?;
+    Ok(open(Endpoint::Socket(socket, None)))
+}
+
+pub fn send_to(socket: i64, data: &[u8], address: &str) -> Result<(), String> {
+    with_socket(socket, |socket, _| socket.send_to(data, address)).map(drop)
+}
+
+/// The next datagram, cut to `count` bytes.
+pub fn receive_from(socket: i64, count: usize) -> Result<Vec<u8>, String> {
+    with_socket(socket, |socket, sender| {
+        let mut data = vec![0; count];
+        let (received, from) = socket.recv_from(&mut data)?;
+        *sender = Some(from);
+        data.truncate(received);
+        Ok(data)
+    })
+}
+
+pub fn peer(connection: i64) -> Result<String, String> {
+    with_connection(connection, |stream| stream.peer_addr()).map(|address| address.to_string())
+}
+
+/// Where the last datagram `receive_from` returned came from.
+pub fn sender(socket: i64) -> Result<String, String> {
+    with_socket(socket, |_, sender| Ok(*sender))?
+        .map(|address| address.to_string())
+        .ok_or_else(|| format!("Socket {} hasn't received anything", socket))
+}
+
+pub fn local_address(handle: i64) -> Result<String, String> {
+    with_endpoint(handle, |endpoint| {
+        let address = match endpoint {
+            Endpoint::Listener(listener) => listener.local_addr(),
+            Endpoint::Connection(stream) => stream.local_addr(),
+            Endpoint::Socket(socket, _) => socket.local_addr(),
+        };
+        address.map(|address| address.to_string()).map_err(|e| e.to_string())
+    })
+}
+
+/// Watches an endpoint, replacing its token if it's already watched.
+pub fn watch(handle: i64, token: i64) -> Result<(), String> {
+    with_endpoint(handle, |_| Ok(()))?;
+    unwatch(handle);
+    WATCHED.with(|watched| watched.borrow_mut().0.push((handle, token)));
+    Ok(())
+}
+
+pub fn unwatch(handle: i64) {
+    WATCHED.with(|watched| watched.borrow_mut().0.retain(|&(watched, _)| watched != handle));
+}
+
+/// Waits up to `timeout` milliseconds, or forever if it's negative, for a
+/// watched endpoint to be ready. Returns its token, or none on timeout.
+pub fn poll(timeout: i64) -> Result<Option<i64>, String> {
+    let (watched, start) = WATCHED.with(|watched| watched.borrow().clone());
+    if watched.is_empty() {
+        return Err("No socket is watched".to_string());
+    }
+    let mut descriptors = Vec::with_capacity(watched.len());
+    for &(handle, _) in &watched {
+        let fd: RawFd = with_endpoint(handle, |endpoint| Ok(match endpoint {
+            Endpoint::Listener(listener) => listener.as_raw_fd(),
+            Endpoint::Connection(stream) => stream.as_raw_fd(),
+            Endpoint::Socket(socket, _) => socket.as_raw_fd(),
+        }))?;
+        descriptors.push(libc::pollfd { fd, events: libc::POLLIN, revents: 0 });
+    }
+    let timeout = timeout.clamp(-1, libc::c_int::MAX as i64) as libc::c_int;
+    // Safety: `descriptors` is a valid array of as many pollfds as passed
+    let ready = unsafe { libc::poll(descriptors.as_mut_ptr(), descriptors.len() as libc::nfds_t, timeout) };
+    if ready < 0 {
+        return Err(io::Error::last_os_error().to_string());
+    }
+    // Errors and hangups count as ready, so the next operation reports them
+    let found = (0..watched.len())
+        .map(|offset| (start + offset) % watched.len())
+        .find(|&index| descriptors[index].revents != 0);
+    Ok(found.map(|index| {
+        WATCHED.with(|watched| watched.borrow_mut().1 = index + 1);
+        watched[index].1
+    }))
+}
+
+/// Closes any kind of endpoint, and stops watching it. A connection is
+/// closed in both directions when its handle is dropped.
+pub fn close(handle: i64) -> Result<(), String> {
+    with_endpoint(handle, |_| Ok(()))?;
+    unwatch(handle);
+    ENDPOINTS.with(|endpoints| endpoints.borrow_mut()[handle as usize] = None);
+    Ok(())
+}
+
+// Entry points called by natively compiled Gard code. A failed operation
+// raises a catchable I/O error.
+
+fn raise_io(message: String) -> ! {
+    raise(GardError { kind: ErrorKind::Io, message })
+}
+
+unsafe fn string<'a>(value: *const c_char) -> &'a str {
+    CStr::from_ptr(value).to_str().unwrap_or_default()
+}
+
+/// # Safety
+/// `address` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_net_listen(address: *const c_char) -> i64 {
+    listen(string(address)).unwrap_or_else(|message| raise_io(message))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_accept(listener: i64) -> i64 {
+    accept(listener).unwrap_or_else(|message| raise_io(message))
+}
+
+/// # Safety
+/// `address` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_net_connect(address: *const c_char) -> i64 {
+    connect(string(address)).unwrap_or_else(|message| raise_io(message))
+}
+
+/// # Safety
+/// `data` must be a bytes block.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_net_send(connection: i64, data: *const u8) {
+    send(connection, from_block(data)).unwrap_or_else(|message| raise_io(message))
+}
+
+/// Returns a bytes block.
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_receive(connection: i64, count: i64) -> *mut u8 {
+    match receive(connection, count.max(0) as usize) {
+        Ok(data) => to_block(&data),
+        Err(message) => raise_io(message),
+    }
+}
+
+/// # Safety
+/// `address` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_net_bind(address: *const c_char) -> i64 {
+    bind(string(address)).unwrap_or_else(|message| raise_io(message))
+}
+
+/// # Safety
+/// `data` must be a bytes block and `address` a valid NUL-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_net_send_to(socket: i64, data: *const u8, address: *const c_char) {
+    send_to(socket, from_block(data), string(address)).unwrap_or_else(|message| raise_io(message))
+}
+
+/// Returns a bytes block.
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_receive_from(socket: i64, count: i64) -> *mut u8 {
+    match receive_from(socket, count.max(0) as usize) {
+        Ok(data) => to_block(&data),
+        Err(message) => raise_io(message),
+    }
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_peer(connection: i64) -> *mut c_char {
+    to_c_string(&peer(connection).unwrap_or_else(|message| raise_io(message)))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_sender(socket: i64) -> *mut c_char {
+    to_c_string(&sender(socket).unwrap_or_else(|message| raise_io(message)))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_local_address(handle: i64) -> *mut c_char {
+    to_c_string(&local_address(handle).unwrap_or_else(|message| raise_io(message)))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_close(handle: i64) {
+    close(handle).unwrap_or_else(|message| raise_io(message))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_watch(handle: i64, token: i64) {
+    watch(handle, token).unwrap_or_else(|message| raise_io(message))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_unwatch(handle: i64) {
+    unwatch(handle)
+}
+
+/// Returns -1 on timeout.
+#[no_mangle]
+pub extern "C-unwind" fn gard_net_poll(timeout: i64) -> i64 {
+    poll(timeout).unwrap_or_else(|message| raise_io(message)).unwrap_or(-1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::catch;
+
+    #[test]
+    fn test_tcp_round_trip() {
+        let listener = listen("127.0.0.1:0").unwrap();
+        let address = local_address(listener).unwrap();
+        // The table is per thread, so the client gets its own
+        let client = std::thread::spawn(move || {
+            let connection = connect(&address).unwrap();
+            send(connection, b"ping").unwrap();
+            let reply = receive(connection, 16).unwrap();
+            close(connection).unwrap();
+            reply
+        });
+
+        let connection = accept(listener).unwrap();
+        assert_eq!(receive(connection, 16), Ok(b"ping".to_vec()));
+        assert!(peer(connection).unwrap().starts_with("127.0.0.1:"));
+        send(connection, b"pong").unwrap();
+        assert_eq!(client.join().unwrap(), b"pong");
+        assert_eq!(receive(connection, 16), Ok(vec![]));
+        assert!(receive(listener, 16).unwrap_err().contains("not a connection"));
+        close(connection).unwrap();
+        close(listener).unwrap();
+        assert!(accept(listener).is_err());
+    }
+
+    #[test]
+    fn test_udp_datagrams() {
+        let server = bind("127.0.0.1:0").unwrap();
+        let client = bind("127.0.0.1:0").unwrap();
+        assert!(sender(server).is_err());
+
+        send_to(client, b"hello", &local_address(server).unwrap()).unwrap();
+        assert_eq!(receive_from(server, 3), Ok(b"hel".to_vec()));
+        assert_eq!(sender(server), local_address(client));
+
+        let error = catch(|| unsafe { gard_net_connect(c"not an address".as_ptr()) }).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Io);
+    }
+
+    #[test]
+    fn test_polling() {
+        assert!(poll(0).is_err());
+        let listener = listen("127.0.0.1:0").unwrap();
+        let socket = bind("127.0.0.1:0").unwrap();
+        watch(listener, 1).unwrap();
+        watch(socket, 2).unwrap();
+        assert_eq!(poll(0), Ok(None));
+
+        let address = local_address(listener).unwrap();
+        let client = std::thread::spawn(move || {
+            let connection = connect(&address).unwrap();
+            send(connection, b"ping").unwrap();
+            connection
+        });
+        assert_eq!(poll(1000), Ok(Some(1)));
+        let connection = accept(listener).unwrap();
+        client.join().unwrap();
+        watch(connection, 3).unwrap();
+        send_to(socket, b"hello", &local_address(socket).unwrap()).unwrap();
+
+        // Both are ready; each poll starts after the last one it returned
+        assert_eq!(poll(1000), Ok(Some(2)));
+        assert_eq!(poll(1000), Ok(Some(3)));
+        receive_from(socket, 16).unwrap();
+        assert_eq!(poll(1000), Ok(Some(3)));
+        assert_eq!(receive(connection, 16), Ok(b"ping".to_vec()));
+
+        close(connection).unwrap();
+        unwatch(socket);
+        assert_eq!(poll(0), Ok(None));
+        assert!(watch(connection, 3).is_err());
+    }
+}
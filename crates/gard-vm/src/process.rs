@@ -0,0 +1,289 @@
+//! `std.process`: building commands, running them as child processes and
+//! collecting their output. Gard code holds commands and children as int
+//! handles into per-thread tables.
+
+use crate::bytes::{from_block, to_block};
+use crate::error::{raise, ErrorKind, GardError};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr};
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, Output, Stdio};
+
+enum Process {
+    Running(Child),
+    Finished(Output),
+}
+
+thread_local! {
+    static COMMANDS: RefCell<Vec<Command>> = const { RefCell::new(Vec::new()) };
+    static CHILDREN: RefCell<Vec<Process>> = const { RefCell::new(Vec::new()) };
+}
+
+fn with_command<T>(handle: i64, f: impl FnOnce(&mut Command) -> T) -> Result<T, String> {
+    COMMANDS.with(|commands| {
+        let mut commands = commands.borrow_mut();
+        match usize::try_from(handle).ok().and_then(|handle| commands.get_mut(handle)) {
+            Some(command) => Ok(f(command)),
+            None => Err(format!("Command {} does not exist", handle)),
+        }
+    })
+}
+
+fn with_child<T>(handle: i64, f: impl FnOnce(&mut Process) -> Result<T, String>) -> Result<T, String> {
+    CHILDREN.with(|children| {
+        let mut children = children.borrow_mut();
+        match usize::try_from(handle).ok().and_then(|handle| children.get_mut(handle)) {
+            Some(child) => f(child),
+            None => Err(format!("Process {} does not exist", handle)),
+        }
+    })
+}
+
+/// A command running `program`, found on the `PATH` unless it has a
+/// directory.
+pub fn command(program: &str) -> i64 {
+    COMMANDS.with(|commands| {
+        let mut commands = commands.borrow_mut();
+        commands.push(Command::new(program));
+        commands.len() as i64 - 1
+    })
+}
+
+pub fn arg(command: i64, arg: &str) -> Result<(), String> {
+    with_command(command, |command| {
+        command.arg(arg);
+    })
+}
+
+pub fn env(command: i64, name: &str, value: &str) -> Result<(), String> {
+    with_command(command, |command| {
+        command.env(name, value);
+    })
+}
+
+/// Starts the command's environment empty instead of inheriting this
+/// process's.
+pub fn clear_env(command: i64) -> Result<(), String> {
+    with_command(command, |command| {
+        command.env_clear();
+    })
+}
+
+pub fn directory(command: i64, path: &str) -> Result<(), String> {
+    with_command(command, |command| {
+        command.current_dir(path);
+    })
+}
+
+fn run(command: i64, stdin: Stdio) -> Result<i64, String> {
+    let child = with_command(command, |command| {
+        let program = command.get_program().to_string_lossy().into_owned();
+        command.stdin(stdin).stdout(Stdio::piped()).stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: {}", program, e))
+    })??;
+    Ok(CHILDREN.with(|children| {
+        let mut children = children.borrow_mut();
+        children.push(Process::Running(child));
+        children.len() as i64 - 1
+    }))
+}
+
+/// Runs a command with its standard streams piped to this process.
+pub fn start(command: i64) -> Result<i64, String> {
+    run(command, Stdio::piped())
+}
+
+/// Runs `command` reading the output of `child`, which then captures
+/// none itself.
+pub fn pipe(child: i64, command: i64) -> Result<i64, String> {
+    let output = with_child(child, |child| match child {
+        Process::Running(child) => child.stdout.take().ok_or_else(|| "Output is already piped".to_string()),
+        Process::Finished(_) => Err("Process has finished".to_string()),
+    })?;
+    run(command, Stdio::from(output))
+}
+
+/// Writes to a running child's stdin.
+pub fn write(child: i64, data: &[u8]) -> Result<(), String> {
+    with_child(child, |child| match child {
+        Process::Running(Child { stdin: Some(stdin), .. }) => stdin.write_all(data).map_err(|e| e.to_string()),
+        Process::Running(_) => Err("Process doesn't read its input from this one".to_string()),
+        Process::Finished(_) => Err("Process has finished".to_string()),
+    })
+}
+
+/// Closes the child's stdin, waits for it to exit, collecting its output,
+/// and returns its exit code, or -1 if a signal ended it.
+pub fn finish(child: i64) -> Result<i64, String> {
+    with_child(child, |process| {
+        if let Process::Running(child) = process {
+            *process = Process::Finished(collect(child).map_err(|e| e.to_string())?);
+        }
+        let Process::Finished(output) = process else { unreachable!("the child finished above") };
+        Ok(output.status.code().map_or(-1, i64::from))
+    })
+}
+
+/// Like `Child::wait_with_output` without taking the child. Stderr is read
+/// on another thread so a child filling either pipe can't block.
+fn collect(child: &mut Child) -> io::Result<Output> {
+    drop(child.stdin.take());
+    let stderr = child.stderr.take();
+    let stderr = std::thread::spawn(move || read_all(stderr));
+    let stdout = read_all(child.stdout.take())?;
+    let stderr = stderr.join().unwrap_or_else(|_| Ok(Vec::new()))?;
+    Ok(Output { status: child.wait()?, stdout, stderr })
+}
+
+fn read_all(stream: Option<impl Read>) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if let Some(mut stream) = stream {
+        stream.read_to_end(&mut data)?;
+    }
+    Ok(data)
+}
+
+fn output(child: i64, stream: fn(&Output) -> &[u8]) -> Result<Vec<u8>, String> {
+    with_child(child, |child| match child {
+        Process::Finished(output) => Ok(stream(output).to_vec()),
+        Process::Running(_) => Err("Process hasn't finished".to_string()),
+    })
+}
+
+/// What the child wrote to stdout, once it has finished.
+pub fn stdout(child: i64) -> Result<Vec<u8>, String> {
+    output(child, |output| &output.stdout)
+}
+
+/// What the child wrote to stderr, once it has finished.
+pub fn stderr(child: i64) -> Result<Vec<u8>, String> {
+    output(child, |output| &output.stderr)
+}
+
+pub fn kill(child: i64) -> Result<(), String> {
+    with_child(child, |child| match child {
+        Process::Running(child) => child.kill().map_err(|e| e.to_string()),
+        Process::Finished(_) => Ok(()),
+    })
+}
+
+// Entry points called by natively compiled Gard code. A failed operation
+// raises a catchable I/O error; a command failing is an exit code.
+
+fn raise_io(message: String) -> ! {
+    raise(GardError { kind: ErrorKind::Io, message })
+}
+
+unsafe fn string<'a>(value: *const c_char) -> &'a str {
+    CStr::from_ptr(value).to_str().unwrap_or_default()
+}
+
+/// # Safety
+/// `program` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_process_command(program: *const c_char) -> i64 {
+    command(string(program))
+}
+
+/// # Safety
+/// `value` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_process_arg(command: i64, value: *const c_char) {
+    arg(command, string(value)).unwrap_or_else(|message| raise_io(message))
+}
+
+/// # Safety
+/// `name` and `value` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_process_env(command: i64, name: *const c_char, value: *const c_char) {
+    env(command, string(name), string(value)).unwrap_or_else(|message| raise_io(message))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_process_clear_env(command: i64) {
+    clear_env(command).unwrap_or_else(|message| raise_io(message))
+}
+
+/// # Safety
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_process_directory(command: i64, path: *const c_char) {
+    directory(command, string(path)).unwrap_or_else(|message| raise_io(message))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_process_start(command: i64) -> i64 {
+    start(command).unwrap_or_else(|message| raise_io(message))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_process_pipe(child: i64, command: i64) -> i64 {
+    pipe(child, command).unwrap_or_else(|message| raise_io(message))
+}
+
+/// # Safety
+/// `data` must be a bytes block.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_process_write(child: i64, data: *const u8) {
+    write(child, from_block(data)).unwrap_or_else(|message| raise_io(message))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_process_finish(child: i64) -> i64 {
+    finish(child).unwrap_or_else(|message| raise_io(message))
+}
+
+/// Returns a bytes block.
+#[no_mangle]
+pub extern "C-unwind" fn gard_process_stdout(child: i64) -> *mut u8 {
+    to_block(&stdout(child).unwrap_or_else(|message| raise_io(message)))
+}
+
+/// Returns a bytes block.
+#[no_mangle]
+pub extern "C-unwind" fn gard_process_stderr(child: i64) -> *mut u8 {
+    to_block(&stderr(child).unwrap_or_else(|message| raise_io(message)))
+}
+
+#[no_mangle]
+pub extern "C-unwind" fn gard_process_kill(child: i64) {
+    kill(child).unwrap_or_else(|message| raise_io(message))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_output_and_exit_code() {
+        let shell = command("sh");
+        arg(shell, "-c").unwrap();
+        arg(shell, "echo \"$GREETING\"; echo oops >&2; exit 3").unwrap();
+        clear_env(shell).unwrap();
+        env(shell, "GREETING", "hi").unwrap();
+
+        let child = start(shell).unwrap();
+        assert!(stdout(child).is_err());
+        assert_eq!(finish(child), Ok(3));
+        assert_eq!(finish(child), Ok(3));
+        assert_eq!(stdout(child), Ok(b"hi\n".to_vec()));
+        assert_eq!(stderr(child), Ok(b"oops\n".to_vec()));
+        assert!(start(command("/no/such/program")).unwrap_err().starts_with("/no/such/program"));
+    }
+
+    #[test]
+    fn test_pipe_between_children() {
+        let cat = start(command("cat")).unwrap();
+        let count = command("wc");
+        arg(count, "-c").unwrap();
+        let counter = pipe(cat, count).unwrap();
+        write(cat, b"twelve bytes").unwrap();
+
+        assert_eq!(finish(cat), Ok(0));
+        assert_eq!(stdout(cat), Ok(vec![]));
+        assert_eq!(finish(counter), Ok(0));
+        assert_eq!(String::from_utf8(stdout(counter).unwrap()).unwrap().trim(), "12");
+        assert!(write(counter, b"late").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! `std.regex`, backed by the regex crate. A pattern is passed around as its
+//! source string, so `regex.compile` only checks it; each pattern is
+//! compiled once per thread and cached, whether it came from `compile`, a
+//! `re"..."` literal or a plain string.
+
+use crate::error::{raise, ErrorKind, GardError};
+use crate::memory::gard_alloc;
+use ::regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+/// Patterns cached per thread before the cache starts over.
+const CACHE_SIZE: usize = 256;
+
+thread_local! {
+    static CACHE: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+pub fn compile(pattern: &str) -> Result<Regex, String> {
+    CACHE.with(|cache| {
+        if let Some(regex) = cache.borrow().get(pattern) {
+            return Ok(regex.clone());
+        }
+        let regex = Regex::new(pattern).map_err(|e| format!("'{}': {}", pattern, e))?;
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    })
+}
+
+pub fn is_match(pattern: &str, text: &str) -> Result<bool, String> {
+    Ok(compile(pattern)?.is_match(text))
+}
+
+/// The first match in `text`.
+pub fn find(pattern: &str, text: &str) -> Result<Option<String>, String> {
+    Ok(compile(pattern)?.find(text).map(|found| found.as_str().to_string()))
+}
+
+/// The groups of the first match, the whole match first. A group that
+/// didn't take part in the match is `None`.
+pub fn captures(pattern: &str, text: &str) -> Result<Option<Vec<Option<String>>>, String> {
+    Ok(compile(pattern)?.captures(text).map(|captures| {
+        captures.iter().map(|group| group.map(|group| group.as_str().to_string())).collect()
+    }))
+}
+
+/// Replaces every match; `$1` or `${name}` in the replacement stands for a
+/// group.
+pub fn replace(pattern: &str, text: &str, replacement: &str) -> Result<String, String> {
+    Ok(compile(pattern)?.replace_all(text, replacement).into_owned())
+}
+
+// Entry points called by natively compiled Gard code. An invalid pattern
+// raises a catchable error. Strings and arrays returned are allocated with
+// `gard_alloc`, and null stands for no match.
+
+fn raise_invalid(message: String) -> ! {
+    raise(GardError { kind: ErrorKind::InvalidPattern, message })
+}
+
+unsafe fn string<'a>(value: *const c_char) -> &'a str {
+    CStr::from_ptr(value).to_str().unwrap_or_default()
+}
+
+fn to_c_string(value: &str) -> *mut c_char {
+    let block = gard_alloc(value.len() + 1);
+    if !block.is_null() {
+        unsafe {
+            ptr::copy_nonoverlapping(value.as_ptr(), block, value.len());
+            block.add(value.len()).write(0);
+        }
+    }
+    block as *mut c_char
+}
+
+/// Returns `pattern` after checking that it compiles.
+///
+/// # Safety
+/// `pattern` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_regex_compile(pattern: *const c_char) -> *const c_char {
+    if let Err(message) = compile(string(pattern)) {
+        raise_invalid(message);
+    }
+    pattern
+}
+
+/// # Safety
+/// `pattern` and `text` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_regex_is_match(pattern: *const c_char, text: *const c_char) -> bool {
+    is_match(string(pattern), string(text)).unwrap_or_else(|message| raise_invalid(message))
+}
+
+/// # Safety
+/// `pattern` and `text` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_regex_find(pattern: *const c_char, text: *const c_char) -> *mut c_char {
+    match find(string(pattern), string(text)) {
+        Ok(Some(found)) => to_c_string(&found),
+        Ok(None) => ptr::null_mut(),
+        Err(message) => raise_invalid(message),
+    }
+}
+
+/// Returns a Gard `string[]`: the group count as a 32-bit length, then a
+/// string pointer per group at the next pointer-aligned offset, null for a
+/// group that didn't match.
+///
+/// # Safety
+/// `pattern` and `text` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_regex_captures(pattern: *const c_char, text: *const c_char) -> *mut u8 {
+    let groups = match captures(string(pattern), string(text)) {
+        Ok(Some(groups)) => groups,
+        Ok(None) => return ptr::null_mut(),
+        Err(message) => raise_invalid(message),
+    };
+    let offset = std::mem::size_of::<*mut c_char>();
+    let block = gard_alloc(offset + groups.len() * offset);
+    if block.is_null() {
+        return block;
+    }
+    (block as *mut u32).write(groups.len() as u32);
+    let elements = block.add(offset) as *mut *mut c_char;
+    for (i, group) in groups.iter().enumerate() {
+        elements.add(i).write(group.as_deref().map_or(ptr::null_mut(), to_c_string));
+    }
+    block
+}
+
+/// # Safety
+/// `pattern`, `text` and `replacement` must be valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_regex_replace(pattern: *const c_char, text: *const c_char, replacement: *const c_char) -> *mut c_char {
+    match replace(string(pattern), string(text), string(replacement)) {
+        Ok(replaced) => to_c_string(&replaced),
+        Err(message) => raise_invalid(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::catch;
+    use crate::memory::gard_free;
+
+    #[test]
+    fn test_regex_functions() {
+        assert_eq!(is_match(r"^\d{4}-\d{2}$", "2024-06"), Ok(true));
+        assert_eq!(find(r"\d+", "order 66, row 7"), Ok(Some("66".to_string())));
+        assert_eq!(find(r"\d+", "none"), Ok(None));
+        assert_eq!(captures(r"(\w+)@(\w+)?", "me@"), Ok(Some(vec![Some("me@".to_string()), Some("me".to_string()), None])));
+        assert_eq!(replace(r"(\w+) (\w+)", "hello world", "$2 $1"), Ok("world hello".to_string()));
+        assert!(compile("(unclosed").unwrap_err().starts_with("'(unclosed': "));
+    }
+
+    #[test]
+    fn test_native_entry_points() {
+        unsafe {
+            let found = gard_regex_find(c"[a-z]+".as_ptr(), c"42 apples".as_ptr());
+            assert_eq!(CStr::from_ptr(found).to_str(), Ok("apples"));
+            gard_free(found as *mut u8);
+
+            let groups = gard_regex_captures(c"(a)(b)?".as_ptr(), c"a".as_ptr());
+            assert_eq!((groups as *const u32).read(), 3);
+            let elements = groups.add(std::mem::size_of::<*mut c_char>()) as *const *mut c_char;
+            assert_eq!(CStr::from_ptr(elements.add(1).read()).to_str(), Ok("a"));
+            assert!(elements.add(2).read().is_null());
+
+            let error = catch(|| gard_regex_is_match(c"[".as_ptr(), c"".as_ptr())).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::InvalidPattern);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Type info behind `is` and `as?`. The compiler emits a `TypeInfo` for
+//! each class and interface that's tested, and puts a class's in the first
+//! slot of each of its itables, so an interface value carries its class's.
+
+use std::ffi::c_char;
+use std::ptr;
+
+#[repr(C)]
+pub struct TypeInfo {
+    /// The base class's type info, or null
+    pub base: *const TypeInfo,
+    pub name: *const c_char,
+    /// The interfaces the class implements, itself or through a base class,
+    /// ending with one whose `interface` is null. Null for an interface.
+    pub implementations: *const Implementation,
+}
+
+#[repr(C)]
+pub struct Implementation {
+    pub interface: *const TypeInfo,
+    /// The class's itable for the interface
+    pub itable: *const *const u8,
+}
+
+/// The class's itable for `interface`, or null if neither it nor a base
+/// class implements it.
+///
+/// # Safety
+/// `info` and `interface` must be type infos the compiler emitted.
+unsafe fn find_itable(mut info: *const TypeInfo, interface: *const TypeInfo) -> *const *const u8 {
+    while !info.is_null() {
+        let mut implementation = (*info).implementations;
+        while !implementation.is_null() && !(*implementation).interface.is_null() {
+            if (*implementation).interface == interface {
+                return (*implementation).itable;
+            }
+            implementation = implementation.add(1);
+        }
+        info = (*info).base;
+    }
+    ptr::null()
+}
+
+/// Whether a class is `target`, a subclass of it, or implements it.
+///
+/// # Safety
+/// `info` and `target` must be type infos the compiler emitted.
+unsafe fn is_a(info: *const TypeInfo, target: *const TypeInfo) -> bool {
+    let mut class = info;
+    while !class.is_null() {
+        if class == target {
+            return true;
+        }
+        class = (*class).base;
+    }
+    !find_itable(info, target).is_null()
+}
+
+/// Whether the class an itable belongs to is a `target`. A null itable, of
+/// a null interface value, is nothing.
+///
+/// # Safety
+/// `itable` must be null or an itable the compiler emitted, whose first
+/// slot is its class's type info, and `target` a type info.
+#[no_mangle]
+pub unsafe extern "C" fn gard_rtti_is(itable: *const *const TypeInfo, target: *const TypeInfo) -> bool {
+    !itable.is_null() && is_a(*itable, target)
+}
+
+/// The itable for `interface` of the class an itable belongs to, to view
+/// its instance as another interface; null if the class doesn't implement
+/// it.
+///
+/// # Safety
+/// As for `gard_rtti_is`.
+#[no_mangle]
+pub unsafe extern "C" fn gard_rtti_itable(itable: *const *const TypeInfo, interface: *const TypeInfo) -> *const *const u8 {
+    if itable.is_null() {
+        return ptr::null();
+    }
+    find_itable(*itable, interface)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(base: *const TypeInfo, implementations: *const Implementation) -> TypeInfo {
+        TypeInfo { base, name: ptr::null(), implementations }
+    }
+
+    #[test]
+    fn test_subclasses_and_interfaces() {
+        let shape = info(ptr::null(), ptr::null());
+        let named = info(ptr::null(), ptr::null());
+        let polygon = info(ptr::null(), ptr::null());
+        let unrelated = info(ptr::null(), ptr::null());
+
+        let mut square_shape: [*const TypeInfo; 2] = [ptr::null(), ptr::null()];
+        let square_implementations = [
+            Implementation { interface: &shape, itable: square_shape.as_ptr() as *const *const u8 },
+            Implementation { interface: ptr::null(), itable: ptr::null() },
+        ];
+        let square = info(&polygon, square_implementations.as_ptr());
+        square_shape[0] = &square;
+
+        unsafe {
+            let itable = square_shape.as_ptr();
+            assert!(gard_rtti_is(itable, &square));
+            assert!(gard_rtti_is(itable, &polygon));
+            assert!(gard_rtti_is(itable, &shape));
+            assert!(!gard_rtti_is(itable, &unrelated));
+            assert!(!gard_rtti_is(ptr::null(), &square));
+
+            assert_eq!(gard_rtti_itable(itable, &shape), itable as *const *const u8);
+            assert!(gard_rtti_itable(itable, &named).is_null());
+            assert!(gard_rtti_itable(ptr::null(), &shape).is_null());
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Indexing and iterating strings. Natively compiled code stores a string
+//! as NUL-terminated UTF-8 and, like the interpreter, counts it in
+//! characters rather than bytes: `s[i]` is the `i`th character, as a string
+//! of its own, and `foreach` visits each character once.
+
+use crate::bytes::to_c_string;
+use crate::error::gard_trap;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+/// The character at `index`, counting characters from 0.
+pub fn char_at(text: &str, index: i64) -> Result<&str, String> {
+    usize::try_from(index).ok()
+        .and_then(|position| text.char_indices().nth(position))
+        .map(|(start, c)| &text[start..start + c.len_utf8()])
+        .ok_or_else(|| format!("Index {} out of bounds for a string of {} characters", index, text.chars().count()))
+}
+
+/// The character starting at byte `offset`, with the offset of the one
+/// after it; none at the end.
+pub fn next_char(text: &str, offset: usize) -> Option<(&str, usize)> {
+    let c = text.get(offset..)?.chars().next()?;
+    let end = offset + c.len_utf8();
+    Some((&text[offset..end], end))
+}
+
+// Entry points called by natively compiled Gard code. An index out of
+// bounds traps like an array's.
+
+/// # Safety
+/// `text` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_string_char_at(text: *const c_char, index: i64) -> *mut c_char {
+    match char_at(CStr::from_ptr(text).to_str().unwrap_or_default(), index) {
+        Ok(c) => to_c_string(c),
+        Err(message) => {
+            let message = CString::new(message).unwrap_or_default();
+            gard_trap(message.as_ptr())
+        },
+    }
+}
+
+/// The character at byte `*offset`, moving the offset past it, or null
+/// at the end of the string.
+///
+/// # Safety
+/// `text` must be a valid NUL-terminated string, and `offset` point to the
+/// offset of a character in it or of its end.
+#[no_mangle]
+pub unsafe extern "C-unwind" fn gard_string_next(text: *const c_char, offset: *mut i64) -> *mut c_char {
+    let text = CStr::from_ptr(text).to_str().unwrap_or_default();
+    match next_char(text, *offset as usize) {
+        Some((c, end)) => {
+            *offset = end as i64;
+            to_c_string(c)
+        },
+        None => ptr::null_mut(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::gard_free;
+
+    #[test]
+    fn test_characters() {
+        assert_eq!(char_at("héllo", 1), Ok("é"));
+        assert_eq!(char_at("héllo", 4), Ok("o"));
+        assert_eq!(char_at("héllo", 5), Err("Index 5 out of bounds for a string of 5 characters".to_string()));
+        assert!(char_at("héllo", -1).is_err());
+        assert_eq!(next_char("añ", 1), Some(("ñ", 3)));
+        assert_eq!(next_char("añ", 3), None);
+
+        unsafe {
+            let mut offset = 0;
+            let mut characters = Vec::new();
+            loop {
+                let c = gard_string_next(c"gá".as_ptr(), &mut offset);
+                if c.is_null() {
+                    break;
+                }
+                characters.push(CStr::from_ptr(c).to_str().unwrap().to_string());
+                gard_free(c as *mut u8);
+            }
+            assert_eq!(characters, ["g", "á"]);
+
+            let c = gard_string_char_at(c"gá".as_ptr(), 1);
+            assert_eq!(CStr::from_ptr(c).to_str(), Ok("á"));
+            gard_free(c as *mut u8);
+        }
+    }
+}
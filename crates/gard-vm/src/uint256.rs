@@ -0,0 +1,176 @@
+use crate::crypto::Address;
+use num_bigint::BigUint;
+use num_traits::{Num, One, Zero};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArithmeticError {
+    Overflow { operation: &'static str },
+    Underflow { operation: &'static str },
+    DivisionByZero,
+    InvalidLiteral { literal: String },
+}
+
+impl fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticError::Overflow { operation } => write!(f, "uint256 overflow in {}", operation),
+            ArithmeticError::Underflow { operation } => write!(f, "uint256 underflow in {}", operation),
+            ArithmeticError::DivisionByZero => write!(f, "uint256 division by zero"),
+            ArithmeticError::InvalidLiteral { literal } => write!(f, "Invalid uint256 literal '{}'", literal),
+        }
+    }
+}
+
+impl std::error::Error for ArithmeticError {}
+
+/// Unsigned 256-bit integer with EVM semantics. Every arithmetic operation is
+/// checked; values never silently wrap.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct U256(BigUint);
+
+impl U256 {
+    pub const BITS: u64 = 256;
+
+    pub fn zero() -> Self {
+        U256(BigUint::zero())
+    }
+
+    pub fn max_value() -> Self {
+        U256((BigUint::one() << Self::BITS) - BigUint::one())
+    }
+
+    /// Parses a decimal or `0x`-prefixed hexadecimal literal.
+    pub fn from_literal(literal: &str) -> Result<Self, ArithmeticError> {
+        let invalid = || ArithmeticError::InvalidLiteral { literal: literal.to_string() };
+        let value = match literal.strip_prefix("0x").or_else(|| literal.strip_prefix("0X")) {
+            Some(hex) => BigUint::from_str_radix(hex, 16),
+            None => BigUint::from_str_radix(literal, 10),
+        }.map_err(|_| invalid())?;
+
+        Self::from_biguint(value).ok_or_else(invalid)
+    }
+
+    pub fn from_biguint(value: BigUint) -> Option<Self> {
+        if value.bits() <= Self::BITS {
+            Some(U256(value))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_biguint(&self) -> &BigUint {
+        &self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn checked_add(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        Self::from_biguint(&self.0 + &other.0).ok_or(ArithmeticError::Overflow { operation: "addition" })
+    }
+
+    pub fn checked_sub(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        if self.0 < other.0 {
+            return Err(ArithmeticError::Underflow { operation: "subtraction" });
+        }
+        Ok(U256(&self.0 - &other.0))
+    }
+
+    pub fn checked_mul(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        Self::from_biguint(&self.0 * &other.0).ok_or(ArithmeticError::Overflow { operation: "multiplication" })
+    }
+
+    pub fn checked_div(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        if other.is_zero() {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        Ok(U256(&self.0 / &other.0))
+    }
+
+    pub fn checked_rem(&self, other: &Self) -> Result<Self, ArithmeticError> {
+        if other.is_zero() {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        Ok(U256(&self.0 % &other.0))
+    }
+
+    /// Big-endian 32-byte encoding, as stored in EVM words.
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let digits = self.0.to_bytes_be();
+        let mut bytes = [0u8; 32];
+        bytes[32 - digits.len()..].copy_from_slice(&digits);
+        bytes
+    }
+
+    pub fn from_be_bytes(bytes: &[u8; 32]) -> Self {
+        U256(BigUint::from_bytes_be(bytes))
+    }
+
+    /// Interprets the value as an address. Fails if it doesn't fit in 160 bits.
+    pub fn to_address(&self) -> Option<Address> {
+        if self.0.bits() > 160 {
+            return None;
+        }
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&self.to_be_bytes()[12..]);
+        Some(address)
+    }
+
+    pub fn from_address(address: &Address) -> Self {
+        U256(BigUint::from_bytes_be(address))
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256(BigUint::from(value))
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_and_decimal_literals() {
+        assert_eq!(U256::from_literal("0xff").unwrap(), U256::from(255));
+        assert_eq!(U256::from_literal("1000000000000000000").unwrap().to_string(), "1000000000000000000");
+        assert_eq!(U256::from_literal(&format!("0x{}", "f".repeat(64))).unwrap(), U256::max_value());
+        assert!(U256::from_literal(&format!("0x1{}", "0".repeat(64))).is_err());
+        assert!(U256::from_literal("12ab").is_err());
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let max = U256::max_value();
+        assert_eq!(max.checked_add(&U256::from(1)), Err(ArithmeticError::Overflow { operation: "addition" }));
+        assert_eq!(U256::zero().checked_sub(&U256::from(1)), Err(ArithmeticError::Underflow { operation: "subtraction" }));
+        assert!(max.checked_mul(&U256::from(2)).is_err());
+        assert_eq!(U256::from(7).checked_div(&U256::zero()), Err(ArithmeticError::DivisionByZero));
+        assert_eq!(U256::from(7).checked_rem(&U256::from(4)).unwrap(), U256::from(3));
+        assert_eq!(max.checked_sub(&max).unwrap(), U256::zero());
+    }
+
+    #[test]
+    fn test_address_conversion() {
+        let address = U256::from_literal("0x7e5f4552091a69125d5dfcb7b8c2659029395bdf").unwrap();
+        let bytes = address.to_address().unwrap();
+        assert_eq!(U256::from_address(&bytes), address);
+        assert!(U256::max_value().to_address().is_none());
+    }
+
+    #[test]
+    fn test_be_bytes_round_trip() {
+        let value = U256::from_literal("0x0102030405").unwrap();
+        assert_eq!(U256::from_be_bytes(&value.to_be_bytes()), value);
+        assert_eq!(value.to_be_bytes()[31], 0x05);
+    }
+}